@@ -61,6 +61,8 @@ pub fn app_container() -> Html {
 pub struct App {
     user_info: Option<(String, bool)>,
     redirect_to: Option<AppRoute>,
+    /// Authorization request of an OpenID Connect client, to go back to after logging in.
+    oidc_continue: Option<String>,
     password_reset_enabled: Option<bool>,
 }
 
@@ -75,21 +77,28 @@ impl Component for App {
     type Properties = ();
 
     fn create(ctx: &Context<Self>) -> Self {
+        let oidc_continue = Self::get_oidc_continue();
         let app = Self {
-            user_info: get_cookie("user_id")
-                .unwrap_or_else(|e| {
-                    error!(&e.to_string());
-                    None
-                })
-                .and_then(|u| {
-                    get_cookie("is_admin")
-                        .map(|so| so.map(|s| (u, s == "true")))
-                        .unwrap_or_else(|e| {
-                            error!(&e.to_string());
-                            None
-                        })
-                }),
+            // The server only asks for a login when the session is gone, the cookies are stale.
+            user_info: if oidc_continue.is_some() {
+                None
+            } else {
+                get_cookie("user_id")
+                    .unwrap_or_else(|e| {
+                        error!(&e.to_string());
+                        None
+                    })
+                    .and_then(|u| {
+                        get_cookie("is_admin")
+                            .map(|so| so.map(|s| (u, s == "true")))
+                            .unwrap_or_else(|e| {
+                                error!(&e.to_string());
+                                None
+                            })
+                    })
+            },
             redirect_to: Self::get_redirect_route(ctx),
+            oidc_continue,
             password_reset_enabled: None,
         };
         ctx.link().send_future(async move {
@@ -103,6 +112,13 @@ impl Component for App {
         let history = ctx.link().history().unwrap();
         match msg {
             Msg::Login((user_name, is_admin)) => {
+                if let Some(url) = &self.oidc_continue {
+                    if let Some(window) = web_sys::window() {
+                        if window.location().set_href(url).is_ok() {
+                            return false;
+                        }
+                    }
+                }
                 self.user_info = Some((user_name.clone(), is_admin));
                 history.push(self.redirect_to.take().unwrap_or_else(|| {
                     if is_admin {
@@ -155,6 +171,16 @@ impl Component for App {
 }
 
 impl App {
+    // The authorization endpoint sends the users that aren't logged in to "/login?continue=...".
+    fn get_oidc_continue() -> Option<String> {
+        let search = web_sys::window()?.location().search().ok()?;
+        let url = web_sys::UrlSearchParams::new_with_str(&search)
+            .ok()?
+            .get("continue")?;
+        // Only relative links to the authorization endpoint, to avoid open redirects.
+        url.starts_with("/auth/oidc/authorize?").then_some(url)
+    }
+
     // Get the page to land on after logging in, defaulting to the index.
     fn get_redirect_route(ctx: &Context<Self>) -> Option<AppRoute> {
         let route = ctx.link().history().unwrap().location().route::<AppRoute>();
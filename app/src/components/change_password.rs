@@ -147,6 +147,9 @@ impl CommonComponent<ChangePasswordForm> for ChangePasswordForm {
             }
             Msg::RegistrationStartResponse(res) => {
                 let res = res.context("Could not initiate password change")?;
+                if let Some(policy) = &res.password_policy {
+                    policy.check(&self.form.model().password)?;
+                }
                 match self.opaque_data.take() {
                     OpaqueData::Registration(registration) => {
                         let mut rng = rand::rngs::OsRng;
@@ -136,6 +136,9 @@ impl CommonComponent<CreateUserForm> for CreateUserForm {
             }
             Msg::RegistrationStartResponse((registration_start, response)) => {
                 let response = response?;
+                if let Some(policy) = &response.password_policy {
+                    policy.check(&self.form.model().password)?;
+                }
                 let mut rng = rand::rngs::OsRng;
                 let registration_upload = opaque::client::registration::finish_registration(
                     registration_start,
@@ -26,6 +26,8 @@ pub struct FormModel {
     username: String,
     #[validate(length(min = 8, message = "Invalid password. Min length: 8"))]
     password: String,
+    /// Only for the users with two-factor authentication.
+    totp_code: String,
 }
 
 #[derive(Clone, PartialEq, Properties)]
@@ -37,6 +39,7 @@ pub struct Props {
 pub enum Msg {
     Update,
     Submit,
+    PasskeyLogin,
     AuthenticationRefreshResponse(Result<(String, bool)>),
     AuthenticationStartResponse(
         (
@@ -60,7 +63,9 @@ impl CommonComponent<LoginForm> for LoginForm {
                 if !self.form.validate() {
                     bail!("Check the form for errors");
                 }
-                let FormModel { username, password } = self.form.model();
+                let FormModel {
+                    username, password, ..
+                } = self.form.model();
                 let mut rng = rand::rngs::OsRng;
                 let opaque::client::login::ClientLoginStartResult { state, message } =
                     opaque::client::login::start_login(&password, &mut rng)
@@ -75,6 +80,18 @@ impl CommonComponent<LoginForm> for LoginForm {
                     });
                 Ok(true)
             }
+            Msg::PasskeyLogin => {
+                let username = self.form.model().username;
+                if username.is_empty() {
+                    bail!("Enter your username to log in with a passkey");
+                }
+                self.common.call_backend(
+                    ctx,
+                    HostService::passkey_login(username),
+                    Msg::AuthenticationFinishResponse,
+                );
+                Ok(true)
+            }
             Msg::AuthenticationStartResponse((login_start, res)) => {
                 let res = res.context("Could not log in (invalid response to login start)")?;
                 let login_finish =
@@ -89,9 +106,11 @@ impl CommonComponent<LoginForm> for LoginForm {
                         }
                         Ok(l) => l,
                     };
+                let totp_code = self.form.model().totp_code;
                 let req = login::ClientLoginFinishRequest {
                     server_data: res.server_data,
                     credential_finalization: login_finish.message,
+                    totp_code: (!totp_code.is_empty()).then_some(totp_code),
                 };
                 self.common.call_backend(
                     ctx,
@@ -189,6 +208,21 @@ impl Component for LoginForm {
                       placeholder="Password"
                       autocomplete="current-password" />
                   </div>
+                  <div class="input-group">
+                    <div class="input-group-prepend">
+                      <span class="input-group-text">
+                        <i class="bi-shield-lock-fill"/>
+                      </span>
+                    </div>
+                    <Field
+                      class="form-control"
+                      class_invalid="is-invalid has-error"
+                      class_valid="has-success"
+                      form={&self.form}
+                      field_name="totp_code"
+                      placeholder="Two-factor code (if enabled)"
+                      autocomplete="one-time-code" />
+                  </div>
                   <div class="form-group mt-3">
                     <button
                       type="submit"
@@ -198,6 +232,14 @@ impl Component for LoginForm {
                       <i class="bi-box-arrow-in-right me-2"/>
                       {"Login"}
                     </button>
+                    <button
+                      type="button"
+                      class="btn btn-secondary ms-2"
+                      disabled={self.common.is_task_running()}
+                      onclick={link.callback(|e: MouseEvent| {e.prevent_default(); Msg::PasskeyLogin})}>
+                      <i class="bi-key-fill me-2"/>
+                      {"Sign in with a passkey"}
+                    </button>
                     { if password_reset_enabled {
                       html! {
                         <Link
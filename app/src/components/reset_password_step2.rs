@@ -81,6 +81,9 @@ impl CommonComponent<ResetPasswordStep2Form> for ResetPasswordStep2Form {
             }
             Msg::RegistrationStartResponse(res) => {
                 let res = res.context("Could not initiate password change")?;
+                if let Some(policy) = &res.password_policy {
+                    policy.check(&self.form.model().password)?;
+                }
                 let registration = self.opaque_data.take().expect("Missing registration data");
                 let mut rng = rand::rngs::OsRng;
                 let registration_finish = opaque_registration::finish_registration(
@@ -148,11 +151,21 @@ impl Component for ResetPasswordStep2Form {
                 }
             }
             (None, Some(e)) => {
+                // The token was refused: unknown, expired or already used.
                 return html! {
                   <>
+                    <h2>{"This link has expired"}</h2>
+                    <p>
+                      {"The password reset links can only be used once, and only for a limited time. You can request a new one."}
+                    </p>
                     <div class="alert alert-danger">
                       {e.to_string() }
                     </div>
+                    <Link
+                      classes="btn btn-primary"
+                      to={AppRoute::StartResetPassword}>
+                      {"Request a new link"}
+                    </Link>
                     <Link
                       classes="btn-link btn"
                       disabled={self.common.is_task_running()}
@@ -160,7 +173,7 @@ impl Component for ResetPasswordStep2Form {
                       {"Back"}
                     </Link>
                   </>
-                }
+                };
             }
             _ => (),
         };
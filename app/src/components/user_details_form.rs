@@ -201,6 +201,17 @@ impl Component for UserDetailsForm {
                   <span id="creationDate" class="form-control-static">{&self.user.creation_date.naive_local().date()}</span>
                 </div>
               </div>
+              <div class="form-group row mb-3">
+                <label for="lastLogin"
+                  class="form-label col-4 col-form-label">
+                  {"Last login: "}
+                </label>
+                <div class="col-8">
+                  <span id="lastLogin" class="form-control-static">
+                    {self.user.last_login.map(|d| d.naive_local().to_string()).unwrap_or_else(|| "Never".to_owned())}
+                  </span>
+                </div>
+              </div>
               <div class="form-group row mb-3">
                 <label for="uuid"
                   class="form-label col-4 col-form-label">
@@ -20,30 +20,62 @@ pub struct ListUsersQuery;
 
 use list_users_query::{RequestFilter, ResponseData};
 
-type User = list_users_query::ListUsersQueryUsers;
+type User = list_users_query::ListUsersQueryUsersConnectionEdgesNode;
+
+/// Number of users loaded at a time.
+const PAGE_SIZE: i64 = 100;
 
 pub struct UserTable {
     common: CommonComponentParts<Self>,
     users: Option<Vec<User>>,
+    total_count: i64,
+    /// Cursor of the last loaded user, if there are more to load.
+    next_cursor: Option<String>,
 }
 
 pub enum Msg {
     ListUsersResponse(Result<ResponseData>),
+    LoadMore,
     OnUserDeleted(String),
     OnError(Error),
 }
 
 impl CommonComponent<UserTable> for UserTable {
-    fn handle_msg(&mut self, _: &Context<Self>, msg: <Self as Component>::Message) -> Result<bool> {
+    fn handle_msg(
+        &mut self,
+        ctx: &Context<Self>,
+        msg: <Self as Component>::Message,
+    ) -> Result<bool> {
         match msg {
-            Msg::ListUsersResponse(users) => {
-                self.users = Some(users?.users.into_iter().collect());
+            Msg::ListUsersResponse(response) => {
+                let connection = response?.users_connection;
+                self.total_count = connection.total_count;
+                self.next_cursor = if connection.page_info.has_next_page {
+                    connection.page_info.end_cursor
+                } else {
+                    None
+                };
+                self.users
+                    .get_or_insert_with(Vec::new)
+                    .extend(connection.edges.into_iter().map(|edge| edge.node));
+                Ok(true)
+            }
+            Msg::LoadMore => {
+                self.get_users(ctx, None, PAGE_SIZE, self.next_cursor.clone());
                 Ok(true)
             }
             Msg::OnError(e) => Err(e),
             Msg::OnUserDeleted(user_id) => {
                 debug_assert!(self.users.is_some());
-                self.users.as_mut().unwrap().retain(|u| u.id != user_id);
+                let users = self.users.as_mut().unwrap();
+                users.retain(|u| u.id != user_id);
+                self.total_count -= 1;
+                if self.next_cursor.is_some() {
+                    // The cursors are offsets, they moved: reload what was already shown.
+                    let loaded = (users.len() as i64).max(1);
+                    self.users = None;
+                    self.get_users(ctx, None, loaded, None);
+                }
                 Ok(true)
             }
         }
@@ -55,10 +87,20 @@ impl CommonComponent<UserTable> for UserTable {
 }
 
 impl UserTable {
-    fn get_users(&mut self, ctx: &Context<Self>, req: Option<RequestFilter>) {
+    fn get_users(
+        &mut self,
+        ctx: &Context<Self>,
+        req: Option<RequestFilter>,
+        first: i64,
+        after: Option<String>,
+    ) {
         self.common.call_graphql::<ListUsersQuery, _>(
             ctx,
-            list_users_query::Variables { filters: req },
+            list_users_query::Variables {
+                filters: req,
+                first: Some(first),
+                after,
+            },
             Msg::ListUsersResponse,
             "Error trying to fetch users",
         );
@@ -73,8 +115,10 @@ impl Component for UserTable {
         let mut table = UserTable {
             common: CommonComponentParts::<Self>::create(),
             users: None,
+            total_count: 0,
+            next_cursor: None,
         };
-        table.get_users(ctx, None);
+        table.get_users(ctx, None, PAGE_SIZE, None);
         table
     }
 
@@ -118,7 +162,33 @@ impl UserTable {
         };
         match &self.users {
             None => html! {{"Loading..."}},
-            Some(users) => make_table(users),
+            Some(users) => html! {
+              <>
+                {make_table(users)}
+                {self.view_load_more(ctx, users.len())}
+              </>
+            },
+        }
+    }
+
+    fn view_load_more(&self, ctx: &Context<Self>, loaded: usize) -> Html {
+        let link = ctx.link();
+        html! {
+          <div class="d-flex align-items-center">
+            <span class="me-3">{format!("Showing {} of {} users", loaded, self.total_count)}</span>
+            {if self.next_cursor.is_some() {
+              html! {
+                <button
+                  class="btn btn-secondary"
+                  disabled={self.common.is_task_running()}
+                  onclick={link.callback(|_| Msg::LoadMore)}>
+                  {"Load more"}
+                </button>
+              }
+            } else {
+              html! {}
+            }}
+          </div>
         }
     }
 
@@ -2,7 +2,7 @@ use super::cookies::set_cookie;
 use anyhow::{anyhow, Context, Result};
 use gloo_net::http::{Method, Request};
 use graphql_client::GraphQLQuery;
-use lldap_auth::{login, registration, JWTClaims};
+use lldap_auth::{login, passkey_login, registration, JWTClaims};
 
 use serde::{de::DeserializeOwned, Serialize};
 use web_sys::RequestCredentials;
@@ -74,6 +74,25 @@ fn set_cookies_from_jwt(response: login::ServerLoginResponse) -> Result<(String,
         .context("Error setting cookie")
 }
 
+/// Asks the browser to sign the challenge with one of the user's passkeys.
+async fn get_passkey_assertion(challenge: &str) -> Result<String> {
+    let challenge: webauthn_rs_proto::RequestChallengeResponse =
+        serde_json::from_str(challenge).context("Could not parse the passkey challenge")?;
+    let promise = web_sys::window()
+        .ok_or_else(|| anyhow!("No window"))?
+        .navigator()
+        .credentials()
+        .get_with_options(&challenge.into())
+        .map_err(|e| anyhow!("Passkeys are not supported by this browser: {:?}", e))?;
+    let credential = wasm_bindgen_futures::JsFuture::from(promise)
+        .await
+        .map_err(|e| anyhow!("The passkey login was cancelled: {:?}", e))?;
+    let credential = webauthn_rs_proto::PublicKeyCredential::from(
+        web_sys::PublicKeyCredential::from(credential),
+    );
+    Ok(serde_json::to_string(&credential)?)
+}
+
 impl HostService {
     pub async fn graphql_query<QueryType>(
         variables: QueryType::Variables,
@@ -126,6 +145,27 @@ impl HostService {
         .and_then(set_cookies_from_jwt)
     }
 
+    pub async fn passkey_login(username: String) -> Result<(String, bool)> {
+        let start: passkey_login::ServerPasskeyLoginStartResponse =
+            call_server_json_with_error_message(
+                "/auth/passkey/login/start",
+                Some(passkey_login::ClientPasskeyLoginStartRequest { username }),
+                "Could not start the passkey login: ",
+            )
+            .await?;
+        let credential = get_passkey_assertion(&start.challenge).await?;
+        call_server_json_with_error_message::<login::ServerLoginResponse, _>(
+            "/auth/passkey/login/finish",
+            Some(passkey_login::ClientPasskeyLoginFinishRequest {
+                server_data: start.server_data,
+                credential,
+            }),
+            "Could not log in with the passkey",
+        )
+        .await
+        .and_then(set_cookies_from_jwt)
+    }
+
     pub async fn register_start(
         request: registration::ClientRegistrationStartRequest,
     ) -> Result<Box<registration::ServerRegistrationStartResponse>> {
@@ -6,6 +6,7 @@ use std::collections::HashSet;
 use std::fmt;
 
 pub mod opaque;
+pub mod password_policy;
 
 /// The messages for the 3-step OPAQUE and simple login process.
 pub mod login {
@@ -35,12 +36,18 @@ pub mod login {
         /// Encrypted ServerData from the previous step.
         pub server_data: String,
         pub credential_finalization: opaque::client::login::CredentialFinalization,
+        /// Code from the authenticator app, for the users with TOTP enabled.
+        #[serde(default)]
+        pub totp_code: Option<String>,
     }
 
     #[derive(Serialize, Deserialize, Clone)]
     pub struct ClientSimpleLoginRequest {
         pub username: String,
         pub password: String,
+        /// Code from the authenticator app, for the users with TOTP enabled.
+        #[serde(default)]
+        pub totp_code: Option<String>,
     }
 
     impl fmt::Debug for ClientSimpleLoginRequest {
@@ -81,6 +88,10 @@ pub mod registration {
         /// Base64, encrypted ServerData to be passed back to the server.
         pub server_data: String,
         pub registration_response: opaque::client::registration::RegistrationResponse,
+        /// Rules for the new password, to be checked by the client. Missing when they don't
+        /// apply, e.g. for an admin allowed to override them.
+        #[serde(default)]
+        pub password_policy: Option<password_policy::PasswordPolicy>,
     }
 
     #[derive(Serialize, Deserialize, Clone)]
@@ -104,6 +115,32 @@ pub mod password_reset {
     }
 }
 
+/// The messages for the 2-step WebAuthn login, with a passkey instead of a password.
+pub mod passkey_login {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    pub struct ClientPasskeyLoginStartRequest {
+        pub username: String,
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    pub struct ServerPasskeyLoginStartResponse {
+        /// Base64, encrypted state of the ceremony, to be passed back to the server.
+        pub server_data: String,
+        /// The options for `navigator.credentials.get`, as JSON.
+        pub challenge: String,
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    pub struct ClientPasskeyLoginFinishRequest {
+        /// Encrypted state from the previous step.
+        pub server_data: String,
+        /// The assertion of the authenticator, as JSON.
+        pub credential: String,
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct JWTClaims {
     pub exp: DateTime<Utc>,
@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+
+/// Rules that the new passwords must follow.
+///
+/// With OPAQUE, the server never sees the passwords set through the web app or the CLI, so it
+/// sends these rules to the client at the start of the registration, and the client checks them.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PasswordPolicy {
+    /// Minimum number of characters, 0 for no minimum.
+    #[serde(default)]
+    pub min_length: usize,
+    #[serde(default)]
+    pub require_lowercase: bool,
+    #[serde(default)]
+    pub require_uppercase: bool,
+    #[serde(default)]
+    pub require_digit: bool,
+    /// Any character that isn't a letter or a digit.
+    #[serde(default)]
+    pub require_special: bool,
+}
+
+/// The rules that a password doesn't follow.
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+#[error("The password doesn't match the policy: {}", .0.join(", "))]
+pub struct PasswordPolicyError(pub Vec<String>);
+
+impl PasswordPolicy {
+    /// The rules that the password doesn't follow, if any.
+    pub fn failed_rules(&self, password: &str) -> Vec<String> {
+        let mut failed = Vec::new();
+        if password.chars().count() < self.min_length {
+            failed.push(format!("at least {} characters", self.min_length));
+        }
+        if self.require_lowercase && !password.chars().any(char::is_lowercase) {
+            failed.push("a lowercase letter".to_owned());
+        }
+        if self.require_uppercase && !password.chars().any(char::is_uppercase) {
+            failed.push("an uppercase letter".to_owned());
+        }
+        if self.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+            failed.push("a digit".to_owned());
+        }
+        if self.require_special && password.chars().all(char::is_alphanumeric) {
+            failed.push("a special character".to_owned());
+        }
+        failed
+    }
+
+    pub fn check(&self, password: &str) -> Result<(), PasswordPolicyError> {
+        let failed = self.failed_rules(password);
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(PasswordPolicyError(failed))
+        }
+    }
+}
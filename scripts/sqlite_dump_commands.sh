@@ -1,6 +1,6 @@
 #! /bin/bash
 
-tables=("users" "groups" "memberships" "jwt_refresh_storage" "jwt_storage" "password_reset_tokens" "group_attribute_schema" "group_attributes")
+tables=("users" "groups" "memberships" "group_memberships" "jwt_refresh_storage" "jwt_storage" "password_reset_tokens" "group_attribute_schema" "group_attributes" "group_attribute_index" "user_lockouts" "password_history" "api_tokens" "audit_log" "passkeys")
 echo ".header on"
 
 for table in ${tables[@]}; do
@@ -13,3 +13,11 @@ echo "select * from user_attribute_schema where user_attribute_schema_name not i
 
 echo ".mode insert user_attributes"
 echo "select * from user_attributes;"
+
+echo ".mode insert user_attribute_index"
+echo "select * from user_attribute_index;"
+
+# The uid numbers of the new users follow the imported ones.
+echo ".header off"
+echo ".mode list"
+echo "select 'UPDATE metadata SET next_uid_number = ' || (coalesce(max(uid_number), 0) + 1) || ';' from users;"
@@ -0,0 +1,31 @@
+use crate::domain::{error::Result, types::ApiTokenScope};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+/// All the API tokens start with this prefix, to tell them apart from the JWTs.
+pub const API_TOKEN_PREFIX: &str = "lldap_";
+
+/// A long-lived credential for the scripts. The token itself is only given on creation.
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
+pub struct ApiToken {
+    pub name: String,
+    pub scope: ApiTokenScope,
+    pub creation_date: chrono::NaiveDateTime,
+    pub expiry_date: Option<chrono::NaiveDateTime>,
+}
+
+#[async_trait]
+pub trait ApiTokenHandler: Send + Sync {
+    async fn list_api_tokens(&self) -> Result<Vec<ApiToken>>;
+    /// Creates a token, and returns it. Only a hash of the token is stored, it can't be
+    /// retrieved later.
+    async fn create_api_token(
+        &self,
+        name: &str,
+        scope: ApiTokenScope,
+        expiry_date: Option<chrono::NaiveDateTime>,
+    ) -> Result<String>;
+    async fn revoke_api_token(&self, name: &str) -> Result<()>;
+    /// Finds the token, failing if it doesn't exist or expired.
+    async fn check_api_token(&self, token: &str) -> Result<ApiToken>;
+}
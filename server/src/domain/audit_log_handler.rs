@@ -0,0 +1,31 @@
+use crate::domain::error::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+/// A change made to a user or a group.
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
+pub struct AuditLogEntry {
+    pub timestamp: chrono::NaiveDateTime,
+    /// The user (or API token) that made the change.
+    pub actor: String,
+    pub action: String,
+    /// The user or group that was changed.
+    pub target: String,
+    /// A JSON object of the changed fields, with their values before and after the change.
+    pub changes: Option<String>,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct AuditLogFilter {
+    pub actor: Option<String>,
+    pub target: Option<String>,
+    pub after: Option<chrono::NaiveDateTime>,
+    pub before: Option<chrono::NaiveDateTime>,
+}
+
+#[async_trait]
+pub trait AuditLogHandler: Send + Sync {
+    async fn record_audit_event(&self, entry: AuditLogEntry) -> Result<()>;
+    /// Lists the matching entries, most recent first.
+    async fn list_audit_events(&self, filter: AuditLogFilter) -> Result<Vec<AuditLogEntry>>;
+}
@@ -0,0 +1,294 @@
+//! Dynamic groups: their members are the users matching a stored filter, e.g. all the users with
+//! department=engineering, instead of being added by hand.
+//!
+//! The members are computed when the groups are read, and cached until the next change of the
+//! users or groups (or for a few seconds, for the changes made by other instances). The
+//! memberships in the filter of a dynamic group only match the regular groups.
+
+use crate::domain::{
+    error::Result,
+    handler::{GroupRequestFilter, UserRequestFilter},
+    model::{self, GroupColumn, UserColumn},
+    sql_backend_handler::SqlBackendHandler,
+    sql_user_backend_handler::get_user_condition,
+    types::{GroupDetails, UserId},
+};
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QuerySelect};
+use std::{
+    collections::BTreeSet,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tracing::{debug, warn};
+
+const CACHE_DURATION: Duration = Duration::from_secs(5);
+
+/// The permission groups can't be dynamic.
+pub const RESERVED_GROUP_PREFIX: &str = "lldap_";
+
+pub struct DynamicGroup {
+    pub details: GroupDetails,
+    pub filter: UserRequestFilter,
+    pub members: BTreeSet<UserId>,
+}
+
+type CachedGroups = Option<(Instant, Arc<Vec<DynamicGroup>>)>;
+
+#[derive(Clone, Default)]
+pub struct DynamicGroupCache {
+    groups: Arc<Mutex<CachedGroups>>,
+}
+
+impl DynamicGroupCache {
+    pub fn invalidate(&self) {
+        *self.groups.lock().unwrap() = None;
+    }
+
+    fn get(&self) -> Option<Arc<Vec<DynamicGroup>>> {
+        match &*self.groups.lock().unwrap() {
+            Some((time, groups)) if time.elapsed() < CACHE_DURATION => Some(groups.clone()),
+            _ => None,
+        }
+    }
+
+    fn set(&self, groups: Arc<Vec<DynamicGroup>>) {
+        *self.groups.lock().unwrap() = Some((Instant::now(), groups));
+    }
+}
+
+pub fn parse_dynamic_filter(filter: &str) -> Option<UserRequestFilter> {
+    serde_json::from_str(filter)
+        .map_err(|e| warn!(%filter, "Invalid filter of dynamic group: {:#}", e))
+        .ok()
+}
+
+pub fn serialize_dynamic_filter(filter: &UserRequestFilter) -> String {
+    serde_json::to_string(filter).expect("The filters can always be serialized")
+}
+
+impl SqlBackendHandler {
+    /// The dynamic groups, with their current members.
+    pub(crate) async fn get_dynamic_groups(&self) -> Result<Arc<Vec<DynamicGroup>>> {
+        if let Some(groups) = self.dynamic_groups.get() {
+            return Ok(groups);
+        }
+        let pool = self.read_pool();
+        let mut groups = Vec::new();
+        for group in model::Group::find()
+            .filter(GroupColumn::DynamicFilter.is_not_null())
+            .all(pool)
+            .await?
+        {
+            let filter = match group
+                .dynamic_filter
+                .as_deref()
+                .and_then(parse_dynamic_filter)
+            {
+                Some(filter) => filter,
+                None => continue,
+            };
+            let members = model::User::find()
+                .filter(get_user_condition(Some(filter.clone())))
+                .select_only()
+                .column(UserColumn::UserId)
+                .into_tuple::<UserId>()
+                .all(pool)
+                .await?
+                .into_iter()
+                .collect();
+            groups.push(DynamicGroup {
+                details: group.into(),
+                filter,
+                members,
+            });
+        }
+        debug!(
+            count = groups.len(),
+            "Computed the members of the dynamic groups"
+        );
+        let groups = Arc::new(groups);
+        self.dynamic_groups.set(groups.clone());
+        Ok(groups)
+    }
+}
+
+/// Replaces the memberships of the dynamic groups in a user filter by the groups' filters.
+pub fn expand_user_filter(filter: UserRequestFilter, groups: &[DynamicGroup]) -> UserRequestFilter {
+    use UserRequestFilter::*;
+    let expand_all = |filters: Vec<UserRequestFilter>| {
+        filters
+            .into_iter()
+            .map(|f| expand_user_filter(f, groups))
+            .collect()
+    };
+    let group_filter = |matches: &dyn Fn(&GroupDetails) -> bool| {
+        groups
+            .iter()
+            .find(|g| matches(&g.details))
+            .map(|g| g.filter.clone())
+    };
+    // The members as user ids, for the filters that must apply to all the groups of the user at
+    // once.
+    let with_members = |filter: UserRequestFilter, matches: &dyn Fn(i32) -> bool| {
+        let members = groups
+            .iter()
+            .filter(|g| matches(g.details.group_id.0))
+            .flat_map(|g| g.members.iter().cloned().map(UserRequestFilter::UserId))
+            .collect::<Vec<_>>();
+        if members.is_empty() {
+            filter
+        } else {
+            Or(std::iter::once(filter).chain(members).collect())
+        }
+    };
+    match filter {
+        And(filters) => And(expand_all(filters)),
+        Or(filters) => Or(expand_all(filters)),
+        Not(filter) => Not(Box::new(expand_user_filter(*filter, groups))),
+        MemberOf(name) => {
+            let expanded = group_filter(&|g| g.display_name == name);
+            expanded.unwrap_or(MemberOf(name))
+        }
+        MemberOfId(group_id) => {
+            let expanded = group_filter(&|g| g.group_id == group_id);
+            expanded.unwrap_or(MemberOfId(group_id))
+        }
+        MemberOfIdBelow(group_id) => with_members(MemberOfIdBelow(group_id), &|id| id < group_id.0),
+        MemberOfAnyId(group_ids) => {
+            let ids = group_ids.iter().map(|id| id.0).collect::<Vec<_>>();
+            with_members(MemberOfAnyId(group_ids), &|id| ids.contains(&id))
+        }
+        filter => filter,
+    }
+}
+
+/// Adds the dynamic groups to the membership conditions of a group filter.
+pub fn expand_group_filter(
+    filter: GroupRequestFilter,
+    groups: &[DynamicGroup],
+) -> GroupRequestFilter {
+    use GroupRequestFilter::*;
+    let expand_all = |filters: Vec<GroupRequestFilter>| {
+        filters
+            .into_iter()
+            .map(|f| expand_group_filter(f, groups))
+            .collect()
+    };
+    let with_dynamic_groups = |filter: GroupRequestFilter, is_member: &dyn Fn(&UserId) -> bool| {
+        Or(std::iter::once(filter)
+            .chain(
+                groups
+                    .iter()
+                    .filter(|g| g.members.iter().any(is_member))
+                    .map(|g| GroupId(g.details.group_id)),
+            )
+            .collect())
+    };
+    match filter {
+        And(filters) => And(expand_all(filters)),
+        Or(filters) => Or(expand_all(filters)),
+        Not(filter) => Not(Box::new(expand_group_filter(*filter, groups))),
+        Member(user_id) => {
+            let member = user_id.clone();
+            with_dynamic_groups(Member(user_id), &|u| u == &member)
+        }
+        MemberSubString(substring) => {
+            let matcher = substring.clone();
+            with_dynamic_groups(MemberSubString(substring), &|u| matcher.matches(u.as_str()))
+        }
+        filter => filter,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        handler::SubStringFilter,
+        types::{GroupId, Uuid},
+    };
+
+    fn make_dynamic_group(id: i32, name: &str, members: &[&str]) -> DynamicGroup {
+        let now = chrono::Utc::now().naive_utc();
+        DynamicGroup {
+            details: GroupDetails {
+                group_id: GroupId(id),
+                display_name: name.to_owned(),
+                creation_date: now,
+                uuid: Uuid::from_name_and_date(name, &now),
+            },
+            filter: UserRequestFilter::AttributeEquality("department".to_owned(), name.to_owned()),
+            members: members.iter().map(|m| UserId::new(m)).collect(),
+        }
+    }
+
+    #[test]
+    fn test_expand_user_filter() {
+        let groups = [make_dynamic_group(2, "engineering", &["bob"])];
+        assert_eq!(
+            expand_user_filter(
+                UserRequestFilter::Or(vec![
+                    UserRequestFilter::MemberOf("engineering".to_owned()),
+                    UserRequestFilter::Not(Box::new(UserRequestFilter::MemberOfId(GroupId(2)))),
+                    UserRequestFilter::MemberOf("admins".to_owned()),
+                ]),
+                &groups
+            ),
+            UserRequestFilter::Or(vec![
+                groups[0].filter.clone(),
+                UserRequestFilter::Not(Box::new(groups[0].filter.clone())),
+                UserRequestFilter::MemberOf("admins".to_owned()),
+            ])
+        );
+        // The filters on all the groups of the user list the members.
+        let bob = || UserRequestFilter::UserId(UserId::new("bob"));
+        assert_eq!(
+            expand_user_filter(
+                UserRequestFilter::And(vec![
+                    UserRequestFilter::MemberOfIdBelow(GroupId(3)),
+                    UserRequestFilter::MemberOfIdBelow(GroupId(2)),
+                    UserRequestFilter::MemberOfAnyId(vec![GroupId(1), GroupId(2)]),
+                ]),
+                &groups
+            ),
+            UserRequestFilter::And(vec![
+                UserRequestFilter::Or(vec![UserRequestFilter::MemberOfIdBelow(GroupId(3)), bob()]),
+                UserRequestFilter::MemberOfIdBelow(GroupId(2)),
+                UserRequestFilter::Or(vec![
+                    UserRequestFilter::MemberOfAnyId(vec![GroupId(1), GroupId(2)]),
+                    bob()
+                ]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_expand_group_filter() {
+        let groups = [
+            make_dynamic_group(2, "engineering", &["bob", "john"]),
+            make_dynamic_group(3, "sales", &["patrick"]),
+        ];
+        assert_eq!(
+            expand_group_filter(GroupRequestFilter::Member(UserId::new("bob")), &groups),
+            GroupRequestFilter::Or(vec![
+                GroupRequestFilter::Member(UserId::new("bob")),
+                GroupRequestFilter::GroupId(GroupId(2)),
+            ])
+        );
+        let substring = SubStringFilter {
+            initial: Some("pat".to_owned()),
+            any: vec![],
+            final_: None,
+        };
+        assert_eq!(
+            expand_group_filter(
+                GroupRequestFilter::MemberSubString(substring.clone()),
+                &groups
+            ),
+            GroupRequestFilter::Or(vec![
+                GroupRequestFilter::MemberSubString(substring),
+                GroupRequestFilter::GroupId(GroupId(3)),
+            ])
+        );
+    }
+}
@@ -19,10 +19,27 @@ pub enum DomainError {
     Base64DecodeError(#[from] base64::DecodeError),
     #[error("Entity not found: `{0}`")]
     EntityNotFound(String),
+    #[error("Entity already exists: `{0}`")]
+    EntityAlreadyExists(String),
+    #[error("Invalid input: `{0}`")]
+    InvalidInput(String),
     #[error("Internal error: `{0}`")]
     InternalError(String),
 }
 
+impl DomainError {
+    /// No database connection was available before the acquire timeout, e.g. under load.
+    pub fn is_busy(&self) -> bool {
+        matches!(
+            self,
+            DomainError::DatabaseError(sea_orm::DbErr::ConnectionAcquire)
+                | DomainError::DatabaseTransactionError(sea_orm::TransactionError::Connection(
+                    sea_orm::DbErr::ConnectionAcquire
+                ))
+        )
+    }
+}
+
 impl From<sea_orm::TransactionError<DomainError>> for DomainError {
     fn from(value: sea_orm::TransactionError<DomainError>) -> Self {
         match value {
@@ -1,11 +1,18 @@
 use crate::domain::{
+    api_token_handler::ApiTokenHandler,
+    audit_log_handler::AuditLogHandler,
     error::Result,
+    lockout_handler::LockoutHandler,
+    passkey_handler::PasskeyHandler,
+    session_handler::SessionHandler,
+    totp_handler::TotpHandler,
     types::{
-        AttributeType, Group, GroupDetails, GroupId, JpegPhoto, User, UserAndGroups, UserColumn,
-        UserId, Uuid,
+        AttributeType, AttributeValue, Group, GroupDetails, GroupId, JpegPhoto, Serialized, User,
+        UserAndGroups, UserColumn, UserId, Uuid,
     },
 };
 use async_trait::async_trait;
+use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 
@@ -22,7 +29,20 @@ pub struct SubStringFilter {
     pub final_: Option<String>,
 }
 
+/// Escapes the wildcards of a LIKE pattern, and the escape character itself.
+pub fn escape_sql_like(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
 impl SubStringFilter {
+    /// The lowercase LIKE pattern, with `\` escaping the wildcards in the values.
     pub fn to_sql_filter(&self) -> String {
         let mut filter = String::with_capacity(
             self.initial.as_ref().map(String::len).unwrap_or_default()
@@ -32,18 +52,40 @@ impl SubStringFilter {
                 + self.final_.as_ref().map(String::len).unwrap_or_default(),
         );
         if let Some(f) = &self.initial {
-            filter.push_str(&f.to_ascii_lowercase());
+            filter.push_str(&escape_sql_like(&f.to_ascii_lowercase()));
         }
         filter.push('%');
         for part in self.any.iter() {
-            filter.push_str(&part.to_ascii_lowercase());
+            filter.push_str(&escape_sql_like(&part.to_ascii_lowercase()));
             filter.push('%');
         }
         if let Some(f) = &self.final_ {
-            filter.push_str(&f.to_ascii_lowercase());
+            filter.push_str(&escape_sql_like(&f.to_ascii_lowercase()));
         }
         filter
     }
+
+    /// Same as the SQL filter, for the values that are not in the database.
+    pub fn matches(&self, value: &str) -> bool {
+        let value = value.to_ascii_lowercase();
+        let lowercase = |f: &Option<String>| f.as_deref().unwrap_or_default().to_ascii_lowercase();
+        let mut rest = match value.strip_prefix(&lowercase(&self.initial)) {
+            Some(rest) => rest,
+            None => return false,
+        };
+        rest = match rest.strip_suffix(&lowercase(&self.final_)) {
+            Some(rest) => rest,
+            None => return false,
+        };
+        for part in &self.any {
+            let part = part.to_ascii_lowercase();
+            match rest.find(&part) {
+                Some(index) => rest = &rest[index + part.len()..],
+                None => return false,
+            }
+        }
+        true
+    }
 }
 
 #[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
@@ -52,14 +94,44 @@ pub enum UserRequestFilter {
     Or(Vec<UserRequestFilter>),
     Not(Box<UserRequestFilter>),
     UserId(UserId),
+    UidNumber(i32),
+    UuidEquality(Uuid),
+    IsEnabled(bool),
     UserIdSubString(SubStringFilter),
     Equality(UserColumn, String),
+    // Exact match, for the case-sensitive attributes.
     AttributeEquality(String, String),
+    // Case-insensitive match on a single-valued string attribute.
+    AttributeEqualityIgnoreCase(String, String),
+    // Match on a single-valued attribute, with the value converted to the attribute type.
+    AttributeValueEquality(String, Serialized),
     SubString(UserColumn, SubStringFilter),
+    AttributeSubString(String, SubStringFilter),
+    // The user has a value for the attribute.
+    AttributePresent(String),
+    // One of the values of a multi-valued string attribute is equal to the value.
+    AttributeListContains(String, String),
+    // Case-insensitive containment, for approximate matches.
+    ApproxMatch(UserColumn, String),
+    CreationDateAfter(NaiveDateTime),
+    CreationDateBefore(NaiveDateTime),
+    ModifiedDateAfter(NaiveDateTime),
+    ModifiedDateBefore(NaiveDateTime),
+    // The users with an expiration date, up to this one.
+    ExpirationDateBefore(NaiveDateTime),
+    // The users who logged in since this date.
+    LastLoginAfter(NaiveDateTime),
+    // The users who logged in, but not since this date.
+    LastLoginBefore(NaiveDateTime),
     // Check if a user belongs to a group identified by name.
     MemberOf(String),
     // Same, by id.
     MemberOfId(GroupId),
+    // The user is a member of a group with a lower id, e.g. so that this one is not their primary
+    // group. Unlike MemberOfId, it can be negated.
+    MemberOfIdBelow(GroupId),
+    // The user is a member of one of the groups. Unlike an Or of MemberOfId, it can be negated.
+    MemberOfAnyId(Vec<GroupId>),
 }
 
 impl From<bool> for UserRequestFilter {
@@ -83,6 +155,16 @@ pub enum GroupRequestFilter {
     GroupId(GroupId),
     // Check if the group contains a user identified by uid.
     Member(UserId),
+    // Check if the group contains a user whose uid matches the filter.
+    MemberSubString(SubStringFilter),
+    // Check if the group directly contains a group identified by name.
+    MemberGroup(String),
+    // Match on a custom attribute, with the value converted to the attribute type.
+    AttributeEquality(String, Serialized),
+    // Case-insensitive match on a single-valued string attribute.
+    AttributeEqualityIgnoreCase(String, String),
+    // The group has a value for the attribute.
+    AttributePresent(String),
 }
 
 impl From<bool> for GroupRequestFilter {
@@ -95,6 +177,21 @@ impl From<bool> for GroupRequestFilter {
     }
 }
 
+/// The fields the users can be sorted by, e.g. for the LDAP server-side sort control.
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Copy)]
+pub enum UserSortColumn {
+    UserId,
+    DisplayName,
+    LastName,
+    Email,
+}
+
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct UserSortKey {
+    pub column: UserSortColumn,
+    pub descending: bool,
+}
+
 #[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Default)]
 pub struct CreateUserRequest {
     // Same fields as User, but no creation_date, and with password.
@@ -106,6 +203,12 @@ pub struct CreateUserRequest {
     pub avatar: Option<JpegPhoto>,
 }
 
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ImportUserRequest {
+    pub user: CreateUserRequest,
+    pub group_ids: Vec<GroupId>,
+}
+
 #[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Default)]
 pub struct UpdateUserRequest {
     // Same fields as CreateUserRequest, but no with an extra layer of Option.
@@ -115,12 +218,18 @@ pub struct UpdateUserRequest {
     pub first_name: Option<String>,
     pub last_name: Option<String>,
     pub avatar: Option<JpegPhoto>,
+    /// Custom attributes to set, replacing the previous values.
+    pub insert_attributes: Vec<AttributeValue>,
+    pub delete_attributes: Vec<String>,
 }
 
 #[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
 pub struct UpdateGroupRequest {
     pub group_id: GroupId,
     pub display_name: Option<String>,
+    /// Custom attributes to set, replacing the previous values.
+    pub insert_attributes: Vec<AttributeValue>,
+    pub delete_attributes: Vec<String>,
 }
 
 #[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
@@ -132,6 +241,10 @@ pub struct AttributeSchema {
     pub is_visible: bool,
     pub is_editable: bool,
     pub is_hardcoded: bool,
+    /// Whether the values are compared case-sensitively in the filters.
+    pub is_case_sensitive: bool,
+    /// If set, the only values accepted for this string attribute.
+    pub allowed_values: Option<Vec<String>>,
 }
 
 #[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
@@ -146,6 +259,10 @@ impl AttributeList {
             .find(|a| a.name == name)
             .map(|a| (a.attribute_type, a.is_list))
     }
+
+    pub fn get_attribute_schema(&self, name: &str) -> Option<&AttributeSchema> {
+        self.attributes.iter().find(|a| a.name == name)
+    }
 }
 
 #[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
@@ -157,6 +274,17 @@ pub struct Schema {
 #[async_trait]
 pub trait LoginHandler: Send + Sync {
     async fn bind(&self, request: BindRequest) -> Result<()>;
+    /// Fails like a bind with a wrong password, after the same password verification work, for
+    /// the binds rejected before checking the password (unknown email, user outside of the
+    /// naming context), so that they can't be told apart by their timing.
+    async fn reject_bind(&self, request: BindRequest) -> Result<()>;
+    /// Whether the password matches one of the user's last passwords, according to the
+    /// configured history size.
+    async fn is_password_recently_used(&self, user_id: &UserId, password: &str) -> Result<bool>;
+    /// Sets a password hash imported from another system, e.g. "{SSHA}..." (see
+    /// `imported_password`). It is verified by the binds, and replaced by a password file on the
+    /// first successful one.
+    async fn import_password_hash(&self, user_id: &UserId, password_hash: &str) -> Result<()>;
 }
 
 #[async_trait]
@@ -170,6 +298,19 @@ pub trait GroupBackendHandler: SchemaBackendHandler {
     async fn update_group(&self, request: UpdateGroupRequest) -> Result<()>;
     async fn create_group(&self, group_name: &str) -> Result<GroupId>;
     async fn delete_group(&self, group_id: GroupId) -> Result<()>;
+    /// Makes the group dynamic, or static again without a filter.
+    async fn set_group_dynamic_filter(
+        &self,
+        group_id: GroupId,
+        filter: Option<UserRequestFilter>,
+    ) -> Result<()>;
+    /// Nests a group in another one. Fails if it would create a cycle.
+    async fn add_group_to_group(&self, member_group_id: GroupId, group_id: GroupId) -> Result<()>;
+    async fn remove_group_from_group(
+        &self,
+        member_group_id: GroupId,
+        group_id: GroupId,
+    ) -> Result<()>;
 }
 
 #[async_trait]
@@ -179,14 +320,64 @@ pub trait UserListerBackendHandler: SchemaBackendHandler {
         filters: Option<UserRequestFilter>,
         get_groups: bool,
     ) -> Result<Vec<UserAndGroups>>;
+    /// Same as list_users, but only returns (at most) `limit` users, skipping the first `offset`
+    /// ones in user id order.
+    async fn list_users_page(
+        &self,
+        filters: Option<UserRequestFilter>,
+        get_groups: bool,
+        offset: u64,
+        limit: u64,
+    ) -> Result<Vec<UserAndGroups>> {
+        Ok(self
+            .list_users(filters, get_groups)
+            .await?
+            .into_iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .collect())
+    }
+    /// Same as list_users, but sorted by the keys, ignoring the case, then by user id. The users
+    /// without a value for a key sort after the others (before them in descending order). With a
+    /// page `(offset, limit)`, only returns that slice of the sorted users.
+    async fn list_users_sorted(
+        &self,
+        filters: Option<UserRequestFilter>,
+        get_groups: bool,
+        sort: Vec<UserSortKey>,
+        page: Option<(u64, u64)>,
+    ) -> Result<Vec<UserAndGroups>>;
+    /// Number of users matching the filters.
+    async fn count_users(&self, filters: Option<UserRequestFilter>) -> Result<u64> {
+        Ok(self.list_users(filters, false).await?.len() as u64)
+    }
 }
 
 #[async_trait]
 pub trait UserBackendHandler: SchemaBackendHandler {
     async fn get_user_details(&self, user_id: &UserId) -> Result<User>;
     async fn create_user(&self, request: CreateUserRequest) -> Result<()>;
+    /// Creates the users and their memberships in a single transaction, and returns the result
+    /// for each of them, in order. The users that fail (e.g. if the id is taken) are skipped,
+    /// unless `atomic` is set: then nothing is created if one of them fails.
+    async fn import_users(
+        &self,
+        requests: Vec<ImportUserRequest>,
+        atomic: bool,
+    ) -> Result<Vec<Result<()>>>;
     async fn update_user(&self, request: UpdateUserRequest) -> Result<()>;
+    /// With a retention period, the user is only hidden until it's purged, and can be restored
+    /// in the meantime.
     async fn delete_user(&self, user_id: &UserId) -> Result<()>;
+    async fn restore_user(&self, user_id: &UserId) -> Result<()>;
+    /// Disabling a user also revokes their sessions.
+    async fn set_user_enabled(&self, user_id: &UserId, enabled: bool) -> Result<()>;
+    /// After the expiration date, the user is treated as disabled. None to never expire.
+    async fn set_user_expiration_date(
+        &self,
+        user_id: &UserId,
+        expiration_date: Option<NaiveDateTime>,
+    ) -> Result<()>;
     async fn add_user_to_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()>;
     async fn remove_user_from_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()>;
     async fn get_user_groups(&self, user_id: &UserId) -> Result<HashSet<GroupDetails>>;
@@ -197,6 +388,16 @@ pub trait SchemaBackendHandler {
     async fn get_schema(&self) -> Result<Schema>;
 }
 
+#[async_trait]
+pub trait SchemaWriterBackendHandler {
+    /// Restricts the values of a string user attribute, or lifts the restriction with None.
+    async fn set_user_attribute_allowed_values(
+        &self,
+        name: &str,
+        allowed_values: Option<Vec<String>>,
+    ) -> Result<()>;
+}
+
 #[async_trait]
 pub trait BackendHandler:
     Send
@@ -206,6 +407,13 @@ pub trait BackendHandler:
     + UserListerBackendHandler
     + GroupListerBackendHandler
     + SchemaBackendHandler
+    + SchemaWriterBackendHandler
+    + TotpHandler
+    + PasskeyHandler
+    + LockoutHandler
+    + ApiTokenHandler
+    + AuditLogHandler
+    + SessionHandler
 {
 }
 
@@ -240,4 +448,28 @@ mod tests {
             .unwrap();
         JpegPhoto::try_from(base64_jpeg).unwrap();
     }
+
+    #[test]
+    fn test_substring_filter_matches() {
+        let filter = SubStringFilter {
+            initial: Some("Jo".to_owned()),
+            any: vec!["h".to_owned(), "n".to_owned()],
+            final_: Some("y".to_owned()),
+        };
+        assert_eq!(filter.to_sql_filter(), "jo%h%n%y");
+        assert!(filter.matches("johnny"));
+        assert!(filter.matches("JOHNY"));
+        assert!(!filter.matches("johny_"));
+        assert!(!filter.matches("jony"));
+    }
+
+    #[test]
+    fn test_substring_filter_escapes_wildcards() {
+        let filter = SubStringFilter {
+            initial: Some("50%".to_owned()),
+            any: vec!["a_b".to_owned()],
+            final_: Some("c\\d".to_owned()),
+        };
+        assert_eq!(filter.to_sql_filter(), "50\\%%a\\_b%c\\\\d");
+    }
 }
@@ -0,0 +1,260 @@
+//! Password hashes imported from other systems, e.g. OpenLDAP. They are verified by the binds,
+//! and replaced by an OPAQUE password file on the first successful one.
+//!
+//! The supported formats are the RFC 2307 schemes "{SSHA}<base64>", "{SHA}<base64>" and
+//! "{CRYPT}<crypt(3) hash>" (DES, MD5, SHA-256, SHA-512 or bcrypt), and the bare bcrypt hashes
+//! "$2b$<cost>$<salt and hash>" (or the $2a$, $2x$ and $2y$ variants).
+//!
+//! The other way around, the hashes exposed to the trusted readers of `userPassword` are
+//! computed here.
+
+use crate::infra::configuration::PasswordReplicationFormat;
+use argon2::password_hash::{PasswordHasher, SaltString};
+use base64::Engine;
+use sha1::{Digest, Sha1};
+
+const SHA1_LENGTH: usize = 20;
+
+#[derive(Debug, PartialEq, Eq)]
+enum Scheme {
+    Ssha,
+    Sha,
+    Crypt,
+}
+
+fn is_bcrypt_hash(hash: &[u8]) -> bool {
+    hash.len() == 60
+        && [b"$2a$", b"$2b$", b"$2x$", b"$2y$"]
+            .iter()
+            .any(|prefix| hash.starts_with(*prefix))
+}
+
+/// Splits "{scheme}value", for the supported schemes.
+fn split_scheme(hash: &[u8]) -> Option<(Scheme, &[u8])> {
+    let end = hash.strip_prefix(b"{")?.iter().position(|&c| c == b'}')?;
+    let scheme = match hash[1..end + 1].to_ascii_uppercase().as_slice() {
+        b"SSHA" => Scheme::Ssha,
+        b"SHA" => Scheme::Sha,
+        b"CRYPT" => Scheme::Crypt,
+        _ => return None,
+    };
+    Some((scheme, &hash[end + 2..]))
+}
+
+/// Whether the stored password is an imported hash, rather than an OPAQUE password file.
+pub(crate) fn is_imported_password_hash(hash: &[u8]) -> bool {
+    is_bcrypt_hash(hash) || split_scheme(hash).is_some()
+}
+
+/// Checks that a hash can be imported, with a message suitable for the import reports
+/// otherwise.
+pub(crate) fn check_imported_password_hash(hash: &str) -> Result<(), String> {
+    if is_bcrypt_hash(hash.as_bytes()) {
+        return Ok(());
+    }
+    let valid = match split_scheme(hash.as_bytes()) {
+        Some((Scheme::Ssha, value)) => decode_sha(value).map_or(false, |d| d.len() > SHA1_LENGTH),
+        Some((Scheme::Sha, value)) => decode_sha(value).map_or(false, |d| d.len() == SHA1_LENGTH),
+        Some((Scheme::Crypt, value)) => {
+            !value.is_empty() && pwhash::unix::crypt("", &String::from_utf8_lossy(value)).is_ok()
+        }
+        None => {
+            return Err(match hash.strip_prefix('{').and_then(|h| h.split_once('}')) {
+                Some((scheme, _)) => format!(
+                    "Unsupported password scheme `{{{}}}`, expected {{SSHA}}, {{SHA}}, {{CRYPT}} or \
+                     a bcrypt hash",
+                    scheme
+                ),
+                None => "Unsupported password: only hashes can be imported".to_owned(),
+            })
+        }
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err("Invalid password hash".to_owned())
+    }
+}
+
+fn decode_sha(value: &[u8]) -> Option<Vec<u8>> {
+    base64::engine::general_purpose::STANDARD.decode(value).ok()
+}
+
+/// Whether the password matches the imported hash.
+pub(crate) fn verify_imported_password_hash(hash: &[u8], password: &str) -> bool {
+    if is_bcrypt_hash(hash) {
+        return std::str::from_utf8(hash)
+            .ok()
+            .and_then(|hash| bcrypt::verify(password, hash).ok())
+            .unwrap_or(false);
+    }
+    match split_scheme(hash) {
+        Some((Scheme::Ssha | Scheme::Sha, value)) => match decode_sha(value) {
+            Some(decoded) if decoded.len() >= SHA1_LENGTH => {
+                let (digest, salt) = decoded.split_at(SHA1_LENGTH);
+                let mut hasher = Sha1::new();
+                hasher.update(password.as_bytes());
+                hasher.update(salt);
+                orion::util::secure_cmp(&hasher.finalize(), digest).is_ok()
+            }
+            _ => false,
+        },
+        Some((Scheme::Crypt, value)) => {
+            !value.is_empty() && pwhash::unix::verify(password, &String::from_utf8_lossy(value))
+        }
+        None => false,
+    }
+}
+
+fn replication_scheme(format: PasswordReplicationFormat) -> &'static str {
+    match format {
+        PasswordReplicationFormat::Ssha => "{SSHA}",
+        PasswordReplicationFormat::Crypt => "{CRYPT}",
+        PasswordReplicationFormat::Argon2 => "{ARGON2}",
+    }
+}
+
+/// Whether the hash was computed for this format and, for argon2, with these parameters: it is
+/// outdated after a change of the configuration.
+pub(crate) fn is_current_replication_hash(
+    hash: &str,
+    format: PasswordReplicationFormat,
+    argon2_params: &argon2::Params,
+) -> bool {
+    let value = match hash.strip_prefix(replication_scheme(format)) {
+        Some(value) => value,
+        None => return false,
+    };
+    match format {
+        // The PHC string records the parameters.
+        PasswordReplicationFormat::Argon2 => argon2::PasswordHash::new(value)
+            .ok()
+            .and_then(|hash| argon2::Params::try_from(&hash).ok())
+            .map_or(false, |params| {
+                params.m_cost() == argon2_params.m_cost()
+                    && params.t_cost() == argon2_params.t_cost()
+                    && params.p_cost() == argon2_params.p_cost()
+            }),
+        PasswordReplicationFormat::Ssha | PasswordReplicationFormat::Crypt => true,
+    }
+}
+
+/// Hashes the password in the RFC 2307 format, with a random salt.
+pub(crate) fn hash_password_for_replication(
+    format: PasswordReplicationFormat,
+    argon2_params: &argon2::Params,
+    password: &str,
+) -> Result<String, String> {
+    Ok(match format {
+        PasswordReplicationFormat::Ssha => {
+            let salt = rand::random::<[u8; 8]>();
+            let mut hasher = Sha1::new();
+            hasher.update(password.as_bytes());
+            hasher.update(salt);
+            let mut value = hasher.finalize().to_vec();
+            value.extend_from_slice(&salt);
+            base64::engine::general_purpose::STANDARD.encode(value)
+        }
+        PasswordReplicationFormat::Crypt => {
+            pwhash::sha512_crypt::hash(password).map_err(|e| e.to_string())?
+        }
+        PasswordReplicationFormat::Argon2 => {
+            let salt = SaltString::generate(&mut rand::rngs::OsRng);
+            argon2::Argon2::new(
+                argon2::Algorithm::Argon2id,
+                argon2::Version::V0x13,
+                argon2_params.clone(),
+            )
+            .hash_password(password.as_bytes(), &salt)
+            .map_err(|e| e.to_string())?
+            .to_string()
+        }
+    })
+    .map(|value| format!("{}{}", replication_scheme(format), value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_imported_password_hash() {
+        assert_eq!(
+            check_imported_password_hash(&bcrypt::hash("pass", 4).unwrap()),
+            Ok(())
+        );
+        assert_eq!(
+            check_imported_password_hash("{ssha}abcdef"),
+            Err("Invalid password hash".to_owned())
+        );
+        assert_eq!(
+            check_imported_password_hash("{MD5}X03MO1qnZdYdgyfeuILPmQ=="),
+            Err(
+                "Unsupported password scheme `{MD5}`, expected {SSHA}, {SHA}, {CRYPT} or a bcrypt \
+                 hash"
+                    .to_owned()
+            )
+        );
+        assert_eq!(
+            check_imported_password_hash("password"),
+            Err("Unsupported password: only hashes can be imported".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_verify_imported_password_hash() {
+        // The hashes of "password", with the salt "saltsalt" for the salted schemes.
+        let hashes = [
+            "{SSHA}yrht1iYXEIkejLVu42JWkadd80RzYWx0c2FsdA==".to_owned(),
+            "{SHA}W6ph5Mm5Pz8GgiULbPgzG37mj9g=".to_owned(),
+            "{CRYPT}$6$saltsalt$qFmFH.bQmmtXzyBY0s9v7Oicd2z4XSIecDzlB5KiA2/jctKu9YterLp8wwnSq.qc.eoxqOmSuNp2xS0ktL3nh/".to_owned(),
+            "{crypt}$1$saltsalt$qjXMvbEw8oaL.CzflDtaK/".to_owned(),
+            bcrypt::hash("password", 4).unwrap(),
+        ];
+        for hash in hashes {
+            assert!(is_imported_password_hash(hash.as_bytes()));
+            check_imported_password_hash(&hash).unwrap();
+            assert!(
+                verify_imported_password_hash(hash.as_bytes(), "password"),
+                "{}",
+                hash
+            );
+            assert!(!verify_imported_password_hash(
+                hash.as_bytes(),
+                "wrong_password"
+            ));
+        }
+        assert!(!is_imported_password_hash(b"{MD5}X03MO1qnZdYdgyfeuILPmQ=="));
+    }
+
+    #[test]
+    fn test_hash_password_for_replication() {
+        let params = argon2::Params::default();
+        for format in [
+            PasswordReplicationFormat::Ssha,
+            PasswordReplicationFormat::Crypt,
+        ] {
+            let hash = hash_password_for_replication(format, &params, "password").unwrap();
+            assert!(is_current_replication_hash(&hash, format, &params));
+            assert!(verify_imported_password_hash(hash.as_bytes(), "password"));
+        }
+        let format = PasswordReplicationFormat::Argon2;
+        let hash = hash_password_for_replication(format, &params, "password").unwrap();
+        assert!(is_current_replication_hash(&hash, format, &params));
+        let parsed = argon2::PasswordHash::new(hash.strip_prefix("{ARGON2}").unwrap()).unwrap();
+        assert!(argon2::PasswordVerifier::verify_password(
+            &argon2::Argon2::default(),
+            b"password",
+            &parsed
+        )
+        .is_ok());
+        // The hash is outdated with other parameters, or in another format.
+        let other_params = argon2::Params::new(1024, 1, 1, None).unwrap();
+        assert!(!is_current_replication_hash(&hash, format, &other_params));
+        assert!(!is_current_replication_hash(
+            &hash,
+            PasswordReplicationFormat::Ssha,
+            &params
+        ));
+    }
+}
@@ -1,5 +1,7 @@
 use ldap3_proto::LdapResultCode;
 
+use crate::domain::error::DomainError;
+
 #[derive(Debug, PartialEq)]
 pub struct LdapError {
     pub code: LdapResultCode,
@@ -15,3 +17,13 @@ impl std::fmt::Display for LdapError {
 impl std::error::Error for LdapError {}
 
 pub type LdapResult<T> = std::result::Result<T, LdapError>;
+
+/// The result code for a backend error: `busy` when there was no database connection available,
+/// so that the client can retry.
+pub fn backend_error_code(error: &DomainError, default: LdapResultCode) -> LdapResultCode {
+    if error.is_busy() {
+        LdapResultCode::Busy
+    } else {
+        default
+    }
+}
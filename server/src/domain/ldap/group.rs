@@ -1,59 +1,107 @@
+use std::collections::BTreeMap;
+
+use chrono::TimeZone;
 use ldap3_proto::{
     proto::LdapOp, LdapFilter, LdapPartialAttribute, LdapResultCode, LdapSearchResultEntry,
 };
 use tracing::{debug, instrument, warn};
 
 use crate::domain::{
-    handler::{GroupListerBackendHandler, GroupRequestFilter},
-    ldap::error::LdapError,
-    types::{Group, UserId, Uuid},
+    handler::{
+        GroupListerBackendHandler, GroupRequestFilter, Schema, UserListerBackendHandler,
+        UserRequestFilter,
+    },
+    ldap::error::{backend_error_code, LdapError},
+    types::{AttributeType, Group, GroupId, Serialized, UserId, Uuid},
 };
 
 use super::{
     error::LdapResult,
     utils::{
-        expand_attribute_wildcards, get_group_id_from_distinguished_name,
-        get_user_id_from_distinguished_name, map_group_field, LdapInfo,
+        convert_filter_value, expand_attribute_wildcards, get_group_custom_attribute,
+        get_group_id_from_distinguished_name, get_user_id_from_distinguished_name,
+        handle_unsupported_filter, map_group_field, LdapInfo,
     },
 };
 
+/// Synthesized attribute listing the emails of the members, e.g. for the mailing lists.
+const MEMBER_MAIL_ATTRIBUTE: &str = "membermail";
+const UNRECOGNIZED_ATTRIBUTE_MESSAGE: &str = r#"Ignoring unrecognized group attribute. To disable this warning, add it to "ignored_group_attributes" in the config."#;
+const UNKNOWN_FILTER_ATTRIBUTE_MESSAGE: &str = r#"Ignoring unknown group attribute in filter. To disable this warning, add it to "ignored_group_attributes" in the config."#;
+
 pub fn get_group_attribute(
     group: &Group,
-    base_dn_str: &str,
+    ldap_info: &LdapInfo,
     attribute: &str,
     user_filter: &Option<UserId>,
-    ignored_group_attributes: &[String],
+    schema: &Schema,
+    member_emails: &BTreeMap<UserId, String>,
 ) -> Option<Vec<Vec<u8>>> {
     let attribute = attribute.to_ascii_lowercase();
     let attribute_values = match attribute.as_str() {
-        "objectclass" => vec![b"groupOfUniqueNames".to_vec()],
+        "objectclass" => ldap_info
+            .group_object_classes
+            .iter()
+            .map(|c| c.clone().into_bytes())
+            .collect(),
         // Always returned as part of the base response.
         "dn" | "distinguishedname" => return None,
         "cn" | "uid" | "id" => vec![group.display_name.clone().into_bytes()],
         "entryuuid" | "uuid" => vec![group.uuid.to_string().into_bytes()],
+        "createtimestamp" | "modifytimestamp" => vec![chrono::Utc
+            .from_utc_datetime(&group.creation_date)
+            .to_rfc3339()
+            .into_bytes()],
+        "member" if !ldap_info.group_member_attribute.has_member() => return None,
+        "uniquemember" if !ldap_info.group_member_attribute.has_unique_member() => return None,
         "member" | "uniquemember" => group
             .users
             .iter()
             .filter(|u| user_filter.as_ref().map(|f| *u == f).unwrap_or(true))
-            .map(|u| format!("uid={},ou=people,{}", u, base_dn_str).into_bytes())
+            .map(|u| ldap_info.make_user_dn(u.as_str()).into_bytes())
+            // The nested groups, unless the reader can only see their own memberships.
+            .chain(
+                group
+                    .member_groups
+                    .iter()
+                    .filter(|_| user_filter.is_none())
+                    .map(|g| ldap_info.make_group_dn(&g.display_name).into_bytes()),
+            )
+            .collect(),
+        "memberuid" => group
+            .users
+            .iter()
+            .filter(|u| user_filter.as_ref().map(|f| *u == f).unwrap_or(true))
+            .map(|u| u.to_string().into_bytes())
+            .collect(),
+        "gidnumber" => vec![get_gid_number(group.id, ldap_info).to_string().into_bytes()],
+        MEMBER_MAIL_ATTRIBUTE => group
+            .users
+            .iter()
+            .filter(|u| user_filter.as_ref().map(|f| *u == f).unwrap_or(true))
+            .filter_map(|u| member_emails.get(u))
+            .map(|email| email.clone().into_bytes())
             .collect(),
         "1.1" => return None,
-        // We ignore the operational attribute wildcard
-        "+" => return None,
-        "*" => {
+        "*" | "+" => {
             panic!(
-                "Matched {}, * should have been expanded into attribute list and * removed",
+                "Matched {}, wildcards should have been expanded into attribute list and removed",
                 attribute
             )
         }
+        _ if schema
+            .group_attributes
+            .get_attribute_type(&attribute)
+            .is_some() =>
+        {
+            get_group_custom_attribute(&group.attributes, &attribute, schema)?
+        }
         _ => {
-            if !ignored_group_attributes.contains(&attribute) {
-                warn!(
-                    r#"Ignoring unrecognized group attribute: {}\n\
-                      To disable this warning, add it to "ignored_group_attributes" in the config."#,
-                    attribute
-                );
-            }
+            ldap_info.log_unknown_attribute(
+                &ldap_info.ignored_group_attributes,
+                &attribute,
+                UNRECOGNIZED_ATTRIBUTE_MESSAGE,
+            );
             return None;
         }
     };
@@ -64,40 +112,91 @@ pub fn get_group_attribute(
     }
 }
 
+/// Same as the gidNumber of the users for which the group is the primary group.
+fn get_gid_number(group_id: GroupId, ldap_info: &LdapInfo) -> i64 {
+    ldap_info.posix_options.gid_number_offset as i64 + group_id.0 as i64
+}
+
 const ALL_GROUP_ATTRIBUTE_KEYS: &[&str] = &[
     "objectclass",
     "uid",
     "cn",
     "member",
     "uniquemember",
-    "entryuuid",
+    "gidnumber",
+    "memberuid",
 ];
 
+/// Only returned when explicitly requested, or with the "+" wildcard.
+const ALL_GROUP_OPERATIONAL_ATTRIBUTE_KEYS: &[&str] =
+    &["createtimestamp", "modifytimestamp", "entryuuid"];
+
 fn expand_group_attribute_wildcards(attributes: &[String]) -> Vec<&str> {
-    expand_attribute_wildcards(attributes, ALL_GROUP_ATTRIBUTE_KEYS)
+    expand_attribute_wildcards(
+        attributes,
+        ALL_GROUP_ATTRIBUTE_KEYS,
+        ALL_GROUP_OPERATIONAL_ATTRIBUTE_KEYS,
+    )
+}
+
+/// The group entry for an export of the directory. Only the attributes allowed by the
+/// groupOfUniqueNames object class are included, so that the entry can be imported as is.
+pub fn make_group_export_entry(
+    group: Group,
+    ldap_info: &LdapInfo,
+    schema: &Schema,
+) -> LdapSearchResultEntry {
+    let mut entry = make_ldap_search_group_result_entry(
+        group,
+        ldap_info,
+        &["cn".to_owned(), "uniquemember".to_owned()],
+        &None,
+        schema,
+        &BTreeMap::new(),
+    );
+    // posixGroup is a structural class in RFC 2307, it can't be combined with groupOfUniqueNames.
+    entry.attributes.insert(
+        0,
+        LdapPartialAttribute {
+            atype: "objectclass".to_owned(),
+            vals: vec![b"groupOfUniqueNames".to_vec()],
+        },
+    );
+    entry
 }
 
 fn make_ldap_search_group_result_entry(
     group: Group,
-    base_dn_str: &str,
+    ldap_info: &LdapInfo,
     attributes: &[String],
     user_filter: &Option<UserId>,
-    ignored_group_attributes: &[String],
+    schema: &Schema,
+    member_emails: &BTreeMap<UserId, String>,
 ) -> LdapSearchResultEntry {
-    let expanded_attributes = expand_group_attribute_wildcards(attributes);
+    let mut expanded_attributes = expand_group_attribute_wildcards(attributes);
+    if attributes.iter().any(|a| a == "*") {
+        for attribute in schema
+            .group_attributes
+            .attributes
+            .iter()
+            .filter(|a| !a.is_hardcoded)
+        {
+            if !expanded_attributes
+                .iter()
+                .any(|a| a.eq_ignore_ascii_case(&attribute.name))
+            {
+                expanded_attributes.push(&attribute.name);
+            }
+        }
+    }
 
     LdapSearchResultEntry {
-        dn: format!("cn={},ou=groups,{}", group.display_name, base_dn_str),
+        dn: ldap_info.make_group_dn(&group.display_name),
         attributes: expanded_attributes
             .iter()
             .filter_map(|a| {
-                let values = get_group_attribute(
-                    &group,
-                    base_dn_str,
-                    a,
-                    user_filter,
-                    ignored_group_attributes,
-                )?;
+                let values =
+                    get_group_attribute(&group, ldap_info, a, user_filter, schema, member_emails)?;
                 Some(LdapPartialAttribute {
                     atype: a.to_string(),
                     vals: values,
@@ -107,36 +206,99 @@ fn make_ldap_search_group_result_entry(
     }
 }
 
+fn is_custom_group_attribute(schema: &Schema, name: &str) -> bool {
+    schema
+        .group_attributes
+        .get_attribute_schema(name)
+        .map(|a| !a.is_hardcoded)
+        .unwrap_or(false)
+}
+
+/// Equality filter on a custom group attribute, e.g. `(mail=list@example.com)`.
+fn convert_attribute_equality(schema: &Schema, name: &str, value: &str) -> GroupRequestFilter {
+    let attribute_schema = schema
+        .group_attributes
+        .get_attribute_schema(name)
+        .expect("Checked by is_custom_group_attribute");
+    let attribute_type = (attribute_schema.attribute_type, attribute_schema.is_list);
+    match convert_filter_value(attribute_type, value) {
+        Some(Some(value)) => GroupRequestFilter::AttributeEquality(name.to_owned(), value),
+        Some(None) => {
+            debug!(%name, %value, "Invalid value for the attribute type in filter");
+            GroupRequestFilter::from(false)
+        }
+        None => match attribute_type {
+            (AttributeType::String, false) if !attribute_schema.is_case_sensitive => {
+                GroupRequestFilter::AttributeEqualityIgnoreCase(name.to_owned(), value.to_owned())
+            }
+            (AttributeType::String, false) => {
+                GroupRequestFilter::AttributeEquality(name.to_owned(), Serialized::from(value))
+            }
+            _ => {
+                warn!(%name, "Filters on multi-valued group attributes are not supported");
+                GroupRequestFilter::from(false)
+            }
+        },
+    }
+}
+
 fn convert_group_filter(
     ldap_info: &LdapInfo,
+    schema: &Schema,
     filter: &LdapFilter,
 ) -> LdapResult<GroupRequestFilter> {
-    let rec = |f| convert_group_filter(ldap_info, f);
+    convert_group_filter_node(ldap_info, schema, filter).or_else(|e| match filter {
+        // The errors in the nested filters are already handled.
+        LdapFilter::And(_) | LdapFilter::Or(_) | LdapFilter::Not(_) => Err(e),
+        _ => handle_unsupported_filter(ldap_info, filter, e),
+    })
+}
+
+fn convert_group_filter_node(
+    ldap_info: &LdapInfo,
+    schema: &Schema,
+    filter: &LdapFilter,
+) -> LdapResult<GroupRequestFilter> {
+    let rec = |f| convert_group_filter(ldap_info, schema, f);
     match filter {
         LdapFilter::Equality(field, value) => {
             let field = &field.to_ascii_lowercase();
+            let raw_value = value;
             let value = &value.to_ascii_lowercase();
             match field.as_str() {
                 "member" | "uniquemember" => {
-                    let user_name = get_user_id_from_distinguished_name(
-                        value,
-                        &ldap_info.base_dn,
-                        &ldap_info.base_dn_str,
-                    )?;
-                    Ok(GroupRequestFilter::Member(user_name))
+                    match get_user_id_from_distinguished_name(value, ldap_info) {
+                        Ok(user_name) => Ok(GroupRequestFilter::Member(user_name)),
+                        Err(e) => match get_group_id_from_distinguished_name(value, ldap_info) {
+                            Ok(group_name) => Ok(GroupRequestFilter::MemberGroup(group_name)),
+                            Err(_) => Err(e),
+                        },
+                    }
                 }
-                "objectclass" => Ok(GroupRequestFilter::from(matches!(
-                    value.as_str(),
-                    "groupofuniquenames" | "groupofnames"
-                ))),
+                // The raw value, the user id normalization may preserve the case.
+                "memberuid" => Ok(GroupRequestFilter::Member(UserId::new(raw_value))),
+                "gidnumber" => Ok(value
+                    .parse::<i64>()
+                    .ok()
+                    .and_then(|gid_number| {
+                        i32::try_from(gid_number - ldap_info.posix_options.gid_number_offset as i64)
+                            .ok()
+                    })
+                    .map(|id| GroupRequestFilter::GroupId(GroupId(id)))
+                    .unwrap_or_else(|| GroupRequestFilter::from(false))),
+                "objectclass" => Ok(GroupRequestFilter::from(
+                    ldap_info
+                        .group_object_classes
+                        .iter()
+                        .any(|c| c.eq_ignore_ascii_case(value)),
+                )),
                 "dn" => Ok(get_group_id_from_distinguished_name(
                     value.to_ascii_lowercase().as_str(),
-                    &ldap_info.base_dn,
-                    &ldap_info.base_dn_str,
+                    ldap_info,
                 )
                 .map(GroupRequestFilter::DisplayName)
                 .unwrap_or_else(|_| {
-                    warn!("Invalid dn filter on group: {}", value);
+                    warn!(%value, "Invalid dn filter on group");
                     GroupRequestFilter::from(false)
                 })),
                 _ => match map_group_field(field) {
@@ -147,14 +309,15 @@ fn convert_group_filter(
                             message: format!("Invalid UUID: {:#}", e),
                         })?,
                     )),
+                    _ if is_custom_group_attribute(schema, field) => {
+                        Ok(convert_attribute_equality(schema, field, raw_value))
+                    }
                     _ => {
-                        if !ldap_info.ignored_group_attributes.contains(field) {
-                            warn!(
-                                r#"Ignoring unknown group attribute "{:?}" in filter.\n\
-                                To disable this warning, add it to "ignored_group_attributes" in the config."#,
-                                field
-                            );
-                        }
+                        ldap_info.log_unknown_attribute(
+                            &ldap_info.ignored_group_attributes,
+                            field,
+                            UNKNOWN_FILTER_ATTRIBUTE_MESSAGE,
+                        );
                         Ok(GroupRequestFilter::from(false))
                     }
                 },
@@ -169,10 +332,15 @@ fn convert_group_filter(
         LdapFilter::Not(filter) => Ok(GroupRequestFilter::Not(Box::new(rec(filter)?))),
         LdapFilter::Present(field) => {
             let field = &field.to_ascii_lowercase();
+            if is_custom_group_attribute(schema, field) {
+                return Ok(GroupRequestFilter::AttributePresent(field.clone()));
+            }
             Ok(GroupRequestFilter::from(
                 field == "objectclass"
                     || field == "dn"
                     || field == "distinguishedname"
+                    || field == "gidnumber"
+                    || field == "memberuid"
                     || map_group_field(field).is_some(),
             ))
         }
@@ -182,6 +350,9 @@ fn convert_group_filter(
                 Some("display_name") => Ok(GroupRequestFilter::DisplayNameSubString(
                     substring_filter.clone().into(),
                 )),
+                _ if field == "memberuid" => Ok(GroupRequestFilter::MemberSubString(
+                    substring_filter.clone().into(),
+                )),
                 _ => Err(LdapError {
                     code: LdapResultCode::UnwillingToPerform,
                     message: format!(
@@ -193,7 +364,7 @@ fn convert_group_filter(
         }
         _ => Err(LdapError {
             code: LdapResultCode::UnwillingToPerform,
-            message: format!("Unsupported group filter: {:?}", filter),
+            message: "Unsupported group filter".to_owned(),
         }),
     }
 }
@@ -204,32 +375,77 @@ pub async fn get_groups_list<Backend: GroupListerBackendHandler>(
     ldap_filter: &LdapFilter,
     base: &str,
     backend: &Backend,
+    schema: &Schema,
 ) -> LdapResult<Vec<Group>> {
     debug!(?ldap_filter);
-    let filters = convert_group_filter(ldap_info, ldap_filter)?;
+    let filters = convert_group_filter(ldap_info, schema, ldap_filter)?;
     debug!(?filters);
     backend
         .list_groups(Some(filters))
         .await
         .map_err(|e| LdapError {
-            code: LdapResultCode::Other,
+            code: backend_error_code(&e, LdapResultCode::Other),
             message: format!(r#"Error while listing groups "{}": {:#}"#, base, e),
         })
 }
 
+/// Fetches the emails of the members of the groups, if the member emails were requested.
+#[instrument(skip_all, level = "debug")]
+pub async fn get_member_emails<Backend: UserListerBackendHandler>(
+    groups: &[Group],
+    attributes: &[String],
+    backend: &Backend,
+) -> LdapResult<BTreeMap<UserId, String>> {
+    if !attributes
+        .iter()
+        .any(|a| a.eq_ignore_ascii_case(MEMBER_MAIL_ATTRIBUTE))
+    {
+        return Ok(BTreeMap::new());
+    }
+    let mut members = groups
+        .iter()
+        .flat_map(|g| g.users.iter())
+        .collect::<Vec<_>>();
+    members.sort();
+    members.dedup();
+    if members.is_empty() {
+        return Ok(BTreeMap::new());
+    }
+    let filter = UserRequestFilter::Or(
+        members
+            .into_iter()
+            .cloned()
+            .map(UserRequestFilter::UserId)
+            .collect(),
+    );
+    Ok(backend
+        .list_users(Some(filter), false)
+        .await
+        .map_err(|e| LdapError {
+            code: backend_error_code(&e, LdapResultCode::Other),
+            message: format!("Error while listing the group members: {:#}", e),
+        })?
+        .into_iter()
+        .map(|u| (u.user.user_id, u.user.email))
+        .collect())
+}
+
 pub fn convert_groups_to_ldap_op<'a>(
     groups: Vec<Group>,
     attributes: &'a [String],
     ldap_info: &'a LdapInfo,
     user_filter: &'a Option<UserId>,
+    schema: &'a Schema,
+    member_emails: &'a BTreeMap<UserId, String>,
 ) -> impl Iterator<Item = LdapOp> + 'a {
     groups.into_iter().map(move |g| {
         LdapOp::SearchResultEntry(make_ldap_search_group_result_entry(
             g,
-            &ldap_info.base_dn_str,
+            ldap_info,
             attributes,
             user_filter,
-            &ldap_info.ignored_group_attributes,
+            schema,
+            member_emails,
         ))
     })
 }
@@ -1,42 +1,162 @@
+use std::collections::{BTreeSet, HashMap};
+
 use chrono::TimeZone;
+use itertools::Itertools;
 use ldap3_proto::{
     proto::LdapOp, LdapFilter, LdapPartialAttribute, LdapResultCode, LdapSearchResultEntry,
 };
 use tracing::{debug, instrument, warn};
 
-use crate::domain::{
-    handler::{Schema, UserListerBackendHandler, UserRequestFilter},
-    ldap::{
-        error::{LdapError, LdapResult},
-        utils::{
-            expand_attribute_wildcards, get_custom_attribute, get_group_id_from_distinguished_name,
-            get_user_id_from_distinguished_name, map_user_field, LdapInfo, UserFieldType,
+use crate::{
+    domain::{
+        handler::{
+            GroupListerBackendHandler, Schema, UserListerBackendHandler, UserRequestFilter,
+            UserSortColumn, UserSortKey,
+        },
+        ldap::{
+            error::{backend_error_code, LdapError, LdapResult},
+            utils::{
+                convert_filter_value, expand_attribute_wildcards, get_custom_attribute,
+                get_group_id_from_distinguished_name, get_user_id_from_distinguished_name,
+                handle_unsupported_filter, map_user_field_with_schema, parse_generalized_time,
+                LdapInfo, LdapReader, TemplatePart, UserFieldType,
+            },
+        },
+        types::{
+            AttributeType, Group, GroupDetails, GroupId, User, UserAndGroups, UserColumn, UserId,
+            Uuid,
         },
     },
-    types::{GroupDetails, User, UserAndGroups, UserColumn, UserId},
+    infra::configuration::LdapCnSource,
 };
 
+/// Optional multi-valued attribute with additional emails, returned as extra "mail" values.
+pub const MAIL_ALIASES_ATTRIBUTE: &str = "mail_aliases";
+/// Optional attributes overriding the configured POSIX home directory and login shell.
+const HOME_DIRECTORY_ATTRIBUTE: &str = "home_directory";
+const LOGIN_SHELL_ATTRIBUTE: &str = "login_shell";
+/// Seconds between 1601-01-01, the epoch of the Active Directory timestamps, and 1970-01-01.
+const AD_EPOCH_OFFSET_SECONDS: i64 = 11_644_473_600;
+const UNRECOGNIZED_ATTRIBUTE_MESSAGE: &str = r#"Ignoring unrecognized user attribute. To disable this warning, add it to "ignored_user_attributes" in the config."#;
+const UNKNOWN_FILTER_ATTRIBUTE_MESSAGE: &str = r#"Ignoring unknown user attribute in filter. To disable this warning, add it to "ignored_user_attributes" in the config."#;
+
+/// Returns the first configured source of the cn with a value.
+fn get_user_cn(user: &User, schema: &Schema, ldap_info: &LdapInfo) -> Option<String> {
+    let get_name = |attribute| {
+        get_custom_attribute(&user.attributes, attribute, schema)
+            .and_then(|values| values.into_iter().next())
+            .and_then(|value| String::from_utf8(value).ok())
+    };
+    ldap_info.cn_sources.iter().find_map(|source| {
+        match source {
+            LdapCnSource::DisplayName => user.display_name.clone(),
+            LdapCnSource::FullName => Some(
+                [get_name("first_name"), get_name("last_name")]
+                    .into_iter()
+                    .flatten()
+                    .filter(|name| !name.is_empty())
+                    .join(" "),
+            ),
+            LdapCnSource::UserId => Some(user.user_id.to_string()),
+        }
+        .filter(|name| !name.is_empty())
+    })
+}
+
+/// Returns the values of the attribute, or None if the reader is not allowed to see it.
 pub fn get_user_attribute(
     user: &User,
     attribute: &str,
-    base_dn_str: &str,
     groups: Option<&[GroupDetails]>,
-    ignored_user_attributes: &[String],
     schema: &Schema,
+    ldap_info: &LdapInfo,
+    reader: &LdapReader,
+) -> Option<Vec<Vec<u8>>> {
+    let attribute = ldap_info.resolve_user_attribute(attribute);
+    if attribute == "userpassword" {
+        // Not subject to the access rules: only the configured readers can see it.
+        if !ldap_info.can_read_user_password(reader) {
+            return None;
+        }
+        return user
+            .replicated_password_hash
+            .as_ref()
+            .map(|hash| vec![hash.clone().into_bytes()]);
+    }
+    if !ldap_info.can_read_user_attribute(reader, &user.user_id, &attribute) {
+        return None;
+    }
+    match ldap_info.virtual_user_attributes.get(&attribute) {
+        Some(template) => {
+            get_virtual_user_attribute(template, user, groups, schema, ldap_info, reader)
+        }
+        None => get_concrete_user_attribute(user, attribute, groups, schema, ldap_info),
+    }
+}
+
+/// Evaluates the template of a virtual attribute. The attributes in the template can't be virtual
+/// themselves, and the attribute is absent if one of them has no value or can't be read.
+fn get_virtual_user_attribute(
+    template: &[TemplatePart],
+    user: &User,
+    groups: Option<&[GroupDetails]>,
+    schema: &Schema,
+    ldap_info: &LdapInfo,
+    reader: &LdapReader,
 ) -> Option<Vec<Vec<u8>>> {
-    let attribute = attribute.to_ascii_lowercase();
+    let value = template
+        .iter()
+        .map(|part| match part {
+            TemplatePart::Literal(literal) => Some(literal.clone()),
+            TemplatePart::Attribute(name) => {
+                let attribute = ldap_info.resolve_user_attribute(name);
+                if !ldap_info.can_read_user_attribute(reader, &user.user_id, &attribute) {
+                    return None;
+                }
+                get_concrete_user_attribute(user, attribute, groups, schema, ldap_info)?
+                    .into_iter()
+                    .next()
+                    .and_then(|value| String::from_utf8(value).ok())
+            }
+        })
+        .collect::<Option<String>>()?;
+    Some(vec![value.into_bytes()])
+}
+
+fn get_concrete_user_attribute(
+    user: &User,
+    attribute: String,
+    groups: Option<&[GroupDetails]>,
+    schema: &Schema,
+    ldap_info: &LdapInfo,
+) -> Option<Vec<Vec<u8>>> {
+    let posix_options = &ldap_info.posix_options;
+    let uid_number = posix_options.uid_number_offset as i64 + user.uid_number as i64;
     let attribute_values = match attribute.as_str() {
-        "objectclass" => vec![
-            b"inetOrgPerson".to_vec(),
-            b"posixAccount".to_vec(),
-            b"mailAccount".to_vec(),
-            b"person".to_vec(),
-        ],
+        "objectclass" => ldap_info
+            .user_object_classes
+            .iter()
+            .map(|c| c.clone().into_bytes())
+            .collect(),
         // dn is always returned as part of the base response.
         "dn" | "distinguishedname" => return None,
+        "entrydn" => vec![ldap_info.make_user_dn(user.user_id.as_str()).into_bytes()],
+        "creatorsname" => vec![ldap_info.creators_name.clone().into_bytes()],
+        "hassubordinates" => vec![b"FALSE".to_vec()],
+        // Same as 389-ds and FreeIPA: only set on the disabled accounts.
+        "nsaccountlock" if user.is_enabled => return None,
+        "nsaccountlock" => vec![b"TRUE".to_vec()],
         "uid" | "user_id" | "id" => vec![user.user_id.to_string().into_bytes()],
         "entryuuid" | "uuid" => vec![user.uuid.to_string().into_bytes()],
-        "mail" | "email" => vec![user.email.clone().into_bytes()],
+        "mail" | "email" => {
+            // The primary email comes first, for the clients that only read one value.
+            let mut mails = vec![user.email.clone().into_bytes()];
+            mails.extend(
+                get_custom_attribute(&user.attributes, MAIL_ALIASES_ATTRIBUTE, schema)
+                    .unwrap_or_default(),
+            );
+            mails
+        }
         "givenname" | "first_name" | "firstname" => {
             get_custom_attribute(&user.attributes, "first_name", schema)?
         }
@@ -44,37 +164,119 @@ pub fn get_user_attribute(
             get_custom_attribute(&user.attributes, "last_name", schema)?
         }
         "jpegphoto" | "avatar" => get_custom_attribute(&user.attributes, "avatar", schema)?,
+        "telephonenumber" | "phone" => get_custom_attribute(&user.attributes, "phone", schema)?,
+        "mobile" => get_custom_attribute(&user.attributes, "mobile", schema)?,
+        // The direct memberships, and with `ldap_flatten_nested_groups` the groups containing them
+        // (added by `get_user_list`).
         "memberof" => groups
             .into_iter()
             .flatten()
             .map(|id_and_name| {
-                format!("cn={},ou=groups,{}", &id_and_name.display_name, base_dn_str).into_bytes()
+                ldap_info
+                    .make_group_dn(&id_and_name.display_name)
+                    .into_bytes()
             })
             .collect(),
-        "cn" | "displayname" => vec![user.display_name.clone()?.into_bytes()],
-        "creationdate" | "creation_date" | "createtimestamp" | "modifytimestamp" => {
+        "cn" | "displayname" => vec![get_user_cn(user, schema, ldap_info)?.into_bytes()],
+        "uidnumber" => vec![uid_number.to_string().into_bytes()],
+        "gidnumber" => {
+            // The primary group is the one with the lowest id. Users without groups get a
+            // user-private group with the same number as their uid.
+            let gid_number = groups
+                .into_iter()
+                .flatten()
+                .map(|g| g.group_id.0)
+                .min()
+                .map(|id| posix_options.gid_number_offset as i64 + id as i64)
+                .unwrap_or(uid_number);
+            vec![gid_number.to_string().into_bytes()]
+        }
+        "homedirectory" => get_custom_attribute(&user.attributes, HOME_DIRECTORY_ATTRIBUTE, schema)
+            .unwrap_or_else(|| {
+                vec![posix_options
+                    .home_directory_template
+                    .replace("{uid}", user.user_id.as_str())
+                    .into_bytes()]
+            }),
+        "loginshell" => get_custom_attribute(&user.attributes, LOGIN_SHELL_ATTRIBUTE, schema)
+            .unwrap_or_else(|| vec![posix_options.login_shell.clone().into_bytes()]),
+        "gecos" => vec![get_user_cn(user, schema, ldap_info)?.into_bytes()],
+        "shadowlastchange" => {
+            // Number of days since the epoch.
+            let days = user.password_modified_date?.timestamp() / (24 * 60 * 60);
+            vec![days.to_string().into_bytes()]
+        }
+        "shadowmax" => get_custom_attribute(&user.attributes, "shadow_max", schema)
+            .or_else(|| Some(vec![posix_options.shadow_max?.to_string().into_bytes()]))?,
+        // The expiration date of the account, enforced by LLDAP, comes first.
+        "shadowexpire" => match user.expiration_date {
+            Some(date) => vec![(date.timestamp() / (24 * 60 * 60)).to_string().into_bytes()],
+            None => get_custom_attribute(&user.attributes, "shadow_expire", schema)
+                .or_else(|| Some(vec![posix_options.shadow_expire?.to_string().into_bytes()]))?,
+        },
+        // Active Directory format: 100ns intervals since 1601-01-01. Only returned when
+        // requested, and for the accounts with an expiration date.
+        "accountexpires" => {
+            let intervals =
+                (user.expiration_date?.timestamp() + AD_EPOCH_OFFSET_SECONDS) * 10_000_000;
+            vec![intervals.to_string().into_bytes()]
+        }
+        "creationdate" | "creation_date" | "createtimestamp" => {
             vec![chrono::Utc
                 .from_utc_datetime(&user.creation_date)
                 .to_rfc3339()
                 .into_bytes()]
         }
+        "modifytimestamp" | "modified_date" => {
+            vec![chrono::Utc
+                .from_utc_datetime(&user.modified_date)
+                .to_rfc3339()
+                .into_bytes()]
+        }
+        // Like the password policy overlay of OpenLDAP, but only the last failure is kept.
+        "pwdfailuretime" => {
+            vec![chrono::Utc
+                .from_utc_datetime(&user.authentication_failures.as_ref()?.last_failure_date)
+                .to_rfc3339()
+                .into_bytes()]
+        }
+        // The account is locked by its last failed login.
+        "pwdaccountlockedtime" => match &user.authentication_failures {
+            Some(failures) if failures.locked_until.is_some() => {
+                vec![chrono::Utc
+                    .from_utc_datetime(&failures.last_failure_date)
+                    .to_rfc3339()
+                    .into_bytes()]
+            }
+            _ => return None,
+        },
+        // Not set for the users who never logged in.
+        "authtimestamp" | "last_login" => {
+            vec![chrono::Utc
+                .from_utc_datetime(&user.last_login?)
+                .to_rfc3339()
+                .into_bytes()]
+        }
         "1.1" => return None,
-        // We ignore the operational attribute wildcard.
-        "+" => return None,
-        "*" => {
+        "*" | "+" => {
             panic!(
-                "Matched {}, * should have been expanded into attribute list and * removed",
+                "Matched {}, wildcards should have been expanded into attribute list and removed",
                 attribute
             )
         }
+        _ if schema
+            .user_attributes
+            .get_attribute_type(&attribute)
+            .is_some() =>
+        {
+            get_custom_attribute(&user.attributes, &attribute, schema)?
+        }
         _ => {
-            if !ignored_user_attributes.contains(&attribute) {
-                warn!(
-                    r#"Ignoring unrecognized group attribute: {}\n\
-                      To disable this warning, add it to "ignored_user_attributes" in the config."#,
-                    attribute
-                );
-            }
+            ldap_info.log_unknown_attribute(
+                &ldap_info.ignored_user_attributes,
+                &attribute,
+                UNRECOGNIZED_ATTRIBUTE_MESSAGE,
+            );
             return None;
         }
     };
@@ -93,33 +295,114 @@ const ALL_USER_ATTRIBUTE_KEYS: &[&str] = &[
     "sn",
     "cn",
     "jpegPhoto",
+    "telephonenumber",
+    "mobile",
+    "uidnumber",
+    "gidnumber",
+    "homedirectory",
+    "loginshell",
+    "gecos",
+    "shadowlastchange",
+    "shadowmax",
+    "shadowexpire",
+    // Only for the readers allowed to see it.
+    "userpassword",
+];
+
+/// Only returned when explicitly requested, or with the "+" wildcard.
+const ALL_USER_OPERATIONAL_ATTRIBUTE_KEYS: &[&str] = &[
     "createtimestamp",
+    "modifytimestamp",
     "entryuuid",
+    "entrydn",
+    "creatorsname",
+    "hassubordinates",
+    "nsaccountlock",
+    "authtimestamp",
+    "pwdfailuretime",
+    "pwdaccountlockedtime",
+];
+
+/// Custom attributes that are already exported under a standard LDAP attribute name.
+const STANDARD_ATTRIBUTE_SOURCES: &[&str] = &[
+    MAIL_ALIASES_ATTRIBUTE,
+    HOME_DIRECTORY_ATTRIBUTE,
+    LOGIN_SHELL_ATTRIBUTE,
+    "phone",
+    "mobile",
+    "shadow_max",
+    "shadow_expire",
 ];
 
+/// The optional custom attribute that overrides a computed LDAP attribute, if any.
+pub fn get_overriding_custom_attribute(attribute: &str) -> Option<&'static str> {
+    Some(match attribute {
+        "homedirectory" => HOME_DIRECTORY_ATTRIBUTE,
+        "loginshell" => LOGIN_SHELL_ATTRIBUTE,
+        "shadowmax" => "shadow_max",
+        "shadowexpire" => "shadow_expire",
+        _ => return None,
+    })
+}
+
+/// The user entry with all the non-operational attributes, including the custom ones, for an
+/// export of the directory.
+pub fn make_user_export_entry(
+    user: User,
+    groups: Option<&[GroupDetails]>,
+    schema: &Schema,
+    ldap_info: &LdapInfo,
+) -> LdapSearchResultEntry {
+    let attributes = ALL_USER_ATTRIBUTE_KEYS
+        .iter()
+        .map(|a| a.to_string())
+        .chain(
+            schema
+                .user_attributes
+                .attributes
+                .iter()
+                .filter(|a| {
+                    !a.is_hardcoded && !STANDARD_ATTRIBUTE_SOURCES.contains(&a.name.as_str())
+                })
+                .map(|a| a.name.clone()),
+        )
+        .collect::<Vec<_>>();
+    make_ldap_search_user_result_entry(
+        user,
+        &attributes,
+        groups,
+        schema,
+        ldap_info,
+        &LdapReader::admin(),
+    )
+}
+
 fn make_ldap_search_user_result_entry(
     user: User,
-    base_dn_str: &str,
     attributes: &[String],
     groups: Option<&[GroupDetails]>,
-    ignored_user_attributes: &[String],
     schema: &Schema,
+    ldap_info: &LdapInfo,
+    reader: &LdapReader,
 ) -> LdapSearchResultEntry {
-    let expanded_attributes = expand_user_attribute_wildcards(attributes);
-    let dn = format!("uid={},ou=people,{}", user.user_id.as_str(), base_dn_str);
+    let mut expanded_attributes = expand_user_attribute_wildcards(attributes);
+    if attributes.iter().any(|a| a == "*") {
+        for name in ldap_info.virtual_user_attributes.keys() {
+            if !expanded_attributes
+                .iter()
+                .any(|a| a.eq_ignore_ascii_case(name))
+            {
+                expanded_attributes.push(name);
+            }
+        }
+    }
+    let dn = ldap_info.make_user_dn(user.user_id.as_str());
     LdapSearchResultEntry {
         dn,
         attributes: expanded_attributes
             .iter()
             .filter_map(|a| {
-                let values = get_user_attribute(
-                    &user,
-                    a,
-                    base_dn_str,
-                    groups,
-                    ignored_user_attributes,
-                    schema,
-                )?;
+                let values = get_user_attribute(&user, a, groups, schema, ldap_info, reader)?;
                 Some(LdapPartialAttribute {
                     atype: a.to_string(),
                     vals: values,
@@ -129,8 +412,61 @@ fn make_ldap_search_user_result_entry(
     }
 }
 
-fn convert_user_filter(ldap_info: &LdapInfo, filter: &LdapFilter) -> LdapResult<UserRequestFilter> {
-    let rec = |f| convert_user_filter(ldap_info, f);
+/// Matches the users with the custom attribute set to `value`, or without the attribute but
+/// matching `default_filter`.
+fn custom_attribute_or_default_filter(
+    attribute: &str,
+    value: &str,
+    default_filter: UserRequestFilter,
+) -> UserRequestFilter {
+    UserRequestFilter::Or(vec![
+        UserRequestFilter::AttributeEquality(attribute.to_owned(), value.to_owned()),
+        UserRequestFilter::And(vec![
+            UserRequestFilter::Not(Box::new(UserRequestFilter::AttributePresent(
+                attribute.to_owned(),
+            ))),
+            default_filter,
+        ]),
+    ])
+}
+
+/// Matches the users whose home directory from the template is `value`.
+fn default_home_directory_filter(template: &str, value: &str) -> UserRequestFilter {
+    match template.split_once("{uid}") {
+        None => UserRequestFilter::from(template == value),
+        Some((prefix, suffix)) => value
+            .strip_prefix(prefix)
+            .and_then(|v| v.strip_suffix(suffix))
+            .filter(|uid| template.replace("{uid}", uid) == value)
+            .map(|uid| UserRequestFilter::UserId(UserId::new(uid)))
+            .unwrap_or_else(|| UserRequestFilter::from(false)),
+    }
+}
+
+fn is_virtual_user_attribute(ldap_info: &LdapInfo, attribute: &str) -> bool {
+    ldap_info
+        .virtual_user_attributes
+        .contains_key(&ldap_info.resolve_user_attribute(attribute))
+}
+
+fn convert_user_filter(
+    ldap_info: &LdapInfo,
+    schema: &Schema,
+    filter: &LdapFilter,
+) -> LdapResult<UserRequestFilter> {
+    convert_user_filter_node(ldap_info, schema, filter).or_else(|e| match filter {
+        // The errors in the nested filters are already handled.
+        LdapFilter::And(_) | LdapFilter::Or(_) | LdapFilter::Not(_) => Err(e),
+        _ => handle_unsupported_filter(ldap_info, filter, e),
+    })
+}
+
+fn convert_user_filter_node(
+    ldap_info: &LdapInfo,
+    schema: &Schema,
+    filter: &LdapFilter,
+) -> LdapResult<UserRequestFilter> {
+    let rec = |f| convert_user_filter(ldap_info, schema, f);
     match filter {
         LdapFilter::And(filters) => Ok(UserRequestFilter::And(
             filters.iter().map(rec).collect::<LdapResult<_>>()?,
@@ -139,73 +475,210 @@ fn convert_user_filter(ldap_info: &LdapInfo, filter: &LdapFilter) -> LdapResult<
             filters.iter().map(rec).collect::<LdapResult<_>>()?,
         )),
         LdapFilter::Not(filter) => Ok(UserRequestFilter::Not(Box::new(rec(filter)?))),
+        LdapFilter::Present(field) if is_virtual_user_attribute(ldap_info, field) => {
+            Ok(UserRequestFilter::from(true))
+        }
+        LdapFilter::Equality(field, _)
+        | LdapFilter::Substring(field, _)
+        | LdapFilter::GreaterOrEqual(field, _)
+        | LdapFilter::LessOrEqual(field, _)
+        | LdapFilter::Approx(field, _)
+            if is_virtual_user_attribute(ldap_info, field) =>
+        {
+            warn!(%field, "Filters on virtual attributes are not supported, matching nothing");
+            Ok(UserRequestFilter::from(false))
+        }
         LdapFilter::Equality(field, value) => {
-            let field = &field.to_ascii_lowercase();
+            let field = &ldap_info.resolve_user_attribute(field);
             match field.as_str() {
-                "memberof" => Ok(UserRequestFilter::MemberOf(
-                    get_group_id_from_distinguished_name(
-                        &value.to_ascii_lowercase(),
-                        &ldap_info.base_dn,
-                        &ldap_info.base_dn_str,
-                    )?,
+                "memberof" => match get_group_id_from_distinguished_name(
+                    &value.to_ascii_lowercase(),
+                    ldap_info,
+                ) {
+                    Ok(group) => Ok(UserRequestFilter::MemberOf(group)),
+                    // Not a DN, treat it as a bare group name.
+                    Err(_) if !value.contains('=') => {
+                        Ok(UserRequestFilter::MemberOf(value.clone()))
+                    }
+                    Err(e) => Err(e),
+                },
+                // Every entry is implicitly a "top", the root of the class hierarchy.
+                "objectclass" => Ok(UserRequestFilter::from(
+                    value.eq_ignore_ascii_case("top")
+                        || ldap_info
+                            .user_object_classes
+                            .iter()
+                            .any(|c| c.eq_ignore_ascii_case(value)),
+                )),
+                "uidnumber" => Ok(value
+                    .parse::<i64>()
+                    .ok()
+                    .and_then(|n| {
+                        i32::try_from(n - ldap_info.posix_options.uid_number_offset as i64).ok()
+                    })
+                    .map(UserRequestFilter::UidNumber)
+                    .unwrap_or_else(|| {
+                        warn!(%value, "Invalid uidNumber filter on user");
+                        UserRequestFilter::from(false)
+                    })),
+                "gidnumber" => Ok(value
+                    .parse::<i64>()
+                    .ok()
+                    .map(|n| get_primary_gid_filter(ldap_info, n))
+                    .unwrap_or_else(|| {
+                        warn!(%value, "Invalid gidNumber filter on user");
+                        UserRequestFilter::from(false)
+                    })),
+                "homedirectory" => Ok(custom_attribute_or_default_filter(
+                    HOME_DIRECTORY_ATTRIBUTE,
+                    value,
+                    default_home_directory_filter(
+                        &ldap_info.posix_options.home_directory_template,
+                        value,
+                    ),
+                )),
+                "loginshell" => Ok(custom_attribute_or_default_filter(
+                    LOGIN_SHELL_ATTRIBUTE,
+                    value,
+                    UserRequestFilter::from(value == &ldap_info.posix_options.login_shell),
+                )),
+                "nsaccountlock" => Ok(UserRequestFilter::IsEnabled(
+                    !value.eq_ignore_ascii_case("true"),
                 )),
-                "objectclass" => Ok(UserRequestFilter::from(matches!(
-                    value.to_ascii_lowercase().as_str(),
-                    "person" | "inetorgperson" | "posixaccount" | "mailaccount"
-                ))),
                 "dn" => Ok(get_user_id_from_distinguished_name(
                     value.to_ascii_lowercase().as_str(),
-                    &ldap_info.base_dn,
-                    &ldap_info.base_dn_str,
+                    ldap_info,
                 )
                 .map(UserRequestFilter::UserId)
                 .unwrap_or_else(|_| {
-                    warn!("Invalid dn filter on user: {}", value);
+                    warn!(%value, "Invalid dn filter on user");
                     UserRequestFilter::from(false)
                 })),
-                _ => match map_user_field(field) {
+                _ => match map_user_field_with_schema(field, schema) {
                     UserFieldType::PrimaryField(UserColumn::UserId) => {
                         Ok(UserRequestFilter::UserId(UserId::new(value)))
                     }
+                    UserFieldType::PrimaryField(UserColumn::Uuid) => {
+                        Ok(UserRequestFilter::UuidEquality(
+                            Uuid::try_from(value.as_str()).map_err(|e| LdapError {
+                                code: LdapResultCode::UnwillingToPerform,
+                                message: format!("Invalid UUID: {:#}", e),
+                            })?,
+                        ))
+                    }
+                    UserFieldType::PrimaryField(UserColumn::Email)
+                        if schema
+                            .user_attributes
+                            .get_attribute_type(MAIL_ALIASES_ATTRIBUTE)
+                            .is_some() =>
+                    {
+                        Ok(UserRequestFilter::Or(vec![
+                            UserRequestFilter::Equality(UserColumn::Email, value.clone()),
+                            UserRequestFilter::AttributeListContains(
+                                MAIL_ALIASES_ATTRIBUTE.to_owned(),
+                                value.clone(),
+                            ),
+                        ]))
+                    }
                     UserFieldType::PrimaryField(field) => {
                         Ok(UserRequestFilter::Equality(field, value.clone()))
                     }
-                    UserFieldType::Attribute(field) => Ok(UserRequestFilter::AttributeEquality(
-                        field.to_owned(),
-                        value.clone(),
-                    )),
-                    UserFieldType::NoMatch => {
-                        if !ldap_info.ignored_user_attributes.contains(field) {
-                            warn!(
-                                r#"Ignoring unknown user attribute "{}" in filter.\n\
-                                      To disable this warning, add it to "ignored_user_attributes" in the config"#,
-                                field
-                            );
+                    UserFieldType::Attribute(field) => {
+                        let attribute_schema = schema.user_attributes.get_attribute_schema(&field);
+                        if let Some(converted) = attribute_schema.and_then(|a| {
+                            convert_filter_value((a.attribute_type, a.is_list), value)
+                        }) {
+                            return Ok(match converted {
+                                Some(value) => {
+                                    UserRequestFilter::AttributeValueEquality(field, value)
+                                }
+                                None => {
+                                    debug!(%field, %value, "Invalid value for the attribute type in filter");
+                                    UserRequestFilter::from(false)
+                                }
+                            });
                         }
+                        let ignore_case = attribute_schema
+                            .map(|a| {
+                                a.attribute_type == AttributeType::String
+                                    && !a.is_list
+                                    && !a.is_case_sensitive
+                            })
+                            .unwrap_or(false);
+                        Ok(if ignore_case {
+                            UserRequestFilter::AttributeEqualityIgnoreCase(field, value.clone())
+                        } else {
+                            UserRequestFilter::AttributeEquality(field, value.clone())
+                        })
+                    }
+                    UserFieldType::NoMatch => {
+                        ldap_info.log_unknown_attribute(
+                            &ldap_info.ignored_user_attributes,
+                            field,
+                            UNKNOWN_FILTER_ATTRIBUTE_MESSAGE,
+                        );
                         Ok(UserRequestFilter::from(false))
                     }
                 },
             }
         }
         LdapFilter::Present(field) => {
-            let field = &field.to_ascii_lowercase();
+            let field = &ldap_info.resolve_user_attribute(field);
+            if let UserFieldType::Attribute(name) = map_user_field_with_schema(field, schema) {
+                return Ok(UserRequestFilter::AttributePresent(name));
+            }
+            if field == "nsaccountlock" {
+                return Ok(UserRequestFilter::IsEnabled(false));
+            }
+            if let UserFieldType::PrimaryField(UserColumn::LastLogin) =
+                map_user_field_with_schema(field, schema)
+            {
+                // All the login dates are after the epoch.
+                return Ok(UserRequestFilter::LastLoginAfter(
+                    chrono::NaiveDateTime::default(),
+                ));
+            }
             // Check that it's a field we support.
             Ok(UserRequestFilter::from(
                 field == "objectclass"
                     || field == "dn"
                     || field == "distinguishedname"
-                    || !matches!(map_user_field(field), UserFieldType::NoMatch),
+                    || field == "uidnumber"
+                    || field == "gidnumber"
+                    || field == "homedirectory"
+                    || field == "loginshell"
+                    || field == "gecos"
+                    || field == "shadowlastchange"
+                    || field == "entrydn"
+                    || field == "creatorsname"
+                    || field == "hassubordinates"
+                    || !matches!(
+                        map_user_field_with_schema(field, schema),
+                        UserFieldType::NoMatch
+                    ),
             ))
         }
         LdapFilter::Substring(field, substring_filter) => {
-            let field = &field.to_ascii_lowercase();
-            match map_user_field(field.as_str()) {
+            let field = &ldap_info.resolve_user_attribute(field);
+            match map_user_field_with_schema(field, schema) {
                 UserFieldType::PrimaryField(UserColumn::UserId) => Ok(
                     UserRequestFilter::UserIdSubString(substring_filter.clone().into()),
                 ),
+                // Only single-valued strings can be matched, the other types are serialized.
+                UserFieldType::Attribute(name)
+                    if schema.user_attributes.get_attribute_type(&name)
+                        == Some((AttributeType::String, false)) =>
+                {
+                    Ok(UserRequestFilter::AttributeSubString(
+                        name,
+                        substring_filter.clone().into(),
+                    ))
+                }
                 UserFieldType::NoMatch
                 | UserFieldType::Attribute(_)
                 | UserFieldType::PrimaryField(UserColumn::CreationDate)
+                | UserFieldType::PrimaryField(UserColumn::ModifiedDate)
+                | UserFieldType::PrimaryField(UserColumn::LastLogin)
                 | UserFieldType::PrimaryField(UserColumn::Uuid) => Err(LdapError {
                     code: LdapResultCode::UnwillingToPerform,
                     message: format!(
@@ -219,35 +692,352 @@ fn convert_user_filter(ldap_info: &LdapInfo, filter: &LdapFilter) -> LdapResult<
                 )),
             }
         }
+        LdapFilter::GreaterOrEqual(field, value) | LdapFilter::LessOrEqual(field, value) => {
+            let field = &ldap_info.resolve_user_attribute(field);
+            match map_user_field_with_schema(field, schema) {
+                UserFieldType::PrimaryField(
+                    column @ (UserColumn::CreationDate
+                    | UserColumn::ModifiedDate
+                    | UserColumn::LastLogin),
+                ) => {
+                    let date = parse_generalized_time(value).ok_or_else(|| LdapError {
+                        code: LdapResultCode::UnwillingToPerform,
+                        message: format!("Invalid date for {}: {:?}", field, value),
+                    })?;
+                    let is_after = matches!(filter, LdapFilter::GreaterOrEqual(_, _));
+                    Ok(match (column, is_after) {
+                        (UserColumn::CreationDate, true) => {
+                            UserRequestFilter::CreationDateAfter(date)
+                        }
+                        (UserColumn::CreationDate, false) => {
+                            UserRequestFilter::CreationDateBefore(date)
+                        }
+                        (UserColumn::LastLogin, true) => UserRequestFilter::LastLoginAfter(date),
+                        (UserColumn::LastLogin, false) => UserRequestFilter::LastLoginBefore(date),
+                        (_, true) => UserRequestFilter::ModifiedDateAfter(date),
+                        (_, false) => UserRequestFilter::ModifiedDateBefore(date),
+                    })
+                }
+                UserFieldType::NoMatch => {
+                    ldap_info.log_unknown_attribute(
+                        &ldap_info.ignored_user_attributes,
+                        field,
+                        UNKNOWN_FILTER_ATTRIBUTE_MESSAGE,
+                    );
+                    Ok(UserRequestFilter::from(false))
+                }
+                _ => Err(LdapError {
+                    code: LdapResultCode::UnwillingToPerform,
+                    message: format!(
+                        "Unsupported user attribute for ordering filter: {:?}",
+                        field
+                    ),
+                }),
+            }
+        }
+        LdapFilter::Approx(field, value) => {
+            let field = &ldap_info.resolve_user_attribute(field);
+            match map_user_field_with_schema(field, schema) {
+                UserFieldType::PrimaryField(
+                    column @ (UserColumn::UserId | UserColumn::Email | UserColumn::DisplayName),
+                ) => Ok(UserRequestFilter::ApproxMatch(column, value.clone())),
+                UserFieldType::Attribute(field) => {
+                    // Attribute values are stored serialized, so they can only be compared
+                    // exactly.
+                    debug!(
+                        %field,
+                        "Approximate match on attribute is treated as an equality"
+                    );
+                    Ok(UserRequestFilter::AttributeEquality(field, value.clone()))
+                }
+                UserFieldType::PrimaryField(_) => Err(LdapError {
+                    code: LdapResultCode::UnwillingToPerform,
+                    message: format!(
+                        "Unsupported user attribute for approximate filter: {:?}",
+                        field
+                    ),
+                }),
+                UserFieldType::NoMatch => {
+                    ldap_info.log_unknown_attribute(
+                        &ldap_info.ignored_user_attributes,
+                        field,
+                        UNKNOWN_FILTER_ATTRIBUTE_MESSAGE,
+                    );
+                    Ok(UserRequestFilter::from(false))
+                }
+            }
+        }
+        LdapFilter::Extensible(assertion) => {
+            let field = assertion.type_.as_ref().ok_or_else(|| LdapError {
+                code: LdapResultCode::UnwillingToPerform,
+                message: "Unsupported extensible filter without attribute".to_owned(),
+            })?;
+            // The ":dn" flag is ignored: only the uid is part of the DN, and it's matched by the
+            // equality anyway.
+            let equality = convert_user_filter_node(
+                ldap_info,
+                schema,
+                &LdapFilter::Equality(field.clone(), assertion.match_value.clone()),
+            )?;
+            match assertion
+                .matching_rule
+                .as_deref()
+                .map(str::to_ascii_lowercase)
+                .as_deref()
+            {
+                None => Ok(equality),
+                Some("caseexactmatch" | "2.5.13.5") => Ok(match equality {
+                    UserRequestFilter::AttributeEqualityIgnoreCase(name, value) => {
+                        UserRequestFilter::AttributeEquality(name, value)
+                    }
+                    f => f,
+                }),
+                Some("caseignorematch" | "2.5.13.2") => Ok(match equality {
+                    UserRequestFilter::AttributeEquality(name, value)
+                        if schema.user_attributes.get_attribute_type(&name)
+                            == Some((AttributeType::String, false)) =>
+                    {
+                        UserRequestFilter::AttributeEqualityIgnoreCase(name, value)
+                    }
+                    f => f,
+                }),
+                Some(rule) => Err(LdapError {
+                    code: LdapResultCode::UnwillingToPerform,
+                    message: format!("Unsupported matching rule: {:?}", rule),
+                }),
+            }
+        }
         _ => Err(LdapError {
             code: LdapResultCode::UnwillingToPerform,
-            message: format!("Unsupported user filter: {:?}", filter),
+            message: "Unsupported user filter".to_owned(),
         }),
     }
 }
 
 fn expand_user_attribute_wildcards(attributes: &[String]) -> Vec<&str> {
-    expand_attribute_wildcards(attributes, ALL_USER_ATTRIBUTE_KEYS)
+    expand_attribute_wildcards(
+        attributes,
+        ALL_USER_ATTRIBUTE_KEYS,
+        ALL_USER_OPERATIONAL_ATTRIBUTE_KEYS,
+    )
+}
+
+/// The users whose gidNumber is this one: the members of the group who are not in a group with a
+/// lower id, and the users without groups whose uidNumber is this one.
+fn get_primary_gid_filter(ldap_info: &LdapInfo, gid_number: i64) -> UserRequestFilter {
+    use UserRequestFilter::*;
+    let posix_options = &ldap_info.posix_options;
+    let not_member_below = |group_id| Not(Box::new(MemberOfIdBelow(group_id)));
+    let primary_group = i32::try_from(gid_number - posix_options.gid_number_offset as i64)
+        .ok()
+        .map(|id| And(vec![MemberOfId(GroupId(id)), not_member_below(GroupId(id))]));
+    let user_private_group = i32::try_from(gid_number - posix_options.uid_number_offset as i64)
+        .ok()
+        .map(|uid_number| {
+            And(vec![
+                UidNumber(uid_number),
+                // Below all the group ids: in no group at all.
+                not_member_below(GroupId(i32::MAX)),
+            ])
+        });
+    Or(primary_group
+        .into_iter()
+        .chain(user_private_group)
+        .collect())
+}
+
+/// Follows the group nesting from each group, e.g. to the groups nested in it at any depth.
+fn get_transitive_groups(
+    edges: &HashMap<GroupId, Vec<GroupId>>,
+    group_id: GroupId,
+) -> Vec<GroupId> {
+    let mut result = BTreeSet::new();
+    let mut to_visit = vec![group_id];
+    while let Some(current) = to_visit.pop() {
+        for next in edges.get(&current).into_iter().flatten() {
+            if result.insert(next.0) {
+                to_visit.push(*next);
+            }
+        }
+    }
+    result.into_iter().map(GroupId).collect()
+}
+
+/// The membership of a group also matches the members of the groups nested in it.
+fn expand_nested_member_of(
+    filter: UserRequestFilter,
+    groups: &[Group],
+    member_groups: &HashMap<GroupId, Vec<GroupId>>,
+) -> UserRequestFilter {
+    use UserRequestFilter::*;
+    let rec = |f| expand_nested_member_of(f, groups, member_groups);
+    let with_nested_groups = |filter: UserRequestFilter, group_id: Option<GroupId>| {
+        let nested = group_id
+            .map(|id| get_transitive_groups(member_groups, id))
+            .unwrap_or_default();
+        if nested.is_empty() {
+            filter
+        } else {
+            Or(std::iter::once(filter)
+                .chain(nested.into_iter().map(MemberOfId))
+                .collect())
+        }
+    };
+    match filter {
+        And(filters) => And(filters.into_iter().map(rec).collect()),
+        Or(filters) => Or(filters.into_iter().map(rec).collect()),
+        Not(filter) => Not(Box::new(rec(*filter))),
+        MemberOf(name) => {
+            let group_id = groups
+                .iter()
+                .find(|g| g.display_name.eq_ignore_ascii_case(&name))
+                .map(|g| g.id);
+            with_nested_groups(MemberOf(name), group_id)
+        }
+        MemberOfId(group_id) => with_nested_groups(MemberOfId(group_id), Some(group_id)),
+        // The members of the groups nested in the lower groups are also in the lower groups.
+        MemberOfIdBelow(group_id) => {
+            let nested = groups
+                .iter()
+                .filter(|g| g.id.0 < group_id.0)
+                .flat_map(|g| get_transitive_groups(member_groups, g.id))
+                .unique()
+                .collect::<Vec<_>>();
+            if nested.is_empty() {
+                MemberOfIdBelow(group_id)
+            } else {
+                Or(vec![MemberOfIdBelow(group_id), MemberOfAnyId(nested)])
+            }
+        }
+        MemberOfAnyId(group_ids) => MemberOfAnyId(
+            group_ids
+                .iter()
+                .flat_map(|id| {
+                    std::iter::once(*id).chain(get_transitive_groups(member_groups, *id))
+                })
+                .unique()
+                .collect(),
+        ),
+        filter => filter,
+    }
+}
+
+/// Adds the groups containing the users' groups, at any depth.
+fn add_parent_groups(users: &mut [UserAndGroups], groups: &[Group]) {
+    let mut parent_groups = HashMap::<GroupId, Vec<GroupId>>::new();
+    for group in groups {
+        for member_group in &group.member_groups {
+            parent_groups
+                .entry(member_group.group_id)
+                .or_default()
+                .push(group.id);
+        }
+    }
+    let details = groups
+        .iter()
+        .map(|g| {
+            (
+                g.id,
+                GroupDetails {
+                    group_id: g.id,
+                    display_name: g.display_name.clone(),
+                    creation_date: g.creation_date,
+                    uuid: g.uuid.clone(),
+                },
+            )
+        })
+        .collect::<HashMap<_, _>>();
+    for user_groups in users.iter_mut().filter_map(|u| u.groups.as_mut()) {
+        let direct_groups = user_groups.iter().map(|g| g.group_id).collect::<Vec<_>>();
+        for group_id in direct_groups
+            .iter()
+            .flat_map(|id| get_transitive_groups(&parent_groups, *id))
+            .unique()
+            .filter(|id| !direct_groups.contains(id))
+        {
+            user_groups.extend(details.get(&group_id).cloned());
+        }
+        user_groups.sort_by(|g1, g2| g1.display_name.cmp(&g2.display_name));
+    }
+}
+
+/// The column to sort the users by for an attribute of the server-side sort control, if
+/// supported. "cn" sorts by the display name: the users without one come last.
+pub fn get_user_sort_column(ldap_info: &LdapInfo, attribute: &str) -> Option<UserSortColumn> {
+    match ldap_info.resolve_user_attribute(attribute).as_str() {
+        "uid" | "user_id" | "id" => Some(UserSortColumn::UserId),
+        "cn" | "displayname" | "display_name" => Some(UserSortColumn::DisplayName),
+        "sn" | "last_name" | "lastname" => Some(UserSortColumn::LastName),
+        "mail" | "email" => Some(UserSortColumn::Email),
+        _ => None,
+    }
 }
 
 #[instrument(skip_all, level = "debug")]
-pub async fn get_user_list<Backend: UserListerBackendHandler>(
+#[allow(clippy::too_many_arguments)]
+pub async fn get_user_list<Backend: UserListerBackendHandler + GroupListerBackendHandler>(
     ldap_info: &LdapInfo,
     ldap_filter: &LdapFilter,
     request_groups: bool,
     base: &str,
     backend: &Backend,
+    schema: &Schema,
+    sort: &[UserSortKey],
+    page: Option<(u64, u64)>,
 ) -> LdapResult<Vec<UserAndGroups>> {
-    debug!(?ldap_filter);
-    let filters = convert_user_filter(ldap_info, ldap_filter)?;
+    debug!(?ldap_filter, ?sort);
+    let filters = convert_user_filter(ldap_info, schema, ldap_filter)?;
+    let filters = match &ldap_info.member_of_group {
+        Some(group) => {
+            UserRequestFilter::And(vec![filters, UserRequestFilter::MemberOf(group.clone())])
+        }
+        None => filters,
+    };
+    let filters = if ldap_info.hide_disabled_users {
+        UserRequestFilter::And(vec![filters, UserRequestFilter::IsEnabled(true)])
+    } else {
+        filters
+    };
+    let nested_groups = if ldap_info.flatten_nested_groups {
+        Some(backend.list_groups(None).await.map_err(|e| LdapError {
+            code: backend_error_code(&e, LdapResultCode::Other),
+            message: format!("Error while listing the nested groups: {:#}", e),
+        })?)
+    } else {
+        None
+    };
+    let filters = match &nested_groups {
+        Some(groups) => {
+            let member_groups = groups
+                .iter()
+                .map(|g| (g.id, g.member_groups.iter().map(|m| m.group_id).collect()))
+                .collect();
+            expand_nested_member_of(filters, groups, &member_groups)
+        }
+        None => filters,
+    };
     debug!(?filters);
-    backend
-        .list_users(Some(filters), request_groups)
-        .await
-        .map_err(|e| LdapError {
-            code: LdapResultCode::Other,
-            message: format!(r#"Error while searching user "{}": {:#}"#, base, e),
-        })
+    let mut users = match page {
+        _ if !sort.is_empty() => {
+            backend
+                .list_users_sorted(Some(filters), request_groups, sort.to_vec(), page)
+                .await
+        }
+        Some((offset, limit)) => {
+            backend
+                .list_users_page(Some(filters), request_groups, offset, limit)
+                .await
+        }
+        None => backend.list_users(Some(filters), request_groups).await,
+    }
+    .map_err(|e| LdapError {
+        code: backend_error_code(&e, LdapResultCode::Other),
+        message: format!(r#"Error while searching user "{}": {:#}"#, base, e),
+    })?;
+    if let Some(groups) = &nested_groups {
+        add_parent_groups(&mut users, groups);
+    }
+    Ok(users)
 }
 
 pub fn convert_users_to_ldap_op<'a>(
@@ -255,15 +1045,16 @@ pub fn convert_users_to_ldap_op<'a>(
     attributes: &'a [String],
     ldap_info: &'a LdapInfo,
     schema: &'a Schema,
+    reader: &'a LdapReader,
 ) -> impl Iterator<Item = LdapOp> + 'a {
     users.into_iter().map(move |u| {
         LdapOp::SearchResultEntry(make_ldap_search_user_result_entry(
             u.user,
-            &ldap_info.base_dn_str,
             attributes,
             u.groups.as_deref(),
-            &ldap_info.ignored_user_attributes,
             schema,
+            ldap_info,
+            reader,
         ))
     })
 }
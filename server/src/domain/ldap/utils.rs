@@ -1,12 +1,27 @@
-use chrono::{NaiveDateTime, TimeZone};
+use base64::Engine;
+use chrono::NaiveDateTime;
 use itertools::Itertools;
-use ldap3_proto::{proto::LdapSubstringFilter, LdapResultCode};
+use ldap3_proto::{
+    proto::{LdapFilter, LdapSubstringFilter},
+    LdapResultCode,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::atomic::{AtomicU64, Ordering},
+};
 use tracing::{debug, instrument, warn};
+use url::Url;
 
-use crate::domain::{
-    handler::{Schema, SubStringFilter},
-    ldap::error::{LdapError, LdapResult},
-    types::{AttributeType, AttributeValue, JpegPhoto, UserColumn, UserId},
+use crate::{
+    domain::{
+        handler::{AttributeList, Schema, SubStringFilter},
+        ldap::error::{LdapError, LdapResult},
+        types::{AttributeType, AttributeValue, JpegPhoto, Serialized, UserColumn, UserId},
+    },
+    infra::configuration::{
+        Configuration, LdapCnSource, LdapGroupMemberAttribute, LdapUserRdnAttribute,
+        PasswordPolicyOptions, PosixOptions,
+    },
 };
 
 impl From<LdapSubstringFilter> for SubStringFilter {
@@ -49,6 +64,40 @@ where
     })
 }
 
+/// Parses an LDAP GeneralizedTime ("20240101000000Z", "20240101000000.5+0100"), or an RFC3339
+/// date as we return them for createTimestamp.
+pub fn parse_generalized_time(value: &str) -> Option<NaiveDateTime> {
+    chrono::DateTime::parse_from_str(value, "%Y%m%d%H%M%S%.f%z")
+        .or_else(|_| chrono::DateTime::parse_from_rfc3339(value))
+        .map(|d| d.naive_utc())
+        .ok()
+        .or_else(|| NaiveDateTime::parse_from_str(value.strip_suffix('Z')?, "%Y%m%d%H%M%S%.f").ok())
+}
+
+/// Formats a date as an LDAP GeneralizedTime, in UTC.
+pub fn to_generalized_time(date: &NaiveDateTime) -> String {
+    date.format("%Y%m%d%H%M%SZ").to_string()
+}
+
+/// Parses an LDAP Boolean: "TRUE" or "FALSE", ignoring the case.
+pub fn parse_ldap_boolean(value: &str) -> Option<bool> {
+    if value.eq_ignore_ascii_case("true") {
+        Some(true)
+    } else if value.eq_ignore_ascii_case("false") {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+fn to_ldap_boolean(value: bool) -> Vec<u8> {
+    if value {
+        b"TRUE".to_vec()
+    } else {
+        b"FALSE".to_vec()
+    }
+}
+
 pub fn parse_distinguished_name(dn: &str) -> LdapResult<Vec<(String, String)>> {
     assert!(dn == dn.to_ascii_lowercase());
     dn.split(',')
@@ -58,29 +107,26 @@ pub fn parse_distinguished_name(dn: &str) -> LdapResult<Vec<(String, String)>> {
 
 fn get_id_from_distinguished_name(
     dn: &str,
-    base_tree: &[(String, String)],
-    base_dn_str: &str,
-    is_group: bool,
+    ldap_info: &LdapInfo,
+    ou: &(String, String),
 ) -> LdapResult<String> {
     let parts = parse_distinguished_name(dn)?;
     {
-        let ou = if is_group { "groups" } else { "people" };
-        if !is_subtree(&parts, base_tree) {
+        if !is_subtree(&parts, &ldap_info.base_dn) {
             Err("Not a subtree of the base tree".to_string())
-        } else if parts.len() == base_tree.len() + 2 {
-            if parts[1].0 != "ou" || parts[1].1 != ou || (parts[0].0 != "cn" && parts[0].0 != "uid")
-            {
-                Err(format!(
-                    r#"Unexpected DN format. Got "{}", expected: "uid=id,ou={},{}""#,
-                    dn, ou, base_dn_str
-                ))
-            } else {
-                Ok(parts[0].1.to_string())
-            }
+        } else if parts.len() == ldap_info.base_dn.len() + 2
+            && &parts[1] == ou
+            && (parts[0].0 == "cn" || parts[0].0 == "uid")
+        {
+            Ok(parts[0].1.to_string())
         } else {
             Err(format!(
-                r#"Unexpected DN format. Got "{}", expected: "uid=id,ou={},{}""#,
-                dn, ou, base_dn_str
+                r#"Unexpected DN format. Got "{}", expected: "{}=id,{}={},{}""#,
+                dn,
+                ldap_info.user_rdn_attribute.as_str(),
+                ou.0,
+                ou.1,
+                ldap_info.base_dn_str
             ))
         }
     }
@@ -90,26 +136,41 @@ fn get_id_from_distinguished_name(
     })
 }
 
-pub fn get_user_id_from_distinguished_name(
-    dn: &str,
-    base_tree: &[(String, String)],
-    base_dn_str: &str,
-) -> LdapResult<UserId> {
-    get_id_from_distinguished_name(dn, base_tree, base_dn_str, false).map(UserId::from)
+pub fn get_user_id_from_distinguished_name(dn: &str, ldap_info: &LdapInfo) -> LdapResult<UserId> {
+    get_id_from_distinguished_name(dn, ldap_info, &ldap_info.people_ou).map(UserId::from)
+}
+
+/// Binds can also use the email of the user, either bare ("user@example.com") or as a DN
+/// ("mail=user@example.com,<people ou>,<base dn>").
+pub fn get_email_from_bind_dn(dn: &str, ldap_info: &LdapInfo) -> Option<String> {
+    if !dn.contains('=') {
+        return dn.contains('@').then(|| dn.to_owned());
+    }
+    let parts = parse_distinguished_name(dn).ok()?;
+    (is_subtree(&parts, &ldap_info.base_dn)
+        && parts.len() == ldap_info.base_dn.len() + 2
+        && parts[1] == ldap_info.people_ou
+        && parts[0].0 == "mail")
+        .then(|| parts[0].1.clone())
+}
+
+pub fn get_group_id_from_distinguished_name(dn: &str, ldap_info: &LdapInfo) -> LdapResult<String> {
+    get_id_from_distinguished_name(dn, ldap_info, &ldap_info.groups_ou)
 }
 
-pub fn get_group_id_from_distinguished_name(
+/// The API tokens can bind as "uid=<token name>,ou=tokens,<base dn>".
+pub fn get_api_token_name_from_distinguished_name(
     dn: &str,
-    base_tree: &[(String, String)],
-    base_dn_str: &str,
+    ldap_info: &LdapInfo,
 ) -> LdapResult<String> {
-    get_id_from_distinguished_name(dn, base_tree, base_dn_str, true)
+    get_id_from_distinguished_name(dn, ldap_info, &("ou".to_string(), "tokens".to_string()))
 }
 
 #[instrument(skip_all, level = "debug")]
 pub fn expand_attribute_wildcards<'a>(
     ldap_attributes: &'a [String],
     all_attribute_keys: &'a [&'static str],
+    all_operational_attribute_keys: &'a [&'static str],
 ) -> Vec<&'a str> {
     let mut attributes_out = ldap_attributes
         .iter()
@@ -122,6 +183,11 @@ pub fn expand_attribute_wildcards<'a>(
         // Splice in all non-operational attributes
         attributes_out.extend(all_attribute_keys.iter());
     }
+    if attributes_out.iter().any(|&x| x == "+") {
+        // Replace '+' with the operational attributes, which are only returned on request.
+        attributes_out.retain(|&x| x != "+");
+        attributes_out.extend(all_operational_attribute_keys.iter());
+    }
 
     // Deduplicate, preserving order
     let resolved_attributes = attributes_out
@@ -132,6 +198,129 @@ pub fn expand_attribute_wildcards<'a>(
     resolved_attributes
 }
 
+fn escape_filter_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '*' | '(' | ')' | '\\' | '\0' => escaped.push_str(&format!("\\{:02x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Renders the filter in the string representation of RFC 4515, e.g. "(&(uid=bob)(mail=*))".
+pub fn format_ldap_filter(filter: &LdapFilter) -> String {
+    let join = |filters: &[LdapFilter]| filters.iter().map(format_ldap_filter).join("");
+    match filter {
+        LdapFilter::And(filters) => format!("(&{})", join(filters)),
+        LdapFilter::Or(filters) => format!("(|{})", join(filters)),
+        LdapFilter::Not(filter) => format!("(!{})", format_ldap_filter(filter)),
+        LdapFilter::Equality(attribute, value) => {
+            format!("({}={})", attribute, escape_filter_value(value))
+        }
+        LdapFilter::Substring(attribute, substring) => format!(
+            "({}={}*{}{})",
+            attribute,
+            escape_filter_value(substring.initial.as_deref().unwrap_or_default()),
+            substring
+                .any
+                .iter()
+                .map(|part| format!("{}*", escape_filter_value(part)))
+                .join(""),
+            escape_filter_value(substring.final_.as_deref().unwrap_or_default()),
+        ),
+        LdapFilter::GreaterOrEqual(attribute, value) => {
+            format!("({}>={})", attribute, escape_filter_value(value))
+        }
+        LdapFilter::LessOrEqual(attribute, value) => {
+            format!("({}<={})", attribute, escape_filter_value(value))
+        }
+        LdapFilter::Present(attribute) => format!("({}=*)", attribute),
+        LdapFilter::Approx(attribute, value) => {
+            format!("({}~={})", attribute, escape_filter_value(value))
+        }
+        LdapFilter::Extensible(assertion) => format!(
+            "({}{}{}:={})",
+            assertion.type_.as_deref().unwrap_or_default(),
+            if assertion.dn_attributes { ":dn" } else { "" },
+            assertion
+                .matching_rule
+                .as_deref()
+                .map(|rule| format!(":{}", rule))
+                .unwrap_or_default(),
+            escape_filter_value(&assertion.match_value),
+        ),
+    }
+}
+
+/// The name of the filter type in RFC 4511, and its index in UNSUPPORTED_FILTER_COUNTS.
+fn get_filter_type(filter: &LdapFilter) -> (usize, &'static str) {
+    match filter {
+        LdapFilter::And(_) => (0, "and"),
+        LdapFilter::Or(_) => (1, "or"),
+        LdapFilter::Not(_) => (2, "not"),
+        LdapFilter::Equality(_, _) => (3, "equalityMatch"),
+        LdapFilter::Substring(_, _) => (4, "substrings"),
+        LdapFilter::GreaterOrEqual(_, _) => (5, "greaterOrEqual"),
+        LdapFilter::LessOrEqual(_, _) => (6, "lessOrEqual"),
+        LdapFilter::Present(_) => (7, "present"),
+        LdapFilter::Approx(_, _) => (8, "approxMatch"),
+        LdapFilter::Extensible(_) => (9, "extensibleMatch"),
+    }
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_UNSUPPORTED_FILTER: AtomicU64 = AtomicU64::new(0);
+/// Number of unsupported filters since the start, by filter type.
+static UNSUPPORTED_FILTER_COUNTS: [AtomicU64; 10] = [NO_UNSUPPORTED_FILTER; 10];
+
+/// Handles a filter (not a combination of filters) that can't be converted: it's logged and
+/// counted, and either the search fails with the filter in the message, or the filter matches
+/// nothing if the unsupported filters are ignored.
+pub fn handle_unsupported_filter<T: From<bool>>(
+    ldap_info: &LdapInfo,
+    filter: &LdapFilter,
+    error: LdapError,
+) -> LdapResult<T> {
+    let rendered = format_ldap_filter(filter);
+    let (index, filter_type) = get_filter_type(filter);
+    let count = UNSUPPORTED_FILTER_COUNTS[index].fetch_add(1, Ordering::Relaxed) + 1;
+    warn!(
+        filter = %rendered,
+        filter_type,
+        count,
+        error = %error.message,
+        "Unsupported LDAP filter"
+    );
+    if ldap_info.ignore_unsupported_filters {
+        return Ok(T::from(false));
+    }
+    Err(LdapError {
+        code: error.code,
+        message: format!("{} in {}", error.message, rendered),
+    })
+}
+
+/// Whether the attribute is in the list, case-insensitively. The listed attributes can contain
+/// "*" wildcards, e.g. "apple-*".
+fn is_ignored_attribute(ignored_attributes: &[String], attribute: &str) -> bool {
+    ignored_attributes.iter().any(|ignored| {
+        if !ignored.contains('*') {
+            return ignored.eq_ignore_ascii_case(attribute);
+        }
+        let mut parts = ignored.split('*').map(str::to_owned).collect::<Vec<_>>();
+        let final_ = parts.pop();
+        let initial = parts.remove(0);
+        SubStringFilter {
+            initial: Some(initial),
+            any: parts,
+            final_,
+        }
+        .matches(attribute)
+    })
+}
+
 pub fn is_subtree(subtree: &[(String, String)], base_tree: &[(String, String)]) -> bool {
     for (k, v) in subtree {
         assert!(k == &k.to_ascii_lowercase());
@@ -156,7 +345,7 @@ pub fn is_subtree(subtree: &[(String, String)], base_tree: &[(String, String)])
 pub enum UserFieldType {
     NoMatch,
     PrimaryField(UserColumn),
-    Attribute(&'static str),
+    Attribute(String),
 }
 
 pub fn map_user_field(field: &str) -> UserFieldType {
@@ -167,17 +356,35 @@ pub fn map_user_field(field: &str) -> UserFieldType {
         "cn" | "displayname" | "display_name" => {
             UserFieldType::PrimaryField(UserColumn::DisplayName)
         }
-        "givenname" | "first_name" | "firstname" => UserFieldType::Attribute("first_name"),
-        "sn" | "last_name" | "lastname" => UserFieldType::Attribute("last_name"),
-        "avatar" | "jpegphoto" => UserFieldType::Attribute("avatar"),
-        "creationdate" | "createtimestamp" | "modifytimestamp" | "creation_date" => {
+        "givenname" | "first_name" | "firstname" => {
+            UserFieldType::Attribute("first_name".to_owned())
+        }
+        "sn" | "last_name" | "lastname" => UserFieldType::Attribute("last_name".to_owned()),
+        "avatar" | "jpegphoto" => UserFieldType::Attribute("avatar".to_owned()),
+        "telephonenumber" | "phone" => UserFieldType::Attribute("phone".to_owned()),
+        "mobile" => UserFieldType::Attribute("mobile".to_owned()),
+        "creationdate" | "createtimestamp" | "creation_date" => {
             UserFieldType::PrimaryField(UserColumn::CreationDate)
         }
+        "modifytimestamp" | "modified_date" => {
+            UserFieldType::PrimaryField(UserColumn::ModifiedDate)
+        }
+        "authtimestamp" | "last_login" => UserFieldType::PrimaryField(UserColumn::LastLogin),
         "entryuuid" | "uuid" => UserFieldType::PrimaryField(UserColumn::Uuid),
         _ => UserFieldType::NoMatch,
     }
 }
 
+/// Same as map_user_field, but also recognizes the custom attributes declared in the schema.
+pub fn map_user_field_with_schema(field: &str, schema: &Schema) -> UserFieldType {
+    match map_user_field(field) {
+        UserFieldType::NoMatch if schema.user_attributes.get_attribute_type(field).is_some() => {
+            UserFieldType::Attribute(field.to_owned())
+        }
+        field_type => field_type,
+    }
+}
+
 pub fn map_group_field(field: &str) -> Option<&'static str> {
     assert!(field == field.to_ascii_lowercase());
     Some(match field {
@@ -188,11 +395,460 @@ pub fn map_group_field(field: &str) -> Option<&'static str> {
     })
 }
 
+#[derive(Clone, Copy, Debug)]
+pub struct SearchLimits {
+    /// Maximum number of entries per page of paged results.
+    pub max_page_size: u32,
+    /// Maximum number of entries returned by a search, 0 for no limit.
+    pub size_limit: u32,
+}
+
+fn default_group_object_classes(member_attribute: LdapGroupMemberAttribute) -> Vec<String> {
+    let mut object_classes = vec![];
+    if member_attribute.has_unique_member() {
+        object_classes.push("groupOfUniqueNames".to_owned());
+    }
+    if member_attribute.has_member() {
+        object_classes.push("groupOfNames".to_owned());
+    }
+    object_classes.push("posixGroup".to_owned());
+    object_classes
+}
+
+/// Names of the same user attribute, so that an access rule on one of them covers them all.
+const USER_ATTRIBUTE_SYNONYMS: &[&[&str]] = &[
+    &["uid", "user_id", "id"],
+    &["entryuuid", "uuid"],
+    &["mail", "email"],
+    &["givenname", "first_name", "firstname"],
+    &["sn", "last_name", "lastname"],
+    &["jpegphoto", "avatar"],
+    &["telephonenumber", "phone"],
+    &["cn", "displayname", "gecos"],
+    &["homedirectory", "home_directory"],
+    &["loginshell", "login_shell"],
+    &["shadowmax", "shadow_max"],
+    &["shadowexpire", "shadow_expire"],
+    &["creationdate", "creation_date", "createtimestamp"],
+    &["modifytimestamp", "modified_date"],
+    &["authtimestamp", "last_login"],
+];
+
+/// The identity reading the LDAP entries, checked against the attribute access rules.
+#[derive(Clone, Debug, Default)]
+pub struct LdapReader {
+    /// None for the API tokens and the anonymous searches.
+    pub user_id: Option<UserId>,
+    pub is_admin: bool,
+    /// Display names of the reader's groups.
+    pub groups: Vec<String>,
+}
+
+impl LdapReader {
+    pub fn admin() -> Self {
+        Self {
+            is_admin: true,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct LdapInfo {
     pub base_dn: Vec<(String, String)>,
     pub base_dn_str: String,
+    /// Lowercase RDN of the users' organizational unit, e.g. ("ou", "people").
+    pub people_ou: (String, String),
+    /// Lowercase RDN of the groups' organizational unit, e.g. ("ou", "groups").
+    pub groups_ou: (String, String),
+    pub user_rdn_attribute: LdapUserRdnAttribute,
+    pub group_member_attribute: LdapGroupMemberAttribute,
+    pub user_object_classes: Vec<String>,
+    pub group_object_classes: Vec<String>,
     pub ignored_user_attributes: Vec<String>,
     pub ignored_group_attributes: Vec<String>,
+    pub posix_options: PosixOptions,
+    /// DN of the admin user, returned as the creatorsName of all the entries.
+    pub creators_name: String,
+    /// Lowercase alias -> lowercase user attribute.
+    pub user_attribute_aliases: HashMap<String, String>,
+    /// Lowercase name -> template of the read-only user attributes computed from the others.
+    pub virtual_user_attributes: BTreeMap<String, Vec<TemplatePart>>,
+    /// Lowercase user attribute -> readers allowed besides the admins, "self" or group names.
+    pub user_attribute_access: HashMap<String, Vec<String>>,
+    /// Users that can read the `userPassword` of the others, if the replication is configured.
+    pub user_password_readers: Vec<UserId>,
+    pub search_limits: SearchLimits,
+    /// Sources of the users' cn, the first one with a value is used.
+    pub cn_sources: Vec<LdapCnSource>,
+    pub password_policy: PasswordPolicyOptions,
+    /// Refuse the binds on unencrypted connections.
+    pub require_tls_for_bind: bool,
+    pub anonymous_bind: bool,
+    /// Lowercase attributes that the anonymous searches can filter on and return.
+    pub anonymous_attributes: Vec<String>,
+    /// Only the members of this group are visible in this naming context.
+    pub member_of_group: Option<String>,
+    pub hide_disabled_users: bool,
+    /// Resolve the memberOf of the users through the nested groups.
+    pub flatten_nested_groups: bool,
+    /// Additional base DNs, each restricted to the members of a group.
+    pub naming_contexts: Vec<LdapInfo>,
+    /// If set, the only users that get the admin rights when they bind, if they are admins.
+    pub admin_bind_users: Option<Vec<UserId>>,
+    /// Base DNs held by other servers, with their URL.
+    pub referrals: Vec<(Vec<(String, String)>, Url)>,
+    /// Replace the filters that can't be converted by a filter matching nothing, instead of
+    /// failing the search.
+    pub ignore_unsupported_filters: bool,
+    /// Log the unknown attributes at the debug level rather than as warnings.
+    pub quiet_unknown_attributes: bool,
+    /// Return the DNs with the case of the search base or of the bind DN.
+    pub preserve_dn_case: bool,
+}
+
+impl LdapInfo {
+    pub fn new(config: &Configuration) -> Self {
+        let mut ldap_info = Self {
+            naming_contexts: config
+                .ldap_naming_contexts
+                .iter()
+                .map(|context| {
+                    Self::for_base_dn(config, &context.base_dn, Some(context.group.clone()))
+                })
+                .collect(),
+            referrals: config
+                .ldap_referrals
+                .iter()
+                .map(|referral| {
+                    let base_dn = parse_distinguished_name(&referral.base_dn.to_ascii_lowercase())
+                        .unwrap_or_else(|_| {
+                            panic!("Invalid base DN in ldap_referrals: {}", referral.base_dn)
+                        });
+                    (base_dn, referral.url.clone())
+                })
+                .collect(),
+            ..Self::for_base_dn(config, &config.ldap_base_dn, None)
+        };
+        if !config.ldap_admin_bind_dns.is_empty() {
+            let admin_bind_users = std::iter::once(UserId::new(&config.ldap_user_dn))
+                .chain(config.ldap_admin_bind_dns.iter().map(|dn| {
+                    get_user_id_from_distinguished_name(&dn.to_ascii_lowercase(), &ldap_info)
+                        .unwrap_or_else(|e| panic!("Invalid DN in ldap_admin_bind_dns: {}", e))
+                }))
+                .collect();
+            ldap_info.admin_bind_users = Some(admin_bind_users);
+        }
+        ldap_info
+    }
+
+    /// Logs an unknown attribute, unless it is ignored: as a warning, or at the debug level if
+    /// the unknown attributes are quiet.
+    pub fn log_unknown_attribute(
+        &self,
+        ignored_attributes: &[String],
+        attribute: &str,
+        message: &str,
+    ) {
+        if is_ignored_attribute(ignored_attributes, attribute) {
+            return;
+        }
+        if self.quiet_unknown_attributes {
+            debug!(%attribute, "{}", message);
+        } else {
+            warn!(%attribute, "{}", message);
+        }
+    }
+
+    /// Whether the user can get the admin rights by binding.
+    pub fn is_admin_bind_allowed(&self, user_id: &UserId) -> bool {
+        self.admin_bind_users
+            .as_ref()
+            .map_or(true, |users| users.contains(user_id))
+    }
+
+    fn for_base_dn(config: &Configuration, base_dn: &str, member_of_group: Option<String>) -> Self {
+        let ldap_base_dn = base_dn.to_ascii_lowercase();
+        let user_attribute_aliases: HashMap<String, String> = config
+            .ldap_attribute_aliases
+            .iter()
+            .map(|a| {
+                (
+                    a.alias.to_ascii_lowercase(),
+                    a.attribute.to_ascii_lowercase(),
+                )
+            })
+            .collect();
+        let user_attribute_access = config
+            .ldap_attribute_access
+            .iter()
+            .flat_map(|rule| {
+                let attribute = rule.attribute.to_ascii_lowercase();
+                let attribute = user_attribute_aliases
+                    .get(&attribute)
+                    .cloned()
+                    .unwrap_or(attribute);
+                let names = USER_ATTRIBUTE_SYNONYMS
+                    .iter()
+                    .find(|names| names.contains(&attribute.as_str()))
+                    .map(|names| names.iter().map(|n| n.to_string()).collect())
+                    .unwrap_or_else(|| vec![attribute]);
+                names
+                    .into_iter()
+                    .map(move |name| (name, rule.readers.clone()))
+            })
+            .collect();
+        Self {
+            base_dn: parse_distinguished_name(&ldap_base_dn)
+                .unwrap_or_else(|_| panic!("Invalid base DN in configuration: {}", ldap_base_dn)),
+            creators_name: format!(
+                "{}={},ou={},{}",
+                config.ldap_user_rdn_attribute.as_str(),
+                config.ldap_user_dn,
+                config.ldap_people_ou.to_ascii_lowercase(),
+                ldap_base_dn
+            ),
+            base_dn_str: ldap_base_dn,
+            people_ou: ("ou".to_owned(), config.ldap_people_ou.to_ascii_lowercase()),
+            groups_ou: ("ou".to_owned(), config.ldap_groups_ou.to_ascii_lowercase()),
+            user_rdn_attribute: config.ldap_user_rdn_attribute,
+            group_member_attribute: config.ldap_group_member_attribute,
+            user_object_classes: config.ldap_user_object_classes.clone(),
+            group_object_classes: if config.ldap_group_object_classes.is_empty() {
+                default_group_object_classes(config.ldap_group_member_attribute)
+            } else {
+                config.ldap_group_object_classes.clone()
+            },
+            ignored_user_attributes: config.ignored_user_attributes.clone(),
+            ignored_group_attributes: config.ignored_group_attributes.clone(),
+            posix_options: config.posix_options.clone(),
+            user_attribute_aliases,
+            virtual_user_attributes: config
+                .ldap_virtual_attributes
+                .iter()
+                .map(|a| {
+                    (
+                        a.name.to_ascii_lowercase(),
+                        parse_attribute_template(&a.template).unwrap_or_else(|e| {
+                            panic!("Invalid template for virtual attribute {}: {}", a.name, e)
+                        }),
+                    )
+                })
+                .collect(),
+            user_attribute_access,
+            user_password_readers: if config.ldap_password_replication.format.is_some() {
+                config
+                    .ldap_password_replication
+                    .readers
+                    .iter()
+                    .map(|r| UserId::new(r))
+                    .collect()
+            } else {
+                Vec::new()
+            },
+            search_limits: SearchLimits {
+                max_page_size: config.ldap_max_page_size,
+                size_limit: config.ldap_search_size_limit,
+            },
+            cn_sources: config.ldap_cn_sources.clone(),
+            password_policy: config.password_policy.clone(),
+            require_tls_for_bind: config.ldaps_options.require_tls_for_bind,
+            anonymous_bind: config.ldap_anonymous_bind,
+            anonymous_attributes: config
+                .ldap_anonymous_attributes
+                .iter()
+                .map(|a| a.to_ascii_lowercase())
+                .collect(),
+            member_of_group,
+            hide_disabled_users: config.ldap_hide_disabled_users,
+            flatten_nested_groups: config.ldap_flatten_nested_groups,
+            ignore_unsupported_filters: config.ldap_ignore_unsupported_filters,
+            quiet_unknown_attributes: config.quiet_unknown_attributes,
+            preserve_dn_case: config.ldap_preserve_dn_case,
+            naming_contexts: Vec::new(),
+            admin_bind_users: None,
+            referrals: Vec::new(),
+        }
+    }
+
+    /// Returns the naming context that contains the DN: the one with the longest matching base
+    /// DN, or the main one.
+    pub fn context_for(&self, dn: &str) -> &LdapInfo {
+        let dn_parts = match parse_distinguished_name(&dn.to_ascii_lowercase()) {
+            Ok(parts) => parts,
+            Err(_) => return self,
+        };
+        std::iter::once(self)
+            .chain(self.naming_contexts.iter())
+            .filter(|context| is_subtree(&dn_parts, &context.base_dn))
+            .max_by_key(|context| context.base_dn.len())
+            .unwrap_or(self)
+    }
+
+    /// The referral URL for the DN, if it is under one of the configured referrals rather than
+    /// under a naming context (the deepest base DN wins).
+    pub fn referral_for(&self, dn: &str) -> Option<String> {
+        let dn_parts = parse_distinguished_name(&dn.to_ascii_lowercase()).ok()?;
+        let context_depth = std::iter::once(self)
+            .chain(self.naming_contexts.iter())
+            .filter(|context| is_subtree(&dn_parts, &context.base_dn))
+            .map(|context| context.base_dn.len())
+            .max();
+        let (base_dn, url) = self
+            .referrals
+            .iter()
+            .filter(|(base_dn, _)| is_subtree(&dn_parts, base_dn))
+            .max_by_key(|(base_dn, _)| base_dn.len())?;
+        if context_depth.map_or(false, |depth| depth >= base_dn.len()) {
+            return None;
+        }
+        let mut url = url.clone();
+        url.set_path(dn);
+        Some(url.to_string())
+    }
+
+    /// The base DNs of all the naming contexts, starting with the main one.
+    pub fn all_base_dns(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.base_dn_str.as_str())
+            .chain(self.naming_contexts.iter().map(|c| c.base_dn_str.as_str()))
+    }
+
+    /// DN of the users' organizational unit, e.g. "ou=people,dc=example,dc=com".
+    pub fn people_dn(&self) -> String {
+        format!("ou={},{}", self.people_ou.1, self.base_dn_str)
+    }
+
+    /// DN of the groups' organizational unit, e.g. "ou=groups,dc=example,dc=com".
+    pub fn groups_dn(&self) -> String {
+        format!("ou={},{}", self.groups_ou.1, self.base_dn_str)
+    }
+
+    pub fn make_user_dn(&self, user_id: &str) -> String {
+        format!(
+            "{}={},{}",
+            self.user_rdn_attribute.as_str(),
+            user_id,
+            self.people_dn()
+        )
+    }
+
+    pub fn make_group_dn(&self, display_name: &str) -> String {
+        format!("cn={},{}", display_name, self.groups_dn())
+    }
+
+    /// Whether the reader can see the attribute (lowercase, with the aliases resolved) of the user.
+    pub fn can_read_user_attribute(
+        &self,
+        reader: &LdapReader,
+        user_id: &UserId,
+        attribute: &str,
+    ) -> bool {
+        if reader.is_admin {
+            return true;
+        }
+        match self.user_attribute_access.get(attribute) {
+            None => true,
+            Some(readers) => readers.iter().any(|r| {
+                if r == "self" {
+                    reader.user_id.as_ref() == Some(user_id)
+                } else {
+                    reader.groups.contains(r)
+                }
+            }),
+        }
+    }
+
+    /// Whether the reader can see the `userPassword` of the users. The admins can't, unless they
+    /// are listed.
+    pub fn can_read_user_password(&self, reader: &LdapReader) -> bool {
+        reader.user_id.as_ref().map_or(false, |user_id| {
+            self.user_password_readers.contains(user_id)
+        })
+    }
+
+    /// Lowercases the user attribute name, and resolves the configured aliases.
+    pub fn resolve_user_attribute(&self, attribute: &str) -> String {
+        let attribute = attribute.to_ascii_lowercase();
+        self.user_attribute_aliases
+            .get(&attribute)
+            .cloned()
+            .unwrap_or(attribute)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TemplatePart {
+    Literal(String),
+    /// Lowercase name of an attribute, replaced by its first value.
+    Attribute(String),
+}
+
+/// Parses the template of a virtual attribute, e.g. "{sn}, {givenname}". "{{" and "}}" are
+/// literal braces.
+pub fn parse_attribute_template(template: &str) -> Result<Vec<TemplatePart>, String> {
+    let mut parts = Vec::new();
+    let mut literal = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '{' => {
+                let mut name = String::new();
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    if c == '}' {
+                        closed = true;
+                        break;
+                    }
+                    name.push(c);
+                }
+                if !closed {
+                    return Err(format!("Unclosed '{{{}'", name));
+                }
+                let name = name.trim();
+                if name.is_empty() || name.contains('{') {
+                    return Err(format!("Invalid attribute name: '{{{}}}'", name));
+                }
+                if !literal.is_empty() {
+                    parts.push(TemplatePart::Literal(std::mem::take(&mut literal)));
+                }
+                parts.push(TemplatePart::Attribute(name.to_ascii_lowercase()));
+            }
+            '}' => return Err("Unexpected '}'".to_owned()),
+            c => literal.push(c),
+        }
+    }
+    if !literal.is_empty() {
+        parts.push(TemplatePart::Literal(literal));
+    }
+    Ok(parts)
+}
+
+/// Returns the raw JPEG bytes of a photo that may have been stored as a base64 string, with or
+/// without a `data:image/...;base64,` prefix. Raw binary is returned unchanged.
+pub fn decode_jpeg_photo(photo: Vec<u8>) -> Vec<u8> {
+    // All JPEG files start with the SOI marker.
+    if photo.starts_with(&[0xFF, 0xD8]) {
+        return photo;
+    }
+    let encoded = match photo.strip_prefix(b"data:") {
+        Some(data_url) => match data_url.windows(8).position(|w| w == b";base64,") {
+            Some(position) => &data_url[position + 8..],
+            None => return photo,
+        },
+        None => photo.as_slice(),
+    };
+    match base64::engine::general_purpose::STANDARD.decode(encoded) {
+        Ok(decoded) => decoded,
+        Err(_) => photo,
+    }
 }
 
 pub fn get_custom_attribute(
@@ -200,14 +856,25 @@ pub fn get_custom_attribute(
     attribute_name: &str,
     schema: &Schema,
 ) -> Option<Vec<Vec<u8>>> {
-    let convert_date = |date| {
-        chrono::Utc
-            .from_utc_datetime(&date)
-            .to_rfc3339()
-            .into_bytes()
-    };
-    schema
-        .user_attributes
+    get_attribute_values(attributes, attribute_name, &schema.user_attributes)
+}
+
+/// Same as `get_custom_attribute`, for the custom attributes of the groups.
+pub fn get_group_custom_attribute(
+    attributes: &[AttributeValue],
+    attribute_name: &str,
+    schema: &Schema,
+) -> Option<Vec<Vec<u8>>> {
+    get_attribute_values(attributes, attribute_name, &schema.group_attributes)
+}
+
+fn get_attribute_values(
+    attributes: &[AttributeValue],
+    attribute_name: &str,
+    attribute_list: &AttributeList,
+) -> Option<Vec<Vec<u8>>> {
+    let convert_date = |date| to_generalized_time(&date).into_bytes();
+    attribute_list
         .get_attribute_type(attribute_name)
         .and_then(|attribute_type| {
             attributes
@@ -222,11 +889,16 @@ pub fn get_custom_attribute(
                         vec![attribute.value.unwrap::<i64>().to_string().into_bytes()]
                     }
                     (AttributeType::JpegPhoto, false) => {
-                        vec![attribute.value.unwrap::<JpegPhoto>().into_bytes()]
+                        vec![decode_jpeg_photo(
+                            attribute.value.unwrap::<JpegPhoto>().into_bytes(),
+                        )]
                     }
                     (AttributeType::DateTime, false) => {
                         vec![convert_date(attribute.value.unwrap::<NaiveDateTime>())]
                     }
+                    (AttributeType::Boolean, false) => {
+                        vec![to_ldap_boolean(attribute.value.unwrap::<bool>())]
+                    }
                     (AttributeType::String, true) => attribute
                         .value
                         .unwrap::<Vec<String>>()
@@ -245,6 +917,7 @@ pub fn get_custom_attribute(
                         .unwrap::<Vec<JpegPhoto>>()
                         .into_iter()
                         .map(JpegPhoto::into_bytes)
+                        .map(decode_jpeg_photo)
                         .collect(),
                     (AttributeType::DateTime, true) => attribute
                         .value
@@ -252,6 +925,389 @@ pub fn get_custom_attribute(
                         .into_iter()
                         .map(convert_date)
                         .collect(),
+                    (AttributeType::Boolean, true) => attribute
+                        .value
+                        .unwrap::<Vec<bool>>()
+                        .into_iter()
+                        .map(to_ldap_boolean)
+                        .collect(),
                 })
         })
 }
+
+/// Inverse of `get_custom_attribute`: converts LDAP values to the serialized attribute value.
+pub fn convert_custom_attribute_values(
+    attribute_name: &str,
+    values: Vec<Vec<u8>>,
+    schema: &Schema,
+) -> LdapResult<Serialized> {
+    convert_attribute_values(attribute_name, values, &schema.user_attributes)
+}
+
+/// Same as `convert_custom_attribute_values`, for the custom attributes of the groups.
+pub fn convert_group_custom_attribute_values(
+    attribute_name: &str,
+    values: Vec<Vec<u8>>,
+    schema: &Schema,
+) -> LdapResult<Serialized> {
+    convert_attribute_values(attribute_name, values, &schema.group_attributes)
+}
+
+/// Converts the values of an attribute of the list, see `convert_custom_attribute_values`.
+pub fn convert_attribute_values(
+    attribute_name: &str,
+    mut values: Vec<Vec<u8>>,
+    attribute_list: &AttributeList,
+) -> LdapResult<Serialized> {
+    let invalid_value = |e: String| LdapError {
+        code: LdapResultCode::InvalidAttributeSyntax,
+        message: format!("Invalid value for attribute {}: {}", attribute_name, e),
+    };
+    let to_string = |v: Vec<u8>| String::from_utf8(v).map_err(|e| invalid_value(e.to_string()));
+    let to_integer = |v: Vec<u8>| {
+        to_string(v)?
+            .parse::<i64>()
+            .map_err(|e| invalid_value(e.to_string()))
+    };
+    let to_photo = |v: Vec<u8>| JpegPhoto::try_from(v).map_err(|e| invalid_value(e.to_string()));
+    let to_date = |v: Vec<u8>| {
+        let value = to_string(v)?;
+        parse_generalized_time(&value).ok_or_else(|| invalid_value(value))
+    };
+    let to_boolean = |v: Vec<u8>| {
+        let value = to_string(v)?;
+        parse_ldap_boolean(&value).ok_or_else(|| invalid_value(value))
+    };
+    let attribute_schema = attribute_list
+        .get_attribute_schema(attribute_name)
+        .ok_or_else(|| LdapError {
+            code: LdapResultCode::UnwillingToPerform,
+            message: format!("Unknown attribute: {}", attribute_name),
+        })?;
+    let to_allowed_string = |v: Vec<u8>| {
+        let value = to_string(v)?;
+        match &attribute_schema.allowed_values {
+            Some(allowed_values) if !allowed_values.contains(&value) => Err(LdapError {
+                code: LdapResultCode::ConstraintViolation,
+                message: format!(
+                    "Value '{}' is not allowed for attribute {}, expected one of: {}",
+                    value,
+                    attribute_name,
+                    allowed_values.join(", ")
+                ),
+            }),
+            _ => Ok(value),
+        }
+    };
+    let attribute_type = (attribute_schema.attribute_type, attribute_schema.is_list);
+    if !attribute_type.1 && values.len() != 1 {
+        return Err(LdapError {
+            code: LdapResultCode::ConstraintViolation,
+            message: format!("Expected a single value for attribute {}", attribute_name),
+        });
+    }
+    Ok(match attribute_type {
+        (AttributeType::String, false) => Serialized::from(&to_allowed_string(values.remove(0))?),
+        (AttributeType::Integer, false) => Serialized::from(&to_integer(values.remove(0))?),
+        (AttributeType::JpegPhoto, false) => Serialized::from(&to_photo(values.remove(0))?),
+        (AttributeType::DateTime, false) => Serialized::from(&to_date(values.remove(0))?),
+        (AttributeType::Boolean, false) => Serialized::from(&to_boolean(values.remove(0))?),
+        (AttributeType::String, true) => Serialized::from(
+            &values
+                .into_iter()
+                .map(to_allowed_string)
+                .collect::<LdapResult<Vec<_>>>()?,
+        ),
+        (AttributeType::Integer, true) => Serialized::from(
+            &values
+                .into_iter()
+                .map(to_integer)
+                .collect::<LdapResult<Vec<_>>>()?,
+        ),
+        (AttributeType::JpegPhoto, true) => Serialized::from(
+            &values
+                .into_iter()
+                .map(to_photo)
+                .collect::<LdapResult<Vec<_>>>()?,
+        ),
+        (AttributeType::DateTime, true) => Serialized::from(
+            &values
+                .into_iter()
+                .map(to_date)
+                .collect::<LdapResult<Vec<_>>>()?,
+        ),
+        (AttributeType::Boolean, true) => Serialized::from(
+            &values
+                .into_iter()
+                .map(to_boolean)
+                .collect::<LdapResult<Vec<_>>>()?,
+        ),
+    })
+}
+
+/// Converts an equality filter value to the serialized value of a single-valued, non-string
+/// attribute, to compare it with the stored values. Returns None for the string attributes, and
+/// Some(None) if the value is not valid for the attribute type.
+pub fn convert_filter_value(
+    attribute_type: (AttributeType, bool),
+    value: &str,
+) -> Option<Option<Serialized>> {
+    match attribute_type {
+        (AttributeType::Integer, false) => Some(
+            value
+                .trim()
+                .parse::<i64>()
+                .ok()
+                .map(|i| Serialized::from(&i)),
+        ),
+        (AttributeType::Boolean, false) => {
+            Some(parse_ldap_boolean(value.trim()).map(|b| Serialized::from(&b)))
+        }
+        (AttributeType::DateTime, false) => {
+            Some(parse_generalized_time(value.trim()).map(|d| Serialized::from(&d)))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::handler::{AttributeList, AttributeSchema};
+
+    #[test]
+    fn test_is_ignored_attribute() {
+        let ignored = vec![
+            "sAMAccountName".to_owned(),
+            "apple-*".to_owned(),
+            "*-ms-*-id".to_owned(),
+        ];
+        assert!(is_ignored_attribute(&ignored, "samaccountname"));
+        assert!(is_ignored_attribute(&ignored, "apple-generateduid"));
+        assert!(is_ignored_attribute(&ignored, "Apple-"));
+        assert!(is_ignored_attribute(&ignored, "x-ms-ds-id"));
+        assert!(!is_ignored_attribute(&ignored, "samaccountnames"));
+        assert!(!is_ignored_attribute(&ignored, "not-apple-uid"));
+        assert!(!is_ignored_attribute(&ignored, "x-ms-ds-ids"));
+    }
+
+    #[test]
+    fn test_format_ldap_filter() {
+        use ldap3_proto::proto::LdapMatchingRuleAssertion;
+        assert_eq!(
+            format_ldap_filter(&LdapFilter::And(vec![
+                LdapFilter::Equality("uid".to_owned(), "bob (*)".to_owned()),
+                LdapFilter::Not(Box::new(LdapFilter::Present("mail".to_owned()))),
+                LdapFilter::Or(vec![
+                    LdapFilter::Substring(
+                        "cn".to_owned(),
+                        LdapSubstringFilter {
+                            initial: Some("a".to_owned()),
+                            any: vec!["b".to_owned(), "c".to_owned()],
+                            final_: None,
+                        },
+                    ),
+                    LdapFilter::GreaterOrEqual("createTimestamp".to_owned(), "2023".to_owned()),
+                    LdapFilter::Extensible(LdapMatchingRuleAssertion {
+                        matching_rule: Some("caseExactMatch".to_owned()),
+                        type_: Some("cn".to_owned()),
+                        match_value: "Bob".to_owned(),
+                        dn_attributes: true,
+                    }),
+                ]),
+            ])),
+            r"(&(uid=bob \28\2a\29)(!(mail=*))(|(cn=a*b*c*)(createTimestamp>=2023)(cn:dn:caseExactMatch:=Bob)))"
+        );
+    }
+
+    #[test]
+    fn test_decode_jpeg_photo() {
+        let raw = JpegPhoto::for_tests().into_bytes();
+        assert_eq!(decode_jpeg_photo(raw.clone()), raw);
+        let encoded = base64::engine::general_purpose::STANDARD.encode(&raw);
+        assert_eq!(decode_jpeg_photo(encoded.clone().into_bytes()), raw);
+        let data_url = format!("data:image/jpeg;base64,{}", encoded);
+        assert_eq!(decode_jpeg_photo(data_url.into_bytes()), raw);
+        // Not base64, returned as is.
+        assert_eq!(decode_jpeg_photo(b"not a photo!".to_vec()), b"not a photo!");
+    }
+
+    fn get_typed_schema() -> Schema {
+        let attribute = |name: &str, attribute_type| AttributeSchema {
+            name: name.to_owned(),
+            attribute_type,
+            is_list: false,
+            is_visible: true,
+            is_editable: true,
+            is_hardcoded: false,
+            is_case_sensitive: false,
+            allowed_values: None,
+        };
+        Schema {
+            user_attributes: AttributeList {
+                attributes: vec![
+                    attribute("employee_id", AttributeType::Integer),
+                    attribute("account_enabled", AttributeType::Boolean),
+                    attribute("hire_date", AttributeType::DateTime),
+                ],
+            },
+            group_attributes: AttributeList {
+                attributes: Vec::new(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_typed_custom_attributes_round_trip() {
+        let schema = get_typed_schema();
+        let convert = |name: &str, value: &str| {
+            convert_custom_attribute_values(name, vec![value.as_bytes().to_vec()], &schema)
+        };
+        let render = |name: &str, value: Serialized| {
+            get_custom_attribute(
+                &[AttributeValue {
+                    name: name.to_owned(),
+                    value,
+                }],
+                name,
+                &schema,
+            )
+            .unwrap()
+        };
+        let enabled = convert("account_enabled", "true").unwrap();
+        assert_eq!(enabled, Serialized::from(&true));
+        assert_eq!(render("account_enabled", enabled), vec![b"TRUE".to_vec()]);
+        let hire_date = convert("hire_date", "20230101120000Z").unwrap();
+        assert_eq!(
+            render("hire_date", hire_date),
+            vec![b"20230101120000Z".to_vec()]
+        );
+        let employee_id = convert("employee_id", "42").unwrap();
+        assert_eq!(render("employee_id", employee_id), vec![b"42".to_vec()]);
+
+        assert!(convert("account_enabled", "yes").is_err());
+        assert!(convert("employee_id", "forty-two").is_err());
+        assert!(convert("hire_date", "yesterday").is_err());
+    }
+
+    #[test]
+    fn test_convert_allowed_values() {
+        let schema = Schema {
+            user_attributes: AttributeList {
+                attributes: vec![AttributeSchema {
+                    name: "department".to_owned(),
+                    attribute_type: AttributeType::String,
+                    is_list: true,
+                    is_visible: true,
+                    is_editable: true,
+                    is_hardcoded: false,
+                    is_case_sensitive: false,
+                    allowed_values: Some(vec!["Sales".to_owned(), "R&D".to_owned()]),
+                }],
+            },
+            group_attributes: AttributeList {
+                attributes: Vec::new(),
+            },
+        };
+        let convert = |values: &[&str]| {
+            convert_custom_attribute_values(
+                "department",
+                values.iter().map(|v| v.as_bytes().to_vec()).collect(),
+                &schema,
+            )
+        };
+        assert_eq!(
+            convert(&["Sales", "R&D"]).unwrap(),
+            Serialized::from(&vec!["Sales".to_owned(), "R&D".to_owned()])
+        );
+        let error = convert(&["Sales", "Sails"]).unwrap_err();
+        assert_eq!(error.code, LdapResultCode::ConstraintViolation);
+        assert_eq!(
+            error.message,
+            "Value 'Sails' is not allowed for attribute department, expected one of: Sales, R&D"
+        );
+    }
+
+    #[test]
+    fn test_parse_attribute_template() {
+        assert_eq!(
+            parse_attribute_template("SMTP:{Mail}"),
+            Ok(vec![
+                TemplatePart::Literal("SMTP:".to_owned()),
+                TemplatePart::Attribute("mail".to_owned()),
+            ])
+        );
+        assert_eq!(
+            parse_attribute_template("{sn}, {givenname} {{x}}"),
+            Ok(vec![
+                TemplatePart::Attribute("sn".to_owned()),
+                TemplatePart::Literal(", ".to_owned()),
+                TemplatePart::Attribute("givenname".to_owned()),
+                TemplatePart::Literal(" {x}".to_owned()),
+            ])
+        );
+        assert!(parse_attribute_template("{mail").is_err());
+        assert!(parse_attribute_template("mail}").is_err());
+        assert!(parse_attribute_template("{}").is_err());
+    }
+
+    #[test]
+    fn test_convert_filter_value() {
+        assert_eq!(
+            convert_filter_value((AttributeType::Boolean, false), "FALSE"),
+            Some(Some(Serialized::from(&false)))
+        );
+        assert_eq!(
+            convert_filter_value((AttributeType::Integer, false), " 42"),
+            Some(Some(Serialized::from(&42i64)))
+        );
+        assert_eq!(
+            convert_filter_value((AttributeType::Integer, false), "abc"),
+            Some(None)
+        );
+        assert_eq!(
+            convert_filter_value((AttributeType::String, false), "abc"),
+            None
+        );
+        assert_eq!(
+            convert_filter_value((AttributeType::Integer, true), "42"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_can_read_user_attribute() {
+        use crate::infra::configuration::{
+            ConfigurationBuilder, LdapAttributeAccess, LdapAttributeAlias,
+        };
+        let ldap_info = LdapInfo::new(&Configuration {
+            ldap_attribute_aliases: vec![LdapAttributeAlias {
+                alias: "photo".to_owned(),
+                attribute: "jpegphoto".to_owned(),
+            }],
+            ldap_attribute_access: vec![LdapAttributeAccess {
+                attribute: "Photo".to_owned(),
+                readers: vec!["self".to_owned(), "hr".to_owned()],
+            }],
+            ..ConfigurationBuilder::for_tests()
+        });
+        let bob = UserId::new("bob");
+        let reader = |user_id: Option<&str>, groups: &[&str]| LdapReader {
+            user_id: user_id.map(UserId::new),
+            is_admin: false,
+            groups: groups.iter().map(|g| g.to_string()).collect(),
+        };
+        // All the names of the attribute are restricted.
+        for attribute in ["jpegphoto", "avatar"] {
+            assert!(!ldap_info.can_read_user_attribute(
+                &reader(Some("alice"), &[]),
+                &bob,
+                attribute
+            ));
+            assert!(ldap_info.can_read_user_attribute(&reader(Some("bob"), &[]), &bob, attribute));
+            assert!(ldap_info.can_read_user_attribute(&reader(None, &["hr"]), &bob, attribute));
+            assert!(ldap_info.can_read_user_attribute(&LdapReader::admin(), &bob, attribute));
+        }
+        assert!(ldap_info.can_read_user_attribute(&reader(None, &[]), &bob, "mail"));
+    }
+}
@@ -0,0 +1,18 @@
+use crate::domain::{error::Result, types::UserId};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
+pub struct UserLockout {
+    pub user_id: UserId,
+    pub failure_count: i32,
+    pub locked_until: chrono::NaiveDateTime,
+}
+
+#[async_trait]
+pub trait LockoutHandler: Send + Sync {
+    /// Lists the accounts that are currently locked.
+    async fn list_user_lockouts(&self) -> Result<Vec<UserLockout>>;
+    /// Unlocks the account, and forgets the previous failed logins.
+    async fn clear_user_lockout(&self, user_id: &UserId) -> Result<()>;
+}
@@ -1,13 +1,29 @@
+pub mod api_token_handler;
+pub mod audit_log_handler;
+pub mod dynamic_groups;
 pub mod error;
 pub mod handler;
+pub mod imported_password;
 pub mod ldap;
+pub mod lockout_handler;
 pub mod model;
 pub mod opaque_handler;
+pub mod passkey_handler;
+pub mod session_handler;
+pub mod sql_api_token_handler;
+pub mod sql_attribute_index;
+pub mod sql_audit_log_handler;
 pub mod sql_backend_handler;
 pub mod sql_group_backend_handler;
+pub mod sql_lockout_handler;
 pub mod sql_migrations;
 pub mod sql_opaque_handler;
+pub mod sql_passkey_handler;
 pub mod sql_schema_backend_handler;
+pub mod sql_session_handler;
 pub mod sql_tables;
+pub mod sql_totp_handler;
 pub mod sql_user_backend_handler;
+pub mod totp;
+pub mod totp_handler;
 pub mod types;
@@ -0,0 +1,49 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::domain::types::{GroupId, Serialized};
+
+/// One row per value of the string attributes, derived from `group_attributes` for the filters.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
+#[sea_orm(table_name = "group_attribute_index")]
+pub struct Model {
+    #[sea_orm(primary_key, column_name = "group_attribute_index_id")]
+    pub id: i32,
+    #[sea_orm(column_name = "group_attribute_index_group_id")]
+    pub group_id: GroupId,
+    #[sea_orm(column_name = "group_attribute_index_name")]
+    pub attribute_name: String,
+    /// The serialized string, compared as binary for the case-sensitive matches.
+    #[sea_orm(column_name = "group_attribute_index_value")]
+    pub value: Serialized,
+    #[sea_orm(column_name = "group_attribute_index_lowercase_value")]
+    pub lowercase_value: String,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::groups::Entity",
+        from = "Column::GroupId",
+        to = "super::groups::Column::GroupId",
+        on_update = "Cascade",
+        on_delete = "Cascade"
+    )]
+    Groups,
+    #[sea_orm(
+        belongs_to = "super::group_attribute_schema::Entity",
+        from = "Column::AttributeName",
+        to = "super::group_attribute_schema::Column::AttributeName",
+        on_update = "Cascade",
+        on_delete = "Cascade"
+    )]
+    GroupAttributeSchema,
+}
+
+impl Related<super::Group> for Entity {
+    fn to() -> RelationDef {
+        Relation::Groups.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -22,6 +22,8 @@ pub struct Model {
     pub is_group_editable: bool,
     #[sea_orm(column_name = "group_attribute_schema_is_hardcoded")]
     pub is_hardcoded: bool,
+    #[sea_orm(column_name = "group_attribute_schema_is_case_sensitive")]
+    pub is_case_sensitive: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -47,6 +49,8 @@ impl From<Model> for AttributeSchema {
             is_visible: value.is_group_visible,
             is_editable: value.is_group_editable,
             is_hardcoded: value.is_hardcoded,
+            is_case_sensitive: value.is_case_sensitive,
+            allowed_values: None,
         }
     }
 }
@@ -13,6 +13,8 @@ pub struct Model {
     pub display_name: String,
     pub creation_date: chrono::NaiveDateTime,
     pub uuid: Uuid,
+    /// The serialized `UserRequestFilter` of a dynamic group.
+    pub dynamic_filter: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -37,6 +39,12 @@ impl From<Model> for crate::domain::types::Group {
             creation_date: group.creation_date,
             uuid: group.uuid,
             users: vec![],
+            attributes: vec![],
+            dynamic_filter: group
+                .dynamic_filter
+                .as_deref()
+                .and_then(crate::domain::dynamic_groups::parse_dynamic_filter),
+            member_groups: vec![],
         }
     }
 }
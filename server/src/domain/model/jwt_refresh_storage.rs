@@ -12,6 +12,10 @@ pub struct Model {
     pub refresh_token_hash: i64,
     pub user_id: UserId,
     pub expiry_date: chrono::NaiveDateTime,
+    pub session_id: String,
+    pub session_creation_date: chrono::NaiveDateTime,
+    pub creation_date: chrono::NaiveDateTime,
+    pub replaced: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -2,16 +2,24 @@
 
 pub mod prelude;
 
+pub mod api_tokens;
+pub mod audit_log;
+pub mod group_memberships;
 pub mod groups;
 pub mod jwt_refresh_storage;
 pub mod jwt_storage;
 pub mod memberships;
+pub mod passkeys;
+pub mod password_history;
 pub mod password_reset_tokens;
+pub mod user_lockouts;
 pub mod users;
 
+pub mod user_attribute_index;
 pub mod user_attribute_schema;
 pub mod user_attributes;
 
+pub mod group_attribute_index;
 pub mod group_attribute_schema;
 pub mod group_attributes;
 
@@ -1,9 +1,17 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.10.3
 
+pub use super::api_tokens::Column as ApiTokensColumn;
+pub use super::api_tokens::Entity as ApiTokens;
+pub use super::audit_log::Column as AuditLogColumn;
+pub use super::audit_log::Entity as AuditLog;
+pub use super::group_attribute_index::Column as GroupAttributeIndexColumn;
+pub use super::group_attribute_index::Entity as GroupAttributeIndex;
 pub use super::group_attribute_schema::Column as GroupAttributeSchemaColumn;
 pub use super::group_attribute_schema::Entity as GroupAttributeSchema;
 pub use super::group_attributes::Column as GroupAttributesColumn;
 pub use super::group_attributes::Entity as GroupAttributes;
+pub use super::group_memberships::Column as GroupMembershipColumn;
+pub use super::group_memberships::Entity as GroupMembership;
 pub use super::groups::Column as GroupColumn;
 pub use super::groups::Entity as Group;
 pub use super::jwt_refresh_storage::Column as JwtRefreshStorageColumn;
@@ -12,11 +20,19 @@ pub use super::jwt_storage::Column as JwtStorageColumn;
 pub use super::jwt_storage::Entity as JwtStorage;
 pub use super::memberships::Column as MembershipColumn;
 pub use super::memberships::Entity as Membership;
+pub use super::passkeys::Column as PasskeysColumn;
+pub use super::passkeys::Entity as Passkeys;
+pub use super::password_history::Column as PasswordHistoryColumn;
+pub use super::password_history::Entity as PasswordHistory;
 pub use super::password_reset_tokens::Column as PasswordResetTokensColumn;
 pub use super::password_reset_tokens::Entity as PasswordResetTokens;
+pub use super::user_attribute_index::Column as UserAttributeIndexColumn;
+pub use super::user_attribute_index::Entity as UserAttributeIndex;
 pub use super::user_attribute_schema::Column as UserAttributeSchemaColumn;
 pub use super::user_attribute_schema::Entity as UserAttributeSchema;
 pub use super::user_attributes::Column as UserAttributesColumn;
 pub use super::user_attributes::Entity as UserAttributes;
+pub use super::user_lockouts::Column as UserLockoutsColumn;
+pub use super::user_lockouts::Entity as UserLockouts;
 pub use super::users::Column as UserColumn;
 pub use super::users::Entity as User;
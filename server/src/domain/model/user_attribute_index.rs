@@ -0,0 +1,49 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::domain::types::{Serialized, UserId};
+
+/// One row per value of the string attributes, derived from `user_attributes` for the filters.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
+#[sea_orm(table_name = "user_attribute_index")]
+pub struct Model {
+    #[sea_orm(primary_key, column_name = "user_attribute_index_id")]
+    pub id: i32,
+    #[sea_orm(column_name = "user_attribute_index_user_id")]
+    pub user_id: UserId,
+    #[sea_orm(column_name = "user_attribute_index_name")]
+    pub attribute_name: String,
+    /// The serialized string, compared as binary for the case-sensitive matches.
+    #[sea_orm(column_name = "user_attribute_index_value")]
+    pub value: Serialized,
+    #[sea_orm(column_name = "user_attribute_index_lowercase_value")]
+    pub lowercase_value: String,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::users::Entity",
+        from = "Column::UserId",
+        to = "super::users::Column::UserId",
+        on_update = "Cascade",
+        on_delete = "Cascade"
+    )]
+    Users,
+    #[sea_orm(
+        belongs_to = "super::user_attribute_schema::Entity",
+        from = "Column::AttributeName",
+        to = "super::user_attribute_schema::Column::AttributeName",
+        on_update = "Cascade",
+        on_delete = "Cascade"
+    )]
+    UserAttributeSchema,
+}
+
+impl Related<super::User> for Entity {
+    fn to() -> RelationDef {
+        Relation::Users.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
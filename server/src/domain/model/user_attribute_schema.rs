@@ -22,6 +22,11 @@ pub struct Model {
     pub is_user_editable: bool,
     #[sea_orm(column_name = "user_attribute_schema_is_hardcoded")]
     pub is_hardcoded: bool,
+    #[sea_orm(column_name = "user_attribute_schema_is_case_sensitive")]
+    pub is_case_sensitive: bool,
+    /// JSON list of the allowed values, if restricted.
+    #[sea_orm(column_name = "user_attribute_schema_allowed_values")]
+    pub allowed_values: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -47,6 +52,10 @@ impl From<Model> for AttributeSchema {
             is_visible: value.is_user_visible,
             is_editable: value.is_user_editable,
             is_hardcoded: value.is_hardcoded,
+            is_case_sensitive: value.is_case_sensitive,
+            allowed_values: value
+                .allowed_values
+                .and_then(|values| serde_json::from_str(&values).ok()),
         }
     }
 }
@@ -20,6 +20,23 @@ pub struct Model {
     pub totp_secret: Option<String>,
     pub mfa_type: Option<String>,
     pub uuid: Uuid,
+    pub uid_number: i32,
+    pub password_modified_date: Option<chrono::NaiveDateTime>,
+    pub modified_date: chrono::NaiveDateTime,
+    pub totp_encrypted_secret: Option<Vec<u8>>,
+    pub totp_last_step: Option<i64>,
+    /// Set when the user is soft-deleted, until it's restored or purged.
+    pub deleted_date: Option<chrono::NaiveDateTime>,
+    /// The email in lowercase, for the case-insensitive uniqueness and lookups.
+    pub lowercase_email: String,
+    /// Cleared when the password changes, until the next bind.
+    pub replicated_password_hash: Option<String>,
+    /// The disabled users can't log in.
+    pub is_enabled: bool,
+    /// After this date, the user can't log in anymore.
+    pub expiration_date: Option<chrono::NaiveDateTime>,
+    /// The last successful login or bind, updated at most once a minute.
+    pub last_login: Option<chrono::NaiveDateTime>,
 }
 
 impl EntityName for Entity {
@@ -38,6 +55,17 @@ pub enum Column {
     TotpSecret,
     MfaType,
     Uuid,
+    UidNumber,
+    PasswordModifiedDate,
+    ModifiedDate,
+    TotpEncryptedSecret,
+    TotpLastStep,
+    DeletedDate,
+    LowercaseEmail,
+    ReplicatedPasswordHash,
+    IsEnabled,
+    ExpirationDate,
+    LastLogin,
 }
 
 impl ColumnTrait for Column {
@@ -53,6 +81,17 @@ impl ColumnTrait for Column {
             Column::TotpSecret => ColumnType::String(Some(64)),
             Column::MfaType => ColumnType::String(Some(64)),
             Column::Uuid => ColumnType::String(Some(36)),
+            Column::UidNumber => ColumnType::Integer,
+            Column::PasswordModifiedDate => ColumnType::DateTime,
+            Column::ModifiedDate => ColumnType::DateTime,
+            Column::TotpEncryptedSecret => ColumnType::Binary(BlobSize::Blob(None)),
+            Column::TotpLastStep => ColumnType::BigInteger,
+            Column::DeletedDate => ColumnType::DateTime,
+            Column::LowercaseEmail => ColumnType::String(Some(255)),
+            Column::ReplicatedPasswordHash => ColumnType::Text,
+            Column::IsEnabled => ColumnType::Boolean,
+            Column::ExpirationDate => ColumnType::DateTime,
+            Column::LastLogin => ColumnType::DateTime,
         }
         .def()
     }
@@ -68,6 +107,10 @@ pub enum Relation {
     JwtStorage,
     #[sea_orm(has_many = "super::password_reset_tokens::Entity")]
     PasswordResetTokens,
+    #[sea_orm(has_one = "super::user_lockouts::Entity")]
+    UserLockouts,
+    #[sea_orm(has_many = "super::password_history::Entity")]
+    PasswordHistory,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DerivePrimaryKey)]
@@ -107,6 +150,18 @@ impl Related<super::password_reset_tokens::Entity> for Entity {
     }
 }
 
+impl Related<super::user_lockouts::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::UserLockouts.def()
+    }
+}
+
+impl Related<super::password_history::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::PasswordHistory.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
 
 impl From<Model> for crate::domain::types::User {
@@ -117,6 +172,14 @@ impl From<Model> for crate::domain::types::User {
             display_name: user.display_name,
             creation_date: user.creation_date,
             uuid: user.uuid,
+            uid_number: user.uid_number,
+            password_modified_date: user.password_modified_date,
+            modified_date: user.modified_date,
+            replicated_password_hash: user.replicated_password_hash,
+            is_enabled: user.is_enabled,
+            expiration_date: user.expiration_date,
+            last_login: user.last_login,
+            authentication_failures: None,
             attributes: Vec::new(),
         }
     }
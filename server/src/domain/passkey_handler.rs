@@ -0,0 +1,43 @@
+use crate::domain::{error::Result, types::UserId};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
+pub struct PasskeyInfo {
+    pub passkey_id: i32,
+    pub name: String,
+    pub creation_date: chrono::NaiveDateTime,
+    pub last_used_date: Option<chrono::NaiveDateTime>,
+}
+
+/// The first step of a WebAuthn ceremony.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct PasskeyChallenge {
+    /// The options for the browser's `navigator.credentials` call, as JSON.
+    pub options: String,
+    /// The id of the ceremony: to be sent back with the credential.
+    pub server_data: String,
+}
+
+#[async_trait]
+pub trait PasskeyHandler: Send + Sync {
+    async fn list_passkeys(&self, user_id: &UserId) -> Result<Vec<PasskeyInfo>>;
+    /// Starts the registration of a new passkey for the user, excluding the ones they already
+    /// have.
+    async fn start_passkey_registration(&self, user_id: &UserId) -> Result<PasskeyChallenge>;
+    /// Stores the passkey, if the credential created by the browser (as JSON) matches the
+    /// challenge.
+    async fn finish_passkey_registration(
+        &self,
+        user_id: &UserId,
+        name: &str,
+        server_data: &str,
+        credential: &str,
+    ) -> Result<PasskeyInfo>;
+    async fn delete_passkey(&self, user_id: &UserId, passkey_id: i32) -> Result<()>;
+    /// Starts a login with one of the user's passkeys. Fails if they don't have any.
+    async fn start_passkey_login(&self, user_id: &UserId) -> Result<PasskeyChallenge>;
+    /// Checks the assertion of the browser (as JSON), and returns the user that logged in. Like
+    /// the password logins, the failures count towards the lockouts.
+    async fn finish_passkey_login(&self, server_data: &str, credential: &str) -> Result<UserId>;
+}
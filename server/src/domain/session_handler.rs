@@ -0,0 +1,9 @@
+use crate::domain::{error::Result, types::UserId};
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait SessionHandler: Send + Sync {
+    /// Revokes all the refresh tokens of the user. Their JWTs stay valid until they expire, but
+    /// they can't be renewed.
+    async fn revoke_all_sessions(&self, user_id: &UserId) -> Result<()>;
+}
@@ -0,0 +1,207 @@
+use super::{
+    api_token_handler::{ApiToken, ApiTokenHandler, API_TOKEN_PREFIX},
+    error::{DomainError, Result},
+    model::{self, ApiTokensColumn},
+    sql_backend_handler::SqlBackendHandler,
+    types::ApiTokenScope,
+};
+use async_trait::async_trait;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, EntityTrait, IntoActiveModel, QueryFilter, QueryOrder,
+};
+use sha2::{Digest, Sha256};
+use tracing::{debug, instrument};
+
+const API_TOKEN_SECRET_LENGTH: usize = 40;
+
+fn generate_token() -> String {
+    use rand::{distributions::Alphanumeric, rngs::OsRng, Rng};
+    let secret: String = OsRng
+        .sample_iter(Alphanumeric)
+        .map(char::from)
+        .take(API_TOKEN_SECRET_LENGTH)
+        .collect();
+    format!("{}{}", API_TOKEN_PREFIX, secret)
+}
+
+/// The tokens are long random strings, so a plain hash is enough to protect them.
+fn hash_token(token: &str) -> String {
+    data_encoding::HEXLOWER.encode(&Sha256::digest(token.as_bytes()))
+}
+
+/// The names end up in the LDAP DNs, so only a few characters are allowed.
+fn is_valid_token_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 255
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "-_.".contains(c))
+}
+
+impl From<model::api_tokens::Model> for ApiToken {
+    fn from(token: model::api_tokens::Model) -> Self {
+        Self {
+            name: token.name,
+            scope: token.scope,
+            creation_date: token.creation_date,
+            expiry_date: token.expiry_date,
+        }
+    }
+}
+
+#[async_trait]
+impl ApiTokenHandler for SqlBackendHandler {
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn list_api_tokens(&self) -> Result<Vec<ApiToken>> {
+        Ok(model::ApiTokens::find()
+            .order_by_asc(ApiTokensColumn::Name)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn create_api_token(
+        &self,
+        name: &str,
+        scope: ApiTokenScope,
+        expiry_date: Option<chrono::NaiveDateTime>,
+    ) -> Result<String> {
+        debug!(?name, ?scope, ?expiry_date);
+        if !is_valid_token_name(name) {
+            return Err(DomainError::InvalidInput(format!(
+                "Invalid API token name '{}': only lowercase letters, digits, '-', '_' and '.' are allowed",
+                name
+            )));
+        }
+        if model::ApiTokens::find_by_id(name.to_owned())
+            .one(&self.sql_pool)
+            .await?
+            .is_some()
+        {
+            return Err(DomainError::EntityAlreadyExists(name.to_owned()));
+        }
+        let token = generate_token();
+        model::api_tokens::Model {
+            name: name.to_owned(),
+            token_hash: hash_token(&token),
+            scope,
+            creation_date: chrono::Utc::now().naive_utc(),
+            expiry_date,
+        }
+        .into_active_model()
+        .insert(&self.sql_pool)
+        .await?;
+        Ok(token)
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn revoke_api_token(&self, name: &str) -> Result<()> {
+        debug!(?name);
+        let res = model::ApiTokens::delete_by_id(name.to_owned())
+            .exec(&self.sql_pool)
+            .await?;
+        if res.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such API token: '{}'",
+                name
+            )));
+        }
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn check_api_token(&self, token: &str) -> Result<ApiToken> {
+        let now = chrono::Utc::now().naive_utc();
+        model::ApiTokens::find()
+            .filter(ApiTokensColumn::TokenHash.eq(hash_token(token)))
+            .one(&self.sql_pool)
+            .await?
+            .filter(|t| t.expiry_date.map(|expiry| expiry > now).unwrap_or(true))
+            .map(Into::into)
+            .ok_or_else(|| {
+                DomainError::AuthenticationError("Invalid or expired API token".to_owned())
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::sql_backend_handler::tests::*;
+
+    async fn get_handler() -> SqlBackendHandler {
+        SqlBackendHandler::new(get_default_config(), get_initialized_db().await)
+    }
+
+    #[tokio::test]
+    async fn test_create_and_check_api_token() {
+        let handler = &get_handler().await;
+        let token = handler
+            .create_api_token("ci", ApiTokenScope::Readonly, None)
+            .await
+            .unwrap();
+        assert!(token.starts_with(API_TOKEN_PREFIX));
+        let checked = handler.check_api_token(&token).await.unwrap();
+        assert_eq!(checked.name, "ci");
+        assert_eq!(checked.scope, ApiTokenScope::Readonly);
+        handler
+            .check_api_token(&format!("{}x", token))
+            .await
+            .unwrap_err();
+
+        // The names are unique.
+        assert!(matches!(
+            handler
+                .create_api_token("ci", ApiTokenScope::Admin, None)
+                .await,
+            Err(DomainError::EntityAlreadyExists(_))
+        ));
+        assert!(matches!(
+            handler
+                .create_api_token("Not valid", ApiTokenScope::Admin, None)
+                .await,
+            Err(DomainError::InvalidInput(_))
+        ));
+
+        assert_eq!(
+            handler
+                .list_api_tokens()
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|t| t.name)
+                .collect::<Vec<_>>(),
+            vec!["ci"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_expired_api_token() {
+        let handler = &get_handler().await;
+        let token = handler
+            .create_api_token(
+                "old",
+                ApiTokenScope::Admin,
+                Some(chrono::Utc::now().naive_utc() - chrono::Duration::hours(1)),
+            )
+            .await
+            .unwrap();
+        handler.check_api_token(&token).await.unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn test_revoke_api_token() {
+        let handler = &get_handler().await;
+        let token = handler
+            .create_api_token("ci", ApiTokenScope::Admin, None)
+            .await
+            .unwrap();
+        handler.revoke_api_token("ci").await.unwrap();
+        handler.check_api_token(&token).await.unwrap_err();
+        assert!(handler.list_api_tokens().await.unwrap().is_empty());
+        handler.revoke_api_token("ci").await.unwrap_err();
+    }
+}
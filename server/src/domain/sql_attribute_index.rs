@@ -0,0 +1,112 @@
+//! The values of the string attributes, one row per value of the lists, kept next to the
+//! serialized attributes. The filters compare them with plain SQL, which works the same on all
+//! the backends, instead of decoding the serialized values in the database.
+
+use crate::domain::{
+    model,
+    types::{GroupId, Serialized, UserId},
+};
+use sea_orm::{
+    sea_query::{Cond, IntoCondition},
+    ActiveValue, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, QueryFilter, Value,
+};
+
+const INSERT_BATCH_SIZE: usize = 100;
+
+/// Restricts to the rows of one user or group, if any.
+fn owner_condition<V: Into<Value>>(owner: Option<V>, column: impl ColumnTrait) -> Cond {
+    match owner {
+        Some(owner) => column.eq(owner).into_condition(),
+        None => Cond::all(),
+    }
+}
+
+/// Rebuilds the index of the attributes of the user, or of all the users.
+pub(crate) async fn reindex_user_attributes<C: ConnectionTrait>(
+    connection: &C,
+    user_id: Option<&UserId>,
+) -> Result<(), DbErr> {
+    model::UserAttributeIndex::delete_many()
+        .filter(owner_condition(
+            user_id,
+            model::UserAttributeIndexColumn::UserId,
+        ))
+        .exec(connection)
+        .await?;
+    let rows = model::UserAttributes::find()
+        .filter(owner_condition(
+            user_id,
+            model::UserAttributesColumn::UserId,
+        ))
+        .find_also_related(model::UserAttributeSchema)
+        .all(connection)
+        .await?
+        .into_iter()
+        .filter_map(|(attribute, schema)| Some((attribute, schema?)))
+        .flat_map(|(attribute, schema)| {
+            let values = attribute
+                .value
+                .string_values(schema.attribute_type, schema.is_list);
+            values
+                .into_iter()
+                .map(move |value| model::user_attribute_index::ActiveModel {
+                    user_id: ActiveValue::Set(attribute.user_id.clone()),
+                    attribute_name: ActiveValue::Set(attribute.attribute_name.clone()),
+                    value: ActiveValue::Set(Serialized::from(&value)),
+                    lowercase_value: ActiveValue::Set(value.to_lowercase()),
+                    ..Default::default()
+                })
+        })
+        .collect::<Vec<_>>();
+    for batch in rows.chunks(INSERT_BATCH_SIZE) {
+        model::UserAttributeIndex::insert_many(batch.to_vec())
+            .exec(connection)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Rebuilds the index of the attributes of the group, or of all the groups.
+pub(crate) async fn reindex_group_attributes<C: ConnectionTrait>(
+    connection: &C,
+    group_id: Option<GroupId>,
+) -> Result<(), DbErr> {
+    model::GroupAttributeIndex::delete_many()
+        .filter(owner_condition(
+            group_id,
+            model::GroupAttributeIndexColumn::GroupId,
+        ))
+        .exec(connection)
+        .await?;
+    let rows = model::GroupAttributes::find()
+        .filter(owner_condition(
+            group_id,
+            model::GroupAttributesColumn::GroupId,
+        ))
+        .find_also_related(model::GroupAttributeSchema)
+        .all(connection)
+        .await?
+        .into_iter()
+        .filter_map(|(attribute, schema)| Some((attribute, schema?)))
+        .flat_map(|(attribute, schema)| {
+            let values = attribute
+                .value
+                .string_values(schema.attribute_type, schema.is_list);
+            values
+                .into_iter()
+                .map(move |value| model::group_attribute_index::ActiveModel {
+                    group_id: ActiveValue::Set(attribute.group_id),
+                    attribute_name: ActiveValue::Set(attribute.attribute_name.clone()),
+                    value: ActiveValue::Set(Serialized::from(&value)),
+                    lowercase_value: ActiveValue::Set(value.to_lowercase()),
+                    ..Default::default()
+                })
+        })
+        .collect::<Vec<_>>();
+    for batch in rows.chunks(INSERT_BATCH_SIZE) {
+        model::GroupAttributeIndex::insert_many(batch.to_vec())
+            .exec(connection)
+            .await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,144 @@
+use super::{
+    audit_log_handler::{AuditLogEntry, AuditLogFilter, AuditLogHandler},
+    error::Result,
+    model::{self, AuditLogColumn},
+    sql_backend_handler::SqlBackendHandler,
+};
+use async_trait::async_trait;
+use sea_orm::{
+    sea_query::Cond, ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, QueryFilter,
+    QueryOrder,
+};
+use tracing::instrument;
+
+impl From<model::audit_log::Model> for AuditLogEntry {
+    fn from(entry: model::audit_log::Model) -> Self {
+        Self {
+            timestamp: entry.timestamp,
+            actor: entry.actor,
+            action: entry.action,
+            target: entry.target,
+            changes: entry.changes,
+        }
+    }
+}
+
+#[async_trait]
+impl AuditLogHandler for SqlBackendHandler {
+    #[instrument(skip_all, level = "debug", err)]
+    async fn record_audit_event(&self, entry: AuditLogEntry) -> Result<()> {
+        model::audit_log::ActiveModel {
+            timestamp: ActiveValue::Set(entry.timestamp),
+            actor: ActiveValue::Set(entry.actor),
+            action: ActiveValue::Set(entry.action),
+            target: ActiveValue::Set(entry.target),
+            changes: ActiveValue::Set(entry.changes),
+            ..Default::default()
+        }
+        .insert(&self.sql_pool)
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn list_audit_events(&self, filter: AuditLogFilter) -> Result<Vec<AuditLogEntry>> {
+        let mut condition = Cond::all();
+        if let Some(actor) = filter.actor {
+            condition = condition.add(AuditLogColumn::Actor.eq(actor));
+        }
+        if let Some(target) = filter.target {
+            condition = condition.add(AuditLogColumn::Target.eq(target));
+        }
+        if let Some(after) = filter.after {
+            condition = condition.add(AuditLogColumn::Timestamp.gte(after));
+        }
+        if let Some(before) = filter.before {
+            condition = condition.add(AuditLogColumn::Timestamp.lt(before));
+        }
+        Ok(model::AuditLog::find()
+            .filter(condition)
+            .order_by_desc(AuditLogColumn::Timestamp)
+            .order_by_desc(AuditLogColumn::AuditLogId)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::sql_backend_handler::tests::*;
+    use chrono::NaiveDate;
+
+    fn make_entry(actor: &str, target: &str, day: u32) -> AuditLogEntry {
+        AuditLogEntry {
+            timestamp: NaiveDate::from_ymd_opt(2023, 1, day)
+                .unwrap()
+                .and_hms_opt(12, 0, 0)
+                .unwrap(),
+            actor: actor.to_owned(),
+            action: "update_user".to_owned(),
+            target: target.to_owned(),
+            changes: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_audit_events() {
+        let handler = &SqlBackendHandler::new(get_default_config(), get_initialized_db().await);
+        handler
+            .record_audit_event(make_entry("admin", "bob", 1))
+            .await
+            .unwrap();
+        handler
+            .record_audit_event(make_entry("admin", "patrick", 2))
+            .await
+            .unwrap();
+        handler
+            .record_audit_event(make_entry("bob", "bob", 3))
+            .await
+            .unwrap();
+        let list = |filter| async move {
+            handler
+                .list_audit_events(filter)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|e| format!("{}:{}", e.actor, e.target))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            list(AuditLogFilter::default()).await,
+            vec!["bob:bob", "admin:patrick", "admin:bob"]
+        );
+        assert_eq!(
+            list(AuditLogFilter {
+                actor: Some("admin".to_owned()),
+                ..Default::default()
+            })
+            .await,
+            vec!["admin:patrick", "admin:bob"]
+        );
+        assert_eq!(
+            list(AuditLogFilter {
+                target: Some("bob".to_owned()),
+                ..Default::default()
+            })
+            .await,
+            vec!["bob:bob", "admin:bob"]
+        );
+        assert_eq!(
+            list(AuditLogFilter {
+                after: Some(make_entry("", "", 2).timestamp),
+                before: Some(make_entry("", "", 3).timestamp),
+                ..Default::default()
+            })
+            .await,
+            vec!["admin:patrick"]
+        );
+    }
+}
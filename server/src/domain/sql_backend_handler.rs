@@ -1,16 +1,99 @@
-use crate::domain::{handler::BackendHandler, sql_tables::DbConnection};
-use crate::infra::configuration::Configuration;
+use crate::domain::{
+    dynamic_groups::DynamicGroupCache, handler::BackendHandler,
+    sql_passkey_handler::PasskeyCeremonies, sql_tables::DbConnection,
+};
+use crate::infra::{
+    configuration::Configuration,
+    webhooks::{WebhookEvent, WebhookNotifier},
+};
 use async_trait::async_trait;
+use sea_orm::sea_query::LikeExpr;
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// After a write, the reads go to the primary for this long, so that they see the write even if
+/// the replica lags behind.
+const PRIMARY_PIN_DURATION: Duration = Duration::from_secs(5);
+
+/// A LIKE pattern escaped with `escape_sql_like`: the backends don't share a default escape
+/// character.
+pub(crate) fn like_pattern(pattern: &str) -> LikeExpr {
+    LikeExpr::str(pattern).escape('\\')
+}
+
+#[derive(Clone)]
+struct ReadReplica {
+    pool: DbConnection,
+    last_write: Arc<Mutex<Option<Instant>>>,
+}
 
 #[derive(Clone)]
 pub struct SqlBackendHandler {
     pub(crate) config: Configuration,
     pub(crate) sql_pool: DbConnection,
+    read_replica: Option<ReadReplica>,
+    pub(crate) webhooks: Option<WebhookNotifier>,
+    pub(crate) dynamic_groups: DynamicGroupCache,
+    pub(crate) passkey_ceremonies: PasskeyCeremonies,
 }
 
 impl SqlBackendHandler {
     pub fn new(config: Configuration, sql_pool: DbConnection) -> Self {
-        SqlBackendHandler { config, sql_pool }
+        SqlBackendHandler {
+            config,
+            sql_pool,
+            read_replica: None,
+            webhooks: None,
+            dynamic_groups: DynamicGroupCache::default(),
+            passkey_ceremonies: PasskeyCeremonies::default(),
+        }
+    }
+
+    pub fn with_webhooks(self, webhooks: Option<WebhookNotifier>) -> Self {
+        Self { webhooks, ..self }
+    }
+
+    /// Sends the listings of users and groups to a read-only replica of the database.
+    pub fn with_read_replica(self, replica_pool: Option<DbConnection>) -> Self {
+        Self {
+            read_replica: replica_pool.map(|pool| ReadReplica {
+                pool,
+                last_write: Arc::default(),
+            }),
+            ..self
+        }
+    }
+
+    /// The pool for the reads that can lag a bit behind: the replica if there is one, unless the
+    /// reads are pinned to the primary after a write.
+    pub(crate) fn read_pool(&self) -> &DbConnection {
+        match &self.read_replica {
+            Some(replica)
+                if !matches!(*replica.last_write.lock().unwrap(),
+                    Some(time) if time.elapsed() < PRIMARY_PIN_DURATION) =>
+            {
+                &replica.pool
+            }
+            _ => &self.sql_pool,
+        }
+    }
+
+    /// Reads from the primary for a while, to read our own writes.
+    pub(crate) fn pin_reads_to_primary(&self) {
+        if let Some(replica) = &self.read_replica {
+            *replica.last_write.lock().unwrap() = Some(Instant::now());
+        }
+    }
+
+    pub(crate) fn notify(&self, event: WebhookEvent) {
+        // Any change of the users or groups can change the members of the dynamic groups.
+        self.dynamic_groups.invalidate();
+        self.pin_reads_to_primary();
+        if let Some(webhooks) = &self.webhooks {
+            webhooks.notify(event);
+        }
     }
 }
 
@@ -51,6 +134,9 @@ pub mod tests {
     pub async fn get_initialized_db() -> DbConnection {
         let sql_pool = get_in_memory_db().await;
         init_table(&sql_pool).await.unwrap();
+        crate::infra::jwt_sql_tables::init_table(&sql_pool)
+            .await
+            .unwrap();
         sql_pool
     }
 
@@ -167,4 +253,29 @@ pub mod tests {
             assert_eq!(user.user_id, user_name);
         }
     }
+
+    #[tokio::test]
+    async fn test_read_replica() {
+        let fixture = TestFixture::new().await;
+        // An empty replica, to see where the reads go.
+        let handler = fixture
+            .handler
+            .with_read_replica(Some(get_initialized_db().await));
+        assert_eq!(get_user_names(&handler, None).await, Vec::<String>::new());
+        // The authentication paths always read from the primary.
+        assert_eq!(
+            handler
+                .get_user_groups(&UserId::new("bob"))
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        // Right after a write, the reads go to the primary.
+        insert_group(&handler, "New Group").await;
+        assert_eq!(
+            get_user_names(&handler, None).await,
+            vec!["bob", "john", "nogroup", "patrick"]
+        );
+    }
 }
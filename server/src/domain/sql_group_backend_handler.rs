@@ -1,20 +1,60 @@
-use crate::domain::{
-    error::{DomainError, Result},
-    handler::{
-        GroupBackendHandler, GroupListerBackendHandler, GroupRequestFilter, UpdateGroupRequest,
+use crate::{
+    domain::{
+        dynamic_groups::{expand_group_filter, serialize_dynamic_filter, RESERVED_GROUP_PREFIX},
+        error::{DomainError, Result},
+        handler::{
+            GroupBackendHandler, GroupListerBackendHandler, GroupRequestFilter, UpdateGroupRequest,
+            UserRequestFilter,
+        },
+        model::{
+            self, GroupAttributesColumn, GroupColumn, GroupMembershipColumn, MembershipColumn,
+            UserColumn,
+        },
+        sql_attribute_index::reindex_group_attributes,
+        sql_backend_handler::{like_pattern, SqlBackendHandler},
+        types::{AttributeValue, Group, GroupDetails, GroupId, UserId, Uuid},
     },
-    model::{self, GroupColumn, MembershipColumn},
-    sql_backend_handler::SqlBackendHandler,
-    types::{Group, GroupDetails, GroupId, Uuid},
+    infra::webhooks::{WebhookEvent, WebhookEventType},
 };
 use async_trait::async_trait;
+use itertools::Itertools;
 use sea_orm::{
-    sea_query::{Alias, Cond, Expr, Func, IntoCondition, SimpleExpr},
-    ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, QueryFilter, QueryOrder, QuerySelect,
-    QueryTrait,
+    sea_query::{query::OnConflict, Alias, Cond, Expr, Func, IntoCondition, SimpleExpr},
+    ActiveModelTrait, ActiveValue, ColumnTrait, DatabaseTransaction, EntityTrait, QueryFilter,
+    QueryOrder, QuerySelect, QueryTrait, Set, TransactionTrait,
 };
+use std::collections::{BTreeSet, HashMap};
 use tracing::{debug, instrument};
 
+/// The groups with a value of the attribute matching the condition.
+fn attribute_condition(name: String, condition: Cond) -> Cond {
+    GroupColumn::GroupId
+        .in_subquery(
+            model::GroupAttributes::find()
+                .select_only()
+                .column(GroupAttributesColumn::GroupId)
+                .filter(GroupAttributesColumn::AttributeName.eq(name))
+                .filter(condition)
+                .into_query(),
+        )
+        .into_condition()
+}
+
+/// The groups with a value of the string attribute matching the condition, one of the values for
+/// the lists.
+fn attribute_index_condition(name: String, condition: SimpleExpr) -> Cond {
+    GroupColumn::GroupId
+        .in_subquery(
+            model::GroupAttributeIndex::find()
+                .select_only()
+                .column(model::GroupAttributeIndexColumn::GroupId)
+                .filter(model::GroupAttributeIndexColumn::AttributeName.eq(name))
+                .filter(condition)
+                .into_query(),
+        )
+        .into_condition()
+}
+
 fn get_group_filter_expr(filter: GroupRequestFilter) -> Cond {
     use GroupRequestFilter::*;
     let group_table = Alias::new("groups");
@@ -49,20 +89,106 @@ fn get_group_filter_expr(filter: GroupRequestFilter) -> Cond {
                     .into_query(),
             )
             .into_condition(),
+        MemberSubString(filter) => GroupColumn::GroupId
+            .in_subquery(
+                model::Membership::find()
+                    .select_only()
+                    .column(MembershipColumn::GroupId)
+                    .filter(
+                        Expr::col(MembershipColumn::UserId.as_column_ref())
+                            .like(like_pattern(&filter.to_sql_filter())),
+                    )
+                    .into_query(),
+            )
+            .into_condition(),
+        // WHERE (group_id in (SELECT parent_group_id FROM group_memberships WHERE member_group_id
+        //   in (SELECT group_id FROM groups WHERE display_name = name)))
+        MemberGroup(name) => GroupColumn::GroupId
+            .in_subquery(
+                model::GroupMembership::find()
+                    .select_only()
+                    .column(GroupMembershipColumn::ParentGroupId)
+                    .filter(
+                        GroupMembershipColumn::MemberGroupId.in_subquery(
+                            model::Group::find()
+                                .select_only()
+                                .column(GroupColumn::GroupId)
+                                .filter(GroupColumn::DisplayName.eq(name))
+                                .into_query(),
+                        ),
+                    )
+                    .into_query(),
+            )
+            .into_condition(),
         DisplayNameSubString(filter) => SimpleExpr::FunctionCall(Func::lower(Expr::col((
             group_table,
             GroupColumn::DisplayName,
         ))))
-        .like(filter.to_sql_filter())
+        .like(like_pattern(&filter.to_sql_filter()))
         .into_condition(),
+        AttributeEquality(name, value) => attribute_condition(
+            name,
+            GroupAttributesColumn::Value.eq(value).into_condition(),
+        ),
+        AttributeEqualityIgnoreCase(name, value) => attribute_index_condition(
+            name,
+            model::GroupAttributeIndexColumn::LowercaseValue.eq(value.to_lowercase()),
+        ),
+        AttributePresent(name) => attribute_condition(name, Cond::all()),
     }
 }
 
+async fn update_group_with_transaction(
+    request: UpdateGroupRequest,
+    transaction: &DatabaseTransaction,
+) -> Result<()> {
+    if let Some(display_name) = request.display_name {
+        model::groups::ActiveModel {
+            group_id: ActiveValue::Set(request.group_id),
+            display_name: ActiveValue::Set(display_name),
+            ..Default::default()
+        }
+        .update(transaction)
+        .await?;
+    }
+    if !request.insert_attributes.is_empty() {
+        model::GroupAttributes::insert_many(request.insert_attributes.into_iter().map(|a| {
+            model::group_attributes::ActiveModel {
+                group_id: Set(request.group_id),
+                attribute_name: Set(a.name),
+                value: Set(a.value),
+            }
+        }))
+        .on_conflict(
+            OnConflict::columns([
+                GroupAttributesColumn::GroupId,
+                GroupAttributesColumn::AttributeName,
+            ])
+            .update_column(GroupAttributesColumn::Value)
+            .to_owned(),
+        )
+        .exec(transaction)
+        .await?;
+    }
+    if !request.delete_attributes.is_empty() {
+        model::GroupAttributes::delete_many()
+            .filter(GroupAttributesColumn::GroupId.eq(request.group_id))
+            .filter(GroupAttributesColumn::AttributeName.is_in(request.delete_attributes))
+            .exec(transaction)
+            .await?;
+    }
+    reindex_group_attributes(transaction, Some(request.group_id)).await?;
+    Ok(())
+}
+
 #[async_trait]
 impl GroupListerBackendHandler for SqlBackendHandler {
     #[instrument(skip_all, level = "debug", ret, err)]
     async fn list_groups(&self, filters: Option<GroupRequestFilter>) -> Result<Vec<Group>> {
         debug!(?filters);
+        let dynamic_groups = self.get_dynamic_groups().await?;
+        let filters = filters.map(|f| expand_group_filter(f, &dynamic_groups));
+        let pool = self.read_pool();
         let results = model::Group::find()
             // The order_by must be before find_with_related otherwise the primary order is by group_id.
             .order_by_asc(GroupColumn::DisplayName)
@@ -83,18 +209,118 @@ impl GroupListerBackendHandler for SqlBackendHandler {
                     })
                     .unwrap_or_else(|| SimpleExpr::Value(true.into()).into_condition()),
             )
-            .all(&self.sql_pool)
+            .all(pool)
             .await?;
-        Ok(results
+        let deleted_users = model::User::find()
+            .filter(UserColumn::DeletedDate.is_not_null())
+            .select_only()
+            .column(UserColumn::UserId)
+            .into_tuple::<UserId>()
+            .all(pool)
+            .await?
+            .into_iter()
+            .collect::<BTreeSet<_>>();
+        let mut groups: Vec<_> = results
             .into_iter()
             .map(|(group, users)| {
-                let users: Vec<_> = users.into_iter().map(|u| u.user_id).collect();
+                let users: Vec<_> = match dynamic_groups
+                    .iter()
+                    .find(|g| g.details.group_id == group.group_id)
+                {
+                    Some(dynamic_group) => dynamic_group.members.iter().cloned().collect(),
+                    None => users
+                        .into_iter()
+                        .map(|u| u.user_id)
+                        .filter(|u| !deleted_users.contains(u))
+                        .collect(),
+                };
                 Group {
                     users,
                     ..group.into()
                 }
             })
-            .collect())
+            .collect();
+        // The groups are sorted by name, fetch their attributes with another query.
+        let mut attributes = model::GroupAttributes::find()
+            .filter(GroupAttributesColumn::GroupId.is_in(groups.iter().map(|g| g.id)))
+            .order_by_asc(GroupAttributesColumn::GroupId)
+            .order_by_asc(GroupAttributesColumn::AttributeName)
+            .all(pool)
+            .await?
+            .into_iter()
+            .group_by(|a| a.group_id)
+            .into_iter()
+            .map(|(group_id, attributes)| {
+                (
+                    group_id,
+                    attributes.map(AttributeValue::from).collect::<Vec<_>>(),
+                )
+            })
+            .collect::<std::collections::HashMap<_, _>>();
+        for group in groups.iter_mut() {
+            group.attributes = attributes.remove(&group.id).unwrap_or_default();
+        }
+        let mut member_groups = self
+            .get_member_groups(groups.iter().map(|g| g.id).collect())
+            .await?;
+        for group in groups.iter_mut() {
+            group.member_groups = member_groups.remove(&group.id).unwrap_or_default();
+        }
+        Ok(groups)
+    }
+}
+
+impl SqlBackendHandler {
+    /// The groups directly nested in each of the given groups, sorted by name.
+    async fn get_member_groups(
+        &self,
+        group_ids: Vec<GroupId>,
+    ) -> Result<HashMap<GroupId, Vec<GroupDetails>>> {
+        let pool = self.read_pool();
+        let edges = model::GroupMembership::find()
+            .filter(GroupMembershipColumn::ParentGroupId.is_in(group_ids))
+            .all(pool)
+            .await?;
+        if edges.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let details = model::Group::find()
+            .filter(GroupColumn::GroupId.is_in(edges.iter().map(|e| e.member_group_id)))
+            .order_by_asc(GroupColumn::DisplayName)
+            .into_model::<GroupDetails>()
+            .all(pool)
+            .await?;
+        let mut member_groups = HashMap::<GroupId, Vec<GroupDetails>>::new();
+        for group in details {
+            for edge in edges.iter().filter(|e| e.member_group_id == group.group_id) {
+                member_groups
+                    .entry(edge.parent_group_id)
+                    .or_default()
+                    .push(group.clone());
+            }
+        }
+        Ok(member_groups)
+    }
+
+    /// Whether `group_id` is `member_group_id` or one of the groups nested in it, at any depth.
+    async fn is_nested_in(&self, group_id: GroupId, member_group_id: GroupId) -> Result<bool> {
+        let edges = model::GroupMembership::find().all(&self.sql_pool).await?;
+        let mut visited = BTreeSet::new();
+        let mut to_visit = vec![member_group_id];
+        while let Some(current) = to_visit.pop() {
+            if current == group_id {
+                return Ok(true);
+            }
+            if visited.insert(current.0) {
+                to_visit.extend(
+                    edges
+                        .iter()
+                        .filter(|e| e.parent_group_id == current)
+                        .map(|e| e.member_group_id),
+                );
+            }
+        }
+        Ok(false)
     }
 }
 
@@ -105,7 +331,7 @@ impl GroupBackendHandler for SqlBackendHandler {
         debug!(?group_id);
         model::Group::find_by_id(group_id)
             .into_model::<GroupDetails>()
-            .one(&self.sql_pool)
+            .one(self.read_pool())
             .await?
             .ok_or_else(|| DomainError::EntityNotFound(format!("{:?}", group_id)))
     }
@@ -113,15 +339,20 @@ impl GroupBackendHandler for SqlBackendHandler {
     #[instrument(skip_all, level = "debug", err)]
     async fn update_group(&self, request: UpdateGroupRequest) -> Result<()> {
         debug!(?request.group_id);
-        let update_group = model::groups::ActiveModel {
-            group_id: ActiveValue::Set(request.group_id),
-            display_name: request
-                .display_name
-                .map(ActiveValue::Set)
-                .unwrap_or_default(),
-            ..Default::default()
-        };
-        update_group.update(&self.sql_pool).await?;
+        let mut event = WebhookEvent::for_group(WebhookEventType::GroupUpdated, request.group_id);
+        event.changed_fields = request
+            .display_name
+            .iter()
+            .map(|_| "display_name".to_owned())
+            .chain(request.insert_attributes.iter().map(|a| a.name.clone()))
+            .chain(request.delete_attributes.iter().cloned())
+            .collect();
+        self.sql_pool
+            .transaction::<_, (), DomainError>(|transaction| {
+                Box::pin(async move { update_group_with_transaction(request, transaction).await })
+            })
+            .await?;
+        self.notify(event);
         Ok(())
     }
 
@@ -136,7 +367,12 @@ impl GroupBackendHandler for SqlBackendHandler {
             uuid: ActiveValue::Set(uuid),
             ..Default::default()
         };
-        Ok(new_group.insert(&self.sql_pool).await?.group_id)
+        let group_id = new_group.insert(&self.sql_pool).await?.group_id;
+        self.notify(WebhookEvent::for_group(
+            WebhookEventType::GroupCreated,
+            group_id,
+        ));
+        Ok(group_id)
     }
 
     #[instrument(skip_all, level = "debug", err)]
@@ -151,6 +387,107 @@ impl GroupBackendHandler for SqlBackendHandler {
                 group_id
             )));
         }
+        self.notify(WebhookEvent::for_group(
+            WebhookEventType::GroupDeleted,
+            group_id,
+        ));
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn set_group_dynamic_filter(
+        &self,
+        group_id: GroupId,
+        filter: Option<UserRequestFilter>,
+    ) -> Result<()> {
+        debug!(?group_id, ?filter);
+        let group = model::Group::find_by_id(group_id)
+            .one(&self.sql_pool)
+            .await?
+            .ok_or_else(|| DomainError::EntityNotFound(format!("{:?}", group_id)))?;
+        if group.display_name.starts_with(RESERVED_GROUP_PREFIX) {
+            return Err(DomainError::InternalError(format!(
+                "The group '{}' can't be dynamic",
+                group.display_name
+            )));
+        }
+        if filter.is_some()
+            && model::Membership::find()
+                .filter(MembershipColumn::GroupId.eq(group_id))
+                .one(&self.sql_pool)
+                .await?
+                .is_some()
+        {
+            return Err(DomainError::InternalError(
+                "Remove the members of the group before making it dynamic".to_owned(),
+            ));
+        }
+        model::groups::ActiveModel {
+            group_id: ActiveValue::Set(group_id),
+            dynamic_filter: ActiveValue::Set(filter.as_ref().map(serialize_dynamic_filter)),
+            ..Default::default()
+        }
+        .update(&self.sql_pool)
+        .await?;
+        let mut event = WebhookEvent::for_group(WebhookEventType::GroupUpdated, group_id);
+        event.changed_fields = vec!["dynamic_filter".to_owned()];
+        self.notify(event);
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn add_group_to_group(&self, member_group_id: GroupId, group_id: GroupId) -> Result<()> {
+        debug!(?member_group_id, ?group_id);
+        for id in [member_group_id, group_id] {
+            self.get_group_details(id).await?;
+        }
+        if self
+            .get_dynamic_groups()
+            .await?
+            .iter()
+            .any(|g| g.details.group_id == group_id)
+        {
+            return Err(DomainError::InternalError(
+                "Can't add members to a dynamic group".to_owned(),
+            ));
+        }
+        // Also refuses to add a group to itself.
+        if self.is_nested_in(group_id, member_group_id).await? {
+            return Err(DomainError::InternalError(
+                "Adding the group would create a cycle".to_owned(),
+            ));
+        }
+        model::group_memberships::ActiveModel {
+            parent_group_id: ActiveValue::Set(group_id),
+            member_group_id: ActiveValue::Set(member_group_id),
+        }
+        .insert(&self.sql_pool)
+        .await?;
+        let mut event = WebhookEvent::for_group(WebhookEventType::GroupUpdated, group_id);
+        event.changed_fields = vec!["member_groups".to_owned()];
+        self.notify(event);
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn remove_group_from_group(
+        &self,
+        member_group_id: GroupId,
+        group_id: GroupId,
+    ) -> Result<()> {
+        debug!(?member_group_id, ?group_id);
+        let res = model::GroupMembership::delete_by_id((group_id, member_group_id))
+            .exec(&self.sql_pool)
+            .await?;
+        if res.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such group membership: {:?} -> {:?}",
+                member_group_id, group_id
+            )));
+        }
+        let mut event = WebhookEvent::for_group(WebhookEventType::GroupUpdated, group_id);
+        event.changed_fields = vec!["member_groups".to_owned()];
+        self.notify(event);
         Ok(())
     }
 }
@@ -158,7 +495,12 @@ impl GroupBackendHandler for SqlBackendHandler {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::domain::{handler::SubStringFilter, sql_backend_handler::tests::*, types::UserId};
+    use crate::domain::{
+        handler::SubStringFilter,
+        sql_backend_handler::tests::*,
+        types::{AttributeType, Serialized, UserId},
+    };
+    use sea_orm::DbBackend;
 
     async fn get_group_ids(
         handler: &SqlBackendHandler,
@@ -251,6 +593,24 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_list_groups_member_substring_filter() {
+        let fixture = TestFixture::new().await;
+        assert_eq!(
+            get_group_ids(
+                &fixture.handler,
+                Some(GroupRequestFilter::MemberSubString(SubStringFilter {
+                    initial: Some("Jo".to_owned()),
+                    any: vec![],
+                    final_: None,
+                })),
+            )
+            .await,
+            // Worst group
+            vec![fixture.groups[1]]
+        );
+    }
+
     #[tokio::test]
     async fn test_get_group_details() {
         let fixture = TestFixture::new().await;
@@ -279,6 +639,8 @@ mod tests {
             .update_group(UpdateGroupRequest {
                 group_id: fixture.groups[0],
                 display_name: Some("Awesomest Group".to_owned()),
+                insert_attributes: vec![],
+                delete_attributes: vec![],
             })
             .await
             .unwrap();
@@ -290,6 +652,93 @@ mod tests {
         assert_eq!(details.display_name, "Awesomest Group");
     }
 
+    #[test]
+    fn test_attribute_filters_sql_is_portable() {
+        let filter = GroupRequestFilter::AttributeEqualityIgnoreCase(
+            "mail".to_owned(),
+            "Best@Example.com".to_owned(),
+        );
+        for backend in [DbBackend::Sqlite, DbBackend::MySql, DbBackend::Postgres] {
+            let sql = model::Group::find()
+                .filter(get_group_filter_expr(filter.clone()))
+                .build(backend)
+                .to_string();
+            assert!(!sql.contains("SUBSTR"), "{:?}: {}", backend, sql);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_group_attributes() {
+        let fixture = TestFixture::new().await;
+        model::GroupAttributeSchema::insert(model::group_attribute_schema::ActiveModel {
+            attribute_name: Set("mail".to_owned()),
+            attribute_type: Set(AttributeType::String),
+            is_list: Set(false),
+            is_group_visible: Set(true),
+            is_group_editable: Set(true),
+            is_hardcoded: Set(false),
+            is_case_sensitive: Set(false),
+        })
+        .exec(&fixture.handler.sql_pool)
+        .await
+        .unwrap();
+        let mail = AttributeValue {
+            name: "mail".to_owned(),
+            value: Serialized::from("best@example.com"),
+        };
+        fixture
+            .handler
+            .update_group(UpdateGroupRequest {
+                group_id: fixture.groups[0],
+                display_name: None,
+                insert_attributes: vec![mail.clone()],
+                delete_attributes: vec![],
+            })
+            .await
+            .unwrap();
+        let groups = fixture
+            .handler
+            .list_groups(Some(GroupRequestFilter::AttributeEqualityIgnoreCase(
+                "mail".to_owned(),
+                "Best@Example.com".to_owned(),
+            )))
+            .await
+            .unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].id, fixture.groups[0]);
+        assert_eq!(groups[0].display_name, "Best Group");
+        assert_eq!(groups[0].attributes, vec![mail.clone()]);
+        assert_eq!(
+            get_group_ids(
+                &fixture.handler,
+                Some(GroupRequestFilter::AttributeEquality(
+                    "mail".to_owned(),
+                    mail.value
+                )),
+            )
+            .await,
+            vec![fixture.groups[0]]
+        );
+        fixture
+            .handler
+            .update_group(UpdateGroupRequest {
+                group_id: fixture.groups[0],
+                display_name: None,
+                insert_attributes: vec![],
+                delete_attributes: vec!["mail".to_owned()],
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            get_group_ids(
+                &fixture.handler,
+                Some(GroupRequestFilter::AttributePresent("mail".to_owned())),
+            )
+            .await,
+            Vec::<GroupId>::new()
+        );
+    }
+
     #[tokio::test]
     async fn test_delete_group() {
         let fixture = TestFixture::new().await;
@@ -307,4 +756,181 @@ mod tests {
             vec![fixture.groups[2], fixture.groups[1]]
         );
     }
+
+    #[tokio::test]
+    async fn test_group_webhook_events() {
+        let fixture = TestFixture::new().await;
+        let (notifier, mut events) = crate::infra::webhooks::WebhookNotifier::new_for_tests();
+        let handler = fixture.handler.with_webhooks(Some(notifier));
+        let group_id = handler.create_group("NewGroup").await.unwrap();
+        handler
+            .update_group(UpdateGroupRequest {
+                group_id,
+                display_name: Some("Renamed".to_owned()),
+                insert_attributes: Vec::new(),
+                delete_attributes: vec!["mail".to_owned()],
+            })
+            .await
+            .unwrap();
+        handler.delete_group(group_id).await.unwrap();
+        // Failed operations don't send events.
+        handler.delete_group(group_id).await.unwrap_err();
+
+        let event = events.try_recv().unwrap();
+        assert_eq!(event.event, WebhookEventType::GroupCreated);
+        assert_eq!(event.group_id, Some(group_id));
+        assert_eq!(event.user_id, None);
+        let event = events.try_recv().unwrap();
+        assert_eq!(event.event, WebhookEventType::GroupUpdated);
+        assert_eq!(event.changed_fields, vec!["display_name", "mail"]);
+        let event = events.try_recv().unwrap();
+        assert_eq!(event.event, WebhookEventType::GroupDeleted);
+        assert!(events.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_dynamic_group() {
+        use crate::domain::handler::{UserBackendHandler, UserListerBackendHandler};
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        let group_id = insert_group(handler, "Dynamic Group").await;
+        handler
+            .set_group_dynamic_filter(
+                group_id,
+                Some(UserRequestFilter::Or(vec![
+                    UserRequestFilter::UserId(UserId::new("bob")),
+                    UserRequestFilter::UserId(UserId::new("John")),
+                ])),
+            )
+            .await
+            .unwrap();
+
+        let groups = handler
+            .list_groups(Some(GroupRequestFilter::GroupId(group_id)))
+            .await
+            .unwrap();
+        assert_eq!(
+            groups[0].users,
+            vec![UserId::new("bob"), UserId::new("John")]
+        );
+        assert!(groups[0].dynamic_filter.is_some());
+        assert_eq!(
+            get_group_ids(
+                handler,
+                Some(GroupRequestFilter::Member(UserId::new("bob")))
+            )
+            .await,
+            vec![fixture.groups[0], group_id]
+        );
+        assert!(handler
+            .get_user_groups(&UserId::new("bob"))
+            .await
+            .unwrap()
+            .iter()
+            .any(|g| g.group_id == group_id));
+        assert_eq!(
+            get_user_names(handler, Some(UserRequestFilter::MemberOfId(group_id))).await,
+            vec!["bob", "john"]
+        );
+
+        // The members are computed again after a change.
+        insert_user(handler, "bobby", "pass").await;
+        handler
+            .set_group_dynamic_filter(
+                group_id,
+                Some(UserRequestFilter::UserIdSubString(SubStringFilter {
+                    initial: Some("bob".to_owned()),
+                    any: vec![],
+                    final_: None,
+                })),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            get_user_names(
+                handler,
+                Some(UserRequestFilter::MemberOf("Dynamic Group".to_owned()))
+            )
+            .await,
+            vec!["bob", "bobby"]
+        );
+
+        handler
+            .add_user_to_group(&UserId::new("patrick"), group_id)
+            .await
+            .unwrap_err();
+        // Groups with members and the permission groups can't be dynamic.
+        handler
+            .set_group_dynamic_filter(fixture.groups[0], Some(UserRequestFilter::And(vec![])))
+            .await
+            .unwrap_err();
+        let permission_group = insert_group(handler, "lldap_password_manager").await;
+        handler
+            .set_group_dynamic_filter(permission_group, Some(UserRequestFilter::And(vec![])))
+            .await
+            .unwrap_err();
+
+        handler
+            .set_group_dynamic_filter(group_id, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            get_group_ids(handler, Some(GroupRequestFilter::GroupId(group_id))).await,
+            vec![group_id]
+        );
+        assert!(handler
+            .list_groups(Some(GroupRequestFilter::GroupId(group_id)))
+            .await
+            .unwrap()[0]
+            .users
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_nested_groups() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        let (best, worst, empty) = (fixture.groups[0], fixture.groups[1], fixture.groups[2]);
+        handler.add_group_to_group(empty, best).await.unwrap();
+        handler.add_group_to_group(worst, empty).await.unwrap();
+        // Cycles are refused.
+        handler.add_group_to_group(best, best).await.unwrap_err();
+        handler.add_group_to_group(best, empty).await.unwrap_err();
+        handler.add_group_to_group(best, worst).await.unwrap_err();
+
+        let groups = handler
+            .list_groups(Some(GroupRequestFilter::GroupId(best)))
+            .await
+            .unwrap();
+        assert_eq!(
+            groups[0]
+                .member_groups
+                .iter()
+                .map(|g| g.group_id)
+                .collect::<Vec<_>>(),
+            vec![empty]
+        );
+        assert_eq!(
+            get_group_ids(
+                handler,
+                Some(GroupRequestFilter::MemberGroup("Worst Group".to_owned()))
+            )
+            .await,
+            vec![empty]
+        );
+
+        handler.remove_group_from_group(empty, best).await.unwrap();
+        handler
+            .remove_group_from_group(empty, best)
+            .await
+            .unwrap_err();
+        // The memberships are deleted with the groups.
+        handler.delete_group(worst).await.unwrap();
+        assert!(handler
+            .list_groups(None)
+            .await
+            .unwrap()
+            .iter()
+            .all(|g| g.member_groups.is_empty()));
+    }
 }
@@ -0,0 +1,240 @@
+use super::{
+    error::Result,
+    lockout_handler::{LockoutHandler, UserLockout},
+    model::{self, UserLockoutsColumn},
+    sql_backend_handler::SqlBackendHandler,
+    types::{AuthenticationFailures, UserId},
+};
+use async_trait::async_trait;
+use sea_orm::{ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, QueryFilter, QueryOrder};
+use std::collections::BTreeMap;
+use tracing::{debug, instrument};
+
+impl SqlBackendHandler {
+    fn lockouts_enabled(&self) -> bool {
+        self.config.lockout_options.max_failures > 0
+    }
+
+    /// Whether the user is currently locked out. The callers should fail the authentication
+    /// without checking the password, and without telling the user about the lockout.
+    #[instrument(skip_all, level = "debug", err)]
+    pub(crate) async fn is_locked_out(&self, user_id: &UserId) -> Result<bool> {
+        if !self.lockouts_enabled() {
+            return Ok(false);
+        }
+        let now = chrono::Utc::now().naive_utc();
+        Ok(model::UserLockouts::find_by_id(user_id.clone())
+            .one(&self.sql_pool)
+            .await?
+            .and_then(|lockout| lockout.locked_until)
+            .map(|locked_until| locked_until > now)
+            .unwrap_or(false))
+    }
+
+    /// Counts a failed authentication, and locks the account if there were too many of them in
+    /// the window. The failures are counted even without lockouts, for the monitoring.
+    #[instrument(skip_all, level = "debug", err)]
+    pub(crate) async fn record_authentication_failure(&self, user_id: &UserId) -> Result<()> {
+        let options = &self.config.lockout_options;
+        let now = chrono::Utc::now().naive_utc();
+        let window = chrono::Duration::seconds(options.failure_window_seconds as i64);
+        let existing = model::UserLockouts::find_by_id(user_id.clone())
+            .one(&self.sql_pool)
+            .await?;
+        if existing.is_none()
+            && model::User::find_by_id(user_id.clone())
+                .one(&self.sql_pool)
+                .await?
+                .is_none()
+        {
+            // Nothing to lock.
+            return Ok(());
+        }
+        // A previous (expired) lockout or an old failure starts a new count.
+        let (failure_count, first_failure_date) = match &existing {
+            Some(lockout)
+                if lockout.locked_until.is_none() && lockout.first_failure_date + window > now =>
+            {
+                (lockout.failure_count + 1, lockout.first_failure_date)
+            }
+            _ => (1, now),
+        };
+        let locked_until = (self.lockouts_enabled()
+            && failure_count as u32 >= options.max_failures)
+            .then(|| now + chrono::Duration::seconds(options.lockout_duration_seconds as i64));
+        if locked_until.is_some() {
+            debug!(r#"Locking out user "{}""#, user_id);
+        }
+        let lockout = model::user_lockouts::ActiveModel {
+            user_id: ActiveValue::Set(user_id.clone()),
+            failure_count: ActiveValue::Set(failure_count),
+            first_failure_date: ActiveValue::Set(first_failure_date),
+            locked_until: ActiveValue::Set(locked_until),
+            last_failure_date: ActiveValue::Set(Some(now)),
+        };
+        if existing.is_some() {
+            lockout.update(&self.sql_pool).await?;
+        } else {
+            lockout.insert(&self.sql_pool).await?;
+        }
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    pub(crate) async fn reset_authentication_failures(&self, user_id: &UserId) -> Result<()> {
+        model::UserLockouts::delete_by_id(user_id.clone())
+            .exec(&self.sql_pool)
+            .await?;
+        Ok(())
+    }
+
+    /// The failed logins of the users, for those who have some.
+    #[instrument(skip_all, level = "debug", err)]
+    pub(crate) async fn get_authentication_failures(
+        &self,
+        user_ids: &[UserId],
+    ) -> Result<BTreeMap<UserId, AuthenticationFailures>> {
+        let now = chrono::Utc::now().naive_utc();
+        Ok(model::UserLockouts::find()
+            .filter(UserLockoutsColumn::UserId.is_in(user_ids))
+            .all(self.read_pool())
+            .await?
+            .into_iter()
+            .map(|lockout| {
+                let mut failures = AuthenticationFailures::from(lockout.clone());
+                // The expired lockouts are kept until the next login.
+                failures.locked_until = failures.locked_until.filter(|date| *date > now);
+                (lockout.user_id, failures)
+            })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl LockoutHandler for SqlBackendHandler {
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn list_user_lockouts(&self) -> Result<Vec<UserLockout>> {
+        let now = chrono::Utc::now().naive_utc();
+        Ok(model::UserLockouts::find()
+            .filter(UserLockoutsColumn::LockedUntil.gt(now))
+            .order_by_asc(UserLockoutsColumn::UserId)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .filter_map(|lockout| {
+                Some(UserLockout {
+                    locked_until: lockout.locked_until?,
+                    user_id: lockout.user_id,
+                    failure_count: lockout.failure_count,
+                })
+            })
+            .collect())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn clear_user_lockout(&self, user_id: &UserId) -> Result<()> {
+        debug!(?user_id);
+        model::UserLockouts::delete_by_id(user_id.clone())
+            .exec(&self.sql_pool)
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        domain::{
+            handler::{BindRequest, LoginHandler},
+            sql_backend_handler::tests::*,
+        },
+        infra::configuration::LockoutOptionsBuilder,
+    };
+
+    async fn get_handler(max_failures: u32) -> SqlBackendHandler {
+        let mut config = get_default_config();
+        config.lockout_options = LockoutOptionsBuilder::default()
+            .max_failures(max_failures)
+            .build()
+            .unwrap();
+        let handler = SqlBackendHandler::new(config, get_initialized_db().await);
+        insert_user(&handler, "bob", "bob00").await;
+        handler
+    }
+
+    async fn bind(handler: &SqlBackendHandler, password: &str) -> Result<()> {
+        handler
+            .bind(BindRequest {
+                name: UserId::new("bob"),
+                password: password.to_string(),
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_lockout_after_failed_binds() {
+        let handler = get_handler(2).await;
+
+        // A successful bind resets the count.
+        bind(&handler, "wrong").await.unwrap_err();
+        bind(&handler, "bob00").await.unwrap();
+        bind(&handler, "wrong").await.unwrap_err();
+        assert!(handler.list_user_lockouts().await.unwrap().is_empty());
+
+        bind(&handler, "wrong").await.unwrap_err();
+        let lockouts = handler.list_user_lockouts().await.unwrap();
+        assert_eq!(lockouts.len(), 1);
+        assert_eq!(lockouts[0].user_id, UserId::new("bob"));
+        assert_eq!(lockouts[0].failure_count, 2);
+        // The right password is refused while locked.
+        bind(&handler, "bob00").await.unwrap_err();
+
+        handler
+            .clear_user_lockout(&UserId::new("bob"))
+            .await
+            .unwrap();
+        assert!(handler.list_user_lockouts().await.unwrap().is_empty());
+        bind(&handler, "bob00").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_lockout_disabled() {
+        let handler = get_handler(0).await;
+        for _ in 0..10 {
+            bind(&handler, "wrong").await.unwrap_err();
+        }
+        assert!(handler.list_user_lockouts().await.unwrap().is_empty());
+        bind(&handler, "bob00").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_authentication_failures() {
+        let handler = get_handler(2).await;
+        let bob = UserId::new("bob");
+        let get_failures = || async {
+            handler
+                .get_authentication_failures(&[bob.clone()])
+                .await
+                .unwrap()
+                .remove(&bob)
+        };
+        assert_eq!(get_failures().await, None);
+
+        bind(&handler, "wrong").await.unwrap_err();
+        let failures = get_failures().await.unwrap();
+        assert_eq!(failures.failure_count, 1);
+        assert_eq!(failures.locked_until, None);
+
+        bind(&handler, "wrong").await.unwrap_err();
+        let failures = get_failures().await.unwrap();
+        assert_eq!(failures.failure_count, 2);
+        assert!(failures.locked_until.is_some());
+
+        handler.clear_user_lockout(&bob).await.unwrap();
+        assert_eq!(get_failures().await, None);
+        bind(&handler, "wrong").await.unwrap_err();
+        bind(&handler, "bob00").await.unwrap();
+        assert_eq!(get_failures().await, None);
+    }
+}
@@ -1,14 +1,17 @@
-use crate::domain::{
-    sql_tables::{DbConnection, SchemaVersion, LAST_SCHEMA_VERSION},
-    types::{AttributeType, GroupId, JpegPhoto, Serialized, UserId, Uuid},
+use crate::{
+    domain::{
+        sql_tables::{DbConnection, SchemaVersion, LAST_SCHEMA_VERSION},
+        types::{AttributeType, GroupId, JpegPhoto, Serialized, UserId, Uuid},
+    },
+    infra::jwt_sql_tables::JwtRefreshStorage,
 };
 use itertools::Itertools;
 use sea_orm::{
     sea_query::{
         self, all, ColumnDef, Expr, ForeignKey, ForeignKeyAction, Func, Index, Query, Table, Value,
     },
-    ConnectionTrait, DatabaseTransaction, DbErr, FromQueryResult, Iden, Order, Statement,
-    TransactionTrait,
+    ConnectionTrait, DatabaseTransaction, DbBackend, DbErr, FromQueryResult, Iden, Order,
+    Statement, TransactionTrait,
 };
 use serde::{Deserialize, Serialize};
 use tracing::{error, info, instrument, warn};
@@ -27,6 +30,17 @@ pub enum Users {
     TotpSecret,
     MfaType,
     Uuid,
+    UidNumber,
+    PasswordModifiedDate,
+    ModifiedDate,
+    TotpEncryptedSecret,
+    TotpLastStep,
+    DeletedDate,
+    LowercaseEmail,
+    ReplicatedPasswordHash,
+    IsEnabled,
+    ExpirationDate,
+    LastLogin,
 }
 
 #[derive(Iden, PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Copy)]
@@ -36,6 +50,7 @@ pub enum Groups {
     DisplayName,
     CreationDate,
     Uuid,
+    DynamicFilter,
 }
 
 #[derive(Iden, Clone, Copy)]
@@ -45,6 +60,13 @@ pub enum Memberships {
     GroupId,
 }
 
+#[derive(Iden, Clone, Copy)]
+pub enum GroupMemberships {
+    Table,
+    ParentGroupId,
+    MemberGroupId,
+}
+
 #[derive(Iden, PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Copy)]
 pub enum UserAttributeSchema {
     Table,
@@ -54,6 +76,8 @@ pub enum UserAttributeSchema {
     UserAttributeSchemaIsUserVisible,
     UserAttributeSchemaIsUserEditable,
     UserAttributeSchemaIsHardcoded,
+    UserAttributeSchemaIsCaseSensitive,
+    UserAttributeSchemaAllowedValues,
 }
 
 #[derive(Iden, PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Copy)]
@@ -64,6 +88,16 @@ pub enum UserAttributes {
     UserAttributeValue,
 }
 
+#[derive(Iden, Clone, Copy)]
+pub enum UserAttributeIndex {
+    Table,
+    UserAttributeIndexId,
+    UserAttributeIndexUserId,
+    UserAttributeIndexName,
+    UserAttributeIndexValue,
+    UserAttributeIndexLowercaseValue,
+}
+
 #[derive(Iden, PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Copy)]
 pub enum GroupAttributeSchema {
     Table,
@@ -73,6 +107,7 @@ pub enum GroupAttributeSchema {
     GroupAttributeSchemaIsGroupVisible,
     GroupAttributeSchemaIsGroupEditable,
     GroupAttributeSchemaIsHardcoded,
+    GroupAttributeSchemaIsCaseSensitive,
 }
 
 #[derive(Iden, PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Copy)]
@@ -83,12 +118,75 @@ pub enum GroupAttributes {
     GroupAttributeValue,
 }
 
+#[derive(Iden, Clone, Copy)]
+pub enum GroupAttributeIndex {
+    Table,
+    GroupAttributeIndexId,
+    GroupAttributeIndexGroupId,
+    GroupAttributeIndexName,
+    GroupAttributeIndexValue,
+    GroupAttributeIndexLowercaseValue,
+}
+
+#[derive(Iden, Clone, Copy)]
+pub enum UserLockouts {
+    Table,
+    UserId,
+    FailureCount,
+    FirstFailureDate,
+    LockedUntil,
+    LastFailureDate,
+}
+
+#[derive(Iden, Clone, Copy)]
+pub enum PasswordHistory {
+    Table,
+    PasswordHistoryId,
+    UserId,
+    PasswordHash,
+    CreationDate,
+}
+
+#[derive(Iden, Clone, Copy)]
+pub enum Passkeys {
+    Table,
+    PasskeyId,
+    UserId,
+    Name,
+    Credential,
+    CreationDate,
+    LastUsedDate,
+}
+
+#[derive(Iden, Clone, Copy)]
+pub enum ApiTokens {
+    Table,
+    Name,
+    TokenHash,
+    Scope,
+    CreationDate,
+    ExpiryDate,
+}
+
+#[derive(Iden, Clone, Copy)]
+pub enum AuditLog {
+    Table,
+    AuditLogId,
+    Timestamp,
+    Actor,
+    Action,
+    Target,
+    Changes,
+}
+
 // Metadata about the SQL DB.
 #[derive(Iden)]
 pub enum Metadata {
     Table,
     // Which version of the schema we're at.
     Version,
+    // The uid number of the next user.
+    NextUidNumber,
 }
 
 #[derive(FromQueryResult, PartialEq, Eq, Debug)]
@@ -875,37 +973,1111 @@ async fn migrate_to_v5(transaction: DatabaseTransaction) -> Result<DatabaseTrans
     Ok(transaction)
 }
 
-// This is needed to make an array of async functions.
-macro_rules! to_sync {
-    ($l:ident) => {
-        move |transaction| -> std::pin::Pin<
-            Box<dyn std::future::Future<Output = Result<DatabaseTransaction, DbErr>>>,
-        > { Box::pin($l(transaction)) }
-    };
+async fn migrate_to_v6(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    // Add a stable numeric id to the users, used for the POSIX uidNumber.
+    transaction
+        .execute(
+            builder.build(
+                Table::alter().table(Users::Table).add_column(
+                    ColumnDef::new(Users::UidNumber)
+                        .integer()
+                        .not_null()
+                        .default(0),
+                ),
+            ),
+        )
+        .await?;
+    #[derive(FromQueryResult)]
+    struct JustUserId {
+        user_id: UserId,
+    }
+    let users = JustUserId::find_by_statement(
+        builder.build(
+            Query::select()
+                .from(Users::Table)
+                .column(Users::UserId)
+                .order_by_columns([
+                    (Users::CreationDate, Order::Asc),
+                    (Users::UserId, Order::Asc),
+                ]),
+        ),
+    )
+    .all(&transaction)
+    .await?;
+    for (index, user) in users.into_iter().enumerate() {
+        transaction
+            .execute(
+                builder.build(
+                    Query::update()
+                        .table(Users::Table)
+                        .value(Users::UidNumber, Value::from(index as i32 + 1))
+                        .and_where(Expr::col(Users::UserId).eq(user.user_id)),
+                ),
+            )
+            .await?;
+    }
+    Ok(transaction)
 }
 
-pub async fn migrate_from_version(
-    pool: &DbConnection,
-    version: SchemaVersion,
-    last_version: SchemaVersion,
-) -> anyhow::Result<()> {
-    match version.cmp(&last_version) {
-        std::cmp::Ordering::Less => (),
-        std::cmp::Ordering::Equal => return Ok(()),
-        std::cmp::Ordering::Greater => anyhow::bail!("DB version downgrading is not supported"),
-    }
-    info!("Upgrading DB schema from version {}", version.0);
+async fn migrate_to_v7(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    // Keep track of the last password change, for the shadowLastChange attribute.
+    transaction
+        .execute(
+            builder.build(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column(ColumnDef::new(Users::PasswordModifiedDate).date_time()),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v8(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    // Attribute values are compared case-insensitively in the filters, unless marked otherwise.
+    transaction
+        .execute(
+            builder.build(
+                Table::alter().table(UserAttributeSchema::Table).add_column(
+                    ColumnDef::new(UserAttributeSchema::UserAttributeSchemaIsCaseSensitive)
+                        .boolean()
+                        .not_null()
+                        .default(false),
+                ),
+            ),
+        )
+        .await?;
+    transaction
+        .execute(
+            builder.build(
+                Table::alter()
+                    .table(GroupAttributeSchema::Table)
+                    .add_column(
+                        ColumnDef::new(GroupAttributeSchema::GroupAttributeSchemaIsCaseSensitive)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    ),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v9(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    // Keep track of the last modification, for the modifyTimestamp attribute.
+    transaction
+        .execute(
+            builder.build(
+                Table::alter().table(Users::Table).add_column(
+                    ColumnDef::new(Users::ModifiedDate)
+                        .date_time()
+                        .not_null()
+                        .default(chrono::Utc::now().naive_utc()),
+                ),
+            ),
+        )
+        .await?;
+    // Existing users haven't been modified since their creation, as far as we know.
+    transaction
+        .execute(
+            builder.build(
+                Query::update()
+                    .table(Users::Table)
+                    .value(Users::ModifiedDate, Expr::col(Users::CreationDate)),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v10(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    // The TOTP secret, encrypted with the server key, and the last step used to log in, to
+    // prevent replays.
+    transaction
+        .execute(
+            builder.build(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column(ColumnDef::new(Users::TotpEncryptedSecret).binary()),
+            ),
+        )
+        .await?;
+    transaction
+        .execute(
+            builder.build(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column(ColumnDef::new(Users::TotpLastStep).big_integer()),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v11(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    // Failed authentication attempts, to lock the accounts after too many of them.
+    transaction
+        .execute(
+            builder.build(
+                Table::create()
+                    .table(UserLockouts::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(UserLockouts::UserId)
+                            .string_len(255)
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(UserLockouts::FailureCount)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(UserLockouts::FirstFailureDate)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(UserLockouts::LockedUntil).date_time())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("UserLockoutsUserForeignKey")
+                            .from(UserLockouts::Table, UserLockouts::UserId)
+                            .to(Users::Table, Users::UserId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    ),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v12(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    // The last password files of each user, to prevent reusing a password.
+    transaction
+        .execute(
+            builder.build(
+                Table::create()
+                    .table(PasswordHistory::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(PasswordHistory::PasswordHistoryId)
+                            .integer()
+                            .auto_increment()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(PasswordHistory::UserId)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(PasswordHistory::PasswordHash)
+                            .binary()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(PasswordHistory::CreationDate)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("PasswordHistoryUserForeignKey")
+                            .from(PasswordHistory::Table, PasswordHistory::UserId)
+                            .to(Users::Table, Users::UserId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    ),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v13(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    // Long-lived credentials for the scripts, with only a hash of the token stored.
+    transaction
+        .execute(
+            builder.build(
+                Table::create()
+                    .table(ApiTokens::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ApiTokens::Name)
+                            .string_len(255)
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ApiTokens::TokenHash)
+                            .string_len(64)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(ApiTokens::Scope).string_len(16).not_null())
+                    .col(
+                        ColumnDef::new(ApiTokens::CreationDate)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ApiTokens::ExpiryDate).date_time()),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v14(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    // Soft-deleted users are kept until the end of the retention period.
+    transaction
+        .execute(
+            builder.build(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column(ColumnDef::new(Users::DeletedDate).date_time()),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v15(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    // Who changed which user or group, and when. The entries outlive the users.
+    transaction
+        .execute(
+            builder.build(
+                Table::create()
+                    .table(AuditLog::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AuditLog::AuditLogId)
+                            .integer()
+                            .auto_increment()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(AuditLog::Timestamp).date_time().not_null())
+                    .col(ColumnDef::new(AuditLog::Actor).string_len(255).not_null())
+                    .col(ColumnDef::new(AuditLog::Action).string_len(64).not_null())
+                    .col(ColumnDef::new(AuditLog::Target).string_len(255).not_null())
+                    .col(ColumnDef::new(AuditLog::Changes).text()),
+            ),
+        )
+        .await?;
+    transaction
+        .execute(
+            builder.build(
+                Index::create()
+                    .if_not_exists()
+                    .name("audit-log-timestamp")
+                    .table(AuditLog::Table)
+                    .col(AuditLog::Timestamp),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+/// Logs the values of the column that are equal when ignoring the case, with their users.
+/// Returns whether there were any.
+fn log_case_insensitive_duplicates(column: &str, users: &[(String, String)]) -> bool {
+    let mut any_duplicate = false;
+    for (value, users) in &users
+        .iter()
+        .map(|(user_id, value)| (value.to_lowercase(), user_id))
+        .sorted()
+        .group_by(|(value, _)| value.clone())
+    {
+        let users = users.map(|(_, user_id)| user_id).collect::<Vec<_>>();
+        if users.len() > 1 {
+            any_duplicate = true;
+            warn!("{} (ignoring case): {}", column, value);
+            for user in users {
+                warn!("    User: {}", user);
+            }
+        }
+    }
+    any_duplicate
+}
+
+async fn migrate_to_v16(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    // The user ids and the emails are unique regardless of the case.
+    #[derive(FromQueryResult)]
+    struct UserAndEmail {
+        user_id: String,
+        email: String,
+    }
+    let users = UserAndEmail::find_by_statement(
+        builder.build(
+            Query::select()
+                .from(Users::Table)
+                .columns([Users::UserId, Users::Email]),
+        ),
+    )
+    .all(&transaction)
+    .await?;
+    let has_duplicate_ids = log_case_insensitive_duplicates(
+        "User id",
+        &users
+            .iter()
+            .map(|u| (u.user_id.clone(), u.user_id.clone()))
+            .collect::<Vec<_>>(),
+    );
+    let has_duplicate_emails = log_case_insensitive_duplicates(
+        "Email",
+        &users
+            .iter()
+            .map(|u| (u.user_id.clone(), u.email.clone()))
+            .collect::<Vec<_>>(),
+    );
+    if has_duplicate_ids || has_duplicate_emails {
+        error!("Found several users with the same id or email, ignoring the case. Rename them or change their emails: until then, only one of them can be found by email.");
+    }
+    // For the duplicate emails, one user gets the lowercase email: the one whose email is already
+    // lowercase, if any. The others keep their email as it is, which is different from any
+    // lowercase email, so that the unique index can still be created. Changing their email
+    // updates it.
+    let mut lowercase_emails = std::collections::HashMap::<String, String>::new();
+    for user in users
+        .iter()
+        .sorted_by_key(|u| (u.email != u.email.to_lowercase(), u.user_id.clone()))
+    {
+        lowercase_emails
+            .entry(user.email.to_lowercase())
+            .or_insert_with(|| user.user_id.clone());
+    }
+    transaction
+        .execute(
+            builder.build(
+                Table::alter().table(Users::Table).add_column(
+                    ColumnDef::new(Users::LowercaseEmail)
+                        .string_len(255)
+                        .not_null()
+                        .default(""),
+                ),
+            ),
+        )
+        .await?;
+    for user in users {
+        let lowercase_email = user.email.to_lowercase();
+        let lowercase_email = if lowercase_emails[&lowercase_email] == user.user_id {
+            lowercase_email
+        } else {
+            user.email
+        };
+        transaction
+            .execute(
+                builder.build(
+                    Query::update()
+                        .table(Users::Table)
+                        .value(Users::LowercaseEmail, lowercase_email)
+                        .and_where(Expr::col(Users::UserId).eq(user.user_id)),
+                ),
+            )
+            .await?;
+    }
+    transaction
+        .execute(
+            builder.build(
+                Index::create()
+                    .if_not_exists()
+                    .name("unique-user-lowercase-email")
+                    .table(Users::Table)
+                    .col(Users::LowercaseEmail)
+                    .unique(),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v17(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    // Optional set of allowed values for the string attributes, as a JSON list.
+    transaction
+        .execute(
+            builder.build(Table::alter().table(UserAttributeSchema::Table).add_column(
+                ColumnDef::new(UserAttributeSchema::UserAttributeSchemaAllowedValues).text(),
+            )),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v18(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    // Hash of the password exposed to the trusted readers of `userPassword`, if configured.
+    transaction
+        .execute(
+            builder.build(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column(ColumnDef::new(Users::ReplicatedPasswordHash).text()),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v19(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    // The refresh tokens are now grouped in sessions. The table is created again with the new
+    // columns by `jwt_sql_tables::init_table`, and the users log in again.
+    transaction
+        .execute(builder.build(Table::drop().table(JwtRefreshStorage::Table).if_exists()))
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v20(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    // The disabled users can't log in, but keep their data and memberships.
+    transaction
+        .execute(
+            builder.build(
+                Table::alter().table(Users::Table).add_column(
+                    ColumnDef::new(Users::IsEnabled)
+                        .boolean()
+                        .not_null()
+                        .default(true),
+                ),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v21(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    // After this date, the users are treated as disabled.
+    transaction
+        .execute(
+            builder.build(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column(ColumnDef::new(Users::ExpirationDate).date_time().null()),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v22(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    // The serialized filter of the dynamic groups, whose members are computed.
+    transaction
+        .execute(
+            builder.build(
+                Table::alter()
+                    .table(Groups::Table)
+                    .add_column(ColumnDef::new(Groups::DynamicFilter).text().null()),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v23(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    // The groups nested in other groups.
+    transaction
+        .execute(
+            builder.build(
+                Table::create()
+                    .table(GroupMemberships::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(GroupMemberships::ParentGroupId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(GroupMemberships::MemberGroupId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("GroupMembershipParentForeignKey")
+                            .from(GroupMemberships::Table, GroupMemberships::ParentGroupId)
+                            .to(Groups::Table, Groups::GroupId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("GroupMembershipMemberForeignKey")
+                            .from(GroupMemberships::Table, GroupMemberships::MemberGroupId)
+                            .to(Groups::Table, Groups::GroupId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(GroupMemberships::ParentGroupId)
+                            .col(GroupMemberships::MemberGroupId),
+                    ),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v24(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    // The last successful login or bind of the users.
+    transaction
+        .execute(
+            builder.build(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column(ColumnDef::new(Users::LastLogin).date_time().null()),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v25(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    // The date of the last failed login, for the monitoring. The failures are now tracked even
+    // when the lockouts are disabled.
+    transaction
+        .execute(
+            builder.build(
+                Table::alter().table(UserLockouts::Table).add_column(
+                    ColumnDef::new(UserLockouts::LastFailureDate)
+                        .date_time()
+                        .null(),
+                ),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v26(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    // The WebAuthn credentials of the users, serialized as JSON.
+    transaction
+        .execute(
+            builder.build(
+                Table::create()
+                    .table(Passkeys::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Passkeys::PasskeyId)
+                            .integer()
+                            .auto_increment()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Passkeys::UserId).string_len(255).not_null())
+                    .col(ColumnDef::new(Passkeys::Name).string_len(255).not_null())
+                    .col(ColumnDef::new(Passkeys::Credential).text().not_null())
+                    .col(
+                        ColumnDef::new(Passkeys::CreationDate)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Passkeys::LastUsedDate).date_time().null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("PasskeysUserForeignKey")
+                            .from(Passkeys::Table, Passkeys::UserId)
+                            .to(Users::Table, Users::UserId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    ),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+async fn migrate_to_v27(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    // One row per value of the string attributes, so that the filters can match the values of the
+    // lists and ignore the case without decoding the serialized values.
+    transaction
+        .execute(
+            builder.build(
+                Table::create()
+                    .table(UserAttributeIndex::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(UserAttributeIndex::UserAttributeIndexId)
+                            .integer()
+                            .auto_increment()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(UserAttributeIndex::UserAttributeIndexUserId)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(UserAttributeIndex::UserAttributeIndexName)
+                            .string_len(64)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(UserAttributeIndex::UserAttributeIndexValue)
+                            .blob(sea_query::BlobSize::Long)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(UserAttributeIndex::UserAttributeIndexLowercaseValue)
+                            .text()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("UserAttributeIndexUserIdForeignKey")
+                            .from(
+                                UserAttributeIndex::Table,
+                                UserAttributeIndex::UserAttributeIndexUserId,
+                            )
+                            .to(Users::Table, Users::UserId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("UserAttributeIndexNameForeignKey")
+                            .from(
+                                UserAttributeIndex::Table,
+                                UserAttributeIndex::UserAttributeIndexName,
+                            )
+                            .to(
+                                UserAttributeSchema::Table,
+                                UserAttributeSchema::UserAttributeSchemaName,
+                            )
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    ),
+            ),
+        )
+        .await?;
+    transaction
+        .execute(
+            builder.build(
+                Table::create()
+                    .table(GroupAttributeIndex::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(GroupAttributeIndex::GroupAttributeIndexId)
+                            .integer()
+                            .auto_increment()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(GroupAttributeIndex::GroupAttributeIndexGroupId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(GroupAttributeIndex::GroupAttributeIndexName)
+                            .string_len(64)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(GroupAttributeIndex::GroupAttributeIndexValue)
+                            .blob(sea_query::BlobSize::Long)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(GroupAttributeIndex::GroupAttributeIndexLowercaseValue)
+                            .text()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("GroupAttributeIndexGroupIdForeignKey")
+                            .from(
+                                GroupAttributeIndex::Table,
+                                GroupAttributeIndex::GroupAttributeIndexGroupId,
+                            )
+                            .to(Groups::Table, Groups::GroupId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("GroupAttributeIndexNameForeignKey")
+                            .from(
+                                GroupAttributeIndex::Table,
+                                GroupAttributeIndex::GroupAttributeIndexName,
+                            )
+                            .to(
+                                GroupAttributeSchema::Table,
+                                GroupAttributeSchema::GroupAttributeSchemaName,
+                            )
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    ),
+            ),
+        )
+        .await?;
+
+    #[derive(FromQueryResult)]
+    struct UserAttributeSchemaType {
+        user_attribute_schema_name: String,
+        user_attribute_schema_type: AttributeType,
+        user_attribute_schema_is_list: bool,
+    }
+    #[derive(FromQueryResult)]
+    struct UserAttributeValue {
+        user_attribute_user_id: String,
+        user_attribute_name: String,
+        user_attribute_value: Serialized,
+    }
+    let user_schema = UserAttributeSchemaType::find_by_statement(builder.build(
+        Query::select().from(UserAttributeSchema::Table).columns([
+            UserAttributeSchema::UserAttributeSchemaName,
+            UserAttributeSchema::UserAttributeSchemaType,
+            UserAttributeSchema::UserAttributeSchemaIsList,
+        ]),
+    ))
+    .all(&transaction)
+    .await?;
+    let user_attributes = UserAttributeValue::find_by_statement(builder.build(
+        Query::select().from(UserAttributes::Table).columns([
+            UserAttributes::UserAttributeUserId,
+            UserAttributes::UserAttributeName,
+            UserAttributes::UserAttributeValue,
+        ]),
+    ))
+    .all(&transaction)
+    .await?;
+    for attribute in user_attributes {
+        let schema = match user_schema
+            .iter()
+            .find(|s| s.user_attribute_schema_name == attribute.user_attribute_name)
+        {
+            Some(schema) => schema,
+            None => continue,
+        };
+        for value in attribute.user_attribute_value.string_values(
+            schema.user_attribute_schema_type,
+            schema.user_attribute_schema_is_list,
+        ) {
+            transaction
+                .execute(
+                    builder.build(
+                        Query::insert()
+                            .into_table(UserAttributeIndex::Table)
+                            .columns([
+                                UserAttributeIndex::UserAttributeIndexUserId,
+                                UserAttributeIndex::UserAttributeIndexName,
+                                UserAttributeIndex::UserAttributeIndexValue,
+                                UserAttributeIndex::UserAttributeIndexLowercaseValue,
+                            ])
+                            .values_panic([
+                                attribute.user_attribute_user_id.clone().into(),
+                                attribute.user_attribute_name.clone().into(),
+                                Serialized::from(&value).into(),
+                                value.to_lowercase().into(),
+                            ]),
+                    ),
+                )
+                .await?;
+        }
+    }
+
+    #[derive(FromQueryResult)]
+    struct GroupAttributeSchemaType {
+        group_attribute_schema_name: String,
+        group_attribute_schema_type: AttributeType,
+        group_attribute_schema_is_list: bool,
+    }
+    #[derive(FromQueryResult)]
+    struct GroupAttributeValue {
+        group_attribute_group_id: GroupId,
+        group_attribute_name: String,
+        group_attribute_value: Serialized,
+    }
+    let group_schema = GroupAttributeSchemaType::find_by_statement(builder.build(
+        Query::select().from(GroupAttributeSchema::Table).columns([
+            GroupAttributeSchema::GroupAttributeSchemaName,
+            GroupAttributeSchema::GroupAttributeSchemaType,
+            GroupAttributeSchema::GroupAttributeSchemaIsList,
+        ]),
+    ))
+    .all(&transaction)
+    .await?;
+    let group_attributes = GroupAttributeValue::find_by_statement(builder.build(
+        Query::select().from(GroupAttributes::Table).columns([
+            GroupAttributes::GroupAttributeGroupId,
+            GroupAttributes::GroupAttributeName,
+            GroupAttributes::GroupAttributeValue,
+        ]),
+    ))
+    .all(&transaction)
+    .await?;
+    for attribute in group_attributes {
+        let schema = match group_schema
+            .iter()
+            .find(|s| s.group_attribute_schema_name == attribute.group_attribute_name)
+        {
+            Some(schema) => schema,
+            None => continue,
+        };
+        for value in attribute.group_attribute_value.string_values(
+            schema.group_attribute_schema_type,
+            schema.group_attribute_schema_is_list,
+        ) {
+            transaction
+                .execute(
+                    builder.build(
+                        Query::insert()
+                            .into_table(GroupAttributeIndex::Table)
+                            .columns([
+                                GroupAttributeIndex::GroupAttributeIndexGroupId,
+                                GroupAttributeIndex::GroupAttributeIndexName,
+                                GroupAttributeIndex::GroupAttributeIndexValue,
+                                GroupAttributeIndex::GroupAttributeIndexLowercaseValue,
+                            ])
+                            .values_panic([
+                                attribute.group_attribute_group_id.into(),
+                                attribute.group_attribute_name.clone().into(),
+                                Serialized::from(&value).into(),
+                                value.to_lowercase().into(),
+                            ]),
+                    ),
+                )
+                .await?;
+        }
+    }
+    Ok(transaction)
+}
+
+async fn migrate_to_v28(transaction: DatabaseTransaction) -> Result<DatabaseTransaction, DbErr> {
+    let builder = transaction.get_database_backend();
+    // The uid numbers come from a counter instead of the highest one plus one, so that the numbers
+    // of the deleted users are not reused.
+    transaction
+        .execute(
+            builder.build(
+                Table::alter().table(Metadata::Table).add_column(
+                    ColumnDef::new(Metadata::NextUidNumber)
+                        .integer()
+                        .not_null()
+                        .default(1),
+                ),
+            ),
+        )
+        .await?;
+    // Concurrent creations could give the same number to several users: the ones created last get
+    // new numbers.
+    #[derive(FromQueryResult)]
+    struct UserUidNumber {
+        user_id: String,
+        uid_number: i32,
+    }
+    let users = UserUidNumber::find_by_statement(
+        builder.build(
+            Query::select()
+                .from(Users::Table)
+                .columns([Users::UserId, Users::UidNumber])
+                .order_by_columns([
+                    (Users::UidNumber, Order::Asc),
+                    (Users::CreationDate, Order::Asc),
+                    (Users::UserId, Order::Asc),
+                ]),
+        ),
+    )
+    .all(&transaction)
+    .await?;
+    let mut next_uid_number = users.iter().map(|u| u.uid_number).max().unwrap_or_default() + 1;
+    let mut renumbered_users = Vec::new();
+    for (_, users) in &users.into_iter().group_by(|u| u.uid_number) {
+        for user in users.skip(1) {
+            warn!(
+                "User {} had the same uidNumber {} as another user, changed to {}",
+                user.user_id, user.uid_number, next_uid_number
+            );
+            renumbered_users.push((user.user_id, next_uid_number));
+            next_uid_number += 1;
+        }
+    }
+    for (user_id, uid_number) in renumbered_users {
+        transaction
+            .execute(
+                builder.build(
+                    Query::update()
+                        .table(Users::Table)
+                        .value(Users::UidNumber, Value::from(uid_number))
+                        .and_where(Expr::col(Users::UserId).eq(user_id)),
+                ),
+            )
+            .await?;
+    }
+    transaction
+        .execute(
+            builder.build(
+                Query::update()
+                    .table(Metadata::Table)
+                    .value(Metadata::NextUidNumber, Value::from(next_uid_number)),
+            ),
+        )
+        .await?;
+    transaction
+        .execute(
+            builder.build(
+                Index::create()
+                    .if_not_exists()
+                    .name("unique-user-uid-number")
+                    .table(Users::Table)
+                    .col(Users::UidNumber)
+                    .unique(),
+            ),
+        )
+        .await?;
+    Ok(transaction)
+}
+
+/// What each migration does, starting with the migration to version 2.
+const MIGRATION_DESCRIPTIONS: [&str; (LAST_SCHEMA_VERSION.0 - 1) as usize] = [
+    "Allow nulls in the display names",
+    "Allow nulls in the first and last names",
+    "Make the emails and UUIDs unique",
+    "Move the user attributes to the tables of custom attributes",
+    "Add the uidNumber of the users",
+    "Add the date of the last password change",
+    "Add the case sensitivity of the attributes",
+    "Add the modification date of the users",
+    "Add the TOTP secrets",
+    "Add the lockouts after failed logins",
+    "Add the password history",
+    "Add the API tokens",
+    "Add the soft deletion of the users",
+    "Add the audit log",
+    "Make the user ids and emails unique regardless of the case (logs the duplicates)",
+    "Add the allowed values of the attributes",
+    "Add the replicated password hashes",
+    "Group the refresh tokens in sessions (drops the existing sessions)",
+    "Add the disabled users",
+    "Add the expiration date of the users",
+    "Add the dynamic groups",
+    "Add the nested groups",
+    "Add the date of the last login",
+    "Add the date of the last failed login",
+    "Add the passkeys of the users",
+    "Index the values of the string attributes, for the filters",
+    "Give the uidNumbers from a counter, and make them unique",
+];
+
+/// The description of the migration to this version, from 2 to the last version.
+pub fn migration_description(version: SchemaVersion) -> &'static str {
+    MIGRATION_DESCRIPTIONS[(version.0 - 2) as usize]
+}
+
+// This is needed to make an array of async functions.
+macro_rules! to_sync {
+    ($l:ident) => {
+        move |transaction| -> std::pin::Pin<
+            Box<dyn std::future::Future<Output = Result<DatabaseTransaction, DbErr>>>,
+        > { Box::pin($l(transaction)) }
+    };
+}
+
+pub async fn migrate_from_version(
+    pool: &DbConnection,
+    version: SchemaVersion,
+    last_version: SchemaVersion,
+) -> anyhow::Result<()> {
+    match version.cmp(&last_version) {
+        std::cmp::Ordering::Less => (),
+        std::cmp::Ordering::Equal => return Ok(()),
+        std::cmp::Ordering::Greater => anyhow::bail!("DB version downgrading is not supported"),
+    }
+    info!("Upgrading DB schema from version {}", version.0);
+    run_migrations(pool, version, last_version).await
+}
+
+/// Runs the pending migrations in a transaction that is rolled back: with the logging of the
+/// statements, they are printed without being applied.
+pub async fn dry_run_migrations(
+    pool: &DbConnection,
+    version: SchemaVersion,
+    last_version: SchemaVersion,
+) -> anyhow::Result<()> {
+    if pool.get_database_backend() == DbBackend::MySql {
+        // The schema changes commit the transaction implicitly.
+        anyhow::bail!("The dry run is not supported with MySQL/MariaDB, whose schema changes can't be rolled back");
+    }
+    info!(
+        "Dry run of the DB schema upgrade from version {}",
+        version.0
+    );
+    let transaction = pool.begin().await?;
+    run_migrations(&transaction, version, last_version).await?;
+    transaction.rollback().await?;
+    Ok(())
+}
+
+/// Each migration runs in its own transaction, nested in a transaction for the dry runs.
+async fn run_migrations<C: TransactionTrait>(
+    connection: &C,
+    version: SchemaVersion,
+    last_version: SchemaVersion,
+) -> anyhow::Result<()> {
     let migrations = [
         to_sync!(migrate_to_v2),
         to_sync!(migrate_to_v3),
         to_sync!(migrate_to_v4),
         to_sync!(migrate_to_v5),
+        to_sync!(migrate_to_v6),
+        to_sync!(migrate_to_v7),
+        to_sync!(migrate_to_v8),
+        to_sync!(migrate_to_v9),
+        to_sync!(migrate_to_v10),
+        to_sync!(migrate_to_v11),
+        to_sync!(migrate_to_v12),
+        to_sync!(migrate_to_v13),
+        to_sync!(migrate_to_v14),
+        to_sync!(migrate_to_v15),
+        to_sync!(migrate_to_v16),
+        to_sync!(migrate_to_v17),
+        to_sync!(migrate_to_v18),
+        to_sync!(migrate_to_v19),
+        to_sync!(migrate_to_v20),
+        to_sync!(migrate_to_v21),
+        to_sync!(migrate_to_v22),
+        to_sync!(migrate_to_v23),
+        to_sync!(migrate_to_v24),
+        to_sync!(migrate_to_v25),
+        to_sync!(migrate_to_v26),
+        to_sync!(migrate_to_v27),
+        to_sync!(migrate_to_v28),
     ];
     assert_eq!(migrations.len(), (LAST_SCHEMA_VERSION.0 - 1) as usize);
     for migration in 2..=last_version.0 {
         if version < SchemaVersion(migration) && SchemaVersion(migration) <= last_version {
-            info!("Upgrading DB schema to version {}", migration);
-            let transaction = pool.begin().await?;
+            info!(
+                "Upgrading DB schema to version {}: {}",
+                migration,
+                migration_description(SchemaVersion(migration))
+            );
+            let transaction = connection.begin().await?;
             let transaction = migrations[(migration - 2) as usize](transaction).await?;
             let builder = transaction.get_database_backend();
             transaction
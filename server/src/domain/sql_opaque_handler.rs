@@ -1,37 +1,57 @@
 use super::{
     error::{DomainError, Result},
     handler::{BindRequest, LoginHandler},
-    model::{self, UserColumn},
+    imported_password::{
+        check_imported_password_hash, hash_password_for_replication, is_current_replication_hash,
+        is_imported_password_hash, verify_imported_password_hash,
+    },
+    model::{self, PasswordHistoryColumn, PasswordResetTokensColumn, UserColumn},
     opaque_handler::{login, registration, OpaqueHandler},
+    session_handler::SessionHandler,
     sql_backend_handler::SqlBackendHandler,
     types::UserId,
 };
 use async_trait::async_trait;
 use base64::Engine;
 use lldap_auth::opaque;
-use sea_orm::{ActiveModelTrait, ActiveValue, EntityTrait, QuerySelect};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ActiveValue, ColumnTrait, Condition, EntityTrait,
+    QueryFilter, QueryOrder, QuerySelect,
+};
 use secstr::SecUtf8;
-use tracing::{debug, instrument};
+use tracing::{debug, instrument, warn};
 
 type SqlOpaqueHandler = SqlBackendHandler;
 
+#[cfg(test)]
+thread_local! {
+    /// The OPAQUE verifications run by the current thread, real or simulated.
+    static PASSWORD_VERIFICATIONS: std::cell::Cell<usize> = std::cell::Cell::new(0);
+}
+
+/// Without a password file, the verification runs against the fake record of the protocol, and
+/// fails after the same Argon2 work.
 #[instrument(skip_all, level = "debug", err)]
 fn passwords_match(
-    password_file_bytes: &[u8],
+    password_file_bytes: Option<&[u8]>,
     clear_password: &str,
     server_setup: &opaque::server::ServerSetup,
     username: &UserId,
 ) -> Result<()> {
     use opaque::{client, server};
+    #[cfg(test)]
+    PASSWORD_VERIFICATIONS.with(|count| count.set(count.get() + 1));
     let mut rng = rand::rngs::OsRng;
     let client_login_start_result = client::login::start_login(clear_password, &mut rng)?;
 
-    let password_file = server::ServerRegistration::deserialize(password_file_bytes)
+    let password_file = password_file_bytes
+        .map(server::ServerRegistration::deserialize)
+        .transpose()
         .map_err(opaque::AuthenticationError::ProtocolError)?;
     let server_login_start_result = server::login::start_login(
         &mut rng,
         server_setup,
-        Some(password_file),
+        password_file,
         client_login_start_result.message,
         username.as_str(),
     )?;
@@ -42,17 +62,67 @@ fn passwords_match(
     Ok(())
 }
 
+/// Checks the password against either kind of stored hash.
+fn verify_password(
+    password_hash: &[u8],
+    clear_password: &str,
+    server_setup: &opaque::server::ServerSetup,
+    username: &UserId,
+) -> Result<()> {
+    if !is_imported_password_hash(password_hash) {
+        passwords_match(Some(password_hash), clear_password, server_setup, username)
+    } else if verify_imported_password_hash(password_hash, clear_password) {
+        Ok(())
+    } else {
+        Err(DomainError::AuthenticationError(format!(
+            " for user '{}'",
+            username
+        )))
+    }
+}
+
+/// Spends the time of a password verification when there is no password to check, e.g. for an
+/// unknown user: otherwise the faster failure would reveal which users exist.
+fn simulate_password_verification(
+    clear_password: &str,
+    server_setup: &opaque::server::ServerSetup,
+    username: &UserId,
+) {
+    let _ = passwords_match(None, clear_password, server_setup, username);
+}
+
+/// The last login dates are precise to the minute.
+const LAST_LOGIN_UPDATE_INTERVAL_SECONDS: i64 = 60;
+
 impl SqlBackendHandler {
-    fn get_orion_secret_key(&self) -> Result<orion::aead::SecretKey> {
+    pub(crate) fn get_orion_secret_key(&self) -> Result<orion::aead::SecretKey> {
         Ok(orion::aead::SecretKey::from_slice(
             self.config.get_server_keys().private(),
         )?)
     }
 
+    /// Whether the user exists and was disabled, or their account expired. Like the lockouts,
+    /// the callers should fail the authentication as if the password was wrong.
+    #[instrument(skip_all, level = "debug", err)]
+    pub(crate) async fn is_user_disabled(&self, user_id: &UserId) -> Result<bool> {
+        let now = chrono::Utc::now().naive_utc();
+        Ok(model::User::find_by_id(user_id.clone())
+            .select_only()
+            .column(UserColumn::IsEnabled)
+            .column(UserColumn::ExpirationDate)
+            .into_tuple::<(bool, Option<chrono::NaiveDateTime>)>()
+            .one(&self.sql_pool)
+            .await?
+            .map_or(false, |(is_enabled, expiration_date)| {
+                !is_enabled || expiration_date.map_or(false, |date| date <= now)
+            }))
+    }
+
     #[instrument(skip_all, level = "debug", err)]
     async fn get_password_file_for_user(&self, user_id: UserId) -> Result<Option<Vec<u8>>> {
         // Fetch the previously registered password file from the DB.
         Ok(model::User::find_by_id(user_id)
+            .filter(UserColumn::DeletedDate.is_null())
             .select_only()
             .column(UserColumn::PasswordHash)
             .into_tuple::<(Option<Vec<u8>>,)>()
@@ -60,30 +130,181 @@ impl SqlBackendHandler {
             .await?
             .and_then(|u| u.0))
     }
+
+    /// Whether the hash exposed as `userPassword` is configured, and missing or outdated: in another
+    /// format, or with other argon2 parameters.
+    #[instrument(skip_all, level = "debug", err)]
+    async fn needs_replicated_password_hash(&self, user_id: &UserId) -> Result<bool> {
+        let options = &self.config.ldap_password_replication;
+        let format = match options.format {
+            Some(format) => format,
+            None => return Ok(false),
+        };
+        let argon2_params = options
+            .argon2_params()
+            .map_err(|e| DomainError::InternalError(e.to_string()))?;
+        let hash = model::User::find_by_id(user_id.clone())
+            .select_only()
+            .column(UserColumn::ReplicatedPasswordHash)
+            .into_tuple::<(Option<String>,)>()
+            .one(&self.sql_pool)
+            .await?
+            .and_then(|(hash,)| hash);
+        Ok(!hash.map_or(false, |hash| {
+            is_current_replication_hash(&hash, format, &argon2_params)
+        }))
+    }
+
+    /// Stores the hash of the password exposed as `userPassword`, if configured.
+    #[instrument(skip_all, level = "debug", err)]
+    async fn set_replicated_password_hash(&self, user_id: &UserId, password: &str) -> Result<()> {
+        let options = &self.config.ldap_password_replication;
+        let format = match options.format {
+            Some(format) => format,
+            None => return Ok(()),
+        };
+        let argon2_params = options
+            .argon2_params()
+            .map_err(|e| DomainError::InternalError(e.to_string()))?;
+        let hash = hash_password_for_replication(format, &argon2_params, password)
+            .map_err(DomainError::InternalError)?;
+        model::User::update_many()
+            .col_expr(UserColumn::ReplicatedPasswordHash, Expr::value(hash))
+            .filter(UserColumn::UserId.eq(user_id))
+            .exec(&self.sql_pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Records a successful login or bind. The date is only written if the previous one is more
+    /// than a minute old, to avoid a write on every bind of the busy clients. The login succeeds
+    /// even if it fails.
+    pub(crate) async fn record_login(&self, user_id: &UserId) {
+        let now = chrono::Utc::now().naive_utc();
+        if let Err(e) = model::User::update_many()
+            .col_expr(UserColumn::LastLogin, Expr::value(now))
+            .filter(UserColumn::UserId.eq(user_id))
+            .filter(
+                Condition::any().add(UserColumn::LastLogin.is_null()).add(
+                    UserColumn::LastLogin
+                        .lt(now - chrono::Duration::seconds(LAST_LOGIN_UPDATE_INTERVAL_SECONDS)),
+                ),
+            )
+            .exec(&self.sql_pool)
+            .await
+        {
+            warn!(r#"Could not record the login of "{}": {}"#, user_id, e);
+        }
+    }
+
+    /// Adds the new password file to the user's history, and prunes the oldest ones.
+    #[instrument(skip_all, level = "debug", err)]
+    async fn record_password_history(
+        &self,
+        user_id: &UserId,
+        password_hash: Vec<u8>,
+    ) -> Result<()> {
+        let history_size = self.config.password_policy.history_size as usize;
+        if history_size == 0 {
+            return Ok(());
+        }
+        model::password_history::ActiveModel {
+            user_id: ActiveValue::Set(user_id.clone()),
+            password_hash: ActiveValue::Set(password_hash),
+            creation_date: ActiveValue::Set(chrono::Utc::now().naive_utc()),
+            ..Default::default()
+        }
+        .insert(&self.sql_pool)
+        .await?;
+        let old_entries = model::PasswordHistory::find()
+            .select_only()
+            .column(PasswordHistoryColumn::PasswordHistoryId)
+            .filter(PasswordHistoryColumn::UserId.eq(user_id))
+            .order_by_desc(PasswordHistoryColumn::PasswordHistoryId)
+            .into_tuple::<(i32,)>()
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .skip(history_size)
+            .map(|(id,)| id)
+            .collect::<Vec<_>>();
+        if !old_entries.is_empty() {
+            model::PasswordHistory::delete_many()
+                .filter(PasswordHistoryColumn::PasswordHistoryId.is_in(old_entries))
+                .exec(&self.sql_pool)
+                .await?;
+        }
+        Ok(())
+    }
 }
 
 #[async_trait]
 impl LoginHandler for SqlBackendHandler {
     #[instrument(skip_all, level = "debug", err)]
     async fn bind(&self, request: BindRequest) -> Result<()> {
-        if let Some(password_hash) = self
-            .get_password_file_for_user(request.name.clone())
-            .await?
-        {
-            if let Err(e) = passwords_match(
+        let password_hash = if self.is_locked_out(&request.name).await? {
+            debug!(r#"User "{}" is locked out"#, &request.name);
+            None
+        } else if self.is_user_disabled(&request.name).await? {
+            debug!(r#"User "{}" is disabled"#, &request.name);
+            None
+        } else {
+            let password_hash = self
+                .get_password_file_for_user(request.name.clone())
+                .await?;
+            if password_hash.is_none() {
+                debug!(
+                    r#"User "{}" doesn't exist or has no password"#,
+                    &request.name
+                );
+            }
+            password_hash
+        };
+        if let Some(password_hash) = password_hash {
+            if let Err(e) = verify_password(
                 &password_hash,
                 &request.password,
                 self.config.get_server_setup(),
                 &request.name,
             ) {
                 debug!(r#"Invalid password for "{}": {}"#, &request.name, e);
+                self.record_authentication_failure(&request.name).await?;
             } else {
+                self.reset_authentication_failures(&request.name).await?;
+                self.record_login(&request.name).await;
+                if is_imported_password_hash(&password_hash) {
+                    // Replace the imported hash, now that we know the password. The bind succeeds
+                    // even if it fails: the hash is still valid.
+                    if let Err(e) = register_password(
+                        self,
+                        &request.name,
+                        &SecUtf8::from(request.password.clone()),
+                    )
+                    .await
+                    {
+                        warn!(
+                            r#"Could not rehash the password of "{}": {}"#,
+                            &request.name, e
+                        );
+                    }
+                } else if self.needs_replicated_password_hash(&request.name).await? {
+                    if let Err(e) = self
+                        .set_replicated_password_hash(&request.name, &request.password)
+                        .await
+                    {
+                        warn!(
+                            r#"Could not store the replicated password of "{}": {}"#,
+                            &request.name, e
+                        );
+                    }
+                }
                 return Ok(());
             }
         } else {
-            debug!(
-                r#"User "{}" doesn't exist or has no password"#,
-                &request.name
+            simulate_password_verification(
+                &request.password,
+                self.config.get_server_setup(),
+                &request.name,
             );
         }
         Err(DomainError::AuthenticationError(format!(
@@ -91,6 +312,72 @@ impl LoginHandler for SqlBackendHandler {
             request.name
         )))
     }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn reject_bind(&self, request: BindRequest) -> Result<()> {
+        simulate_password_verification(
+            &request.password,
+            self.config.get_server_setup(),
+            &request.name,
+        );
+        Err(DomainError::AuthenticationError(format!(
+            " for user '{}'",
+            request.name
+        )))
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn is_password_recently_used(&self, user_id: &UserId, password: &str) -> Result<bool> {
+        if self.config.password_policy.history_size == 0 {
+            return Ok(false);
+        }
+        let mut password_files = model::PasswordHistory::find()
+            .select_only()
+            .column(PasswordHistoryColumn::PasswordHash)
+            .filter(PasswordHistoryColumn::UserId.eq(user_id))
+            .into_tuple::<(Vec<u8>,)>()
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(|(password_hash,)| password_hash)
+            .collect::<Vec<_>>();
+        // The current password may predate the history.
+        if let Some(current) = self.get_password_file_for_user(user_id.clone()).await? {
+            if !password_files.contains(&current) {
+                password_files.push(current);
+            }
+        }
+        Ok(password_files.iter().any(|password_file| {
+            verify_password(
+                password_file,
+                password,
+                self.config.get_server_setup(),
+                user_id,
+            )
+            .is_ok()
+        }))
+    }
+
+    #[instrument(skip_all, level = "debug", err, fields(user_id = %user_id))]
+    async fn import_password_hash(&self, user_id: &UserId, password_hash: &str) -> Result<()> {
+        check_imported_password_hash(password_hash).map_err(DomainError::InternalError)?;
+        let res = model::User::update_many()
+            .col_expr(
+                UserColumn::PasswordHash,
+                Expr::value(password_hash.as_bytes().to_vec()),
+            )
+            .filter(UserColumn::UserId.eq(user_id))
+            .filter(UserColumn::DeletedDate.is_null())
+            .exec(&self.sql_pool)
+            .await?;
+        if res.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such user: '{}'",
+                user_id
+            )));
+        }
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -103,6 +390,8 @@ impl OpaqueHandler for SqlOpaqueHandler {
         let maybe_password_file = self
             .get_password_file_for_user(UserId::new(&request.username))
             .await?
+            // The imported hashes can only be checked by the binds.
+            .filter(|bytes| !is_imported_password_hash(bytes))
             .map(|bytes| {
                 opaque::server::ServerRegistration::deserialize(&bytes).map_err(|_| {
                     DomainError::InternalError(format!(
@@ -145,13 +434,34 @@ impl OpaqueHandler for SqlOpaqueHandler {
             &secret_key,
             &base64::engine::general_purpose::STANDARD.decode(&request.server_data)?,
         )?)?;
+        let user_id = UserId::new(&username);
+        if self.is_locked_out(&user_id).await? {
+            debug!(r#"User "{}" is locked out"#, &user_id);
+            return Err(DomainError::AuthenticationError(format!(
+                " for user '{}'",
+                user_id
+            )));
+        }
+        if self.is_user_disabled(&user_id).await? {
+            debug!(r#"User "{}" is disabled"#, &user_id);
+            return Err(DomainError::AuthenticationError(format!(
+                " for user '{}'",
+                user_id
+            )));
+        }
         // Finish the login: this makes sure the client data is correct, and gives a session key we
         // don't need.
-        let _session_key =
-            opaque::server::login::finish_login(server_login, request.credential_finalization)?
-                .session_key;
-
-        Ok(UserId::new(&username))
+        match opaque::server::login::finish_login(server_login, request.credential_finalization) {
+            Ok(_session_key) => {
+                self.reset_authentication_failures(&user_id).await?;
+                self.record_login(&user_id).await;
+                Ok(user_id)
+            }
+            Err(e) => {
+                self.record_authentication_failure(&user_id).await?;
+                Err(e.into())
+            }
+        }
     }
 
     #[instrument(skip_all, level = "debug", err)]
@@ -173,6 +483,7 @@ impl OpaqueHandler for SqlOpaqueHandler {
         Ok(registration::ServerRegistrationStartResponse {
             server_data: base64::engine::general_purpose::STANDARD.encode(encrypted_state),
             registration_response: start_response.message,
+            password_policy: None,
         })
     }
 
@@ -189,13 +500,26 @@ impl OpaqueHandler for SqlOpaqueHandler {
 
         let password_file =
             opaque::server::registration::get_password_file(request.registration_upload);
+        let user_id = UserId::new(&username);
+        let password_hash = password_file.serialize();
         // Set the user password to the new password.
         let user_update = model::users::ActiveModel {
-            user_id: ActiveValue::Set(UserId::new(&username)),
-            password_hash: ActiveValue::Set(Some(password_file.serialize())),
+            user_id: ActiveValue::Set(user_id.clone()),
+            password_hash: ActiveValue::Set(Some(password_hash.clone())),
+            password_modified_date: ActiveValue::Set(Some(chrono::Utc::now().naive_utc())),
+            // Outdated, computed again at the next bind.
+            replicated_password_hash: ActiveValue::Set(None),
             ..Default::default()
         };
         user_update.update(&self.sql_pool).await?;
+        self.record_password_history(&user_id, password_hash)
+            .await?;
+        // A new password logs out all the sessions, and invalidates the pending reset links.
+        self.revoke_all_sessions(&user_id).await?;
+        model::PasswordResetTokens::delete_many()
+            .filter(PasswordResetTokensColumn::UserId.eq(&user_id))
+            .exec(&self.sql_pool)
+            .await?;
         Ok(())
     }
 }
@@ -227,6 +551,9 @@ pub(crate) async fn register_password(
             server_data: start_response.server_data,
             registration_upload: registration_finish.message,
         })
+        .await?;
+    opaque_handler
+        .set_replicated_password_hash(username, password.unsecure())
         .await
 }
 
@@ -257,6 +584,7 @@ mod tests {
             .login_finish(ClientLoginFinishRequest {
                 server_data: start_response.server_data,
                 credential_finalization: login_finish.message,
+                totp_code: None,
             })
             .await?;
         Ok(())
@@ -317,6 +645,64 @@ mod tests {
             .unwrap_err();
     }
 
+    #[tokio::test]
+    async fn test_bind_timing() {
+        let sql_pool = get_initialized_db().await;
+        let config = get_default_config();
+        let handler = SqlOpaqueHandler::new(config, sql_pool);
+        insert_user(&handler, "bob", "bob00").await;
+        let verifications = || PASSWORD_VERIFICATIONS.with(|count| count.get());
+        // All the failures run one verification, and fail with the same error.
+        for (name, rejected) in [("bob", false), ("andrew", false), ("bob", true)] {
+            let request = BindRequest {
+                name: UserId::new(name),
+                password: "wrong_password".to_string(),
+            };
+            let before = verifications();
+            let error = if rejected {
+                handler.reject_bind(request).await
+            } else {
+                handler.bind(request).await
+            }
+            .unwrap_err();
+            assert_eq!(verifications(), before + 1, "{}", name);
+            assert_eq!(
+                error.to_string(),
+                format!("Authentication error: ` for user '{}'`", name)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_last_login() -> Result<()> {
+        use crate::domain::handler::UserBackendHandler;
+        let sql_pool = get_initialized_db().await;
+        let config = get_default_config();
+        let handler = SqlOpaqueHandler::new(config, sql_pool);
+        insert_user(&handler, "bob", "bob00").await;
+        let bob = UserId::new("bob");
+        let bind = |password: &str| {
+            handler.bind(BindRequest {
+                name: bob.clone(),
+                password: password.to_string(),
+            })
+        };
+        assert_eq!(handler.get_user_details(&bob).await?.last_login, None);
+
+        bind("wrong_password").await.unwrap_err();
+        assert_eq!(handler.get_user_details(&bob).await?.last_login, None);
+
+        bind("bob00").await?;
+        let last_login = handler.get_user_details(&bob).await?.last_login;
+        assert!(last_login.is_some());
+
+        // Not updated again within the minute.
+        bind("bob00").await?;
+        attempt_login(&handler, "bob", "bob00").await?;
+        assert_eq!(handler.get_user_details(&bob).await?.last_login, last_login);
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_user_no_password() {
         let sql_pool = get_initialized_db().await;
@@ -332,4 +718,218 @@ mod tests {
             .await
             .unwrap_err();
     }
+
+    #[tokio::test]
+    async fn test_disabled_user() -> Result<()> {
+        use crate::domain::handler::UserBackendHandler;
+        let sql_pool = get_initialized_db().await;
+        let config = get_default_config();
+        let handler = SqlOpaqueHandler::new(config, sql_pool);
+        insert_user(&handler, "bob", "bob00").await;
+        let bob = UserId::new("bob");
+        let bind = || {
+            handler.bind(BindRequest {
+                name: bob.clone(),
+                password: "bob00".to_string(),
+            })
+        };
+
+        handler.set_user_enabled(&bob, false).await?;
+        bind().await.unwrap_err();
+        attempt_login(&handler, "bob", "bob00").await.unwrap_err();
+        assert!(!handler.get_user_details(&bob).await?.is_enabled);
+
+        handler.set_user_enabled(&bob, true).await?;
+        bind().await?;
+        attempt_login(&handler, "bob", "bob00").await?;
+
+        handler
+            .set_user_enabled(&UserId::new("andrew"), false)
+            .await
+            .unwrap_err();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_expired_user() -> Result<()> {
+        use crate::domain::handler::UserBackendHandler;
+        let sql_pool = get_initialized_db().await;
+        let config = get_default_config();
+        let handler = SqlOpaqueHandler::new(config, sql_pool);
+        insert_user(&handler, "bob", "bob00").await;
+        let bob = UserId::new("bob");
+        let bind = || {
+            handler.bind(BindRequest {
+                name: bob.clone(),
+                password: "bob00".to_string(),
+            })
+        };
+        let now = chrono::Utc::now().naive_utc();
+
+        handler
+            .set_user_expiration_date(&bob, Some(now + chrono::Duration::days(1)))
+            .await?;
+        bind().await?;
+
+        handler
+            .set_user_expiration_date(&bob, Some(now - chrono::Duration::days(1)))
+            .await?;
+        bind().await.unwrap_err();
+        attempt_login(&handler, "bob", "bob00").await.unwrap_err();
+
+        handler.set_user_expiration_date(&bob, None).await?;
+        bind().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_imported_password_hash() {
+        let sql_pool = get_initialized_db().await;
+        let config = get_default_config();
+        let handler = SqlOpaqueHandler::new(config, sql_pool);
+        insert_user_no_password(&handler, "bob").await;
+        let bob = UserId::new("bob");
+        handler
+            .import_password_hash(&bob, "{MD5}X03MO1qnZdYdgyfeuILPmQ==")
+            .await
+            .unwrap_err();
+        handler
+            .import_password_hash(&bob, "{SSHA}yrht1iYXEIkejLVu42JWkadd80RzYWx0c2FsdA==")
+            .await
+            .unwrap();
+        let bind = |password: &str| BindRequest {
+            name: bob.clone(),
+            password: password.to_owned(),
+        };
+        handler.bind(bind("wrong_password")).await.unwrap_err();
+        // The OPAQUE login needs a password file.
+        attempt_login(&handler, "bob", "password")
+            .await
+            .unwrap_err();
+        handler.bind(bind("password")).await.unwrap();
+        // The hash was replaced.
+        let password_file = handler
+            .get_password_file_for_user(bob.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!is_imported_password_hash(&password_file));
+        attempt_login(&handler, "bob", "password").await.unwrap();
+        handler.bind(bind("password")).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replicated_password_hash() {
+        use crate::domain::handler::UserBackendHandler;
+        let sql_pool = get_initialized_db().await;
+        let mut config = get_default_config();
+        config.ldap_password_replication.format =
+            Some(crate::infra::configuration::PasswordReplicationFormat::Ssha);
+        let handler = SqlOpaqueHandler::new(config, sql_pool);
+        // Registered with OPAQUE: the password is unknown.
+        insert_user(&handler, "bob", "bob00").await;
+        let bob = UserId::new("bob");
+        let get_hash = || async {
+            handler
+                .get_user_details(&bob)
+                .await
+                .unwrap()
+                .replicated_password_hash
+        };
+        assert_eq!(get_hash().await, None);
+        handler
+            .bind(BindRequest {
+                name: bob.clone(),
+                password: "bob00".to_owned(),
+            })
+            .await
+            .unwrap();
+        let hash = get_hash().await.unwrap();
+        assert!(verify_imported_password_hash(hash.as_bytes(), "bob00"));
+        register_password(&handler, &bob, &SecUtf8::from("bob01"))
+            .await
+            .unwrap();
+        let hash = get_hash().await.unwrap();
+        assert!(verify_imported_password_hash(hash.as_bytes(), "bob01"));
+    }
+
+    #[tokio::test]
+    async fn test_replicated_password_hash_rehash() {
+        use crate::domain::handler::UserBackendHandler;
+        let sql_pool = get_initialized_db().await;
+        let make_handler = |memory_kib| {
+            let mut config = get_default_config();
+            let options = &mut config.ldap_password_replication;
+            options.format = Some(crate::infra::configuration::PasswordReplicationFormat::Argon2);
+            options.argon2_memory_kib = memory_kib;
+            options.argon2_iterations = 1;
+            SqlOpaqueHandler::new(config, sql_pool.clone())
+        };
+        let bob = UserId::new("bob");
+        let bind_and_get_hash = |handler: SqlOpaqueHandler| {
+            let bob = bob.clone();
+            async move {
+                handler
+                    .bind(BindRequest {
+                        name: bob.clone(),
+                        password: "bob00".to_owned(),
+                    })
+                    .await
+                    .unwrap();
+                handler
+                    .get_user_details(&bob)
+                    .await
+                    .unwrap()
+                    .replicated_password_hash
+                    .unwrap()
+            }
+        };
+        insert_user(&make_handler(1024), "bob", "bob00").await;
+        let hash = bind_and_get_hash(make_handler(1024)).await;
+        assert!(hash.contains("m=1024,"), "{}", hash);
+        // Up to date: kept.
+        assert_eq!(bind_and_get_hash(make_handler(1024)).await, hash);
+        // Outdated parameters: recomputed at the bind.
+        let new_hash = bind_and_get_hash(make_handler(2048)).await;
+        assert!(new_hash.contains("m=2048,"), "{}", new_hash);
+        let parsed = argon2::PasswordHash::new(new_hash.strip_prefix("{ARGON2}").unwrap()).unwrap();
+        assert!(argon2::PasswordVerifier::verify_password(
+            &argon2::Argon2::default(),
+            b"bob00",
+            &parsed
+        )
+        .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_password_history() {
+        let sql_pool = get_initialized_db().await;
+        let mut config = get_default_config();
+        config.password_policy.history_size = 2;
+        let handler = SqlOpaqueHandler::new(config, sql_pool);
+        insert_user(&handler, "bob", "pass1").await;
+        let bob = UserId::new("bob");
+        for password in ["pass2", "pass3"] {
+            register_password(&handler, &bob, &SecUtf8::from(password))
+                .await
+                .unwrap();
+        }
+        assert!(handler
+            .is_password_recently_used(&bob, "pass3")
+            .await
+            .unwrap());
+        assert!(handler
+            .is_password_recently_used(&bob, "pass2")
+            .await
+            .unwrap());
+        // Pruned from the history.
+        assert!(!handler
+            .is_password_recently_used(&bob, "pass1")
+            .await
+            .unwrap());
+        assert!(!handler
+            .is_password_recently_used(&bob, "pass4")
+            .await
+            .unwrap());
+    }
 }
@@ -0,0 +1,506 @@
+use super::{
+    error::{DomainError, Result},
+    model::{self, PasskeysColumn, UserColumn},
+    passkey_handler::{PasskeyChallenge, PasskeyHandler, PasskeyInfo},
+    sql_backend_handler::SqlBackendHandler,
+    types::UserId,
+};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::RngCore;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, QueryFilter, QueryOrder, QuerySelect,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tracing::{debug, instrument};
+use webauthn_rs::{prelude as webauthn, Webauthn, WebauthnBuilder};
+
+/// The ceremonies must be finished within this delay.
+const CEREMONY_VALIDITY: Duration = Duration::from_secs(300);
+
+/// The state of a WebAuthn ceremony, kept by the server between the two steps.
+struct CeremonyState<T> {
+    user_id: UserId,
+    expires_at: Instant,
+    state: T,
+}
+
+/// The ongoing ceremonies, by random id. They are kept in memory, so the second step must reach
+/// the same instance as the first one.
+struct CeremonyStore<T> {
+    ceremonies: Arc<Mutex<HashMap<String, CeremonyState<T>>>>,
+}
+
+impl<T> Default for CeremonyStore<T> {
+    fn default() -> Self {
+        Self {
+            ceremonies: Arc::default(),
+        }
+    }
+}
+
+impl<T> Clone for CeremonyStore<T> {
+    fn clone(&self) -> Self {
+        Self {
+            ceremonies: self.ceremonies.clone(),
+        }
+    }
+}
+
+impl<T> CeremonyStore<T> {
+    fn insert(&self, user_id: &UserId, state: T) -> String {
+        let mut bytes = [0; 32];
+        rand::rngs::OsRng.fill_bytes(&mut bytes);
+        let id = URL_SAFE_NO_PAD.encode(bytes);
+        let now = Instant::now();
+        let mut ceremonies = self.ceremonies.lock().unwrap();
+        ceremonies.retain(|_, ceremony| ceremony.expires_at > now);
+        ceremonies.insert(
+            id.clone(),
+            CeremonyState {
+                user_id: user_id.clone(),
+                expires_at: now + CEREMONY_VALIDITY,
+                state,
+            },
+        );
+        id
+    }
+
+    /// The ceremonies can only be finished once: they are removed as soon as they are read. With
+    /// a `user_id`, the ceremonies of other users are left untouched.
+    fn take(&self, id: &str, user_id: Option<&UserId>) -> Result<CeremonyState<T>> {
+        let mut ceremonies = self.ceremonies.lock().unwrap();
+        if let (Some(user_id), Some(ceremony)) = (user_id, ceremonies.get(id)) {
+            if &ceremony.user_id != user_id {
+                return Err(DomainError::AuthenticationError(format!(
+                    "The passkey challenge is not for user '{}'",
+                    user_id
+                )));
+            }
+        }
+        ceremonies
+            .remove(id)
+            .filter(|ceremony| ceremony.expires_at > Instant::now())
+            .ok_or_else(|| {
+                DomainError::AuthenticationError(
+                    "The passkey challenge expired or was already used".to_owned(),
+                )
+            })
+    }
+}
+
+/// The states of the registrations and of the logins in progress.
+#[derive(Clone, Default)]
+pub struct PasskeyCeremonies {
+    registrations: CeremonyStore<webauthn::PasskeyRegistration>,
+    logins: CeremonyStore<webauthn::PasskeyAuthentication>,
+}
+
+fn invalid_credential(e: impl std::fmt::Display) -> DomainError {
+    DomainError::AuthenticationError(format!("Invalid passkey: {}", e))
+}
+
+fn parse_passkey(model: &model::passkeys::Model) -> Result<webauthn::Passkey> {
+    serde_json::from_str(&model.credential).map_err(|e| {
+        DomainError::InternalError(format!("Invalid passkey {}: {}", model.passkey_id, e))
+    })
+}
+
+fn serialize_passkey(passkey: &webauthn::Passkey) -> Result<String> {
+    serde_json::to_string(passkey).map_err(|e| DomainError::InternalError(e.to_string()))
+}
+
+impl SqlBackendHandler {
+    /// The relying party is the host of the web UI: the passkeys only work for this host.
+    fn get_webauthn(&self) -> Result<Webauthn> {
+        let url = &self.config.http_url;
+        let rp_id = url
+            .host_str()
+            .ok_or_else(|| DomainError::InternalError(format!("No host in the URL {}", url)))?;
+        WebauthnBuilder::new(rp_id, url)
+            .and_then(|builder| builder.rp_name("LLDAP").build())
+            .map_err(|e| DomainError::InternalError(format!("Invalid WebAuthn config: {}", e)))
+    }
+
+    async fn get_passkey_models(&self, user_id: &UserId) -> Result<Vec<model::passkeys::Model>> {
+        Ok(model::Passkeys::find()
+            .filter(PasskeysColumn::UserId.eq(user_id))
+            .order_by_asc(PasskeysColumn::PasskeyId)
+            .all(&self.sql_pool)
+            .await?)
+    }
+
+    /// The WebAuthn handle of the user: their UUID, which doesn't change with a rename.
+    async fn get_webauthn_user(&self, user_id: &UserId) -> Result<(webauthn::Uuid, String)> {
+        let (uuid, display_name) = model::User::find_by_id(user_id.clone())
+            .filter(UserColumn::DeletedDate.is_null())
+            .select_only()
+            .column(UserColumn::Uuid)
+            .column(UserColumn::DisplayName)
+            .into_tuple::<(String, Option<String>)>()
+            .one(&self.sql_pool)
+            .await?
+            .ok_or_else(|| DomainError::EntityNotFound(user_id.to_string()))?;
+        let uuid = webauthn::Uuid::parse_str(&uuid)
+            .map_err(|e| DomainError::InternalError(e.to_string()))?;
+        Ok((
+            uuid,
+            display_name
+                .filter(|name| !name.is_empty())
+                .unwrap_or_else(|| user_id.to_string()),
+        ))
+    }
+}
+
+#[async_trait]
+impl PasskeyHandler for SqlBackendHandler {
+    #[instrument(skip_all, level = "debug", err)]
+    async fn list_passkeys(&self, user_id: &UserId) -> Result<Vec<PasskeyInfo>> {
+        debug!(?user_id);
+        Ok(self
+            .get_passkey_models(user_id)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn start_passkey_registration(&self, user_id: &UserId) -> Result<PasskeyChallenge> {
+        debug!(?user_id);
+        let (uuid, display_name) = self.get_webauthn_user(user_id).await?;
+        let existing_credentials = self
+            .get_passkey_models(user_id)
+            .await?
+            .iter()
+            .map(|model| Ok(parse_passkey(model)?.cred_id().clone()))
+            .collect::<Result<Vec<_>>>()?;
+        let (challenge, state) = self
+            .get_webauthn()?
+            .start_passkey_registration(
+                uuid,
+                user_id.as_str(),
+                &display_name,
+                Some(existing_credentials),
+            )
+            .map_err(|e| DomainError::InternalError(e.to_string()))?;
+        Ok(PasskeyChallenge {
+            options: serde_json::to_string(&challenge)
+                .map_err(|e| DomainError::InternalError(e.to_string()))?,
+            server_data: self.passkey_ceremonies.registrations.insert(user_id, state),
+        })
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn finish_passkey_registration(
+        &self,
+        user_id: &UserId,
+        name: &str,
+        server_data: &str,
+        credential: &str,
+    ) -> Result<PasskeyInfo> {
+        debug!(?user_id, ?name);
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(DomainError::InternalError(
+                "The passkey needs a name".to_owned(),
+            ));
+        }
+        let state = self
+            .passkey_ceremonies
+            .registrations
+            .take(server_data, Some(user_id))?;
+        let credential: webauthn::RegisterPublicKeyCredential =
+            serde_json::from_str(credential).map_err(invalid_credential)?;
+        let passkey = self
+            .get_webauthn()?
+            .finish_passkey_registration(&credential, &state.state)
+            .map_err(invalid_credential)?;
+        Ok(model::passkeys::ActiveModel {
+            user_id: ActiveValue::Set(user_id.clone()),
+            name: ActiveValue::Set(name.to_owned()),
+            credential: ActiveValue::Set(serialize_passkey(&passkey)?),
+            creation_date: ActiveValue::Set(chrono::Utc::now().naive_utc()),
+            last_used_date: ActiveValue::Set(None),
+            ..Default::default()
+        }
+        .insert(&self.sql_pool)
+        .await?
+        .into())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn delete_passkey(&self, user_id: &UserId, passkey_id: i32) -> Result<()> {
+        debug!(?user_id, ?passkey_id);
+        let result = model::Passkeys::delete_many()
+            .filter(PasskeysColumn::UserId.eq(user_id))
+            .filter(PasskeysColumn::PasskeyId.eq(passkey_id))
+            .exec(&self.sql_pool)
+            .await?;
+        if result.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No passkey {} for user '{}'",
+                passkey_id, user_id
+            )));
+        }
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn start_passkey_login(&self, user_id: &UserId) -> Result<PasskeyChallenge> {
+        debug!(?user_id);
+        let passkeys = self
+            .get_passkey_models(user_id)
+            .await?
+            .iter()
+            .map(parse_passkey)
+            .collect::<Result<Vec<_>>>()?;
+        if passkeys.is_empty() {
+            return Err(DomainError::AuthenticationError(format!(
+                "No passkey for user '{}'",
+                user_id
+            )));
+        }
+        let (challenge, state) = self
+            .get_webauthn()?
+            .start_passkey_authentication(&passkeys)
+            .map_err(|e| DomainError::InternalError(e.to_string()))?;
+        Ok(PasskeyChallenge {
+            options: serde_json::to_string(&challenge)
+                .map_err(|e| DomainError::InternalError(e.to_string()))?,
+            server_data: self.passkey_ceremonies.logins.insert(user_id, state),
+        })
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn finish_passkey_login(&self, server_data: &str, credential: &str) -> Result<UserId> {
+        let state = self.passkey_ceremonies.logins.take(server_data, None)?;
+        let user_id = state.user_id;
+        if self.is_locked_out(&user_id).await? || self.is_user_disabled(&user_id).await? {
+            debug!(r#"User "{}" is locked out or disabled"#, &user_id);
+            return Err(DomainError::AuthenticationError(format!(
+                " for user '{}'",
+                user_id
+            )));
+        }
+        let result = serde_json::from_str::<webauthn::PublicKeyCredential>(credential)
+            .map_err(invalid_credential)
+            .and_then(|credential| {
+                self.get_webauthn()?
+                    .finish_passkey_authentication(&credential, &state.state)
+                    .map_err(invalid_credential)
+            });
+        let result = match result {
+            Ok(result) => result,
+            Err(e) => {
+                self.record_authentication_failure(&user_id).await?;
+                return Err(e);
+            }
+        };
+        // Store the new signature counter of the passkey that was used.
+        for model in self.get_passkey_models(&user_id).await? {
+            let mut passkey = parse_passkey(&model)?;
+            if passkey.update_credential(&result).is_some() {
+                model::passkeys::ActiveModel {
+                    passkey_id: ActiveValue::Set(model.passkey_id),
+                    credential: ActiveValue::Set(serialize_passkey(&passkey)?),
+                    last_used_date: ActiveValue::Set(Some(chrono::Utc::now().naive_utc())),
+                    ..Default::default()
+                }
+                .update(&self.sql_pool)
+                .await?;
+            }
+        }
+        self.reset_authentication_failures(&user_id).await?;
+        self.record_login(&user_id).await;
+        Ok(user_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::sql_backend_handler::tests::*;
+    use webauthn_authenticator_rs::{softpasskey::SoftPasskey, WebauthnAuthenticator};
+
+    #[tokio::test]
+    async fn test_passkey_registration_and_login_start() {
+        let fixture = TestFixture::new().await;
+        let bob = UserId::new("bob");
+        assert!(fixture
+            .handler
+            .list_passkeys(&bob)
+            .await
+            .unwrap()
+            .is_empty());
+        // No passkey to log in with yet.
+        fixture.handler.start_passkey_login(&bob).await.unwrap_err();
+
+        let challenge = fixture
+            .handler
+            .start_passkey_registration(&bob)
+            .await
+            .unwrap();
+        let options: serde_json::Value = serde_json::from_str(&challenge.options).unwrap();
+        assert_eq!(options["publicKey"]["rp"]["id"], "localhost");
+        assert_eq!(options["publicKey"]["user"]["name"], "bob");
+        // The state is bound to the user.
+        fixture
+            .handler
+            .finish_passkey_registration(
+                &UserId::new("patrick"),
+                "key",
+                &challenge.server_data,
+                "{}",
+            )
+            .await
+            .unwrap_err();
+        // An invalid credential is rejected.
+        let error = fixture
+            .handler
+            .finish_passkey_registration(&bob, "key", &challenge.server_data, "{}")
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("Invalid passkey"), "{}", error);
+        // The challenge can't be used again.
+        let error = fixture
+            .handler
+            .finish_passkey_registration(&bob, "key", &challenge.server_data, "{}")
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("already used"), "{}", error);
+        assert!(fixture
+            .handler
+            .list_passkeys(&bob)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_passkey_ceremony_store() {
+        let store = CeremonyStore::default();
+        let bob = UserId::new("bob");
+        let id = store.insert(&bob, 42);
+        // Not for this user: the ceremony stays.
+        store.take(&id, Some(&UserId::new("patrick"))).unwrap_err();
+        let state = store.take(&id, Some(&bob)).unwrap();
+        assert_eq!(state.user_id, bob);
+        assert_eq!(state.state, 42);
+        // Single use.
+        store.take(&id, Some(&bob)).unwrap_err();
+        store.take(&id, None).unwrap_err();
+        // Expired.
+        let id = store.insert(&bob, 42);
+        store
+            .ceremonies
+            .lock()
+            .unwrap()
+            .get_mut(&id)
+            .unwrap()
+            .expires_at = Instant::now() - Duration::from_secs(1);
+        store.take(&id, None).unwrap_err();
+        // The expired ceremonies are cleaned up.
+        store.insert(&bob, 43);
+        assert_eq!(store.ceremonies.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_passkey_login_replay() {
+        let fixture = TestFixture::new().await;
+        let bob = UserId::new("bob");
+        let origin = fixture.handler.config.http_url.clone();
+        let mut authenticator = WebauthnAuthenticator::new(SoftPasskey::new());
+        let challenge = fixture
+            .handler
+            .start_passkey_registration(&bob)
+            .await
+            .unwrap();
+        let credential = authenticator
+            .do_registration(
+                origin.clone(),
+                serde_json::from_str(&challenge.options).unwrap(),
+            )
+            .unwrap();
+        fixture
+            .handler
+            .finish_passkey_registration(
+                &bob,
+                "key",
+                &challenge.server_data,
+                &serde_json::to_string(&credential).unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let challenge = fixture.handler.start_passkey_login(&bob).await.unwrap();
+        let credential = serde_json::to_string(
+            &authenticator
+                .do_authentication(origin, serde_json::from_str(&challenge.options).unwrap())
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            fixture
+                .handler
+                .finish_passkey_login(&challenge.server_data, &credential)
+                .await
+                .unwrap(),
+            bob
+        );
+        // The same valid assertion can't be replayed.
+        let error = fixture
+            .handler
+            .finish_passkey_login(&challenge.server_data, &credential)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("already used"), "{}", error);
+        // Neither can a ceremony that was never started.
+        fixture
+            .handler
+            .finish_passkey_login("unknown", &credential)
+            .await
+            .unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn test_delete_passkey() {
+        let fixture = TestFixture::new().await;
+        let bob = UserId::new("bob");
+        let passkey = model::passkeys::ActiveModel {
+            user_id: ActiveValue::Set(bob.clone()),
+            name: ActiveValue::Set("key".to_owned()),
+            credential: ActiveValue::Set("{}".to_owned()),
+            creation_date: ActiveValue::Set(chrono::Utc::now().naive_utc()),
+            last_used_date: ActiveValue::Set(None),
+            ..Default::default()
+        }
+        .insert(&fixture.handler.sql_pool)
+        .await
+        .unwrap();
+        let passkeys = fixture.handler.list_passkeys(&bob).await.unwrap();
+        assert_eq!(passkeys.len(), 1);
+        assert_eq!(passkeys[0].name, "key");
+        // Only the owner can delete it.
+        fixture
+            .handler
+            .delete_passkey(&UserId::new("patrick"), passkey.passkey_id)
+            .await
+            .unwrap_err();
+        fixture
+            .handler
+            .delete_passkey(&bob, passkey.passkey_id)
+            .await
+            .unwrap();
+        assert!(fixture
+            .handler
+            .list_passkeys(&bob)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -1,11 +1,13 @@
 use crate::domain::{
-    error::Result,
-    handler::{AttributeSchema, Schema, SchemaBackendHandler},
+    error::{DomainError, Result},
+    handler::{AttributeSchema, Schema, SchemaBackendHandler, SchemaWriterBackendHandler},
     model,
     sql_backend_handler::SqlBackendHandler,
+    types::AttributeType,
 };
 use async_trait::async_trait;
-use sea_orm::{EntityTrait, QueryOrder};
+use sea_orm::{ActiveModelTrait, EntityTrait, QueryOrder, Set};
+use tracing::{debug, instrument};
 
 use super::handler::AttributeList;
 
@@ -23,11 +25,41 @@ impl SchemaBackendHandler for SqlBackendHandler {
     }
 }
 
+#[async_trait]
+impl SchemaWriterBackendHandler for SqlBackendHandler {
+    #[instrument(skip_all, level = "debug", err)]
+    async fn set_user_attribute_allowed_values(
+        &self,
+        name: &str,
+        allowed_values: Option<Vec<String>>,
+    ) -> Result<()> {
+        debug!(?name, ?allowed_values);
+        let attribute = model::UserAttributeSchema::find_by_id(name.to_owned())
+            .one(&self.sql_pool)
+            .await?
+            .ok_or_else(|| DomainError::EntityNotFound(format!("Attribute {}", name)))?;
+        if allowed_values.is_some() && attribute.attribute_type != AttributeType::String {
+            return Err(DomainError::InternalError(format!(
+                "Only the string attributes can have a set of allowed values, not '{}'",
+                name
+            )));
+        }
+        let mut attribute: model::user_attribute_schema::ActiveModel = attribute.into();
+        attribute.allowed_values = Set(allowed_values
+            .map(|values| serde_json::to_string(&values))
+            .transpose()
+            .map_err(|e| DomainError::InternalError(e.to_string()))?);
+        attribute.update(&self.sql_pool).await?;
+        self.pin_reads_to_primary();
+        Ok(())
+    }
+}
+
 impl SqlBackendHandler {
     async fn get_user_attributes(&self) -> Result<Vec<AttributeSchema>> {
         Ok(model::UserAttributeSchema::find()
             .order_by_asc(model::UserAttributeSchemaColumn::AttributeName)
-            .all(&self.sql_pool)
+            .all(self.read_pool())
             .await?
             .into_iter()
             .map(|m| m.into())
@@ -37,7 +69,7 @@ impl SqlBackendHandler {
     async fn get_group_attributes(&self) -> Result<Vec<AttributeSchema>> {
         Ok(model::GroupAttributeSchema::find()
             .order_by_asc(model::GroupAttributeSchemaColumn::AttributeName)
-            .all(&self.sql_pool)
+            .all(self.read_pool())
             .await?
             .into_iter()
             .map(|m| m.into())
@@ -67,6 +99,8 @@ mod tests {
                             is_visible: true,
                             is_editable: true,
                             is_hardcoded: true,
+                            is_case_sensitive: false,
+                            allowed_values: None,
                         },
                         AttributeSchema {
                             name: "first_name".to_owned(),
@@ -75,6 +109,8 @@ mod tests {
                             is_visible: true,
                             is_editable: true,
                             is_hardcoded: true,
+                            is_case_sensitive: false,
+                            allowed_values: None,
                         },
                         AttributeSchema {
                             name: "last_name".to_owned(),
@@ -83,6 +119,8 @@ mod tests {
                             is_visible: true,
                             is_editable: true,
                             is_hardcoded: true,
+                            is_case_sensitive: false,
+                            allowed_values: None,
                         }
                     ]
                 },
@@ -92,4 +130,49 @@ mod tests {
             }
         );
     }
+
+    #[tokio::test]
+    async fn test_set_user_attribute_allowed_values() {
+        let fixture = TestFixture::new().await;
+        let get_allowed_values = || async {
+            fixture
+                .handler
+                .get_schema()
+                .await
+                .unwrap()
+                .user_attributes
+                .get_attribute_schema("first_name")
+                .unwrap()
+                .allowed_values
+                .clone()
+        };
+        fixture
+            .handler
+            .set_user_attribute_allowed_values(
+                "first_name",
+                Some(vec!["Bob".to_owned(), "Patrick".to_owned()]),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            get_allowed_values().await,
+            Some(vec!["Bob".to_owned(), "Patrick".to_owned()])
+        );
+        fixture
+            .handler
+            .set_user_attribute_allowed_values("first_name", None)
+            .await
+            .unwrap();
+        assert_eq!(get_allowed_values().await, None);
+        fixture
+            .handler
+            .set_user_attribute_allowed_values("avatar", Some(vec!["Bob".to_owned()]))
+            .await
+            .unwrap_err();
+        fixture
+            .handler
+            .set_user_attribute_allowed_values("unknown", None)
+            .await
+            .unwrap_err();
+    }
 }
@@ -0,0 +1,72 @@
+use super::{
+    error::Result,
+    model::{self, JwtRefreshStorageColumn},
+    session_handler::SessionHandler,
+    sql_backend_handler::SqlBackendHandler,
+    types::UserId,
+};
+use async_trait::async_trait;
+use sea_orm::{ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter};
+use tracing::{debug, instrument};
+
+impl SqlBackendHandler {
+    /// Same as `revoke_all_sessions`, within a transaction.
+    pub(crate) async fn revoke_all_sessions_in<C: ConnectionTrait>(
+        connection: &C,
+        user_id: &UserId,
+    ) -> Result<()> {
+        let res = model::JwtRefreshStorage::delete_many()
+            .filter(JwtRefreshStorageColumn::UserId.eq(user_id))
+            .exec(connection)
+            .await?;
+        debug!(revoked_tokens = res.rows_affected);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SessionHandler for SqlBackendHandler {
+    #[instrument(skip_all, level = "debug", err)]
+    async fn revoke_all_sessions(&self, user_id: &UserId) -> Result<()> {
+        debug!(?user_id);
+        Self::revoke_all_sessions_in(&self.sql_pool, user_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        domain::{sql_backend_handler::tests::*, sql_opaque_handler::register_password},
+        infra::tcp_backend_handler::TcpBackendHandler,
+    };
+    use secstr::SecUtf8;
+
+    async fn session_count(handler: &SqlBackendHandler, user_id: &UserId) -> usize {
+        handler.list_sessions(user_id).await.unwrap().len()
+    }
+
+    #[tokio::test]
+    async fn test_revoke_all_sessions() {
+        let fixture = TestFixture::new().await;
+        let bob = UserId::new("bob");
+        let patrick = UserId::new("patrick");
+        for user_id in [&bob, &bob, &patrick] {
+            fixture.handler.create_refresh_token(user_id).await.unwrap();
+        }
+        fixture.handler.revoke_all_sessions(&bob).await.unwrap();
+        assert_eq!(session_count(&fixture.handler, &bob).await, 0);
+        assert_eq!(session_count(&fixture.handler, &patrick).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_password_change_revokes_sessions() {
+        let fixture = TestFixture::new().await;
+        let bob = UserId::new("bob");
+        fixture.handler.create_refresh_token(&bob).await.unwrap();
+        register_password(&fixture.handler, &bob, &SecUtf8::from("new_password"))
+            .await
+            .unwrap();
+        assert_eq!(session_count(&fixture.handler, &bob).await, 0);
+    }
+}
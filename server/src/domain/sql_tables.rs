@@ -21,7 +21,7 @@ impl From<SchemaVersion> for Value {
     }
 }
 
-pub const LAST_SCHEMA_VERSION: SchemaVersion = SchemaVersion(5);
+pub const LAST_SCHEMA_VERSION: SchemaVersion = SchemaVersion(28);
 
 pub async fn init_table(pool: &DbConnection) -> anyhow::Result<()> {
     let version = {
@@ -387,6 +387,89 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_migration_to_v16() {
+        crate::infra::logging::init_for_tests();
+        let sql_pool = get_in_memory_db().await;
+        upgrade_to_v1(&sql_pool).await.unwrap();
+        migrate_from_version(&sql_pool, SchemaVersion(1), SchemaVersion(15))
+            .await
+            .unwrap();
+        sql_pool
+            .execute(raw_statement(
+                r#"INSERT INTO users (user_id, email, display_name, creation_date, uuid)
+                       VALUES ("bob", "bob@bob.com", "", "1970-01-01 00:00:00", "a02eaf13-48a7-30f6-a3d4-040ff7c52b04")"#,
+            ))
+            .await
+            .unwrap();
+        sql_pool
+            .execute(raw_statement(
+                r#"INSERT INTO users (user_id, email, display_name, creation_date, uuid)
+                       VALUES ("bob2", "Bob@Bob.com", "", "1970-01-01 00:00:00", "986765a5-3f03-389e-b47b-536b2d6e1bec")"#,
+            ))
+            .await
+            .unwrap();
+        sql_pool
+            .execute(raw_statement(
+                r#"INSERT INTO users (user_id, email, display_name, creation_date, uuid)
+                       VALUES ("bob3", "new@bob.com", "", "1970-01-01 00:00:00", "6a4a3b0e-4c1d-3c8f-9a31-0d1b84c5ae6e")"#,
+            ))
+            .await
+            .unwrap();
+        // The duplicates are logged, the migration goes on.
+        migrate_from_version(&sql_pool, SchemaVersion(15), SchemaVersion(16))
+            .await
+            .unwrap();
+        #[derive(FromQueryResult, PartialEq, Eq, Debug)]
+        struct LowercaseEmail {
+            lowercase_email: String,
+        }
+        assert_eq!(
+            LowercaseEmail::find_by_statement(raw_statement(
+                r#"SELECT lowercase_email FROM users ORDER BY user_id"#
+            ))
+            .all(&sql_pool)
+            .await
+            .unwrap(),
+            vec![
+                LowercaseEmail {
+                    lowercase_email: "bob@bob.com".to_owned()
+                },
+                // Only the lowercase email can be found ignoring the case.
+                LowercaseEmail {
+                    lowercase_email: "Bob@Bob.com".to_owned()
+                },
+                LowercaseEmail {
+                    lowercase_email: "new@bob.com".to_owned()
+                }
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_migrations() {
+        crate::infra::logging::init_for_tests();
+        let sql_pool = get_in_memory_db().await;
+        upgrade_to_v1(&sql_pool).await.unwrap();
+        migrate_from_version(&sql_pool, SchemaVersion(1), SchemaVersion(21))
+            .await
+            .unwrap();
+        sql_migrations::dry_run_migrations(&sql_pool, SchemaVersion(21), LAST_SCHEMA_VERSION)
+            .await
+            .unwrap();
+        // Nothing was applied.
+        assert_eq!(get_schema_version(&sql_pool).await, Some(SchemaVersion(21)));
+        assert!(sql_pool
+            .execute(raw_statement(r#"SELECT dynamic_filter FROM groups"#))
+            .await
+            .is_err());
+        init_table(&sql_pool).await.unwrap();
+        assert_eq!(
+            get_schema_version(&sql_pool).await,
+            Some(LAST_SCHEMA_VERSION)
+        );
+    }
+
     #[tokio::test]
     async fn test_too_high_version() {
         let sql_pool = get_in_memory_db().await;
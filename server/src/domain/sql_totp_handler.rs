@@ -0,0 +1,174 @@
+use super::{
+    error::{DomainError, Result},
+    model::{self, UserColumn},
+    sql_backend_handler::SqlBackendHandler,
+    totp,
+    totp_handler::TotpHandler,
+    types::UserId,
+};
+use async_trait::async_trait;
+use sea_orm::{ActiveModelTrait, ActiveValue, EntityTrait, QuerySelect};
+use tracing::{debug, instrument};
+
+/// Value of the `mfa_type` column for the users with TOTP enabled.
+const TOTP_MFA_TYPE: &str = "totp";
+
+struct TotpState {
+    is_enabled: bool,
+    encrypted_secret: Option<Vec<u8>>,
+    last_used_step: Option<i64>,
+}
+
+impl SqlBackendHandler {
+    #[instrument(skip_all, level = "debug", err)]
+    async fn get_totp_state(&self, user_id: &UserId) -> Result<TotpState> {
+        let (mfa_type, encrypted_secret, last_used_step) = model::User::find_by_id(user_id.clone())
+            .select_only()
+            .column(UserColumn::MfaType)
+            .column(UserColumn::TotpEncryptedSecret)
+            .column(UserColumn::TotpLastStep)
+            .into_tuple::<(Option<String>, Option<Vec<u8>>, Option<i64>)>()
+            .one(&self.sql_pool)
+            .await?
+            .ok_or_else(|| DomainError::EntityNotFound(user_id.to_string()))?;
+        Ok(TotpState {
+            is_enabled: mfa_type.as_deref() == Some(TOTP_MFA_TYPE),
+            encrypted_secret,
+            last_used_step,
+        })
+    }
+
+    /// Checks the code against the stored secret, and returns the step it matched.
+    fn verify_totp_code(&self, state: &TotpState, code: &str) -> Result<i64> {
+        let secret = orion::aead::open(
+            &self.get_orion_secret_key()?,
+            state
+                .encrypted_secret
+                .as_ref()
+                .ok_or_else(|| DomainError::InternalError("No TOTP secret".to_string()))?,
+        )?;
+        totp::verify_code(
+            &secret,
+            code,
+            chrono::Utc::now().timestamp(),
+            state.last_used_step,
+        )
+        .ok_or_else(|| DomainError::AuthenticationError("Invalid TOTP code".to_string()))
+    }
+}
+
+#[async_trait]
+impl TotpHandler for SqlBackendHandler {
+    #[instrument(skip_all, level = "debug", err)]
+    async fn start_totp_enrollment(&self, user_id: &UserId) -> Result<Vec<u8>> {
+        debug!(?user_id);
+        let secret = totp::generate_secret();
+        let encrypted_secret = orion::aead::seal(&self.get_orion_secret_key()?, &secret)?;
+        // TOTP stays disabled until the secret is confirmed.
+        model::users::ActiveModel {
+            user_id: ActiveValue::Set(user_id.clone()),
+            mfa_type: ActiveValue::Set(None),
+            totp_encrypted_secret: ActiveValue::Set(Some(encrypted_secret)),
+            totp_last_step: ActiveValue::Set(None),
+            ..Default::default()
+        }
+        .update(&self.sql_pool)
+        .await?;
+        Ok(secret)
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn confirm_totp_enrollment(&self, user_id: &UserId, code: &str) -> Result<()> {
+        debug!(?user_id);
+        let state = self.get_totp_state(user_id).await?;
+        let step = self.verify_totp_code(&state, code)?;
+        model::users::ActiveModel {
+            user_id: ActiveValue::Set(user_id.clone()),
+            mfa_type: ActiveValue::Set(Some(TOTP_MFA_TYPE.to_owned())),
+            totp_last_step: ActiveValue::Set(Some(step)),
+            ..Default::default()
+        }
+        .update(&self.sql_pool)
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn disable_totp(&self, user_id: &UserId) -> Result<()> {
+        debug!(?user_id);
+        model::users::ActiveModel {
+            user_id: ActiveValue::Set(user_id.clone()),
+            mfa_type: ActiveValue::Set(None),
+            totp_encrypted_secret: ActiveValue::Set(None),
+            totp_last_step: ActiveValue::Set(None),
+            ..Default::default()
+        }
+        .update(&self.sql_pool)
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn check_totp_code(&self, user_id: &UserId, code: Option<String>) -> Result<()> {
+        let state = self.get_totp_state(user_id).await?;
+        if !state.is_enabled {
+            return Ok(());
+        }
+        let code = code.ok_or_else(|| {
+            DomainError::AuthenticationError(format!("TOTP code required for user '{}'", user_id))
+        })?;
+        let step = self.verify_totp_code(&state, &code)?;
+        model::users::ActiveModel {
+            user_id: ActiveValue::Set(user_id.clone()),
+            totp_last_step: ActiveValue::Set(Some(step)),
+            ..Default::default()
+        }
+        .update(&self.sql_pool)
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::sql_backend_handler::tests::*;
+
+    #[tokio::test]
+    async fn test_totp_flow() {
+        let fixture = TestFixture::new().await;
+        let bob = UserId::new("bob");
+        // Disabled by default.
+        fixture.handler.check_totp_code(&bob, None).await.unwrap();
+
+        let secret = fixture.handler.start_totp_enrollment(&bob).await.unwrap();
+        // Not enabled until confirmed.
+        fixture.handler.check_totp_code(&bob, None).await.unwrap();
+        fixture
+            .handler
+            .confirm_totp_enrollment(&bob, "000000a")
+            .await
+            .unwrap_err();
+        let code = totp::code_at(&secret, chrono::Utc::now().timestamp());
+        fixture
+            .handler
+            .confirm_totp_enrollment(&bob, &code)
+            .await
+            .unwrap();
+
+        fixture
+            .handler
+            .check_totp_code(&bob, None)
+            .await
+            .unwrap_err();
+        // The code used for the enrollment can't be replayed.
+        fixture
+            .handler
+            .check_totp_code(&bob, Some(code))
+            .await
+            .unwrap_err();
+
+        fixture.handler.disable_totp(&bob).await.unwrap();
+        fixture.handler.check_totp_code(&bob, None).await.unwrap();
+    }
+}
@@ -1,37 +1,181 @@
 use crate::domain::{
+    dynamic_groups::expand_user_filter,
     error::{DomainError, Result},
     handler::{
-        CreateUserRequest, UpdateUserRequest, UserBackendHandler, UserListerBackendHandler,
-        UserRequestFilter,
+        escape_sql_like, CreateUserRequest, ImportUserRequest, SubStringFilter, UpdateUserRequest,
+        UserBackendHandler, UserListerBackendHandler, UserRequestFilter, UserSortColumn,
+        UserSortKey,
     },
     model::{self, GroupColumn, UserColumn},
-    sql_backend_handler::SqlBackendHandler,
+    sql_attribute_index::reindex_user_attributes,
+    sql_backend_handler::{like_pattern, SqlBackendHandler},
     types::{AttributeValue, GroupDetails, GroupId, Serialized, User, UserAndGroups, UserId, Uuid},
 };
+use crate::infra::webhooks::{WebhookEvent, WebhookEventType};
 use async_trait::async_trait;
 use sea_orm::{
     sea_query::{
-        query::OnConflict, Alias, Cond, Expr, Func, IntoColumnRef, IntoCondition, SimpleExpr,
+        query::OnConflict, Alias, Cond, Expr, Func, IntoColumnRef, IntoCondition, Order, SimpleExpr,
     },
-    ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, IntoActiveValue, ModelTrait,
-    QueryFilter, QueryOrder, QuerySelect, QueryTrait, Set, TransactionTrait,
+    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DatabaseTransaction, EntityTrait,
+    FromQueryResult, IntoActiveValue, ModelTrait, PaginatorTrait, QueryFilter, QueryOrder,
+    QuerySelect, QueryTrait, Select, Set, Statement, TransactionTrait,
 };
-use std::collections::HashSet;
-use tracing::{debug, instrument};
+use std::collections::{HashMap, HashSet};
+use tracing::{debug, instrument, warn};
+
+/// The lowercase value of the users to sort by, NULL or empty if they don't have one. The last
+/// name comes from the attribute index, joined by `order_users`.
+fn sort_value(column: UserSortColumn) -> SimpleExpr {
+    match column {
+        UserSortColumn::UserId => {
+            SimpleExpr::Column(UserColumn::UserId.as_column_ref().into_column_ref())
+        }
+        UserSortColumn::DisplayName => SimpleExpr::FunctionCall(Func::lower(Expr::col(
+            UserColumn::DisplayName.as_column_ref(),
+        ))),
+        UserSortColumn::LastName => SimpleExpr::Column(
+            (
+                model::UserAttributeIndex,
+                model::UserAttributeIndexColumn::LowercaseValue,
+            )
+                .into_column_ref(),
+        ),
+        UserSortColumn::Email => {
+            SimpleExpr::Column(UserColumn::LowercaseEmail.as_column_ref().into_column_ref())
+        }
+    }
+}
+
+/// Sorts the users by the keys then by user id. The missing values sort after the others.
+fn order_users(mut query: Select<model::User>, sort: &[UserSortKey]) -> Select<model::User> {
+    if sort
+        .iter()
+        .any(|key| key.column == UserSortColumn::LastName)
+    {
+        // The last name has at most one value: the join doesn't duplicate the users.
+        QueryTrait::query(&mut query).left_join(
+            model::UserAttributeIndex,
+            Expr::col((
+                model::UserAttributeIndex,
+                model::UserAttributeIndexColumn::UserId,
+            ))
+            .equals(UserColumn::UserId.as_column_ref())
+            .and(
+                Expr::col((
+                    model::UserAttributeIndex,
+                    model::UserAttributeIndexColumn::AttributeName,
+                ))
+                .eq("last_name"),
+            ),
+        );
+    }
+    for key in sort {
+        let order = if key.descending {
+            Order::Desc
+        } else {
+            Order::Asc
+        };
+        let value = sort_value(key.column);
+        query = query
+            .order_by(
+                Expr::expr(value.clone())
+                    .is_null()
+                    .or(Expr::expr(value.clone()).eq("")),
+                order.clone(),
+            )
+            .order_by(value, order);
+    }
+    query.order_by_asc(UserColumn::UserId)
+}
+
+/// The users with a (regular) membership matching the condition. Contrary to the conditions on the
+/// joined groups, it applies to all the memberships of the user at once.
+fn membership_condition(condition: SimpleExpr) -> Cond {
+    Expr::in_subquery(
+        Expr::col(UserColumn::UserId.as_column_ref()),
+        model::Membership::find()
+            .select_only()
+            .column(model::MembershipColumn::UserId)
+            .filter(condition)
+            .into_query(),
+    )
+    .into_condition()
+}
+
+/// The users with a value of the string attribute matching the condition, one of the values for
+/// the lists.
+fn attribute_index_condition(name: String, condition: SimpleExpr) -> Cond {
+    Expr::in_subquery(
+        Expr::col(UserColumn::UserId.as_column_ref()),
+        model::UserAttributeIndex::find()
+            .select_only()
+            .column(model::UserAttributeIndexColumn::UserId)
+            .filter(model::UserAttributeIndexColumn::AttributeName.eq(name))
+            .filter(condition)
+            .into_query(),
+    )
+    .into_condition()
+}
 
 fn attribute_condition(name: String, value: String) -> Cond {
+    // A multi-valued attribute matches if any of its values is equal.
+    let serialized = Serialized::from(&value);
+    Cond::any()
+        .add(attribute_value_condition(name.clone(), serialized.clone()))
+        .add(attribute_index_condition(
+            name,
+            model::UserAttributeIndexColumn::Value.eq(serialized),
+        ))
+}
+
+fn attribute_value_condition(name: String, value: Serialized) -> Cond {
+    Expr::in_subquery(
+        Expr::col(UserColumn::UserId.as_column_ref()),
+        model::UserAttributes::find()
+            .select_only()
+            .column(model::UserAttributesColumn::UserId)
+            .filter(model::UserAttributesColumn::AttributeName.eq(name))
+            .filter(model::UserAttributesColumn::Value.eq(value))
+            .into_query(),
+    )
+    .into_condition()
+}
+
+fn attribute_ignore_case_condition(name: String, value: String) -> Cond {
+    attribute_index_condition(
+        name,
+        model::UserAttributeIndexColumn::LowercaseValue.eq(value.to_lowercase()),
+    )
+}
+
+fn attribute_list_contains_condition(name: String, value: String) -> Cond {
+    attribute_index_condition(
+        name,
+        model::UserAttributeIndexColumn::Value.eq(Serialized::from(&value)),
+    )
+}
+
+fn attribute_present_condition(name: String) -> Cond {
     Expr::in_subquery(
         Expr::col(UserColumn::UserId.as_column_ref()),
         model::UserAttributes::find()
             .select_only()
             .column(model::UserAttributesColumn::UserId)
             .filter(model::UserAttributesColumn::AttributeName.eq(name))
-            .filter(model::UserAttributesColumn::Value.eq(Serialized::from(&value)))
             .into_query(),
     )
     .into_condition()
 }
 
+fn attribute_substring_condition(name: String, filter: SubStringFilter) -> Cond {
+    attribute_index_condition(
+        name,
+        Expr::col(model::UserAttributeIndexColumn::LowercaseValue.as_column_ref())
+            .like(like_pattern(&filter.to_sql_filter())),
+    )
+}
+
 fn get_user_filter_expr(filter: UserRequestFilter) -> Cond {
     use UserRequestFilter::*;
     let group_table = Alias::new("r1");
@@ -53,26 +197,63 @@ fn get_user_filter_expr(filter: UserRequestFilter) -> Cond {
         Or(fs) => get_repeated_filter(fs, Cond::any(), false),
         Not(f) => get_user_filter_expr(*f).not(),
         UserId(user_id) => ColumnTrait::eq(&UserColumn::UserId, user_id).into_condition(),
+        UidNumber(uid_number) => {
+            ColumnTrait::eq(&UserColumn::UidNumber, uid_number).into_condition()
+        }
+        UuidEquality(uuid) => ColumnTrait::eq(&UserColumn::Uuid, uuid.to_string()).into_condition(),
+        IsEnabled(is_enabled) => {
+            ColumnTrait::eq(&UserColumn::IsEnabled, is_enabled).into_condition()
+        }
         Equality(s1, s2) => {
             if s1 == UserColumn::UserId {
                 panic!("User id should be wrapped")
+            } else if s1 == UserColumn::Email {
+                // The emails are unique regardless of the case.
+                ColumnTrait::eq(&UserColumn::LowercaseEmail, s2.to_lowercase()).into_condition()
             } else {
                 ColumnTrait::eq(&s1, s2).into_condition()
             }
         }
+        // The serialized values are compared as binary, so the match is case-sensitive.
         AttributeEquality(s1, s2) => attribute_condition(s1, s2),
+        AttributeEqualityIgnoreCase(s1, s2) => attribute_ignore_case_condition(s1, s2),
+        AttributeValueEquality(name, value) => attribute_value_condition(name, value),
         MemberOf(group) => Expr::col((group_table, GroupColumn::DisplayName))
             .eq(group)
             .into_condition(),
         MemberOfId(group_id) => Expr::col((group_table, GroupColumn::GroupId))
             .eq(group_id)
             .into_condition(),
-        UserIdSubString(filter) => UserColumn::UserId
-            .like(&filter.to_sql_filter())
+        MemberOfIdBelow(group_id) => {
+            membership_condition(model::MembershipColumn::GroupId.lt(group_id))
+        }
+        MemberOfAnyId(group_ids) => {
+            membership_condition(model::MembershipColumn::GroupId.is_in(group_ids))
+        }
+        UserIdSubString(filter) => Expr::col(UserColumn::UserId.as_column_ref())
+            .like(like_pattern(&filter.to_sql_filter()))
             .into_condition(),
         SubString(col, filter) => {
             SimpleExpr::FunctionCall(Func::lower(Expr::col(col.as_column_ref())))
-                .like(filter.to_sql_filter())
+                .like(like_pattern(&filter.to_sql_filter()))
+                .into_condition()
+        }
+        AttributeSubString(name, filter) => attribute_substring_condition(name, filter),
+        AttributePresent(name) => attribute_present_condition(name),
+        AttributeListContains(name, value) => attribute_list_contains_condition(name, value),
+        CreationDateAfter(date) => UserColumn::CreationDate.gte(date).into_condition(),
+        CreationDateBefore(date) => UserColumn::CreationDate.lte(date).into_condition(),
+        ModifiedDateAfter(date) => UserColumn::ModifiedDate.gte(date).into_condition(),
+        ModifiedDateBefore(date) => UserColumn::ModifiedDate.lte(date).into_condition(),
+        ExpirationDateBefore(date) => UserColumn::ExpirationDate.lte(date).into_condition(),
+        LastLoginAfter(date) => UserColumn::LastLogin.gte(date).into_condition(),
+        LastLoginBefore(date) => UserColumn::LastLogin.lte(date).into_condition(),
+        ApproxMatch(col, value) => {
+            SimpleExpr::FunctionCall(Func::lower(Expr::col(col.as_column_ref())))
+                .like(like_pattern(&format!(
+                    "%{}%",
+                    escape_sql_like(&value.to_ascii_lowercase())
+                )))
                 .into_condition()
         }
     }
@@ -89,40 +270,288 @@ fn to_value(opt_name: &Option<String>) -> ActiveValue<Option<String>> {
     }
 }
 
-#[async_trait]
-impl UserListerBackendHandler for SqlBackendHandler {
-    #[instrument(skip_all, level = "debug", ret, err)]
-    async fn list_users(
+pub(crate) fn get_user_condition(filters: Option<UserRequestFilter>) -> Cond {
+    let filter_condition = filters
+        .map(|f| {
+            UserColumn::UserId
+                .in_subquery(
+                    model::User::find()
+                        .find_also_linked(model::memberships::UserToGroup)
+                        .select_only()
+                        .column(UserColumn::UserId)
+                        .filter(get_user_filter_expr(f))
+                        .into_query(),
+                )
+                .into_condition()
+        })
+        .unwrap_or_else(|| SimpleExpr::Value(true.into()).into_condition());
+    // The soft-deleted users are hidden.
+    Cond::all()
+        .add(UserColumn::DeletedDate.is_null())
+        .add(filter_condition)
+}
+
+fn user_created_event(request: &CreateUserRequest) -> WebhookEvent {
+    let mut event = WebhookEvent::new(WebhookEventType::UserCreated, request.user_id.clone());
+    event.changed_fields = [
+        ("email", true),
+        ("display_name", request.display_name.is_some()),
+        ("first_name", request.first_name.is_some()),
+        ("last_name", request.last_name.is_some()),
+        ("avatar", request.avatar.is_some()),
+    ]
+    .into_iter()
+    .filter(|(_, is_set)| *is_set)
+    .map(|(field, _)| field.to_owned())
+    .collect();
+    event
+}
+
+/// Fails if another user has the same email, ignoring the case.
+async fn check_email_is_available(
+    transaction: &DatabaseTransaction,
+    email: &str,
+    user_id: &UserId,
+) -> Result<()> {
+    if let Some(other_user) = model::User::find()
+        .filter(UserColumn::LowercaseEmail.eq(email.to_lowercase()))
+        .filter(UserColumn::UserId.ne(user_id))
+        .one(transaction)
+        .await?
+    {
+        return Err(DomainError::EntityAlreadyExists(format!(
+            "Email '{}' is already used by user '{}'",
+            email, other_user.user_id
+        )));
+    }
+    Ok(())
+}
+
+/// Takes a number from the counter in the metadata. Unlike the highest uid number plus one, the
+/// numbers of the deleted users are not reused, and the lock on the updated row serializes the
+/// concurrent creations.
+async fn next_uid_number(transaction: &DatabaseTransaction) -> Result<i32> {
+    #[derive(FromQueryResult)]
+    struct NextUidNumber {
+        next_uid_number: i32,
+    }
+    let builder = transaction.get_database_backend();
+    transaction
+        .execute(Statement::from_string(
+            builder,
+            "UPDATE metadata SET next_uid_number = next_uid_number + 1".to_owned(),
+        ))
+        .await?;
+    let next = NextUidNumber::find_by_statement(Statement::from_string(
+        builder,
+        "SELECT next_uid_number FROM metadata".to_owned(),
+    ))
+    .one(transaction)
+    .await?
+    .ok_or_else(|| DomainError::InternalError("Missing metadata".to_owned()))?;
+    Ok(next.next_uid_number - 1)
+}
+
+async fn insert_user(transaction: &DatabaseTransaction, request: CreateUserRequest) -> Result<()> {
+    // The user ids are already lowercase.
+    if model::User::find_by_id(request.user_id.clone())
+        .one(transaction)
+        .await?
+        .is_some()
+    {
+        return Err(DomainError::EntityAlreadyExists(
+            request.user_id.into_string(),
+        ));
+    }
+    check_email_is_available(transaction, &request.email, &request.user_id).await?;
+    let now = chrono::Utc::now().naive_utc();
+    let uuid = Uuid::from_name_and_date(request.user_id.as_str(), &now);
+    let mut new_user = model::users::ActiveModel {
+        user_id: Set(request.user_id.clone()),
+        lowercase_email: Set(request.email.to_lowercase()),
+        email: Set(request.email),
+        display_name: to_value(&request.display_name),
+        creation_date: ActiveValue::Set(now),
+        modified_date: ActiveValue::Set(now),
+        uuid: ActiveValue::Set(uuid),
+        is_enabled: ActiveValue::Set(true),
+        expiration_date: ActiveValue::Set(None),
+        ..Default::default()
+    };
+    let user_id = request.user_id.clone();
+    let mut new_user_attributes = Vec::new();
+    if let Some(first_name) = request.first_name {
+        new_user_attributes.push(model::user_attributes::ActiveModel {
+            user_id: Set(request.user_id.clone()),
+            attribute_name: Set("first_name".to_owned()),
+            value: Set(Serialized::from(&first_name)),
+        });
+    }
+    if let Some(last_name) = request.last_name {
+        new_user_attributes.push(model::user_attributes::ActiveModel {
+            user_id: Set(request.user_id.clone()),
+            attribute_name: Set("last_name".to_owned()),
+            value: Set(Serialized::from(&last_name)),
+        });
+    }
+    if let Some(avatar) = request.avatar {
+        new_user_attributes.push(model::user_attributes::ActiveModel {
+            user_id: Set(request.user_id),
+            attribute_name: Set("avatar".to_owned()),
+            value: Set(Serialized::from(&avatar)),
+        });
+    }
+    new_user.uid_number = Set(next_uid_number(transaction).await?);
+    new_user.insert(transaction).await?;
+    if !new_user_attributes.is_empty() {
+        model::UserAttributes::insert_many(new_user_attributes)
+            .exec(transaction)
+            .await?;
+        reindex_user_attributes(transaction, Some(&user_id)).await?;
+    }
+    Ok(())
+}
+
+/// Adds the new user to the configured default groups, besides the ones in `existing_groups`.
+/// The groups that don't exist are skipped with a warning. Returns the groups joined.
+async fn add_to_default_groups(
+    transaction: &DatabaseTransaction,
+    user_id: &UserId,
+    default_groups: &[String],
+    existing_groups: &[GroupId],
+) -> Result<Vec<GroupId>> {
+    if default_groups.is_empty() {
+        return Ok(Vec::new());
+    }
+    let groups = model::Group::find()
+        .filter(GroupColumn::DisplayName.is_in(default_groups.iter().cloned()))
+        .all(transaction)
+        .await?;
+    for name in default_groups {
+        if !groups.iter().any(|g| &g.display_name == name) {
+            warn!(group = %name, "Default group not found, skipping it");
+        }
+    }
+    let group_ids = groups
+        .into_iter()
+        .map(|g| g.group_id)
+        .filter(|group_id| !existing_groups.contains(group_id))
+        .collect::<Vec<_>>();
+    for &group_id in &group_ids {
+        model::memberships::ActiveModel {
+            user_id: ActiveValue::Set(user_id.clone()),
+            group_id: ActiveValue::Set(group_id),
+        }
+        .insert(transaction)
+        .await?;
+    }
+    Ok(group_ids)
+}
+
+fn user_added_to_group_event(user_id: &UserId, group_id: GroupId) -> WebhookEvent {
+    WebhookEvent {
+        group_id: Some(group_id),
+        ..WebhookEvent::new(WebhookEventType::UserAddedToGroup, user_id.clone())
+    }
+}
+
+/// Creates the user and its memberships, with explicit errors for the existing users and the
+/// missing groups. Returns the default groups joined.
+async fn import_user(
+    transaction: &DatabaseTransaction,
+    request: ImportUserRequest,
+    default_groups: &[String],
+) -> Result<Vec<GroupId>> {
+    let user_id = request.user.user_id.clone();
+    insert_user(transaction, request.user).await?;
+    for &group_id in &request.group_ids {
+        if model::Group::find_by_id(group_id)
+            .one(transaction)
+            .await?
+            .is_none()
+        {
+            return Err(DomainError::EntityNotFound(format!("{:?}", group_id)));
+        }
+        model::memberships::ActiveModel {
+            user_id: ActiveValue::Set(user_id.clone()),
+            group_id: ActiveValue::Set(group_id),
+        }
+        .insert(transaction)
+        .await?;
+    }
+    add_to_default_groups(transaction, &user_id, default_groups, &request.group_ids).await
+}
+
+impl SqlBackendHandler {
+    /// Hides the user and closes its sessions, but keeps its data. Returns the number of users
+    /// deleted.
+    async fn soft_delete_user(&self, user_id: &UserId) -> Result<u64> {
+        let user_id = user_id.clone();
+        Ok(self
+            .sql_pool
+            .transaction::<_, u64, DomainError>(|transaction| {
+                Box::pin(async move {
+                    let res = model::User::update_many()
+                        .col_expr(
+                            UserColumn::DeletedDate,
+                            Expr::value(chrono::Utc::now().naive_utc()),
+                        )
+                        .filter(UserColumn::UserId.eq(&user_id))
+                        .filter(UserColumn::DeletedDate.is_null())
+                        .exec(transaction)
+                        .await?;
+                    SqlBackendHandler::revoke_all_sessions_in(transaction, &user_id).await?;
+                    model::PasswordResetTokens::delete_many()
+                        .filter(model::PasswordResetTokensColumn::UserId.eq(&user_id))
+                        .exec(transaction)
+                        .await?;
+                    Ok(res.rows_affected)
+                })
+            })
+            .await?)
+    }
+
+    async fn list_users_impl(
         &self,
         filters: Option<UserRequestFilter>,
-        // To simplify the query, we always fetch groups. TODO: cleanup.
-        _get_groups: bool,
+        sort: &[UserSortKey],
+        page: Option<(u64, u64)>,
     ) -> Result<Vec<UserAndGroups>> {
-        debug!(?filters);
+        debug!(?filters, ?sort, ?page);
+        let pool = self.read_pool();
+        let dynamic_groups = self.get_dynamic_groups().await?;
+        let mut condition =
+            get_user_condition(filters.map(|f| expand_user_filter(f, &dynamic_groups)));
+        // The main query returns one row per membership, in user id order, so select the users
+        // of the page, in order, first.
+        let mut order = None;
+        if page.is_some() || !sort.is_empty() {
+            let mut query = order_users(model::User::find().filter(condition.clone()), sort);
+            if let Some((offset, limit)) = page {
+                query = query.offset(offset).limit(limit);
+            }
+            let user_ids = query
+                .select_only()
+                .column(UserColumn::UserId)
+                .into_tuple::<UserId>()
+                .all(pool)
+                .await?;
+            if page.is_some() {
+                condition = UserColumn::UserId.is_in(&user_ids).into_condition();
+            }
+            if !sort.is_empty() {
+                order = Some(user_ids);
+            }
+        }
         let results = model::User::find()
-            .filter(
-                filters
-                    .map(|f| {
-                        UserColumn::UserId
-                            .in_subquery(
-                                model::User::find()
-                                    .find_also_linked(model::memberships::UserToGroup)
-                                    .select_only()
-                                    .column(UserColumn::UserId)
-                                    .filter(get_user_filter_expr(f))
-                                    .into_query(),
-                            )
-                            .into_condition()
-                    })
-                    .unwrap_or_else(|| SimpleExpr::Value(true.into()).into_condition()),
-            )
+            .filter(condition)
             .order_by_asc(UserColumn::UserId)
             //find_with_linked?
             .find_also_linked(model::memberships::UserToGroup)
             .order_by_asc(SimpleExpr::Column(
                 (Alias::new("r1"), GroupColumn::GroupId).into_column_ref(),
             ))
-            .all(&self.sql_pool)
+            .all(pool)
             .await?;
         use itertools::Itertools;
         let mut users: Vec<_> = results
@@ -134,6 +563,12 @@ impl UserListerBackendHandler for SqlBackendHandler {
                     .into_iter()
                     .flat_map(|(_, g)| g)
                     .map(|g| GroupDetails::from(g.clone()))
+                    .chain(
+                        dynamic_groups
+                            .iter()
+                            .filter(|g| g.members.contains(&user.user_id))
+                            .map(|g| g.details.clone()),
+                    )
                     .collect();
                 groups.sort_by(|g1, g2| g1.display_name.cmp(&g2.display_name));
                 UserAndGroups {
@@ -151,7 +586,7 @@ impl UserListerBackendHandler for SqlBackendHandler {
             .filter(model::UserAttributesColumn::UserId.is_in(&user_ids))
             .order_by_asc(model::UserAttributesColumn::UserId)
             .order_by_asc(model::UserAttributesColumn::AttributeName)
-            .all(&self.sql_pool)
+            .all(pool)
             .await?;
         let mut attributes_iter = attributes.into_iter().peekable();
         for user in users.iter_mut() {
@@ -166,103 +601,222 @@ impl UserListerBackendHandler for SqlBackendHandler {
                 .map(AttributeValue::from)
                 .collect();
         }
+        let mut failures = self.get_authentication_failures(&user_ids).await?;
+        for user in users.iter_mut() {
+            user.user.authentication_failures = failures.remove(&user.user.user_id);
+        }
+        if let Some(order) = order {
+            let positions: HashMap<_, _> = order
+                .into_iter()
+                .enumerate()
+                .map(|(position, user_id)| (user_id, position))
+                .collect();
+            users.sort_by_key(|user| positions.get(&user.user.user_id).copied());
+        }
         Ok(users)
     }
 }
 
+#[async_trait]
+impl UserListerBackendHandler for SqlBackendHandler {
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn list_users(
+        &self,
+        filters: Option<UserRequestFilter>,
+        // To simplify the query, we always fetch groups. TODO: cleanup.
+        _get_groups: bool,
+    ) -> Result<Vec<UserAndGroups>> {
+        self.list_users_impl(filters, &[], None).await
+    }
+
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn list_users_page(
+        &self,
+        filters: Option<UserRequestFilter>,
+        _get_groups: bool,
+        offset: u64,
+        limit: u64,
+    ) -> Result<Vec<UserAndGroups>> {
+        self.list_users_impl(filters, &[], Some((offset, limit)))
+            .await
+    }
+
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn list_users_sorted(
+        &self,
+        filters: Option<UserRequestFilter>,
+        _get_groups: bool,
+        sort: Vec<UserSortKey>,
+        page: Option<(u64, u64)>,
+    ) -> Result<Vec<UserAndGroups>> {
+        self.list_users_impl(filters, &sort, page).await
+    }
+
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn count_users(&self, filters: Option<UserRequestFilter>) -> Result<u64> {
+        debug!(?filters);
+        let dynamic_groups = self.get_dynamic_groups().await?;
+        let pool = self.read_pool();
+        Ok(model::User::find()
+            .filter(get_user_condition(
+                filters.map(|f| expand_user_filter(f, &dynamic_groups)),
+            ))
+            .count(pool)
+            .await?)
+    }
+}
+
 #[async_trait]
 impl UserBackendHandler for SqlBackendHandler {
     #[instrument(skip_all, level = "debug", ret)]
     async fn get_user_details(&self, user_id: &UserId) -> Result<User> {
         debug!(?user_id);
+        let pool = self.read_pool();
         let mut user = User::from(
             model::User::find_by_id(user_id.to_owned())
-                .one(&self.sql_pool)
+                .filter(UserColumn::DeletedDate.is_null())
+                .one(pool)
                 .await?
                 .ok_or_else(|| DomainError::EntityNotFound(user_id.to_string()))?,
         );
         let attributes = model::UserAttributes::find()
             .filter(model::UserAttributesColumn::UserId.eq(user_id))
             .order_by_asc(model::UserAttributesColumn::AttributeName)
-            .all(&self.sql_pool)
+            .all(pool)
             .await?;
         user.attributes = attributes.into_iter().map(AttributeValue::from).collect();
+        user.authentication_failures = self
+            .get_authentication_failures(&[user_id.clone()])
+            .await?
+            .remove(user_id);
         Ok(user)
     }
 
     #[instrument(skip_all, level = "debug", ret, err)]
     async fn get_user_groups(&self, user_id: &UserId) -> Result<HashSet<GroupDetails>> {
         debug!(?user_id);
+        // The permissions come from the groups: no replica lag here.
         let user = model::User::find_by_id(user_id.to_owned())
+            .filter(UserColumn::DeletedDate.is_null())
             .one(&self.sql_pool)
             .await?
             .ok_or_else(|| DomainError::EntityNotFound(user_id.to_string()))?;
-        Ok(HashSet::from_iter(
+        let mut groups = HashSet::from_iter(
             user.find_linked(model::memberships::UserToGroup)
                 .into_model::<GroupDetails>()
                 .all(&self.sql_pool)
                 .await?,
-        ))
+        );
+        groups.extend(
+            self.get_dynamic_groups()
+                .await?
+                .iter()
+                .filter(|g| g.members.contains(user_id))
+                .map(|g| g.details.clone()),
+        );
+        Ok(groups)
     }
 
     #[instrument(skip_all, level = "debug", err)]
     async fn create_user(&self, request: CreateUserRequest) -> Result<()> {
         debug!(user_id = ?request.user_id);
-        let now = chrono::Utc::now().naive_utc();
-        let uuid = Uuid::from_name_and_date(request.user_id.as_str(), &now);
-        let new_user = model::users::ActiveModel {
-            user_id: Set(request.user_id.clone()),
-            email: Set(request.email),
-            display_name: to_value(&request.display_name),
-            creation_date: ActiveValue::Set(now),
-            uuid: ActiveValue::Set(uuid),
-            ..Default::default()
-        };
-        let mut new_user_attributes = Vec::new();
-        if let Some(first_name) = request.first_name {
-            new_user_attributes.push(model::user_attributes::ActiveModel {
-                user_id: Set(request.user_id.clone()),
-                attribute_name: Set("first_name".to_owned()),
-                value: Set(Serialized::from(&first_name)),
-            });
-        }
-        if let Some(last_name) = request.last_name {
-            new_user_attributes.push(model::user_attributes::ActiveModel {
-                user_id: Set(request.user_id.clone()),
-                attribute_name: Set("last_name".to_owned()),
-                value: Set(Serialized::from(&last_name)),
-            });
-        }
-        if let Some(avatar) = request.avatar {
-            new_user_attributes.push(model::user_attributes::ActiveModel {
-                user_id: Set(request.user_id),
-                attribute_name: Set("avatar".to_owned()),
-                value: Set(Serialized::from(&avatar)),
-            });
-        }
-        self.sql_pool
-            .transaction::<_, (), DomainError>(|transaction| {
+        let event = user_created_event(&request);
+        let user_id = request.user_id.clone();
+        let default_groups = self.config.default_user_groups.clone();
+        let group_ids = self
+            .sql_pool
+            .transaction::<_, Vec<GroupId>, DomainError>(|transaction| {
+                let user_id = user_id.clone();
                 Box::pin(async move {
-                    new_user.insert(transaction).await?;
-                    if !new_user_attributes.is_empty() {
-                        model::UserAttributes::insert_many(new_user_attributes)
-                            .exec(transaction)
-                            .await?;
-                    }
-                    Ok(())
+                    insert_user(transaction, request).await?;
+                    add_to_default_groups(transaction, &user_id, &default_groups, &[]).await
                 })
             })
             .await?;
+        self.notify(event);
+        for group_id in group_ids {
+            self.notify(user_added_to_group_event(&user_id, group_id));
+        }
         Ok(())
     }
 
+    #[instrument(skip_all, level = "debug", err)]
+    async fn import_users(
+        &self,
+        requests: Vec<ImportUserRequest>,
+        atomic: bool,
+    ) -> Result<Vec<Result<()>>> {
+        debug!(count = requests.len(), atomic);
+        let transaction = self.sql_pool.begin().await?;
+        let mut results = Vec::with_capacity(requests.len());
+        let mut events = Vec::new();
+        for request in requests {
+            let user_id = request.user.user_id.clone();
+            let mut user_events = vec![user_created_event(&request.user)];
+            user_events.extend(
+                request
+                    .group_ids
+                    .iter()
+                    .map(|&group_id| user_added_to_group_event(&user_id, group_id)),
+            );
+            // Each user in a savepoint, so that a failure doesn't abort the whole transaction.
+            let savepoint = transaction.begin().await?;
+            match import_user(&savepoint, request, &self.config.default_user_groups).await {
+                Ok(default_group_ids) => {
+                    savepoint.commit().await?;
+                    events.extend(user_events);
+                    events.extend(
+                        default_group_ids
+                            .into_iter()
+                            .map(|group_id| user_added_to_group_event(&user_id, group_id)),
+                    );
+                    results.push(Ok(()));
+                }
+                Err(e) => {
+                    savepoint.rollback().await?;
+                    results.push(Err(e));
+                }
+            }
+        }
+        if atomic && results.iter().any(Result::is_err) {
+            transaction.rollback().await?;
+        } else {
+            transaction.commit().await?;
+            for event in events {
+                self.notify(event);
+            }
+        }
+        Ok(results)
+    }
+
     #[instrument(skip_all, level = "debug", err)]
     async fn update_user(&self, request: UpdateUserRequest) -> Result<()> {
         debug!(user_id = ?request.user_id);
+        let mut event = WebhookEvent::new(WebhookEventType::UserUpdated, request.user_id.clone());
+        event.changed_fields = [
+            ("email", request.email.is_some()),
+            ("display_name", request.display_name.is_some()),
+            ("first_name", request.first_name.is_some()),
+            ("last_name", request.last_name.is_some()),
+            ("avatar", request.avatar.is_some()),
+        ]
+        .into_iter()
+        .filter(|(_, is_set)| *is_set)
+        .map(|(field, _)| field.to_owned())
+        .chain(request.insert_attributes.iter().map(|a| a.name.clone()))
+        .chain(request.delete_attributes.iter().cloned())
+        .collect();
+        let new_email = request.email.clone();
         let update_user = model::users::ActiveModel {
             user_id: ActiveValue::Set(request.user_id.clone()),
+            lowercase_email: request
+                .email
+                .as_ref()
+                .map(|email| ActiveValue::Set(email.to_lowercase()))
+                .unwrap_or_default(),
             email: request.email.map(ActiveValue::Set).unwrap_or_default(),
             display_name: to_value(&request.display_name),
+            modified_date: ActiveValue::Set(chrono::Utc::now().naive_utc()),
             ..Default::default()
         };
         let mut update_user_attributes = Vec::new();
@@ -295,9 +849,18 @@ impl UserBackendHandler for SqlBackendHandler {
         if let Some(avatar) = request.avatar {
             process_serialized(avatar.into_active_value(), "avatar");
         }
+        for attribute in request.insert_attributes {
+            process_serialized(ActiveValue::Set(attribute.value), &attribute.name);
+        }
+        for attribute_name in &request.delete_attributes {
+            process_serialized(ActiveValue::NotSet, attribute_name);
+        }
         self.sql_pool
             .transaction::<_, (), DomainError>(|transaction| {
                 Box::pin(async move {
+                    if let Some(email) = &new_email {
+                        check_email_is_available(transaction, email, &request.user_id).await?;
+                    }
                     update_user.update(transaction).await?;
                     if !update_user_attributes.is_empty() {
                         model::UserAttributes::insert_many(update_user_attributes)
@@ -322,17 +885,112 @@ impl UserBackendHandler for SqlBackendHandler {
                             .exec(transaction)
                             .await?;
                     }
+                    reindex_user_attributes(transaction, Some(&request.user_id)).await?;
                     Ok(())
                 })
             })
             .await?;
+        self.notify(event);
         Ok(())
     }
 
     #[instrument(skip_all, level = "debug", err)]
     async fn delete_user(&self, user_id: &UserId) -> Result<()> {
         debug!(?user_id);
-        let res = model::User::delete_by_id(user_id.clone())
+        let rows_affected = if self.config.deleted_users_retention_days > 0 {
+            self.soft_delete_user(user_id).await?
+        } else {
+            model::User::delete_by_id(user_id.clone())
+                .exec(&self.sql_pool)
+                .await?
+                .rows_affected
+        };
+        if rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such user: '{}'",
+                user_id
+            )));
+        }
+        self.notify(WebhookEvent::new(
+            WebhookEventType::UserDeleted,
+            user_id.clone(),
+        ));
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn restore_user(&self, user_id: &UserId) -> Result<()> {
+        debug!(?user_id);
+        let res = model::User::update_many()
+            .col_expr(
+                UserColumn::DeletedDate,
+                Expr::value(Option::<chrono::NaiveDateTime>::None),
+            )
+            .filter(UserColumn::UserId.eq(user_id))
+            .filter(UserColumn::DeletedDate.is_not_null())
+            .exec(&self.sql_pool)
+            .await?;
+        if res.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No deleted user: '{}'",
+                user_id
+            )));
+        }
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn set_user_enabled(&self, user_id: &UserId, enabled: bool) -> Result<()> {
+        debug!(?user_id, enabled);
+        let user_id = user_id.clone();
+        let rows_affected = self
+            .sql_pool
+            .transaction::<_, u64, DomainError>(|transaction| {
+                Box::pin(async move {
+                    let res = model::User::update_many()
+                        .col_expr(UserColumn::IsEnabled, Expr::value(enabled))
+                        .col_expr(
+                            UserColumn::ModifiedDate,
+                            Expr::value(chrono::Utc::now().naive_utc()),
+                        )
+                        .filter(UserColumn::UserId.eq(&user_id))
+                        .filter(UserColumn::DeletedDate.is_null())
+                        .exec(transaction)
+                        .await?;
+                    if res.rows_affected > 0 && !enabled {
+                        SqlBackendHandler::revoke_all_sessions_in(transaction, &user_id).await?;
+                    }
+                    Ok(res.rows_affected)
+                })
+            })
+            .await?;
+        if rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such user: '{}'",
+                user_id
+            )));
+        }
+        let mut event = WebhookEvent::new(WebhookEventType::UserUpdated, user_id);
+        event.changed_fields = vec!["is_enabled".to_owned()];
+        self.notify(event);
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn set_user_expiration_date(
+        &self,
+        user_id: &UserId,
+        expiration_date: Option<chrono::NaiveDateTime>,
+    ) -> Result<()> {
+        debug!(?user_id, ?expiration_date);
+        let res = model::User::update_many()
+            .col_expr(UserColumn::ExpirationDate, Expr::value(expiration_date))
+            .col_expr(
+                UserColumn::ModifiedDate,
+                Expr::value(chrono::Utc::now().naive_utc()),
+            )
+            .filter(UserColumn::UserId.eq(user_id))
+            .filter(UserColumn::DeletedDate.is_null())
             .exec(&self.sql_pool)
             .await?;
         if res.rows_affected == 0 {
@@ -341,17 +999,31 @@ impl UserBackendHandler for SqlBackendHandler {
                 user_id
             )));
         }
+        let mut event = WebhookEvent::new(WebhookEventType::UserUpdated, user_id.clone());
+        event.changed_fields = vec!["expiration_date".to_owned()];
+        self.notify(event);
         Ok(())
     }
 
     #[instrument(skip_all, level = "debug", err)]
     async fn add_user_to_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()> {
         debug!(?user_id, ?group_id);
+        if self
+            .get_dynamic_groups()
+            .await?
+            .iter()
+            .any(|g| g.details.group_id == group_id)
+        {
+            return Err(DomainError::InternalError(
+                "Can't add members to a dynamic group".to_owned(),
+            ));
+        }
         let new_membership = model::memberships::ActiveModel {
             user_id: ActiveValue::Set(user_id.clone()),
             group_id: ActiveValue::Set(group_id),
         };
         new_membership.insert(&self.sql_pool).await?;
+        self.notify(user_added_to_group_event(user_id, group_id));
         Ok(())
     }
 
@@ -367,6 +1039,10 @@ impl UserBackendHandler for SqlBackendHandler {
                 user_id, group_id
             )));
         }
+        self.notify(WebhookEvent {
+            group_id: Some(group_id),
+            ..WebhookEvent::new(WebhookEventType::UserRemovedFromGroup, user_id.clone())
+        });
         Ok(())
     }
 }
@@ -375,10 +1051,11 @@ impl UserBackendHandler for SqlBackendHandler {
 mod tests {
     use super::*;
     use crate::domain::{
-        handler::SubStringFilter,
+        handler::GroupListerBackendHandler,
         sql_backend_handler::tests::*,
-        types::{JpegPhoto, UserColumn},
+        types::{AttributeType, JpegPhoto, UserColumn},
     };
+    use sea_orm::DbBackend;
 
     #[tokio::test]
     async fn test_list_users_no_filter() {
@@ -388,64 +1065,509 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_list_users_user_id_filter() {
+    async fn test_list_users_user_id_filter() {
+        let fixture = TestFixture::new().await;
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::UserId(UserId::new("bob"))),
+        )
+        .await;
+        assert_eq!(users, vec!["bob"]);
+    }
+
+    #[tokio::test]
+    async fn test_list_users_display_name_filter() {
+        let fixture = TestFixture::new().await;
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::Equality(
+                UserColumn::DisplayName,
+                "display bob".to_string(),
+            )),
+        )
+        .await;
+        assert_eq!(users, vec!["bob"]);
+    }
+
+    #[tokio::test]
+    async fn test_list_users_attribute_equality_ignore_case() {
+        let fixture = TestFixture::new().await;
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::AttributeEquality(
+                "first_name".to_string(),
+                "First Bob".to_string(),
+            )),
+        )
+        .await;
+        assert!(users.is_empty());
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::AttributeEqualityIgnoreCase(
+                "first_name".to_string(),
+                "First Bob".to_string(),
+            )),
+        )
+        .await;
+        assert_eq!(users, vec!["bob"]);
+    }
+
+    #[tokio::test]
+    async fn test_list_users_other_filter() {
+        let fixture = TestFixture::new().await;
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::AttributeEquality(
+                "first_name".to_string(),
+                "first bob".to_string(),
+            )),
+        )
+        .await;
+        assert_eq!(users, vec!["bob"]);
+    }
+
+    #[tokio::test]
+    async fn test_list_users_substring_filter() {
+        let fixture = TestFixture::new().await;
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::And(vec![
+                UserRequestFilter::UserIdSubString(SubStringFilter {
+                    initial: Some("Pa".to_owned()),
+                    any: vec!["rI".to_owned()],
+                    final_: Some("K".to_owned()),
+                }),
+                UserRequestFilter::SubString(
+                    UserColumn::DisplayName,
+                    SubStringFilter {
+                        initial: None,
+                        any: vec!["t".to_owned(), "r".to_owned()],
+                        final_: None,
+                    },
+                ),
+            ])),
+        )
+        .await;
+        assert_eq!(users, vec!["patrick"]);
+    }
+
+    #[tokio::test]
+    async fn test_list_users_approx_filter() {
+        let fixture = TestFixture::new().await;
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::ApproxMatch(
+                UserColumn::DisplayName,
+                "PLAY PAT".to_owned(),
+            )),
+        )
+        .await;
+        assert_eq!(users, vec!["patrick"]);
+        // The wildcards are matched literally.
+        for value in ["%", "_", "\\"] {
+            let users = get_user_names(
+                &fixture.handler,
+                Some(UserRequestFilter::ApproxMatch(
+                    UserColumn::DisplayName,
+                    value.to_owned(),
+                )),
+            )
+            .await;
+            assert_eq!(users, Vec::<String>::new());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_users_attribute_substring_filter() {
+        let fixture = TestFixture::new().await;
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::AttributeSubString(
+                "first_name".to_owned(),
+                SubStringFilter {
+                    initial: Some("FiRsT".to_owned()),
+                    any: vec![],
+                    final_: Some("K".to_owned()),
+                },
+            )),
+        )
+        .await;
+        assert_eq!(users, vec!["patrick"]);
+    }
+
+    #[tokio::test]
+    async fn test_list_users_attribute_present_filter() {
+        let fixture = TestFixture::new().await;
+        fixture
+            .handler
+            .update_user(UpdateUserRequest {
+                user_id: UserId::new("bob"),
+                first_name: Some(String::new()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::AttributePresent("first_name".to_owned())),
+        )
+        .await;
+        assert_eq!(users, vec!["john", "nogroup", "patrick"]);
+    }
+
+    #[tokio::test]
+    async fn test_list_users_attribute_list_contains_filter() {
+        let fixture = TestFixture::new().await;
+        let set_mail_aliases = |user_id: &str, aliases: Vec<&str>| {
+            fixture.handler.update_user(UpdateUserRequest {
+                user_id: UserId::new(user_id),
+                insert_attributes: vec![AttributeValue {
+                    name: "mail_aliases".to_owned(),
+                    value: Serialized::from(&aliases),
+                }],
+                ..Default::default()
+            })
+        };
+        set_mail_aliases("patrick", vec!["pat@example.com", "patrick@example.com"])
+            .await
+            .unwrap();
+        // The serialized value of "a\x01\0\0\0\0\0\0\0b" is in the serialized list, across
+        // the two values.
+        set_mail_aliases("bob", vec!["\x0a\0\0\0\0\0\0\0a", "b"])
+            .await
+            .unwrap();
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::AttributeListContains(
+                "mail_aliases".to_owned(),
+                "patrick@example.com".to_owned(),
+            )),
+        )
+        .await;
+        assert_eq!(users, vec!["patrick"]);
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::AttributeListContains(
+                "mail_aliases".to_owned(),
+                "example.com".to_owned(),
+            )),
+        )
+        .await;
+        assert_eq!(users, Vec::<String>::new());
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::AttributeListContains(
+                "mail_aliases".to_owned(),
+                "a\x01\0\0\0\0\0\0\0b".to_owned(),
+            )),
+        )
+        .await;
+        assert_eq!(users, Vec::<String>::new());
+    }
+
+    #[tokio::test]
+    async fn test_list_users_multi_valued_attribute_equality() {
+        let fixture = TestFixture::new().await;
+        model::UserAttributeSchema::insert(model::user_attribute_schema::ActiveModel {
+            attribute_name: Set("phone_numbers".to_owned()),
+            attribute_type: Set(AttributeType::String),
+            is_list: Set(true),
+            is_user_visible: Set(true),
+            is_user_editable: Set(true),
+            is_hardcoded: Set(false),
+            is_case_sensitive: Set(false),
+            allowed_values: Set(None),
+        })
+        .exec(&fixture.handler.sql_pool)
+        .await
+        .unwrap();
+        fixture
+            .handler
+            .update_user(UpdateUserRequest {
+                user_id: UserId::new("patrick"),
+                insert_attributes: vec![AttributeValue {
+                    name: "phone_numbers".to_owned(),
+                    value: Serialized::from(&vec!["555-0001".to_owned(), "555-0002".to_owned()]),
+                }],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::AttributeEquality(
+                "phone_numbers".to_owned(),
+                "555-0002".to_owned(),
+            )),
+        )
+        .await;
+        assert_eq!(users, vec!["patrick"]);
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::AttributeEquality(
+                "phone_numbers".to_owned(),
+                "555".to_owned(),
+            )),
+        )
+        .await;
+        assert_eq!(users, Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_attribute_filters_sql_is_portable() {
+        // The serialized values can't be decoded the same way by all the backends: the filters
+        // use the index of the values instead.
+        let filters = [
+            UserRequestFilter::AttributeEquality("phone_numbers".to_owned(), "555-0002".to_owned()),
+            UserRequestFilter::AttributeEqualityIgnoreCase(
+                "first_name".to_owned(),
+                "First Bob".to_owned(),
+            ),
+            UserRequestFilter::AttributeListContains(
+                "mail_aliases".to_owned(),
+                "patrick@example.com".to_owned(),
+            ),
+            UserRequestFilter::AttributeSubString(
+                "first_name".to_owned(),
+                SubStringFilter {
+                    initial: Some("FiRsT".to_owned()),
+                    any: vec![],
+                    final_: Some("K".to_owned()),
+                },
+            ),
+        ];
+        for filter in filters {
+            for backend in [DbBackend::Sqlite, DbBackend::MySql, DbBackend::Postgres] {
+                let sql = model::User::find()
+                    .filter(get_user_condition(Some(filter.clone())))
+                    .build(backend)
+                    .to_string();
+                for function in ["INSTR", "SUBSTR"] {
+                    assert!(
+                        !sql.contains(function),
+                        "{:?} uses {} with {:?}: {}",
+                        filter,
+                        function,
+                        backend,
+                        sql
+                    );
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_users_page() {
+        let fixture = TestFixture::new().await;
+        let get_page = |offset, limit| {
+            let handler = &fixture.handler;
+            async move {
+                handler
+                    .list_users_page(None, true, offset, limit)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|u| (u.user.user_id.to_string(), u.groups.unwrap().len()))
+                    .collect::<Vec<_>>()
+            }
+        };
+        assert_eq!(
+            get_page(0, 2).await,
+            vec![("bob".to_owned(), 1), ("john".to_owned(), 1)]
+        );
+        assert_eq!(
+            get_page(2, 2).await,
+            vec![("nogroup".to_owned(), 0), ("patrick".to_owned(), 2)]
+        );
+        assert_eq!(get_page(4, 2).await, vec![]);
+    }
+
+    #[tokio::test]
+    async fn test_list_users_sorted() {
+        let fixture = TestFixture::new().await;
+        for (user_id, last_name) in [("patrick", "Adams"), ("John", "zed"), ("bob", "")] {
+            fixture
+                .handler
+                .update_user(UpdateUserRequest {
+                    user_id: UserId::new(user_id),
+                    last_name: Some(last_name.to_owned()),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+        let get_sorted = |sort: Vec<UserSortKey>, page| {
+            let handler = &fixture.handler;
+            async move {
+                handler
+                    .list_users_sorted(None, true, sort, page)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|u| (u.user.user_id.to_string(), u.groups.unwrap().len()))
+                    .collect::<Vec<_>>()
+            }
+        };
+        let key = |column, descending| UserSortKey { column, descending };
+        let user = |user_id: &str, groups| (user_id.to_owned(), groups);
+        // Ignoring the case, and the users without a last name come last.
+        assert_eq!(
+            get_sorted(vec![key(UserSortColumn::LastName, false)], None).await,
+            vec![
+                user("patrick", 2),
+                user("nogroup", 0),
+                user("john", 1),
+                user("bob", 1)
+            ]
+        );
+        assert_eq!(
+            get_sorted(vec![key(UserSortColumn::LastName, true)], Some((0, 2))).await,
+            vec![user("bob", 1), user("john", 1)]
+        );
+        assert_eq!(
+            get_sorted(vec![key(UserSortColumn::LastName, false)], Some((1, 2))).await,
+            vec![user("nogroup", 0), user("john", 1)]
+        );
+        assert_eq!(
+            get_sorted(
+                vec![
+                    key(UserSortColumn::Email, true),
+                    key(UserSortColumn::DisplayName, false)
+                ],
+                Some((0, 3))
+            )
+            .await,
+            vec![user("patrick", 2), user("nogroup", 0), user("john", 1)]
+        );
+        // Without keys, in user id order.
+        assert_eq!(
+            get_sorted(vec![], Some((3, 2))).await,
+            vec![user("patrick", 2)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_count_users() {
+        let fixture = TestFixture::new().await;
+        assert_eq!(fixture.handler.count_users(None).await.unwrap(), 4);
+        assert_eq!(
+            fixture
+                .handler
+                .count_users(Some(UserRequestFilter::MemberOfId(fixture.groups[0])))
+                .await
+                .unwrap(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_users_creation_date_filter() {
+        let fixture = TestFixture::new().await;
+        let now = chrono::Utc::now().naive_utc();
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::CreationDateAfter(now)),
+        )
+        .await;
+        assert_eq!(users, Vec::<String>::new());
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::CreationDateBefore(now)),
+        )
+        .await;
+        assert_eq!(users, vec!["bob", "john", "nogroup", "patrick"]);
+    }
+
+    #[tokio::test]
+    async fn test_list_users_modified_date_filter() {
         let fixture = TestFixture::new().await;
+        let before_update = chrono::Utc::now().naive_utc();
+        fixture
+            .handler
+            .update_user(UpdateUserRequest {
+                user_id: UserId::new("bob"),
+                display_name: Some("new bob".to_owned()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
         let users = get_user_names(
             &fixture.handler,
-            Some(UserRequestFilter::UserId(UserId::new("bob"))),
+            Some(UserRequestFilter::ModifiedDateAfter(before_update)),
         )
         .await;
         assert_eq!(users, vec!["bob"]);
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::ModifiedDateBefore(before_update)),
+        )
+        .await;
+        assert_eq!(users, vec!["john", "nogroup", "patrick"]);
     }
 
     #[tokio::test]
-    async fn test_list_users_display_name_filter() {
+    async fn test_list_users_expiration_date_filter() {
         let fixture = TestFixture::new().await;
+        let now = chrono::Utc::now().naive_utc();
+        for (user, days) in [("bob", 10), ("patrick", 100)] {
+            fixture
+                .handler
+                .set_user_expiration_date(
+                    &UserId::new(user),
+                    Some(now + chrono::Duration::days(days)),
+                )
+                .await
+                .unwrap();
+        }
+        // The users without an expiration date never match.
         let users = get_user_names(
             &fixture.handler,
-            Some(UserRequestFilter::Equality(
-                UserColumn::DisplayName,
-                "display bob".to_string(),
+            Some(UserRequestFilter::ExpirationDateBefore(
+                now + chrono::Duration::days(30),
             )),
         )
         .await;
         assert_eq!(users, vec!["bob"]);
+        assert!(fixture
+            .handler
+            .get_user_details(&UserId::new("patrick"))
+            .await
+            .unwrap()
+            .expiration_date
+            .is_some());
+        fixture
+            .handler
+            .set_user_expiration_date(&UserId::new("unknown"), None)
+            .await
+            .unwrap_err();
     }
 
     #[tokio::test]
-    async fn test_list_users_other_filter() {
+    async fn test_list_users_last_login_filter() {
         let fixture = TestFixture::new().await;
+        let now = chrono::Utc::now().naive_utc();
+        for (user, days) in [("bob", 1), ("patrick", 100)] {
+            model::User::update_many()
+                .col_expr(
+                    UserColumn::LastLogin,
+                    Expr::value(now - chrono::Duration::days(days)),
+                )
+                .filter(UserColumn::UserId.eq(user))
+                .exec(&fixture.handler.sql_pool)
+                .await
+                .unwrap();
+        }
+        let last_month = now - chrono::Duration::days(30);
         let users = get_user_names(
             &fixture.handler,
-            Some(UserRequestFilter::AttributeEquality(
-                "first_name".to_string(),
-                "first bob".to_string(),
-            )),
+            Some(UserRequestFilter::LastLoginAfter(last_month)),
         )
         .await;
         assert_eq!(users, vec!["bob"]);
-    }
-
-    #[tokio::test]
-    async fn test_list_users_substring_filter() {
-        let fixture = TestFixture::new().await;
+        // The users who never logged in never match.
         let users = get_user_names(
             &fixture.handler,
-            Some(UserRequestFilter::And(vec![
-                UserRequestFilter::UserIdSubString(SubStringFilter {
-                    initial: Some("Pa".to_owned()),
-                    any: vec!["rI".to_owned()],
-                    final_: Some("K".to_owned()),
-                }),
-                UserRequestFilter::SubString(
-                    UserColumn::DisplayName,
-                    SubStringFilter {
-                        initial: None,
-                        any: vec!["t".to_owned(), "r".to_owned()],
-                        final_: None,
-                    },
-                ),
-            ])),
+            Some(UserRequestFilter::LastLoginBefore(last_month)),
         )
         .await;
         assert_eq!(users, vec!["patrick"]);
@@ -489,6 +1611,22 @@ mod tests {
         assert_eq!(users, vec!["bob", "patrick"]);
     }
 
+    #[tokio::test]
+    async fn test_list_users_uuid_filter() {
+        let fixture = TestFixture::new().await;
+        let bob = fixture
+            .handler
+            .get_user_details(&UserId::new("bob"))
+            .await
+            .unwrap();
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::UuidEquality(bob.uuid)),
+        )
+        .await;
+        assert_eq!(users, vec!["bob"]);
+    }
+
     #[tokio::test]
     async fn test_list_users_member_of_id() {
         let fixture = TestFixture::new().await;
@@ -559,6 +1697,13 @@ mod tests {
         assert_eq!(users, vec!["john", "nogroup", "patrick"]);
     }
 
+    #[tokio::test]
+    async fn test_list_users_uid_number_filter() {
+        let fixture = TestFixture::new().await;
+        let users = get_user_names(&fixture.handler, Some(UserRequestFilter::UidNumber(2))).await;
+        assert_eq!(users, vec!["patrick"]);
+    }
+
     #[tokio::test]
     async fn test_list_users_with_groups() {
         let fixture = TestFixture::new().await;
@@ -699,6 +1844,74 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_uid_number_not_reused() {
+        let fixture = TestFixture::new().await;
+        insert_user_no_password(&fixture.handler, "first").await;
+        let first = fixture
+            .handler
+            .get_user_details(&UserId::new("first"))
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .delete_user(&UserId::new("first"))
+            .await
+            .unwrap();
+        insert_user_no_password(&fixture.handler, "second").await;
+        let second = fixture
+            .handler
+            .get_user_details(&UserId::new("second"))
+            .await
+            .unwrap();
+        assert_eq!(second.uid_number, first.uid_number + 1);
+    }
+
+    #[tokio::test]
+    async fn test_soft_delete_user() {
+        let mut fixture = TestFixture::new().await;
+        fixture.handler.config.deleted_users_retention_days = 30;
+        fixture
+            .handler
+            .delete_user(&UserId::new("bob"))
+            .await
+            .unwrap();
+        assert_eq!(
+            get_user_names(&fixture.handler, None).await,
+            vec!["john", "nogroup", "patrick"]
+        );
+        fixture
+            .handler
+            .get_user_details(&UserId::new("bob"))
+            .await
+            .unwrap_err();
+        let groups = fixture.handler.list_groups(None).await.unwrap();
+        assert_eq!(groups[0].display_name, "Best Group");
+        assert_eq!(groups[0].users, vec![UserId::new("patrick")]);
+        // Already deleted.
+        fixture
+            .handler
+            .delete_user(&UserId::new("bob"))
+            .await
+            .unwrap_err();
+
+        fixture
+            .handler
+            .restore_user(&UserId::new("bob"))
+            .await
+            .unwrap();
+        assert_eq!(
+            get_user_names(&fixture.handler, None).await,
+            vec!["bob", "john", "nogroup", "patrick"]
+        );
+        // Not deleted anymore.
+        fixture
+            .handler
+            .restore_user(&UserId::new("bob"))
+            .await
+            .unwrap_err();
+    }
+
     #[tokio::test]
     async fn test_get_user_groups() {
         let fixture = TestFixture::new().await;
@@ -722,6 +1935,40 @@ mod tests {
         assert_eq!(get_group_ids("nogroup").await, vec![]);
     }
 
+    #[tokio::test]
+    async fn test_webhook_events() {
+        let fixture = TestFixture::new().await;
+        let (notifier, mut events) = crate::infra::webhooks::WebhookNotifier::new_for_tests();
+        let handler = fixture.handler.with_webhooks(Some(notifier));
+        handler
+            .update_user(UpdateUserRequest {
+                user_id: UserId::new("bob"),
+                email: Some("email".to_string()),
+                delete_attributes: vec!["avatar".to_owned()],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        handler
+            .remove_user_from_group(&UserId::new("bob"), fixture.groups[0])
+            .await
+            .unwrap();
+        // Failed operations don't send events.
+        handler
+            .delete_user(&UserId::new("unknown"))
+            .await
+            .unwrap_err();
+
+        let event = events.try_recv().unwrap();
+        assert_eq!(event.event, WebhookEventType::UserUpdated);
+        assert_eq!(event.user_id, Some(UserId::new("bob")));
+        assert_eq!(event.changed_fields, vec!["email", "avatar"]);
+        let event = events.try_recv().unwrap();
+        assert_eq!(event.event, WebhookEventType::UserRemovedFromGroup);
+        assert_eq!(event.group_id, Some(fixture.groups[0]));
+        assert!(events.try_recv().is_err());
+    }
+
     #[tokio::test]
     async fn test_update_user_all_values() {
         let fixture = TestFixture::new().await;
@@ -735,6 +1982,7 @@ mod tests {
                 first_name: Some("first_name".to_string()),
                 last_name: Some("last_name".to_string()),
                 avatar: Some(JpegPhoto::for_tests()),
+                ..Default::default()
             })
             .await
             .unwrap();
@@ -802,6 +2050,38 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_update_user_custom_attributes() {
+        let fixture = TestFixture::new().await;
+
+        fixture
+            .handler
+            .update_user(UpdateUserRequest {
+                user_id: UserId::new("bob"),
+                insert_attributes: vec![AttributeValue {
+                    name: "first_name".to_owned(),
+                    value: Serialized::from("new bob"),
+                }],
+                delete_attributes: vec!["last_name".to_owned()],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let user = fixture
+            .handler
+            .get_user_details(&UserId::new("bob"))
+            .await
+            .unwrap();
+        assert_eq!(
+            user.attributes,
+            vec![AttributeValue {
+                name: "first_name".to_owned(),
+                value: Serialized::from("new bob")
+            }]
+        );
+    }
+
     #[tokio::test]
     async fn test_update_user_delete_avatar() {
         let fixture = TestFixture::new().await;
@@ -887,6 +2167,189 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_case_insensitive_uniqueness() {
+        let fixture = TestFixture::new().await;
+
+        assert!(matches!(
+            fixture
+                .handler
+                .create_user(CreateUserRequest {
+                    user_id: UserId::new("BOB"),
+                    email: "other@bob.bob".to_owned(),
+                    ..Default::default()
+                })
+                .await,
+            Err(DomainError::EntityAlreadyExists(_))
+        ));
+        assert!(matches!(
+            fixture
+                .handler
+                .create_user(CreateUserRequest {
+                    user_id: UserId::new("james"),
+                    email: "BOB@bob.bob".to_owned(),
+                    ..Default::default()
+                })
+                .await,
+            Err(DomainError::EntityAlreadyExists(_))
+        ));
+        assert!(matches!(
+            fixture
+                .handler
+                .update_user(UpdateUserRequest {
+                    user_id: UserId::new("patrick"),
+                    email: Some("Bob@Bob.Bob".to_owned()),
+                    ..Default::default()
+                })
+                .await,
+            Err(DomainError::EntityAlreadyExists(_))
+        ));
+        // Changing the case of one's own email is fine.
+        fixture
+            .handler
+            .update_user(UpdateUserRequest {
+                user_id: UserId::new("bob"),
+                email: Some("Bob@Bob.Bob".to_owned()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            get_user_names(
+                &fixture.handler,
+                Some(UserRequestFilter::Equality(
+                    UserColumn::Email,
+                    "bob@BOB.bob".to_owned()
+                )),
+            )
+            .await,
+            vec!["bob"]
+        );
+    }
+
+    fn import_request(user_id: &str, group_ids: Vec<GroupId>) -> ImportUserRequest {
+        ImportUserRequest {
+            user: CreateUserRequest {
+                user_id: UserId::new(user_id),
+                email: format!("{}@example.com", user_id),
+                ..Default::default()
+            },
+            group_ids,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_import_users() {
+        let fixture = TestFixture::new().await;
+
+        let results = fixture
+            .handler
+            .import_users(
+                vec![
+                    import_request("james", vec![fixture.groups[0], fixture.groups[1]]),
+                    import_request("bob", vec![]),
+                    import_request("jane", vec![GroupId(1000)]),
+                    import_request("mary", vec![]),
+                ],
+                false,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(results.len(), 4);
+        assert!(results[0].is_ok());
+        assert!(matches!(
+            results[1],
+            Err(DomainError::EntityAlreadyExists(_))
+        ));
+        assert!(matches!(results[2], Err(DomainError::EntityNotFound(_))));
+        assert!(results[3].is_ok());
+        assert_eq!(
+            get_user_names(&fixture.handler, None).await,
+            vec!["bob", "james", "john", "mary", "nogroup", "patrick"]
+        );
+        assert_eq!(
+            get_user_names(
+                &fixture.handler,
+                Some(UserRequestFilter::MemberOfId(fixture.groups[1])),
+            )
+            .await,
+            vec!["james", "john", "patrick"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_default_user_groups() {
+        let mut fixture = TestFixture::new().await;
+        fixture.handler.config.default_user_groups =
+            vec!["Empty Group".to_owned(), "Missing Group".to_owned()];
+
+        // The missing group doesn't prevent the creation.
+        fixture
+            .handler
+            .create_user(CreateUserRequest {
+                user_id: UserId::new("james"),
+                email: "james@example.com".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        // A default group can also be requested explicitly.
+        let results = fixture
+            .handler
+            .import_users(
+                vec![import_request(
+                    "mary",
+                    vec![fixture.groups[0], fixture.groups[2]],
+                )],
+                true,
+            )
+            .await
+            .unwrap();
+        assert!(results[0].is_ok());
+
+        assert_eq!(
+            get_user_names(
+                &fixture.handler,
+                Some(UserRequestFilter::MemberOfId(fixture.groups[2])),
+            )
+            .await,
+            vec!["james", "mary"]
+        );
+        assert_eq!(
+            get_user_names(
+                &fixture.handler,
+                Some(UserRequestFilter::MemberOfId(fixture.groups[0])),
+            )
+            .await,
+            vec!["bob", "mary", "patrick"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_import_users_atomic() {
+        let fixture = TestFixture::new().await;
+
+        let results = fixture
+            .handler
+            .import_users(
+                vec![
+                    import_request("james", vec![fixture.groups[0]]),
+                    import_request("bob", vec![]),
+                ],
+                true,
+            )
+            .await
+            .unwrap();
+
+        assert!(results[0].is_ok());
+        assert!(results[1].is_err());
+        assert_eq!(
+            get_user_names(&fixture.handler, None).await,
+            vec!["bob", "john", "nogroup", "patrick"]
+        );
+    }
+
     #[tokio::test]
     async fn test_remove_user_from_group() {
         let fixture = TestFixture::new().await;
@@ -0,0 +1,140 @@
+//! Time-based one-time passwords (RFC 6238), used as a second factor for the web UI login.
+
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha1::Sha1;
+
+/// Duration of a time step, in seconds.
+pub const TOTP_STEP_SECONDS: i64 = 30;
+const TOTP_DIGITS: u32 = 6;
+/// Number of steps before and after the current one that are accepted, to allow for clock skew.
+const TOTP_SKEW_STEPS: i64 = 1;
+/// Length of the shared secret, in bytes (160 bits, as recommended by RFC 4226).
+const TOTP_SECRET_LENGTH: usize = 20;
+
+pub fn generate_secret() -> Vec<u8> {
+    let mut secret = vec![0; TOTP_SECRET_LENGTH];
+    rand::rngs::OsRng.fill_bytes(&mut secret);
+    secret
+}
+
+/// Base32 encoding of the secret, as expected by the authenticator apps.
+pub fn encode_secret(secret: &[u8]) -> String {
+    data_encoding::BASE32_NOPAD.encode(secret)
+}
+
+/// URI to display as a QR code, for the authenticator apps.
+pub fn otpauth_uri(issuer: &str, account: &str, secret: &[u8]) -> String {
+    format!(
+        "otpauth://totp/{issuer}:{account}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={TOTP_DIGITS}&period={TOTP_STEP_SECONDS}",
+        issuer = urlencoding::encode(issuer),
+        account = urlencoding::encode(account),
+        secret = encode_secret(secret),
+    )
+}
+
+/// HMAC-based one-time password (RFC 4226) for the given counter.
+fn hotp(secret: &[u8], counter: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts keys of any size");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    // Dynamic truncation.
+    let offset = (hash[hash.len() - 1] & 0xf) as usize;
+    let code = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    code % 10u32.pow(TOTP_DIGITS)
+}
+
+/// The code expected at the given time.
+pub fn code_at(secret: &[u8], unix_time: i64) -> String {
+    let step = unix_time.div_euclid(TOTP_STEP_SECONDS);
+    format!(
+        "{:0width$}",
+        hotp(secret, step as u64),
+        width = TOTP_DIGITS as usize
+    )
+}
+
+/// Checks the code against the steps around `unix_time`. To prevent replays, the steps up to
+/// `last_used_step` are not accepted.
+/// Returns the matching step, to be stored as the new `last_used_step`.
+pub fn verify_code(
+    secret: &[u8],
+    code: &str,
+    unix_time: i64,
+    last_used_step: Option<i64>,
+) -> Option<i64> {
+    if code.len() != TOTP_DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let code = code.parse::<u32>().ok()?;
+    let current_step = unix_time.div_euclid(TOTP_STEP_SECONDS);
+    ((current_step - TOTP_SKEW_STEPS)..=(current_step + TOTP_SKEW_STEPS))
+        .filter(|&step| step >= 0 && last_used_step.map_or(true, |last| step > last))
+        .find(|&step| hotp(secret, step as u64) == code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Secret from the RFC 4226 and RFC 6238 test vectors.
+    const SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn test_hotp() {
+        assert_eq!(hotp(SECRET, 0), 755224);
+        assert_eq!(hotp(SECRET, 9), 520489);
+    }
+
+    #[test]
+    fn test_verify_code() {
+        // The RFC 6238 test vectors have 8 digits, we only keep the last 6.
+        assert_eq!(verify_code(SECRET, "287082", 59, None), Some(1));
+        assert_eq!(
+            verify_code(SECRET, "081804", 1111111109, None),
+            Some(37037036)
+        );
+        assert_eq!(
+            verify_code(SECRET, "005924", 1234567890, None),
+            Some(41152263)
+        );
+        // Clock skew.
+        assert_eq!(
+            verify_code(SECRET, "005924", 1234567890 + 30, None),
+            Some(41152263)
+        );
+        assert_eq!(verify_code(SECRET, "005924", 1234567890 + 60, None), None);
+        assert_eq!(verify_code(SECRET, "123456", 1234567890, None), None);
+        assert_eq!(verify_code(SECRET, "5924", 1234567890, None), None);
+    }
+
+    #[test]
+    fn test_code_at() {
+        assert_eq!(code_at(SECRET, 1111111109), "081804");
+    }
+
+    #[test]
+    fn test_verify_code_replay() {
+        assert_eq!(
+            verify_code(SECRET, "005924", 1234567890, Some(41152262)),
+            Some(41152263)
+        );
+        assert_eq!(
+            verify_code(SECRET, "005924", 1234567890, Some(41152263)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_otpauth_uri() {
+        assert_eq!(
+            otpauth_uri("LLDAP", "bob", SECRET),
+            "otpauth://totp/LLDAP:bob?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&issuer=LLDAP&algorithm=SHA1&digits=6&period=30"
+        );
+    }
+}
@@ -0,0 +1,15 @@
+use crate::domain::{error::Result, types::UserId};
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait TotpHandler: Send + Sync {
+    /// Generates a new secret for the user, to be confirmed with `confirm_totp_enrollment`.
+    /// Returns the raw secret.
+    async fn start_totp_enrollment(&self, user_id: &UserId) -> Result<Vec<u8>>;
+    /// Enables the second factor for the user, if the code matches the pending secret.
+    async fn confirm_totp_enrollment(&self, user_id: &UserId, code: &str) -> Result<()>;
+    async fn disable_totp(&self, user_id: &UserId) -> Result<()>;
+    /// Checks the second factor of a login: succeeds if the user doesn't have TOTP enabled, or
+    /// if the code is valid and wasn't used before.
+    async fn check_totp_code(&self, user_id: &UserId, code: Option<String>) -> Result<()>;
+}
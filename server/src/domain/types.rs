@@ -1,5 +1,6 @@
 use base64::Engine;
 use chrono::{NaiveDateTime, TimeZone};
+use once_cell::sync::OnceCell;
 use sea_orm::{
     entity::IntoActiveValue,
     sea_query::{value::ValueType, ArrayType, BlobSize, ColumnType, Nullable, ValueTypeErr},
@@ -7,6 +8,10 @@ use sea_orm::{
     DbErr, FromQueryResult, QueryResult, TryFromU64, TryGetError, TryGetable, Value,
 };
 use serde::{Deserialize, Serialize};
+use tracing::warn;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::infra::configuration::UserIdNormalizationOptions;
 
 pub use super::model::{GroupColumn, UserColumn};
 
@@ -112,30 +117,7 @@ const SERIALIZED_I64_LEN: usize = 8;
 impl std::fmt::Debug for Serialized {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_tuple("Serialized")
-            .field(
-                &self
-                    .convert_to()
-                    .and_then(|s| {
-                        String::from_utf8(s)
-                            .map_err(|_| Box::new(bincode::ErrorKind::InvalidCharEncoding))
-                    })
-                    .or_else(|e| {
-                        if self.0.len() == SERIALIZED_I64_LEN {
-                            self.convert_to::<i64>()
-                                .map(|i| i.to_string())
-                                .map_err(|_| Box::new(bincode::ErrorKind::InvalidCharEncoding))
-                        } else {
-                            Err(e)
-                        }
-                    })
-                    .unwrap_or_else(|_| {
-                        format!("hash: {:#016X}", {
-                            let mut hasher = std::collections::hash_map::DefaultHasher::new();
-                            std::hash::Hash::hash(&self.0, &mut hasher);
-                            std::hash::Hasher::finish(&hasher)
-                        })
-                    }),
-            )
+            .field(&self.to_display_string())
             .finish()
     }
 }
@@ -158,6 +140,40 @@ impl Serialized {
     pub fn expect<'a, T: Deserialize<'a>>(&'a self, message: &str) -> T {
         self.convert_to().expect(message)
     }
+
+    /// The strings of a string attribute, one per value for the lists. Empty for the other types.
+    pub fn string_values(&self, attribute_type: AttributeType, is_list: bool) -> Vec<String> {
+        match (attribute_type, is_list) {
+            (AttributeType::String, false) => self.convert_to::<String>().into_iter().collect(),
+            (AttributeType::String, true) => self.convert_to().unwrap_or_default(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// A best-effort readable form of the value: the string or the integer, or a hash of the
+    /// bytes for the other types.
+    pub fn to_display_string(&self) -> String {
+        self.convert_to()
+            .and_then(|s| {
+                String::from_utf8(s).map_err(|_| Box::new(bincode::ErrorKind::InvalidCharEncoding))
+            })
+            .or_else(|e| {
+                if self.0.len() == SERIALIZED_I64_LEN {
+                    self.convert_to::<i64>()
+                        .map(|i| i.to_string())
+                        .map_err(|_| Box::new(bincode::ErrorKind::InvalidCharEncoding))
+                } else {
+                    Err(e)
+                }
+            })
+            .unwrap_or_else(|_| {
+                format!("hash: {:#016X}", {
+                    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+                    std::hash::Hash::hash(&self.0, &mut hasher);
+                    std::hash::Hasher::finish(&hasher)
+                })
+            })
+    }
 }
 
 impl From<Serialized> for Value {
@@ -198,13 +214,44 @@ impl ValueType for Serialized {
     }
 }
 
+/// Set once at startup, from the configuration.
+static USER_ID_NORMALIZATION: OnceCell<UserIdNormalizationOptions> = OnceCell::new();
+
+fn normalize_user_id(user_id: &str, options: &UserIdNormalizationOptions) -> String {
+    let mut user_id = if options.unicode_nfkc {
+        user_id.nfkc().collect()
+    } else {
+        user_id.to_owned()
+    };
+    if options.trim {
+        user_id = user_id.trim().to_owned();
+    }
+    if options.lowercase {
+        user_id = user_id.to_lowercase();
+    }
+    user_id
+}
+
+/// A normalized user id. By default, the surrounding whitespace is trimmed and the id is
+/// lowercased; the `user_id_normalization` options can disable either, and enable the Unicode
+/// NFKC normalization (applied first, so that e.g. a full-width space gets trimmed).
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Default, Serialize, Deserialize)]
 #[serde(from = "String")]
 pub struct UserId(String);
 
 impl UserId {
     pub fn new(user_id: &str) -> Self {
-        Self(user_id.to_lowercase())
+        Self(normalize_user_id(
+            user_id,
+            &USER_ID_NORMALIZATION.get().copied().unwrap_or_default(),
+        ))
+    }
+
+    /// Sets the normalization applied by `new`. Only the first call has an effect.
+    pub fn set_normalization(options: UserIdNormalizationOptions) {
+        if USER_ID_NORMALIZATION.set(options).is_err() {
+            warn!("The user id normalization is already set, ignoring the new one");
+        }
     }
 
     pub fn as_str(&self) -> &str {
@@ -428,9 +475,41 @@ pub struct User {
     pub display_name: Option<String>,
     pub creation_date: NaiveDateTime,
     pub uuid: Uuid,
+    pub uid_number: i32,
+    pub password_modified_date: Option<NaiveDateTime>,
+    pub modified_date: NaiveDateTime,
+    /// The password hash exposed as `userPassword` to the trusted readers.
+    #[serde(skip)]
+    pub replicated_password_hash: Option<String>,
+    /// The disabled users can't log in, but are kept with their memberships.
+    pub is_enabled: bool,
+    /// After this date, the user is treated as disabled.
+    pub expiration_date: Option<NaiveDateTime>,
+    /// The last successful login or bind, updated at most once a minute.
+    pub last_login: Option<NaiveDateTime>,
+    /// The failed logins since the last successful one, if any.
+    pub authentication_failures: Option<AuthenticationFailures>,
     pub attributes: Vec<AttributeValue>,
 }
 
+/// The failed logins of a user since their last successful one, counted within the lockout
+/// window.
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+pub struct AuthenticationFailures {
+    pub failure_count: i32,
+    pub last_failure_date: NaiveDateTime,
+    /// Only set while the account is locked out.
+    pub locked_until: Option<NaiveDateTime>,
+}
+
+impl User {
+    /// Whether the user was disabled, or their account expired: either way, they can't log in.
+    pub fn is_disabled(&self) -> bool {
+        let now = chrono::Utc::now().naive_utc();
+        !self.is_enabled || self.expiration_date.map_or(false, |date| date <= now)
+    }
+}
+
 #[cfg(test)]
 impl Default for User {
     fn default() -> Self {
@@ -441,6 +520,14 @@ impl Default for User {
             display_name: None,
             creation_date: epoch,
             uuid: Uuid::from_name_and_date("", &epoch),
+            uid_number: 0,
+            password_modified_date: None,
+            modified_date: epoch,
+            replicated_password_hash: None,
+            is_enabled: true,
+            expiration_date: None,
+            last_login: None,
+            authentication_failures: None,
             attributes: Vec::new(),
         }
     }
@@ -493,6 +580,7 @@ pub enum AttributeType {
     Integer,
     JpegPhoto,
     DateTime,
+    Boolean,
 }
 
 impl From<AttributeType> for Value {
@@ -530,6 +618,51 @@ impl ValueType for AttributeType {
     }
 }
 
+/// What an API token gives access to.
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, EnumString, IntoStaticStr,
+)]
+#[strum(ascii_case_insensitive)]
+pub enum ApiTokenScope {
+    Readonly,
+    Admin,
+}
+
+impl From<ApiTokenScope> for Value {
+    fn from(scope: ApiTokenScope) -> Self {
+        Into::<&'static str>::into(scope).into()
+    }
+}
+
+impl TryGetable for ApiTokenScope {
+    fn try_get_by<I: sea_orm::ColIdx>(res: &QueryResult, index: I) -> Result<Self, TryGetError> {
+        use std::str::FromStr;
+        Ok(ApiTokenScope::from_str(&String::try_get_by(res, index)?).expect("Invalid enum value"))
+    }
+}
+
+impl ValueType for ApiTokenScope {
+    fn try_from(v: Value) -> Result<Self, ValueTypeErr> {
+        use std::str::FromStr;
+        Ok(
+            ApiTokenScope::from_str(&<String as ValueType>::try_from(v)?)
+                .expect("Invalid enum value"),
+        )
+    }
+
+    fn type_name() -> String {
+        "ApiTokenScope".to_owned()
+    }
+
+    fn array_type() -> ArrayType {
+        ArrayType::String
+    }
+
+    fn column_type() -> ColumnType {
+        ColumnType::String(Some(16))
+    }
+}
+
 #[derive(PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub struct Group {
     pub id: GroupId,
@@ -537,6 +670,11 @@ pub struct Group {
     pub creation_date: NaiveDateTime,
     pub uuid: Uuid,
     pub users: Vec<UserId>,
+    pub attributes: Vec<AttributeValue>,
+    /// For the dynamic groups, the filter matching their members.
+    pub dynamic_filter: Option<crate::domain::handler::UserRequestFilter>,
+    /// The groups nested in this group, sorted by name.
+    pub member_groups: Vec<GroupDetails>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, FromQueryResult)]
@@ -557,6 +695,35 @@ pub struct UserAndGroups {
 mod tests {
     use super::*;
 
+    fn normalize(user_id: &str, trim: bool, lowercase: bool, unicode_nfkc: bool) -> String {
+        normalize_user_id(
+            user_id,
+            &UserIdNormalizationOptions {
+                trim,
+                lowercase,
+                unicode_nfkc,
+            },
+        )
+    }
+
+    #[test]
+    fn test_user_id_normalization() {
+        // The default.
+        assert_eq!(UserId::new(" JSmith\t").as_str(), "jsmith");
+        assert_eq!(normalize(" JSmith ", true, false, false), "JSmith");
+        assert_eq!(normalize(" JSmith ", false, true, false), " jsmith ");
+        // Full-width letters, and a full-width space.
+        assert_eq!(
+            normalize("ＪＳｍｉｔｈ\u{3000}", false, false, true),
+            "JSmith "
+        );
+        assert_eq!(
+            normalize("ＪＳｍｉｔｈ\u{3000}", true, true, true),
+            "jsmith"
+        );
+        assert_eq!(normalize("ＪＳｍｉｔｈ", true, true, false), "ｊｓｍｉｔｈ");
+    }
+
     #[test]
     fn test_serialized_debug_string() {
         assert_eq!(
@@ -1,17 +1,23 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 
 use async_trait::async_trait;
-use tracing::info;
+use tracing::{error, info};
 
 use crate::domain::{
-    error::Result,
+    api_token_handler::{ApiToken, ApiTokenHandler},
+    audit_log_handler::{AuditLogEntry, AuditLogFilter, AuditLogHandler},
+    error::{DomainError, Result},
     handler::{
         AttributeSchema, BackendHandler, CreateUserRequest, GroupBackendHandler,
-        GroupListerBackendHandler, GroupRequestFilter, Schema, SchemaBackendHandler,
-        UpdateGroupRequest, UpdateUserRequest, UserBackendHandler, UserListerBackendHandler,
-        UserRequestFilter,
+        GroupListerBackendHandler, GroupRequestFilter, ImportUserRequest, Schema,
+        SchemaBackendHandler, SchemaWriterBackendHandler, UpdateGroupRequest, UpdateUserRequest,
+        UserBackendHandler, UserListerBackendHandler, UserRequestFilter, UserSortKey,
     },
-    types::{Group, GroupDetails, GroupId, User, UserAndGroups, UserId},
+    lockout_handler::{LockoutHandler, UserLockout},
+    passkey_handler::PasskeyHandler,
+    session_handler::SessionHandler,
+    totp_handler::TotpHandler,
+    types::{ApiTokenScope, Group, GroupDetails, GroupId, User, UserAndGroups, UserId},
 };
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
@@ -24,8 +30,11 @@ pub enum Permission {
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ValidationResults {
+    /// The name of the token, for an API token.
     pub user: UserId,
     pub permission: Permission,
+    /// The API tokens don't belong to a user, even if one has the same name.
+    pub is_api_token: bool,
 }
 
 impl ValidationResults {
@@ -34,9 +43,43 @@ impl ValidationResults {
         Self {
             user: UserId::new("admin"),
             permission: Permission::Admin,
+            is_api_token: false,
         }
     }
 
+    /// Read-only credentials of the anonymous LDAP searches, which don't belong to any user.
+    pub fn anonymous() -> Self {
+        Self {
+            user: UserId::new(""),
+            permission: Permission::Readonly,
+            is_api_token: true,
+        }
+    }
+
+    pub fn for_api_token(token: &ApiToken) -> Self {
+        Self {
+            user: UserId::new(&token.name),
+            permission: match token.scope {
+                ApiTokenScope::Admin => Permission::Admin,
+                ApiTokenScope::Readonly => Permission::Readonly,
+            },
+            is_api_token: true,
+        }
+    }
+
+    /// The name recorded in the audit log for the changes made with these credentials.
+    pub fn audit_actor(&self) -> String {
+        if self.is_api_token {
+            format!("api_token:{}", self.user)
+        } else {
+            self.user.to_string()
+        }
+    }
+
+    fn is_user(&self, user: &UserId) -> bool {
+        !self.is_api_token && &self.user == user
+    }
+
     #[must_use]
     pub fn is_admin(&self) -> bool {
         self.permission == Permission::Admin
@@ -54,19 +97,19 @@ impl ValidationResults {
         self.permission == Permission::Admin
             || self.permission == Permission::PasswordManager
             || self.permission == Permission::Readonly
-            || &self.user == user
+            || self.is_user(user)
     }
 
     #[must_use]
     pub fn can_change_password(&self, user: &UserId, user_is_admin: bool) -> bool {
         self.permission == Permission::Admin
             || (self.permission == Permission::PasswordManager && !user_is_admin)
-            || &self.user == user
+            || self.is_user(user)
     }
 
     #[must_use]
     pub fn can_write(&self, user: &UserId) -> bool {
-        self.permission == Permission::Admin || &self.user == user
+        self.permission == Permission::Admin || self.is_user(user)
     }
 }
 
@@ -83,6 +126,14 @@ pub trait ReadonlyBackendHandler: UserReadableBackendHandler {
         filters: Option<UserRequestFilter>,
         get_groups: bool,
     ) -> Result<Vec<UserAndGroups>>;
+    async fn list_users_page(
+        &self,
+        filters: Option<UserRequestFilter>,
+        get_groups: bool,
+        offset: u64,
+        limit: u64,
+    ) -> Result<Vec<UserAndGroups>>;
+    async fn count_users(&self, filters: Option<UserRequestFilter>) -> Result<u64>;
     async fn list_groups(&self, filters: Option<GroupRequestFilter>) -> Result<Vec<Group>>;
     async fn get_group_details(&self, group_id: GroupId) -> Result<GroupDetails>;
 }
@@ -97,12 +148,51 @@ pub trait AdminBackendHandler:
     UserWriteableBackendHandler + ReadonlyBackendHandler + UserWriteableBackendHandler
 {
     async fn create_user(&self, request: CreateUserRequest) -> Result<()>;
+    async fn import_users(
+        &self,
+        requests: Vec<ImportUserRequest>,
+        atomic: bool,
+    ) -> Result<Vec<Result<()>>>;
     async fn delete_user(&self, user_id: &UserId) -> Result<()>;
+    async fn restore_user(&self, user_id: &UserId) -> Result<()>;
+    async fn set_user_enabled(&self, user_id: &UserId, enabled: bool) -> Result<()>;
+    async fn set_user_expiration_date(
+        &self,
+        user_id: &UserId,
+        expiration_date: Option<chrono::NaiveDateTime>,
+    ) -> Result<()>;
     async fn add_user_to_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()>;
     async fn remove_user_from_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()>;
     async fn update_group(&self, request: UpdateGroupRequest) -> Result<()>;
     async fn create_group(&self, group_name: &str) -> Result<GroupId>;
     async fn delete_group(&self, group_id: GroupId) -> Result<()>;
+    async fn set_group_dynamic_filter(
+        &self,
+        group_id: GroupId,
+        filter: Option<UserRequestFilter>,
+    ) -> Result<()>;
+    async fn add_group_to_group(&self, member_group_id: GroupId, group_id: GroupId) -> Result<()>;
+    async fn remove_group_from_group(
+        &self,
+        member_group_id: GroupId,
+        group_id: GroupId,
+    ) -> Result<()>;
+    async fn list_user_lockouts(&self) -> Result<Vec<UserLockout>>;
+    async fn clear_user_lockout(&self, user_id: &UserId) -> Result<()>;
+    async fn list_api_tokens(&self) -> Result<Vec<ApiToken>>;
+    async fn create_api_token(
+        &self,
+        name: &str,
+        scope: ApiTokenScope,
+        expiry_date: Option<chrono::NaiveDateTime>,
+    ) -> Result<String>;
+    async fn revoke_api_token(&self, name: &str) -> Result<()>;
+    async fn list_audit_events(&self, filter: AuditLogFilter) -> Result<Vec<AuditLogEntry>>;
+    async fn set_user_attribute_allowed_values(
+        &self,
+        name: &str,
+        allowed_values: Option<Vec<String>>,
+    ) -> Result<()>;
 }
 
 #[async_trait]
@@ -124,6 +214,21 @@ impl<Handler: BackendHandler> ReadonlyBackendHandler for Handler {
     ) -> Result<Vec<UserAndGroups>> {
         <Handler as UserListerBackendHandler>::list_users(self, filters, get_groups).await
     }
+    async fn list_users_page(
+        &self,
+        filters: Option<UserRequestFilter>,
+        get_groups: bool,
+        offset: u64,
+        limit: u64,
+    ) -> Result<Vec<UserAndGroups>> {
+        <Handler as UserListerBackendHandler>::list_users_page(
+            self, filters, get_groups, offset, limit,
+        )
+        .await
+    }
+    async fn count_users(&self, filters: Option<UserRequestFilter>) -> Result<u64> {
+        <Handler as UserListerBackendHandler>::count_users(self, filters).await
+    }
     async fn list_groups(&self, filters: Option<GroupRequestFilter>) -> Result<Vec<Group>> {
         <Handler as GroupListerBackendHandler>::list_groups(self, filters).await
     }
@@ -143,9 +248,30 @@ impl<Handler: BackendHandler> AdminBackendHandler for Handler {
     async fn create_user(&self, request: CreateUserRequest) -> Result<()> {
         <Handler as UserBackendHandler>::create_user(self, request).await
     }
+    async fn import_users(
+        &self,
+        requests: Vec<ImportUserRequest>,
+        atomic: bool,
+    ) -> Result<Vec<Result<()>>> {
+        <Handler as UserBackendHandler>::import_users(self, requests, atomic).await
+    }
     async fn delete_user(&self, user_id: &UserId) -> Result<()> {
         <Handler as UserBackendHandler>::delete_user(self, user_id).await
     }
+    async fn restore_user(&self, user_id: &UserId) -> Result<()> {
+        <Handler as UserBackendHandler>::restore_user(self, user_id).await
+    }
+    async fn set_user_enabled(&self, user_id: &UserId, enabled: bool) -> Result<()> {
+        <Handler as UserBackendHandler>::set_user_enabled(self, user_id, enabled).await
+    }
+    async fn set_user_expiration_date(
+        &self,
+        user_id: &UserId,
+        expiration_date: Option<chrono::NaiveDateTime>,
+    ) -> Result<()> {
+        <Handler as UserBackendHandler>::set_user_expiration_date(self, user_id, expiration_date)
+            .await
+    }
     async fn add_user_to_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()> {
         <Handler as UserBackendHandler>::add_user_to_group(self, user_id, group_id).await
     }
@@ -161,6 +287,59 @@ impl<Handler: BackendHandler> AdminBackendHandler for Handler {
     async fn delete_group(&self, group_id: GroupId) -> Result<()> {
         <Handler as GroupBackendHandler>::delete_group(self, group_id).await
     }
+    async fn set_group_dynamic_filter(
+        &self,
+        group_id: GroupId,
+        filter: Option<UserRequestFilter>,
+    ) -> Result<()> {
+        <Handler as GroupBackendHandler>::set_group_dynamic_filter(self, group_id, filter).await
+    }
+    async fn add_group_to_group(&self, member_group_id: GroupId, group_id: GroupId) -> Result<()> {
+        <Handler as GroupBackendHandler>::add_group_to_group(self, member_group_id, group_id).await
+    }
+    async fn remove_group_from_group(
+        &self,
+        member_group_id: GroupId,
+        group_id: GroupId,
+    ) -> Result<()> {
+        <Handler as GroupBackendHandler>::remove_group_from_group(self, member_group_id, group_id)
+            .await
+    }
+    async fn list_user_lockouts(&self) -> Result<Vec<UserLockout>> {
+        <Handler as LockoutHandler>::list_user_lockouts(self).await
+    }
+    async fn clear_user_lockout(&self, user_id: &UserId) -> Result<()> {
+        <Handler as LockoutHandler>::clear_user_lockout(self, user_id).await
+    }
+    async fn list_api_tokens(&self) -> Result<Vec<ApiToken>> {
+        <Handler as ApiTokenHandler>::list_api_tokens(self).await
+    }
+    async fn create_api_token(
+        &self,
+        name: &str,
+        scope: ApiTokenScope,
+        expiry_date: Option<chrono::NaiveDateTime>,
+    ) -> Result<String> {
+        <Handler as ApiTokenHandler>::create_api_token(self, name, scope, expiry_date).await
+    }
+    async fn revoke_api_token(&self, name: &str) -> Result<()> {
+        <Handler as ApiTokenHandler>::revoke_api_token(self, name).await
+    }
+    async fn list_audit_events(&self, filter: AuditLogFilter) -> Result<Vec<AuditLogEntry>> {
+        <Handler as AuditLogHandler>::list_audit_events(self, filter).await
+    }
+    async fn set_user_attribute_allowed_values(
+        &self,
+        name: &str,
+        allowed_values: Option<Vec<String>>,
+    ) -> Result<()> {
+        <Handler as SchemaWriterBackendHandler>::set_user_attribute_allowed_values(
+            self,
+            name,
+            allowed_values,
+        )
+        .await
+    }
 }
 
 pub struct AccessControlledBackendHandler<Handler> {
@@ -189,8 +368,10 @@ impl<Handler: BackendHandler> AccessControlledBackendHandler<Handler> {
     pub fn get_admin_handler(
         &self,
         validation_result: &ValidationResults,
-    ) -> Option<&impl AdminBackendHandler> {
-        validation_result.is_admin().then_some(&self.handler)
+    ) -> Option<impl AdminBackendHandler + '_> {
+        validation_result
+            .is_admin()
+            .then(|| self.get_audited_handler(validation_result))
     }
 
     pub fn get_readonly_handler(
@@ -204,7 +385,51 @@ impl<Handler: BackendHandler> AccessControlledBackendHandler<Handler> {
         &self,
         validation_result: &ValidationResults,
         user_id: &UserId,
-    ) -> Option<&impl UserWriteableBackendHandler> {
+    ) -> Option<impl UserWriteableBackendHandler + '_> {
+        validation_result
+            .can_write(user_id)
+            .then(|| self.get_audited_handler(validation_result))
+    }
+
+    fn get_audited_handler(
+        &self,
+        validation_result: &ValidationResults,
+    ) -> AuditedBackendHandler<'_, Handler> {
+        AuditedBackendHandler {
+            handler: &self.handler,
+            actor: validation_result.audit_actor(),
+        }
+    }
+
+    /// The TOTP settings can be changed by the users themselves, and by the admins.
+    pub fn get_totp_handler(
+        &self,
+        validation_result: &ValidationResults,
+        user_id: &UserId,
+    ) -> Option<&impl TotpHandler> {
+        validation_result
+            .can_write(user_id)
+            .then_some(&self.handler)
+    }
+
+    /// Like the TOTP settings, the passkeys are managed by the users themselves, and by the
+    /// admins.
+    pub fn get_passkey_handler(
+        &self,
+        validation_result: &ValidationResults,
+        user_id: &UserId,
+    ) -> Option<&impl PasskeyHandler> {
+        validation_result
+            .can_write(user_id)
+            .then_some(&self.handler)
+    }
+
+    /// The sessions can be revoked by the users themselves, and by the admins.
+    pub fn get_session_handler(
+        &self,
+        validation_result: &ValidationResults,
+        user_id: &UserId,
+    ) -> Option<&impl SessionHandler> {
         validation_result
             .can_write(user_id)
             .then_some(&self.handler)
@@ -233,9 +458,25 @@ impl<Handler: BackendHandler> AccessControlledBackendHandler<Handler> {
         }
     }
 
-    pub async fn get_permissions_for_user(&self, user_id: UserId) -> Result<ValidationResults> {
-        let user_groups = self.handler.get_user_groups(&user_id).await?;
-        Ok(self.get_permissions_from_groups(user_id, user_groups.iter().map(|g| &g.display_name)))
+    /// The permissions of the user, and the display names of their groups.
+    pub async fn get_permissions_for_user(
+        &self,
+        user_id: UserId,
+    ) -> Result<(ValidationResults, Vec<String>)> {
+        let user_groups = self
+            .handler
+            .get_user_groups(&user_id)
+            .await?
+            .into_iter()
+            .map(|g| g.display_name)
+            .collect::<Vec<_>>();
+        let permissions = self.get_permissions_from_groups(user_id, user_groups.iter());
+        Ok((permissions, user_groups))
+    }
+
+    pub async fn get_permissions_for_api_token(&self, token: &str) -> Result<ValidationResults> {
+        let token = self.handler.check_api_token(token).await?;
+        Ok(ValidationResults::for_api_token(&token))
     }
 
     pub fn get_permissions_from_groups<'a, Groups: Iterator<Item = &'a String> + Clone + 'a>(
@@ -255,7 +496,430 @@ impl<Handler: BackendHandler> AccessControlledBackendHandler<Handler> {
             } else {
                 Permission::Regular
             },
+            is_api_token: false,
+        }
+    }
+}
+
+/// Records the changes made through it in the audit log.
+pub struct AuditedBackendHandler<'a, Handler> {
+    handler: &'a Handler,
+    actor: String,
+}
+
+type AuditFields = BTreeMap<String, serde_json::Value>;
+
+fn user_audit_fields(user: &User) -> AuditFields {
+    let mut fields = AuditFields::from([
+        ("email".to_owned(), user.email.clone().into()),
+        ("display_name".to_owned(), user.display_name.clone().into()),
+    ]);
+    fields.extend(
+        user.attributes
+            .iter()
+            .map(|a| (a.name.clone(), a.value.to_display_string().into())),
+    );
+    fields
+}
+
+fn group_audit_fields(group: &Group) -> AuditFields {
+    let mut fields =
+        AuditFields::from([("display_name".to_owned(), group.display_name.clone().into())]);
+    fields.extend(
+        group
+            .attributes
+            .iter()
+            .map(|a| (a.name.clone(), a.value.to_display_string().into())),
+    );
+    fields
+}
+
+/// The fields that changed, with their values before and after.
+fn diff_audit_fields(before: &AuditFields, after: &AuditFields) -> serde_json::Value {
+    before
+        .keys()
+        .chain(after.keys())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .filter(|field| before.get(*field) != after.get(*field))
+        .map(|field| {
+            (
+                field.clone(),
+                serde_json::json!({"before": before.get(field), "after": after.get(field)}),
+            )
+        })
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+fn user_target(user_id: &UserId) -> String {
+    format!("user:{}", user_id)
+}
+
+fn group_target(group_id: GroupId) -> String {
+    format!("group:{}", group_id.0)
+}
+
+impl<'a, Handler: BackendHandler> AuditedBackendHandler<'a, Handler> {
+    /// Failing to record the change doesn't undo it, but it must not go unnoticed.
+    async fn record(&self, action: &str, target: String, changes: Option<serde_json::Value>) {
+        let entry = AuditLogEntry {
+            timestamp: chrono::Utc::now().naive_utc(),
+            actor: self.actor.clone(),
+            action: action.to_owned(),
+            target,
+            changes: changes.map(|c| c.to_string()),
+        };
+        if let Err(e) = self.handler.record_audit_event(entry.clone()).await {
+            error!(
+                "Could not record a change in the audit log: {:#}. Lost entry: {:?}",
+                e, entry
+            );
+        }
+    }
+
+    /// Fetches the user fields to diff, logging (but otherwise ignoring) the errors.
+    async fn get_user_audit_fields(&self, user_id: &UserId) -> Option<AuditFields> {
+        match <Handler as UserBackendHandler>::get_user_details(self.handler, user_id).await {
+            Ok(user) => Some(user_audit_fields(&user)),
+            Err(e) => {
+                error!(
+                    "Could not get the details of user `{}` for the audit log: {:#}",
+                    user_id, e
+                );
+                None
+            }
+        }
+    }
+
+    async fn get_group_audit_fields(&self, group_id: GroupId) -> Option<AuditFields> {
+        let group = <Handler as GroupListerBackendHandler>::list_groups(
+            self.handler,
+            Some(GroupRequestFilter::GroupId(group_id)),
+        )
+        .await
+        .and_then(|groups| {
+            groups
+                .into_iter()
+                .next()
+                .ok_or_else(|| DomainError::EntityNotFound(format!("{:?}", group_id)))
+        });
+        match group {
+            Ok(group) => Some(group_audit_fields(&group)),
+            Err(e) => {
+                error!(
+                    "Could not get the details of group {} for the audit log: {:#}",
+                    group_id.0, e
+                );
+                None
+            }
+        }
+    }
+
+    async fn record_create_user(&self, request: &CreateUserRequest, group_ids: &[GroupId]) {
+        let mut changes = serde_json::json!({
+            "email": request.email,
+            "display_name": request.display_name,
+            "first_name": request.first_name,
+            "last_name": request.last_name,
+            "avatar": request.avatar.is_some(),
+        });
+        if !group_ids.is_empty() {
+            changes["group_ids"] = group_ids.iter().map(|g| g.0).collect::<Vec<_>>().into();
         }
+        self.record("create_user", user_target(&request.user_id), Some(changes))
+            .await;
+    }
+}
+
+#[async_trait]
+impl<'a, Handler: BackendHandler> UserReadableBackendHandler
+    for AuditedBackendHandler<'a, Handler>
+{
+    async fn get_user_details(&self, user_id: &UserId) -> Result<User> {
+        <Handler as UserBackendHandler>::get_user_details(self.handler, user_id).await
+    }
+    async fn get_user_groups(&self, user_id: &UserId) -> Result<HashSet<GroupDetails>> {
+        <Handler as UserBackendHandler>::get_user_groups(self.handler, user_id).await
+    }
+}
+
+#[async_trait]
+impl<'a, Handler: BackendHandler> ReadonlyBackendHandler for AuditedBackendHandler<'a, Handler> {
+    async fn list_users(
+        &self,
+        filters: Option<UserRequestFilter>,
+        get_groups: bool,
+    ) -> Result<Vec<UserAndGroups>> {
+        <Handler as ReadonlyBackendHandler>::list_users(self.handler, filters, get_groups).await
+    }
+    async fn list_users_page(
+        &self,
+        filters: Option<UserRequestFilter>,
+        get_groups: bool,
+        offset: u64,
+        limit: u64,
+    ) -> Result<Vec<UserAndGroups>> {
+        <Handler as ReadonlyBackendHandler>::list_users_page(
+            self.handler,
+            filters,
+            get_groups,
+            offset,
+            limit,
+        )
+        .await
+    }
+    async fn count_users(&self, filters: Option<UserRequestFilter>) -> Result<u64> {
+        <Handler as ReadonlyBackendHandler>::count_users(self.handler, filters).await
+    }
+    async fn list_groups(&self, filters: Option<GroupRequestFilter>) -> Result<Vec<Group>> {
+        <Handler as ReadonlyBackendHandler>::list_groups(self.handler, filters).await
+    }
+    async fn get_group_details(&self, group_id: GroupId) -> Result<GroupDetails> {
+        <Handler as ReadonlyBackendHandler>::get_group_details(self.handler, group_id).await
+    }
+}
+
+#[async_trait]
+impl<'a, Handler: BackendHandler> UserWriteableBackendHandler
+    for AuditedBackendHandler<'a, Handler>
+{
+    async fn update_user(&self, request: UpdateUserRequest) -> Result<()> {
+        let user_id = request.user_id.clone();
+        let before = self.get_user_audit_fields(&user_id).await;
+        <Handler as UserBackendHandler>::update_user(self.handler, request).await?;
+        let after = self.get_user_audit_fields(&user_id).await;
+        let changes = before
+            .zip(after)
+            .map(|(before, after)| diff_audit_fields(&before, &after));
+        self.record("update_user", user_target(&user_id), changes)
+            .await;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<'a, Handler: BackendHandler> AdminBackendHandler for AuditedBackendHandler<'a, Handler> {
+    async fn create_user(&self, request: CreateUserRequest) -> Result<()> {
+        <Handler as UserBackendHandler>::create_user(self.handler, request.clone()).await?;
+        self.record_create_user(&request, &[]).await;
+        Ok(())
+    }
+    async fn import_users(
+        &self,
+        requests: Vec<ImportUserRequest>,
+        atomic: bool,
+    ) -> Result<Vec<Result<()>>> {
+        let results =
+            <Handler as UserBackendHandler>::import_users(self.handler, requests.clone(), atomic)
+                .await?;
+        for (request, result) in requests.iter().zip(results.iter()) {
+            if result.is_ok() {
+                self.record_create_user(&request.user, &request.group_ids)
+                    .await;
+            }
+        }
+        Ok(results)
+    }
+    async fn delete_user(&self, user_id: &UserId) -> Result<()> {
+        let before = self.get_user_audit_fields(user_id).await;
+        <Handler as UserBackendHandler>::delete_user(self.handler, user_id).await?;
+        let changes = before.map(|before| diff_audit_fields(&before, &AuditFields::new()));
+        self.record("delete_user", user_target(user_id), changes)
+            .await;
+        Ok(())
+    }
+    async fn restore_user(&self, user_id: &UserId) -> Result<()> {
+        <Handler as UserBackendHandler>::restore_user(self.handler, user_id).await?;
+        self.record("restore_user", user_target(user_id), None)
+            .await;
+        Ok(())
+    }
+    async fn set_user_enabled(&self, user_id: &UserId, enabled: bool) -> Result<()> {
+        <Handler as UserBackendHandler>::set_user_enabled(self.handler, user_id, enabled).await?;
+        self.record(
+            "set_user_enabled",
+            user_target(user_id),
+            Some(serde_json::json!({ "is_enabled": enabled })),
+        )
+        .await;
+        Ok(())
+    }
+    async fn set_user_expiration_date(
+        &self,
+        user_id: &UserId,
+        expiration_date: Option<chrono::NaiveDateTime>,
+    ) -> Result<()> {
+        <Handler as UserBackendHandler>::set_user_expiration_date(
+            self.handler,
+            user_id,
+            expiration_date,
+        )
+        .await?;
+        self.record(
+            "set_user_expiration_date",
+            user_target(user_id),
+            Some(serde_json::json!({ "expiration_date": expiration_date })),
+        )
+        .await;
+        Ok(())
+    }
+    async fn add_user_to_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()> {
+        <Handler as UserBackendHandler>::add_user_to_group(self.handler, user_id, group_id).await?;
+        self.record(
+            "add_user_to_group",
+            group_target(group_id),
+            Some(serde_json::json!({ "user_id": user_id })),
+        )
+        .await;
+        Ok(())
+    }
+    async fn remove_user_from_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()> {
+        <Handler as UserBackendHandler>::remove_user_from_group(self.handler, user_id, group_id)
+            .await?;
+        self.record(
+            "remove_user_from_group",
+            group_target(group_id),
+            Some(serde_json::json!({ "user_id": user_id })),
+        )
+        .await;
+        Ok(())
+    }
+    async fn update_group(&self, request: UpdateGroupRequest) -> Result<()> {
+        let group_id = request.group_id;
+        let before = self.get_group_audit_fields(group_id).await;
+        <Handler as GroupBackendHandler>::update_group(self.handler, request).await?;
+        let after = self.get_group_audit_fields(group_id).await;
+        let changes = before
+            .zip(after)
+            .map(|(before, after)| diff_audit_fields(&before, &after));
+        self.record("update_group", group_target(group_id), changes)
+            .await;
+        Ok(())
+    }
+    async fn create_group(&self, group_name: &str) -> Result<GroupId> {
+        let group_id =
+            <Handler as GroupBackendHandler>::create_group(self.handler, group_name).await?;
+        self.record(
+            "create_group",
+            group_target(group_id),
+            Some(serde_json::json!({ "display_name": group_name })),
+        )
+        .await;
+        Ok(group_id)
+    }
+    async fn delete_group(&self, group_id: GroupId) -> Result<()> {
+        <Handler as GroupBackendHandler>::delete_group(self.handler, group_id).await?;
+        self.record("delete_group", group_target(group_id), None)
+            .await;
+        Ok(())
+    }
+    async fn set_group_dynamic_filter(
+        &self,
+        group_id: GroupId,
+        filter: Option<UserRequestFilter>,
+    ) -> Result<()> {
+        let details = serde_json::json!({ "dynamic_filter": filter });
+        <Handler as GroupBackendHandler>::set_group_dynamic_filter(self.handler, group_id, filter)
+            .await?;
+        self.record(
+            "set_group_dynamic_filter",
+            group_target(group_id),
+            Some(details),
+        )
+        .await;
+        Ok(())
+    }
+    async fn add_group_to_group(&self, member_group_id: GroupId, group_id: GroupId) -> Result<()> {
+        <Handler as GroupBackendHandler>::add_group_to_group(
+            self.handler,
+            member_group_id,
+            group_id,
+        )
+        .await?;
+        self.record(
+            "add_group_to_group",
+            group_target(group_id),
+            Some(serde_json::json!({ "member_group_id": member_group_id })),
+        )
+        .await;
+        Ok(())
+    }
+    async fn remove_group_from_group(
+        &self,
+        member_group_id: GroupId,
+        group_id: GroupId,
+    ) -> Result<()> {
+        <Handler as GroupBackendHandler>::remove_group_from_group(
+            self.handler,
+            member_group_id,
+            group_id,
+        )
+        .await?;
+        self.record(
+            "remove_group_from_group",
+            group_target(group_id),
+            Some(serde_json::json!({ "member_group_id": member_group_id })),
+        )
+        .await;
+        Ok(())
+    }
+    async fn list_user_lockouts(&self) -> Result<Vec<UserLockout>> {
+        <Handler as LockoutHandler>::list_user_lockouts(self.handler).await
+    }
+    async fn clear_user_lockout(&self, user_id: &UserId) -> Result<()> {
+        <Handler as LockoutHandler>::clear_user_lockout(self.handler, user_id).await?;
+        self.record("clear_user_lockout", user_target(user_id), None)
+            .await;
+        Ok(())
+    }
+    async fn list_api_tokens(&self) -> Result<Vec<ApiToken>> {
+        <Handler as ApiTokenHandler>::list_api_tokens(self.handler).await
+    }
+    async fn create_api_token(
+        &self,
+        name: &str,
+        scope: ApiTokenScope,
+        expiry_date: Option<chrono::NaiveDateTime>,
+    ) -> Result<String> {
+        let token =
+            <Handler as ApiTokenHandler>::create_api_token(self.handler, name, scope, expiry_date)
+                .await?;
+        self.record(
+            "create_api_token",
+            format!("api_token:{}", name),
+            Some(serde_json::json!({ "scope": scope, "expiry_date": expiry_date })),
+        )
+        .await;
+        Ok(token)
+    }
+    async fn revoke_api_token(&self, name: &str) -> Result<()> {
+        <Handler as ApiTokenHandler>::revoke_api_token(self.handler, name).await?;
+        self.record("revoke_api_token", format!("api_token:{}", name), None)
+            .await;
+        Ok(())
+    }
+    async fn list_audit_events(&self, filter: AuditLogFilter) -> Result<Vec<AuditLogEntry>> {
+        <Handler as AuditLogHandler>::list_audit_events(self.handler, filter).await
+    }
+    async fn set_user_attribute_allowed_values(
+        &self,
+        name: &str,
+        allowed_values: Option<Vec<String>>,
+    ) -> Result<()> {
+        <Handler as SchemaWriterBackendHandler>::set_user_attribute_allowed_values(
+            self.handler,
+            name,
+            allowed_values.clone(),
+        )
+        .await?;
+        self.record(
+            "set_user_attribute_allowed_values",
+            format!("attribute:{}", name),
+            Some(serde_json::json!({ "allowed_values": allowed_values })),
+        )
+        .await;
+        Ok(())
     }
 }
 
@@ -264,6 +928,24 @@ pub struct UserRestrictedListerBackendHandler<'a, Handler> {
     pub user_filter: Option<UserId>,
 }
 
+impl<'a, Handler> UserRestrictedListerBackendHandler<'a, Handler> {
+    fn restrict_user_filter(
+        &self,
+        filters: Option<UserRequestFilter>,
+    ) -> Option<UserRequestFilter> {
+        let user_filter = self
+            .user_filter
+            .as_ref()
+            .map(|u| UserRequestFilter::UserId(u.clone()));
+        match (filters, user_filter) {
+            (None, None) => None,
+            (None, u) => u,
+            (f, None) => f,
+            (Some(f), Some(u)) => Some(UserRequestFilter::And(vec![f, u])),
+        }
+    }
+}
+
 #[async_trait]
 impl<'a, Handler: SchemaBackendHandler + Sync> SchemaBackendHandler
     for UserRestrictedListerBackendHandler<'a, Handler>
@@ -290,17 +972,44 @@ impl<'a, Handler: UserListerBackendHandler + Sync> UserListerBackendHandler
         filters: Option<UserRequestFilter>,
         get_groups: bool,
     ) -> Result<Vec<UserAndGroups>> {
-        let user_filter = self
-            .user_filter
-            .as_ref()
-            .map(|u| UserRequestFilter::UserId(u.clone()));
-        let filters = match (filters, user_filter) {
-            (None, None) => None,
-            (None, u) => u,
-            (f, None) => f,
-            (Some(f), Some(u)) => Some(UserRequestFilter::And(vec![f, u])),
-        };
-        self.handler.list_users(filters, get_groups).await
+        self.handler
+            .list_users(self.restrict_user_filter(filters), get_groups)
+            .await
+    }
+
+    async fn list_users_page(
+        &self,
+        filters: Option<UserRequestFilter>,
+        get_groups: bool,
+        offset: u64,
+        limit: u64,
+    ) -> Result<Vec<UserAndGroups>> {
+        self.handler
+            .list_users_page(
+                self.restrict_user_filter(filters),
+                get_groups,
+                offset,
+                limit,
+            )
+            .await
+    }
+
+    async fn list_users_sorted(
+        &self,
+        filters: Option<UserRequestFilter>,
+        get_groups: bool,
+        sort: Vec<UserSortKey>,
+        page: Option<(u64, u64)>,
+    ) -> Result<Vec<UserAndGroups>> {
+        self.handler
+            .list_users_sorted(self.restrict_user_filter(filters), get_groups, sort, page)
+            .await
+    }
+
+    async fn count_users(&self, filters: Option<UserRequestFilter>) -> Result<u64> {
+        self.handler
+            .count_users(self.restrict_user_filter(filters))
+            .await
     }
 }
 
@@ -334,3 +1043,72 @@ impl<'a, Handler: GroupListerBackendHandler + UserListerBackendHandler + Sync>
     UserAndGroupListerBackendHandler for UserRestrictedListerBackendHandler<'a, Handler>
 {
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{domain::error::DomainError, infra::test_utils::MockTestBackendHandler};
+    use mockall::predicate::eq;
+
+    #[tokio::test]
+    async fn test_audit_update_user() {
+        let mut mock = MockTestBackendHandler::new();
+        let mut emails = vec!["new@bob", "bob@bob"];
+        mock.expect_get_user_details()
+            .with(eq(UserId::new("bob")))
+            .times(2)
+            .returning(move |_| {
+                Ok(User {
+                    user_id: UserId::new("bob"),
+                    email: emails.pop().unwrap().to_owned(),
+                    ..Default::default()
+                })
+            });
+        mock.expect_update_user().times(1).return_once(|_| Ok(()));
+        mock.expect_record_audit_event()
+            .withf(|entry| {
+                entry.actor == "admin"
+                    && entry.action == "update_user"
+                    && entry.target == "user:bob"
+                    && entry
+                        .changes
+                        .as_deref()
+                        .map(|c| serde_json::from_str::<serde_json::Value>(c).unwrap())
+                        == Some(serde_json::json!({
+                            "email": {"before": "bob@bob", "after": "new@bob"}
+                        }))
+            })
+            .times(1)
+            .return_once(|_| Ok(()));
+        let handler = AccessControlledBackendHandler::new(mock);
+        handler
+            .get_admin_handler(&ValidationResults::admin())
+            .unwrap()
+            .update_user(UpdateUserRequest {
+                user_id: UserId::new("bob"),
+                email: Some("new@bob".to_owned()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_audit_failure_does_not_fail_the_change() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_delete_group()
+            .with(eq(GroupId(3)))
+            .times(1)
+            .return_once(|_| Ok(()));
+        mock.expect_record_audit_event()
+            .times(1)
+            .return_once(|_| Err(DomainError::InternalError("DB down".to_owned())));
+        let handler = AccessControlledBackendHandler::new(mock);
+        handler
+            .get_admin_handler(&ValidationResults::admin())
+            .unwrap()
+            .delete_group(GroupId(3))
+            .await
+            .unwrap();
+    }
+}
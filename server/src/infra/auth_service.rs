@@ -20,13 +20,16 @@ use sha2::Sha512;
 use time::ext::NumericalDuration;
 use tracing::{debug, info, instrument, warn};
 
-use lldap_auth::{login, password_reset, registration, JWTClaims};
+use lldap_auth::{login, passkey_login, password_reset, registration, JWTClaims};
 
 use crate::{
     domain::{
+        api_token_handler::API_TOKEN_PREFIX,
         error::DomainError,
         handler::{BackendHandler, BindRequest, LoginHandler, UserRequestFilter},
         opaque_handler::OpaqueHandler,
+        passkey_handler::PasskeyHandler,
+        totp_handler::TotpHandler,
         types::{GroupDetails, UserColumn, UserId},
     },
     infra::{
@@ -39,9 +42,14 @@ use crate::{
 type Token<S> = jwt::Token<jwt::Header, JWTClaims, S>;
 type SignedToken = Token<jwt::token::Signed>;
 
-fn create_jwt(key: &Hmac<Sha512>, user: String, groups: HashSet<GroupDetails>) -> SignedToken {
+pub(crate) fn create_jwt(
+    key: &Hmac<Sha512>,
+    user: String,
+    groups: HashSet<GroupDetails>,
+    ttl: chrono::Duration,
+) -> SignedToken {
     let claims = JWTClaims {
-        exp: Utc::now() + chrono::Duration::days(1),
+        exp: Utc::now() + ttl,
         iat: Utc::now(),
         user,
         groups: groups.into_iter().map(|g| g.display_name).collect(),
@@ -53,6 +61,24 @@ fn create_jwt(key: &Hmac<Sha512>, user: String, groups: HashSet<GroupDetails>) -
     jwt::Token::new(header, claims).sign_with_key(key).unwrap()
 }
 
+fn token_cookie(token: &SignedToken, ttl: chrono::Duration) -> Cookie<'static> {
+    Cookie::build("token", token.as_str().to_owned())
+        .max_age(ttl.num_minutes().minutes())
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .finish()
+}
+
+fn refresh_token_cookie(refresh_token_plus_name: String, ttl: chrono::Duration) -> Cookie<'static> {
+    Cookie::build("refresh_token", refresh_token_plus_name)
+        .max_age(ttl.num_days().days())
+        .path("/auth")
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .finish()
+}
+
 fn parse_refresh_token(token: &str) -> TcpResult<(u64, UserId)> {
     match token.split_once('+') {
         None => Err(DomainError::AuthenticationError("Invalid refresh token".to_string()).into()),
@@ -67,7 +93,7 @@ fn parse_refresh_token(token: &str) -> TcpResult<(u64, UserId)> {
     }
 }
 
-fn get_refresh_token(request: HttpRequest) -> TcpResult<(u64, UserId)> {
+pub(crate) fn get_refresh_token(request: HttpRequest) -> TcpResult<(u64, UserId)> {
     match (
         request.cookie("refresh_token"),
         request.headers().get("refresh-token"),
@@ -88,37 +114,27 @@ async fn get_refresh<Backend>(
 where
     Backend: TcpBackendHandler + BackendHandler + 'static,
 {
-    let jwt_key = &data.jwt_key;
     let (refresh_token_hash, user) = get_refresh_token(request)?;
-    let found = data
+    let new_refresh_token = data
         .get_tcp_handler()
-        .check_token(refresh_token_hash, &user)
-        .await?;
-    if !found {
-        return Err(TcpError::DomainError(DomainError::AuthenticationError(
-            "Invalid refresh token".to_string(),
-        )));
+        .use_refresh_token(refresh_token_hash, &user)
+        .await?
+        .map(|(refresh_token, ttl)| (refresh_token + "+" + user.as_str(), ttl));
+    let groups = data.get_readonly_handler().get_user_groups(&user).await?;
+    let ttl = data.session_options.access_token_ttl();
+    let token = create_jwt(&data.jwt_key, user.to_string(), groups, ttl);
+    let mut response = HttpResponse::Ok();
+    response.cookie(token_cookie(&token, ttl));
+    if let Some((refresh_token_plus_name, refresh_ttl)) = &new_refresh_token {
+        response.cookie(refresh_token_cookie(
+            refresh_token_plus_name.clone(),
+            *refresh_ttl,
+        ));
     }
-    Ok(data
-        .get_readonly_handler()
-        .get_user_groups(&user)
-        .await
-        .map(|groups| create_jwt(jwt_key, user.to_string(), groups))
-        .map(|token| {
-            HttpResponse::Ok()
-                .cookie(
-                    Cookie::build("token", token.as_str())
-                        .max_age(1.days())
-                        .path("/")
-                        .http_only(true)
-                        .same_site(SameSite::Strict)
-                        .finish(),
-                )
-                .json(&login::ServerLoginResponse {
-                    token: token.as_str().to_owned(),
-                    refresh_token: None,
-                })
-        })?)
+    Ok(response.json(&login::ServerLoginResponse {
+        token: token.as_str().to_owned(),
+        refresh_token: new_refresh_token.map(|(refresh_token, _)| refresh_token),
+    }))
 }
 
 async fn get_refresh_handler<Backend>(
@@ -163,6 +179,18 @@ where
         ));
     }
     let user = &user_results[0].user;
+    let mail_sender = data
+        .mail_sender
+        .as_ref()
+        .ok_or_else(|| TcpError::InternalServerError("Password reset is not enabled".to_owned()))?;
+    // Same response as a successful request, to not reveal whether the user exists.
+    if !mail_sender.allow_password_reset_email(&user.email) {
+        info!(
+            "Too many password reset requests for {}, not sending the email",
+            &user.user_id
+        );
+        return Ok(());
+    }
     let token = match data
         .get_tcp_handler()
         .start_password_reset(&user.user_id)
@@ -171,17 +199,14 @@ where
         None => return Ok(()),
         Some(token) => token,
     };
-    if let Err(e) = super::mail::send_password_reset_email(
+    if let Err(e) = mail_sender.queue_password_reset_email(
         user.display_name
             .as_deref()
             .unwrap_or_else(|| user.user_id.as_str()),
         &user.email,
         &token,
         &data.server_url,
-        &data.mail_options,
-    )
-    .await
-    {
+    ) {
         warn!("Error sending email: {:#?}", e);
         info!("Reset token: {}", token);
         return Err(TcpError::InternalServerError(format!(
@@ -217,20 +242,29 @@ where
         .match_info()
         .get("token")
         .ok_or_else(|| TcpError::BadRequest("Missing reset token".to_owned()))?;
+    let expired_token = |e: DomainError| {
+        debug!("Reset token error: {e:#}");
+        TcpError::NotFoundError(
+            "The password reset link is invalid, expired or was already used".to_owned(),
+        )
+    };
     let user_id = data
         .get_tcp_handler()
         .get_user_id_for_password_reset_token(token)
         .await
-        .map_err(|e| {
-            debug!("Reset token error: {e:#}");
-            TcpError::NotFoundError("Wrong or expired reset token".to_owned())
-        })?;
-    let _ = data
-        .get_tcp_handler()
+        .map_err(expired_token)?;
+    // The links are single-use: only the request that deletes the token goes through.
+    data.get_tcp_handler()
         .delete_password_reset_token(token)
-        .await;
+        .await
+        .map_err(expired_token)?;
     let groups = HashSet::new();
-    let token = create_jwt(&data.jwt_key, user_id.to_string(), groups);
+    let token = create_jwt(
+        &data.jwt_key,
+        user_id.to_string(),
+        groups,
+        data.session_options.access_token_ttl(),
+    );
     Ok(HttpResponse::Ok()
         .cookie(
             Cookie::build("token", token.as_str())
@@ -296,6 +330,83 @@ where
         .finish())
 }
 
+/// The user of the JWT, for the management of their own sessions.
+async fn get_session_user<Backend>(
+    data: &AppState<Backend>,
+    request: &HttpRequest,
+) -> TcpResult<UserId>
+where
+    Backend: BackendHandler,
+{
+    use actix_web::FromRequest;
+    BearerAuth::extract(request)
+        .await
+        .ok()
+        .and_then(|bearer| check_if_token_is_valid(data, bearer.token()).ok())
+        .map(|validation_result| validation_result.user)
+        .ok_or_else(|| TcpError::UnauthorizedError("Not logged in".to_string()))
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn get_sessions<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+) -> TcpResult<HttpResponse>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    let user = get_session_user(&data, &request).await?;
+    let sessions = data.get_tcp_handler().list_sessions(&user).await?;
+    Ok(HttpResponse::Ok().json(sessions))
+}
+
+async fn get_sessions_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+) -> HttpResponse
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    get_sessions(data, request)
+        .await
+        .unwrap_or_else(error_to_http_response)
+}
+
+/// Revokes the refresh token of the session. Its current JWT stays valid until it expires.
+#[instrument(skip_all, level = "debug")]
+async fn delete_session<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+    session_id: web::Path<String>,
+) -> TcpResult<HttpResponse>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    let user = get_session_user(&data, &request).await?;
+    debug!(?user, %session_id);
+    data.get_tcp_handler()
+        .delete_session(&user, &session_id)
+        .await
+        .map_err(|e| match e {
+            DomainError::EntityNotFound(message) => TcpError::NotFoundError(message),
+            e => e.into(),
+        })?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+async fn delete_session_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+    session_id: web::Path<String>,
+) -> HttpResponse
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    delete_session(data, request, session_id)
+        .await
+        .unwrap_or_else(error_to_http_response)
+}
+
 async fn get_logout_handler<Backend>(
     data: web::Data<AppState<Backend>>,
     request: HttpRequest,
@@ -341,26 +452,16 @@ where
     // token.
     let groups = data.get_readonly_handler().get_user_groups(name).await?;
     let (refresh_token, max_age) = data.get_tcp_handler().create_refresh_token(name).await?;
-    let token = create_jwt(&data.jwt_key, name.to_string(), groups);
+    let ttl = data.session_options.access_token_ttl();
+    let token = create_jwt(&data.jwt_key, name.to_string(), groups, ttl);
     let refresh_token_plus_name = refresh_token + "+" + name.as_str();
 
     Ok(HttpResponse::Ok()
-        .cookie(
-            Cookie::build("token", token.as_str())
-                .max_age(1.days())
-                .path("/")
-                .http_only(true)
-                .same_site(SameSite::Strict)
-                .finish(),
-        )
-        .cookie(
-            Cookie::build("refresh_token", refresh_token_plus_name.clone())
-                .max_age(max_age.num_days().days())
-                .path("/auth")
-                .http_only(true)
-                .same_site(SameSite::Strict)
-                .finish(),
-        )
+        .cookie(token_cookie(&token, ttl))
+        .cookie(refresh_token_cookie(
+            refresh_token_plus_name.clone(),
+            max_age,
+        ))
         .json(&login::ServerLoginResponse {
             token: token.as_str().to_owned(),
             refresh_token: Some(refresh_token_plus_name),
@@ -375,9 +476,11 @@ async fn opaque_login_finish<Backend>(
 where
     Backend: TcpBackendHandler + BackendHandler + OpaqueHandler + 'static,
 {
-    let name = data
-        .get_opaque_handler()
-        .login_finish(request.into_inner())
+    let request = request.into_inner();
+    let totp_code = request.totp_code.clone();
+    let name = data.get_opaque_handler().login_finish(request).await?;
+    data.get_totp_handler()
+        .check_totp_code(&name, totp_code)
         .await?;
     get_login_successful_response(&data, &name).await
 }
@@ -408,6 +511,9 @@ where
         password: request.password.clone(),
     };
     data.get_login_handler().bind(bind_request).await?;
+    data.get_totp_handler()
+        .check_totp_code(&user_id, request.totp_code.clone())
+        .await?;
     get_login_successful_response(&data, &user_id).await
 }
 
@@ -423,6 +529,54 @@ where
         .unwrap_or_else(error_to_http_response)
 }
 
+#[instrument(skip_all, level = "debug")]
+async fn passkey_login_start<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: web::Json<passkey_login::ClientPasskeyLoginStartRequest>,
+) -> ApiResult<passkey_login::ServerPasskeyLoginStartResponse>
+where
+    Backend: BackendHandler + 'static,
+{
+    data.get_passkey_handler()
+        .start_passkey_login(&UserId::new(&request.username))
+        .await
+        .map(|challenge| {
+            ApiResult::Left(web::Json(passkey_login::ServerPasskeyLoginStartResponse {
+                server_data: challenge.server_data,
+                challenge: challenge.options,
+            }))
+        })
+        .unwrap_or_else(error_to_api_response)
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn passkey_login_finish<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: web::Json<passkey_login::ClientPasskeyLoginFinishRequest>,
+) -> TcpResult<HttpResponse>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    // The passkeys verify the user themselves: no TOTP code is needed on top of them.
+    let user_id = data
+        .get_passkey_handler()
+        .finish_passkey_login(&request.server_data, &request.credential)
+        .await?;
+    get_login_successful_response(&data, &user_id).await
+}
+
+async fn passkey_login_finish_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: web::Json<passkey_login::ClientPasskeyLoginFinishRequest>,
+) -> HttpResponse
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    passkey_login_finish(data, request)
+        .await
+        .unwrap_or_else(error_to_http_response)
+}
+
 #[instrument(skip_all, level = "debug")]
 async fn post_authorize<Backend>(
     data: web::Data<AppState<Backend>>,
@@ -434,6 +588,8 @@ where
     let name = request.name.clone();
     debug!(%name);
     data.get_login_handler().bind(request.into_inner()).await?;
+    // This endpoint doesn't take a second factor: it's rejected if the user has TOTP enabled.
+    data.get_totp_handler().check_totp_code(&name, None).await?;
     get_login_successful_response(&data, &name).await
 }
 
@@ -487,10 +643,16 @@ where
             "Not authorized to change the user's password".to_string(),
         ));
     }
-    Ok(data
+    let mut response = data
         .get_opaque_handler()
         .registration_start(registration_start_request)
-        .await?)
+        .await?;
+    // The server doesn't see the password, the client checks the policy.
+    let policy = &data.password_policy;
+    if !(policy.admin_override && validation_result.is_admin()) {
+        response.password_policy = Some(policy.rules());
+    }
+    Ok(response)
 }
 
 async fn opaque_register_start_handler<Backend>(
@@ -619,6 +781,25 @@ pub(crate) fn check_if_token_is_valid<Backend: BackendHandler>(
     ))
 }
 
+/// Same as `check_if_token_is_valid`, but also accepts the API tokens.
+#[instrument(skip_all, level = "debug", err, ret)]
+pub(crate) async fn check_if_bearer_token_is_valid<Backend: BackendHandler>(
+    state: &AppState<Backend>,
+    token_str: &str,
+) -> Result<ValidationResults, actix_web::Error> {
+    if token_str.starts_with(API_TOKEN_PREFIX) {
+        return state
+            .backend_handler
+            .get_permissions_for_api_token(token_str)
+            .await
+            .map_err(|e| {
+                debug!("Invalid API token: {}", e);
+                ErrorUnauthorized("Invalid API token")
+            });
+    }
+    check_if_token_is_valid(state, token_str)
+}
+
 pub fn configure_server<Backend>(cfg: &mut web::ServiceConfig, enable_password_reset: bool)
 where
     Backend: TcpBackendHandler + LoginHandler + OpaqueHandler + BackendHandler + 'static,
@@ -635,8 +816,25 @@ where
         .service(
             web::resource("/simple/login").route(web::post().to(simple_login_handler::<Backend>)),
         )
+        .service(
+            web::resource("/passkey/login/start")
+                .route(web::post().to(passkey_login_start::<Backend>)),
+        )
+        .service(
+            web::resource("/passkey/login/finish")
+                .route(web::post().to(passkey_login_finish_handler::<Backend>)),
+        )
         .service(web::resource("/refresh").route(web::get().to(get_refresh_handler::<Backend>)))
         .service(web::resource("/logout").route(web::get().to(get_logout_handler::<Backend>)))
+        .service(
+            web::scope("/sessions")
+                .wrap(CookieToHeaderTranslatorFactory)
+                .service(web::resource("").route(web::get().to(get_sessions_handler::<Backend>)))
+                .service(
+                    web::resource("/{session_id}")
+                        .route(web::delete().to(delete_session_handler::<Backend>)),
+                ),
+        )
         .service(
             web::scope("/opaque/register")
                 .wrap(CookieToHeaderTranslatorFactory)
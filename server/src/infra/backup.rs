@@ -0,0 +1,381 @@
+//! Backups of the directory as a versioned JSON archive: the users, groups, memberships and
+//! custom attributes, and optionally the credentials. Unlike a dump of the database, they can be
+//! restored with another database engine, e.g. from SQLite to PostgreSQL.
+//!
+//! The password files and the TOTP secrets depend on the server key: they are only useful when
+//! restored on a server with the same key. The archive itself can be encrypted with a
+//! passphrase.
+
+use crate::domain::{
+    model,
+    sql_attribute_index::{reindex_group_attributes, reindex_user_attributes},
+    sql_backend_handler::SqlBackendHandler,
+    sql_tables::LAST_SCHEMA_VERSION,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use base64::Engine;
+use sea_orm::{
+    ActiveModelTrait, ConnectionTrait, DatabaseTransaction, DbBackend, EntityTrait,
+    IntoActiveModel, PaginatorTrait, Statement, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+
+/// Version of the archive format, independent of the version of the database schema.
+const BACKUP_FORMAT_VERSION: u32 = 1;
+const ENCRYPTION: &str = "argon2i-xchacha20-poly1305";
+/// Stays below the limit of bound variables in a statement with SQLite.
+const INSERT_BATCH_SIZE: usize = 100;
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Backup {
+    format_version: u32,
+    schema_version: i16,
+    creation_date: chrono::NaiveDateTime,
+    includes_passwords: bool,
+    users: Vec<model::users::Model>,
+    groups: Vec<model::groups::Model>,
+    memberships: Vec<model::memberships::Model>,
+    group_memberships: Vec<model::group_memberships::Model>,
+    user_attribute_schema: Vec<model::user_attribute_schema::Model>,
+    group_attribute_schema: Vec<model::group_attribute_schema::Model>,
+    user_attributes: Vec<model::user_attributes::Model>,
+    group_attributes: Vec<model::group_attributes::Model>,
+    password_history: Vec<model::password_history::Model>,
+    passkeys: Vec<model::passkeys::Model>,
+}
+
+impl Backup {
+    pub fn user_count(&self) -> usize {
+        self.users.len()
+    }
+
+    pub fn group_count(&self) -> usize {
+        self.groups.len()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct EncryptedBackup {
+    format_version: u32,
+    encryption: String,
+    salt: String,
+    ciphertext: String,
+}
+
+/// The versions, read before the rest of the archive, whose format depends on them.
+#[derive(Deserialize)]
+struct BackupVersions {
+    format_version: u32,
+    schema_version: Option<i16>,
+}
+
+fn clear_credentials(user: &mut model::users::Model) {
+    user.password_hash = None;
+    user.replicated_password_hash = None;
+    user.totp_secret = None;
+    user.mfa_type = None;
+    user.totp_encrypted_secret = None;
+    user.totp_last_step = None;
+}
+
+pub async fn create_backup(handler: &SqlBackendHandler, include_passwords: bool) -> Result<Backup> {
+    let pool = &handler.sql_pool;
+    let mut users = model::User::find().all(pool).await?;
+    let (password_history, passkeys) = if include_passwords {
+        (
+            model::PasswordHistory::find().all(pool).await?,
+            model::Passkeys::find().all(pool).await?,
+        )
+    } else {
+        users.iter_mut().for_each(clear_credentials);
+        (Vec::new(), Vec::new())
+    };
+    Ok(Backup {
+        format_version: BACKUP_FORMAT_VERSION,
+        schema_version: LAST_SCHEMA_VERSION.0,
+        creation_date: chrono::Utc::now().naive_utc(),
+        includes_passwords: include_passwords,
+        users,
+        groups: model::Group::find().all(pool).await?,
+        memberships: model::Membership::find().all(pool).await?,
+        group_memberships: model::GroupMembership::find().all(pool).await?,
+        user_attribute_schema: model::UserAttributeSchema::find().all(pool).await?,
+        group_attribute_schema: model::GroupAttributeSchema::find().all(pool).await?,
+        user_attributes: model::UserAttributes::find().all(pool).await?,
+        group_attributes: model::GroupAttributes::find().all(pool).await?,
+        password_history,
+        passkeys,
+    })
+}
+
+fn derive_key(passphrase: &str, salt: &orion::kdf::Salt) -> Result<orion::aead::SecretKey> {
+    let password = orion::kdf::Password::from_slice(passphrase.as_bytes())?;
+    let key = orion::kdf::derive_key(&password, salt, 3, 1 << 16, 32)?;
+    Ok(orion::aead::SecretKey::from_slice(
+        key.unprotected_as_bytes(),
+    )?)
+}
+
+/// Serializes the backup, encrypted if there is a passphrase.
+pub fn write_backup(backup: &Backup, passphrase: Option<&str>) -> Result<Vec<u8>> {
+    let json = serde_json::to_vec_pretty(backup)?;
+    let passphrase = match passphrase {
+        Some(passphrase) => passphrase,
+        None => return Ok(json),
+    };
+    let salt = orion::kdf::Salt::default();
+    let ciphertext = orion::aead::seal(&derive_key(passphrase, &salt)?, &json)?;
+    let base64 = base64::engine::general_purpose::STANDARD;
+    Ok(serde_json::to_vec_pretty(&EncryptedBackup {
+        format_version: BACKUP_FORMAT_VERSION,
+        encryption: ENCRYPTION.to_owned(),
+        salt: base64.encode(salt.as_ref()),
+        ciphertext: base64.encode(ciphertext),
+    })?)
+}
+
+fn check_versions(value: &serde_json::Value) -> Result<()> {
+    let versions = BackupVersions::deserialize(value).context("Invalid backup file")?;
+    if versions.format_version > BACKUP_FORMAT_VERSION {
+        bail!(
+            "The backup was made by a newer version of lldap (format version {})",
+            versions.format_version
+        );
+    }
+    match versions.schema_version {
+        Some(version) if version != LAST_SCHEMA_VERSION.0 => bail!(
+            "The backup was made with the database schema version {}, and this version of lldap \
+             uses version {}: restore it with the version of lldap that made it, then upgrade",
+            version,
+            LAST_SCHEMA_VERSION.0
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// Parses the backup, after checking its versions and decrypting it if needed.
+pub fn read_backup(data: &[u8], passphrase: Option<&str>) -> Result<Backup> {
+    let value: serde_json::Value = serde_json::from_slice(data).context("Invalid backup file")?;
+    check_versions(&value)?;
+    let value = if value.get("ciphertext").is_some() {
+        let encrypted: EncryptedBackup = serde_json::from_value(value)?;
+        if encrypted.encryption != ENCRYPTION {
+            bail!("Unsupported encryption: {}", encrypted.encryption);
+        }
+        let passphrase =
+            passphrase.ok_or_else(|| anyhow!("The backup is encrypted, a passphrase is needed"))?;
+        let base64 = base64::engine::general_purpose::STANDARD;
+        let salt = orion::kdf::Salt::from_slice(&base64.decode(encrypted.salt)?)?;
+        let json = orion::aead::open(
+            &derive_key(passphrase, &salt)?,
+            &base64.decode(encrypted.ciphertext)?,
+        )
+        .map_err(|_| anyhow!("Could not decrypt the backup, is the passphrase right?"))?;
+        let value = serde_json::from_slice(&json).context("Invalid backup file")?;
+        check_versions(&value)?;
+        value
+    } else {
+        value
+    };
+    serde_json::from_value(value).context("Invalid backup file")
+}
+
+async fn insert_all<A>(
+    transaction: &DatabaseTransaction,
+    models: Vec<<A::Entity as EntityTrait>::Model>,
+) -> Result<()>
+where
+    A: ActiveModelTrait,
+    <A::Entity as EntityTrait>::Model: IntoActiveModel<A>,
+{
+    let mut models = models.into_iter().peekable();
+    while models.peek().is_some() {
+        <A::Entity as EntityTrait>::insert_many(
+            models
+                .by_ref()
+                .take(INSERT_BATCH_SIZE)
+                .map(IntoActiveModel::into_active_model),
+        )
+        .exec(transaction)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Loads the backup in an empty database, in a single transaction.
+pub async fn restore_backup(handler: &SqlBackendHandler, backup: Backup) -> Result<()> {
+    let pool = &handler.sql_pool;
+    if model::User::find().count(pool).await? > 0 || model::Group::find().count(pool).await? > 0 {
+        bail!("The database already contains users or groups, a backup can only be restored in an empty one");
+    }
+    let transaction = pool.begin().await?;
+    // The hardcoded attributes are created with the tables, and are also in the backup.
+    model::UserAttributeSchema::delete_many()
+        .exec(&transaction)
+        .await?;
+    model::GroupAttributeSchema::delete_many()
+        .exec(&transaction)
+        .await?;
+    insert_all::<model::user_attribute_schema::ActiveModel>(
+        &transaction,
+        backup.user_attribute_schema,
+    )
+    .await?;
+    insert_all::<model::group_attribute_schema::ActiveModel>(
+        &transaction,
+        backup.group_attribute_schema,
+    )
+    .await?;
+    insert_all::<model::users::ActiveModel>(&transaction, backup.users).await?;
+    // The uid numbers of the new users follow the restored ones.
+    transaction
+        .execute(Statement::from_string(
+            transaction.get_database_backend(),
+            "UPDATE metadata SET next_uid_number = \
+             (SELECT COALESCE(MAX(uid_number), 0) + 1 FROM users)"
+                .to_owned(),
+        ))
+        .await?;
+    insert_all::<model::groups::ActiveModel>(&transaction, backup.groups).await?;
+    insert_all::<model::memberships::ActiveModel>(&transaction, backup.memberships).await?;
+    insert_all::<model::group_memberships::ActiveModel>(&transaction, backup.group_memberships)
+        .await?;
+    insert_all::<model::user_attributes::ActiveModel>(&transaction, backup.user_attributes).await?;
+    insert_all::<model::group_attributes::ActiveModel>(&transaction, backup.group_attributes)
+        .await?;
+    reindex_user_attributes(&transaction, None).await?;
+    reindex_group_attributes(&transaction, None).await?;
+    insert_all::<model::password_history::ActiveModel>(&transaction, backup.password_history)
+        .await?;
+    insert_all::<model::passkeys::ActiveModel>(&transaction, backup.passkeys).await?;
+    // PostgreSQL doesn't advance the sequences of the ids inserted explicitly.
+    if transaction.get_database_backend() == DbBackend::Postgres {
+        for (table, column) in [
+            ("groups", "group_id"),
+            ("password_history", "password_history_id"),
+            ("passkeys", "passkey_id"),
+        ] {
+            transaction
+                .execute(Statement::from_string(
+                    DbBackend::Postgres,
+                    format!(
+                        "SELECT setval(pg_get_serial_sequence('{0}', '{1}'), \
+                         COALESCE(MAX({1}), 0) + 1, false) FROM {0}",
+                        table, column
+                    ),
+                ))
+                .await?;
+        }
+    }
+    transaction.commit().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        handler::{
+            BindRequest, GroupBackendHandler, GroupListerBackendHandler, LoginHandler,
+            UserBackendHandler, UserListerBackendHandler,
+        },
+        sql_backend_handler::tests::*,
+        types::UserId,
+    };
+
+    async fn get_handler() -> SqlBackendHandler {
+        SqlBackendHandler::new(get_default_config(), get_initialized_db().await)
+    }
+
+    #[tokio::test]
+    async fn test_backup_and_restore() {
+        let handler = get_handler().await;
+        insert_user(&handler, "bob", "bob00").await;
+        let group = insert_group(&handler, "Best Group").await;
+        insert_membership(&handler, group, "bob").await;
+        let other_group = handler.create_group("Nested Group").await.unwrap();
+        handler
+            .add_group_to_group(other_group, group)
+            .await
+            .unwrap();
+
+        let backup = create_backup(&handler, false).await.unwrap();
+        assert!(backup.users[0].password_hash.is_none());
+        let data = write_backup(&backup, None).unwrap();
+        let restored = read_backup(&data, None).unwrap();
+        assert_eq!(restored, backup);
+
+        let target = get_handler().await;
+        restore_backup(&target, restored).await.unwrap();
+        assert_eq!(get_user_names(&target, None).await, vec!["bob".to_owned()]);
+        let groups = target.list_groups(None).await.unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].users, vec![UserId::new("bob")]);
+        assert_eq!(groups[0].member_groups[0].group_id, other_group);
+        assert_eq!(target.list_users(None, false).await.unwrap().len(), 1);
+        // The new ids follow the restored ones.
+        assert!(target.create_group("New Group").await.unwrap().0 > other_group.0);
+        insert_user(&target, "new", "new00").await;
+        assert_eq!(
+            target
+                .get_user_details(&UserId::new("new"))
+                .await
+                .unwrap()
+                .uid_number,
+            2
+        );
+
+        // Only in an empty database.
+        let backup = create_backup(&handler, false).await.unwrap();
+        assert!(restore_backup(&target, backup).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_backup_with_passwords() {
+        let handler = get_handler().await;
+        insert_user(&handler, "bob", "bob00").await;
+        let backup = create_backup(&handler, true).await.unwrap();
+        assert!(backup.users[0].password_hash.is_some());
+        let target = get_handler().await;
+        restore_backup(&target, backup).await.unwrap();
+        target
+            .bind(BindRequest {
+                name: UserId::new("bob"),
+                password: "bob00".to_owned(),
+            })
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_backup() {
+        let handler = get_handler().await;
+        insert_user(&handler, "bob", "bob00").await;
+        let backup = create_backup(&handler, true).await.unwrap();
+        let data = write_backup(&backup, Some("passphrase")).unwrap();
+        assert!(!String::from_utf8_lossy(&data).contains("bob"));
+        assert!(read_backup(&data, None).is_err());
+        assert!(read_backup(&data, Some("wrong passphrase")).is_err());
+        assert_eq!(read_backup(&data, Some("passphrase")).unwrap(), backup);
+    }
+
+    #[test]
+    fn test_read_backup_versions() {
+        let newer = serde_json::json!({
+            "format_version": BACKUP_FORMAT_VERSION + 1,
+            "schema_version": LAST_SCHEMA_VERSION.0,
+        });
+        assert!(read_backup(newer.to_string().as_bytes(), None)
+            .unwrap_err()
+            .to_string()
+            .contains("newer version"));
+        let older_schema = serde_json::json!({
+            "format_version": BACKUP_FORMAT_VERSION,
+            "schema_version": LAST_SCHEMA_VERSION.0 - 1,
+        });
+        assert!(read_backup(older_schema.to_string().as_bytes(), None)
+            .unwrap_err()
+            .to_string()
+            .contains("schema version"));
+    }
+}
@@ -30,6 +30,24 @@ pub enum Command {
     /// Create database schema.
     #[clap(name = "create_schema")]
     CreateSchema(RunOpts),
+    /// Apply the pending migrations of the database schema, or list them.
+    #[clap(name = "migrate")]
+    Migrate(MigrateOpts),
+    /// Export all the users and groups as LDIF.
+    #[clap(name = "export_ldif")]
+    ExportLdif(ExportLdifOpts),
+    /// Create or update users and groups from an LDIF file.
+    #[clap(name = "import_ldif")]
+    ImportLdif(ImportLdifOpts),
+    /// Create users from a CSV file.
+    #[clap(name = "import_csv")]
+    ImportCsv(ImportCsvOpts),
+    /// Back up the users, groups and attributes to a JSON file.
+    #[clap(name = "backup")]
+    Backup(BackupOpts),
+    /// Restore a backup in an empty database.
+    #[clap(name = "restore")]
+    Restore(RestoreOpts),
 }
 
 #[derive(Debug, Parser, Clone)]
@@ -184,6 +202,109 @@ pub struct ExportGraphQLSchemaOpts {
     pub output_file: Option<String>,
 }
 
+#[derive(Debug, Parser, Clone)]
+pub struct MigrateOpts {
+    #[clap(flatten)]
+    pub run_opts: RunOpts,
+
+    /// Print the SQL statements of the pending migrations, without applying them. Exits with the
+    /// code 2 if some are pending. Not supported with MySQL/MariaDB.
+    #[clap(long)]
+    pub dry_run: bool,
+
+    #[clap(subcommand)]
+    pub command: Option<MigrateCommand>,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub enum MigrateCommand {
+    /// List the applied and pending migrations. Exits with the code 2 if some are pending.
+    #[clap(name = "status")]
+    Status,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct ExportLdifOpts {
+    #[clap(flatten)]
+    pub run_opts: RunOpts,
+
+    /// Output file for the LDIF.
+    #[clap(short, long)]
+    pub output_file: String,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct ImportLdifOpts {
+    #[clap(flatten)]
+    pub run_opts: RunOpts,
+
+    /// LDIF file to import.
+    #[clap(short, long)]
+    pub input_file: String,
+
+    /// Only check the file and report what would be imported, without writing anything.
+    #[clap(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct ImportCsvOpts {
+    #[clap(flatten)]
+    pub run_opts: RunOpts,
+
+    /// CSV file to import, with a header row.
+    #[clap(short, long)]
+    pub input_file: String,
+
+    /// Fields of the columns, as "column=field" (comma-separated or repeated). The fields are
+    /// uid, email, display_name, first_name, last_name, groups, password_hash (bcrypt, {SSHA},
+    /// {SHA} or {CRYPT}) or a custom attribute. Columns named after a field don't need a mapping, and the other columns
+    /// are ignored.
+    #[clap(short, long, value_delimiter = ',')]
+    pub mapping: Vec<String>,
+
+    /// File to write the rows that couldn't be imported to. Default: <input_file>.errors.csv
+    #[clap(long)]
+    pub errors_file: Option<String>,
+
+    /// Send a password reset email to the created users, except those with a password hash.
+    #[clap(long)]
+    pub send_password_reset: bool,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct BackupOpts {
+    #[clap(flatten)]
+    pub run_opts: RunOpts,
+
+    /// Output file for the backup.
+    #[clap(short, long)]
+    pub output_file: String,
+
+    /// Also back up the password files, the TOTP secrets and the password history. They can only
+    /// be restored with the same server key.
+    #[clap(long)]
+    pub include_passwords: bool,
+
+    /// Encrypt the backup with this passphrase.
+    #[clap(long, env = "LLDAP_BACKUP_PASSPHRASE", hide_env_values = true)]
+    pub passphrase: Option<String>,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct RestoreOpts {
+    #[clap(flatten)]
+    pub run_opts: RunOpts,
+
+    /// Backup file to restore.
+    #[clap(short, long)]
+    pub input_file: String,
+
+    /// Passphrase of the backup, if it is encrypted.
+    #[clap(long, env = "LLDAP_BACKUP_PASSPHRASE", hide_env_values = true)]
+    pub passphrase: Option<String>,
+}
+
 pub fn init() -> CLIOpts {
     CLIOpts::parse()
 }
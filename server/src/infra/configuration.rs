@@ -1,6 +1,9 @@
 use crate::{
     domain::types::UserId,
-    infra::cli::{GeneralConfigOpts, LdapsOpts, RunOpts, SmtpEncryption, SmtpOpts, TestEmailOpts},
+    infra::cli::{
+        BackupOpts, ExportLdifOpts, GeneralConfigOpts, ImportCsvOpts, ImportLdifOpts, LdapsOpts,
+        MigrateOpts, RestoreOpts, RunOpts, SmtpEncryption, SmtpOpts, TestEmailOpts,
+    },
 };
 use anyhow::{Context, Result};
 use figment::{
@@ -8,9 +11,13 @@ use figment::{
     Figment,
 };
 use lettre::message::Mailbox;
-use lldap_auth::opaque::{server::ServerSetup, KeyPair};
+use lldap_auth::{
+    opaque::{server::ServerSetup, KeyPair},
+    password_policy::{PasswordPolicy, PasswordPolicyError},
+};
 use secstr::SecUtf8;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use url::Url;
 
 #[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
@@ -32,11 +39,35 @@ pub struct MailOptions {
     pub password: SecUtf8,
     #[builder(default = "SmtpEncryption::Tls")]
     pub smtp_encryption: SmtpEncryption,
+    /// Maximum number of pooled connections to the SMTP server.
+    #[builder(default = "4")]
+    pub pool_size: u32,
+    /// Timeout of the SMTP commands, in seconds.
+    #[builder(default = "10")]
+    pub timeout_seconds: u64,
+    /// Directory with the custom email templates, see `mail::EmailTemplates`.
+    #[builder(default = "None")]
+    pub templates_dir: Option<String>,
+    /// Maximum number of password reset emails sent to the same address within the window, 0 for
+    /// no limit.
+    #[builder(default = "3")]
+    pub password_reset_rate_limit: u32,
+    #[builder(default = "3600")]
+    pub password_reset_rate_limit_window_seconds: u64,
+    /// How long the password reset links stay valid.
+    #[builder(default = "10")]
+    pub reset_token_validity_minutes: u64,
     /// Deprecated.
     #[builder(default = "None")]
     pub tls_required: Option<bool>,
 }
 
+impl MailOptions {
+    pub fn reset_token_validity(&self) -> chrono::Duration {
+        chrono::Duration::minutes(self.reset_token_validity_minutes as i64)
+    }
+}
+
 impl std::default::Default for MailOptions {
     fn default() -> Self {
         MailOptionsBuilder::default().build().unwrap()
@@ -54,6 +85,9 @@ pub struct LdapsOptions {
     pub cert_file: String,
     #[builder(default = r#"String::from("key.pem")"#)]
     pub key_file: String,
+    /// Refuse the binds on the plain LDAP port until the connection is upgraded with StartTLS.
+    #[builder(default = "false")]
+    pub require_tls_for_bind: bool,
 }
 
 impl std::default::Default for LdapsOptions {
@@ -62,6 +96,436 @@ impl std::default::Default for LdapsOptions {
     }
 }
 
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct PosixOptions {
+    /// Added to the user's numeric id to compute the uidNumber.
+    #[builder(default = "10000")]
+    pub uid_number_offset: u32,
+    /// Added to the group id to compute the gidNumber.
+    #[builder(default = "10000")]
+    pub gid_number_offset: u32,
+    /// "{uid}" is replaced with the user id.
+    #[builder(default = r#"String::from("/home/{uid}")"#)]
+    pub home_directory_template: String,
+    #[builder(default = r#"String::from("/bin/bash")"#)]
+    pub login_shell: String,
+    /// Default for shadowMax, unless the user has a "shadow_max" attribute.
+    #[builder(default)]
+    pub shadow_max: Option<i64>,
+    /// Default for shadowExpire, unless the user has an expiration date or a "shadow_expire"
+    /// attribute.
+    #[builder(default)]
+    pub shadow_expire: Option<i64>,
+}
+
+impl std::default::Default for PosixOptions {
+    fn default() -> Self {
+        PosixOptionsBuilder::default().build().unwrap()
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct LockoutOptions {
+    /// Number of failed logins within the window that locks the account, 0 to disable lockouts.
+    #[builder(default = "0")]
+    pub max_failures: u32,
+    #[builder(default = "900")]
+    pub failure_window_seconds: u64,
+    #[builder(default = "900")]
+    pub lockout_duration_seconds: u64,
+}
+
+impl std::default::Default for LockoutOptions {
+    fn default() -> Self {
+        LockoutOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// Lifetimes of the web UI sessions.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct SessionOptions {
+    /// Lifetime of the JWTs, renewed with the refresh token.
+    #[builder(default = "1440")]
+    pub access_token_ttl_minutes: u64,
+    /// The sessions not refreshed for this long expire.
+    #[builder(default = "30")]
+    pub refresh_token_ttl_days: u64,
+    /// Each refresh replaces the refresh token. Reusing a replaced token revokes the session.
+    #[builder(default = "true")]
+    pub refresh_token_rotation: bool,
+}
+
+impl SessionOptions {
+    pub fn access_token_ttl(&self) -> chrono::Duration {
+        chrono::Duration::minutes(self.access_token_ttl_minutes as i64)
+    }
+
+    pub fn refresh_token_ttl(&self) -> chrono::Duration {
+        chrono::Duration::days(self.refresh_token_ttl_days as i64)
+    }
+}
+
+impl std::default::Default for SessionOptions {
+    fn default() -> Self {
+        SessionOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// Sizing and timeouts of the pools of database connections.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct DatabasePoolOptions {
+    #[builder(default = "5")]
+    pub max_connections: u32,
+    /// Connections kept open even when idle.
+    #[builder(default = "0")]
+    pub min_connections: u32,
+    /// How long a request waits for a free connection before failing.
+    #[builder(default = "30")]
+    pub acquire_timeout_seconds: u64,
+    /// Idle connections above `min_connections` are closed after this long.
+    #[builder(default = "600")]
+    pub idle_timeout_seconds: u64,
+    /// Connections are replaced after this long.
+    #[builder(default = "1800")]
+    pub max_lifetime_seconds: u64,
+}
+
+impl DatabasePoolOptions {
+    /// Applies the options to the connection to the database (or its replica).
+    pub fn connect_options(&self, database_url: &str) -> sea_orm::ConnectOptions {
+        let mut sql_opt = sea_orm::ConnectOptions::new(database_url.to_owned());
+        sql_opt
+            .max_connections(self.max_connections)
+            .min_connections(self.min_connections)
+            .acquire_timeout(std::time::Duration::from_secs(self.acquire_timeout_seconds))
+            .idle_timeout(std::time::Duration::from_secs(self.idle_timeout_seconds))
+            .max_lifetime(std::time::Duration::from_secs(self.max_lifetime_seconds));
+        sql_opt
+    }
+}
+
+impl std::default::Default for DatabasePoolOptions {
+    fn default() -> Self {
+        DatabasePoolOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// How the user ids are normalized, see `UserId::new`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct UserIdNormalizationOptions {
+    /// Remove the leading and trailing whitespace.
+    #[builder(default = "true")]
+    pub trim: bool,
+    #[builder(default = "true")]
+    pub lowercase: bool,
+    /// Unicode NFKC normalization, e.g. to turn the full-width characters into ASCII.
+    #[builder(default = "false")]
+    pub unicode_nfkc: bool,
+}
+
+impl std::default::Default for UserIdNormalizationOptions {
+    fn default() -> Self {
+        UserIdNormalizationOptionsBuilder::default()
+            .build()
+            .unwrap()
+    }
+}
+
+/// Common passwords, loaded from `PasswordPolicyOptions::denylist_file`.
+#[derive(Clone, Default)]
+pub struct PasswordDenylist(std::sync::Arc<HashSet<String>>);
+
+impl std::fmt::Debug for PasswordDenylist {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "PasswordDenylist({} entries)", self.0.len())
+    }
+}
+
+/// Rules for the new passwords. The server only enforces them for the passwords it receives in
+/// clear, over LDAP: with OPAQUE, e.g. from the web UI or the set-password tool, the client checks
+/// them, so a custom client can set any password.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct PasswordPolicyOptions {
+    /// 0 for no minimum.
+    #[builder(default = "0")]
+    pub min_length: usize,
+    #[builder(default = "false")]
+    pub require_lowercase: bool,
+    #[builder(default = "false")]
+    pub require_uppercase: bool,
+    #[builder(default = "false")]
+    pub require_digit: bool,
+    #[builder(default = "false")]
+    pub require_special: bool,
+    /// File with one common password per line, to reject. Only checked over LDAP: the OPAQUE
+    /// clients (the web UI, the password reset page, the set-password tool) don't have it.
+    #[builder(default)]
+    pub denylist_file: Option<String>,
+    /// Whether the admins can set passwords that don't follow the policy.
+    #[builder(default = "false")]
+    pub admin_override: bool,
+    /// Number of previous passwords that can't be reused, 0 to disable the history.
+    #[builder(default = "0")]
+    pub history_size: u32,
+    #[serde(skip)]
+    #[builder(default)]
+    pub denylist: PasswordDenylist,
+}
+
+impl std::default::Default for PasswordPolicyOptions {
+    fn default() -> Self {
+        PasswordPolicyOptionsBuilder::default().build().unwrap()
+    }
+}
+
+impl PasswordPolicyOptions {
+    /// The rules that can be checked without the denylist, e.g. by the OPAQUE clients.
+    pub fn rules(&self) -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: self.min_length,
+            require_lowercase: self.require_lowercase,
+            require_uppercase: self.require_uppercase,
+            require_digit: self.require_digit,
+            require_special: self.require_special,
+        }
+    }
+
+    pub fn check(&self, password: &str) -> std::result::Result<(), PasswordPolicyError> {
+        let mut failed = self.rules().failed_rules(password);
+        if self.denylist.0.contains(&password.to_lowercase()) {
+            failed.push("not a common password".to_owned());
+        }
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(PasswordPolicyError(failed))
+        }
+    }
+
+    fn load_denylist(&mut self) -> Result<()> {
+        if let Some(file) = &self.denylist_file {
+            let contents = std::fs::read_to_string(file)
+                .with_context(|| format!("while reading the password denylist {}", file))?;
+            self.denylist = PasswordDenylist(std::sync::Arc::new(
+                contents
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty())
+                    .map(str::to_lowercase)
+                    .collect(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LdapAttributeAlias {
+    /// Attribute name used by the LDAP client, e.g. "mail-alternate".
+    pub alias: String,
+    /// Attribute it resolves to: either a built-in LDAP name like "email", or a custom attribute.
+    pub attribute: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LdapVirtualAttribute {
+    /// Name of the read-only user attribute, e.g. "proxyAddresses".
+    pub name: String,
+    /// Value of the attribute, with the other attributes between braces, e.g. "SMTP:{mail}".
+    pub template: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LdapAttributeAccess {
+    /// User attribute restricted over LDAP, e.g. "jpegPhoto".
+    pub attribute: String,
+    /// Who can read it besides the admins: "self" for the user's own entry, or group names.
+    pub readers: Vec<String>,
+}
+
+/// Format of the password hashes exposed as `userPassword`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PasswordReplicationFormat {
+    /// "{SSHA}<base64>", salted SHA-1.
+    Ssha,
+    /// "{CRYPT}$6$...", SHA-512 crypt.
+    Crypt,
+    /// "{ARGON2}$argon2id$...", the PHC string.
+    Argon2,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct LdapPasswordReplicationOptions {
+    /// If set, a hash of the password in this format is kept, computed at the binds since the
+    /// password is not known otherwise.
+    #[serde(default)]
+    #[builder(default)]
+    pub format: Option<PasswordReplicationFormat>,
+    /// Users (e.g. the replication accounts) that can read the `userPassword` of the others. The
+    /// admins can't, unless they are listed.
+    #[serde(default)]
+    #[builder(default)]
+    pub readers: Vec<String>,
+    /// Parameters of the "argon2" format. The hashes computed with other parameters are replaced
+    /// at the next bind.
+    #[builder(default = "argon2::Params::DEFAULT_M_COST")]
+    pub argon2_memory_kib: u32,
+    #[builder(default = "argon2::Params::DEFAULT_T_COST")]
+    pub argon2_iterations: u32,
+    #[builder(default = "argon2::Params::DEFAULT_P_COST")]
+    pub argon2_parallelism: u32,
+}
+
+impl std::default::Default for LdapPasswordReplicationOptions {
+    fn default() -> Self {
+        LdapPasswordReplicationOptionsBuilder::default()
+            .build()
+            .unwrap()
+    }
+}
+
+impl LdapPasswordReplicationOptions {
+    pub fn argon2_params(&self) -> Result<argon2::Params, argon2::Error> {
+        argon2::Params::new(
+            self.argon2_memory_kib,
+            self.argon2_iterations,
+            self.argon2_parallelism,
+            None,
+        )
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LdapNamingContext {
+    /// Additional base DN, e.g. "dc=org1,dc=com".
+    pub base_dn: String,
+    /// Only the members of this group are visible under the base DN, and can bind with it.
+    pub group: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LdapReferral {
+    /// Base DN held by another server, e.g. "dc=corp,dc=com".
+    pub base_dn: String,
+    /// URL of the server, e.g. "ldap://ldap.corp.com:389".
+    pub url: Url,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WebhookOptions {
+    /// Endpoint receiving the user lifecycle events, as a JSON POST.
+    pub url: Url,
+    /// Key of the HMAC-SHA256 signature of the body, sent in the X-Lldap-Signature header.
+    pub secret: SecUtf8,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct OidcClient {
+    pub client_id: String,
+    /// Secret of the confidential clients. Public clients (e.g. single-page apps) have none, and
+    /// only rely on PKCE.
+    #[serde(default)]
+    pub client_secret: Option<SecUtf8>,
+    /// The only URIs that the users can be sent back to, compared exactly.
+    pub redirect_uris: Vec<Url>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct OidcOptions {
+    /// The OpenID Connect provider is disabled when there are no clients.
+    #[builder(default)]
+    pub clients: Vec<OidcClient>,
+    /// RSA key signing the tokens, in PKCS#8 PEM. Generated if it doesn't exist.
+    #[builder(default = r#"String::from("oidc_key.pem")"#)]
+    pub key_file: String,
+    /// Name of the claim listing the user's groups.
+    #[builder(default = r#"String::from("groups")"#)]
+    pub groups_claim: String,
+    #[builder(default = "3600")]
+    pub token_lifetime_seconds: u64,
+}
+
+impl std::default::Default for OidcOptions {
+    fn default() -> Self {
+        OidcOptionsBuilder::default().build().unwrap()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// Human-readable, grouped by span.
+    #[default]
+    Pretty,
+    /// One JSON object per event, with the span context and the structured fields.
+    Json,
+}
+
+/// Where to take the users' cn (and displayName) from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LdapCnSource {
+    DisplayName,
+    /// "{first_name} {last_name}", with whichever of the two is set.
+    FullName,
+    UserId,
+}
+
+/// Attribute used in the RDN of the users' DNs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LdapUserRdnAttribute {
+    #[default]
+    Uid,
+    Cn,
+}
+
+impl LdapUserRdnAttribute {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LdapUserRdnAttribute::Uid => "uid",
+            LdapUserRdnAttribute::Cn => "cn",
+        }
+    }
+}
+
+/// Attribute(s) listing the DNs of the members of a group.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LdapGroupMemberAttribute {
+    /// "member", from the groupOfNames object class.
+    Member,
+    /// "uniqueMember", from the groupOfUniqueNames object class.
+    UniqueMember,
+    #[default]
+    Both,
+}
+
+impl LdapGroupMemberAttribute {
+    pub fn has_member(&self) -> bool {
+        matches!(
+            self,
+            LdapGroupMemberAttribute::Member | LdapGroupMemberAttribute::Both
+        )
+    }
+
+    pub fn has_unique_member(&self) -> bool {
+        matches!(
+            self,
+            LdapGroupMemberAttribute::UniqueMember | LdapGroupMemberAttribute::Both
+        )
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
 #[builder(pattern = "owned", build_fn(name = "private_build"))]
 pub struct Configuration {
@@ -85,12 +549,22 @@ pub struct Configuration {
     pub ldap_user_pass: SecUtf8,
     #[builder(default = r#"String::from("sqlite://users.db?mode=rwc")"#)]
     pub database_url: String,
+    /// Read-only replica of the database, for the listings of users and groups.
+    #[builder(default)]
+    pub database_replica_url: Option<String>,
+    #[builder(default)]
+    pub database_pool_options: DatabasePoolOptions,
     #[builder(default)]
     pub ignored_user_attributes: Vec<String>,
     #[builder(default)]
     pub ignored_group_attributes: Vec<String>,
+    /// Log the unknown attributes at the debug level rather than as warnings.
+    #[builder(default = "false")]
+    pub quiet_unknown_attributes: bool,
     #[builder(default = "false")]
     pub verbose: bool,
+    #[builder(default)]
+    pub log_format: LogFormat,
     #[builder(default = r#"String::from("server_key")"#)]
     pub key_file: String,
     // We want an Option to see whether there is a value or not, since the value is printed as
@@ -101,6 +575,104 @@ pub struct Configuration {
     pub smtp_options: MailOptions,
     #[builder(default)]
     pub ldaps_options: LdapsOptions,
+    #[builder(default)]
+    pub posix_options: PosixOptions,
+    #[builder(default)]
+    pub lockout_options: LockoutOptions,
+    #[builder(default)]
+    pub session_options: SessionOptions,
+    #[builder(default)]
+    pub user_id_normalization: UserIdNormalizationOptions,
+    #[builder(default)]
+    pub password_policy: PasswordPolicyOptions,
+    #[builder(default)]
+    pub ldap_attribute_aliases: Vec<LdapAttributeAlias>,
+    #[builder(default)]
+    pub ldap_virtual_attributes: Vec<LdapVirtualAttribute>,
+    #[builder(default)]
+    pub ldap_attribute_access: Vec<LdapAttributeAccess>,
+    #[builder(default)]
+    pub ldap_password_replication: LdapPasswordReplicationOptions,
+    #[builder(default)]
+    pub webhooks: Vec<WebhookOptions>,
+    #[builder(default)]
+    pub oidc_options: OidcOptions,
+    /// Maximum number of entries per page, for the clients using the paged results control.
+    #[builder(default = "1000")]
+    pub ldap_max_page_size: u32,
+    /// Maximum number of entries returned by a search, 0 for no limit.
+    #[builder(default = "0")]
+    pub ldap_search_size_limit: u32,
+    #[builder(
+        default = "vec![LdapCnSource::DisplayName, LdapCnSource::FullName, LdapCnSource::UserId]"
+    )]
+    pub ldap_cn_sources: Vec<LdapCnSource>,
+    /// Name of the organizational unit containing the users: "ou=<name>,<base dn>".
+    #[builder(default = r#"String::from("people")"#)]
+    pub ldap_people_ou: String,
+    /// Name of the organizational unit containing the groups: "ou=<name>,<base dn>".
+    #[builder(default = r#"String::from("groups")"#)]
+    pub ldap_groups_ou: String,
+    #[builder(default)]
+    pub ldap_user_rdn_attribute: LdapUserRdnAttribute,
+    #[builder(default)]
+    pub ldap_group_member_attribute: LdapGroupMemberAttribute,
+    /// Object classes of the user entries.
+    #[builder(
+        default = r#"["inetOrgPerson", "posixAccount", "mailAccount", "person", "shadowAccount", "top"].into_iter().map(String::from).collect()"#
+    )]
+    pub ldap_user_object_classes: Vec<String>,
+    /// Object classes of the group entries. If empty, they follow the
+    /// `ldap_group_member_attribute`, plus posixGroup.
+    #[builder(default)]
+    pub ldap_group_object_classes: Vec<String>,
+    /// Accept the binds with an empty DN and password.
+    #[builder(default = "false")]
+    pub ldap_anonymous_bind: bool,
+    /// Attributes returned to the anonymous searches. If empty, anonymous clients can only read
+    /// the root DSE.
+    #[builder(default)]
+    pub ldap_anonymous_attributes: Vec<String>,
+    #[builder(default)]
+    pub ldap_naming_contexts: Vec<LdapNamingContext>,
+    /// The searches and binds under these base DNs get a referral to another server.
+    #[builder(default)]
+    pub ldap_referrals: Vec<LdapReferral>,
+    /// Leave the disabled users out of the LDAP searches.
+    #[builder(default = "false")]
+    pub ldap_hide_disabled_users: bool,
+    /// Also list the groups containing the users' groups in their memberOf, and match them in
+    /// the memberOf filters. Also accepted as `enable_nested_groups` (see `load_config`).
+    #[builder(default = "false")]
+    pub ldap_flatten_nested_groups: bool,
+    /// Return the DNs of the entries with the case used by the client in the search base or in
+    /// the bind DN, instead of lowercase.
+    #[builder(default = "false")]
+    pub ldap_preserve_dn_case: bool,
+    /// Replace the unsupported LDAP filters by a filter matching nothing, instead of failing the
+    /// search.
+    #[builder(default = "false")]
+    pub ldap_ignore_unsupported_filters: bool,
+    /// If set, only these DNs (and the admin user) get the admin rights over LDAP, when they are
+    /// members of lldap_admin. The other admins get read-only rights.
+    #[builder(default)]
+    pub ldap_admin_bind_dns: Vec<String>,
+    /// Maximum number of concurrent LDAP connections, 0 for no limit.
+    #[builder(default = "0")]
+    pub ldap_max_connections: u32,
+    /// Idle LDAP connections are closed after this many seconds, 0 to keep them open.
+    #[builder(default = "0")]
+    pub ldap_idle_timeout_seconds: u64,
+    /// Deleted users are kept (hidden) for this many days and can be restored, 0 to delete them
+    /// right away.
+    #[builder(default = "0")]
+    pub deleted_users_retention_days: u32,
+    /// Fields and custom attributes that the users can change in their own profile.
+    #[builder(default = r#"vec!["display_name".to_owned(), "avatar".to_owned()]"#)]
+    pub self_service_attributes: Vec<String>,
+    /// Names of the groups that every new user joins.
+    #[builder(default)]
+    pub default_user_groups: Vec<String>,
     #[builder(default = r#"Url::parse("http://localhost").unwrap()"#)]
     pub http_url: Url,
     #[serde(skip)]
@@ -152,7 +724,7 @@ fn generate_random_private_key() -> ServerSetup {
     ServerSetup::new(&mut rng)
 }
 
-fn write_to_readonly_file(path: &std::path::Path, buffer: &[u8]) -> Result<()> {
+pub(crate) fn write_to_readonly_file(path: &std::path::Path, buffer: &[u8]) -> Result<()> {
     use std::{fs::File, io::Write};
     assert!(!path.exists());
     let mut file = File::create(path)?;
@@ -211,6 +783,42 @@ impl TopLevelCommandOpts for RunOpts {
     }
 }
 
+impl TopLevelCommandOpts for ExportLdifOpts {
+    fn general_config(&self) -> &GeneralConfigOpts {
+        &self.run_opts.general_config
+    }
+}
+
+impl TopLevelCommandOpts for ImportLdifOpts {
+    fn general_config(&self) -> &GeneralConfigOpts {
+        &self.run_opts.general_config
+    }
+}
+
+impl TopLevelCommandOpts for ImportCsvOpts {
+    fn general_config(&self) -> &GeneralConfigOpts {
+        &self.run_opts.general_config
+    }
+}
+
+impl TopLevelCommandOpts for MigrateOpts {
+    fn general_config(&self) -> &GeneralConfigOpts {
+        &self.run_opts.general_config
+    }
+}
+
+impl TopLevelCommandOpts for BackupOpts {
+    fn general_config(&self) -> &GeneralConfigOpts {
+        &self.run_opts.general_config
+    }
+}
+
+impl TopLevelCommandOpts for RestoreOpts {
+    fn general_config(&self) -> &GeneralConfigOpts {
+        &self.run_opts.general_config
+    }
+}
+
 impl TopLevelCommandOpts for TestEmailOpts {
     fn general_config(&self) -> &GeneralConfigOpts {
         &self.general_config
@@ -249,6 +857,42 @@ impl ConfigOverrider for RunOpts {
     }
 }
 
+impl ConfigOverrider for ExportLdifOpts {
+    fn override_config(&self, config: &mut Configuration) {
+        self.run_opts.override_config(config);
+    }
+}
+
+impl ConfigOverrider for ImportLdifOpts {
+    fn override_config(&self, config: &mut Configuration) {
+        self.run_opts.override_config(config);
+    }
+}
+
+impl ConfigOverrider for ImportCsvOpts {
+    fn override_config(&self, config: &mut Configuration) {
+        self.run_opts.override_config(config);
+    }
+}
+
+impl ConfigOverrider for MigrateOpts {
+    fn override_config(&self, config: &mut Configuration) {
+        self.run_opts.override_config(config);
+    }
+}
+
+impl ConfigOverrider for BackupOpts {
+    fn override_config(&self, config: &mut Configuration) {
+        self.run_opts.override_config(config);
+    }
+}
+
+impl ConfigOverrider for RestoreOpts {
+    fn override_config(&self, config: &mut Configuration) {
+        self.run_opts.override_config(config);
+    }
+}
+
 impl ConfigOverrider for TestEmailOpts {
     fn override_config(&self, config: &mut Configuration) {
         self.general_config.override_config(config);
@@ -313,6 +957,24 @@ impl ConfigOverrider for SmtpOpts {
     }
 }
 
+/// Reads the configuration file and the environment, over the defaults.
+fn load_config(config_file: &str) -> Result<Configuration> {
+    use figment_file_provider_adapter::FileAdapter;
+    let ignore_keys = ["key_file", "cert_file"];
+    let figment = Figment::from(Serialized::defaults(
+        ConfigurationBuilder::default().private_build().unwrap(),
+    ))
+    .merge(FileAdapter::wrap(Toml::file(config_file)).ignore(&ignore_keys))
+    .merge(FileAdapter::wrap(Env::prefixed("LLDAP_").split("__")).ignore(&ignore_keys));
+    // Other name of ldap_flatten_nested_groups. Not a serde alias: the defaults always have the
+    // main key, and serde rejects the duplicate field.
+    let figment = match figment.find_value("enable_nested_groups") {
+        Ok(value) => figment.merge(Serialized::default("ldap_flatten_nested_groups", value)),
+        Err(_) => figment,
+    };
+    Ok(figment.extract()?)
+}
+
 pub fn init<C>(overrides: C) -> Result<Configuration>
 where
     C: TopLevelCommandOpts + ConfigOverrider,
@@ -324,14 +986,7 @@ where
         overrides.general_config().config_file
     );
 
-    use figment_file_provider_adapter::FileAdapter;
-    let ignore_keys = ["key_file", "cert_file"];
-    let mut config: Configuration = Figment::from(Serialized::defaults(
-        ConfigurationBuilder::default().private_build().unwrap(),
-    ))
-    .merge(FileAdapter::wrap(Toml::file(config_file)).ignore(&ignore_keys))
-    .merge(FileAdapter::wrap(Env::prefixed("LLDAP_").split("__")).ignore(&ignore_keys))
-    .extract()?;
+    let mut config = load_config(&config_file)?;
 
     overrides.override_config(&mut config);
     if config.verbose {
@@ -345,6 +1000,17 @@ where
             .map(SecUtf8::unsecure)
             .unwrap_or_default(),
     )?);
+    config.password_policy.load_denylist()?;
+    UserId::set_normalization(config.user_id_normalization);
+    // The admin id was parsed before the normalization was known.
+    config.ldap_user_dn = UserId::new(config.ldap_user_dn.as_str());
+    check_ldap_organizational_units(&config)?;
+    config
+        .ldap_password_replication
+        .argon2_params()
+        .map_err(|e| {
+            anyhow::anyhow!("Invalid ldap_password_replication argon2 parameters: {}", e)
+        })?;
     if config.jwt_secret == SecUtf8::from("secretjwtsecret") {
         println!("WARNING: Default JWT secret used! This is highly unsafe and can allow attackers to log in as admin.");
     }
@@ -357,6 +1023,21 @@ where
     Ok(config)
 }
 
+fn check_ldap_organizational_units(config: &Configuration) -> Result<()> {
+    for ou in [&config.ldap_people_ou, &config.ldap_groups_ou] {
+        if ou.is_empty() || ou.contains([',', '=']) {
+            anyhow::bail!("Invalid organizational unit name: {:?}", ou);
+        }
+    }
+    if config
+        .ldap_people_ou
+        .eq_ignore_ascii_case(&config.ldap_groups_ou)
+    {
+        anyhow::bail!("ldap_people_ou and ldap_groups_ou must be different");
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -381,4 +1062,77 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn check_password_policy() {
+        let mut policy = PasswordPolicyOptionsBuilder::default()
+            .min_length(8)
+            .require_uppercase(true)
+            .require_digit(true)
+            .require_special(true)
+            .build()
+            .unwrap();
+        policy.denylist =
+            PasswordDenylist(std::sync::Arc::new(HashSet::from(["passw0rd!".to_owned()])));
+        policy.check("C0rrect-horse").unwrap();
+        assert_eq!(
+            policy.check("pass"),
+            Err(PasswordPolicyError(vec![
+                "at least 8 characters".to_owned(),
+                "an uppercase letter".to_owned(),
+                "a digit".to_owned(),
+                "a special character".to_owned(),
+            ]))
+        );
+        assert_eq!(
+            policy.check("PASSW0RD!"),
+            Err(PasswordPolicyError(
+                vec!["not a common password".to_owned()]
+            ))
+        );
+        // No rules by default.
+        PasswordPolicyOptions::default().check("a").unwrap();
+    }
+
+    #[test]
+    fn load_config_enable_nested_groups() {
+        figment::Jail::expect_with(|jail| {
+            jail.create_file("lldap_config.toml", "")?;
+            assert!(
+                !load_config("lldap_config.toml")
+                    .unwrap()
+                    .ldap_flatten_nested_groups
+            );
+            jail.create_file("lldap_config.toml", "enable_nested_groups = true")?;
+            assert!(
+                load_config("lldap_config.toml")
+                    .unwrap()
+                    .ldap_flatten_nested_groups
+            );
+            jail.create_file("lldap_config.toml", "")?;
+            jail.set_env("LLDAP_ENABLE_NESTED_GROUPS", "true");
+            assert!(
+                load_config("lldap_config.toml")
+                    .unwrap()
+                    .ldap_flatten_nested_groups
+            );
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn check_organizational_units() {
+        check_ldap_organizational_units(&Configuration::default()).unwrap();
+        let config = ConfigurationBuilder::default()
+            .ldap_people_ou("users".to_owned())
+            .ldap_groups_ou("Users".to_owned())
+            .build()
+            .unwrap();
+        check_ldap_organizational_units(&config).unwrap_err();
+        let config = ConfigurationBuilder::default()
+            .ldap_people_ou("ou=users".to_owned())
+            .build()
+            .unwrap();
+        check_ldap_organizational_units(&config).unwrap_err();
+    }
 }
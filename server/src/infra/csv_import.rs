@@ -0,0 +1,543 @@
+//! Creation of users from a CSV file, e.g. exported by an HR system.
+
+use crate::{
+    domain::{
+        handler::{
+            BackendHandler, CreateUserRequest, ImportUserRequest, LoginHandler, Schema,
+            UpdateUserRequest,
+        },
+        imported_password::check_imported_password_hash,
+        ldap::utils::convert_custom_attribute_values,
+        types::{AttributeValue, GroupId, UserId},
+    },
+    infra::{configuration::Configuration, mail, tcp_backend_handler::TcpBackendHandler},
+};
+use anyhow::{anyhow, bail, Context, Result};
+use csv::StringRecord;
+use std::collections::HashMap;
+use tracing::{info, warn};
+
+/// Separator of the values in a cell, for the groups and the multi-valued attributes.
+const VALUE_SEPARATOR: char = ';';
+
+/// The user field that a CSV column maps to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CsvField {
+    UserId,
+    Email,
+    DisplayName,
+    FirstName,
+    LastName,
+    /// The names of the groups to add the user to.
+    Groups,
+    /// A hash of the password, e.g. bcrypt or "{SSHA}...", replaced on the first LDAP bind.
+    PasswordHash,
+    Attribute(String),
+}
+
+impl From<&str> for CsvField {
+    fn from(field: &str) -> Self {
+        match field.to_ascii_lowercase().as_str() {
+            "uid" | "user_id" | "id" => CsvField::UserId,
+            "email" | "mail" => CsvField::Email,
+            "display_name" | "displayname" | "cn" => CsvField::DisplayName,
+            "first_name" | "firstname" | "givenname" => CsvField::FirstName,
+            "last_name" | "lastname" | "sn" => CsvField::LastName,
+            "group" | "groups" => CsvField::Groups,
+            "password_hash" | "userpassword" | "bcrypt" => CsvField::PasswordHash,
+            _ => CsvField::Attribute(field.to_owned()),
+        }
+    }
+}
+
+/// Parses the "column=field" mappings given on the command line.
+pub fn parse_mapping(mapping: &[String]) -> Result<HashMap<String, CsvField>> {
+    mapping
+        .iter()
+        .map(|m| {
+            let (column, field) = m
+                .split_once('=')
+                .ok_or_else(|| anyhow!(r#"Invalid mapping "{}", expected "column=field""#, m))?;
+            Ok((column.trim().to_owned(), CsvField::from(field.trim())))
+        })
+        .collect()
+}
+
+/// A row that couldn't be imported.
+#[derive(Debug)]
+pub struct RowError {
+    pub line: u64,
+    pub record: StringRecord,
+    pub message: String,
+}
+
+#[derive(Debug)]
+pub struct CreatedUser {
+    pub user_id: UserId,
+    pub email: String,
+    pub display_name: Option<String>,
+    /// Whether a password hash was imported.
+    pub has_password: bool,
+}
+
+#[derive(Debug)]
+pub struct CsvImportResult {
+    pub headers: StringRecord,
+    pub created_users: Vec<CreatedUser>,
+    pub errors: Vec<RowError>,
+}
+
+/// Returns the field of each column, `None` for the ignored columns. The columns that aren't in
+/// the mapping are used if their name is the name of a field.
+fn get_column_fields(
+    headers: &StringRecord,
+    mapping: &HashMap<String, CsvField>,
+    schema: &Schema,
+) -> Result<Vec<Option<CsvField>>> {
+    if let Some(column) = mapping
+        .keys()
+        .find(|c| !headers.iter().any(|h| h == c.as_str()))
+    {
+        bail!(r#"Mapped column "{}" is not in the CSV header"#, column);
+    }
+    let fields = headers
+        .iter()
+        .map(|header| {
+            let field = mapping
+                .get(header)
+                .cloned()
+                .unwrap_or_else(|| CsvField::from(header));
+            match &field {
+                CsvField::Attribute(name)
+                    if schema.user_attributes.get_attribute_type(name).is_none() =>
+                {
+                    if mapping.contains_key(header) {
+                        bail!(
+                            r#"Column "{}" is mapped to unknown attribute "{}""#,
+                            header,
+                            name
+                        );
+                    }
+                    warn!(r#"Ignoring column "{}""#, header);
+                    Ok(None)
+                }
+                _ => Ok(Some(field)),
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+    for required in [CsvField::UserId, CsvField::Email] {
+        if !fields.contains(&Some(required.clone())) {
+            bail!("No column is mapped to {:?}", required);
+        }
+    }
+    Ok(fields)
+}
+
+/// What is set after creating the user.
+#[derive(Debug, Default)]
+struct ImportedFields {
+    attributes: Vec<AttributeValue>,
+    password_hash: Option<String>,
+}
+
+/// Validates a row, and converts it to the request to create the user, with the custom
+/// attributes and password hash to set afterwards.
+fn parse_row(
+    record: &StringRecord,
+    fields: &[Option<CsvField>],
+    schema: &Schema,
+    groups: &HashMap<String, GroupId>,
+) -> std::result::Result<(ImportUserRequest, ImportedFields), String> {
+    let mut request = ImportUserRequest::default();
+    let mut imported_fields = ImportedFields::default();
+    let split_values = |value: &str| {
+        value
+            .split(VALUE_SEPARATOR)
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(str::to_owned)
+            .collect::<Vec<_>>()
+    };
+    for (field, value) in fields.iter().zip(record.iter()) {
+        let value = value.trim();
+        let optional = || (!value.is_empty()).then(|| value.to_owned());
+        match field {
+            None => (),
+            Some(CsvField::UserId) => request.user.user_id = UserId::new(value),
+            Some(CsvField::Email) => request.user.email = value.to_owned(),
+            Some(CsvField::DisplayName) => request.user.display_name = optional(),
+            Some(CsvField::FirstName) => request.user.first_name = optional(),
+            Some(CsvField::LastName) => request.user.last_name = optional(),
+            Some(CsvField::Groups) => {
+                for name in split_values(value) {
+                    request.group_ids.push(
+                        *groups
+                            .get(&name)
+                            .ok_or_else(|| format!(r#"Unknown group "{}""#, name))?,
+                    );
+                }
+            }
+            Some(CsvField::PasswordHash) if value.is_empty() => (),
+            Some(CsvField::PasswordHash) => {
+                check_imported_password_hash(value)?;
+                imported_fields.password_hash = Some(value.to_owned());
+            }
+            Some(CsvField::Attribute(name)) => {
+                let values = match schema.user_attributes.get_attribute_type(name) {
+                    Some((_, true)) => split_values(value),
+                    _ => optional().into_iter().collect(),
+                };
+                if !values.is_empty() {
+                    let value = convert_custom_attribute_values(
+                        name,
+                        values.into_iter().map(String::into_bytes).collect(),
+                        schema,
+                    )
+                    .map_err(|e| e.message)?;
+                    imported_fields.attributes.push(AttributeValue {
+                        name: name.clone(),
+                        value,
+                    });
+                }
+            }
+        }
+    }
+    if request.user.user_id.as_str().is_empty() {
+        return Err("Missing user id".to_owned());
+    }
+    if request.user.email.parse::<lettre::Address>().is_err() {
+        return Err(format!(r#"Invalid email "{}""#, request.user.email));
+    }
+    Ok((request, imported_fields))
+}
+
+/// Sets the custom attributes and the password hash of a created user.
+async fn set_imported_fields<Backend: BackendHandler + LoginHandler>(
+    backend: &Backend,
+    user_id: &UserId,
+    imported_fields: ImportedFields,
+) -> std::result::Result<(), String> {
+    if !imported_fields.attributes.is_empty() {
+        backend
+            .update_user(UpdateUserRequest {
+                user_id: user_id.clone(),
+                insert_attributes: imported_fields.attributes,
+                ..Default::default()
+            })
+            .await
+            .map_err(|e| format!("User created, but the attributes were not set: {:#}", e))?;
+    }
+    if let Some(password_hash) = imported_fields.password_hash {
+        backend
+            .import_password_hash(user_id, &password_hash)
+            .await
+            .map_err(|e| format!("User created, but the password was not set: {:#}", e))?;
+    }
+    Ok(())
+}
+
+/// Creates the users of the CSV file. The rows with errors are skipped, and returned with the
+/// error; the other rows are still imported.
+pub async fn import_csv<Backend: BackendHandler + LoginHandler>(
+    backend: &Backend,
+    input: impl std::io::Read,
+    mapping: &HashMap<String, CsvField>,
+) -> Result<CsvImportResult> {
+    let schema = backend
+        .get_schema()
+        .await
+        .context("while reading the schema")?;
+    let groups = backend
+        .list_groups(None)
+        .await
+        .context("while listing the groups")?
+        .into_iter()
+        .map(|g| (g.display_name, g.id))
+        .collect::<HashMap<_, _>>();
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(input);
+    let headers = reader
+        .headers()
+        .context("while reading the header")?
+        .clone();
+    let fields = get_column_fields(&headers, mapping, &schema)?;
+    let mut errors = Vec::new();
+    // The valid rows, with their custom attributes and password hash.
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                errors.push(RowError {
+                    line: e.position().map(|p| p.line()).unwrap_or_default(),
+                    record: StringRecord::new(),
+                    message: e.to_string(),
+                });
+                continue;
+            }
+        };
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        match parse_row(&record, &fields, &schema, &groups) {
+            Ok((request, imported_fields)) => rows.push((record, request, imported_fields)),
+            Err(message) => errors.push(RowError {
+                line,
+                record,
+                message,
+            }),
+        }
+    }
+    let results = backend
+        .import_users(rows.iter().map(|(_, r, _)| r.clone()).collect(), false)
+        .await
+        .context("while creating the users")?;
+    let mut created_users = Vec::new();
+    for ((record, request, imported_fields), result) in rows.into_iter().zip(results) {
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        let CreateUserRequest {
+            user_id,
+            email,
+            display_name,
+            ..
+        } = request.user;
+        let has_password = imported_fields.password_hash.is_some();
+        let result = match result {
+            Ok(()) => set_imported_fields(backend, &user_id, imported_fields).await,
+            Err(e) => Err(e.to_string()),
+        };
+        match result {
+            Ok(()) => created_users.push(CreatedUser {
+                user_id,
+                email,
+                display_name,
+                has_password,
+            }),
+            Err(message) => errors.push(RowError {
+                line,
+                record,
+                message,
+            }),
+        }
+    }
+    errors.sort_by_key(|e| e.line);
+    Ok(CsvImportResult {
+        headers,
+        created_users,
+        errors,
+    })
+}
+
+/// Writes the rows that couldn't be imported, as they were, with their line and the error.
+pub fn write_errors(result: &CsvImportResult, output: impl std::io::Write) -> Result<()> {
+    let mut writer = csv::WriterBuilder::new().flexible(true).from_writer(output);
+    let mut headers = result.headers.clone();
+    headers.push_field("line");
+    headers.push_field("error");
+    writer.write_record(&headers)?;
+    for error in &result.errors {
+        // The row as it was, padded so that the line and error are in their columns.
+        let mut record = error.record.clone();
+        while record.len() < result.headers.len() {
+            record.push_field("");
+        }
+        record.truncate(result.headers.len());
+        record.push_field(&error.line.to_string());
+        record.push_field(&error.message);
+        writer.write_record(&record)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Sends a password reset email to each of the created users without an imported password hash,
+/// so that they can set their password.
+pub async fn send_password_reset_emails<Backend: TcpBackendHandler>(
+    backend: &Backend,
+    users: &[CreatedUser],
+    config: &Configuration,
+) -> Result<()> {
+    if !config.smtp_options.enable_password_reset {
+        bail!("Password reset is not enabled in the SMTP options");
+    }
+    let templates = mail::EmailTemplates::load(config.smtp_options.templates_dir.as_deref())
+        .context("while loading the email templates")?;
+    for user in users.iter().filter(|u| !u.has_password) {
+        let result = async {
+            let token = backend
+                .start_password_reset(&user.user_id)
+                .await?
+                .ok_or_else(|| anyhow!("User not found"))?;
+            mail::send_password_reset_email(
+                user.display_name
+                    .as_deref()
+                    .unwrap_or_else(|| user.user_id.as_str()),
+                &user.email,
+                &token,
+                &config.http_url,
+                &config.smtp_options,
+                &templates,
+            )
+            .await
+        }
+        .await;
+        match result {
+            Ok(()) => info!("Password reset email sent to {}", user.user_id),
+            Err(e) => warn!(
+                "Could not send the password reset email to {}: {:#}",
+                user.user_id, e
+            ),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        handler::{GroupBackendHandler, UserBackendHandler, UserListerBackendHandler},
+        sql_backend_handler::{tests::*, SqlBackendHandler},
+    };
+
+    async fn get_handler() -> SqlBackendHandler {
+        let handler = SqlBackendHandler::new(get_default_config(), get_initialized_db().await);
+        insert_user_no_password(&handler, "bob").await;
+        handler.create_group("Staff").await.unwrap();
+        handler
+    }
+
+    const CSV: &str = "\
+Login,Mail,Name,Department,Office
+jane,jane@example.com,Jane Doe,Staff,Paris
+bob,bob@example.com,Bob,,
+john,not an email,John,,
+mary,mary@example.com,Mary,Unknown,
+";
+
+    fn mapping() -> HashMap<String, CsvField> {
+        parse_mapping(&[
+            "Login=uid".to_owned(),
+            "Mail=email".to_owned(),
+            "Name=display_name".to_owned(),
+            "Department=groups".to_owned(),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse_mapping() {
+        assert_eq!(
+            parse_mapping(&["Login=uid".to_owned(), "Phone = phone".to_owned()]).unwrap(),
+            HashMap::from([
+                ("Login".to_owned(), CsvField::UserId),
+                ("Phone".to_owned(), CsvField::Attribute("phone".to_owned())),
+            ])
+        );
+        parse_mapping(&["Login".to_owned()]).unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn test_import_csv() {
+        let handler = get_handler().await;
+
+        let result = import_csv(&handler, CSV.as_bytes(), &mapping())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            result
+                .created_users
+                .iter()
+                .map(|u| u.user_id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["jane"]
+        );
+        assert_eq!(
+            result
+                .errors
+                .iter()
+                .map(|e| (e.line, e.message.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                (3, "Entity already exists: `bob`"),
+                (4, r#"Invalid email "not an email""#),
+                (5, r#"Unknown group "Unknown""#),
+            ]
+        );
+        let jane = handler
+            .get_user_details(&UserId::new("jane"))
+            .await
+            .unwrap();
+        assert_eq!(jane.display_name.as_deref(), Some("Jane Doe"));
+        assert_eq!(
+            handler
+                .get_user_groups(&UserId::new("jane"))
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|g| g.display_name)
+                .collect::<Vec<_>>(),
+            vec!["Staff"]
+        );
+        assert_eq!(handler.list_users(None, false).await.unwrap().len(), 2);
+
+        let mut output = Vec::new();
+        write_errors(&result, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\
+Login,Mail,Name,Department,Office,line,error
+bob,bob@example.com,Bob,,,3,Entity already exists: `bob`
+john,not an email,John,,,4,\"Invalid email \"\"not an email\"\"\"
+mary,mary@example.com,Mary,Unknown,,5,\"Unknown group \"\"Unknown\"\"\"
+"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_import_csv_password_hash() {
+        let handler = get_handler().await;
+        let csv = format!(
+            "uid,email,password_hash\njane,jane@example.com,{}\njohn,john@example.com,{{SSHA}}abcdef\nmary,mary@example.com,{{MD5}}X03MO1qnZdYdgyfeuILPmQ==\n",
+            bcrypt::hash("jane00", 4).unwrap()
+        );
+
+        let result = import_csv(&handler, csv.as_bytes(), &HashMap::new())
+            .await
+            .unwrap();
+
+        assert_eq!(result.created_users.len(), 1);
+        assert!(result.created_users[0].has_password);
+        assert_eq!(
+            result
+                .errors
+                .iter()
+                .map(|e| (e.line, e.message.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                (3, "Invalid password hash"),
+                (
+                    4,
+                    "Unsupported password scheme `{MD5}`, expected {SSHA}, {SHA}, {CRYPT} or a \
+                     bcrypt hash"
+                )
+            ]
+        );
+        handler
+            .bind(crate::domain::handler::BindRequest {
+                name: UserId::new("jane"),
+                password: "jane00".to_owned(),
+            })
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_import_csv_unknown_attribute() {
+        let handler = get_handler().await;
+        let mut mapping = mapping();
+        mapping.insert("Office".to_owned(), CsvField::from("office"));
+
+        import_csv(&handler, CSV.as_bytes(), &mapping)
+            .await
+            .unwrap_err();
+    }
+}
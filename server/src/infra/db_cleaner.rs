@@ -1,5 +1,7 @@
 use crate::domain::{
-    model::{self, JwtRefreshStorageColumn, JwtStorageColumn, PasswordResetTokensColumn},
+    model::{
+        self, JwtRefreshStorageColumn, JwtStorageColumn, PasswordResetTokensColumn, UserColumn,
+    },
     sql_tables::DbConnection,
 };
 use actix::prelude::{Actor, AsyncContext, Context};
@@ -12,6 +14,8 @@ use tracing::{error, info, instrument};
 pub struct Scheduler {
     schedule: Schedule,
     sql_pool: DbConnection,
+    /// How long the soft-deleted users are kept, 0 if users are not soft-deleted.
+    deleted_users_retention_days: u32,
 }
 
 // Provide Actor implementation for our actor
@@ -32,13 +36,24 @@ impl Actor for Scheduler {
 }
 
 impl Scheduler {
-    pub fn new(cron_expression: &str, sql_pool: DbConnection) -> Self {
+    pub fn new(
+        cron_expression: &str,
+        sql_pool: DbConnection,
+        deleted_users_retention_days: u32,
+    ) -> Self {
         let schedule = Schedule::from_str(cron_expression).unwrap();
-        Self { schedule, sql_pool }
+        Self {
+            schedule,
+            sql_pool,
+            deleted_users_retention_days,
+        }
     }
 
     fn schedule_task(&self, ctx: &mut Context<Self>) {
-        let future = actix::fut::wrap_future::<_, Self>(Self::cleanup_db(self.sql_pool.clone()));
+        let future = actix::fut::wrap_future::<_, Self>(Self::cleanup_db(
+            self.sql_pool.clone(),
+            self.deleted_users_retention_days,
+        ));
         ctx.spawn(future);
 
         ctx.run_later(self.duration_until_next(), move |this, ctx| {
@@ -47,7 +62,7 @@ impl Scheduler {
     }
 
     #[instrument(skip_all)]
-    async fn cleanup_db(sql_pool: DbConnection) {
+    async fn cleanup_db(sql_pool: DbConnection, deleted_users_retention_days: u32) {
         info!("Cleaning DB");
         if let Err(e) = model::JwtRefreshStorage::delete_many()
             .filter(JwtRefreshStorageColumn::ExpiryDate.lt(chrono::Utc::now().naive_utc()))
@@ -70,6 +85,17 @@ impl Scheduler {
         {
             error!("DB error while cleaning up password reset tokens: {}", e);
         };
+        if deleted_users_retention_days > 0 {
+            let deleted_before = chrono::Utc::now().naive_utc()
+                - chrono::Duration::days(deleted_users_retention_days as i64);
+            if let Err(e) = model::User::delete_many()
+                .filter(UserColumn::DeletedDate.lt(deleted_before))
+                .exec(&sql_pool)
+                .await
+            {
+                error!("DB error while purging the deleted users: {}", e);
+            };
+        }
         info!("DB cleaned!");
     }
 
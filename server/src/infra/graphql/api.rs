@@ -1,14 +1,18 @@
 use crate::{
-    domain::{handler::BackendHandler, types::UserId},
+    domain::{
+        handler::BackendHandler, passkey_handler::PasskeyHandler, session_handler::SessionHandler,
+        totp_handler::TotpHandler, types::UserId,
+    },
     infra::{
         access_control::{
             AccessControlledBackendHandler, AdminBackendHandler, ReadonlyBackendHandler,
             UserReadableBackendHandler, UserWriteableBackendHandler, ValidationResults,
         },
-        auth_service::check_if_token_is_valid,
+        auth_service::check_if_bearer_token_is_valid,
         cli::ExportGraphQLSchemaOpts,
-        graphql::{mutation::Mutation, query::Query},
+        graphql::{mutation::Mutation, query::Query, subscription::Subscription},
         tcp_server::AppState,
+        webhooks::WebhookNotifier,
     },
 };
 use actix_web::FromRequest;
@@ -20,13 +24,18 @@ use juniper::{
         graphiql::graphiql_source, playground::playground_source, GraphQLBatchRequest,
         GraphQLRequest,
     },
-    EmptySubscription, FieldError, RootNode, ScalarValue,
+    DefaultScalarValue, FieldError, RootNode, ScalarValue,
 };
+use std::sync::Arc;
 use tracing::debug;
 
 pub struct Context<Handler: BackendHandler> {
     pub handler: AccessControlledBackendHandler<Handler>,
     pub validation_result: ValidationResults,
+    /// Source of the subscriptions.
+    pub events: WebhookNotifier,
+    /// What the users can change with `updateMyProfile`.
+    pub self_service_attributes: Vec<String>,
 }
 
 pub fn field_error_callback<'a>(
@@ -45,10 +54,13 @@ impl<Handler: BackendHandler> Context<Handler> {
         Self {
             handler: AccessControlledBackendHandler::new(handler),
             validation_result,
+            events: WebhookNotifier::start(Vec::new()),
+            self_service_attributes: crate::infra::configuration::Configuration::default()
+                .self_service_attributes,
         }
     }
 
-    pub fn get_admin_handler(&self) -> Option<&impl AdminBackendHandler> {
+    pub fn get_admin_handler(&self) -> Option<impl AdminBackendHandler + '_> {
         self.handler.get_admin_handler(&self.validation_result)
     }
 
@@ -59,7 +71,7 @@ impl<Handler: BackendHandler> Context<Handler> {
     pub fn get_writeable_handler(
         &self,
         user_id: &UserId,
-    ) -> Option<&impl UserWriteableBackendHandler> {
+    ) -> Option<impl UserWriteableBackendHandler + '_> {
         self.handler
             .get_writeable_handler(&self.validation_result, user_id)
     }
@@ -71,18 +83,32 @@ impl<Handler: BackendHandler> Context<Handler> {
         self.handler
             .get_readable_handler(&self.validation_result, user_id)
     }
+
+    pub fn get_totp_handler(&self, user_id: &UserId) -> Option<&impl TotpHandler> {
+        self.handler
+            .get_totp_handler(&self.validation_result, user_id)
+    }
+
+    pub fn get_passkey_handler(&self, user_id: &UserId) -> Option<&impl PasskeyHandler> {
+        self.handler
+            .get_passkey_handler(&self.validation_result, user_id)
+    }
+
+    pub fn get_session_handler(&self, user_id: &UserId) -> Option<&impl SessionHandler> {
+        self.handler
+            .get_session_handler(&self.validation_result, user_id)
+    }
 }
 
 impl<Handler: BackendHandler> juniper::Context for Context<Handler> {}
 
-type Schema<Handler> =
-    RootNode<'static, Query<Handler>, Mutation<Handler>, EmptySubscription<Context<Handler>>>;
+type Schema<Handler> = RootNode<'static, Query<Handler>, Mutation<Handler>, Subscription<Handler>>;
 
 fn schema<Handler: BackendHandler>() -> Schema<Handler> {
     Schema::new(
         Query::<Handler>::new(),
         Mutation::<Handler>::new(),
-        EmptySubscription::<Context<Handler>>::new(),
+        Subscription::<Handler>::new(),
     )
 }
 
@@ -218,10 +244,12 @@ async fn graphql_route<Handler: BackendHandler + Clone>(
 ) -> Result<HttpResponse, Error> {
     let mut inner_payload = payload.into_inner();
     let bearer = BearerAuth::from_request(&req, &mut inner_payload).await?;
-    let validation_result = check_if_token_is_valid(&data, bearer.token())?;
+    let validation_result = check_if_bearer_token_is_valid(&data, bearer.token()).await?;
     let context = Context::<Handler> {
         handler: data.backend_handler.clone(),
         validation_result,
+        events: data.events.clone(),
+        self_service_attributes: data.self_service_attributes.clone(),
     };
     let schema = &schema();
     let context = &context;
@@ -232,6 +260,90 @@ async fn graphql_route<Handler: BackendHandler + Clone>(
     }
 }
 
+/// GraphQL subscriptions over WebSocket, with the "graphql-ws" protocol. The client is
+/// authenticated when connecting, like for the other requests.
+async fn graphql_ws_route<Handler: BackendHandler + Clone>(
+    req: HttpRequest,
+    payload: web::Payload,
+    data: web::Data<AppState<Handler>>,
+) -> Result<HttpResponse, Error> {
+    let bearer = BearerAuth::extract(&req).await?;
+    let validation_result = check_if_bearer_token_is_valid(&data, bearer.token()).await?;
+    let context = Context::<Handler> {
+        handler: data.backend_handler.clone(),
+        validation_result,
+        events: data.events.clone(),
+        self_service_attributes: data.self_service_attributes.clone(),
+    };
+    let (mut response, session, messages) = actix_ws::handle(&req, payload)?;
+    response.headers_mut().insert(
+        actix_http::header::SEC_WEBSOCKET_PROTOCOL,
+        actix_http::header::HeaderValue::from_static("graphql-ws"),
+    );
+    actix_rt::spawn(serve_subscriptions(
+        Arc::new(schema()),
+        context,
+        session,
+        messages,
+    ));
+    Ok(response)
+}
+
+/// Forwards the messages between the WebSocket and the subscriptions, until either side closes.
+async fn serve_subscriptions<Handler: BackendHandler + Clone>(
+    schema: Arc<Schema<Handler>>,
+    context: Context<Handler>,
+    mut session: actix_ws::Session,
+    mut messages: actix_ws::MessageStream,
+) {
+    use futures::{SinkExt, StreamExt};
+    use juniper_graphql_ws::{ClientMessage, Connection, ConnectionConfig};
+    let (mut to_connection, mut from_connection) =
+        Connection::new(schema, ConnectionConfig::new(context)).split();
+    loop {
+        tokio::select! {
+            message = messages.next() => match message {
+                Some(Ok(actix_ws::Message::Text(text))) => {
+                    match serde_json::from_str::<ClientMessage<DefaultScalarValue>>(&text) {
+                        Ok(message) => {
+                            if to_connection.send(message).await.is_err() {
+                                break;
+                            }
+                        }
+                        Err(e) => {
+                            debug!("Invalid subscription message: {}", e);
+                            break;
+                        }
+                    }
+                }
+                Some(Ok(actix_ws::Message::Ping(bytes))) => {
+                    if session.pong(&bytes).await.is_err() {
+                        break;
+                    }
+                }
+                Some(Ok(actix_ws::Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+            message = from_connection.next() => match message {
+                Some(message) => {
+                    let text = match serde_json::to_string(&message) {
+                        Ok(text) => text,
+                        Err(e) => {
+                            debug!("Could not serialize subscription message: {}", e);
+                            break;
+                        }
+                    };
+                    if session.text(text).await.is_err() {
+                        break;
+                    }
+                }
+                None => break,
+            },
+        }
+    }
+    let _ = session.close(None).await;
+}
+
 pub fn configure_endpoint<Backend>(cfg: &mut web::ServiceConfig)
 where
     Backend: BackendHandler + Clone + 'static,
@@ -255,6 +367,7 @@ where
             .route(web::post().to(graphql_route::<Backend>))
             .route(web::get().to(graphql_route::<Backend>)),
     );
+    cfg.service(web::resource("/graphql/ws").route(web::get().to(graphql_ws_route::<Backend>)));
     cfg.service(web::resource("/graphql/playground").route(web::get().to(playground_route)));
     cfg.service(web::resource("/graphql/graphiql").route(web::get().to(graphiql_route)));
 }
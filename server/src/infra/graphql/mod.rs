@@ -1,3 +1,4 @@
 pub mod api;
 pub mod mutation;
 pub mod query;
+pub mod subscription;
@@ -1,14 +1,25 @@
 use crate::{
     domain::{
-        handler::{BackendHandler, CreateUserRequest, UpdateGroupRequest, UpdateUserRequest},
-        types::{GroupId, JpegPhoto, UserId},
+        handler::{
+            AttributeList, AttributeSchema, BackendHandler, CreateUserRequest, ImportUserRequest,
+            SchemaBackendHandler, UpdateGroupRequest, UpdateUserRequest,
+        },
+        ldap::utils::{convert_attribute_values, decode_jpeg_photo},
+        passkey_handler::PasskeyHandler,
+        session_handler::SessionHandler,
+        totp,
+        totp_handler::TotpHandler,
+        types::{ApiTokenScope, AttributeType, AttributeValue, GroupId, JpegPhoto, UserId},
     },
     infra::{
         access_control::{
             AdminBackendHandler, ReadonlyBackendHandler, UserReadableBackendHandler,
             UserWriteableBackendHandler,
         },
-        graphql::api::field_error_callback,
+        graphql::{
+            api::field_error_callback,
+            query::{convert_request_filter, Passkey, RequestFilter},
+        },
     },
 };
 use anyhow::Context as AnyhowContext;
@@ -44,6 +55,42 @@ pub struct CreateUserInput {
     avatar: Option<String>,
 }
 
+#[derive(PartialEq, Eq, Debug, GraphQLInputObject)]
+/// A user to create with `importUsers`, with its groups.
+pub struct ImportUserInput {
+    id: String,
+    email: String,
+    display_name: Option<String>,
+    first_name: Option<String>,
+    last_name: Option<String>,
+    // Base64 encoded JpegPhoto.
+    avatar: Option<String>,
+    /// The ids of the groups to add the user to.
+    group_ids: Option<Vec<i32>>,
+}
+
+impl TryFrom<ImportUserInput> for ImportUserRequest {
+    type Error = anyhow::Error;
+    fn try_from(user: ImportUserInput) -> anyhow::Result<Self> {
+        Ok(Self {
+            user: CreateUserRequest {
+                user_id: UserId::new(&user.id),
+                email: user.email,
+                display_name: user.display_name,
+                first_name: user.first_name,
+                last_name: user.last_name,
+                avatar: decode_avatar(user.avatar)?,
+            },
+            group_ids: user
+                .group_ids
+                .unwrap_or_default()
+                .into_iter()
+                .map(GroupId)
+                .collect(),
+        })
+    }
+}
+
 #[derive(PartialEq, Eq, Debug, GraphQLInputObject)]
 /// The fields that can be updated for a user.
 pub struct UpdateUserInput {
@@ -54,6 +101,48 @@ pub struct UpdateUserInput {
     last_name: Option<String>,
     // Base64 encoded JpegPhoto.
     avatar: Option<String>,
+    /// Custom attributes to set, replacing their previous values.
+    insert_attributes: Option<Vec<AttributeValueInput>>,
+    /// Names of the custom attributes to remove.
+    remove_attributes: Option<Vec<String>>,
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLInputObject)]
+/// The fields of their own profile that the users can change, if allowed by the configuration.
+pub struct UpdateMyProfileInput {
+    email: Option<String>,
+    display_name: Option<String>,
+    first_name: Option<String>,
+    last_name: Option<String>,
+    // Base64 encoded JpegPhoto.
+    avatar: Option<String>,
+    /// Custom attributes to set, replacing their previous values.
+    insert_attributes: Option<Vec<AttributeValueInput>>,
+    /// Names of the custom attributes to remove.
+    remove_attributes: Option<Vec<String>>,
+}
+
+impl UpdateMyProfileInput {
+    fn into_update_user_input(self, user_id: &UserId) -> UpdateUserInput {
+        UpdateUserInput {
+            id: user_id.to_string(),
+            email: self.email,
+            display_name: self.display_name,
+            first_name: self.first_name,
+            last_name: self.last_name,
+            avatar: self.avatar,
+            insert_attributes: self.insert_attributes,
+            remove_attributes: self.remove_attributes,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLInputObject)]
+/// The values of a custom attribute: a single one for the single-valued attributes. The photos are
+/// base64 encoded.
+pub struct AttributeValueInput {
+    name: String,
+    value: Vec<String>,
 }
 
 #[derive(PartialEq, Eq, Debug, GraphQLInputObject)]
@@ -61,6 +150,10 @@ pub struct UpdateUserInput {
 pub struct UpdateGroupInput {
     id: i32,
     display_name: Option<String>,
+    /// Custom attributes to set, replacing their previous values.
+    insert_attributes: Option<Vec<AttributeValueInput>>,
+    /// Names of the custom attributes to remove.
+    remove_attributes: Option<Vec<String>>,
 }
 
 #[derive(PartialEq, Eq, Debug, GraphQLObject)]
@@ -74,6 +167,161 @@ impl Success {
     }
 }
 
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// The outcome of the import of one user.
+pub struct ImportUserResult {
+    id: String,
+    created: bool,
+    error: Option<String>,
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A new API token. The token itself can't be retrieved later.
+pub struct CreatedApiToken {
+    name: String,
+    token: String,
+}
+
+fn decode_avatar(avatar: Option<String>) -> anyhow::Result<Option<JpegPhoto>> {
+    avatar
+        .map(|bytes| base64::engine::general_purpose::STANDARD.decode(bytes))
+        .transpose()
+        .context("Invalid base64 image")?
+        .map(JpegPhoto::try_from)
+        .transpose()
+        .context("Provided image is not a valid JPEG")
+}
+
+fn get_editable_attribute_schema<'a>(
+    name: &str,
+    attribute_list: &'a AttributeList,
+    user_is_admin: bool,
+) -> anyhow::Result<&'a AttributeSchema> {
+    let attribute_schema = attribute_list
+        .get_attribute_schema(name)
+        .ok_or_else(|| anyhow::anyhow!("Unknown attribute: `{}`", name))?;
+    // The hardcoded attributes have their own fields.
+    if attribute_schema.is_hardcoded || (!user_is_admin && !attribute_schema.is_editable) {
+        anyhow::bail!("Attribute `{}` is not editable", name);
+    }
+    Ok(attribute_schema)
+}
+
+/// Checks that the attributes can be edited, and converts their values to the attribute types.
+fn convert_attribute_inputs(
+    attributes: Vec<AttributeValueInput>,
+    attribute_list: &AttributeList,
+    user_is_admin: bool,
+) -> anyhow::Result<Vec<AttributeValue>> {
+    attributes
+        .into_iter()
+        .map(|attribute| {
+            let attribute_schema =
+                get_editable_attribute_schema(&attribute.name, attribute_list, user_is_admin)?;
+            let values = attribute
+                .value
+                .into_iter()
+                .map(String::into_bytes)
+                .map(|value| match attribute_schema.attribute_type {
+                    AttributeType::JpegPhoto => decode_jpeg_photo(value),
+                    _ => value,
+                })
+                .collect();
+            let value = convert_attribute_values(&attribute.name, values, attribute_list)
+                .map_err(|e| anyhow::anyhow!(e.message))?;
+            Ok(AttributeValue {
+                name: attribute.name,
+                value,
+            })
+        })
+        .collect()
+}
+
+/// Checks that all the fields set in the update are in the allowlist.
+fn check_self_service_fields(user: &UpdateUserInput, allowed: &[String]) -> anyhow::Result<()> {
+    let set_fields = [
+        ("email", user.email.is_some()),
+        ("display_name", user.display_name.is_some()),
+        ("first_name", user.first_name.is_some()),
+        ("last_name", user.last_name.is_some()),
+        ("avatar", user.avatar.is_some()),
+    ]
+    .into_iter()
+    .filter_map(|(name, is_set)| is_set.then_some(name));
+    let attributes = user
+        .insert_attributes
+        .iter()
+        .flatten()
+        .map(|attribute| attribute.name.as_str())
+        .chain(user.remove_attributes.iter().flatten().map(String::as_str));
+    for field in set_fields.chain(attributes) {
+        if !allowed.iter().any(|a| a.eq_ignore_ascii_case(field)) {
+            anyhow::bail!("Field `{}` can't be changed in the profile", field);
+        }
+    }
+    Ok(())
+}
+
+/// Converts the update, once the access to the user has been checked. Unless
+/// `edit_any_attribute`, only the custom attributes editable by the users can be changed.
+async fn make_update_user_request<Handler: BackendHandler>(
+    context: &Context<Handler>,
+    user_id: UserId,
+    user: UpdateUserInput,
+    edit_any_attribute: bool,
+    span: &tracing::Span,
+) -> FieldResult<UpdateUserRequest> {
+    let avatar = decode_avatar(user.avatar)?;
+    let insert_attributes = user.insert_attributes.unwrap_or_default();
+    let delete_attributes = user.remove_attributes.unwrap_or_default();
+    let insert_attributes = if insert_attributes.is_empty() && delete_attributes.is_empty() {
+        Vec::new()
+    } else {
+        let schema = context
+            .handler
+            .get_user_restricted_lister_handler(&context.validation_result)
+            .get_schema()
+            .instrument(span.clone())
+            .await?;
+        for name in &delete_attributes {
+            get_editable_attribute_schema(name, &schema.user_attributes, edit_any_attribute)?;
+        }
+        convert_attribute_inputs(
+            insert_attributes,
+            &schema.user_attributes,
+            edit_any_attribute,
+        )?
+    };
+    Ok(UpdateUserRequest {
+        user_id,
+        email: user.email,
+        display_name: user.display_name,
+        first_name: user.first_name,
+        last_name: user.last_name,
+        avatar,
+        insert_attributes,
+        delete_attributes,
+    })
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// The secret to add to the authenticator app, before confirming the enrollment with a code.
+pub struct TotpEnrollment {
+    /// Base32-encoded secret.
+    secret: String,
+    /// otpauth:// URI, to display as a QR code.
+    uri: String,
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// The challenge of a new passkey, to pass to `navigator.credentials.create`.
+pub struct PasskeyRegistration {
+    /// The JSON creation options.
+    options: String,
+    /// To send back with the new credential to `finishPasskeyRegistration`.
+    server_data: String,
+}
+
 #[graphql_object(context = Context<Handler>)]
 impl<Handler: BackendHandler> Mutation<Handler> {
     async fn create_user(
@@ -88,14 +336,7 @@ impl<Handler: BackendHandler> Mutation<Handler> {
             .get_admin_handler()
             .ok_or_else(field_error_callback(&span, "Unauthorized user creation"))?;
         let user_id = UserId::new(&user.id);
-        let avatar = user
-            .avatar
-            .map(|bytes| base64::engine::general_purpose::STANDARD.decode(bytes))
-            .transpose()
-            .context("Invalid base64 image")?
-            .map(JpegPhoto::try_from)
-            .transpose()
-            .context("Provided image is not a valid JPEG")?;
+        let avatar = decode_avatar(user.avatar)?;
         handler
             .create_user(CreateUserRequest {
                 user_id: user_id.clone(),
@@ -114,6 +355,61 @@ impl<Handler: BackendHandler> Mutation<Handler> {
             .map(Into::into)?)
     }
 
+    /// Creates the users in a single transaction, and reports the outcome for each of them, in
+    /// order. The users that can't be created (e.g. if the id is taken) are skipped, unless
+    /// `atomic` is set: then no user is created if one of them fails.
+    async fn import_users(
+        context: &Context<Handler>,
+        users: Vec<ImportUserInput>,
+        atomic: Option<bool>,
+    ) -> FieldResult<Vec<ImportUserResult>> {
+        let span = debug_span!("[GraphQL mutation] import_users");
+        let atomic = atomic.unwrap_or(false);
+        span.in_scope(|| {
+            debug!(count = users.len(), atomic);
+        });
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(&span, "Unauthorized user creation"))?;
+        let mut results = Vec::with_capacity(users.len());
+        let mut requests = Vec::new();
+        for user in users {
+            let id = user.id.clone();
+            let error = match ImportUserRequest::try_from(user) {
+                Ok(request) => {
+                    requests.push(request);
+                    None
+                }
+                Err(e) => Some(e.to_string()),
+            };
+            results.push(ImportUserResult {
+                id,
+                created: false,
+                error,
+            });
+        }
+        let has_invalid_input = results.iter().any(|r| r.error.is_some());
+        if !(atomic && has_invalid_input) {
+            let mut outcomes = handler
+                .import_users(requests, atomic)
+                .instrument(span)
+                .await?
+                .into_iter();
+            for result in results.iter_mut().filter(|r| r.error.is_none()) {
+                result.error = outcomes
+                    .next()
+                    .expect("One outcome per request")
+                    .err()
+                    .map(|e| e.to_string());
+            }
+        }
+        let rolled_back = atomic && results.iter().any(|r| r.error.is_some());
+        for result in results.iter_mut() {
+            result.created = !rolled_back && result.error.is_none();
+        }
+        Ok(results)
+    }
+
     async fn create_group(
         context: &Context<Handler>,
         name: String,
@@ -145,25 +441,41 @@ impl<Handler: BackendHandler> Mutation<Handler> {
         let handler = context
             .get_writeable_handler(&user_id)
             .ok_or_else(field_error_callback(&span, "Unauthorized user update"))?;
-        let avatar = user
-            .avatar
-            .map(|bytes| base64::engine::general_purpose::STANDARD.decode(bytes))
-            .transpose()
-            .context("Invalid base64 image")?
-            .map(JpegPhoto::try_from)
-            .transpose()
-            .context("Provided image is not a valid JPEG")?;
-        handler
-            .update_user(UpdateUserRequest {
-                user_id,
-                email: user.email,
-                display_name: user.display_name,
-                first_name: user.first_name,
-                last_name: user.last_name,
-                avatar,
-            })
-            .instrument(span)
-            .await?;
+        let request = make_update_user_request(
+            context,
+            user_id,
+            user,
+            context.validation_result.is_admin(),
+            &span,
+        )
+        .await?;
+        handler.update_user(request).instrument(span).await?;
+        Ok(Success::new())
+    }
+
+    /// Lets the users change the fields of their own profile allowed by the
+    /// `self_service_attributes` option.
+    async fn update_my_profile(
+        context: &Context<Handler>,
+        profile: UpdateMyProfileInput,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] update_my_profile");
+        let user_id = context.validation_result.user.clone();
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        if context.validation_result.is_api_token {
+            span.in_scope(|| debug!("API tokens don't have a profile"));
+            return Err("API tokens don't have a profile".into());
+        }
+        let handler = context
+            .get_writeable_handler(&user_id)
+            .ok_or_else(field_error_callback(&span, "Unauthorized profile update"))?;
+        let user = profile.into_update_user_input(&user_id);
+        check_self_service_fields(&user, &context.self_service_attributes)?;
+        // The allowlist takes precedence over the schema.
+        let request = make_update_user_request(context, user_id, user, true, &span).await?;
+        handler.update_user(request).instrument(span).await?;
         Ok(Success::new())
     }
 
@@ -182,10 +494,28 @@ impl<Handler: BackendHandler> Mutation<Handler> {
             span.in_scope(|| debug!("Cannot change admin group details"));
             return Err("Cannot change admin group details".into());
         }
+        let insert_attributes = group.insert_attributes.unwrap_or_default();
+        let delete_attributes = group.remove_attributes.unwrap_or_default();
+        let insert_attributes = if insert_attributes.is_empty() && delete_attributes.is_empty() {
+            Vec::new()
+        } else {
+            let schema = context
+                .handler
+                .get_user_restricted_lister_handler(&context.validation_result)
+                .get_schema()
+                .instrument(span.clone())
+                .await?;
+            for name in &delete_attributes {
+                get_editable_attribute_schema(name, &schema.group_attributes, true)?;
+            }
+            convert_attribute_inputs(insert_attributes, &schema.group_attributes, true)?
+        };
         handler
             .update_group(UpdateGroupRequest {
                 group_id: GroupId(group.id),
                 display_name: group.display_name,
+                insert_attributes,
+                delete_attributes,
             })
             .instrument(span)
             .await?;
@@ -241,6 +571,52 @@ impl<Handler: BackendHandler> Mutation<Handler> {
         Ok(Success::new())
     }
 
+    /// Nests a group in another one. The permissions of the lldap_ groups are only granted to
+    /// their direct members.
+    async fn add_group_to_group(
+        context: &Context<Handler>,
+        member_group_id: i32,
+        group_id: i32,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] add_group_to_group");
+        span.in_scope(|| {
+            debug!(?member_group_id, ?group_id);
+        });
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized group membership modification",
+            ))?;
+        handler
+            .add_group_to_group(GroupId(member_group_id), GroupId(group_id))
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    async fn remove_group_from_group(
+        context: &Context<Handler>,
+        member_group_id: i32,
+        group_id: i32,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] remove_group_from_group");
+        span.in_scope(|| {
+            debug!(?member_group_id, ?group_id);
+        });
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized group membership modification",
+            ))?;
+        handler
+            .remove_group_from_group(GroupId(member_group_id), GroupId(group_id))
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
     async fn delete_user(context: &Context<Handler>, user_id: String) -> FieldResult<Success> {
         let span = debug_span!("[GraphQL mutation] delete_user");
         span.in_scope(|| {
@@ -258,6 +634,69 @@ impl<Handler: BackendHandler> Mutation<Handler> {
         Ok(Success::new())
     }
 
+    /// Restores a user deleted less than `deleted_users_retention_days` ago.
+    async fn restore_user(context: &Context<Handler>, user_id: String) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] restore_user");
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        let user_id = UserId::new(&user_id);
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(&span, "Unauthorized user restoration"))?;
+        handler.restore_user(&user_id).instrument(span).await?;
+        Ok(Success::new())
+    }
+
+    /// A disabled user can't log in nor bind, and their sessions are revoked.
+    async fn set_user_enabled(
+        context: &Context<Handler>,
+        user_id: String,
+        enabled: bool,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] set_user_enabled");
+        span.in_scope(|| {
+            debug!(?user_id, ?enabled);
+        });
+        let user_id = UserId::new(&user_id);
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(&span, "Unauthorized user update"))?;
+        if !enabled && context.validation_result.user == user_id {
+            span.in_scope(|| debug!("Cannot disable current user"));
+            return Err("Cannot disable current user".into());
+        }
+        handler
+            .set_user_enabled(&user_id, enabled)
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    /// After the expiration date, the user can't log in nor bind. Without a date, the account
+    /// never expires.
+    async fn set_user_expiration_date(
+        context: &Context<Handler>,
+        user_id: String,
+        expiration_date: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] set_user_expiration_date");
+        span.in_scope(|| {
+            debug!(?user_id, ?expiration_date);
+        });
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(&span, "Unauthorized user update"))?;
+        handler
+            .set_user_expiration_date(
+                &UserId::new(&user_id),
+                expiration_date.map(|d| d.naive_utc()),
+            )
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
     async fn delete_group(context: &Context<Handler>, group_id: i32) -> FieldResult<Success> {
         let span = debug_span!("[GraphQL mutation] delete_group");
         span.in_scope(|| {
@@ -276,4 +715,291 @@ impl<Handler: BackendHandler> Mutation<Handler> {
             .await?;
         Ok(Success::new())
     }
+
+    /// Makes the group dynamic: its members are the users matching the filter. Without a filter,
+    /// the group is static again, with no members.
+    async fn set_group_dynamic_filter(
+        context: &Context<Handler>,
+        group_id: i32,
+        filter: Option<RequestFilter>,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] set_group_dynamic_filter");
+        span.in_scope(|| {
+            debug!(?group_id);
+        });
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(&span, "Unauthorized group update"))?;
+        let filter = convert_request_filter(context, filter).await?;
+        handler
+            .set_group_dynamic_filter(GroupId(group_id), filter)
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    async fn start_totp_enrollment(
+        context: &Context<Handler>,
+        user_id: String,
+    ) -> FieldResult<TotpEnrollment> {
+        let span = debug_span!("[GraphQL mutation] start_totp_enrollment");
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        let user_id = UserId::new(&user_id);
+        let handler = context
+            .get_totp_handler(&user_id)
+            .ok_or_else(field_error_callback(&span, "Unauthorized TOTP enrollment"))?;
+        let secret = handler
+            .start_totp_enrollment(&user_id)
+            .instrument(span)
+            .await?;
+        Ok(TotpEnrollment {
+            secret: totp::encode_secret(&secret),
+            uri: totp::otpauth_uri("LLDAP", user_id.as_str(), &secret),
+        })
+    }
+
+    async fn confirm_totp_enrollment(
+        context: &Context<Handler>,
+        user_id: String,
+        code: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] confirm_totp_enrollment");
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        let user_id = UserId::new(&user_id);
+        let handler = context
+            .get_totp_handler(&user_id)
+            .ok_or_else(field_error_callback(&span, "Unauthorized TOTP enrollment"))?;
+        handler
+            .confirm_totp_enrollment(&user_id, &code)
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    async fn disable_totp(context: &Context<Handler>, user_id: String) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] disable_totp");
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        let user_id = UserId::new(&user_id);
+        let handler = context
+            .get_totp_handler(&user_id)
+            .ok_or_else(field_error_callback(&span, "Unauthorized TOTP removal"))?;
+        handler.disable_totp(&user_id).instrument(span).await?;
+        Ok(Success::new())
+    }
+
+    async fn start_passkey_registration(
+        context: &Context<Handler>,
+        user_id: String,
+    ) -> FieldResult<PasskeyRegistration> {
+        let span = debug_span!("[GraphQL mutation] start_passkey_registration");
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        let user_id = UserId::new(&user_id);
+        let handler = context
+            .get_passkey_handler(&user_id)
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized passkey registration",
+            ))?;
+        let challenge = handler
+            .start_passkey_registration(&user_id)
+            .instrument(span)
+            .await?;
+        Ok(PasskeyRegistration {
+            options: challenge.options,
+            server_data: challenge.server_data,
+        })
+    }
+
+    /// Stores the credential returned by the browser, as JSON.
+    async fn finish_passkey_registration(
+        context: &Context<Handler>,
+        user_id: String,
+        name: String,
+        server_data: String,
+        credential: String,
+    ) -> FieldResult<Passkey> {
+        let span = debug_span!("[GraphQL mutation] finish_passkey_registration");
+        span.in_scope(|| {
+            debug!(?user_id, ?name);
+        });
+        let user_id = UserId::new(&user_id);
+        let handler = context
+            .get_passkey_handler(&user_id)
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized passkey registration",
+            ))?;
+        Ok(handler
+            .finish_passkey_registration(&user_id, &name, &server_data, &credential)
+            .instrument(span)
+            .await?
+            .into())
+    }
+
+    async fn delete_passkey(
+        context: &Context<Handler>,
+        user_id: String,
+        passkey_id: i32,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] delete_passkey");
+        span.in_scope(|| {
+            debug!(?user_id, ?passkey_id);
+        });
+        let user_id = UserId::new(&user_id);
+        let handler = context
+            .get_passkey_handler(&user_id)
+            .ok_or_else(field_error_callback(&span, "Unauthorized passkey deletion"))?;
+        handler
+            .delete_passkey(&user_id, passkey_id)
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    /// Logs the user out everywhere: the refresh tokens are revoked, and the current access
+    /// tokens stay valid until they expire.
+    async fn revoke_all_sessions(
+        context: &Context<Handler>,
+        user_id: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] revoke_all_sessions");
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        let user_id = UserId::new(&user_id);
+        let handler = context
+            .get_session_handler(&user_id)
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized session revocation",
+            ))?;
+        handler
+            .revoke_all_sessions(&user_id)
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    /// Unlocks the account, and resets its count of failed logins.
+    async fn clear_user_lockout(
+        context: &Context<Handler>,
+        user_id: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] clear_user_lockout");
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(&span, "Unauthorized lockout removal"))?;
+        handler
+            .clear_user_lockout(&UserId::new(&user_id))
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+
+    /// The scope is either "readonly" or "admin".
+    async fn create_api_token(
+        context: &Context<Handler>,
+        name: String,
+        scope: String,
+        expiry_date: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> FieldResult<CreatedApiToken> {
+        let span = debug_span!("[GraphQL mutation] create_api_token");
+        span.in_scope(|| {
+            debug!(?name, ?scope, ?expiry_date);
+        });
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized API token creation",
+            ))?;
+        let scope = scope
+            .parse::<ApiTokenScope>()
+            .map_err(|_| format!("Invalid API token scope: '{}'", scope))?;
+        let token = handler
+            .create_api_token(&name, scope, expiry_date.map(|d| d.naive_utc()))
+            .instrument(span)
+            .await?;
+        Ok(CreatedApiToken { name, token })
+    }
+
+    async fn revoke_api_token(context: &Context<Handler>, name: String) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] revoke_api_token");
+        span.in_scope(|| {
+            debug!(?name);
+        });
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized API token revocation",
+            ))?;
+        handler.revoke_api_token(&name).instrument(span).await?;
+        Ok(Success::new())
+    }
+
+    /// Restricts the values of a string user attribute to a set, checked on every write. Without
+    /// `allowedValues`, any value is accepted again.
+    async fn set_user_attribute_allowed_values(
+        context: &Context<Handler>,
+        name: String,
+        allowed_values: Option<Vec<String>>,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] set_user_attribute_allowed_values");
+        span.in_scope(|| {
+            debug!(?name, ?allowed_values);
+        });
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(&span, "Unauthorized schema update"))?;
+        handler
+            .set_user_attribute_allowed_values(&name, allowed_values)
+            .instrument(span)
+            .await?;
+        Ok(Success::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_self_service_fields() {
+        let allowed = vec!["display_name".to_owned(), "Phone".to_owned()];
+        let update = |display_name: Option<&str>, email: Option<&str>, attribute: &str| {
+            UpdateMyProfileInput {
+                email: email.map(str::to_owned),
+                display_name: display_name.map(str::to_owned),
+                first_name: None,
+                last_name: None,
+                avatar: None,
+                insert_attributes: Some(vec![AttributeValueInput {
+                    name: attribute.to_owned(),
+                    value: vec!["1234".to_owned()],
+                }]),
+                remove_attributes: None,
+            }
+            .into_update_user_input(&UserId::new("bob"))
+        };
+        assert!(check_self_service_fields(&update(Some("Bob"), None, "phone"), &allowed).is_ok());
+        assert_eq!(
+            check_self_service_fields(&update(None, Some("bob@bob"), "phone"), &allowed)
+                .unwrap_err()
+                .to_string(),
+            "Field `email` can't be changed in the profile"
+        );
+        assert!(check_self_service_fields(&update(None, None, "uid_number"), &allowed).is_err());
+    }
 }
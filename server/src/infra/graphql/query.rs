@@ -1,30 +1,40 @@
 use crate::{
     domain::{
-        handler::{BackendHandler, SchemaBackendHandler},
-        ldap::utils::{map_user_field, UserFieldType},
-        types::{GroupDetails, GroupId, JpegPhoto, UserColumn, UserId},
+        audit_log_handler::AuditLogFilter,
+        handler::{BackendHandler, GroupRequestFilter, SchemaBackendHandler, SubStringFilter},
+        ldap::utils::{
+            convert_filter_value, get_custom_attribute, map_user_field_with_schema, UserFieldType,
+        },
+        passkey_handler::PasskeyHandler,
+        types::{AttributeType, GroupDetails, GroupId, JpegPhoto, UserColumn, UserId},
     },
     infra::{
-        access_control::{ReadonlyBackendHandler, UserReadableBackendHandler},
+        access_control::{AdminBackendHandler, ReadonlyBackendHandler, UserReadableBackendHandler},
         graphql::api::field_error_callback,
         schema::PublicSchema,
     },
 };
+use base64::Engine;
 use chrono::TimeZone;
-use juniper::{graphql_object, FieldResult, GraphQLInputObject};
+use juniper::{graphql_object, FieldResult, GraphQLInputObject, GraphQLObject};
 use serde::{Deserialize, Serialize};
 use tracing::{debug, debug_span, Instrument};
 
 type DomainRequestFilter = crate::domain::handler::UserRequestFilter;
+type DomainBackendSchema = crate::domain::handler::Schema;
 type DomainUser = crate::domain::types::User;
 type DomainGroup = crate::domain::types::Group;
 type DomainUserAndGroups = crate::domain::types::UserAndGroups;
 type DomainSchema = crate::infra::schema::PublicSchema;
 type DomainAttributeList = crate::domain::handler::AttributeList;
 type DomainAttributeSchema = crate::domain::handler::AttributeSchema;
+type DomainUserLockout = crate::domain::lockout_handler::UserLockout;
+type DomainApiToken = crate::domain::api_token_handler::ApiToken;
+type DomainAuditLogEntry = crate::domain::audit_log_handler::AuditLogEntry;
+type DomainPasskeyInfo = crate::domain::passkey_handler::PasskeyInfo;
 use super::api::Context;
 
-#[derive(PartialEq, Eq, Debug, GraphQLInputObject)]
+#[derive(PartialEq, Eq, Debug, Default, GraphQLInputObject)]
 /// A filter for requests, specifying a boolean expression based on field constraints. Only one of
 /// the fields can be set at a time.
 pub struct RequestFilter {
@@ -32,40 +42,54 @@ pub struct RequestFilter {
     all: Option<Vec<RequestFilter>>,
     not: Option<Box<RequestFilter>>,
     eq: Option<EqualityConstraint>,
+    substring: Option<SubstringConstraint>,
     member_of: Option<String>,
     member_of_id: Option<i32>,
+    created_after: Option<chrono::DateTime<chrono::Utc>>,
+    created_before: Option<chrono::DateTime<chrono::Utc>>,
+    /// The users that expire up to this date, e.g. to list the accounts expiring soon.
+    expires_before: Option<chrono::DateTime<chrono::Utc>>,
+    /// The users that logged in, but not since this date, e.g. to list the dormant accounts.
+    last_login_before: Option<chrono::DateTime<chrono::Utc>>,
 }
 
-impl TryInto<DomainRequestFilter> for RequestFilter {
-    type Error = String;
-    fn try_into(self) -> Result<DomainRequestFilter, Self::Error> {
-        let mut field_count = 0;
-        if self.any.is_some() {
-            field_count += 1;
-        }
-        if self.all.is_some() {
-            field_count += 1;
-        }
-        if self.not.is_some() {
-            field_count += 1;
-        }
-        if self.eq.is_some() {
-            field_count += 1;
-        }
-        if self.member_of.is_some() {
-            field_count += 1;
-        }
-        if self.member_of_id.is_some() {
-            field_count += 1;
-        }
+impl RequestFilter {
+    /// Converts the filter to the backend one. The fields are either built-in, or custom
+    /// attributes from the schema.
+    fn try_into_domain_filter(
+        self,
+        schema: &DomainBackendSchema,
+    ) -> Result<DomainRequestFilter, String> {
+        let field_count = [
+            self.any.is_some(),
+            self.all.is_some(),
+            self.not.is_some(),
+            self.eq.is_some(),
+            self.substring.is_some(),
+            self.member_of.is_some(),
+            self.member_of_id.is_some(),
+            self.created_after.is_some(),
+            self.created_before.is_some(),
+            self.expires_before.is_some(),
+            self.last_login_before.is_some(),
+        ]
+        .into_iter()
+        .filter(|f| *f)
+        .count();
         if field_count == 0 {
             return Err("No field specified in request filter".to_string());
         }
         if field_count > 1 {
             return Err("Multiple fields specified in request filter".to_string());
         }
+        let convert_all = |filters: Vec<RequestFilter>| {
+            filters
+                .into_iter()
+                .map(|f| f.try_into_domain_filter(schema))
+                .collect::<Result<Vec<_>, String>>()
+        };
         if let Some(e) = self.eq {
-            return match map_user_field(&e.field.to_ascii_lowercase()) {
+            return match map_user_field_with_schema(&e.field.to_ascii_lowercase(), schema) {
                 UserFieldType::NoMatch => Err(format!("Unknown request filter: {}", &e.field)),
                 UserFieldType::PrimaryField(UserColumn::UserId) => {
                     Ok(DomainRequestFilter::UserId(UserId::new(&e.value)))
@@ -73,28 +97,61 @@ impl TryInto<DomainRequestFilter> for RequestFilter {
                 UserFieldType::PrimaryField(column) => {
                     Ok(DomainRequestFilter::Equality(column, e.value))
                 }
-                UserFieldType::Attribute(column) => Ok(DomainRequestFilter::AttributeEquality(
-                    column.to_owned(),
-                    e.value,
+                UserFieldType::Attribute(name) => {
+                    match schema
+                        .user_attributes
+                        .get_attribute_type(&name)
+                        .and_then(|attribute_type| convert_filter_value(attribute_type, &e.value))
+                    {
+                        Some(Some(value)) => {
+                            Ok(DomainRequestFilter::AttributeValueEquality(name, value))
+                        }
+                        Some(None) => Err(format!(
+                            "Invalid value for attribute {}: {}",
+                            &e.field, &e.value
+                        )),
+                        None => Ok(DomainRequestFilter::AttributeEquality(name, e.value)),
+                    }
+                }
+            };
+        }
+        if let Some(s) = self.substring {
+            let filter = SubStringFilter {
+                initial: s.initial,
+                any: s.any.unwrap_or_default(),
+                final_: s.final_,
+            };
+            return match map_user_field_with_schema(&s.field.to_ascii_lowercase(), schema) {
+                UserFieldType::PrimaryField(UserColumn::UserId) => {
+                    Ok(DomainRequestFilter::UserIdSubString(filter))
+                }
+                UserFieldType::PrimaryField(
+                    column @ (UserColumn::Email | UserColumn::DisplayName),
+                ) => Ok(DomainRequestFilter::SubString(column, filter)),
+                // Only single-valued strings can be matched, the other types are serialized.
+                UserFieldType::Attribute(name)
+                    if schema.user_attributes.get_attribute_type(&name)
+                        == Some((AttributeType::String, false)) =>
+                {
+                    Ok(DomainRequestFilter::AttributeSubString(name, filter))
+                }
+                UserFieldType::NoMatch => Err(format!("Unknown request filter: {}", &s.field)),
+                _ => Err(format!(
+                    "Unsupported field for substring filter: {}",
+                    &s.field
                 )),
             };
         }
         if let Some(c) = self.any {
-            return Ok(DomainRequestFilter::Or(
-                c.into_iter()
-                    .map(TryInto::try_into)
-                    .collect::<Result<Vec<_>, String>>()?,
-            ));
+            return Ok(DomainRequestFilter::Or(convert_all(c)?));
         }
         if let Some(c) = self.all {
-            return Ok(DomainRequestFilter::And(
-                c.into_iter()
-                    .map(TryInto::try_into)
-                    .collect::<Result<Vec<_>, String>>()?,
-            ));
+            return Ok(DomainRequestFilter::And(convert_all(c)?));
         }
         if let Some(c) = self.not {
-            return Ok(DomainRequestFilter::Not(Box::new((*c).try_into()?)));
+            return Ok(DomainRequestFilter::Not(Box::new(
+                c.try_into_domain_filter(schema)?,
+            )));
         }
         if let Some(group) = self.member_of {
             return Ok(DomainRequestFilter::MemberOf(group));
@@ -102,6 +159,18 @@ impl TryInto<DomainRequestFilter> for RequestFilter {
         if let Some(group_id) = self.member_of_id {
             return Ok(DomainRequestFilter::MemberOfId(GroupId(group_id)));
         }
+        if let Some(date) = self.created_after {
+            return Ok(DomainRequestFilter::CreationDateAfter(date.naive_utc()));
+        }
+        if let Some(date) = self.created_before {
+            return Ok(DomainRequestFilter::CreationDateBefore(date.naive_utc()));
+        }
+        if let Some(date) = self.expires_before {
+            return Ok(DomainRequestFilter::ExpirationDateBefore(date.naive_utc()));
+        }
+        if let Some(date) = self.last_login_before {
+            return Ok(DomainRequestFilter::LastLoginBefore(date.naive_utc()));
+        }
         unreachable!();
     }
 }
@@ -112,6 +181,35 @@ pub struct EqualityConstraint {
     value: String,
 }
 
+#[derive(PartialEq, Eq, Debug, GraphQLInputObject)]
+/// Matches the string fields that start with `initial`, contain all of `any` in order, and end
+/// with `final`.
+pub struct SubstringConstraint {
+    field: String,
+    initial: Option<String>,
+    any: Option<Vec<String>>,
+    #[graphql(name = "final")]
+    final_: Option<String>,
+}
+
+/// Fetches the schema to convert the filter, if there is one.
+pub(super) async fn convert_request_filter<Handler: BackendHandler>(
+    context: &Context<Handler>,
+    filters: Option<RequestFilter>,
+) -> FieldResult<Option<DomainRequestFilter>> {
+    Ok(match filters {
+        Some(filters) => {
+            let schema = context
+                .handler
+                .get_user_restricted_lister_handler(&context.validation_result)
+                .get_schema()
+                .await?;
+            Some(filters.try_into_domain_filter(&schema)?)
+        }
+        None => None,
+    })
+}
+
 #[derive(PartialEq, Eq, Debug)]
 /// The top-level GraphQL query type.
 pub struct Query<Handler: BackendHandler> {
@@ -167,13 +265,65 @@ impl<Handler: BackendHandler> Query<Handler> {
                 &span,
                 "Unauthorized access to user list",
             ))?;
+        let filters = convert_request_filter(context, filters)
+            .instrument(span.clone())
+            .await?;
         Ok(handler
-            .list_users(filters.map(TryInto::try_into).transpose()?, false)
+            .list_users(filters, false)
             .instrument(span)
             .await
             .map(|v| v.into_iter().map(Into::into).collect())?)
     }
 
+    /// A page of the users, with the Relay cursor-based pagination.
+    async fn users_connection(
+        context: &Context<Handler>,
+        #[graphql(name = "where")] filters: Option<RequestFilter>,
+        first: Option<i32>,
+        after: Option<String>,
+        last: Option<i32>,
+        before: Option<String>,
+    ) -> FieldResult<UserConnection<Handler>> {
+        let span = debug_span!("[GraphQL query] users_connection");
+        span.in_scope(|| {
+            debug!(?filters, ?first, ?after, ?last, ?before);
+        });
+        let handler = context
+            .get_readonly_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized access to user list",
+            ))?;
+        let filters = convert_request_filter(context, filters)
+            .instrument(span.clone())
+            .await?;
+        let total_count = handler
+            .count_users(filters.clone())
+            .instrument(span.clone())
+            .await?;
+        let range = PageRange::new(total_count, first, after, last, before)?;
+        let users = if range.is_empty() {
+            Vec::new()
+        } else {
+            handler
+                .list_users_page(filters, false, range.start, range.end - range.start)
+                .instrument(span)
+                .await?
+        };
+        Ok(UserConnection {
+            edges: users
+                .into_iter()
+                .zip(range.start..)
+                .map(|(user, offset)| UserEdge {
+                    node: user.into(),
+                    cursor: encode_cursor(offset),
+                })
+                .collect(),
+            page_info: range.page_info(total_count),
+            total_count,
+        })
+    }
+
     async fn groups(context: &Context<Handler>) -> FieldResult<Vec<Group<Handler>>> {
         let span = debug_span!("[GraphQL query] groups");
         let handler = context
@@ -189,6 +339,44 @@ impl<Handler: BackendHandler> Query<Handler> {
             .map(|v| v.into_iter().map(Into::into).collect())?)
     }
 
+    /// A page of the groups, with the Relay cursor-based pagination.
+    async fn groups_connection(
+        context: &Context<Handler>,
+        first: Option<i32>,
+        after: Option<String>,
+        last: Option<i32>,
+        before: Option<String>,
+    ) -> FieldResult<GroupConnection<Handler>> {
+        let span = debug_span!("[GraphQL query] groups_connection");
+        span.in_scope(|| {
+            debug!(?first, ?after, ?last, ?before);
+        });
+        let handler = context
+            .get_readonly_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized access to group list",
+            ))?;
+        // There are few groups, they are paginated in memory.
+        let groups = handler.list_groups(None).instrument(span).await?;
+        let total_count = groups.len() as u64;
+        let range = PageRange::new(total_count, first, after, last, before)?;
+        Ok(GroupConnection {
+            edges: groups
+                .into_iter()
+                .zip(0..)
+                .skip(range.start as usize)
+                .take((range.end - range.start) as usize)
+                .map(|(group, offset)| GroupEdge {
+                    node: group.into(),
+                    cursor: encode_cursor(offset),
+                })
+                .collect(),
+            page_info: range.page_info(total_count),
+            total_count,
+        })
+    }
+
     async fn group(context: &Context<Handler>, group_id: i32) -> FieldResult<Group<Handler>> {
         let span = debug_span!("[GraphQL query] group");
         span.in_scope(|| {
@@ -207,6 +395,64 @@ impl<Handler: BackendHandler> Query<Handler> {
             .map(Into::into)?)
     }
 
+    async fn user_lockouts(context: &Context<Handler>) -> FieldResult<Vec<UserLockout>> {
+        let span = debug_span!("[GraphQL query] user_lockouts");
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized access to the lockouts",
+            ))?;
+        Ok(handler
+            .list_user_lockouts()
+            .instrument(span)
+            .await
+            .map(|v| v.into_iter().map(Into::into).collect())?)
+    }
+
+    async fn api_tokens(context: &Context<Handler>) -> FieldResult<Vec<ApiToken>> {
+        let span = debug_span!("[GraphQL query] api_tokens");
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized access to the API tokens",
+            ))?;
+        Ok(handler
+            .list_api_tokens()
+            .instrument(span)
+            .await
+            .map(|v| v.into_iter().map(Into::into).collect())?)
+    }
+
+    /// The changes made to the users and groups, most recent first. `after` is inclusive,
+    /// `before` is exclusive.
+    async fn audit_log(
+        context: &Context<Handler>,
+        actor: Option<String>,
+        target: Option<String>,
+        after: Option<chrono::DateTime<chrono::Utc>>,
+        before: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> FieldResult<Vec<AuditLogEntry>> {
+        let span = debug_span!("[GraphQL query] audit_log");
+        let handler = context
+            .get_admin_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized access to the audit log",
+            ))?;
+        Ok(handler
+            .list_audit_events(AuditLogFilter {
+                actor,
+                target,
+                after: after.map(|d| d.naive_utc()),
+                before: before.map(|d| d.naive_utc()),
+            })
+            .instrument(span)
+            .await
+            .map(|v| v.into_iter().map(Into::into).collect())?)
+    }
+
     async fn schema(context: &Context<Handler>) -> FieldResult<Schema<Handler>> {
         let span = debug_span!("[GraphQL query] get_schema");
         let handler = context
@@ -221,6 +467,257 @@ impl<Handler: BackendHandler> Query<Handler> {
     }
 }
 
+/// The cursors are the offsets of the items in the list, so they are invalidated by the
+/// insertions and deletions before them.
+fn encode_cursor(offset: u64) -> String {
+    base64::engine::general_purpose::STANDARD.encode(format!("offset:{}", offset))
+}
+
+fn decode_cursor(cursor: &str) -> FieldResult<u64> {
+    use anyhow::Context;
+    Ok(base64::engine::general_purpose::STANDARD
+        .decode(cursor)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .and_then(|cursor| cursor.strip_prefix("offset:")?.parse().ok())
+        .context("Invalid cursor")?)
+}
+
+/// The items requested with the pagination arguments, from `start` (included) to `end`
+/// (excluded).
+#[derive(PartialEq, Eq, Debug)]
+struct PageRange {
+    start: u64,
+    end: u64,
+}
+
+impl PageRange {
+    /// Follows the Relay algorithm: `after` and `before` restrict the list, then `first` keeps the
+    /// items at the start of it and `last` the items at the end.
+    fn new(
+        total_count: u64,
+        first: Option<i32>,
+        after: Option<String>,
+        last: Option<i32>,
+        before: Option<String>,
+    ) -> FieldResult<Self> {
+        use anyhow::Context;
+        let get_count = |count: Option<i32>| {
+            count
+                .map(|c| u64::try_from(c).context("The page size can't be negative"))
+                .transpose()
+        };
+        let (first, last) = (get_count(first)?, get_count(last)?);
+        let mut start = match after {
+            Some(cursor) => (decode_cursor(&cursor)? + 1).min(total_count),
+            None => 0,
+        };
+        let mut end = match before {
+            Some(cursor) => decode_cursor(&cursor)?.min(total_count),
+            None => total_count,
+        }
+        .max(start);
+        if let Some(first) = first {
+            end = end.min(start + first);
+        }
+        if let Some(last) = last {
+            start = start.max(end.saturating_sub(last));
+        }
+        Ok(Self { start, end })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    fn page_info(&self, total_count: u64) -> PageInfo {
+        PageInfo {
+            has_next_page: self.end < total_count,
+            has_previous_page: self.start > 0,
+            start_cursor: (!self.is_empty()).then(|| encode_cursor(self.start)),
+            end_cursor: (!self.is_empty()).then(|| encode_cursor(self.end - 1)),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// Where a page is in the list.
+pub struct PageInfo {
+    has_next_page: bool,
+    has_previous_page: bool,
+    start_cursor: Option<String>,
+    end_cursor: Option<String>,
+}
+
+/// A page of users.
+pub struct UserConnection<Handler: BackendHandler> {
+    edges: Vec<UserEdge<Handler>>,
+    page_info: PageInfo,
+    total_count: u64,
+}
+
+#[graphql_object(context = Context<Handler>)]
+impl<Handler: BackendHandler> UserConnection<Handler> {
+    fn edges(&self) -> &Vec<UserEdge<Handler>> {
+        &self.edges
+    }
+    fn page_info(&self) -> &PageInfo {
+        &self.page_info
+    }
+    /// The number of users matching the filters, in all the pages.
+    fn total_count(&self) -> i32 {
+        self.total_count as i32
+    }
+}
+
+pub struct UserEdge<Handler: BackendHandler> {
+    node: User<Handler>,
+    cursor: String,
+}
+
+#[graphql_object(context = Context<Handler>)]
+impl<Handler: BackendHandler> UserEdge<Handler> {
+    fn node(&self) -> &User<Handler> {
+        &self.node
+    }
+    fn cursor(&self) -> &str {
+        &self.cursor
+    }
+}
+
+/// A page of groups.
+pub struct GroupConnection<Handler: BackendHandler> {
+    edges: Vec<GroupEdge<Handler>>,
+    page_info: PageInfo,
+    total_count: u64,
+}
+
+#[graphql_object(context = Context<Handler>)]
+impl<Handler: BackendHandler> GroupConnection<Handler> {
+    fn edges(&self) -> &Vec<GroupEdge<Handler>> {
+        &self.edges
+    }
+    fn page_info(&self) -> &PageInfo {
+        &self.page_info
+    }
+    /// The number of groups, in all the pages.
+    fn total_count(&self) -> i32 {
+        self.total_count as i32
+    }
+}
+
+pub struct GroupEdge<Handler: BackendHandler> {
+    node: Group<Handler>,
+    cursor: String,
+}
+
+#[graphql_object(context = Context<Handler>)]
+impl<Handler: BackendHandler> GroupEdge<Handler> {
+    fn node(&self) -> &Group<Handler> {
+        &self.node
+    }
+    fn cursor(&self) -> &str {
+        &self.cursor
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// An account locked after too many failed logins.
+pub struct UserLockout {
+    user_id: String,
+    failure_count: i32,
+    locked_until: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<DomainUserLockout> for UserLockout {
+    fn from(lockout: DomainUserLockout) -> Self {
+        Self {
+            user_id: lockout.user_id.into_string(),
+            failure_count: lockout.failure_count,
+            locked_until: chrono::Utc.from_utc_datetime(&lockout.locked_until),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A token for the scripts, usable as a bearer token or as an LDAP bind password.
+pub struct ApiToken {
+    name: String,
+    /// Either "readonly" or "admin".
+    scope: String,
+    creation_date: chrono::DateTime<chrono::Utc>,
+    expiry_date: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl From<DomainApiToken> for ApiToken {
+    fn from(token: DomainApiToken) -> Self {
+        Self {
+            name: token.name,
+            scope: Into::<&'static str>::into(token.scope).to_lowercase(),
+            creation_date: chrono::Utc.from_utc_datetime(&token.creation_date),
+            expiry_date: token
+                .expiry_date
+                .map(|expiry| chrono::Utc.from_utc_datetime(&expiry)),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A change made to a user or a group.
+pub struct AuditLogEntry {
+    timestamp: chrono::DateTime<chrono::Utc>,
+    /// The user that made the change, or "api_token:<name>" for an API token.
+    actor: String,
+    action: String,
+    /// "user:<id>", "group:<id>", "api_token:<name>" or "attribute:<name>".
+    target: String,
+    /// A JSON object of the changes. For the updates, each changed field has its values
+    /// "before" and "after" the change.
+    changes: Option<String>,
+}
+
+impl From<DomainAuditLogEntry> for AuditLogEntry {
+    fn from(entry: DomainAuditLogEntry) -> Self {
+        Self {
+            timestamp: chrono::Utc.from_utc_datetime(&entry.timestamp),
+            actor: entry.actor,
+            action: entry.action,
+            target: entry.target,
+            changes: entry.changes,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A WebAuthn credential of a user, to log in without a password.
+pub struct Passkey {
+    passkey_id: i32,
+    name: String,
+    creation_date: chrono::DateTime<chrono::Utc>,
+    last_used_date: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl From<DomainPasskeyInfo> for Passkey {
+    fn from(passkey: DomainPasskeyInfo) -> Self {
+        Self {
+            passkey_id: passkey.passkey_id,
+            name: passkey.name,
+            creation_date: chrono::Utc.from_utc_datetime(&passkey.creation_date),
+            last_used_date: passkey
+                .last_used_date
+                .map(|date| chrono::Utc.from_utc_datetime(&date)),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// The values of a custom attribute: a single one for the single-valued attributes. The photos are
+/// base64 encoded.
+pub struct AttributeValue {
+    name: String,
+    value: Vec<String>,
+}
+
 #[derive(PartialEq, Eq, Debug, Serialize, Deserialize)]
 /// Represents a single user.
 pub struct User<Handler: BackendHandler> {
@@ -286,6 +783,106 @@ impl<Handler: BackendHandler> User<Handler> {
         self.user.uuid.as_str()
     }
 
+    /// A disabled user can't log in nor bind.
+    fn is_enabled(&self) -> bool {
+        self.user.is_enabled
+    }
+
+    /// After this date, the user can't log in nor bind.
+    fn expiration_date(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.user
+            .expiration_date
+            .map(|date| chrono::Utc.from_utc_datetime(&date))
+    }
+
+    /// The last successful login or bind, precise to the minute. Not set if the user never
+    /// logged in.
+    fn last_login(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.user
+            .last_login
+            .map(|date| chrono::Utc.from_utc_datetime(&date))
+    }
+
+    /// The failed logins since the last successful one, within the lockout window.
+    fn failed_login_count(&self) -> i32 {
+        self.user
+            .authentication_failures
+            .as_ref()
+            .map_or(0, |f| f.failure_count)
+    }
+
+    /// Reset by a successful login, or by `clearUserLockout`.
+    fn last_failed_login(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.user
+            .authentication_failures
+            .as_ref()
+            .map(|f| chrono::Utc.from_utc_datetime(&f.last_failure_date))
+    }
+
+    /// Set while the account is locked out after too many failed logins.
+    fn locked_until(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.user
+            .authentication_failures
+            .as_ref()
+            .and_then(|f| f.locked_until)
+            .map(|date| chrono::Utc.from_utc_datetime(&date))
+    }
+
+    /// The custom attributes of the user, with all the values of the multi-valued ones.
+    async fn attributes(&self, context: &Context<Handler>) -> FieldResult<Vec<AttributeValue>> {
+        let span = debug_span!("[GraphQL query] user::attributes");
+        let schema = context
+            .handler
+            .get_user_restricted_lister_handler(&context.validation_result)
+            .get_schema()
+            .instrument(span)
+            .await?;
+        Ok(self
+            .user
+            .attributes
+            .iter()
+            .filter_map(|attribute| {
+                let (attribute_type, _) =
+                    schema.user_attributes.get_attribute_type(&attribute.name)?;
+                let values = get_custom_attribute(&self.user.attributes, &attribute.name, &schema)?;
+                Some(AttributeValue {
+                    name: attribute.name.clone(),
+                    value: values
+                        .into_iter()
+                        .map(|value| match attribute_type {
+                            AttributeType::JpegPhoto => {
+                                base64::engine::general_purpose::STANDARD.encode(value)
+                            }
+                            _ => String::from_utf8_lossy(&value).into_owned(),
+                        })
+                        .collect(),
+                })
+            })
+            .collect())
+    }
+
+    /// Only visible to the user themselves and to the admins.
+    async fn passkeys(&self, context: &Context<Handler>) -> FieldResult<Vec<Passkey>> {
+        let span = debug_span!("[GraphQL query] user::passkeys");
+        span.in_scope(|| {
+            debug!(user_id = ?self.user.user_id);
+        });
+        let handler =
+            context
+                .get_passkey_handler(&self.user.user_id)
+                .ok_or_else(field_error_callback(
+                    &span,
+                    "Unauthorized access to passkeys",
+                ))?;
+        Ok(handler
+            .list_passkeys(&self.user.user_id)
+            .instrument(span)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
     /// The groups to which this user belongs.
     async fn groups(&self, context: &Context<Handler>) -> FieldResult<Vec<Group<Handler>>> {
         let span = debug_span!("[GraphQL query] user::groups");
@@ -336,6 +933,7 @@ pub struct Group<Handler: BackendHandler> {
     creation_date: chrono::NaiveDateTime,
     uuid: String,
     members: Option<Vec<String>>,
+    is_dynamic: Option<bool>,
     _phantom: std::marker::PhantomData<Box<Handler>>,
 }
 
@@ -353,6 +951,43 @@ impl<Handler: BackendHandler> Group<Handler> {
     fn uuid(&self) -> String {
         self.uuid.clone()
     }
+    /// Whether the members are the users matching a filter, instead of being added by hand.
+    async fn is_dynamic(&self, context: &Context<Handler>) -> FieldResult<bool> {
+        if let Some(is_dynamic) = self.is_dynamic {
+            return Ok(is_dynamic);
+        }
+        let span = debug_span!("[GraphQL query] group::is_dynamic");
+        let handler = context
+            .get_readonly_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized access to group data",
+            ))?;
+        Ok(handler
+            .list_groups(Some(GroupRequestFilter::GroupId(GroupId(self.group_id))))
+            .instrument(span)
+            .await?
+            .iter()
+            .any(|g| g.dynamic_filter.is_some()))
+    }
+    /// The groups nested in this group.
+    async fn member_groups(&self, context: &Context<Handler>) -> FieldResult<Vec<Group<Handler>>> {
+        let span = debug_span!("[GraphQL query] group::member_groups");
+        let handler = context
+            .get_readonly_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized access to group data",
+            ))?;
+        Ok(handler
+            .list_groups(Some(GroupRequestFilter::GroupId(GroupId(self.group_id))))
+            .instrument(span)
+            .await?
+            .into_iter()
+            .flat_map(|g| g.member_groups)
+            .map(Into::into)
+            .collect())
+    }
     /// The groups to which this user belongs.
     async fn users(&self, context: &Context<Handler>) -> FieldResult<Vec<User<Handler>>> {
         let span = debug_span!("[GraphQL query] group::users");
@@ -384,6 +1019,7 @@ impl<Handler: BackendHandler> From<GroupDetails> for Group<Handler> {
             creation_date: group_details.creation_date,
             uuid: group_details.uuid.into_string(),
             members: None,
+            is_dynamic: None,
             _phantom: std::marker::PhantomData,
         }
     }
@@ -397,6 +1033,7 @@ impl<Handler: BackendHandler> From<DomainGroup> for Group<Handler> {
             creation_date: group.creation_date,
             uuid: group.uuid.into_string(),
             members: Some(group.users.into_iter().map(UserId::into_string).collect()),
+            is_dynamic: Some(group.dynamic_filter.is_some()),
             _phantom: std::marker::PhantomData,
         }
     }
@@ -429,6 +1066,13 @@ impl<Handler: BackendHandler> AttributeSchema<Handler> {
     fn is_hardcoded(&self) -> bool {
         self.schema.is_hardcoded
     }
+    fn is_case_sensitive(&self) -> bool {
+        self.schema.is_case_sensitive
+    }
+    /// The only values accepted for the attribute, if restricted.
+    fn allowed_values(&self) -> Option<Vec<String>> {
+        self.schema.allowed_values.clone()
+    }
 }
 
 impl<Handler: BackendHandler> From<DomainAttributeSchema> for AttributeSchema<Handler> {
@@ -496,7 +1140,7 @@ impl<Handler: BackendHandler> From<DomainSchema> for Schema<Handler> {
 mod tests {
     use super::*;
     use crate::{
-        domain::{handler::AttributeList, types::AttributeType},
+        domain::handler::AttributeList,
         infra::{
             access_control::{Permission, ValidationResults},
             test_utils::{setup_default_schema, MockTestBackendHandler},
@@ -521,6 +1165,74 @@ mod tests {
         )
     }
 
+    #[tokio::test]
+    async fn get_user_multi_valued_attributes() {
+        const QUERY: &str = r#"{
+          user(userId: "bob") {
+            attributes {
+              name
+              value
+            }
+          }
+        }"#;
+
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_schema().returning(|| {
+            Ok(crate::domain::handler::Schema {
+                user_attributes: AttributeList {
+                    attributes: vec![crate::domain::handler::AttributeSchema {
+                        name: "phone_numbers".to_owned(),
+                        attribute_type: AttributeType::String,
+                        is_list: true,
+                        is_visible: true,
+                        is_editable: true,
+                        is_hardcoded: false,
+                        is_case_sensitive: false,
+                        allowed_values: None,
+                    }],
+                },
+                group_attributes: AttributeList {
+                    attributes: Vec::new(),
+                },
+            })
+        });
+        mock.expect_get_user_details()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| {
+                Ok(DomainUser {
+                    user_id: UserId::new("bob"),
+                    attributes: vec![crate::domain::types::AttributeValue {
+                        name: "phone_numbers".to_owned(),
+                        value: crate::domain::types::Serialized::from(&vec![
+                            "555-0001".to_owned(),
+                            "555-0002".to_owned(),
+                        ]),
+                    }],
+                    ..Default::default()
+                })
+            });
+
+        let context =
+            Context::<MockTestBackendHandler>::new_for_tests(mock, ValidationResults::admin());
+
+        let schema = schema(Query::<MockTestBackendHandler>::new());
+        assert_eq!(
+            execute(QUERY, None, &schema, &Variables::new(), &context).await,
+            Ok((
+                graphql_value!(
+                {
+                    "user": {
+                        "attributes": [{
+                            "name": "phone_numbers",
+                            "value": ["555-0001", "555-0002"]
+                        }]
+                    }
+                }),
+                vec![]
+            ))
+        );
+    }
+
     #[tokio::test]
     async fn get_user_by_id() {
         const QUERY: &str = r#"{
@@ -624,6 +1336,7 @@ mod tests {
         }"#;
 
         let mut mock = MockTestBackendHandler::new();
+        setup_default_schema(&mut mock);
         mock.expect_list_users()
             .with(
                 eq(Some(DomainRequestFilter::Or(vec![
@@ -685,6 +1398,228 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn list_users_with_filters() {
+        const QUERY: &str = r#"{
+          users(filters: {
+            all: [
+              {memberOf: "admins"},
+              {createdAfter: "2023-01-01T00:00:00Z"},
+              {eq: {field: "employee_number", value: "42"}},
+              {substring: {field: "mail", initial: "bob", final: "@bobbers.on"}}
+            ]}) {
+            id
+          }
+        }"#;
+
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_schema().returning(|| {
+            Ok(crate::domain::handler::Schema {
+                user_attributes: AttributeList {
+                    attributes: vec![crate::domain::handler::AttributeSchema {
+                        name: "employee_number".to_owned(),
+                        attribute_type: AttributeType::Integer,
+                        is_list: false,
+                        is_visible: true,
+                        is_editable: true,
+                        is_hardcoded: false,
+                        is_case_sensitive: false,
+                        allowed_values: None,
+                    }],
+                },
+                group_attributes: AttributeList {
+                    attributes: Vec::new(),
+                },
+            })
+        });
+        mock.expect_list_users()
+            .with(
+                eq(Some(DomainRequestFilter::And(vec![
+                    DomainRequestFilter::MemberOf("admins".to_owned()),
+                    DomainRequestFilter::CreationDateAfter(
+                        chrono::Utc
+                            .with_ymd_and_hms(2023, 1, 1, 0, 0, 0)
+                            .unwrap()
+                            .naive_utc(),
+                    ),
+                    DomainRequestFilter::AttributeValueEquality(
+                        "employee_number".to_owned(),
+                        crate::domain::types::Serialized::from(&42i64),
+                    ),
+                    DomainRequestFilter::SubString(
+                        UserColumn::Email,
+                        SubStringFilter {
+                            initial: Some("bob".to_owned()),
+                            any: vec![],
+                            final_: Some("@bobbers.on".to_owned()),
+                        },
+                    ),
+                ]))),
+                eq(false),
+            )
+            .return_once(|_, _| {
+                Ok(vec![DomainUserAndGroups {
+                    user: DomainUser {
+                        user_id: UserId::new("bob"),
+                        ..Default::default()
+                    },
+                    groups: None,
+                }])
+            });
+
+        let context =
+            Context::<MockTestBackendHandler>::new_for_tests(mock, ValidationResults::admin());
+
+        let schema = schema(Query::<MockTestBackendHandler>::new());
+        assert_eq!(
+            execute(QUERY, None, &schema, &Variables::new(), &context).await,
+            Ok((graphql_value!({"users": [{"id": "bob"}]}), vec![]))
+        );
+    }
+
+    #[test]
+    fn test_request_filter_unknown_field() {
+        let schema = crate::domain::handler::Schema {
+            user_attributes: AttributeList {
+                attributes: Vec::new(),
+            },
+            group_attributes: AttributeList {
+                attributes: Vec::new(),
+            },
+        };
+        let filter = RequestFilter {
+            eq: Some(EqualityConstraint {
+                field: "department".to_owned(),
+                value: "R&D".to_owned(),
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            filter.try_into_domain_filter(&schema),
+            Err("Unknown request filter: department".to_owned())
+        );
+        let filter = RequestFilter {
+            substring: Some(SubstringConstraint {
+                field: "creationDate".to_owned(),
+                initial: Some("2023".to_owned()),
+                any: None,
+                final_: None,
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            filter.try_into_domain_filter(&schema),
+            Err("Unsupported field for substring filter: creationDate".to_owned())
+        );
+    }
+
+    #[tokio::test]
+    async fn list_users_connection() {
+        const QUERY: &str = r#"{
+          usersConnection(first: 2) {
+            totalCount
+            edges {
+              cursor
+              node {
+                id
+              }
+            }
+            pageInfo {
+              hasNextPage
+              hasPreviousPage
+              endCursor
+            }
+          }
+        }"#;
+
+        let mut mock = MockTestBackendHandler::new();
+        // Once for the count, once for the page.
+        mock.expect_list_users()
+            .with(eq(None), eq(false))
+            .times(2)
+            .returning(|_, _| {
+                Ok(["bob", "john", "patrick"]
+                    .into_iter()
+                    .map(|user_id| DomainUserAndGroups {
+                        user: DomainUser {
+                            user_id: UserId::new(user_id),
+                            ..Default::default()
+                        },
+                        groups: None,
+                    })
+                    .collect())
+            });
+
+        let context =
+            Context::<MockTestBackendHandler>::new_for_tests(mock, ValidationResults::admin());
+
+        let schema = schema(Query::<MockTestBackendHandler>::new());
+        assert_eq!(
+            execute(QUERY, None, &schema, &Variables::new(), &context).await,
+            Ok((
+                graphql_value!(
+                {
+                    "usersConnection": {
+                        "totalCount": 3,
+                        "edges": [
+                            {
+                                "cursor": "b2Zmc2V0OjA=",
+                                "node": {"id": "bob"}
+                            },
+                            {
+                                "cursor": "b2Zmc2V0OjE=",
+                                "node": {"id": "john"}
+                            },
+                        ],
+                        "pageInfo": {
+                            "hasNextPage": true,
+                            "hasPreviousPage": false,
+                            "endCursor": "b2Zmc2V0OjE="
+                        }
+                    }
+                }),
+                vec![]
+            ))
+        );
+    }
+
+    #[test]
+    fn test_page_range() {
+        let range = |first, after: Option<u64>, last, before: Option<u64>| {
+            PageRange::new(
+                10,
+                first,
+                after.map(encode_cursor),
+                last,
+                before.map(encode_cursor),
+            )
+            .unwrap()
+        };
+        assert_eq!(
+            range(None, None, None, None),
+            PageRange { start: 0, end: 10 }
+        );
+        assert_eq!(
+            range(Some(3), Some(4), None, None),
+            PageRange { start: 5, end: 8 }
+        );
+        assert_eq!(
+            range(None, None, Some(3), None),
+            PageRange { start: 7, end: 10 }
+        );
+        assert_eq!(
+            range(None, None, Some(3), Some(2)),
+            PageRange { start: 0, end: 2 }
+        );
+        assert_eq!(
+            range(Some(3), Some(9), None, None),
+            PageRange { start: 10, end: 10 }
+        );
+        assert!(range(Some(3), Some(9), None, None).is_empty());
+        assert!(PageRange::new(10, Some(-1), None, None, None).is_err());
+        assert!(PageRange::new(10, None, Some("garbage".to_owned()), None, None).is_err());
+    }
+
     #[tokio::test]
     async fn get_schema() {
         const QUERY: &str = r#"{
@@ -861,6 +1796,8 @@ mod tests {
                         is_visible: false,
                         is_editable: true,
                         is_hardcoded: true,
+                        is_case_sensitive: false,
+                        allowed_values: None,
                     }],
                 },
                 group_attributes: AttributeList {
@@ -874,6 +1811,7 @@ mod tests {
             ValidationResults {
                 user: UserId::new("bob"),
                 permission: Permission::Regular,
+                is_api_token: false,
             },
         );
 
@@ -0,0 +1,200 @@
+use crate::{
+    domain::handler::BackendHandler,
+    infra::{
+        graphql::api::field_error_callback,
+        webhooks::{WebhookEvent, WebhookEventType},
+    },
+};
+use futures::{Stream, StreamExt};
+use juniper::{graphql_object, graphql_subscription, FieldResult, GraphQLEnum};
+use std::pin::Pin;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, debug_span, warn};
+
+use super::api::Context;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, GraphQLEnum)]
+pub enum EntityType {
+    User,
+    Group,
+}
+
+impl EntityType {
+    /// The membership events concern both a user and a group.
+    fn matches(&self, event: &WebhookEvent) -> bool {
+        match self {
+            EntityType::User => event.user_id.is_some(),
+            EntityType::Group => event.group_id.is_some(),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug)]
+/// A change in the directory: a user or a group was created, updated or deleted, or a user was
+/// added to or removed from a group.
+pub struct DirectoryEvent {
+    event: WebhookEvent,
+}
+
+impl From<WebhookEvent> for DirectoryEvent {
+    fn from(event: WebhookEvent) -> Self {
+        Self { event }
+    }
+}
+
+fn event_type_name(event_type: WebhookEventType) -> String {
+    // Same names as in the webhook payloads.
+    serde_json::to_value(event_type)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_owned))
+        .unwrap_or_default()
+}
+
+#[graphql_object]
+impl DirectoryEvent {
+    /// The type of the event, e.g. "user_created" or "group_deleted".
+    fn event_type(&self) -> String {
+        event_type_name(self.event.event)
+    }
+
+    /// Not set for the group events.
+    fn user_id(&self) -> Option<&str> {
+        self.event.user_id.as_ref().map(|u| u.as_str())
+    }
+
+    /// Set for the group events, and when a user is added to or removed from a group.
+    fn group_id(&self) -> Option<i32> {
+        self.event.group_id.map(|g| g.0)
+    }
+
+    /// For the creations and updates, the fields that were set.
+    fn changed_fields(&self) -> &[String] {
+        &self.event.changed_fields
+    }
+
+    fn timestamp(&self) -> chrono::DateTime<chrono::Utc> {
+        self.event.timestamp
+    }
+}
+
+type DirectoryEventStream = Pin<Box<dyn Stream<Item = FieldResult<DirectoryEvent>> + Send>>;
+
+#[derive(PartialEq, Eq, Debug)]
+/// The top-level GraphQL subscription type.
+pub struct Subscription<Handler: BackendHandler> {
+    _phantom: std::marker::PhantomData<Box<Handler>>,
+}
+
+impl<Handler: BackendHandler> Subscription<Handler> {
+    pub fn new() -> Self {
+        Self {
+            _phantom: std::marker::PhantomData,
+        }
+    }
+}
+
+#[graphql_subscription(context = Context<Handler>)]
+impl<Handler: BackendHandler> Subscription<Handler> {
+    /// The changes to the users and groups, from now on. Without `entityType`, all the events are
+    /// sent.
+    async fn directory_events(
+        context: &Context<Handler>,
+        entity_type: Option<EntityType>,
+    ) -> FieldResult<DirectoryEventStream> {
+        let span = debug_span!("[GraphQL subscription] directory_events");
+        span.in_scope(|| {
+            debug!(?entity_type);
+        });
+        context
+            .get_readonly_handler()
+            .ok_or_else(field_error_callback(
+                &span,
+                "Unauthorized access to directory events",
+            ))?;
+        let events =
+            futures::stream::unfold(context.events.subscribe(), |mut receiver| async move {
+                loop {
+                    match receiver.recv().await {
+                        Ok(event) => return Some((event, receiver)),
+                        Err(RecvError::Lagged(count)) => {
+                            warn!("Subscriber too slow, skipped {} directory events", count)
+                        }
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            })
+            .filter(move |event| {
+                futures::future::ready(entity_type.map_or(true, |t| t.matches(event)))
+            })
+            .map(|event| Ok(DirectoryEvent::from(event)));
+        Ok(Box::pin(events))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        domain::types::{GroupId, UserId},
+        infra::{access_control::ValidationResults, test_utils::MockTestBackendHandler},
+    };
+    use juniper::{graphql_value, resolve_into_stream, EmptyMutation, RootNode, Value, Variables};
+
+    struct TestQuery;
+
+    #[graphql_object(context = Context<MockTestBackendHandler>)]
+    impl TestQuery {
+        fn api_version() -> &'static str {
+            "1.0"
+        }
+    }
+
+    #[tokio::test]
+    async fn test_directory_events() {
+        const QUERY: &str = r#"subscription {
+          directoryEvents(entityType: GROUP) {
+            eventType
+            userId
+            groupId
+          }
+        }"#;
+        let context = Context::<MockTestBackendHandler>::new_for_tests(
+            MockTestBackendHandler::new(),
+            ValidationResults::admin(),
+        );
+        let schema = RootNode::new(
+            TestQuery,
+            EmptyMutation::<Context<MockTestBackendHandler>>::new(),
+            Subscription::<MockTestBackendHandler>::new(),
+        );
+        let (value, errors) =
+            resolve_into_stream(QUERY, None, &schema, &Variables::new(), &context)
+                .await
+                .unwrap();
+        assert!(errors.is_empty());
+        // The user events are filtered out.
+        context.events.notify(WebhookEvent::new(
+            WebhookEventType::UserCreated,
+            UserId::new("bob"),
+        ));
+        context.events.notify(WebhookEvent::for_group(
+            WebhookEventType::GroupCreated,
+            GroupId(3),
+        ));
+        let mut events = match value {
+            Value::Object(fields) => match fields.into_iter().next() {
+                Some((_, Value::Scalar(events))) => events,
+                _ => panic!("Expected a stream"),
+            },
+            _ => panic!("Expected an object"),
+        };
+        assert_eq!(
+            events.next().await.unwrap().unwrap(),
+            graphql_value!({
+                "eventType": "group_created",
+                "userId": None,
+                "groupId": 3,
+            })
+        );
+    }
+}
@@ -5,13 +5,18 @@ use sea_orm::{
 
 pub use crate::domain::{sql_migrations::Users, sql_tables::DbConnection};
 
-/// Contains the refresh tokens for a given user.
+/// Contains the refresh tokens for a given user. Each login starts a session, and the tokens
+/// replaced by the refresh rotation are kept until they expire, to detect their reuse.
 #[derive(Iden)]
 pub enum JwtRefreshStorage {
     Table,
     RefreshTokenHash,
     UserId,
     ExpiryDate,
+    SessionId,
+    SessionCreationDate,
+    CreationDate,
+    Replaced,
 }
 
 /// Contains the blacklisted JWT that haven't expired yet.
@@ -58,6 +63,27 @@ pub async fn init_table(pool: &DbConnection) -> std::result::Result<(), sea_orm:
                         .date_time()
                         .not_null(),
                 )
+                .col(
+                    ColumnDef::new(JwtRefreshStorage::SessionId)
+                        .string_len(32)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(JwtRefreshStorage::SessionCreationDate)
+                        .date_time()
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(JwtRefreshStorage::CreationDate)
+                        .date_time()
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(JwtRefreshStorage::Replaced)
+                        .boolean()
+                        .default(false)
+                        .not_null(),
+                )
                 .foreign_key(
                     ForeignKey::create()
                         .name("JwtRefreshStorageUserForeignKey")
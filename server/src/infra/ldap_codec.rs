@@ -0,0 +1,626 @@
+//! Wraps the ldap3_proto codec, to also decode the SASL bind requests, the matched values control
+//! (RFC 3876) and the server-side sort control (RFC 2891) that it doesn't support.
+
+use bytes::{Buf, BytesMut};
+use ldap3_proto::{
+    proto::{LdapFilter, LdapMsg, LdapResultCode, LdapSubstringFilter},
+    LdapCodec,
+};
+use tokio_util::codec::{Decoder, Encoder};
+
+// BER tags.
+const SEQUENCE_TAG: u8 = 0x30;
+const BOOLEAN_TAG: u8 = 0x01;
+const INTEGER_TAG: u8 = 0x02;
+const OCTET_STRING_TAG: u8 = 0x04;
+const ENUMERATED_TAG: u8 = 0x0a;
+/// [APPLICATION 0], constructed.
+const BIND_REQUEST_TAG: u8 = 0x60;
+/// [3], constructed.
+const SASL_CREDENTIALS_TAG: u8 = 0xa3;
+/// [0], constructed: the controls of a message.
+const CONTROLS_TAG: u8 = 0xa0;
+// The simple filter items of the matched values control.
+const EQUALITY_MATCH_TAG: u8 = 0xa3;
+const SUBSTRINGS_TAG: u8 = 0xa4;
+const GREATER_OR_EQUAL_TAG: u8 = 0xa5;
+const LESS_OR_EQUAL_TAG: u8 = 0xa6;
+const PRESENT_TAG: u8 = 0x87;
+const APPROX_MATCH_TAG: u8 = 0xa8;
+// The parts of a substrings filter.
+const SUBSTRING_INITIAL_TAG: u8 = 0x80;
+const SUBSTRING_ANY_TAG: u8 = 0x81;
+const SUBSTRING_FINAL_TAG: u8 = 0x82;
+// The optional parts of a sort key, and of the sort result.
+const SORT_ORDERING_RULE_TAG: u8 = 0x80;
+const SORT_REVERSE_ORDER_TAG: u8 = 0x81;
+const SORT_RESULT_ATTRIBUTE_TAG: u8 = 0x80;
+
+pub const MATCHED_VALUES_OID: &str = "1.2.826.0.1.3344810.2.3";
+pub const SORT_REQUEST_OID: &str = "1.2.840.113556.1.4.473";
+const SORT_RESPONSE_OID: &str = "1.2.840.113556.1.4.474";
+
+pub struct SaslBindRequest {
+    pub dn: String,
+    pub mechanism: String,
+    pub credentials: Option<Vec<u8>>,
+}
+
+impl std::fmt::Debug for SaslBindRequest {
+    // The credentials contain the password.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SaslBindRequest")
+            .field("dn", &self.dn)
+            .field("mechanism", &self.mechanism)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortKey {
+    pub attribute: String,
+    pub ordering_rule: Option<String>,
+    pub reverse_order: bool,
+}
+
+/// The server-side sort control of a search.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortRequest {
+    pub keys: Vec<SortKey>,
+    pub critical: bool,
+}
+
+/// The response control to a sort request: the attribute is the one that couldn't be sorted by.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortResult {
+    pub code: LdapResultCode,
+    pub attribute: Option<String>,
+}
+
+impl SortResult {
+    fn to_control(&self) -> Vec<u8> {
+        let mut result = write_element(ENUMERATED_TAG, &[self.code.clone() as u8]);
+        if let Some(attribute) = &self.attribute {
+            result.extend(write_element(
+                SORT_RESULT_ATTRIBUTE_TAG,
+                attribute.as_bytes(),
+            ));
+        }
+        let mut control = write_element(OCTET_STRING_TAG, SORT_RESPONSE_OID.as_bytes());
+        control.extend(write_element(
+            OCTET_STRING_TAG,
+            &write_element(SEQUENCE_TAG, &result),
+        ));
+        write_element(SEQUENCE_TAG, &control)
+    }
+}
+
+#[derive(Debug)]
+pub enum LdapRequest {
+    Message {
+        msg: LdapMsg,
+        /// The filters of the matched values control, empty without it.
+        matched_values: Vec<LdapFilter>,
+        sort: Option<SortRequest>,
+    },
+    SaslBind {
+        msgid: i32,
+        request: SaslBindRequest,
+    },
+}
+
+/// A response, with the controls that ldap3_proto doesn't support.
+#[derive(Debug)]
+pub struct LdapResponse {
+    pub msg: LdapMsg,
+    pub sort_result: Option<SortResult>,
+}
+
+/// Reads a BER element with a definite length: returns its tag, its content and the remaining
+/// bytes, or None if the element is incomplete or invalid.
+fn read_element(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, data) = data.split_first()?;
+    let (&first_length_byte, data) = data.split_first()?;
+    let (length, data) = if first_length_byte < 0x80 {
+        (first_length_byte as usize, data)
+    } else {
+        let length_bytes = (first_length_byte & 0x7f) as usize;
+        if length_bytes == 0 || length_bytes > 4 || data.len() < length_bytes {
+            return None;
+        }
+        let (length, data) = data.split_at(length_bytes);
+        (
+            length
+                .iter()
+                .fold(0usize, |acc, &b| (acc << 8) | b as usize),
+            data,
+        )
+    };
+    if data.len() < length {
+        return None;
+    }
+    let (content, rest) = data.split_at(length);
+    Some((tag, content, rest))
+}
+
+fn write_element(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut element = vec![tag];
+    if content.len() < 0x80 {
+        element.push(content.len() as u8);
+    } else {
+        let length = (content.len() as u32).to_be_bytes();
+        let leading_zeros = length.iter().take_while(|&&b| b == 0).count();
+        element.push(0x80 | (length.len() - leading_zeros) as u8);
+        element.extend_from_slice(&length[leading_zeros..]);
+    }
+    element.extend_from_slice(content);
+    element
+}
+
+fn read_expected<'a>(data: &'a [u8], expected_tag: u8) -> Option<(&'a [u8], &'a [u8])> {
+    match read_element(data)? {
+        (tag, content, rest) if tag == expected_tag => Some((content, rest)),
+        _ => None,
+    }
+}
+
+fn parse_integer(content: &[u8]) -> Option<i32> {
+    if content.is_empty() || content.len() > 4 {
+        return None;
+    }
+    // Two's complement, big-endian.
+    let initial = if content[0] & 0x80 != 0 { -1 } else { 0 };
+    Some(
+        content
+            .iter()
+            .fold(initial, |acc: i32, &b| (acc << 8) | b as i32),
+    )
+}
+
+/// Parses the message at the start of the buffer if it is a complete SASL bind request, and
+/// returns it with its length.
+fn parse_sasl_bind(data: &[u8]) -> Option<(i32, SaslBindRequest, usize)> {
+    let (message, rest) = read_expected(data, SEQUENCE_TAG)?;
+    let (msgid, message) = read_expected(message, INTEGER_TAG)?;
+    // The controls that may follow the bind request are ignored.
+    let (bind_request, _) = read_expected(message, BIND_REQUEST_TAG)?;
+    let (_version, bind_request) = read_expected(bind_request, INTEGER_TAG)?;
+    let (dn, bind_request) = read_expected(bind_request, OCTET_STRING_TAG)?;
+    let (sasl, _) = read_expected(bind_request, SASL_CREDENTIALS_TAG)?;
+    let (mechanism, sasl) = read_expected(sasl, OCTET_STRING_TAG)?;
+    let credentials = if sasl.is_empty() {
+        None
+    } else {
+        Some(read_expected(sasl, OCTET_STRING_TAG)?.0.to_vec())
+    };
+    Some((
+        parse_integer(msgid)?,
+        SaslBindRequest {
+            dn: String::from_utf8(dn.to_vec()).ok()?,
+            mechanism: String::from_utf8(mechanism.to_vec()).ok()?,
+            credentials,
+        },
+        data.len() - rest.len(),
+    ))
+}
+
+fn parse_string(content: &[u8]) -> Option<String> {
+    String::from_utf8(content.to_vec()).ok()
+}
+
+/// Parses an attribute value assertion: the attribute and the value.
+fn parse_assertion(content: &[u8]) -> Option<(String, String)> {
+    let (attribute, content) = read_expected(content, OCTET_STRING_TAG)?;
+    let (value, _) = read_expected(content, OCTET_STRING_TAG)?;
+    Some((parse_string(attribute)?, parse_string(value)?))
+}
+
+fn parse_substrings(content: &[u8]) -> Option<LdapFilter> {
+    let (attribute, content) = read_expected(content, OCTET_STRING_TAG)?;
+    let (mut substrings, _) = read_expected(content, SEQUENCE_TAG)?;
+    let mut filter = LdapSubstringFilter::default();
+    while !substrings.is_empty() {
+        let (tag, value, rest) = read_element(substrings)?;
+        let value = parse_string(value)?;
+        match tag {
+            SUBSTRING_INITIAL_TAG => filter.initial = Some(value),
+            SUBSTRING_ANY_TAG => filter.any.push(value),
+            SUBSTRING_FINAL_TAG => filter.final_ = Some(value),
+            _ => return None,
+        }
+        substrings = rest;
+    }
+    Some(LdapFilter::Substring(parse_string(attribute)?, filter))
+}
+
+/// Parses the value of the matched values control, a sequence of simple filter items. The
+/// extensible matches are not supported, and skipped: their attributes are not filtered.
+fn parse_matched_values(value: &[u8]) -> Option<Vec<LdapFilter>> {
+    let (mut items, _) = read_expected(value, SEQUENCE_TAG)?;
+    let mut filters = Vec::new();
+    while !items.is_empty() {
+        let (tag, content, rest) = read_element(items)?;
+        filters.extend(match tag {
+            EQUALITY_MATCH_TAG => {
+                let (attribute, value) = parse_assertion(content)?;
+                Some(LdapFilter::Equality(attribute, value))
+            }
+            SUBSTRINGS_TAG => Some(parse_substrings(content)?),
+            GREATER_OR_EQUAL_TAG => {
+                let (attribute, value) = parse_assertion(content)?;
+                Some(LdapFilter::GreaterOrEqual(attribute, value))
+            }
+            LESS_OR_EQUAL_TAG => {
+                let (attribute, value) = parse_assertion(content)?;
+                Some(LdapFilter::LessOrEqual(attribute, value))
+            }
+            PRESENT_TAG => Some(LdapFilter::Present(parse_string(content)?)),
+            APPROX_MATCH_TAG => {
+                let (attribute, value) = parse_assertion(content)?;
+                Some(LdapFilter::Approx(attribute, value))
+            }
+            _ => None,
+        });
+        items = rest;
+    }
+    Some(filters)
+}
+
+/// Parses the value of the server-side sort control, a sequence of sort keys.
+fn parse_sort_keys(value: &[u8]) -> Option<Vec<SortKey>> {
+    let (mut keys, _) = read_expected(value, SEQUENCE_TAG)?;
+    let mut sort_keys = Vec::new();
+    while !keys.is_empty() {
+        let (key, rest) = read_expected(keys, SEQUENCE_TAG)?;
+        let (attribute, mut options) = read_expected(key, OCTET_STRING_TAG)?;
+        let mut sort_key = SortKey {
+            attribute: parse_string(attribute)?,
+            ordering_rule: None,
+            reverse_order: false,
+        };
+        while !options.is_empty() {
+            let (tag, content, rest) = read_element(options)?;
+            match tag {
+                SORT_ORDERING_RULE_TAG => sort_key.ordering_rule = Some(parse_string(content)?),
+                SORT_REVERSE_ORDER_TAG => sort_key.reverse_order = parse_boolean(content),
+                _ => return None,
+            }
+            options = rest;
+        }
+        sort_keys.push(sort_key);
+        keys = rest;
+    }
+    Some(sort_keys)
+}
+
+fn parse_boolean(content: &[u8]) -> bool {
+    content.iter().any(|&b| b != 0)
+}
+
+/// The controls that ldap3_proto doesn't support.
+#[derive(Debug, Default)]
+struct ExtractedControls {
+    matched_values: Option<Vec<LdapFilter>>,
+    sort: Option<SortRequest>,
+}
+
+/// If the message at the start of the buffer is complete and has a matched values or a sort
+/// control, returns the message without them (for ldap3_proto), the controls and the length of
+/// the original message.
+fn extract_controls(data: &[u8]) -> Option<(Vec<u8>, ExtractedControls, usize)> {
+    let (message, rest) = read_expected(data, SEQUENCE_TAG)?;
+    let (_msgid, _, after_msgid) = read_element(message)?;
+    let (_op, _, after_op) = read_element(after_msgid)?;
+    let (mut controls, _) = read_expected(after_op, CONTROLS_TAG)?;
+    let mut other_controls = Vec::new();
+    let mut extracted = ExtractedControls::default();
+    while !controls.is_empty() {
+        let (control, next) = read_expected(controls, SEQUENCE_TAG)?;
+        let (oid, control_value) = read_expected(control, OCTET_STRING_TAG)?;
+        // The criticality is optional.
+        let (critical, control_value) = match read_expected(control_value, BOOLEAN_TAG) {
+            Some((criticality, rest)) => (parse_boolean(criticality), rest),
+            None => (false, control_value),
+        };
+        if oid == MATCHED_VALUES_OID.as_bytes() {
+            let (value, _) = read_expected(control_value, OCTET_STRING_TAG)?;
+            extracted.matched_values = Some(parse_matched_values(value)?);
+        } else if oid == SORT_REQUEST_OID.as_bytes() {
+            let (value, _) = read_expected(control_value, OCTET_STRING_TAG)?;
+            extracted.sort = Some(SortRequest {
+                keys: parse_sort_keys(value)?,
+                critical,
+            });
+        } else {
+            other_controls.extend_from_slice(&controls[..controls.len() - next.len()]);
+        }
+        controls = next;
+    }
+    if extracted.matched_values.is_none() && extracted.sort.is_none() {
+        return None;
+    }
+    let mut content = message[..message.len() - after_op.len()].to_vec();
+    if !other_controls.is_empty() {
+        content.extend(write_element(CONTROLS_TAG, &other_controls));
+    }
+    Some((
+        write_element(SEQUENCE_TAG, &content),
+        extracted,
+        data.len() - rest.len(),
+    ))
+}
+
+/// Adds a control to an encoded message, after its other controls.
+fn add_control(message: &[u8], control: &[u8]) -> Option<Vec<u8>> {
+    let (message, _) = read_expected(message, SEQUENCE_TAG)?;
+    let (_msgid, _, after_msgid) = read_element(message)?;
+    let (_op, _, after_op) = read_element(after_msgid)?;
+    let mut controls = read_expected(after_op, CONTROLS_TAG)
+        .map(|(controls, _)| controls.to_vec())
+        .unwrap_or_default();
+    controls.extend_from_slice(control);
+    let mut content = message[..message.len() - after_op.len()].to_vec();
+    content.extend(write_element(CONTROLS_TAG, &controls));
+    Some(write_element(SEQUENCE_TAG, &content))
+}
+
+pub struct LldapCodec;
+
+impl Decoder for LldapCodec {
+    type Item = LdapRequest;
+    type Error = std::io::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if let Some((msgid, request, length)) = parse_sasl_bind(buf) {
+            buf.advance(length);
+            return Ok(Some(LdapRequest::SaslBind { msgid, request }));
+        }
+        if let Some((message, controls, length)) = extract_controls(buf) {
+            buf.advance(length);
+            let msg = LdapCodec
+                .decode(&mut BytesMut::from(message.as_slice()))?
+                .ok_or_else(|| {
+                    std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid LDAP message")
+                })?;
+            return Ok(Some(LdapRequest::Message {
+                msg,
+                matched_values: controls.matched_values.unwrap_or_default(),
+                sort: controls.sort,
+            }));
+        }
+        Ok(LdapCodec.decode(buf)?.map(|msg| LdapRequest::Message {
+            msg,
+            matched_values: vec![],
+            sort: None,
+        }))
+    }
+}
+
+impl Encoder<LdapResponse> for LldapCodec {
+    type Error = std::io::Error;
+
+    fn encode(&mut self, response: LdapResponse, buf: &mut BytesMut) -> Result<(), Self::Error> {
+        let sort_result = match response.sort_result {
+            Some(sort_result) => sort_result,
+            None => return LdapCodec.encode(response.msg, buf),
+        };
+        let mut message = BytesMut::new();
+        LdapCodec.encode(response.msg, &mut message)?;
+        let message = add_control(&message, &sort_result.to_control()).ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid LDAP response")
+        })?;
+        buf.extend_from_slice(&message);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sasl_bind() {
+        let credentials = b"\0bob\0pass";
+        let mut sasl = vec![OCTET_STRING_TAG, 5];
+        sasl.extend_from_slice(b"PLAIN");
+        sasl.extend_from_slice(&[OCTET_STRING_TAG, credentials.len() as u8]);
+        sasl.extend_from_slice(credentials);
+        let mut bind = vec![INTEGER_TAG, 1, 3, OCTET_STRING_TAG, 0, SASL_CREDENTIALS_TAG];
+        bind.push(sasl.len() as u8);
+        bind.extend(sasl);
+        let mut message = vec![INTEGER_TAG, 1, 2, BIND_REQUEST_TAG, bind.len() as u8];
+        message.extend(bind);
+        let mut data = vec![SEQUENCE_TAG, message.len() as u8];
+        data.extend(message);
+        let message_length = data.len();
+        // The next message stays in the buffer.
+        data.extend_from_slice(&[SEQUENCE_TAG, 0]);
+
+        let (msgid, request, length) = parse_sasl_bind(&data).unwrap();
+        assert_eq!(msgid, 2);
+        assert_eq!(request.dn, "");
+        assert_eq!(request.mechanism, "PLAIN");
+        assert_eq!(request.credentials.as_deref(), Some(&credentials[..]));
+        assert_eq!(length, message_length);
+
+        // Incomplete message.
+        assert!(parse_sasl_bind(&data[..message_length - 1]).is_none());
+    }
+
+    #[test]
+    fn test_parse_simple_bind() {
+        // A simple bind is left to ldap3_proto.
+        let data = [
+            SEQUENCE_TAG,
+            12,
+            INTEGER_TAG,
+            1,
+            1,
+            BIND_REQUEST_TAG,
+            7,
+            INTEGER_TAG,
+            1,
+            3,
+            OCTET_STRING_TAG,
+            0,
+            0x80,
+            0,
+        ];
+        assert!(parse_sasl_bind(&data).is_none());
+    }
+
+    #[test]
+    fn test_extract_matched_values() {
+        // (mail=*@example.com) and (cn=bob).
+        let mut substrings = vec![OCTET_STRING_TAG, 4];
+        substrings.extend_from_slice(b"mail");
+        substrings.extend_from_slice(&[SEQUENCE_TAG, 14, SUBSTRING_FINAL_TAG, 12]);
+        substrings.extend_from_slice(b"@example.com");
+        let mut items = vec![SUBSTRINGS_TAG, substrings.len() as u8];
+        items.extend(substrings);
+        items.extend_from_slice(&[EQUALITY_MATCH_TAG, 9, OCTET_STRING_TAG, 2]);
+        items.extend_from_slice(b"cn");
+        items.extend_from_slice(&[OCTET_STRING_TAG, 3]);
+        items.extend_from_slice(b"bob");
+        let value = write_element(OCTET_STRING_TAG, &write_element(SEQUENCE_TAG, &items));
+        let mut matched_values = write_element(OCTET_STRING_TAG, MATCHED_VALUES_OID.as_bytes());
+        matched_values.extend_from_slice(&[BOOLEAN_TAG, 1, 0xff]);
+        matched_values.extend(value);
+        let other_control = write_element(
+            SEQUENCE_TAG,
+            &write_element(OCTET_STRING_TAG, b"2.16.840.1.113730.3.4.2"),
+        );
+        let mut controls = write_element(SEQUENCE_TAG, &matched_values);
+        controls.extend_from_slice(&other_control);
+        // An unbind request, with the controls.
+        let mut message = vec![INTEGER_TAG, 1, 2, 0x42, 0];
+        message.extend(write_element(CONTROLS_TAG, &controls));
+        let mut data = write_element(SEQUENCE_TAG, &message);
+        let message_length = data.len();
+        data.extend_from_slice(&[SEQUENCE_TAG, 0]);
+
+        let (stripped, controls, length) = extract_controls(&data).unwrap();
+        let mut expected = vec![INTEGER_TAG, 1, 2, 0x42, 0];
+        expected.extend(write_element(CONTROLS_TAG, &other_control));
+        assert_eq!(stripped, write_element(SEQUENCE_TAG, &expected));
+        assert_eq!(controls.sort, None);
+        assert_eq!(
+            controls.matched_values.unwrap(),
+            vec![
+                LdapFilter::Substring(
+                    "mail".to_owned(),
+                    LdapSubstringFilter {
+                        initial: None,
+                        any: vec![],
+                        final_: Some("@example.com".to_owned()),
+                    }
+                ),
+                LdapFilter::Equality("cn".to_owned(), "bob".to_owned()),
+            ]
+        );
+        assert_eq!(length, message_length);
+
+        // Without the control, the message is left to ldap3_proto.
+        assert!(extract_controls(&write_element(SEQUENCE_TAG, &expected)).is_none());
+    }
+
+    #[test]
+    fn test_extract_sort_control() {
+        // sn, then uid in reverse order.
+        let mut keys = write_element(SEQUENCE_TAG, &write_element(OCTET_STRING_TAG, b"sn"));
+        let mut uid_key = write_element(OCTET_STRING_TAG, b"uid");
+        uid_key.extend_from_slice(&[SORT_REVERSE_ORDER_TAG, 1, 0xff]);
+        keys.extend(write_element(SEQUENCE_TAG, &uid_key));
+        let mut sort = write_element(OCTET_STRING_TAG, SORT_REQUEST_OID.as_bytes());
+        sort.extend_from_slice(&[BOOLEAN_TAG, 1, 0xff]);
+        sort.extend(write_element(
+            OCTET_STRING_TAG,
+            &write_element(SEQUENCE_TAG, &keys),
+        ));
+        let mut message = vec![INTEGER_TAG, 1, 2, 0x42, 0];
+        message.extend(write_element(
+            CONTROLS_TAG,
+            &write_element(SEQUENCE_TAG, &sort),
+        ));
+        let data = write_element(SEQUENCE_TAG, &message);
+
+        let (stripped, controls, length) = extract_controls(&data).unwrap();
+        assert_eq!(
+            stripped,
+            write_element(SEQUENCE_TAG, &[INTEGER_TAG, 1, 2, 0x42, 0])
+        );
+        assert_eq!(controls.matched_values, None);
+        assert_eq!(
+            controls.sort,
+            Some(SortRequest {
+                keys: vec![
+                    SortKey {
+                        attribute: "sn".to_owned(),
+                        ordering_rule: None,
+                        reverse_order: false,
+                    },
+                    SortKey {
+                        attribute: "uid".to_owned(),
+                        ordering_rule: None,
+                        reverse_order: true,
+                    },
+                ],
+                critical: true,
+            })
+        );
+        assert_eq!(length, data.len());
+    }
+
+    #[test]
+    fn test_add_sort_result_control() {
+        let message = write_element(SEQUENCE_TAG, &[INTEGER_TAG, 1, 2, 0x65, 0]);
+        let sort_result = SortResult {
+            code: LdapResultCode::UnwillingToPerform,
+            attribute: Some("o".to_owned()),
+        };
+        let result = write_element(
+            SEQUENCE_TAG,
+            &[ENUMERATED_TAG, 1, 53, SORT_RESULT_ATTRIBUTE_TAG, 1, b'o'],
+        );
+        let mut control = write_element(OCTET_STRING_TAG, SORT_RESPONSE_OID.as_bytes());
+        control.extend(write_element(OCTET_STRING_TAG, &result));
+        let mut expected = vec![INTEGER_TAG, 1, 2, 0x65, 0];
+        expected.extend(write_element(
+            CONTROLS_TAG,
+            &write_element(SEQUENCE_TAG, &control),
+        ));
+        assert_eq!(
+            add_control(&message, &sort_result.to_control()).unwrap(),
+            write_element(SEQUENCE_TAG, &expected)
+        );
+    }
+
+    #[test]
+    fn test_write_element_long_length() {
+        let content = vec![b'a'; 300];
+        let element = write_element(OCTET_STRING_TAG, &content);
+        assert_eq!(&element[..4], &[OCTET_STRING_TAG, 0x82, 1, 44]);
+        let (tag, read_content, rest) = read_element(&element).unwrap();
+        assert_eq!(tag, OCTET_STRING_TAG);
+        assert_eq!(read_content, &content[..]);
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn test_read_element_long_length() {
+        let mut data = vec![OCTET_STRING_TAG, 0x81, 200];
+        data.extend(std::iter::repeat(b'a').take(200));
+        let (tag, content, rest) = read_element(&data).unwrap();
+        assert_eq!(tag, OCTET_STRING_TAG);
+        assert_eq!(content.len(), 200);
+        assert!(rest.is_empty());
+        assert!(read_element(&data[..100]).is_none());
+    }
+
+    #[test]
+    fn test_parse_integer() {
+        assert_eq!(parse_integer(&[0x01, 0x00]), Some(256));
+        assert_eq!(parse_integer(&[0xff]), Some(-1));
+        assert_eq!(parse_integer(&[]), None);
+    }
+}
@@ -1,35 +1,49 @@
 use crate::{
     domain::{
         handler::{
-            BackendHandler, BindRequest, CreateUserRequest, LoginHandler, SchemaBackendHandler,
+            BackendHandler, BindRequest, CreateUserRequest, LoginHandler, Schema,
+            SchemaBackendHandler, SubStringFilter, UpdateUserRequest, UserBackendHandler,
+            UserListerBackendHandler, UserRequestFilter, UserSortKey,
         },
         ldap::{
-            error::{LdapError, LdapResult},
-            group::{convert_groups_to_ldap_op, get_groups_list},
-            user::{convert_users_to_ldap_op, get_user_list},
+            error::{backend_error_code, LdapError, LdapResult},
+            group::{convert_groups_to_ldap_op, get_groups_list, get_member_emails},
+            user::{convert_users_to_ldap_op, get_user_list, get_user_sort_column},
             utils::{
-                get_user_id_from_distinguished_name, is_subtree, parse_distinguished_name, LdapInfo,
+                convert_custom_attribute_values, get_api_token_name_from_distinguished_name,
+                get_custom_attribute, get_email_from_bind_dn, get_user_id_from_distinguished_name,
+                is_subtree, map_user_field_with_schema, parse_distinguished_name, LdapInfo,
+                LdapReader, UserFieldType,
             },
         },
         opaque_handler::OpaqueHandler,
-        types::{Group, JpegPhoto, UserAndGroups, UserId},
+        types::{AttributeValue, Group, JpegPhoto, UserAndGroups, UserColumn, UserId},
     },
-    infra::access_control::{
-        AccessControlledBackendHandler, AdminBackendHandler, UserAndGroupListerBackendHandler,
-        UserReadableBackendHandler, ValidationResults,
+    infra::{
+        access_control::{
+            AccessControlledBackendHandler, AdminBackendHandler, UserAndGroupListerBackendHandler,
+            UserReadableBackendHandler, UserWriteableBackendHandler, ValidationResults,
+        },
+        ldap_codec::{
+            SaslBindRequest, SortRequest, SortResult, MATCHED_VALUES_OID, SORT_REQUEST_OID,
+        },
     },
 };
 use anyhow::Result;
 use ldap3_proto::proto::{
     LdapAddRequest, LdapBindCred, LdapBindRequest, LdapBindResponse, LdapCompareRequest,
-    LdapDerefAliases, LdapExtendedRequest, LdapExtendedResponse, LdapFilter, LdapModify,
-    LdapModifyRequest, LdapModifyType, LdapOp, LdapPartialAttribute, LdapPasswordModifyRequest,
-    LdapResult as LdapResultOp, LdapResultCode, LdapSearchRequest, LdapSearchResultEntry,
-    LdapSearchScope,
+    LdapControl, LdapDerefAliases, LdapExtendedRequest, LdapExtendedResponse, LdapFilter,
+    LdapModify, LdapModifyRequest, LdapModifyType, LdapOp, LdapPartialAttribute,
+    LdapPasswordModifyRequest, LdapResult as LdapResultOp, LdapResultCode, LdapSearchRequest,
+    LdapSearchResultEntry, LdapSearchScope,
 };
-use std::collections::HashMap;
+use std::{collections::HashMap, time::Duration};
 use tracing::{debug, instrument, warn};
 
+const WHOAMI_OID: &str = "1.3.6.1.4.1.4203.1.11.3";
+const START_TLS_OID: &str = "1.3.6.1.4.1.1466.20037";
+const PAGED_RESULTS_OID: &str = "1.2.840.113556.1.4.319";
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 struct LdapDn(String);
 
@@ -40,34 +54,28 @@ enum SearchScope {
     Groups,
     User(LdapFilter),
     Group(LdapFilter),
+    /// The base is in the tree, but there is no entry at the requested depth.
+    Empty,
     Unknown,
     Invalid,
 }
 
-fn get_search_scope(base_dn: &[(String, String)], dn_parts: &[(String, String)]) -> SearchScope {
-    let base_dn_len = base_dn.len();
-    if !is_subtree(dn_parts, base_dn) {
+fn get_search_scope(ldap_info: &LdapInfo, dn_parts: &[(String, String)]) -> SearchScope {
+    let base_dn_len = ldap_info.base_dn.len();
+    if !is_subtree(dn_parts, &ldap_info.base_dn) {
         SearchScope::Invalid
     } else if dn_parts.len() == base_dn_len {
         SearchScope::Global
-    } else if dn_parts.len() == base_dn_len + 1
-        && dn_parts[0] == ("ou".to_string(), "people".to_string())
-    {
+    } else if dn_parts.len() == base_dn_len + 1 && dn_parts[0] == ldap_info.people_ou {
         SearchScope::Users
-    } else if dn_parts.len() == base_dn_len + 1
-        && dn_parts[0] == ("ou".to_string(), "groups".to_string())
-    {
+    } else if dn_parts.len() == base_dn_len + 1 && dn_parts[0] == ldap_info.groups_ou {
         SearchScope::Groups
-    } else if dn_parts.len() == base_dn_len + 2
-        && dn_parts[1] == ("ou".to_string(), "people".to_string())
-    {
+    } else if dn_parts.len() == base_dn_len + 2 && dn_parts[1] == ldap_info.people_ou {
         SearchScope::User(LdapFilter::Equality(
             dn_parts[0].0.clone(),
             dn_parts[0].1.clone(),
         ))
-    } else if dn_parts.len() == base_dn_len + 2
-        && dn_parts[1] == ("ou".to_string(), "groups".to_string())
-    {
+    } else if dn_parts.len() == base_dn_len + 2 && dn_parts[1] == ldap_info.groups_ou {
         SearchScope::Group(LdapFilter::Equality(
             dn_parts[0].0.clone(),
             dn_parts[0].1.clone(),
@@ -77,6 +85,118 @@ fn get_search_scope(base_dn: &[(String, String)], dn_parts: &[(String, String)])
     }
 }
 
+/// Only the users and groups are entries: the base DN and the OUs are never returned, so e.g. a
+/// base search on "ou=people" or a one-level search on a user's DN returns nothing.
+fn restrict_search_scope(scope: SearchScope, depth: &LdapSearchScope) -> SearchScope {
+    match (depth, scope) {
+        (LdapSearchScope::Base, SearchScope::Global | SearchScope::Users | SearchScope::Groups) => {
+            SearchScope::Empty
+        }
+        (
+            LdapSearchScope::OneLevel,
+            SearchScope::Global | SearchScope::User(_) | SearchScope::Group(_),
+        ) => SearchScope::Empty,
+        (_, scope) => scope,
+    }
+}
+
+fn make_container_entry(dn: String, attributes: Vec<(&str, Vec<&str>)>) -> LdapSearchResultEntry {
+    LdapSearchResultEntry {
+        dn,
+        attributes: attributes
+            .into_iter()
+            .map(|(atype, vals)| LdapPartialAttribute {
+                atype: atype.to_owned(),
+                vals: vals.into_iter().map(|v| v.as_bytes().to_vec()).collect(),
+            })
+            .collect(),
+    }
+}
+
+fn container_matches_filter(entry: &LdapSearchResultEntry, filter: &LdapFilter) -> bool {
+    let get_values = |attribute: &str| {
+        entry
+            .attributes
+            .iter()
+            .find(|a| a.atype.eq_ignore_ascii_case(attribute))
+            .map(|a| &a.vals)
+    };
+    match filter {
+        LdapFilter::And(filters) => filters.iter().all(|f| container_matches_filter(entry, f)),
+        LdapFilter::Or(filters) => filters.iter().any(|f| container_matches_filter(entry, f)),
+        LdapFilter::Not(filter) => !container_matches_filter(entry, filter),
+        LdapFilter::Present(attribute) => get_values(attribute).is_some(),
+        LdapFilter::Equality(attribute, value) => get_values(attribute).map_or(false, |vals| {
+            vals.iter()
+                .any(|v| v.eq_ignore_ascii_case(value.as_bytes()))
+        }),
+        _ => false,
+    }
+}
+
+/// The entries of the base DN and of the OUs, for the clients browsing the tree. They are only
+/// returned by the base and one-level searches.
+fn get_container_entries(ldap_info: &LdapInfo, request: &LdapSearchRequest) -> Vec<LdapOp> {
+    let dn_parts = match parse_distinguished_name(&request.base.to_ascii_lowercase()) {
+        Ok(dn_parts) => dn_parts,
+        Err(_) => return Vec::new(),
+    };
+    let make_ou_entry = |dn: String, (_, ou): &(String, String)| {
+        make_container_entry(
+            dn,
+            vec![
+                ("objectClass", vec!["top", "organizationalUnit"]),
+                ("ou", vec![ou.as_str()]),
+            ],
+        )
+    };
+    let people_entry = || make_ou_entry(ldap_info.people_dn(), &ldap_info.people_ou);
+    let groups_entry = || make_ou_entry(ldap_info.groups_dn(), &ldap_info.groups_ou);
+    let entries = match (&request.scope, get_search_scope(ldap_info, &dn_parts)) {
+        (LdapSearchScope::Base, SearchScope::Global) => match ldap_info.base_dn.first() {
+            Some((rdn_type, rdn_value)) => {
+                let is_dc = rdn_type == "dc";
+                let mut object_classes = vec!["top", "organization"];
+                if is_dc {
+                    object_classes.push("dcObject");
+                }
+                let mut attributes = vec![
+                    ("objectClass", object_classes),
+                    ("o", vec![rdn_value.as_str()]),
+                ];
+                if is_dc {
+                    attributes.push(("dc", vec![rdn_value.as_str()]));
+                }
+                vec![make_container_entry(
+                    ldap_info.base_dn_str.clone(),
+                    attributes,
+                )]
+            }
+            None => Vec::new(),
+        },
+        (LdapSearchScope::Base, SearchScope::Users) => vec![people_entry()],
+        (LdapSearchScope::Base, SearchScope::Groups) => vec![groups_entry()],
+        (LdapSearchScope::OneLevel, SearchScope::Global) => vec![people_entry(), groups_entry()],
+        _ => Vec::new(),
+    };
+    let all_attributes = request.attrs.is_empty() || request.attrs.iter().any(|a| a == "*");
+    entries
+        .into_iter()
+        .filter(|entry| container_matches_filter(entry, &request.filter))
+        .map(|mut entry| {
+            if !all_attributes {
+                entry.attributes.retain(|attribute| {
+                    request
+                        .attrs
+                        .iter()
+                        .any(|a| a.eq_ignore_ascii_case(&attribute.atype))
+                });
+            }
+            LdapOp::SearchResultEntry(entry)
+        })
+        .collect()
+}
+
 fn make_search_request<S: Into<String>>(
     base: &str,
     filter: LdapFilter,
@@ -84,7 +204,7 @@ fn make_search_request<S: Into<String>>(
 ) -> LdapSearchRequest {
     LdapSearchRequest {
         base: base.to_string(),
-        scope: LdapSearchScope::Base,
+        scope: LdapSearchScope::Subtree,
         aliases: LdapDerefAliases::Never,
         sizelimit: 0,
         timelimit: 0,
@@ -94,6 +214,117 @@ fn make_search_request<S: Into<String>>(
     }
 }
 
+/// Whether the filter only uses the attributes visible to the anonymous searches.
+fn is_anonymous_filter_allowed(filter: &LdapFilter, allowed: &[String]) -> bool {
+    let is_allowed = |attribute: &str| {
+        let attribute = attribute.to_ascii_lowercase();
+        attribute == "objectclass" || allowed.contains(&attribute)
+    };
+    match filter {
+        LdapFilter::And(filters) | LdapFilter::Or(filters) => filters
+            .iter()
+            .all(|f| is_anonymous_filter_allowed(f, allowed)),
+        LdapFilter::Not(filter) => is_anonymous_filter_allowed(filter, allowed),
+        LdapFilter::Equality(attribute, _)
+        | LdapFilter::Substring(attribute, _)
+        | LdapFilter::GreaterOrEqual(attribute, _)
+        | LdapFilter::LessOrEqual(attribute, _)
+        | LdapFilter::Approx(attribute, _)
+        | LdapFilter::Present(attribute) => is_allowed(attribute),
+        LdapFilter::Extensible(assertion) => assertion.type_.as_deref().map_or(false, is_allowed),
+    }
+}
+
+/// Removes the attributes that the anonymous searches can't read from the entries.
+fn restrict_to_anonymous_attributes(results: &mut [LdapOp], allowed: &[String]) {
+    for result in results {
+        if let LdapOp::SearchResultEntry(entry) = result {
+            entry
+                .attributes
+                .retain(|a| allowed.contains(&a.atype.to_ascii_lowercase()));
+        }
+    }
+}
+
+fn matched_value_filter_attribute(filter: &LdapFilter) -> Option<&str> {
+    match filter {
+        LdapFilter::Equality(attribute, _)
+        | LdapFilter::Substring(attribute, _)
+        | LdapFilter::GreaterOrEqual(attribute, _)
+        | LdapFilter::LessOrEqual(attribute, _)
+        | LdapFilter::Approx(attribute, _)
+        | LdapFilter::Present(attribute) => Some(attribute),
+        _ => None,
+    }
+}
+
+fn value_matches_filter(value: &[u8], filter: &LdapFilter) -> bool {
+    let value = String::from_utf8_lossy(value);
+    match filter {
+        LdapFilter::Equality(_, expected) | LdapFilter::Approx(_, expected) => {
+            value.eq_ignore_ascii_case(expected)
+        }
+        LdapFilter::Substring(_, substring) => {
+            SubStringFilter::from(substring.clone()).matches(&value)
+        }
+        LdapFilter::GreaterOrEqual(_, bound) => {
+            value.to_ascii_lowercase() >= bound.to_ascii_lowercase()
+        }
+        LdapFilter::LessOrEqual(_, bound) => {
+            value.to_ascii_lowercase() <= bound.to_ascii_lowercase()
+        }
+        _ => true,
+    }
+}
+
+/// Applies the matched values control (RFC 3876): the attributes mentioned by the filters only
+/// keep the values matching one of them, possibly none. The other attributes are unchanged.
+pub fn filter_matched_values(results: &mut [LdapOp], filters: &[LdapFilter]) {
+    if filters.is_empty() {
+        return;
+    }
+    for result in results {
+        if let LdapOp::SearchResultEntry(entry) = result {
+            for attribute in &mut entry.attributes {
+                let attribute_filters: Vec<_> = filters
+                    .iter()
+                    .filter(|f| {
+                        matched_value_filter_attribute(f)
+                            .map_or(false, |a| a.eq_ignore_ascii_case(&attribute.atype))
+                    })
+                    .collect();
+                if !attribute_filters.is_empty() {
+                    attribute
+                        .vals
+                        .retain(|v| attribute_filters.iter().any(|f| value_matches_filter(v, f)));
+                }
+            }
+        }
+    }
+}
+
+/// Rewrites the DNs of the entries with the case used by the client: the bind DN for the bound
+/// user's entry, and the search base for the suffix of the others.
+fn preserve_dn_case(results: &mut [LdapOp], base: &str, bind_dn: Option<&str>) {
+    let lowercase_base = base.to_ascii_lowercase();
+    for result in results {
+        if let LdapOp::SearchResultEntry(entry) = result {
+            if let Some(bind_dn) = bind_dn.filter(|dn| dn.eq_ignore_ascii_case(&entry.dn)) {
+                entry.dn = bind_dn.to_owned();
+                continue;
+            }
+            let dn = entry.dn.to_ascii_lowercase();
+            let is_under_base = match dn.strip_suffix(&lowercase_base) {
+                Some(rdns) => !base.is_empty() && (rdns.is_empty() || rdns.ends_with(',')),
+                None => false,
+            };
+            if is_under_base {
+                entry.dn = format!("{}{}", &entry.dn[..dn.len() - base.len()], base);
+            }
+        }
+    }
+}
+
 fn make_search_success() -> LdapOp {
     make_search_error(LdapResultCode::Success, "".to_string())
 }
@@ -116,6 +347,27 @@ fn make_add_error(code: LdapResultCode, message: String) -> LdapOp {
     })
 }
 
+pub fn make_bind_response(code: LdapResultCode, message: String) -> LdapOp {
+    LdapOp::BindResponse(LdapBindResponse {
+        res: LdapResultOp {
+            code,
+            matcheddn: "".to_string(),
+            message,
+            referral: vec![],
+        },
+        saslcreds: None,
+    })
+}
+
+fn make_referral_result(url: String) -> LdapResultOp {
+    LdapResultOp {
+        code: LdapResultCode::Referral,
+        matcheddn: "".to_string(),
+        message: "".to_string(),
+        referral: vec![url],
+    }
+}
+
 fn make_extended_response(code: LdapResultCode, message: String) -> LdapOp {
     LdapOp::ExtendedResponse(LdapExtendedResponse {
         res: LdapResultOp {
@@ -129,6 +381,37 @@ fn make_extended_response(code: LdapResultCode, message: String) -> LdapOp {
     })
 }
 
+/// Response to a password modify request, carrying the generated password (RFC 3062).
+fn make_password_modify_response(generated_password: &str) -> LdapOp {
+    // PasswdModifyResponseValue ::= SEQUENCE { genPasswd [0] OCTET STRING OPTIONAL }
+    let password = generated_password.as_bytes();
+    assert!(
+        password.len() < 126,
+        "Generated password too long for BER short-form"
+    );
+    let mut value = vec![0x30, password.len() as u8 + 2, 0x80, password.len() as u8];
+    value.extend_from_slice(password);
+    LdapOp::ExtendedResponse(LdapExtendedResponse {
+        res: LdapResultOp {
+            code: LdapResultCode::Success,
+            matcheddn: "".to_string(),
+            message: "".to_string(),
+            referral: vec![],
+        },
+        name: None,
+        value: Some(value),
+    })
+}
+
+fn generate_password() -> String {
+    use rand::{distributions::Alphanumeric, Rng};
+    rand::rngs::OsRng
+        .sample_iter(&Alphanumeric)
+        .take(20)
+        .map(char::from)
+        .collect()
+}
+
 fn make_modify_response(code: LdapResultCode, message: String) -> LdapOp {
     LdapOp::ModifyResponse(LdapResultOp {
         code,
@@ -138,7 +421,15 @@ fn make_modify_response(code: LdapResultCode, message: String) -> LdapOp {
     })
 }
 
-fn root_dse_response(base_dn: &str) -> LdapOp {
+fn root_dse_response(ldap_info: &LdapInfo, start_tls_available: bool) -> LdapOp {
+    // Password modification and "who am I?" extensions.
+    let mut extensions = vec![
+        b"1.3.6.1.4.1.4203.1.11.1".to_vec(),
+        WHOAMI_OID.as_bytes().to_vec(),
+    ];
+    if start_tls_available {
+        extensions.push(START_TLS_OID.as_bytes().to_vec());
+    }
     LdapOp::SearchResultEntry(LdapSearchResultEntry {
         dn: "".to_string(),
         attributes: vec![
@@ -162,12 +453,16 @@ fn root_dse_response(base_dn: &str) -> LdapOp {
             },
             LdapPartialAttribute {
                 atype: "supportedExtension".to_string(),
-                // Password modification extension.
-                vals: vec![b"1.3.6.1.4.1.4203.1.11.1".to_vec()],
+                vals: extensions,
             },
             LdapPartialAttribute {
                 atype: "supportedControl".to_string(),
-                vals: vec![],
+                // Paged results, matched values and server-side sort controls.
+                vals: vec![
+                    PAGED_RESULTS_OID.as_bytes().to_vec(),
+                    MATCHED_VALUES_OID.as_bytes().to_vec(),
+                    SORT_REQUEST_OID.as_bytes().to_vec(),
+                ],
             },
             LdapPartialAttribute {
                 atype: "supportedFeatures".to_string(),
@@ -176,11 +471,14 @@ fn root_dse_response(base_dn: &str) -> LdapOp {
             },
             LdapPartialAttribute {
                 atype: "defaultNamingContext".to_string(),
-                vals: vec![base_dn.to_string().into_bytes()],
+                vals: vec![ldap_info.base_dn_str.clone().into_bytes()],
             },
             LdapPartialAttribute {
                 atype: "namingContexts".to_string(),
-                vals: vec![base_dn.to_string().into_bytes()],
+                vals: ldap_info
+                    .all_base_dns()
+                    .map(|dn| dn.as_bytes().to_vec())
+                    .collect(),
             },
             LdapPartialAttribute {
                 atype: "isGlobalCatalogReady".to_string(),
@@ -190,10 +488,73 @@ fn root_dse_response(base_dn: &str) -> LdapOp {
     })
 }
 
+/// The sort keys of the users, or the sort result for the first unsupported key.
+fn get_user_sort_keys(
+    ldap_info: &LdapInfo,
+    sort: &SortRequest,
+) -> Result<Vec<UserSortKey>, SortResult> {
+    sort.keys
+        .iter()
+        .map(|key| {
+            let unsupported = |code| SortResult {
+                code,
+                attribute: Some(key.attribute.clone()),
+            };
+            if key.ordering_rule.is_some() {
+                return Err(unsupported(LdapResultCode::InappropriateMatching));
+            }
+            match get_user_sort_column(ldap_info, &key.attribute) {
+                Some(column) => Ok(UserSortKey {
+                    column,
+                    descending: key.reverse_order,
+                }),
+                None => Err(unsupported(LdapResultCode::UnwillingToPerform)),
+            }
+        })
+        .collect()
+}
+
+fn has_next_page(users: &[UserAndGroups], page: Option<(u64, u64)>) -> bool {
+    page.map(|(_, limit)| users.len() as u64 > limit)
+        .unwrap_or(false)
+}
+
+/// Paged searches kept per connection: beyond that, the oldest one is dropped, so that a client
+/// that doesn't finish its searches can't grow the memory without bounds.
+const MAX_PAGED_SEARCHES: usize = 16;
+
+/// Position of a paged search, to resume it on the next request.
+struct PagedSearchCursor {
+    base: String,
+    filter: LdapFilter,
+    sort: Vec<UserSortKey>,
+    offset: u64,
+}
+
+/// Whether the connection is encrypted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TlsStatus {
+    /// Plain connection, without a certificate to upgrade it.
+    Unavailable,
+    /// Plain connection, that can be upgraded with StartTLS.
+    StartTlsAvailable,
+    /// StartTLS was accepted: the TLS handshake must happen before reading the next request.
+    Upgrading,
+    Encrypted,
+}
+
 pub struct LdapHandler<Backend> {
     user_info: Option<ValidationResults>,
+    /// Groups of the bound user, for the attribute access rules.
+    user_groups: Vec<String>,
+    /// DN of the bound user, as sent by the client.
+    bind_dn: Option<String>,
+    tls_status: TlsStatus,
     backend_handler: AccessControlledBackendHandler<Backend>,
     ldap_info: LdapInfo,
+    /// Paged searches in progress, by cookie.
+    paged_searches: HashMap<Vec<u8>, PagedSearchCursor>,
+    next_paged_search_cookie: u64,
 }
 
 impl<Backend: LoginHandler> LdapHandler<Backend> {
@@ -211,25 +572,17 @@ impl<Backend: OpaqueHandler> LdapHandler<Backend> {
 impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend> {
     pub fn new(
         backend_handler: AccessControlledBackendHandler<Backend>,
-        mut ldap_base_dn: String,
-        ignored_user_attributes: Vec<String>,
-        ignored_group_attributes: Vec<String>,
+        ldap_info: LdapInfo,
     ) -> Self {
-        ldap_base_dn.make_ascii_lowercase();
         Self {
             user_info: None,
+            user_groups: Vec::new(),
+            bind_dn: None,
+            tls_status: TlsStatus::Unavailable,
             backend_handler,
-            ldap_info: LdapInfo {
-                base_dn: parse_distinguished_name(&ldap_base_dn).unwrap_or_else(|_| {
-                    panic!(
-                        "Invalid value for ldap_base_dn in configuration: {}",
-                        ldap_base_dn
-                    )
-                }),
-                base_dn_str: ldap_base_dn,
-                ignored_user_attributes,
-                ignored_group_attributes,
-            },
+            ldap_info,
+            paged_searches: HashMap::new(),
+            next_paged_search_cookie: 0,
         }
     }
 
@@ -237,45 +590,237 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
     pub fn new_for_tests(backend_handler: Backend, ldap_base_dn: &str) -> Self {
         Self::new(
             AccessControlledBackendHandler::new(backend_handler),
-            ldap_base_dn.to_string(),
-            vec![],
-            vec![],
+            LdapInfo::new(&crate::infra::configuration::Configuration {
+                ldap_base_dn: ldap_base_dn.to_string(),
+                ..crate::infra::configuration::ConfigurationBuilder::for_tests()
+            }),
         )
     }
 
+    pub fn tls_status(&self) -> TlsStatus {
+        self.tls_status
+    }
+
+    pub fn set_tls_status(&mut self, tls_status: TlsStatus) {
+        self.tls_status = tls_status;
+    }
+
+    fn check_tls_for_bind(&self) -> Result<(), (LdapResultCode, String)> {
+        if self.ldap_info.require_tls_for_bind && self.tls_status != TlsStatus::Encrypted {
+            return Err((
+                LdapResultCode::ConfidentialityRequired,
+                "TLS is required to bind, use StartTLS or LDAPS".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
     #[instrument(skip_all, level = "debug")]
     pub async fn do_bind(&mut self, request: &LdapBindRequest) -> (LdapResultCode, String) {
         debug!("DN: {}", &request.dn);
-        let user_id = match get_user_id_from_distinguished_name(
-            &request.dn.to_ascii_lowercase(),
-            &self.ldap_info.base_dn,
-            &self.ldap_info.base_dn_str,
-        ) {
+        self.bind_dn = None;
+        let LdapBindCred::Simple(password) = &request.cred;
+        if request.dn.is_empty() && password.is_empty() {
+            return self.do_anonymous_bind();
+        }
+        if let Err(e) = self.check_tls_for_bind() {
+            return e;
+        }
+        let dn = request.dn.to_ascii_lowercase();
+        if let Ok(token_name) = get_api_token_name_from_distinguished_name(&dn, &self.ldap_info) {
+            return self.do_api_token_bind(&token_name, password).await;
+        }
+        let ldap_info = self.ldap_info.context_for(&dn);
+        let user_id = match get_user_id_from_distinguished_name(&dn, ldap_info) {
             Ok(s) => s,
-            Err(e) => return (LdapResultCode::NamingViolation, e.to_string()),
+            Err(e) => match get_email_from_bind_dn(&dn, ldap_info) {
+                Some(email) => match self.get_user_id_by_email(&email).await {
+                    Some(user_id) => user_id,
+                    None => return self.reject_bind(UserId::new(&email), password).await,
+                },
+                None => return (LdapResultCode::NamingViolation, e.to_string()),
+            },
         };
-        let LdapBindCred::Simple(password) = &request.cred;
+        if let Some(group) = ldap_info.member_of_group.clone() {
+            if !self.is_member_of(&user_id, &group).await {
+                debug!(?user_id, %group, "User outside of the naming context");
+                return self.reject_bind(user_id, password).await;
+            }
+        }
+        let result = self.bind_user(user_id, password).await;
+        if result.0 == LdapResultCode::Success {
+            self.bind_dn = Some(request.dn.clone());
+        }
+        result
+    }
+
+    /// Fails the bind like a wrong password would, taking as long.
+    async fn reject_bind(&self, user_id: UserId, password: &str) -> (LdapResultCode, String) {
+        let _ = self
+            .get_login_handler()
+            .reject_bind(BindRequest {
+                name: user_id,
+                password: password.to_string(),
+            })
+            .await;
+        (LdapResultCode::InvalidCredentials, "".to_string())
+    }
+
+    fn do_anonymous_bind(&mut self) -> (LdapResultCode, String) {
+        if !self.ldap_info.anonymous_bind {
+            return (
+                LdapResultCode::InsufficentAccessRights,
+                "Anonymous binds are not allowed".to_string(),
+            );
+        }
+        self.user_info = None;
+        self.user_groups.clear();
+        (LdapResultCode::Success, "".to_string())
+    }
+
+    /// The user with this email, if there is exactly one.
+    async fn get_user_id_by_email(&self, email: &str) -> Option<UserId> {
+        let users = self
+            .backend_handler
+            .unsafe_get_handler()
+            .list_users(
+                Some(UserRequestFilter::Equality(
+                    UserColumn::Email,
+                    email.to_owned(),
+                )),
+                false,
+            )
+            .await
+            .map_err(|e| warn!("Error while looking up the user by email: {:#}", e))
+            .ok()?;
+        match users.as_slice() {
+            [user] => Some(user.user.user_id.clone()),
+            [] => {
+                debug!(%email, "No user with this email");
+                None
+            }
+            _ => {
+                warn!(%email, "Several users with the same email, refusing the bind");
+                None
+            }
+        }
+    }
+
+    async fn is_member_of(&self, user_id: &UserId, group: &str) -> bool {
+        self.backend_handler
+            .unsafe_get_handler()
+            .get_user_groups(user_id)
+            .await
+            .map(|groups| groups.iter().any(|g| g.display_name == group))
+            .unwrap_or(false)
+    }
+
+    /// SASL bind, RFC 4422. Only the PLAIN mechanism (RFC 4616) is supported, without an
+    /// authorization identity different from the authentication one.
+    #[instrument(skip_all, level = "debug")]
+    pub async fn do_sasl_bind(&mut self, request: &SaslBindRequest) -> (LdapResultCode, String) {
+        debug!("SASL mechanism: {}", &request.mechanism);
+        if let Err(e) = self.check_tls_for_bind() {
+            return e;
+        }
+        if request.mechanism != "PLAIN" {
+            return (
+                LdapResultCode::AuthMethodNotSupported,
+                format!("Unsupported SASL mechanism: {}", &request.mechanism),
+            );
+        }
+        // message = [authzid] NUL authcid NUL passwd
+        let message = request.credentials.as_deref().unwrap_or_default();
+        let parts = message.split(|&b| b == 0).collect::<Vec<_>>();
+        let (authzid, authcid, password) = match (
+            parts.as_slice(),
+            parts.get(1).map(|p| std::str::from_utf8(p)),
+        ) {
+            ([authzid, _, password], Some(Ok(authcid))) if !authcid.is_empty() => {
+                (*authzid, authcid, *password)
+            }
+            _ => {
+                return (
+                    LdapResultCode::ProtocolError,
+                    "Invalid SASL PLAIN message".to_string(),
+                )
+            }
+        };
+        let user_id = UserId::new(authcid);
+        if !authzid.is_empty()
+            && std::str::from_utf8(authzid)
+                .map(|authzid| UserId::new(authzid) != user_id)
+                .unwrap_or(true)
+        {
+            return (
+                LdapResultCode::InsufficentAccessRights,
+                "Authorization as another user is not supported".to_string(),
+            );
+        }
+        let password = match std::str::from_utf8(password) {
+            Ok(password) => password.to_string(),
+            Err(_) => return (LdapResultCode::InvalidCredentials, "".to_string()),
+        };
+        self.bind_user(user_id, &password).await
+    }
+
+    async fn bind_user(&mut self, user_id: UserId, password: &str) -> (LdapResultCode, String) {
         match self
             .get_login_handler()
             .bind(BindRequest {
                 name: user_id.clone(),
-                password: password.clone(),
+                password: password.to_string(),
             })
             .await
         {
             Ok(()) => {
-                self.user_info = self
-                    .backend_handler
-                    .get_permissions_for_user(user_id)
-                    .await
-                    .ok();
+                (self.user_info, self.user_groups) =
+                    match self.backend_handler.get_permissions_for_user(user_id).await {
+                        Ok((user_info, groups))
+                            if user_info.is_admin()
+                                && !self.ldap_info.is_admin_bind_allowed(&user_info.user) =>
+                        {
+                            debug!("Not in the admin bind DNs, binding with read-only rights");
+                            let groups_without_admin = groups
+                                .iter()
+                                .filter(|g| *g != "lldap_admin")
+                                .cloned()
+                                .chain(std::iter::once("lldap_strict_readonly".to_owned()))
+                                .collect::<Vec<_>>();
+                            let permissions = self.backend_handler.get_permissions_from_groups(
+                                user_info.user,
+                                groups_without_admin.iter(),
+                            );
+                            (Some(permissions), groups)
+                        }
+                        Ok((user_info, groups)) => (Some(user_info), groups),
+                        Err(_) => (None, Vec::new()),
+                    };
                 debug!("Success!");
                 (LdapResultCode::Success, "".to_string())
             }
+            Err(e) if e.is_busy() => (LdapResultCode::Busy, format!("{:#}", e)),
             Err(_) => (LdapResultCode::InvalidCredentials, "".to_string()),
         }
     }
 
+    /// Binds with an API token as the password. The token must match the name in the DN.
+    async fn do_api_token_bind(&mut self, name: &str, token: &str) -> (LdapResultCode, String) {
+        match self
+            .backend_handler
+            .get_permissions_for_api_token(token)
+            .await
+        {
+            Ok(validation) if validation.user.as_str() == name => {
+                self.user_info = Some(validation);
+                self.user_groups.clear();
+                debug!("Success!");
+                (LdapResultCode::Success, "".to_string())
+            }
+            _ => (LdapResultCode::InvalidCredentials, "".to_string()),
+        }
+    }
+
     async fn change_password<B: OpaqueHandler>(
         &self,
         backend_handler: &B,
@@ -304,6 +849,39 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
         Ok(())
     }
 
+    /// Checks a new password received in clear against the policy, unless the admins can
+    /// override it, and against the password history of the user.
+    async fn check_new_password(
+        &self,
+        credentials: &ValidationResults,
+        uid: &UserId,
+        password: &str,
+    ) -> LdapResult<()> {
+        let policy = &self.ldap_info.password_policy;
+        if !(policy.admin_override && credentials.is_admin()) {
+            policy.check(password).map_err(|e| LdapError {
+                code: LdapResultCode::ConstraintViolation,
+                message: e.to_string(),
+            })?;
+        }
+        if self
+            .get_login_handler()
+            .is_password_recently_used(uid, password)
+            .await
+            .map_err(|e| LdapError {
+                code: LdapResultCode::OperationsError,
+                message: format!("Error while checking the password history: {:#?}", e),
+            })?
+        {
+            return Err(LdapError {
+                code: LdapResultCode::ConstraintViolation,
+                message: "Password recently used".to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Password modify extended operation, RFC 3062.
     async fn do_password_modification(
         &mut self,
         request: &LdapPasswordModifyRequest,
@@ -312,66 +890,130 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
             code: LdapResultCode::InsufficentAccessRights,
             message: "No user currently bound".to_string(),
         })?;
-        match (&request.user_identity, &request.new_password) {
-            (Some(user), Some(password)) => {
-                match get_user_id_from_distinguished_name(
-                    user,
-                    &self.ldap_info.base_dn,
-                    &self.ldap_info.base_dn_str,
-                ) {
-                    Ok(uid) => {
-                        let user_is_admin = self
-                            .backend_handler
-                            .get_readable_handler(credentials, &uid)
-                            .expect("Unexpected permission error")
-                            .get_user_groups(&uid)
-                            .await
-                            .map_err(|e| LdapError {
-                                code: LdapResultCode::OperationsError,
-                                message: format!(
-                                    "Internal error while requesting user's groups: {:#?}",
-                                    e
-                                ),
-                            })?
-                            .iter()
-                            .any(|g| g.display_name == "lldap_admin");
-                        if !credentials.can_change_password(&uid, user_is_admin) {
-                            Err(LdapError {
-                                code: LdapResultCode::InsufficentAccessRights,
-                                message: format!(
-                                    r#"User `{}` cannot modify the password of user `{}`"#,
-                                    &credentials.user, &uid
-                                ),
-                            })
-                        } else if let Err(e) = self
-                            .change_password(self.get_opaque_handler(), &uid, password.as_bytes())
-                            .await
-                        {
-                            Err(LdapError {
-                                code: LdapResultCode::Other,
-                                message: format!("Error while changing the password: {:#?}", e),
-                            })
-                        } else {
-                            Ok(vec![make_extended_response(
-                                LdapResultCode::Success,
-                                "".to_string(),
-                            )])
-                        }
-                    }
-                    Err(e) => Err(LdapError {
+        // Without a user identity, the request applies to the bound user.
+        let uid = match &request.user_identity {
+            None => credentials.user.clone(),
+            Some(user) => {
+                get_user_id_from_distinguished_name(user, self.ldap_info.context_for(user))
+                    .map_err(|e| LdapError {
                         code: LdapResultCode::InvalidDNSyntax,
                         message: format!("Invalid username: {}", e),
-                    }),
-                }
+                    })?
             }
-            _ => Err(LdapError {
-                code: LdapResultCode::ConstraintViolation,
-                message: "Missing either user_id or password".to_string(),
-            }),
+        };
+        let user_is_admin = self
+            .backend_handler
+            .get_readable_handler(credentials, &uid)
+            .expect("Unexpected permission error")
+            .get_user_groups(&uid)
+            .await
+            .map_err(|e| LdapError {
+                code: LdapResultCode::OperationsError,
+                message: format!("Internal error while requesting user's groups: {:#?}", e),
+            })?
+            .iter()
+            .any(|g| g.display_name == "lldap_admin");
+        if !credentials.can_change_password(&uid, user_is_admin) {
+            return Err(LdapError {
+                code: LdapResultCode::InsufficentAccessRights,
+                message: format!(
+                    r#"User `{}` cannot modify the password of user `{}`"#,
+                    &credentials.user, &uid
+                ),
+            });
+        }
+        if let Some(old_password) = &request.old_password {
+            self.get_login_handler()
+                .bind(BindRequest {
+                    name: uid.clone(),
+                    password: old_password.clone(),
+                })
+                .await
+                .map_err(|_| LdapError {
+                    code: LdapResultCode::InvalidCredentials,
+                    message: "Invalid old password".to_string(),
+                })?;
         }
+        // The generated passwords are not checked against the policy.
+        let (password, is_generated) = match &request.new_password {
+            Some(password) => {
+                self.check_new_password(credentials, &uid, password).await?;
+                (password.clone(), false)
+            }
+            None => (generate_password(), true),
+        };
+        self.change_password(self.get_opaque_handler(), &uid, password.as_bytes())
+            .await
+            .map_err(|e| LdapError {
+                code: LdapResultCode::Other,
+                message: format!("Error while changing the password: {:#?}", e),
+            })?;
+        Ok(vec![if is_generated {
+            make_password_modify_response(&password)
+        } else {
+            make_extended_response(LdapResultCode::Success, "".to_string())
+        }])
+    }
+
+    /// "Who am I?" extended operation, RFC 4532.
+    fn do_whoami(&self) -> Vec<LdapOp> {
+        let authz_id = match &self.user_info {
+            Some(user_info) if user_info.is_api_token => format!(
+                "dn:uid={},ou=tokens,{}",
+                user_info.user.as_str(),
+                &self.ldap_info.base_dn_str
+            ),
+            Some(user_info) => format!(
+                "dn:{}",
+                self.ldap_info.make_user_dn(user_info.user.as_str())
+            ),
+            None => String::new(),
+        };
+        vec![LdapOp::ExtendedResponse(LdapExtendedResponse {
+            res: LdapResultOp {
+                code: LdapResultCode::Success,
+                matcheddn: "".to_string(),
+                message: "".to_string(),
+                referral: vec![],
+            },
+            name: None,
+            value: Some(authz_id.into_bytes()),
+        })]
+    }
+
+    /// StartTLS extended operation, RFC 4511. The server upgrades the connection after sending
+    /// the response.
+    fn do_start_tls(&mut self) -> Vec<LdapOp> {
+        let (code, message) = match self.tls_status {
+            TlsStatus::StartTlsAvailable => {
+                self.tls_status = TlsStatus::Upgrading;
+                (LdapResultCode::Success, "")
+            }
+            TlsStatus::Unavailable => (LdapResultCode::Unavailable, "TLS is not configured"),
+            TlsStatus::Upgrading | TlsStatus::Encrypted => (
+                LdapResultCode::OperationsError,
+                "TLS is already established",
+            ),
+        };
+        vec![LdapOp::ExtendedResponse(LdapExtendedResponse {
+            res: LdapResultOp {
+                code,
+                matcheddn: "".to_string(),
+                message: message.to_string(),
+                referral: vec![],
+            },
+            name: Some(START_TLS_OID.to_string()),
+            value: None,
+        })]
     }
 
     async fn do_extended_request(&mut self, request: &LdapExtendedRequest) -> Vec<LdapOp> {
+        if request.name == WHOAMI_OID {
+            return self.do_whoami();
+        }
+        if request.name == START_TLS_OID {
+            return self.do_start_tls();
+        }
         match LdapPasswordModifyRequest::try_from(request) {
             Ok(password_request) => self
                 .do_password_modification(&password_request)
@@ -412,6 +1054,12 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
             });
         }
         if let [value] = &change.modification.vals.as_slice() {
+            let password = std::str::from_utf8(value).map_err(|_| LdapError {
+                code: LdapResultCode::InvalidAttributeSyntax,
+                message: "The password is not valid UTF-8".to_string(),
+            })?;
+            self.check_new_password(credentials, user_id, password)
+                .await?;
             self.change_password(self.get_opaque_handler(), user_id, value)
                 .await
                 .map_err(|e| LdapError {
@@ -430,13 +1078,156 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
         Ok(())
     }
 
-    async fn handle_modify_request(
+    /// Applies the modifications of the user's (custom or primary) attributes in a single
+    /// `update_user` call.
+    async fn handle_modify_attributes(
         &mut self,
-        request: &LdapModifyRequest,
-    ) -> LdapResult<Vec<LdapOp>> {
-        let credentials = self
-            .user_info
-            .as_ref()
+        user_id: &UserId,
+        credentials: &ValidationResults,
+        user_is_admin: bool,
+        changes: &[&LdapModify],
+    ) -> LdapResult<()> {
+        let backend_handler = self
+            .backend_handler
+            .get_writeable_handler(credentials, user_id)
+            .ok_or_else(|| LdapError {
+                code: LdapResultCode::InsufficentAccessRights,
+                message: format!(
+                    r#"User `{}` cannot modify the attributes of user `{}`"#,
+                    &credentials.user, &user_id
+                ),
+            })?;
+        let schema = self
+            .backend_handler
+            .get_user_restricted_lister_handler(credentials)
+            .get_schema()
+            .await
+            .map_err(|e| LdapError {
+                code: LdapResultCode::OperationsError,
+                message: format!("Unable to get schema: {:#}", e),
+            })?;
+        let user = backend_handler
+            .get_user_details(user_id)
+            .await
+            .map_err(|e| LdapError {
+                code: LdapResultCode::OperationsError,
+                message: format!("Internal error while requesting user's details: {:#?}", e),
+            })?;
+        let mut email = None;
+        let mut display_name = None;
+        let mut attributes = HashMap::<String, Vec<Vec<u8>>>::new();
+        for change in changes {
+            let atype = &change.modification.atype;
+            let field = self.ldap_info.resolve_user_attribute(atype);
+            if self.ldap_info.virtual_user_attributes.contains_key(&field) {
+                return Err(LdapError {
+                    code: LdapResultCode::ConstraintViolation,
+                    message: format!(r#"Attribute `{}` is read-only"#, atype),
+                });
+            }
+            match map_user_field_with_schema(&field, &schema) {
+                UserFieldType::PrimaryField(UserColumn::Email) => {
+                    let values = email.get_or_insert_with(|| vec![user.email.clone().into_bytes()]);
+                    apply_modification(values, false, change)?;
+                }
+                UserFieldType::PrimaryField(UserColumn::DisplayName) => {
+                    let values = display_name.get_or_insert_with(|| {
+                        user.display_name
+                            .iter()
+                            .map(|n| n.clone().into_bytes())
+                            .collect()
+                    });
+                    apply_modification(values, false, change)?;
+                }
+                UserFieldType::Attribute(name) => {
+                    let attribute_schema = schema
+                        .user_attributes
+                        .get_attribute_schema(&name)
+                        .expect("Attribute mapped without schema");
+                    if !user_is_admin && !attribute_schema.is_editable {
+                        return Err(LdapError {
+                            code: LdapResultCode::InsufficentAccessRights,
+                            message: format!(r#"Attribute `{}` is not editable"#, atype),
+                        });
+                    }
+                    let is_list = attribute_schema.is_list;
+                    let values = attributes.entry(name).or_insert_with_key(|name| {
+                        get_custom_attribute(&user.attributes, name, &schema).unwrap_or_default()
+                    });
+                    apply_modification(values, is_list, change)?;
+                }
+                UserFieldType::PrimaryField(_) => {
+                    return Err(LdapError {
+                        code: LdapResultCode::ConstraintViolation,
+                        message: format!(r#"Attribute `{}` is read-only"#, atype),
+                    })
+                }
+                UserFieldType::NoMatch if is_read_only_user_attribute(&field) => {
+                    return Err(LdapError {
+                        code: LdapResultCode::ConstraintViolation,
+                        message: format!(r#"Attribute `{}` is read-only"#, atype),
+                    })
+                }
+                UserFieldType::NoMatch => {
+                    return Err(LdapError {
+                        code: LdapResultCode::UnwillingToPerform,
+                        message: format!(r#"Unsupported attribute: `{}`"#, atype),
+                    })
+                }
+            }
+        }
+        let to_string = |value: Vec<u8>| {
+            String::from_utf8(value).map_err(|e| LdapError {
+                code: LdapResultCode::InvalidAttributeSyntax,
+                message: format!("Invalid UTF-8 value: {}", e),
+            })
+        };
+        let email = match email.map(|mut values| values.pop()) {
+            None => None,
+            Some(Some(value)) => Some(to_string(value)?),
+            Some(None) => {
+                return Err(LdapError {
+                    code: LdapResultCode::ConstraintViolation,
+                    message: "The email attribute cannot be removed".to_string(),
+                })
+            }
+        };
+        let display_name = display_name
+            .map(|mut values| values.pop().map(to_string).unwrap_or(Ok(String::new())))
+            .transpose()?;
+        let mut insert_attributes = Vec::new();
+        let mut delete_attributes = Vec::new();
+        for (name, values) in attributes {
+            if values.is_empty() {
+                delete_attributes.push(name);
+            } else {
+                let value = convert_custom_attribute_values(&name, values, &schema)?;
+                insert_attributes.push(AttributeValue { name, value });
+            }
+        }
+        backend_handler
+            .update_user(UpdateUserRequest {
+                user_id: user_id.clone(),
+                email,
+                display_name,
+                insert_attributes,
+                delete_attributes,
+                ..Default::default()
+            })
+            .await
+            .map_err(|e| LdapError {
+                code: LdapResultCode::OperationsError,
+                message: format!("Error while updating the user: {:#?}", e),
+            })
+    }
+
+    async fn handle_modify_request(
+        &mut self,
+        request: &LdapModifyRequest,
+    ) -> LdapResult<Vec<LdapOp>> {
+        let credentials = self
+            .user_info
+            .as_ref()
             .ok_or_else(|| LdapError {
                 code: LdapResultCode::InsufficentAccessRights,
                 message: "No user currently bound".to_string(),
@@ -444,8 +1235,7 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
             .clone();
         match get_user_id_from_distinguished_name(
             &request.dn,
-            &self.ldap_info.base_dn,
-            &self.ldap_info.base_dn_str,
+            self.ldap_info.context_for(&request.dn),
         ) {
             Ok(uid) => {
                 let user_is_admin = self
@@ -460,7 +1250,20 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
                     })?
                     .iter()
                     .any(|g| g.display_name == "lldap_admin");
-                for change in &request.changes {
+                let (password_changes, attribute_changes): (Vec<_>, Vec<_>) = request
+                    .changes
+                    .iter()
+                    .partition(|c| c.modification.atype.eq_ignore_ascii_case("userpassword"));
+                if !attribute_changes.is_empty() {
+                    self.handle_modify_attributes(
+                        &uid,
+                        &credentials,
+                        user_is_admin,
+                        &attribute_changes,
+                    )
+                    .await?;
+                }
+                for change in password_changes {
                     self.handle_modify_change(&uid, &credentials, user_is_admin, change)
                         .await?
                 }
@@ -485,29 +1288,45 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
     pub async fn do_search_or_dse(
         &mut self,
         request: &LdapSearchRequest,
+    ) -> LdapResult<Vec<LdapOp>> {
+        self.do_sorted_search_or_dse(request, &[]).await
+    }
+
+    /// Same as do_search_or_dse, with the users sorted by the keys.
+    async fn do_sorted_search_or_dse(
+        &mut self,
+        request: &LdapSearchRequest,
+        sort: &[UserSortKey],
     ) -> LdapResult<Vec<LdapOp>> {
         if request.base.is_empty() && request.scope == LdapSearchScope::Base {
             if let LdapFilter::Present(attribute) = &request.filter {
                 if attribute.to_ascii_lowercase() == "objectclass" {
                     debug!("rootDSE request");
                     return Ok(vec![
-                        root_dse_response(&self.ldap_info.base_dn_str),
+                        root_dse_response(
+                            &self.ldap_info,
+                            self.tls_status == TlsStatus::StartTlsAvailable,
+                        ),
                         make_search_success(),
                     ]);
                 }
             }
         }
-        self.do_search(request).await
+        self.do_search(request, sort).await
     }
 
     async fn do_search_internal(
         &self,
+        ldap_info: &LdapInfo,
         backend_handler: &impl UserAndGroupListerBackendHandler,
         request: &LdapSearchRequest,
+        schema: &Schema,
+        sort: &[UserSortKey],
+        page: Option<(u64, u64)>,
     ) -> LdapResult<(Option<Vec<UserAndGroups>>, Option<Vec<Group>>)> {
         let dn_parts = parse_distinguished_name(&request.base.to_ascii_lowercase())?;
-        let scope = get_search_scope(&self.ldap_info.base_dn, &dn_parts);
-        debug!(?request.base, ?scope);
+        let scope = restrict_search_scope(get_search_scope(ldap_info, &dn_parts), &request.scope);
+        debug!(?request.base, ?request.scope, ?scope);
         // Disambiguate the lifetimes.
         fn cast<'a, T, R>(x: T) -> T
         where
@@ -517,27 +1336,40 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
         }
 
         let get_user_list = cast(|filter: &LdapFilter| async {
-            let need_groups = request
-                .attrs
-                .iter()
-                .any(|s| s.to_ascii_lowercase() == "memberof");
+            let need_groups = request.attrs.is_empty()
+                || request.attrs.iter().any(|s| {
+                    matches!(
+                        ldap_info.resolve_user_attribute(s).as_str(),
+                        "memberof" | "gidnumber" | "*"
+                    )
+                });
             get_user_list(
-                &self.ldap_info,
+                ldap_info,
                 filter,
                 need_groups,
                 &request.base,
                 backend_handler,
+                schema,
+                sort,
+                // Fetch one extra user, to know whether there is a next page.
+                page.map(|(offset, limit)| (offset, limit + 1)),
             )
             .await
         });
         let get_group_list = cast(|filter: &LdapFilter| async {
-            get_groups_list(&self.ldap_info, filter, &request.base, backend_handler).await
+            get_groups_list(ldap_info, filter, &request.base, backend_handler, schema).await
         });
         Ok(match scope {
-            SearchScope::Global => (
-                Some(get_user_list(&request.filter).await?),
-                Some(get_group_list(&request.filter).await?),
-            ),
+            SearchScope::Global => {
+                let users = get_user_list(&request.filter).await?;
+                // When paging, the groups are returned after the last page of users.
+                let groups = if has_next_page(&users, page) {
+                    None
+                } else {
+                    Some(get_group_list(&request.filter).await?)
+                };
+                (Some(users), groups)
+            }
             SearchScope::Users => (Some(get_user_list(&request.filter).await?), None),
             SearchScope::Groups => (None, Some(get_group_list(&request.filter).await?)),
             SearchScope::User(filter) => {
@@ -548,10 +1380,13 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
                 let filter = LdapFilter::And(vec![request.filter.clone(), filter]);
                 (None, Some(get_group_list(&filter).await?))
             }
+            SearchScope::Empty => (None, None),
             SearchScope::Unknown => {
                 warn!(
-                    r#"The requested search tree "{}" matches neither the user subtree "ou=people,{}" nor the group subtree "ou=groups,{}""#,
-                    &request.base, &self.ldap_info.base_dn_str, &self.ldap_info.base_dn_str
+                    r#"The requested search tree "{}" matches neither the user subtree "{}" nor the group subtree "{}""#,
+                    &request.base,
+                    ldap_info.people_dn(),
+                    ldap_info.groups_dn()
                 );
                 (None, None)
             }
@@ -559,7 +1394,7 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
                 // Search path is not in our tree, just return an empty success.
                 warn!(
                     "The specified search tree {:?} is not under the common subtree {:?}",
-                    &dn_parts, &self.ldap_info.base_dn
+                    &dn_parts, &ldap_info.base_dn
                 );
                 (None, None)
             }
@@ -567,42 +1402,225 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
     }
 
     #[instrument(skip_all, level = "debug")]
-    pub async fn do_search(&self, request: &LdapSearchRequest) -> LdapResult<Vec<LdapOp>> {
-        let user_info = self.user_info.as_ref().ok_or_else(|| LdapError {
-            code: LdapResultCode::InsufficentAccessRights,
-            message: "No user currently bound".to_string(),
-        })?;
+    pub async fn do_search(
+        &self,
+        request: &LdapSearchRequest,
+        sort: &[UserSortKey],
+    ) -> LdapResult<Vec<LdapOp>> {
+        let search = self.do_size_limited_search(request, sort);
+        match u64::try_from(request.timelimit) {
+            Ok(seconds) if seconds > 0 => {
+                tokio::time::timeout(Duration::from_secs(seconds), search)
+                    .await
+                    .map_err(|_| LdapError {
+                        code: LdapResultCode::TimeLimitExceeded,
+                        message: format!("Search took more than {} seconds", seconds),
+                    })?
+            }
+            _ => search.await,
+        }
+    }
+
+    /// Applies the lowest of the client's and the server's size limits.
+    async fn do_size_limited_search(
+        &self,
+        request: &LdapSearchRequest,
+        sort: &[UserSortKey],
+    ) -> LdapResult<Vec<LdapOp>> {
+        let size_limit = [
+            u32::try_from(request.sizelimit).unwrap_or(0),
+            self.ldap_info.search_limits.size_limit,
+        ]
+        .into_iter()
+        .filter(|limit| *limit > 0)
+        .min();
+        let size_limit = match size_limit {
+            Some(limit) => limit as usize,
+            None => return Ok(self.do_search_page(request, sort, None).await?.0),
+        };
+        let (mut results, is_truncated) = self
+            .do_search_page(request, sort, Some((0, size_limit as u64)))
+            .await?;
+        // The groups are not limited by the page, count all the entries.
+        let num_entries = results
+            .iter()
+            .filter(|op| matches!(op, LdapOp::SearchResultEntry(_)))
+            .count();
+        if is_truncated || num_entries > size_limit {
+            results.truncate(size_limit);
+            results.push(make_search_error(
+                LdapResultCode::SizeLimitExceeded,
+                format!("Search returned more than {} entries", size_limit),
+            ));
+        }
+        Ok(results)
+    }
+
+    /// Returns the results for the page of users (offset, limit), and whether there are more.
+    async fn do_search_page(
+        &self,
+        request: &LdapSearchRequest,
+        sort: &[UserSortKey],
+        page: Option<(u64, u64)>,
+    ) -> LdapResult<(Vec<LdapOp>, bool)> {
+        if let Some(url) = self.ldap_info.referral_for(&request.base) {
+            debug!(%url, "Search referral");
+            return Ok((
+                vec![LdapOp::SearchResultDone(make_referral_result(url))],
+                false,
+            ));
+        }
+        let user_info = match &self.user_info {
+            Some(user_info) => user_info.clone(),
+            None => {
+                self.check_anonymous_search(request)?;
+                ValidationResults::anonymous()
+            }
+        };
         let backend_handler = self
             .backend_handler
-            .get_user_restricted_lister_handler(user_info);
-        let (users, groups) = self.do_search_internal(&backend_handler, request).await?;
-
+            .get_user_restricted_lister_handler(&user_info);
         let schema = backend_handler.get_schema().await.map_err(|e| LdapError {
-            code: LdapResultCode::OperationsError,
+            code: backend_error_code(&e, LdapResultCode::OperationsError),
             message: format!("Unable to get schema: {:#}", e),
         })?;
-        let mut results = Vec::new();
+        let ldap_info = self.ldap_info.context_for(&request.base);
+        let (mut users, groups) = self
+            .do_search_internal(ldap_info, &backend_handler, request, &schema, sort, page)
+            .await?;
+        let mut is_truncated = false;
+        if let (Some(users), Some((_, limit))) = (&mut users, page) {
+            is_truncated = has_next_page(users, page);
+            users.truncate(limit as usize);
+        }
+        // The containers come first, and are not part of the next pages.
+        let mut results = if page.map_or(true, |(offset, _)| offset == 0) {
+            get_container_entries(ldap_info, request)
+        } else {
+            Vec::new()
+        };
         if let Some(users) = users {
+            let reader = LdapReader {
+                user_id: Some(user_info.user.clone()).filter(|_| !user_info.is_api_token),
+                is_admin: user_info.is_admin(),
+                groups: self.user_groups.clone(),
+            };
             results.extend(convert_users_to_ldap_op(
                 users,
                 &request.attrs,
-                &self.ldap_info,
+                ldap_info,
                 &schema,
+                &reader,
             ));
         }
         if let Some(groups) = groups {
+            let member_emails =
+                get_member_emails(&groups, &request.attrs, &backend_handler).await?;
             results.extend(convert_groups_to_ldap_op(
                 groups,
                 &request.attrs,
-                &self.ldap_info,
+                ldap_info,
                 &backend_handler.user_filter,
+                &schema,
+                &member_emails,
             ));
         }
+        if self.user_info.is_none() {
+            restrict_to_anonymous_attributes(&mut results, &self.ldap_info.anonymous_attributes);
+        }
+        if self.ldap_info.preserve_dn_case {
+            preserve_dn_case(&mut results, &request.base, self.bind_dn.as_deref());
+        }
         if results.is_empty() || matches!(results[results.len() - 1], LdapOp::SearchResultEntry(_))
         {
             results.push(make_search_success());
         }
-        Ok(results)
+        Ok((results, is_truncated))
+    }
+
+    /// Without a bound user, the search is anonymous: it can only use the configured attributes.
+    fn check_anonymous_search(&self, request: &LdapSearchRequest) -> LdapResult<()> {
+        let allowed = &self.ldap_info.anonymous_attributes;
+        if !self.ldap_info.anonymous_bind || allowed.is_empty() {
+            return Err(LdapError {
+                code: LdapResultCode::InsufficentAccessRights,
+                message: "No user currently bound".to_string(),
+            });
+        }
+        if !is_anonymous_filter_allowed(&request.filter, allowed) {
+            return Err(LdapError {
+                code: LdapResultCode::InsufficentAccessRights,
+                message: format!(
+                    "Anonymous searches can only filter on: {}",
+                    allowed.join(", ")
+                ),
+            });
+        }
+        Ok(())
+    }
+
+    async fn do_paged_search(
+        &mut self,
+        request: &LdapSearchRequest,
+        sort: &[UserSortKey],
+        size: i32,
+        cookie: Vec<u8>,
+    ) -> LdapResult<(Vec<LdapOp>, Vec<LdapControl>)> {
+        // The size in the response is the estimated total, 0 if unknown.
+        let make_control = |cookie| vec![LdapControl::SimplePagedResults { size: 0, cookie }];
+        if request.base.is_empty() {
+            // Root DSE, there is a single entry.
+            return Ok((
+                self.do_sorted_search_or_dse(request, sort).await?,
+                make_control(vec![]),
+            ));
+        }
+        let offset = if cookie.is_empty() {
+            0
+        } else {
+            let cursor = self
+                .paged_searches
+                .remove(&cookie)
+                .filter(|c| c.base == request.base && c.filter == request.filter && c.sort == sort)
+                .ok_or_else(|| LdapError {
+                    code: LdapResultCode::UnwillingToPerform,
+                    message: "Invalid or expired paged results cookie".to_string(),
+                })?;
+            if size <= 0 {
+                // The client abandons the search.
+                return Ok((vec![make_search_success()], make_control(vec![])));
+            }
+            cursor.offset
+        };
+        let max_page_size = self.ldap_info.search_limits.max_page_size;
+        let limit = match u32::try_from(size) {
+            Ok(size) if size > 0 => size.min(max_page_size),
+            _ => max_page_size,
+        } as u64;
+        let (results, is_truncated) = self
+            .do_search_page(request, sort, Some((offset, limit)))
+            .await?;
+        if !is_truncated {
+            return Ok((results, make_control(vec![])));
+        }
+        if self.paged_searches.len() >= MAX_PAGED_SEARCHES {
+            // The cookies are increasing big-endian numbers: the smallest is the oldest.
+            if let Some(oldest) = self.paged_searches.keys().min().cloned() {
+                self.paged_searches.remove(&oldest);
+            }
+        }
+        let cookie = self.next_paged_search_cookie.to_be_bytes().to_vec();
+        self.next_paged_search_cookie += 1;
+        self.paged_searches.insert(
+            cookie.clone(),
+            PagedSearchCursor {
+                base: request.base.clone(),
+                filter: request.filter.clone(),
+                sort: sort.to_vec(),
+                offset: offset + limit,
+            },
+        );
+        Ok((results, make_control(cookie)))
     }
 
     async fn do_create_user(&self, request: LdapAddRequest) -> LdapResult<Vec<LdapOp>> {
@@ -616,8 +1634,7 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
             })?;
         let user_id = get_user_id_from_distinguished_name(
             &request.dn,
-            &self.ldap_info.base_dn,
-            &self.ldap_info.base_dn_str,
+            self.ldap_info.context_for(&request.dn),
         )?;
         fn parse_attribute(mut attr: LdapPartialAttribute) -> LdapResult<(String, Vec<u8>)> {
             if attr.vals.len() > 1 {
@@ -689,11 +1706,11 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
 
     pub async fn do_compare(&mut self, request: LdapCompareRequest) -> LdapResult<Vec<LdapOp>> {
         let req = make_search_request::<String>(
-            &self.ldap_info.base_dn_str,
+            &self.ldap_info.context_for(&request.dn).base_dn_str,
             LdapFilter::Equality("dn".to_string(), request.dn.to_string()),
             vec![request.atype.clone()],
         );
-        let entries = self.do_search(&req).await?;
+        let entries = self.do_search(&req, &[]).await?;
         if entries.len() > 2 {
             // SearchResultEntry + SearchResultDone
             return Err(LdapError {
@@ -704,10 +1721,20 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
 
         match entries.first() {
             Some(LdapOp::SearchResultEntry(entry)) => {
+                // Object classes are case-insensitive.
+                let is_object_class = request.atype.eq_ignore_ascii_case("objectclass");
                 let available = entry
                     .attributes
                     .iter()
-                    .any(|attr| attr.atype == request.atype && attr.vals.contains(&request.val));
+                    .filter(|attr| attr.atype.eq_ignore_ascii_case(&request.atype))
+                    .flat_map(|attr| attr.vals.iter())
+                    .any(|val| {
+                        if is_object_class {
+                            val.eq_ignore_ascii_case(&request.val)
+                        } else {
+                            val == &request.val
+                        }
+                    });
                 Ok(vec![LdapOp::CompareResult(LdapResultOp {
                     code: if available {
                         LdapResultCode::CompareTrue
@@ -721,7 +1748,7 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
             }
             Some(LdapOp::SearchResultDone(_)) => Ok(vec![LdapOp::CompareResult(LdapResultOp {
                 code: LdapResultCode::NoSuchObject,
-                matcheddn: self.ldap_info.base_dn_str.clone(),
+                matcheddn: self.ldap_info.context_for(&request.dn).base_dn_str.clone(),
                 message: "".to_string(),
                 referral: vec![],
             })]),
@@ -736,26 +1763,115 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
         }
     }
 
-    pub async fn handle_ldap_message(&mut self, ldap_op: LdapOp) -> Option<Vec<LdapOp>> {
-        Some(match ldap_op {
-            LdapOp::BindRequest(request) => {
-                let (code, message) = self.do_bind(&request).await;
-                vec![LdapOp::BindResponse(LdapBindResponse {
-                    res: LdapResultOp {
-                        code,
-                        matcheddn: "".to_string(),
+    /// Same as handle_ldap_message, with the request controls. The response controls go with the
+    /// last response.
+    pub async fn handle_ldap_message_with_controls(
+        &mut self,
+        ldap_op: LdapOp,
+        controls: Vec<LdapControl>,
+    ) -> Option<(Vec<LdapOp>, Vec<LdapControl>)> {
+        if let LdapOp::SearchRequest(request) = &ldap_op {
+            let paged_results = controls.into_iter().find_map(|c| match c {
+                LdapControl::SimplePagedResults { size, cookie } => Some((size, cookie)),
+                _ => None,
+            });
+            if let Some((size, cookie)) = paged_results {
+                return Some(
+                    self.do_paged_search(request, &[], size, cookie)
+                        .await
+                        .unwrap_or_else(|e: LdapError| {
+                            (vec![make_search_error(e.code, e.message)], vec![])
+                        }),
+                );
+            }
+        }
+        self.handle_ldap_message(ldap_op)
+            .await
+            .map(|ops| (ops, vec![]))
+    }
+
+    /// Same as handle_ldap_message_with_controls, for a search with the server-side sort control
+    /// (RFC 2891). The users are sorted by the keys, and the groups, if any, come after them in
+    /// their usual order.
+    pub async fn handle_sorted_search(
+        &mut self,
+        request: LdapSearchRequest,
+        controls: Vec<LdapControl>,
+        sort: &SortRequest,
+    ) -> (Vec<LdapOp>, Vec<LdapControl>, SortResult) {
+        let sort_keys = match get_user_sort_keys(self.ldap_info.context_for(&request.base), sort) {
+            Ok(sort_keys) => sort_keys,
+            Err(sort_result) if sort.critical => {
+                let message = format!(
+                    "Unsupported sort key: {}",
+                    sort_result.attribute.as_deref().unwrap_or_default()
+                );
+                return (
+                    vec![make_search_error(
+                        LdapResultCode::UnavailableCriticalExtension,
                         message,
-                        referral: vec![],
-                    },
-                    saslcreds: None,
-                })]
+                    )],
+                    vec![],
+                    sort_result,
+                );
+            }
+            Err(sort_result) => {
+                // The control is not critical: return the results unsorted.
+                let (results, controls) = self
+                    .handle_ldap_message_with_controls(LdapOp::SearchRequest(request), controls)
+                    .await
+                    .unwrap_or_default();
+                return (results, controls, sort_result);
+            }
+        };
+        let paged_results = controls.into_iter().find_map(|c| match c {
+            LdapControl::SimplePagedResults { size, cookie } => Some((size, cookie)),
+            _ => None,
+        });
+        let (results, controls) = match paged_results {
+            Some((size, cookie)) => {
+                self.do_paged_search(&request, &sort_keys, size, cookie)
+                    .await
             }
+            None => self
+                .do_sorted_search_or_dse(&request, &sort_keys)
+                .await
+                .map(|results| (results, vec![])),
+        }
+        .unwrap_or_else(|e: LdapError| (vec![make_search_error(e.code, e.message)], vec![]));
+        (
+            results,
+            controls,
+            SortResult {
+                code: LdapResultCode::Success,
+                attribute: None,
+            },
+        )
+    }
+
+    pub async fn handle_ldap_message(&mut self, ldap_op: LdapOp) -> Option<Vec<LdapOp>> {
+        Some(match ldap_op {
+            LdapOp::BindRequest(request) => match self.ldap_info.referral_for(&request.dn) {
+                Some(url) => {
+                    debug!(%url, "Bind referral");
+                    vec![LdapOp::BindResponse(LdapBindResponse {
+                        res: make_referral_result(url),
+                        saslcreds: None,
+                    })]
+                }
+                None => {
+                    let (code, message) = self.do_bind(&request).await;
+                    vec![make_bind_response(code, message)]
+                }
+            },
             LdapOp::SearchRequest(request) => self
                 .do_search_or_dse(&request)
                 .await
                 .unwrap_or_else(|e: LdapError| vec![make_search_error(e.code, e.message)]),
             LdapOp::UnbindRequest => {
                 self.user_info = None;
+                self.user_groups.clear();
+                self.bind_dn = None;
                 // No need to notify on unbind (per rfc4511)
                 return None;
             }
@@ -777,16 +1893,90 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
     }
 }
 
+/// Attributes computed by the server, that cannot be modified.
+fn is_read_only_user_attribute(attribute: &str) -> bool {
+    matches!(
+        attribute,
+        "objectclass"
+            | "dn"
+            | "distinguishedname"
+            | "entrydn"
+            | "creatorsname"
+            | "hassubordinates"
+            | "nsaccountlock"
+            | "accountexpires"
+            | "pwdfailuretime"
+            | "pwdaccountlockedtime"
+            | "memberof"
+            | "uidnumber"
+            | "gidnumber"
+            | "shadowlastchange"
+            | "gecos"
+    )
+}
+
+/// Applies an LDAP modification to the current values of an attribute.
+fn apply_modification(
+    values: &mut Vec<Vec<u8>>,
+    is_list: bool,
+    change: &LdapModify,
+) -> LdapResult<()> {
+    let atype = &change.modification.atype;
+    let new_values = &change.modification.vals;
+    match change.operation {
+        LdapModifyType::Add => {
+            if (!is_list && !values.is_empty()) || new_values.iter().any(|v| values.contains(v)) {
+                return Err(LdapError {
+                    code: LdapResultCode::AttributeOrValueExists,
+                    message: format!(r#"Attribute `{}` already has a value"#, atype),
+                });
+            }
+            values.extend(new_values.iter().cloned());
+        }
+        LdapModifyType::Replace => *values = new_values.clone(),
+        LdapModifyType::Delete => {
+            if new_values.is_empty() {
+                values.clear();
+            } else {
+                for value in new_values {
+                    let position =
+                        values
+                            .iter()
+                            .position(|v| v == value)
+                            .ok_or_else(|| LdapError {
+                                code: LdapResultCode::NoSuchAttribute,
+                                message: format!(r#"Attribute `{}` has no such value"#, atype),
+                            })?;
+                    values.remove(position);
+                }
+            }
+        }
+    }
+    if !is_list && values.len() > 1 {
+        return Err(LdapError {
+            code: LdapResultCode::ConstraintViolation,
+            message: format!(r#"Attribute `{}` is single-valued"#, atype),
+        });
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::{
         domain::{handler::*, types::*},
-        infra::test_utils::{setup_default_schema, MockTestBackendHandler},
+        infra::{
+            access_control::Permission,
+            configuration::{LdapCnSource, PasswordPolicyOptionsBuilder},
+            test_utils::{setup_default_schema, MockTestBackendHandler},
+        },
         uuid,
     };
     use chrono::TimeZone;
-    use ldap3_proto::proto::{LdapDerefAliases, LdapSearchScope, LdapSubstringFilter};
+    use ldap3_proto::proto::{
+        LdapDerefAliases, LdapMatchingRuleAssertion, LdapSearchScope, LdapSubstringFilter,
+    };
     use mockall::predicate::eq;
     use std::collections::HashSet;
     use tokio;
@@ -893,64 +2083,368 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_admin_bind() {
+    async fn test_bind_database_busy() {
         let mut mock = MockTestBackendHandler::new();
-        mock.expect_bind()
-            .with(eq(crate::domain::handler::BindRequest {
-                name: UserId::new("test"),
-                password: "pass".to_string(),
-            }))
-            .times(1)
-            .return_once(|_| Ok(()));
-        mock.expect_get_user_groups()
-            .with(eq(UserId::new("test")))
-            .return_once(|_| {
-                let mut set = HashSet::new();
-                set.insert(GroupDetails {
-                    group_id: GroupId(42),
+        mock.expect_bind().times(1).return_once(|_| {
+            Err(crate::domain::error::DomainError::DatabaseError(
+                sea_orm::DbErr::ConnectionAcquire,
+            ))
+        });
+        let mut ldap_handler = LdapHandler::new_for_tests(mock, "dc=eXample,dc=com");
+
+        let request = LdapOp::BindRequest(LdapBindRequest {
+            dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        });
+        match ldap_handler.handle_ldap_message(request).await.as_deref() {
+            Some([LdapOp::BindResponse(response)]) => {
+                assert_eq!(response.res.code, LdapResultCode::Busy)
+            }
+            response => panic!("Unexpected response: {:?}", response),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bind_admin_not_in_admin_bind_dns() {
+        for (admin_bind_dn, expected_permission) in [
+            (
+                "uid=alice,ou=people,dc=example,dc=com",
+                Permission::Readonly,
+            ),
+            ("uid=Test,ou=people,dc=example,dc=com", Permission::Admin),
+        ] {
+            let mut mock = MockTestBackendHandler::new();
+            mock.expect_bind().return_once(|_| Ok(()));
+            mock.expect_get_user_groups().return_once(|_| {
+                Ok(HashSet::from([GroupDetails {
+                    group_id: GroupId(1),
                     display_name: "lldap_admin".to_string(),
                     creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
                     uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
-                });
-                Ok(set)
+                }]))
             });
-        let mut ldap_handler = LdapHandler::new_for_tests(mock, "dc=example,dc=com");
-
-        let request = LdapBindRequest {
-            dn: "uid=test,ou=people,dc=example,dc=com".to_string(),
-            cred: LdapBindCred::Simple("pass".to_string()),
-        };
-        assert_eq!(
-            ldap_handler.do_bind(&request).await.0,
-            LdapResultCode::Success
-        );
+            let mut ldap_handler = LdapHandler::new(
+                AccessControlledBackendHandler::new(mock),
+                LdapInfo::new(&crate::infra::configuration::Configuration {
+                    ldap_admin_bind_dns: vec![admin_bind_dn.to_owned()],
+                    ..crate::infra::configuration::ConfigurationBuilder::for_tests()
+                }),
+            );
+            let request = LdapBindRequest {
+                dn: "uid=test,ou=people,dc=example,dc=com".to_string(),
+                cred: LdapBindCred::Simple("pass".to_string()),
+            };
+            assert_eq!(
+                ldap_handler.do_bind(&request).await.0,
+                LdapResultCode::Success
+            );
+            assert_eq!(
+                ldap_handler.user_info.unwrap().permission,
+                expected_permission
+            );
+        }
     }
 
     #[tokio::test]
-    async fn test_search_regular_user() {
+    async fn test_bind_with_email() {
         let mut mock = MockTestBackendHandler::new();
         mock.expect_list_users()
             .with(
-                eq(Some(UserRequestFilter::And(vec![
-                    true.into(),
-                    UserRequestFilter::UserId(UserId::new("test")),
-                ]))),
+                eq(Some(UserRequestFilter::Equality(
+                    UserColumn::Email,
+                    "bob@example.com".to_owned(),
+                ))),
                 eq(false),
             )
-            .times(1)
-            .return_once(|_, _| {
+            .times(2)
+            .returning(|_, _| {
                 Ok(vec![UserAndGroups {
                     user: User {
-                        user_id: UserId::new("test"),
+                        user_id: UserId::new("bob"),
+                        email: "bob@example.com".to_owned(),
                         ..Default::default()
                     },
                     groups: None,
                 }])
             });
-        let mut ldap_handler = setup_bound_handler_with_group(mock, "regular").await;
+        mock.expect_bind()
+            .with(eq(crate::domain::handler::BindRequest {
+                name: UserId::new("bob"),
+                password: "pass".to_string(),
+            }))
+            .times(2)
+            .returning(|_| Ok(()));
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .returning(|_| Ok(HashSet::new()));
+        let mut ldap_handler = LdapHandler::new_for_tests(mock, "dc=example,dc=com");
+        for dn in [
+            "Bob@Example.com",
+            "mail=bob@example.com,ou=people,dc=example,dc=com",
+        ] {
+            assert_eq!(
+                ldap_handler
+                    .do_bind(&LdapBindRequest {
+                        dn: dn.to_string(),
+                        cred: LdapBindCred::Simple("pass".to_string()),
+                    })
+                    .await,
+                (LdapResultCode::Success, "".to_string()),
+            );
+        }
+    }
 
-        let request =
-            make_user_search_request::<String>(LdapFilter::And(vec![]), vec!["1.1".to_string()]);
+    #[tokio::test]
+    async fn test_bind_with_ambiguous_email() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users().times(1).return_once(|_, _| {
+            Ok(["bob", "bob2"]
+                .into_iter()
+                .map(|user_id| UserAndGroups {
+                    user: User {
+                        user_id: UserId::new(user_id),
+                        email: "bob@example.com".to_owned(),
+                        ..Default::default()
+                    },
+                    groups: None,
+                })
+                .collect())
+        });
+        mock.expect_reject_bind()
+            .with(eq(crate::domain::handler::BindRequest {
+                name: UserId::new("bob@example.com"),
+                password: "pass".to_string(),
+            }))
+            .times(1)
+            .return_once(|_| {
+                Err(crate::domain::error::DomainError::AuthenticationError(
+                    "bob@example.com".to_string(),
+                ))
+            });
+        let mut ldap_handler = LdapHandler::new_for_tests(mock, "dc=example,dc=com");
+        assert_eq!(
+            ldap_handler
+                .do_bind(&LdapBindRequest {
+                    dn: "bob@example.com".to_string(),
+                    cred: LdapBindCred::Simple("pass".to_string()),
+                })
+                .await,
+            (LdapResultCode::InvalidCredentials, "".to_string()),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sasl_plain_bind() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_bind()
+            .with(eq(crate::domain::handler::BindRequest {
+                name: UserId::new("bob"),
+                password: "pass".to_string(),
+            }))
+            .times(2)
+            .returning(|_| Ok(()));
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .returning(|_| Ok(HashSet::new()));
+        let mut ldap_handler = LdapHandler::new_for_tests(mock, "dc=example,dc=com");
+        let request = |mechanism: &str, credentials: &[u8]| SaslBindRequest {
+            dn: "".to_string(),
+            mechanism: mechanism.to_string(),
+            credentials: Some(credentials.to_vec()),
+        };
+
+        assert_eq!(
+            ldap_handler
+                .do_sasl_bind(&request("PLAIN", b"\0bob\0pass"))
+                .await
+                .0,
+            LdapResultCode::Success
+        );
+        assert_eq!(
+            ldap_handler.user_info.as_ref().unwrap().user,
+            UserId::new("bob")
+        );
+        // The authorization identity can only be the user itself.
+        assert_eq!(
+            ldap_handler
+                .do_sasl_bind(&request("PLAIN", b"Bob\0bob\0pass"))
+                .await
+                .0,
+            LdapResultCode::Success
+        );
+        assert_eq!(
+            ldap_handler
+                .do_sasl_bind(&request("PLAIN", b"admin\0bob\0pass"))
+                .await
+                .0,
+            LdapResultCode::InsufficentAccessRights
+        );
+        assert_eq!(
+            ldap_handler
+                .do_sasl_bind(&request("PLAIN", b"bob\0pass"))
+                .await
+                .0,
+            LdapResultCode::ProtocolError
+        );
+        assert_eq!(
+            ldap_handler.do_sasl_bind(&request("EXTERNAL", b"")).await.0,
+            LdapResultCode::AuthMethodNotSupported
+        );
+    }
+
+    #[tokio::test]
+    async fn test_api_token_bind() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_check_api_token()
+            .with(eq("lldap_token"))
+            .times(2)
+            .returning(|_| {
+                Ok(crate::domain::api_token_handler::ApiToken {
+                    name: "ci".to_owned(),
+                    scope: crate::domain::types::ApiTokenScope::Readonly,
+                    creation_date: chrono::Utc.timestamp_opt(0, 0).unwrap().naive_utc(),
+                    expiry_date: None,
+                })
+            });
+        let mut ldap_handler = LdapHandler::new_for_tests(mock, "dc=example,dc=com");
+
+        // The token belongs to another name.
+        let request = LdapBindRequest {
+            dn: "uid=other,ou=tokens,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("lldap_token".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::InvalidCredentials,
+        );
+        let request = LdapBindRequest {
+            dn: "uid=ci,ou=tokens,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("lldap_token".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::Success
+        );
+        let user_info = ldap_handler.user_info.as_ref().unwrap();
+        assert!(user_info.is_api_token);
+        assert!(user_info.can_read_all());
+        assert!(!user_info.can_write(&UserId::new("ci")));
+    }
+
+    #[tokio::test]
+    async fn test_start_tls() {
+        let mut ldap_handler =
+            LdapHandler::new_for_tests(MockTestBackendHandler::new(), "dc=example,dc=com");
+        let request = LdapExtendedRequest {
+            name: START_TLS_OID.to_string(),
+            value: None,
+        };
+        let response_code = |ops: Vec<LdapOp>| match &ops[..] {
+            [LdapOp::ExtendedResponse(response)] => response.res.code,
+            _ => panic!("Unexpected response: {:?}", ops),
+        };
+        assert_eq!(
+            response_code(ldap_handler.do_extended_request(&request).await),
+            LdapResultCode::Unavailable
+        );
+
+        ldap_handler.set_tls_status(TlsStatus::StartTlsAvailable);
+        assert_eq!(
+            response_code(ldap_handler.do_extended_request(&request).await),
+            LdapResultCode::Success
+        );
+        assert_eq!(ldap_handler.tls_status(), TlsStatus::Upgrading);
+
+        ldap_handler.set_tls_status(TlsStatus::Encrypted);
+        assert_eq!(
+            response_code(ldap_handler.do_extended_request(&request).await),
+            LdapResultCode::OperationsError
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bind_requires_tls() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_bind().times(1).return_once(|_| Ok(()));
+        mock.expect_get_user_groups()
+            .return_once(|_| Ok(HashSet::new()));
+        let mut ldap_handler = LdapHandler::new_for_tests(mock, "dc=example,dc=com");
+        ldap_handler.ldap_info.require_tls_for_bind = true;
+        ldap_handler.set_tls_status(TlsStatus::StartTlsAvailable);
+        let request = LdapBindRequest {
+            dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::ConfidentialityRequired
+        );
+        ldap_handler.set_tls_status(TlsStatus::Encrypted);
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::Success
+        );
+    }
+
+    #[tokio::test]
+    async fn test_admin_bind() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_bind()
+            .with(eq(crate::domain::handler::BindRequest {
+                name: UserId::new("test"),
+                password: "pass".to_string(),
+            }))
+            .times(1)
+            .return_once(|_| Ok(()));
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("test")))
+            .return_once(|_| {
+                let mut set = HashSet::new();
+                set.insert(GroupDetails {
+                    group_id: GroupId(42),
+                    display_name: "lldap_admin".to_string(),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                });
+                Ok(set)
+            });
+        let mut ldap_handler = LdapHandler::new_for_tests(mock, "dc=example,dc=com");
+
+        let request = LdapBindRequest {
+            dn: "uid=test,ou=people,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::Success
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_regular_user() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::And(vec![
+                    true.into(),
+                    UserRequestFilter::UserId(UserId::new("test")),
+                ]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("test"),
+                        ..Default::default()
+                    },
+                    groups: None,
+                }])
+            });
+        let mut ldap_handler = setup_bound_handler_with_group(mock, "regular").await;
+
+        let request =
+            make_user_search_request::<String>(LdapFilter::And(vec![]), vec!["1.1".to_string()]);
         assert_eq!(
             ldap_handler.do_search_or_dse(&request).await,
             Ok(vec![
@@ -1022,811 +2516,3360 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_search_user_as_scope() {
+    async fn test_search_nested_groups() {
+        let rockstars = GroupDetails {
+            group_id: GroupId(42),
+            display_name: "rockstars".to_string(),
+            creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+            uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+        };
         let mut mock = MockTestBackendHandler::new();
+        let groups = vec![
+            Group {
+                id: GroupId(1),
+                display_name: "teams".to_string(),
+                creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                users: vec![],
+                attributes: vec![],
+                dynamic_filter: None,
+                member_groups: vec![rockstars.clone()],
+                uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+            },
+            Group {
+                id: GroupId(42),
+                display_name: "rockstars".to_string(),
+                creation_date: rockstars.creation_date,
+                users: vec![UserId::new("bob")],
+                attributes: vec![],
+                dynamic_filter: None,
+                member_groups: vec![],
+                uuid: rockstars.uuid.clone(),
+            },
+        ];
+        mock.expect_list_groups()
+            .with(eq(None))
+            .times(1)
+            .return_once(|_| Ok(groups));
+        // The members of the nested groups match.
         mock.expect_list_users()
             .with(
-                eq(Some(UserRequestFilter::And(vec![
-                    true.into(),
-                    UserRequestFilter::UserId(UserId::new("bob")),
+                eq(Some(UserRequestFilter::Or(vec![
+                    UserRequestFilter::MemberOf("teams".to_owned()),
+                    UserRequestFilter::MemberOfId(GroupId(42)),
                 ]))),
-                eq(false),
+                eq(true),
             )
             .times(1)
-            .return_once(|_, _| Ok(vec![]));
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        ..Default::default()
+                    },
+                    groups: Some(vec![rockstars]),
+                }])
+            });
         let mut ldap_handler = setup_bound_readonly_handler(mock).await;
-
-        let request = LdapSearchRequest {
-            base: "uid=bob,ou=people,Dc=example,dc=com".to_string(),
-            scope: LdapSearchScope::Base,
-            aliases: LdapDerefAliases::Never,
-            sizelimit: 0,
-            timelimit: 0,
-            typesonly: false,
-            filter: LdapFilter::And(vec![]),
-            attrs: vec!["1.1".to_string()],
-        };
+        ldap_handler.ldap_info = LdapInfo::new(&crate::infra::configuration::Configuration {
+            ldap_flatten_nested_groups: true,
+            ..crate::infra::configuration::ConfigurationBuilder::for_tests()
+        });
+        let request = make_user_search_request(
+            LdapFilter::Equality(
+                "memberOf".to_string(),
+                "cn=teams,ou=groups,dc=example,dc=com".to_string(),
+            ),
+            vec!["memberOf"],
+        );
         assert_eq!(
             ldap_handler.do_search_or_dse(&request).await,
-            Ok(vec![make_search_success()]),
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![LdapPartialAttribute {
+                        atype: "memberOf".to_string(),
+                        vals: vec![
+                            b"cn=rockstars,ou=groups,dc=example,dc=com".to_vec(),
+                            b"cn=teams,ou=groups,dc=example,dc=com".to_vec(),
+                        ]
+                    }],
+                }),
+                make_search_success(),
+            ]),
         );
     }
 
     #[tokio::test]
-    async fn test_bind_invalid_dn() {
-        let mock = MockTestBackendHandler::new();
-        let mut ldap_handler = LdapHandler::new_for_tests(mock, "dc=example,dc=com");
-
-        let request = LdapBindRequest {
-            dn: "cn=bob,dc=example,dc=com".to_string(),
-            cred: LdapBindCred::Simple("pass".to_string()),
-        };
-        assert_eq!(
-            ldap_handler.do_bind(&request).await.0,
-            LdapResultCode::NamingViolation,
-        );
-        let request = LdapBindRequest {
-            dn: "uid=bob,dc=example,dc=com".to_string(),
-            cred: LdapBindCred::Simple("pass".to_string()),
-        };
-        assert_eq!(
-            ldap_handler.do_bind(&request).await.0,
-            LdapResultCode::NamingViolation,
-        );
-        let request = LdapBindRequest {
-            dn: "uid=bob,ou=groups,dc=example,dc=com".to_string(),
-            cred: LdapBindCred::Simple("pass".to_string()),
-        };
-        assert_eq!(
-            ldap_handler.do_bind(&request).await.0,
-            LdapResultCode::NamingViolation,
-        );
-        let request = LdapBindRequest {
-            dn: "uid=bob,ou=people,dc=example,dc=fr".to_string(),
-            cred: LdapBindCred::Simple("pass".to_string()),
-        };
-        assert_eq!(
+    async fn test_custom_dn_shape() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_bind()
+            .with(eq(BindRequest {
+                name: UserId::new("test"),
+                password: "pass".to_string(),
+            }))
+            .return_once(|_| Ok(()));
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("test")))
+            .return_once(|_| {
+                Ok(HashSet::from([GroupDetails {
+                    group_id: GroupId(42),
+                    display_name: "lldap_strict_readonly".to_string(),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                }]))
+            });
+        mock.expect_list_users()
+            .with(eq(Some(true.into())), eq(true))
+            .times(1)
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        ..Default::default()
+                    },
+                    groups: Some(vec![GroupDetails {
+                        group_id: GroupId(42),
+                        display_name: "rockstars".to_string(),
+                        creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                        uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                    }]),
+                }])
+            });
+        setup_default_schema(&mut mock);
+        let mut ldap_handler = LdapHandler::new(
+            AccessControlledBackendHandler::new(mock),
+            LdapInfo::new(&crate::infra::configuration::Configuration {
+                ldap_people_ou: "Users".to_string(),
+                ldap_groups_ou: "teams".to_string(),
+                ldap_user_rdn_attribute: crate::infra::configuration::LdapUserRdnAttribute::Cn,
+                ..crate::infra::configuration::ConfigurationBuilder::for_tests()
+            }),
+        );
+        // The default OU is not recognized anymore.
+        let request = LdapBindRequest {
+            dn: "uid=test,ou=people,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        };
+        assert_eq!(
             ldap_handler.do_bind(&request).await.0,
-            LdapResultCode::NamingViolation,
+            LdapResultCode::NamingViolation
         );
         let request = LdapBindRequest {
-            dn: "uid=bob=test,ou=people,dc=example,dc=com".to_string(),
+            dn: "cn=test,ou=users,dc=example,dc=com".to_string(),
             cred: LdapBindCred::Simple("pass".to_string()),
         };
         assert_eq!(
             ldap_handler.do_bind(&request).await.0,
-            LdapResultCode::NamingViolation,
+            LdapResultCode::Success
+        );
+
+        let request = make_search_request::<String>(
+            "ou=users,dc=example,dc=com",
+            LdapFilter::And(vec![]),
+            vec!["memberOf".to_string(), "entryDN".to_string()],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "cn=bob,ou=users,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "memberOf".to_string(),
+                            vals: vec![b"cn=rockstars,ou=teams,dc=example,dc=com".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "entryDN".to_string(),
+                            vals: vec![b"cn=bob,ou=users,dc=example,dc=com".to_vec()]
+                        },
+                    ],
+                }),
+                make_search_success(),
+            ]),
         );
     }
 
-    #[test]
-    fn test_is_subtree() {
-        let subtree1 = &[
-            ("ou".to_string(), "people".to_string()),
-            ("dc".to_string(), "example".to_string()),
-            ("dc".to_string(), "com".to_string()),
-        ];
-        let root = &[
-            ("dc".to_string(), "example".to_string()),
-            ("dc".to_string(), "com".to_string()),
-        ];
-        assert!(is_subtree(subtree1, root));
-        assert!(!is_subtree(&[], root));
+    #[tokio::test]
+    async fn test_naming_contexts() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .returning(|_| Ok(HashSet::new()));
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::And(vec![
+                    true.into(),
+                    UserRequestFilter::MemberOf("org1".to_string()),
+                ]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| Ok(vec![]));
+        mock.expect_reject_bind()
+            .with(eq(BindRequest {
+                name: UserId::new("bob"),
+                password: "pass".to_string(),
+            }))
+            .times(1)
+            .return_once(|_| {
+                Err(crate::domain::error::DomainError::AuthenticationError(
+                    "bob".to_string(),
+                ))
+            });
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+        ldap_handler.ldap_info = LdapInfo::new(&crate::infra::configuration::Configuration {
+            ldap_naming_contexts: vec![crate::infra::configuration::LdapNamingContext {
+                base_dn: "dc=org1,dc=com".to_string(),
+                group: "org1".to_string(),
+            }],
+            ..crate::infra::configuration::ConfigurationBuilder::for_tests()
+        });
+        assert_eq!(
+            ldap_handler.ldap_info.all_base_dns().collect::<Vec<_>>(),
+            vec!["dc=example,dc=com", "dc=org1,dc=com"]
+        );
+        let request = make_search_request::<String>(
+            "ou=people,dc=org1,dc=com",
+            LdapFilter::And(vec![]),
+            vec!["1.1".to_string()],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()]),
+        );
+        // Bob is not a member of the context's group.
+        let request = LdapBindRequest {
+            dn: "uid=bob,ou=people,dc=org1,dc=com".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::InvalidCredentials
+        );
     }
 
-    #[test]
-    fn test_parse_distinguished_name() {
-        let parsed_dn = &[
-            ("ou".to_string(), "people".to_string()),
-            ("dc".to_string(), "example".to_string()),
-            ("dc".to_string(), "com".to_string()),
-        ];
+    #[tokio::test]
+    async fn test_referrals() {
+        let mut ldap_handler = setup_bound_admin_handler(MockTestBackendHandler::new()).await;
+        ldap_handler.ldap_info = LdapInfo::new(&crate::infra::configuration::Configuration {
+            ldap_referrals: vec![
+                crate::infra::configuration::LdapReferral {
+                    base_dn: "dc=corp,dc=com".to_string(),
+                    url: "ldap://ldap.corp.com:389".parse().unwrap(),
+                },
+                crate::infra::configuration::LdapReferral {
+                    base_dn: "ou=legacy,dc=example,dc=com".to_string(),
+                    url: "ldaps://legacy.example.com".parse().unwrap(),
+                },
+            ],
+            ..crate::infra::configuration::ConfigurationBuilder::for_tests()
+        });
         assert_eq!(
-            parse_distinguished_name("ou=people,dc=example,dc=com").expect("parsing failed"),
-            parsed_dn
+            ldap_handler
+                .ldap_info
+                .referral_for("uid=bob,ou=people,dc=example,dc=com"),
+            None
         );
+        assert_eq!(ldap_handler.ldap_info.referral_for("dc=other,dc=com"), None);
         assert_eq!(
-            parse_distinguished_name(" ou  = people , dc = example , dc =  com ")
-                .expect("parsing failed"),
-            parsed_dn
+            ldap_handler
+                .ldap_info
+                .referral_for("uid=bob,ou=legacy,dc=example,dc=com"),
+            Some("ldaps://legacy.example.com/uid=bob,ou=legacy,dc=example,dc=com".to_string())
+        );
+        let request = make_search_request::<String>(
+            "ou=people,dc=corp,dc=com",
+            LdapFilter::And(vec![]),
+            vec!["1.1".to_string()],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![LdapOp::SearchResultDone(make_referral_result(
+                "ldap://ldap.corp.com:389/ou=people,dc=corp,dc=com".to_string()
+            ))]),
+        );
+        let request = LdapOp::BindRequest(LdapBindRequest {
+            dn: "uid=bob,ou=people,dc=corp,dc=com".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        });
+        assert_eq!(
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![LdapOp::BindResponse(LdapBindResponse {
+                res: make_referral_result(
+                    "ldap://ldap.corp.com:389/uid=bob,ou=people,dc=corp,dc=com".to_string()
+                ),
+                saslcreds: None,
+            })])
         );
     }
 
     #[tokio::test]
-    async fn test_search_users() {
-        use chrono::prelude::*;
+    async fn test_search_posix_attributes() {
         let mut mock = MockTestBackendHandler::new();
-        mock.expect_list_users().times(1).return_once(|_, _| {
-            Ok(vec![
-                UserAndGroups {
+        mock.expect_list_users()
+            .with(eq(Some(true.into())), eq(true))
+            .times(1)
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
                     user: User {
-                        user_id: UserId::new("bob_1"),
-                        email: "bob@bobmail.bob".to_string(),
-                        display_name: Some("Bôb Böbberson".to_string()),
-                        uuid: uuid!("698e1d5f-7a40-3151-8745-b9b8a37839da"),
-                        attributes: vec![
-                            AttributeValue {
-                                name: "first_name".to_owned(),
-                                value: Serialized::from("Bôb"),
-                            },
-                            AttributeValue {
-                                name: "last_name".to_owned(),
-                                value: Serialized::from("Böbberson"),
-                            },
-                        ],
+                        user_id: UserId::new("bob"),
+                        uid_number: 3,
                         ..Default::default()
                     },
-                    groups: None,
-                },
-                UserAndGroups {
-                    user: User {
-                        user_id: UserId::new("jim"),
-                        email: "jim@cricket.jim".to_string(),
-                        display_name: Some("Jimminy Cricket".to_string()),
-                        attributes: vec![
-                            AttributeValue {
-                                name: "avatar".to_owned(),
-                                value: Serialized::from(&JpegPhoto::for_tests()),
-                            },
-                            AttributeValue {
-                                name: "first_name".to_owned(),
-                                value: Serialized::from("Jim"),
-                            },
-                            AttributeValue {
-                                name: "last_name".to_owned(),
-                                value: Serialized::from("Cricket"),
-                            },
-                        ],
-                        uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
-                        creation_date: Utc
-                            .with_ymd_and_hms(2014, 7, 8, 9, 10, 11)
-                            .unwrap()
-                            .naive_utc(),
-                    },
-                    groups: None,
-                },
-            ])
-        });
-        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+                    groups: Some(vec![
+                        GroupDetails {
+                            group_id: GroupId(42),
+                            display_name: "rockstars".to_string(),
+                            creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                            uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                        },
+                        GroupDetails {
+                            group_id: GroupId(7),
+                            display_name: "admins".to_string(),
+                            creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                            uuid: uuid!("b1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                        },
+                    ]),
+                }])
+            });
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+
         let request = make_user_search_request(
             LdapFilter::And(vec![]),
-            vec![
-                "objectClass",
-                "dn",
-                "uid",
-                "mail",
-                "givenName",
-                "sn",
-                "cn",
-                "createTimestamp",
-                "entryUuid",
-                "jpegPhoto",
-            ],
+            vec!["uidNumber", "gidNumber", "homeDirectory", "loginShell"],
         );
         assert_eq!(
             ldap_handler.do_search_or_dse(&request).await,
             Ok(vec![
                 LdapOp::SearchResultEntry(LdapSearchResultEntry {
-                    dn: "uid=bob_1,ou=people,dc=example,dc=com".to_string(),
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
                     attributes: vec![
                         LdapPartialAttribute {
-                            atype: "objectClass".to_string(),
-                            vals: vec![
-                                b"inetOrgPerson".to_vec(),
-                                b"posixAccount".to_vec(),
-                                b"mailAccount".to_vec(),
-                                b"person".to_vec()
-                            ]
-                        },
-                        LdapPartialAttribute {
-                            atype: "uid".to_string(),
-                            vals: vec![b"bob_1".to_vec()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "mail".to_string(),
-                            vals: vec![b"bob@bobmail.bob".to_vec()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "givenName".to_string(),
-                            vals: vec!["Bôb".to_string().into_bytes()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "sn".to_string(),
-                            vals: vec!["Böbberson".to_string().into_bytes()]
+                            atype: "uidNumber".to_string(),
+                            vals: vec![b"10003".to_vec()]
                         },
                         LdapPartialAttribute {
-                            atype: "cn".to_string(),
-                            vals: vec!["Bôb Böbberson".to_string().into_bytes()]
+                            atype: "gidNumber".to_string(),
+                            vals: vec![b"10007".to_vec()]
                         },
                         LdapPartialAttribute {
-                            atype: "createTimestamp".to_string(),
-                            vals: vec![b"1970-01-01T00:00:00+00:00".to_vec()]
+                            atype: "homeDirectory".to_string(),
+                            vals: vec![b"/home/bob".to_vec()]
                         },
                         LdapPartialAttribute {
-                            atype: "entryUuid".to_string(),
-                            vals: vec![b"698e1d5f-7a40-3151-8745-b9b8a37839da".to_vec()]
-                        },
-                    ],
-                }),
-                LdapOp::SearchResultEntry(LdapSearchResultEntry {
-                    dn: "uid=jim,ou=people,dc=example,dc=com".to_string(),
-                    attributes: vec![
-                        LdapPartialAttribute {
-                            atype: "objectClass".to_string(),
-                            vals: vec![
-                                b"inetOrgPerson".to_vec(),
-                                b"posixAccount".to_vec(),
-                                b"mailAccount".to_vec(),
-                                b"person".to_vec()
-                            ]
-                        },
-                        LdapPartialAttribute {
-                            atype: "uid".to_string(),
-                            vals: vec![b"jim".to_vec()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "mail".to_string(),
-                            vals: vec![b"jim@cricket.jim".to_vec()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "givenName".to_string(),
-                            vals: vec![b"Jim".to_vec()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "sn".to_string(),
-                            vals: vec![b"Cricket".to_vec()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "cn".to_string(),
-                            vals: vec![b"Jimminy Cricket".to_vec()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "createTimestamp".to_string(),
-                            vals: vec![b"2014-07-08T09:10:11+00:00".to_vec()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "entryUuid".to_string(),
-                            vals: vec![b"04ac75e0-2900-3e21-926c-2f732c26b3fc".to_vec()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "jpegPhoto".to_string(),
-                            vals: vec![JpegPhoto::for_tests().into_bytes()]
+                            atype: "loginShell".to_string(),
+                            vals: vec![b"/bin/bash".to_vec()]
                         },
                     ],
                 }),
                 make_search_success(),
-            ])
+            ]),
         );
     }
 
     #[tokio::test]
-    async fn test_search_groups() {
+    async fn test_search_shadow_attributes() {
         let mut mock = MockTestBackendHandler::new();
-        mock.expect_list_groups()
-            .with(eq(Some(true.into())))
+        mock.expect_list_users()
+            .with(eq(Some(true.into())), eq(false))
             .times(1)
-            .return_once(|_| {
+            .return_once(|_, _| {
                 Ok(vec![
-                    Group {
-                        id: GroupId(1),
-                        display_name: "group_1".to_string(),
-                        creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
-                        users: vec![UserId::new("bob"), UserId::new("john")],
-                        uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    UserAndGroups {
+                        user: User {
+                            user_id: UserId::new("bob"),
+                            password_modified_date: Some(
+                                chrono::Utc
+                                    .with_ymd_and_hms(1970, 1, 11, 12, 0, 0)
+                                    .unwrap()
+                                    .naive_utc(),
+                            ),
+                            ..Default::default()
+                        },
+                        groups: None,
                     },
-                    Group {
-                        id: GroupId(3),
-                        display_name: "BestGroup".to_string(),
-                        creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
-                        users: vec![UserId::new("john")],
-                        uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    UserAndGroups {
+                        user: User {
+                            user_id: UserId::new("john"),
+                            ..Default::default()
+                        },
+                        groups: None,
                     },
                 ])
             });
-        let mut ldap_handler = setup_bound_admin_handler(mock).await;
-        let request = make_group_search_request(
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+
+        let request = make_user_search_request(
             LdapFilter::And(vec![]),
-            vec!["objectClass", "dn", "cn", "uniqueMember", "entryUuid"],
+            vec!["shadowLastChange", "shadowMax", "shadowExpire"],
         );
         assert_eq!(
             ldap_handler.do_search_or_dse(&request).await,
             Ok(vec![
                 LdapOp::SearchResultEntry(LdapSearchResultEntry {
-                    dn: "cn=group_1,ou=groups,dc=example,dc=com".to_string(),
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![LdapPartialAttribute {
+                        atype: "shadowLastChange".to_string(),
+                        vals: vec![b"10".to_vec()]
+                    }],
+                }),
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=john,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![],
+                }),
+                make_search_success(),
+            ]),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_operational_attributes() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users().times(2).returning(|_, _| {
+            Ok(vec![UserAndGroups {
+                user: User {
+                    user_id: UserId::new("bob"),
+                    uuid: uuid!("698e1d5f-7a40-3151-8745-b9b8a37839da"),
+                    ..Default::default()
+                },
+                groups: None,
+            }])
+        });
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+
+        let request = make_user_search_request(LdapFilter::And(vec![]), vec!["entryDN"]);
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![LdapPartialAttribute {
+                        atype: "entryDN".to_string(),
+                        vals: vec![b"uid=bob,ou=people,dc=example,dc=com".to_vec()]
+                    }],
+                }),
+                make_search_success(),
+            ]),
+        );
+
+        let request = make_user_search_request(LdapFilter::And(vec![]), vec!["uid", "+"]);
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
                     attributes: vec![
                         LdapPartialAttribute {
-                            atype: "objectClass".to_string(),
-                            vals: vec![b"groupOfUniqueNames".to_vec(),]
-                        },
-                        LdapPartialAttribute {
-                            atype: "cn".to_string(),
-                            vals: vec![b"group_1".to_vec()]
+                            atype: "uid".to_string(),
+                            vals: vec![b"bob".to_vec()]
                         },
                         LdapPartialAttribute {
-                            atype: "uniqueMember".to_string(),
-                            vals: vec![
-                                b"uid=bob,ou=people,dc=example,dc=com".to_vec(),
-                                b"uid=john,ou=people,dc=example,dc=com".to_vec(),
-                            ]
+                            atype: "createtimestamp".to_string(),
+                            vals: vec![chrono::Utc
+                                .timestamp_opt(0, 0)
+                                .unwrap()
+                                .to_rfc3339()
+                                .into_bytes()]
                         },
                         LdapPartialAttribute {
-                            atype: "entryUuid".to_string(),
-                            vals: vec![b"04ac75e0-2900-3e21-926c-2f732c26b3fc".to_vec()],
+                            atype: "modifytimestamp".to_string(),
+                            vals: vec![chrono::Utc
+                                .timestamp_opt(0, 0)
+                                .unwrap()
+                                .to_rfc3339()
+                                .into_bytes()]
                         },
-                    ],
-                }),
-                LdapOp::SearchResultEntry(LdapSearchResultEntry {
-                    dn: "cn=BestGroup,ou=groups,dc=example,dc=com".to_string(),
-                    attributes: vec![
                         LdapPartialAttribute {
-                            atype: "objectClass".to_string(),
-                            vals: vec![b"groupOfUniqueNames".to_vec(),]
+                            atype: "entryuuid".to_string(),
+                            vals: vec![b"698e1d5f-7a40-3151-8745-b9b8a37839da".to_vec()]
                         },
                         LdapPartialAttribute {
-                            atype: "cn".to_string(),
-                            vals: vec![b"BestGroup".to_vec()]
+                            atype: "entrydn".to_string(),
+                            vals: vec![b"uid=bob,ou=people,dc=example,dc=com".to_vec()]
                         },
                         LdapPartialAttribute {
-                            atype: "uniqueMember".to_string(),
-                            vals: vec![b"uid=john,ou=people,dc=example,dc=com".to_vec()]
+                            atype: "creatorsname".to_string(),
+                            vals: vec![b"uid=admin,ou=people,dc=example,dc=com".to_vec()]
                         },
                         LdapPartialAttribute {
-                            atype: "entryUuid".to_string(),
-                            vals: vec![b"04ac75e0-2900-3e21-926c-2f732c26b3fc".to_vec()],
+                            atype: "hassubordinates".to_string(),
+                            vals: vec![b"FALSE".to_vec()]
                         },
                     ],
                 }),
                 make_search_success(),
-            ])
+            ]),
         );
     }
 
     #[tokio::test]
-    async fn test_search_groups_filter() {
+    async fn test_search_attribute_aliases() {
         let mut mock = MockTestBackendHandler::new();
-        mock.expect_list_groups()
-            .with(eq(Some(GroupRequestFilter::And(vec![
-                GroupRequestFilter::DisplayName("group_1".to_string()),
-                GroupRequestFilter::Member(UserId::new("bob")),
-                GroupRequestFilter::DisplayName("rockstars".to_string()),
-                false.into(),
-                GroupRequestFilter::Uuid(uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc")),
-                true.into(),
-                true.into(),
-                true.into(),
-                true.into(),
-                GroupRequestFilter::Not(Box::new(false.into())),
-                false.into(),
-                GroupRequestFilter::DisplayNameSubString(SubStringFilter {
-                    initial: Some("iNIt".to_owned()),
-                    any: vec!["1".to_owned(), "2aA".to_owned()],
-                    final_: Some("finAl".to_owned()),
-                }),
-            ]))))
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::Equality(
+                    UserColumn::Email,
+                    "bob@bobmail.bob".to_owned(),
+                ))),
+                eq(false),
+            )
             .times(1)
-            .return_once(|_| {
-                Ok(vec![Group {
-                    display_name: "group_1".to_string(),
-                    id: GroupId(1),
-                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
-                    users: vec![],
-                    uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        email: "bob@bobmail.bob".to_owned(),
+                        attributes: vec![AttributeValue {
+                            name: "first_name".to_owned(),
+                            value: Serialized::from("Bôb"),
+                        }],
+                        ..Default::default()
+                    },
+                    groups: None,
                 }])
             });
-        let mut ldap_handler = setup_bound_admin_handler(mock).await;
-        let request = make_group_search_request(
-            LdapFilter::And(vec![
-                LdapFilter::Equality("cN".to_string(), "Group_1".to_string()),
-                LdapFilter::Equality(
-                    "uniqueMember".to_string(),
-                    "uid=bob,ou=peopLe,Dc=eXample,dc=com".to_string(),
-                ),
-                LdapFilter::Equality(
-                    "dn".to_string(),
-                    "uid=rockstars,ou=groups,dc=example,dc=com".to_string(),
-                ),
-                LdapFilter::Equality(
-                    "dn".to_string(),
-                    "uid=rockstars,ou=people,dc=example,dc=com".to_string(),
-                ),
-                LdapFilter::Equality(
-                    "uuid".to_string(),
-                    "04ac75e0-2900-3e21-926c-2f732c26b3fc".to_string(),
-                ),
-                LdapFilter::Equality("obJEctclass".to_string(), "groupofUniqueNames".to_string()),
-                LdapFilter::Equality("objectclass".to_string(), "groupOfNames".to_string()),
-                LdapFilter::Present("objectclass".to_string()),
-                LdapFilter::Present("dn".to_string()),
-                LdapFilter::Not(Box::new(LdapFilter::Present(
-                    "random_attribUte".to_string(),
-                ))),
-                LdapFilter::Equality("unknown_attribute".to_string(), "randomValue".to_string()),
-                LdapFilter::Substring(
-                    "cn".to_owned(),
-                    LdapSubstringFilter {
-                        initial: Some("iNIt".to_owned()),
-                        any: vec!["1".to_owned(), "2aA".to_owned()],
-                        final_: Some("finAl".to_owned()),
-                    },
-                ),
-            ]),
-            vec!["1.1"],
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+        ldap_handler
+            .ldap_info
+            .user_attribute_aliases
+            .insert("mail-alternate".to_owned(), "email".to_owned());
+        ldap_handler
+            .ldap_info
+            .user_attribute_aliases
+            .insert("zimbrafirstname".to_owned(), "first_name".to_owned());
+
+        let request = make_user_search_request(
+            LdapFilter::Equality("Mail-Alternate".to_string(), "bob@bobmail.bob".to_string()),
+            vec!["mail-alternate", "zimbraFirstName"],
         );
         assert_eq!(
             ldap_handler.do_search_or_dse(&request).await,
             Ok(vec![
                 LdapOp::SearchResultEntry(LdapSearchResultEntry {
-                    dn: "cn=group_1,ou=groups,dc=example,dc=com".to_string(),
-                    attributes: vec![],
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "mail-alternate".to_string(),
+                            vals: vec![b"bob@bobmail.bob".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "zimbraFirstName".to_string(),
+                            vals: vec!["Bôb".to_string().into_bytes()]
+                        },
+                    ],
                 }),
                 make_search_success(),
-            ])
+            ]),
         );
     }
 
     #[tokio::test]
-    async fn test_search_groups_filter_2() {
+    async fn test_search_phone_attributes() {
         let mut mock = MockTestBackendHandler::new();
-        mock.expect_list_groups()
-            .with(eq(Some(GroupRequestFilter::Or(vec![
-                GroupRequestFilter::Not(Box::new(GroupRequestFilter::DisplayName(
-                    "group_2".to_string(),
-                ))),
-            ]))))
+        // Takes precedence over the default schema.
+        mock.expect_get_schema().returning(|| {
+            Ok(Schema {
+                user_attributes: AttributeList {
+                    attributes: ["phone", "mobile"]
+                        .into_iter()
+                        .map(|name| AttributeSchema {
+                            name: name.to_owned(),
+                            attribute_type: AttributeType::String,
+                            is_list: false,
+                            is_visible: true,
+                            is_editable: true,
+                            is_hardcoded: false,
+                            is_case_sensitive: false,
+                            allowed_values: None,
+                        })
+                        .collect(),
+                },
+                group_attributes: AttributeList {
+                    attributes: Vec::new(),
+                },
+            })
+        });
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::Or(vec![
+                    UserRequestFilter::AttributeEqualityIgnoreCase(
+                        "phone".to_owned(),
+                        "+123".to_owned(),
+                    ),
+                    UserRequestFilter::AttributeEqualityIgnoreCase(
+                        "mobile".to_owned(),
+                        "+456".to_owned(),
+                    ),
+                ]))),
+                eq(false),
+            )
             .times(1)
-            .return_once(|_| {
-                Ok(vec![Group {
-                    display_name: "group_1".to_string(),
-                    id: GroupId(1),
-                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
-                    users: vec![],
-                    uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        attributes: vec![
+                            AttributeValue {
+                                name: "phone".to_owned(),
+                                value: Serialized::from("+123"),
+                            },
+                            AttributeValue {
+                                name: "mobile".to_owned(),
+                                value: Serialized::from("+456"),
+                            },
+                        ],
+                        ..Default::default()
+                    },
+                    groups: None,
                 }])
             });
-        let mut ldap_handler = setup_bound_admin_handler(mock).await;
-        let request = make_group_search_request(
-            LdapFilter::Or(vec![LdapFilter::Not(Box::new(LdapFilter::Equality(
-                "displayname".to_string(),
-                "group_2".to_string(),
-            )))]),
-            vec!["cn"],
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+
+        let request = make_user_search_request(
+            LdapFilter::Or(vec![
+                LdapFilter::Equality("telephoneNumber".to_string(), "+123".to_string()),
+                LdapFilter::Equality("mobile".to_string(), "+456".to_string()),
+            ]),
+            vec!["telephoneNumber", "mobile"],
         );
         assert_eq!(
             ldap_handler.do_search_or_dse(&request).await,
             Ok(vec![
                 LdapOp::SearchResultEntry(LdapSearchResultEntry {
-                    dn: "cn=group_1,ou=groups,dc=example,dc=com".to_string(),
-                    attributes: vec![LdapPartialAttribute {
-                        atype: "cn".to_string(),
-                        vals: vec![b"group_1".to_vec()]
-                    },],
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "telephoneNumber".to_string(),
+                            vals: vec![b"+123".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "mobile".to_string(),
+                            vals: vec![b"+456".to_vec()]
+                        },
+                    ],
                 }),
                 make_search_success(),
-            ])
+            ]),
         );
     }
 
     #[tokio::test]
-    async fn test_search_group_as_scope() {
+    async fn test_search_virtual_attributes() {
         let mut mock = MockTestBackendHandler::new();
-        mock.expect_list_groups()
-            .with(eq(Some(GroupRequestFilter::And(vec![
-                true.into(),
-                GroupRequestFilter::DisplayName("rockstars".to_string()),
-            ]))))
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::And(vec![
+                    true.into(),
+                    UserRequestFilter::from(false),
+                ]))),
+                eq(false),
+            )
             .times(1)
-            .return_once(|_| Ok(vec![]));
-        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
-
-        let request = LdapSearchRequest {
-            base: "uid=rockstars,ou=groups,Dc=example,dc=com".to_string(),
-            scope: LdapSearchScope::Base,
-            aliases: LdapDerefAliases::Never,
-            sizelimit: 0,
-            timelimit: 0,
-            typesonly: false,
-            filter: LdapFilter::And(vec![]),
-            attrs: vec!["1.1".to_string()],
-        };
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        email: "bob@bobmail.bob".to_string(),
+                        attributes: vec![
+                            AttributeValue {
+                                name: "first_name".to_owned(),
+                                value: Serialized::from("Bob"),
+                            },
+                            AttributeValue {
+                                name: "last_name".to_owned(),
+                                value: Serialized::from("Bobberson"),
+                            },
+                        ],
+                        ..Default::default()
+                    },
+                    groups: None,
+                }])
+            });
+        expect_bob_details(&mut mock);
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        ldap_handler.ldap_info = LdapInfo::new(&crate::infra::configuration::Configuration {
+            ldap_virtual_attributes: vec![
+                crate::infra::configuration::LdapVirtualAttribute {
+                    name: "displayName".to_string(),
+                    template: "{sn}, {givenName}".to_string(),
+                },
+                crate::infra::configuration::LdapVirtualAttribute {
+                    name: "proxyAddresses".to_string(),
+                    template: "SMTP:{mail}".to_string(),
+                },
+            ],
+            ..crate::infra::configuration::ConfigurationBuilder::for_tests()
+        });
+        let request = make_user_search_request(
+            LdapFilter::And(vec![
+                LdapFilter::Present("proxyAddresses".to_string()),
+                // Not evaluated in the filters.
+                LdapFilter::Equality(
+                    "proxyAddresses".to_string(),
+                    "SMTP:bob@bobmail.bob".to_string(),
+                ),
+            ]),
+            vec!["displayName", "proxyAddresses"],
+        );
         assert_eq!(
             ldap_handler.do_search_or_dse(&request).await,
-            Ok(vec![make_search_success()]),
-        );
-    }
-
-    #[tokio::test]
-    async fn test_search_groups_unsupported_substring() {
-        let mut ldap_handler = setup_bound_admin_handler(MockTestBackendHandler::new()).await;
-        let request = make_group_search_request(
-            LdapFilter::Substring("member".to_owned(), LdapSubstringFilter::default()),
-            vec!["cn"],
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "displayName".to_string(),
+                            vals: vec![b"Bobberson, Bob".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "proxyAddresses".to_string(),
+                            vals: vec![b"SMTP:bob@bobmail.bob".to_vec()]
+                        },
+                    ],
+                }),
+                make_search_success()
+            ])
         );
+        // The virtual attributes are read-only.
+        let request = LdapModifyRequest {
+            dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+            changes: vec![make_modify(
+                LdapModifyType::Replace,
+                "proxyAddresses",
+                vec!["SMTP:other@bobmail.bob"],
+            )],
+        };
         assert_eq!(
-            ldap_handler.do_search_or_dse(&request).await,
-            Err(LdapError {
-                code: LdapResultCode::UnwillingToPerform,
-                message: r#"Unsupported group attribute for substring filter: "member""#.to_owned()
-            })
+            ldap_handler.do_modify_request(&request).await,
+            vec![make_modify_response(
+                LdapResultCode::ConstraintViolation,
+                "Attribute `proxyAddresses` is read-only".to_string(),
+            )]
         );
     }
 
     #[tokio::test]
-    async fn test_search_groups_error() {
+    async fn test_search_posix_custom_attributes() {
         let mut mock = MockTestBackendHandler::new();
-        mock.expect_list_groups()
-            .with(eq(Some(GroupRequestFilter::Or(vec![
-                GroupRequestFilter::Not(Box::new(GroupRequestFilter::DisplayName(
-                    "group_2".to_string(),
-                ))),
-            ]))))
+        // Takes precedence over the default schema.
+        mock.expect_get_schema().returning(|| {
+            Ok(Schema {
+                user_attributes: AttributeList {
+                    attributes: ["home_directory", "login_shell"]
+                        .into_iter()
+                        .map(|name| AttributeSchema {
+                            name: name.to_owned(),
+                            attribute_type: AttributeType::String,
+                            is_list: false,
+                            is_visible: true,
+                            is_editable: true,
+                            is_hardcoded: false,
+                            is_case_sensitive: false,
+                            allowed_values: None,
+                        })
+                        .collect(),
+                },
+                group_attributes: AttributeList {
+                    attributes: Vec::new(),
+                },
+            })
+        });
+        let not_present = |name: &str| {
+            UserRequestFilter::Not(Box::new(UserRequestFilter::AttributePresent(
+                name.to_owned(),
+            )))
+        };
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::Or(vec![
+                    UserRequestFilter::Or(vec![
+                        UserRequestFilter::AttributeEquality(
+                            "home_directory".to_owned(),
+                            "/home/bob".to_owned(),
+                        ),
+                        UserRequestFilter::And(vec![
+                            not_present("home_directory"),
+                            UserRequestFilter::UserId(UserId::new("bob")),
+                        ]),
+                    ]),
+                    UserRequestFilter::Or(vec![
+                        UserRequestFilter::AttributeEquality(
+                            "login_shell".to_owned(),
+                            "/usr/sbin/nologin".to_owned(),
+                        ),
+                        UserRequestFilter::And(vec![not_present("login_shell"), false.into()]),
+                    ]),
+                ]))),
+                eq(false),
+            )
             .times(1)
-            .return_once(|_| {
-                Err(crate::domain::error::DomainError::InternalError(
-                    "Error getting groups".to_string(),
-                ))
+            .return_once(|_, _| {
+                Ok(vec![
+                    UserAndGroups {
+                        user: User {
+                            user_id: UserId::new("bob"),
+                            ..Default::default()
+                        },
+                        groups: None,
+                    },
+                    UserAndGroups {
+                        user: User {
+                            user_id: UserId::new("service"),
+                            attributes: vec![
+                                AttributeValue {
+                                    name: "home_directory".to_owned(),
+                                    value: Serialized::from("/var/lib/service"),
+                                },
+                                AttributeValue {
+                                    name: "login_shell".to_owned(),
+                                    value: Serialized::from("/usr/sbin/nologin"),
+                                },
+                            ],
+                            ..Default::default()
+                        },
+                        groups: None,
+                    },
+                ])
             });
-        let mut ldap_handler = setup_bound_admin_handler(mock).await;
-        let request = make_group_search_request(
-            LdapFilter::Or(vec![LdapFilter::Not(Box::new(LdapFilter::Equality(
-                "displayname".to_string(),
-                "group_2".to_string(),
-            )))]),
-            vec!["cn"],
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+
+        let request = make_user_search_request(
+            LdapFilter::Or(vec![
+                LdapFilter::Equality("homeDirectory".to_string(), "/home/bob".to_string()),
+                LdapFilter::Equality("loginShell".to_string(), "/usr/sbin/nologin".to_string()),
+            ]),
+            vec!["homeDirectory", "loginShell"],
         );
         assert_eq!(
             ldap_handler.do_search_or_dse(&request).await,
-            Err(LdapError{
-                code: LdapResultCode::Other,
-                message: r#"Error while listing groups "ou=groups,dc=example,dc=com": Internal error: `Error getting groups`"#.to_string()
-            })
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "homeDirectory".to_string(),
+                            vals: vec![b"/home/bob".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "loginShell".to_string(),
+                            vals: vec![b"/bin/bash".to_vec()]
+                        },
+                    ],
+                }),
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=service,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "homeDirectory".to_string(),
+                            vals: vec![b"/var/lib/service".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "loginShell".to_string(),
+                            vals: vec![b"/usr/sbin/nologin".to_vec()]
+                        },
+                    ],
+                }),
+                make_search_success(),
+            ]),
         );
     }
 
     #[tokio::test]
-    async fn test_search_groups_filter_error() {
-        let mut ldap_handler = setup_bound_admin_handler(MockTestBackendHandler::new()).await;
-        let request = make_group_search_request(
-            LdapFilter::And(vec![LdapFilter::Approx(
-                "whatever".to_owned(),
-                "value".to_owned(),
-            )]),
-            vec!["cn"],
+    async fn test_search_mail_aliases() {
+        let mut mock = MockTestBackendHandler::new();
+        // Takes precedence over the default schema.
+        mock.expect_get_schema().returning(|| {
+            Ok(Schema {
+                user_attributes: AttributeList {
+                    attributes: vec![AttributeSchema {
+                        name: "mail_aliases".to_owned(),
+                        attribute_type: AttributeType::String,
+                        is_list: true,
+                        is_visible: true,
+                        is_editable: true,
+                        is_hardcoded: false,
+                        is_case_sensitive: false,
+                        allowed_values: None,
+                    }],
+                },
+                group_attributes: AttributeList {
+                    attributes: Vec::new(),
+                },
+            })
+        });
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::Or(vec![
+                    UserRequestFilter::Equality(UserColumn::Email, "b@example.com".to_owned()),
+                    UserRequestFilter::AttributeListContains(
+                        "mail_aliases".to_owned(),
+                        "b@example.com".to_owned(),
+                    ),
+                ]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        email: "bob@example.com".to_owned(),
+                        attributes: vec![AttributeValue {
+                            name: "mail_aliases".to_owned(),
+                            value: Serialized::from(&vec![
+                                "b@example.com".to_owned(),
+                                "robert@example.com".to_owned(),
+                            ]),
+                        }],
+                        ..Default::default()
+                    },
+                    groups: None,
+                }])
+            });
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+
+        let request = make_user_search_request(
+            LdapFilter::Equality("mail".to_string(), "b@example.com".to_string()),
+            vec!["mail"],
         );
         assert_eq!(
             ldap_handler.do_search_or_dse(&request).await,
-            Err(LdapError {
-                code: LdapResultCode::UnwillingToPerform,
-                message: r#"Unsupported group filter: Approx("whatever", "value")"#.to_string()
-            })
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![LdapPartialAttribute {
+                        atype: "mail".to_string(),
+                        vals: vec![
+                            b"bob@example.com".to_vec(),
+                            b"b@example.com".to_vec(),
+                            b"robert@example.com".to_vec(),
+                        ]
+                    }],
+                }),
+                make_search_success(),
+            ]),
         );
     }
 
     #[tokio::test]
-    async fn test_search_filters() {
+    async fn test_search_paged_results() {
         let mut mock = MockTestBackendHandler::new();
-        mock.expect_list_users()
-            .with(
-                eq(Some(UserRequestFilter::And(vec![UserRequestFilter::Or(
-                    vec![
-                        UserRequestFilter::Not(Box::new(UserRequestFilter::UserId(UserId::new(
-                            "bob",
-                        )))),
-                        UserRequestFilter::UserId("bob_1".to_string().into()),
-                        false.into(),
-                        true.into(),
-                        false.into(),
-                        true.into(),
-                        true.into(),
-                        false.into(),
-                        UserRequestFilter::AttributeEquality(
-                            "first_name".to_owned(),
-                            "firstname".to_owned(),
-                        ),
-                        false.into(),
-                        UserRequestFilter::UserIdSubString(SubStringFilter {
-                            initial: Some("iNIt".to_owned()),
-                            any: vec!["1".to_owned(), "2aA".to_owned()],
-                            final_: Some("finAl".to_owned()),
-                        }),
-                        UserRequestFilter::SubString(
-                            UserColumn::DisplayName,
-                            SubStringFilter {
-                                initial: Some("iNIt".to_owned()),
-                                any: vec!["1".to_owned(), "2aA".to_owned()],
-                                final_: Some("finAl".to_owned()),
-                            },
-                        ),
-                    ],
-                )]))),
-                eq(false),
-            )
-            .times(1)
-            .return_once(|_, _| Ok(vec![]));
-        let mut ldap_handler = setup_bound_admin_handler(mock).await;
-        let request = make_user_search_request(
-            LdapFilter::And(vec![LdapFilter::Or(vec![
-                LdapFilter::Not(Box::new(LdapFilter::Equality(
-                    "uid".to_string(),
-                    "bob".to_string(),
-                ))),
-                LdapFilter::Equality(
-                    "dn".to_string(),
-                    "uid=bob_1,ou=people,dc=example,dc=com".to_string(),
-                ),
-                LdapFilter::Equality(
-                    "dn".to_string(),
-                    "uid=bob_1,ou=groups,dc=example,dc=com".to_string(),
-                ),
-                LdapFilter::Equality("objectclass".to_string(), "persOn".to_string()),
-                LdapFilter::Equality("objectclass".to_string(), "other".to_string()),
-                LdapFilter::Present("objectClass".to_string()),
-                LdapFilter::Present("uid".to_string()),
-                LdapFilter::Present("unknown".to_string()),
-                LdapFilter::Equality("givenname".to_string(), "firstname".to_string()),
-                LdapFilter::Equality("unknown_attribute".to_string(), "randomValue".to_string()),
-                LdapFilter::Substring(
-                    "uid".to_owned(),
-                    LdapSubstringFilter {
-                        initial: Some("iNIt".to_owned()),
-                        any: vec!["1".to_owned(), "2aA".to_owned()],
-                        final_: Some("finAl".to_owned()),
-                    },
-                ),
-                LdapFilter::Substring(
-                    "displayName".to_owned(),
-                    LdapSubstringFilter {
-                        initial: Some("iNIt".to_owned()),
-                        any: vec!["1".to_owned(), "2aA".to_owned()],
-                        final_: Some("finAl".to_owned()),
+        mock.expect_list_users().times(2).returning(|_, _| {
+            Ok(["bob", "jim", "john"]
+                .into_iter()
+                .map(|name| UserAndGroups {
+                    user: User {
+                        user_id: UserId::new(name),
+                        ..Default::default()
                     },
-                ),
-            ])]),
-            vec!["objectClass"],
+                    groups: None,
+                })
+                .collect())
+        });
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+        let request = LdapOp::SearchRequest(make_user_search_request(
+            LdapFilter::And(vec![]),
+            vec!["1.1"],
+        ));
+        let make_entry = |name: &str| {
+            LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                dn: format!("uid={},ou=people,dc=example,dc=com", name),
+                attributes: vec![],
+            })
+        };
+
+        let (results, controls) = ldap_handler
+            .handle_ldap_message_with_controls(
+                request.clone(),
+                vec![LdapControl::SimplePagedResults {
+                    size: 2,
+                    cookie: vec![],
+                }],
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            results,
+            vec![make_entry("bob"), make_entry("jim"), make_search_success()]
         );
+        let cookie = match controls.as_slice() {
+            [LdapControl::SimplePagedResults { cookie, .. }] => cookie.clone(),
+            _ => panic!("Unexpected controls: {:?}", controls),
+        };
+        assert!(!cookie.is_empty());
+
+        let (results, controls) = ldap_handler
+            .handle_ldap_message_with_controls(
+                request.clone(),
+                vec![LdapControl::SimplePagedResults {
+                    size: 2,
+                    cookie: cookie.clone(),
+                }],
+            )
+            .await
+            .unwrap();
+        assert_eq!(results, vec![make_entry("john"), make_search_success()]);
         assert_eq!(
-            ldap_handler.do_search_or_dse(&request).await,
-            Ok(vec![make_search_success()])
+            controls,
+            vec![LdapControl::SimplePagedResults {
+                size: 0,
+                cookie: vec![],
+            }]
+        );
+
+        // The cookie can't be reused.
+        let (results, _) = ldap_handler
+            .handle_ldap_message_with_controls(
+                request,
+                vec![LdapControl::SimplePagedResults { size: 2, cookie }],
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            results,
+            vec![make_search_error(
+                LdapResultCode::UnwillingToPerform,
+                "Invalid or expired paged results cookie".to_string()
+            )]
         );
     }
 
     #[tokio::test]
-    async fn test_search_unsupported_substring_filter() {
-        let mut ldap_handler = setup_bound_admin_handler(MockTestBackendHandler::new()).await;
-        let request = make_user_search_request(
-            LdapFilter::Substring(
-                "uuid".to_owned(),
-                LdapSubstringFilter {
-                    initial: Some("iNIt".to_owned()),
-                    any: vec!["1".to_owned(), "2aA".to_owned()],
-                    final_: Some("finAl".to_owned()),
-                },
-            ),
-            vec!["objectClass"],
-        );
-        ldap_handler.do_search_or_dse(&request).await.unwrap_err();
-        let request = make_user_search_request(
-            LdapFilter::Substring(
-                "givenname".to_owned(),
-                LdapSubstringFilter {
-                    initial: Some("iNIt".to_owned()),
-                    any: vec!["1".to_owned(), "2aA".to_owned()],
-                    final_: Some("finAl".to_owned()),
-                },
-            ),
-            vec!["objectClass"],
+    async fn test_search_paged_results_max_searches() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users().returning(|_, _| {
+            Ok(["bob", "jim"]
+                .into_iter()
+                .map(|name| UserAndGroups {
+                    user: User {
+                        user_id: UserId::new(name),
+                        ..Default::default()
+                    },
+                    groups: None,
+                })
+                .collect())
+        });
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+        let request = LdapOp::SearchRequest(make_user_search_request(
+            LdapFilter::And(vec![]),
+            vec!["1.1"],
+        ));
+        let mut cookies = Vec::new();
+        for _ in 0..=MAX_PAGED_SEARCHES {
+            let (_, controls) = ldap_handler
+                .handle_ldap_message_with_controls(
+                    request.clone(),
+                    vec![LdapControl::SimplePagedResults {
+                        size: 1,
+                        cookie: vec![],
+                    }],
+                )
+                .await
+                .unwrap();
+            match controls.as_slice() {
+                [LdapControl::SimplePagedResults { cookie, .. }] => cookies.push(cookie.clone()),
+                _ => panic!("Unexpected controls: {:?}", controls),
+            }
+        }
+        assert_eq!(ldap_handler.paged_searches.len(), MAX_PAGED_SEARCHES);
+
+        // The oldest search was dropped.
+        let (results, _) = ldap_handler
+            .handle_ldap_message_with_controls(
+                request.clone(),
+                vec![LdapControl::SimplePagedResults {
+                    size: 1,
+                    cookie: cookies[0].clone(),
+                }],
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            results,
+            vec![make_search_error(
+                LdapResultCode::UnwillingToPerform,
+                "Invalid or expired paged results cookie".to_string()
+            )]
         );
-        ldap_handler.do_search_or_dse(&request).await.unwrap_err();
+        let (results, _) = ldap_handler
+            .handle_ldap_message_with_controls(
+                request,
+                vec![LdapControl::SimplePagedResults {
+                    size: 1,
+                    cookie: cookies[MAX_PAGED_SEARCHES].clone(),
+                }],
+            )
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 2);
     }
 
     #[tokio::test]
-    async fn test_search_member_of_filter() {
+    async fn test_search_sorted() {
+        use crate::domain::handler::UserSortColumn;
+        use crate::infra::ldap_codec::SortKey;
+        fn make_users(names: &[&str]) -> Vec<UserAndGroups> {
+            names
+                .iter()
+                .map(|name| UserAndGroups {
+                    user: User {
+                        user_id: UserId::new(name),
+                        ..Default::default()
+                    },
+                    groups: None,
+                })
+                .collect()
+        }
         let mut mock = MockTestBackendHandler::new();
+        // The backend sorts the users, the pages include one more user to detect the next page.
+        let by_email = vec![UserSortKey {
+            column: UserSortColumn::Email,
+            descending: false,
+        }];
+        mock.expect_list_users_sorted()
+            .withf(move |_, _, sort, page| sort == &by_email && page.is_none())
+            .times(1)
+            .returning(|_, _, _, _| Ok(make_users(&["jim", "bob", "john"])));
+        let by_uid = vec![UserSortKey {
+            column: UserSortColumn::UserId,
+            descending: true,
+        }];
+        let next_page_by_uid = by_uid.clone();
+        mock.expect_list_users_sorted()
+            .withf(move |_, _, sort, page| sort == &by_uid && page == &Some((0, 3)))
+            .times(1)
+            .returning(|_, _, _, _| Ok(make_users(&["john", "jim", "bob"])));
+        mock.expect_list_users_sorted()
+            .withf(move |_, _, sort, page| sort == &next_page_by_uid && page == &Some((2, 3)))
+            .times(1)
+            .returning(|_, _, _, _| Ok(make_users(&["bob"])));
         mock.expect_list_users()
-            .with(
-                eq(Some(UserRequestFilter::MemberOf("group_1".to_string()))),
-                eq(false),
-            )
             .times(1)
-            .return_once(|_, _| Ok(vec![]));
-        let mut ldap_handler = setup_bound_admin_handler(mock).await;
-        let request = make_user_search_request(
-            LdapFilter::Equality(
-                "memberOf".to_string(),
-                "cn=group_1, ou=groups, dc=example,dc=com".to_string(),
-            ),
-            vec!["objectClass"],
-        );
+            .returning(|_, _| Ok(make_users(&["bob", "jim", "john"])));
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+        let request = make_user_search_request(LdapFilter::And(vec![]), vec!["1.1"]);
+        let make_entry = |name: &str| {
+            LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                dn: format!("uid={},ou=people,dc=example,dc=com", name),
+                attributes: vec![],
+            })
+        };
+        let make_sort = |attribute: &str, reverse_order, critical| SortRequest {
+            keys: vec![SortKey {
+                attribute: attribute.to_owned(),
+                ordering_rule: None,
+                reverse_order,
+            }],
+            critical,
+        };
+        let success = SortResult {
+            code: LdapResultCode::Success,
+            attribute: None,
+        };
+
         assert_eq!(
-            ldap_handler.do_search_or_dse(&request).await,
-            Ok(vec![make_search_success()])
-        );
-        let request = make_user_search_request(
-            LdapFilter::Equality("memberOf".to_string(), "group_1".to_string()),
-            vec!["objectClass"],
+            ldap_handler
+                .handle_sorted_search(request.clone(), vec![], &make_sort("mail", false, true))
+                .await,
+            (
+                vec![
+                    make_entry("jim"),
+                    make_entry("bob"),
+                    make_entry("john"),
+                    make_search_success()
+                ],
+                vec![],
+                success.clone()
+            )
         );
+
+        // The pages follow the order.
+        let sort = make_sort("uid", true, false);
+        let (results, controls, sort_result) = ldap_handler
+            .handle_sorted_search(
+                request.clone(),
+                vec![LdapControl::SimplePagedResults {
+                    size: 2,
+                    cookie: vec![],
+                }],
+                &sort,
+            )
+            .await;
         assert_eq!(
-            ldap_handler.do_search_or_dse(&request).await,
-            Err(LdapError {
-                code: LdapResultCode::InvalidDNSyntax,
-                message: "Missing DN value".to_string()
-            })
+            results,
+            vec![make_entry("john"), make_entry("jim"), make_search_success()]
         );
-        let request = make_user_search_request(
-            LdapFilter::Equality(
-                "memberOf".to_string(),
-                "cn=mygroup,dc=example,dc=com".to_string(),
-            ),
-            vec!["objectClass"],
+        assert_eq!(sort_result, success);
+        let cookie = match controls.as_slice() {
+            [LdapControl::SimplePagedResults { cookie, .. }] => cookie.clone(),
+            _ => panic!("Unexpected controls: {:?}", controls),
+        };
+        let (results, _, _) = ldap_handler
+            .handle_sorted_search(
+                request.clone(),
+                vec![LdapControl::SimplePagedResults { size: 2, cookie }],
+                &sort,
+            )
+            .await;
+        assert_eq!(results, vec![make_entry("bob"), make_search_success()]);
+
+        // Unsupported attribute: the critical sort fails, the other one is ignored.
+        let unsupported = SortResult {
+            code: LdapResultCode::UnwillingToPerform,
+            attribute: Some("uidNumber".to_owned()),
+        };
+        assert_eq!(
+            ldap_handler
+                .handle_sorted_search(
+                    request.clone(),
+                    vec![],
+                    &make_sort("uidNumber", false, true)
+                )
+                .await,
+            (
+                vec![make_search_error(
+                    LdapResultCode::UnavailableCriticalExtension,
+                    "Unsupported sort key: uidNumber".to_string()
+                )],
+                vec![],
+                unsupported.clone()
+            )
         );
         assert_eq!(
-            ldap_handler.do_search_or_dse(&request).await,
-            Err(LdapError{
-                code: LdapResultCode::InvalidDNSyntax,
-                message: r#"Unexpected DN format. Got "cn=mygroup,dc=example,dc=com", expected: "uid=id,ou=groups,dc=example,dc=com""#.to_string()
-            })
+            ldap_handler
+                .handle_sorted_search(request, vec![], &make_sort("uidNumber", false, false))
+                .await,
+            (
+                vec![
+                    make_entry("bob"),
+                    make_entry("jim"),
+                    make_entry("john"),
+                    make_search_success()
+                ],
+                vec![],
+                unsupported
+            )
         );
     }
 
     #[tokio::test]
-    async fn test_search_filters_lowercase() {
+    async fn test_search_paged_results_max_page_size() {
         let mut mock = MockTestBackendHandler::new();
-        mock.expect_list_users()
-            .with(
-                eq(Some(UserRequestFilter::And(vec![UserRequestFilter::Or(
-                    vec![UserRequestFilter::Not(Box::new(
-                        UserRequestFilter::Equality(UserColumn::DisplayName, "bob".to_string()),
-                    ))],
-                )]))),
-                eq(false),
-            )
-            .times(1)
-            .return_once(|_, _| {
-                Ok(vec![UserAndGroups {
+        mock.expect_list_users().times(1).returning(|_, _| {
+            Ok(["bob", "jim"]
+                .into_iter()
+                .map(|name| UserAndGroups {
                     user: User {
-                        user_id: UserId::new("bob_1"),
+                        user_id: UserId::new(name),
                         ..Default::default()
                     },
                     groups: None,
-                }])
-            });
-        let mut ldap_handler = setup_bound_admin_handler(mock).await;
-        let request = make_user_search_request(
-            LdapFilter::And(vec![LdapFilter::Or(vec![LdapFilter::Not(Box::new(
-                LdapFilter::Equality("displayname".to_string(), "bob".to_string()),
-            ))])]),
-            vec!["objectclass"],
-        );
-        assert_eq!(
-            ldap_handler.do_search_or_dse(&request).await,
-            Ok(vec![
-                LdapOp::SearchResultEntry(LdapSearchResultEntry {
-                    dn: "uid=bob_1,ou=people,dc=example,dc=com".to_string(),
-                    attributes: vec![LdapPartialAttribute {
-                        atype: "objectclass".to_string(),
-                        vals: vec![
-                            b"inetOrgPerson".to_vec(),
-                            b"posixAccount".to_vec(),
-                            b"mailAccount".to_vec(),
-                            b"person".to_vec()
-                        ]
-                    },]
-                }),
-                make_search_success()
-            ])
+                })
+                .collect())
+        });
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+        ldap_handler.ldap_info.search_limits.max_page_size = 1;
+        let (results, controls) = ldap_handler
+            .handle_ldap_message_with_controls(
+                LdapOp::SearchRequest(make_user_search_request(
+                    LdapFilter::And(vec![]),
+                    vec!["1.1"],
+                )),
+                vec![LdapControl::SimplePagedResults {
+                    size: 100,
+                    cookie: vec![],
+                }],
+            )
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 2);
+        assert_ne!(
+            controls,
+            vec![LdapControl::SimplePagedResults {
+                size: 0,
+                cookie: vec![],
+            }]
         );
     }
 
     #[tokio::test]
-    async fn test_search_both() {
+    async fn test_search_size_limit() {
         let mut mock = MockTestBackendHandler::new();
-        mock.expect_list_users().times(1).return_once(|_, _| {
-            Ok(vec![UserAndGroups {
-                user: User {
-                    user_id: UserId::new("bob_1"),
-                    email: "bob@bobmail.bob".to_string(),
-                    display_name: Some("Bôb Böbberson".to_string()),
-                    attributes: vec![
-                        AttributeValue {
+        mock.expect_list_users().times(3).returning(|_, _| {
+            Ok(["bob", "jim", "john"]
+                .into_iter()
+                .map(|name| UserAndGroups {
+                    user: User {
+                        user_id: UserId::new(name),
+                        ..Default::default()
+                    },
+                    groups: None,
+                })
+                .collect())
+        });
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+        let mut request = make_user_search_request(LdapFilter::And(vec![]), vec!["1.1"]);
+        request.sizelimit = 2;
+        let results = ldap_handler.do_search_or_dse(&request).await.unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(
+            results[2],
+            make_search_error(
+                LdapResultCode::SizeLimitExceeded,
+                "Search returned more than 2 entries".to_string()
+            )
+        );
+        // The server limit is lower than the client's.
+        ldap_handler.ldap_info.search_limits.size_limit = 1;
+        let results = ldap_handler.do_search_or_dse(&request).await.unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(
+            results[1],
+            make_search_error(
+                LdapResultCode::SizeLimitExceeded,
+                "Search returned more than 1 entries".to_string()
+            )
+        );
+        // The limit is not reached.
+        ldap_handler.ldap_info.search_limits.size_limit = 3;
+        request.sizelimit = 0;
+        let results = ldap_handler.do_search_or_dse(&request).await.unwrap();
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[3], make_search_success());
+    }
+
+    #[tokio::test]
+    async fn test_search_gid_number_matches_primary_group() {
+        use crate::domain::sql_backend_handler::{tests::*, SqlBackendHandler};
+        let handler = SqlBackendHandler::new(get_default_config(), get_initialized_db().await);
+        insert_user(&handler, "admin", "pass").await;
+        for user_id in ["bob", "patrick", "nogroup"] {
+            insert_user_no_password(&handler, user_id).await;
+        }
+        let admin_group = insert_group(&handler, "lldap_admin").await;
+        let first_group = insert_group(&handler, "first").await;
+        let second_group = insert_group(&handler, "second").await;
+        insert_membership(&handler, admin_group, "admin").await;
+        insert_membership(&handler, first_group, "bob").await;
+        insert_membership(&handler, second_group, "bob").await;
+        insert_membership(&handler, second_group, "patrick").await;
+        let mut ldap_handler = LdapHandler::new_for_tests(handler, "dc=example,dc=com");
+        assert_eq!(
+            ldap_handler
+                .do_bind(&LdapBindRequest {
+                    dn: "uid=admin,ou=people,dc=example,dc=com".to_string(),
+                    cred: LdapBindCred::Simple("pass".to_string()),
+                })
+                .await
+                .0,
+            LdapResultCode::Success
+        );
+        // Bob is in the second group too, but it's not his primary group.
+        for user_id in ["admin", "bob", "patrick", "nogroup"] {
+            let results = ldap_handler
+                .do_search_or_dse(&make_user_search_request(
+                    LdapFilter::Equality("uid".to_string(), user_id.to_string()),
+                    vec!["gidNumber"],
+                ))
+                .await
+                .unwrap();
+            let gid_number = match &results[0] {
+                LdapOp::SearchResultEntry(entry) => {
+                    String::from_utf8(entry.attributes[0].vals[0].clone()).unwrap()
+                }
+                op => panic!("Unexpected result: {:?}", op),
+            };
+            assert_eq!(
+                ldap_handler
+                    .do_search_or_dse(&make_user_search_request(
+                        LdapFilter::Equality("gidNumber".to_string(), gid_number.clone()),
+                        vec!["1.1"],
+                    ))
+                    .await
+                    .unwrap(),
+                vec![
+                    LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                        dn: format!("uid={},ou=people,dc=example,dc=com", user_id),
+                        attributes: vec![],
+                    }),
+                    make_search_success()
+                ],
+                "gidNumber={}",
+                gid_number
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_search_posix_filters() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::Or(vec![
+                    UserRequestFilter::UidNumber(3),
+                    UserRequestFilter::Or(vec![
+                        UserRequestFilter::And(vec![
+                            UserRequestFilter::MemberOfId(GroupId(7)),
+                            UserRequestFilter::Not(Box::new(UserRequestFilter::MemberOfIdBelow(
+                                GroupId(7),
+                            ))),
+                        ]),
+                        UserRequestFilter::And(vec![
+                            UserRequestFilter::UidNumber(7),
+                            UserRequestFilter::Not(Box::new(UserRequestFilter::MemberOfIdBelow(
+                                GroupId(i32::MAX),
+                            ))),
+                        ]),
+                    ]),
+                    false.into(),
+                    true.into(),
+                ]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| Ok(vec![]));
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+
+        let request = make_user_search_request(
+            LdapFilter::Or(vec![
+                LdapFilter::Equality("uidNumber".to_string(), "10003".to_string()),
+                LdapFilter::Equality("gidNumber".to_string(), "10007".to_string()),
+                LdapFilter::Equality("uidNumber".to_string(), "bob".to_string()),
+                LdapFilter::Present("loginShell".to_string()),
+            ]),
+            vec!["1.1"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()]),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_user_as_scope() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::And(vec![
+                    true.into(),
+                    UserRequestFilter::UserId(UserId::new("bob")),
+                ]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| Ok(vec![]));
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+
+        let request = LdapSearchRequest {
+            base: "uid=bob,ou=people,Dc=example,dc=com".to_string(),
+            scope: LdapSearchScope::Base,
+            aliases: LdapDerefAliases::Never,
+            sizelimit: 0,
+            timelimit: 0,
+            typesonly: false,
+            filter: LdapFilter::And(vec![]),
+            attrs: vec!["1.1".to_string()],
+        };
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()]),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_scope_without_entries() {
+        // No backend call is expected.
+        let mut ldap_handler = setup_bound_admin_handler(MockTestBackendHandler::new()).await;
+        for (base, scope) in [
+            ("dc=example,dc=com", LdapSearchScope::Base),
+            ("ou=people,dc=example,dc=com", LdapSearchScope::Base),
+            ("ou=groups,dc=example,dc=com", LdapSearchScope::Base),
+            ("dc=example,dc=com", LdapSearchScope::OneLevel),
+            (
+                "uid=bob,ou=people,dc=example,dc=com",
+                LdapSearchScope::OneLevel,
+            ),
+            (
+                "cn=group,ou=groups,dc=example,dc=com",
+                LdapSearchScope::OneLevel,
+            ),
+        ] {
+            let request = LdapSearchRequest {
+                scope: scope.clone(),
+                ..make_search_request(
+                    base,
+                    LdapFilter::Equality("uid".to_owned(), "bob".to_owned()),
+                    vec!["1.1"],
+                )
+            };
+            assert_eq!(
+                ldap_handler.do_search_or_dse(&request).await,
+                Ok(vec![make_search_success()]),
+                "{} {:?}",
+                base,
+                scope
+            );
+        }
+    }
+
+    #[test]
+    fn test_preserve_dn_case() {
+        let make_entry = |dn: &str| {
+            LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                dn: dn.to_string(),
+                attributes: vec![],
+            })
+        };
+        let mut results = vec![
+            make_entry("uid=bob,ou=people,dc=example,dc=com"),
+            make_entry("uid=john,ou=people,dc=example,dc=com"),
+            make_entry("cn=group,ou=groups,dc=example,dc=com"),
+            make_search_success(),
+        ];
+        preserve_dn_case(
+            &mut results,
+            "ou=People,DC=Example,dc=com",
+            Some("UID=Bob,ou=people,dc=example,dc=com"),
+        );
+        assert_eq!(
+            results,
+            vec![
+                make_entry("UID=Bob,ou=people,dc=example,dc=com"),
+                make_entry("uid=john,ou=People,DC=Example,dc=com"),
+                make_entry("cn=group,ou=groups,dc=example,dc=com"),
+                make_search_success(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_filter_matched_values() {
+        let mut results = vec![
+            LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                attributes: vec![
+                    LdapPartialAttribute {
+                        atype: "mail".to_string(),
+                        vals: vec![b"bob@example.com".to_vec(), b"bob@other.org".to_vec()],
+                    },
+                    LdapPartialAttribute {
+                        atype: "cn".to_string(),
+                        vals: vec![b"Bob".to_vec()],
+                    },
+                    LdapPartialAttribute {
+                        atype: "uid".to_string(),
+                        vals: vec![b"bob".to_vec()],
+                    },
+                ],
+            }),
+            make_search_success(),
+        ];
+        filter_matched_values(
+            &mut results,
+            &[
+                LdapFilter::Substring(
+                    "Mail".to_owned(),
+                    LdapSubstringFilter {
+                        initial: None,
+                        any: vec![],
+                        final_: Some("@EXAMPLE.com".to_owned()),
+                    },
+                ),
+                LdapFilter::Equality("cn".to_owned(), "alice".to_owned()),
+            ],
+        );
+        assert_eq!(
+            results,
+            vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "mail".to_string(),
+                            vals: vec![b"bob@example.com".to_vec()],
+                        },
+                        LdapPartialAttribute {
+                            atype: "cn".to_string(),
+                            vals: vec![],
+                        },
+                        LdapPartialAttribute {
+                            atype: "uid".to_string(),
+                            vals: vec![b"bob".to_vec()],
+                        },
+                    ],
+                }),
+                make_search_success(),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_container_entries() {
+        let mut ldap_handler = setup_bound_admin_handler(MockTestBackendHandler::new()).await;
+        let request = LdapSearchRequest {
+            scope: LdapSearchScope::Base,
+            ..make_search_request(
+                "dc=example,dc=com",
+                LdapFilter::Present("objectClass".to_owned()),
+                Vec::<String>::new(),
+            )
+        };
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "objectClass".to_string(),
+                            vals: vec![
+                                b"top".to_vec(),
+                                b"organization".to_vec(),
+                                b"dcObject".to_vec()
+                            ]
+                        },
+                        LdapPartialAttribute {
+                            atype: "o".to_string(),
+                            vals: vec![b"example".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "dc".to_string(),
+                            vals: vec![b"example".to_vec()]
+                        },
+                    ],
+                }),
+                make_search_success()
+            ]),
+        );
+        let request = LdapSearchRequest {
+            scope: LdapSearchScope::OneLevel,
+            ..make_search_request(
+                "dc=example,dc=com",
+                LdapFilter::Equality("objectClass".to_owned(), "organizationalUnit".to_owned()),
+                vec!["ou"],
+            )
+        };
+        let make_ou_entry = |ou: &str| {
+            LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                dn: format!("ou={},dc=example,dc=com", ou),
+                attributes: vec![LdapPartialAttribute {
+                    atype: "ou".to_string(),
+                    vals: vec![ou.as_bytes().to_vec()],
+                }],
+            })
+        };
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                make_ou_entry("people"),
+                make_ou_entry("groups"),
+                make_search_success()
+            ]),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_users_one_level() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(eq(Some(true.into())), eq(false))
+            .times(1)
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        ..Default::default()
+                    },
+                    groups: None,
+                }])
+            });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = LdapSearchRequest {
+            scope: LdapSearchScope::OneLevel,
+            ..make_user_search_request(LdapFilter::And(vec![]), vec!["1.1"])
+        };
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![],
+                }),
+                make_search_success()
+            ]),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bind_invalid_dn() {
+        let mock = MockTestBackendHandler::new();
+        let mut ldap_handler = LdapHandler::new_for_tests(mock, "dc=example,dc=com");
+
+        let request = LdapBindRequest {
+            dn: "cn=bob,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::NamingViolation,
+        );
+        let request = LdapBindRequest {
+            dn: "uid=bob,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::NamingViolation,
+        );
+        let request = LdapBindRequest {
+            dn: "uid=bob,ou=groups,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::NamingViolation,
+        );
+        let request = LdapBindRequest {
+            dn: "uid=bob,ou=people,dc=example,dc=fr".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::NamingViolation,
+        );
+        let request = LdapBindRequest {
+            dn: "uid=bob=test,ou=people,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::NamingViolation,
+        );
+    }
+
+    #[test]
+    fn test_is_subtree() {
+        let subtree1 = &[
+            ("ou".to_string(), "people".to_string()),
+            ("dc".to_string(), "example".to_string()),
+            ("dc".to_string(), "com".to_string()),
+        ];
+        let root = &[
+            ("dc".to_string(), "example".to_string()),
+            ("dc".to_string(), "com".to_string()),
+        ];
+        assert!(is_subtree(subtree1, root));
+        assert!(!is_subtree(&[], root));
+    }
+
+    #[test]
+    fn test_parse_distinguished_name() {
+        let parsed_dn = &[
+            ("ou".to_string(), "people".to_string()),
+            ("dc".to_string(), "example".to_string()),
+            ("dc".to_string(), "com".to_string()),
+        ];
+        assert_eq!(
+            parse_distinguished_name("ou=people,dc=example,dc=com").expect("parsing failed"),
+            parsed_dn
+        );
+        assert_eq!(
+            parse_distinguished_name(" ou  = people , dc = example , dc =  com ")
+                .expect("parsing failed"),
+            parsed_dn
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_users() {
+        use chrono::prelude::*;
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users().times(1).return_once(|_, _| {
+            Ok(vec![
+                UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob_1"),
+                        email: "bob@bobmail.bob".to_string(),
+                        display_name: Some("Bôb Böbberson".to_string()),
+                        uuid: uuid!("698e1d5f-7a40-3151-8745-b9b8a37839da"),
+                        attributes: vec![
+                            AttributeValue {
+                                name: "first_name".to_owned(),
+                                value: Serialized::from("Bôb"),
+                            },
+                            AttributeValue {
+                                name: "last_name".to_owned(),
+                                value: Serialized::from("Böbberson"),
+                            },
+                        ],
+                        ..Default::default()
+                    },
+                    groups: None,
+                },
+                UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("jim"),
+                        email: "jim@cricket.jim".to_string(),
+                        display_name: Some("Jimminy Cricket".to_string()),
+                        attributes: vec![
+                            AttributeValue {
+                                name: "avatar".to_owned(),
+                                value: Serialized::from(&JpegPhoto::for_tests()),
+                            },
+                            AttributeValue {
+                                name: "first_name".to_owned(),
+                                value: Serialized::from("Jim"),
+                            },
+                            AttributeValue {
+                                name: "last_name".to_owned(),
+                                value: Serialized::from("Cricket"),
+                            },
+                        ],
+                        uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                        creation_date: Utc
+                            .with_ymd_and_hms(2014, 7, 8, 9, 10, 11)
+                            .unwrap()
+                            .naive_utc(),
+                        ..Default::default()
+                    },
+                    groups: None,
+                },
+            ])
+        });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request(
+            LdapFilter::And(vec![]),
+            vec![
+                "objectClass",
+                "dn",
+                "uid",
+                "mail",
+                "givenName",
+                "sn",
+                "cn",
+                "createTimestamp",
+                "entryUuid",
+                "jpegPhoto",
+            ],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob_1,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "objectClass".to_string(),
+                            vals: vec![
+                                b"inetOrgPerson".to_vec(),
+                                b"posixAccount".to_vec(),
+                                b"mailAccount".to_vec(),
+                                b"person".to_vec(),
+                                b"shadowAccount".to_vec(),
+                                b"top".to_vec(),
+                            ]
+                        },
+                        LdapPartialAttribute {
+                            atype: "uid".to_string(),
+                            vals: vec![b"bob_1".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "mail".to_string(),
+                            vals: vec![b"bob@bobmail.bob".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "givenName".to_string(),
+                            vals: vec!["Bôb".to_string().into_bytes()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "sn".to_string(),
+                            vals: vec!["Böbberson".to_string().into_bytes()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "cn".to_string(),
+                            vals: vec!["Bôb Böbberson".to_string().into_bytes()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "createTimestamp".to_string(),
+                            vals: vec![b"1970-01-01T00:00:00+00:00".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "entryUuid".to_string(),
+                            vals: vec![b"698e1d5f-7a40-3151-8745-b9b8a37839da".to_vec()]
+                        },
+                    ],
+                }),
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=jim,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "objectClass".to_string(),
+                            vals: vec![
+                                b"inetOrgPerson".to_vec(),
+                                b"posixAccount".to_vec(),
+                                b"mailAccount".to_vec(),
+                                b"person".to_vec(),
+                                b"shadowAccount".to_vec(),
+                                b"top".to_vec(),
+                            ]
+                        },
+                        LdapPartialAttribute {
+                            atype: "uid".to_string(),
+                            vals: vec![b"jim".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "mail".to_string(),
+                            vals: vec![b"jim@cricket.jim".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "givenName".to_string(),
+                            vals: vec![b"Jim".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "sn".to_string(),
+                            vals: vec![b"Cricket".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "cn".to_string(),
+                            vals: vec![b"Jimminy Cricket".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "createTimestamp".to_string(),
+                            vals: vec![b"2014-07-08T09:10:11+00:00".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "entryUuid".to_string(),
+                            vals: vec![b"04ac75e0-2900-3e21-926c-2f732c26b3fc".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "jpegPhoto".to_string(),
+                            vals: vec![JpegPhoto::for_tests().into_bytes()]
+                        },
+                    ],
+                }),
+                make_search_success(),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_groups() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_groups()
+            .with(eq(Some(true.into())))
+            .times(1)
+            .return_once(|_| {
+                Ok(vec![
+                    Group {
+                        id: GroupId(1),
+                        display_name: "group_1".to_string(),
+                        creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                        users: vec![UserId::new("bob"), UserId::new("john")],
+                        attributes: vec![],
+                        dynamic_filter: None,
+                        member_groups: vec![GroupDetails {
+                            group_id: GroupId(3),
+                            display_name: "BestGroup".to_string(),
+                            creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                            uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                        }],
+                        uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    },
+                    Group {
+                        id: GroupId(3),
+                        display_name: "BestGroup".to_string(),
+                        creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                        users: vec![UserId::new("john")],
+                        attributes: vec![],
+                        dynamic_filter: None,
+                        member_groups: vec![],
+                        uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    },
+                ])
+            });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_group_search_request(
+            LdapFilter::And(vec![]),
+            vec!["objectClass", "dn", "cn", "uniqueMember", "entryUuid"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "cn=group_1,ou=groups,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "objectClass".to_string(),
+                            vals: vec![
+                                b"groupOfUniqueNames".to_vec(),
+                                b"groupOfNames".to_vec(),
+                                b"posixGroup".to_vec()
+                            ]
+                        },
+                        LdapPartialAttribute {
+                            atype: "cn".to_string(),
+                            vals: vec![b"group_1".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "uniqueMember".to_string(),
+                            vals: vec![
+                                b"uid=bob,ou=people,dc=example,dc=com".to_vec(),
+                                b"uid=john,ou=people,dc=example,dc=com".to_vec(),
+                                b"cn=BestGroup,ou=groups,dc=example,dc=com".to_vec(),
+                            ]
+                        },
+                        LdapPartialAttribute {
+                            atype: "entryUuid".to_string(),
+                            vals: vec![b"04ac75e0-2900-3e21-926c-2f732c26b3fc".to_vec()],
+                        },
+                    ],
+                }),
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "cn=BestGroup,ou=groups,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "objectClass".to_string(),
+                            vals: vec![
+                                b"groupOfUniqueNames".to_vec(),
+                                b"groupOfNames".to_vec(),
+                                b"posixGroup".to_vec()
+                            ]
+                        },
+                        LdapPartialAttribute {
+                            atype: "cn".to_string(),
+                            vals: vec![b"BestGroup".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "uniqueMember".to_string(),
+                            vals: vec![b"uid=john,ou=people,dc=example,dc=com".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "entryUuid".to_string(),
+                            vals: vec![b"04ac75e0-2900-3e21-926c-2f732c26b3fc".to_vec()],
+                        },
+                    ],
+                }),
+                make_search_success(),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_groups_member_attribute() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_groups()
+            .with(eq(Some(true.into())))
+            .times(1)
+            .return_once(|_| {
+                Ok(vec![Group {
+                    id: GroupId(1),
+                    display_name: "group_1".to_string(),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    users: vec![UserId::new("bob")],
+                    attributes: vec![],
+                    dynamic_filter: None,
+                    member_groups: vec![],
+                    uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                }])
+            });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        ldap_handler.ldap_info = LdapInfo::new(&crate::infra::configuration::Configuration {
+            ldap_group_member_attribute:
+                crate::infra::configuration::LdapGroupMemberAttribute::Member,
+            ..crate::infra::configuration::ConfigurationBuilder::for_tests()
+        });
+        let request = make_group_search_request(
+            LdapFilter::And(vec![]),
+            vec!["objectClass", "member", "uniqueMember"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "cn=group_1,ou=groups,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "objectClass".to_string(),
+                            vals: vec![b"groupOfNames".to_vec(), b"posixGroup".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "member".to_string(),
+                            vals: vec![b"uid=bob,ou=people,dc=example,dc=com".to_vec()]
+                        },
+                    ],
+                }),
+                make_search_success(),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_groups_mail() {
+        let mut mock = MockTestBackendHandler::new();
+        // Takes precedence over the default schema.
+        mock.expect_get_schema().returning(|| {
+            Ok(Schema {
+                user_attributes: AttributeList {
+                    attributes: Vec::new(),
+                },
+                group_attributes: AttributeList {
+                    attributes: vec![AttributeSchema {
+                        name: "mail".to_owned(),
+                        attribute_type: AttributeType::String,
+                        is_list: false,
+                        is_visible: true,
+                        is_editable: true,
+                        is_hardcoded: false,
+                        is_case_sensitive: false,
+                        allowed_values: None,
+                    }],
+                },
+            })
+        });
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::And(vec![
+                GroupRequestFilter::AttributeEqualityIgnoreCase(
+                    "mail".to_owned(),
+                    "Rockstars@Example.com".to_owned(),
+                ),
+                GroupRequestFilter::AttributePresent("mail".to_owned()),
+            ]))))
+            .times(1)
+            .return_once(|_| {
+                Ok(vec![Group {
+                    id: GroupId(1),
+                    display_name: "rockstars".to_string(),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    users: vec![UserId::new("bob"), UserId::new("john")],
+                    attributes: vec![AttributeValue {
+                        name: "mail".to_owned(),
+                        value: Serialized::from("rockstars@example.com"),
+                    }],
+                    uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    dynamic_filter: None,
+                    member_groups: vec![],
+                }])
+            });
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::Or(vec![
+                    UserRequestFilter::UserId(UserId::new("bob")),
+                    UserRequestFilter::UserId(UserId::new("john")),
+                ]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| {
+                Ok(vec![
+                    UserAndGroups {
+                        user: User {
+                            user_id: UserId::new("bob"),
+                            email: "bob@example.com".to_owned(),
+                            ..Default::default()
+                        },
+                        groups: None,
+                    },
+                    UserAndGroups {
+                        user: User {
+                            user_id: UserId::new("john"),
+                            email: "john@example.com".to_owned(),
+                            ..Default::default()
+                        },
+                        groups: None,
+                    },
+                ])
+            });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_group_search_request(
+            LdapFilter::And(vec![
+                LdapFilter::Equality("mail".to_string(), "Rockstars@Example.com".to_string()),
+                LdapFilter::Present("mail".to_string()),
+            ]),
+            vec!["mail", "memberMail"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "cn=rockstars,ou=groups,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "mail".to_string(),
+                            vals: vec![b"rockstars@example.com".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "memberMail".to_string(),
+                            vals: vec![b"bob@example.com".to_vec(), b"john@example.com".to_vec()]
+                        },
+                    ],
+                }),
+                make_search_success(),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_groups_filter() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::And(vec![
+                GroupRequestFilter::DisplayName("group_1".to_string()),
+                GroupRequestFilter::Member(UserId::new("bob")),
+                GroupRequestFilter::MemberGroup("rockstars".to_string()),
+                GroupRequestFilter::DisplayName("rockstars".to_string()),
+                false.into(),
+                GroupRequestFilter::Uuid(uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc")),
+                true.into(),
+                true.into(),
+                true.into(),
+                true.into(),
+                GroupRequestFilter::Not(Box::new(false.into())),
+                false.into(),
+                GroupRequestFilter::DisplayNameSubString(SubStringFilter {
+                    initial: Some("iNIt".to_owned()),
+                    any: vec!["1".to_owned(), "2aA".to_owned()],
+                    final_: Some("finAl".to_owned()),
+                }),
+            ]))))
+            .times(1)
+            .return_once(|_| {
+                Ok(vec![Group {
+                    display_name: "group_1".to_string(),
+                    id: GroupId(1),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    users: vec![],
+                    attributes: vec![],
+                    dynamic_filter: None,
+                    member_groups: vec![],
+                    uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                }])
+            });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_group_search_request(
+            LdapFilter::And(vec![
+                LdapFilter::Equality("cN".to_string(), "Group_1".to_string()),
+                LdapFilter::Equality(
+                    "uniqueMember".to_string(),
+                    "uid=bob,ou=peopLe,Dc=eXample,dc=com".to_string(),
+                ),
+                LdapFilter::Equality(
+                    "member".to_string(),
+                    "cn=rockstars,ou=groups,dc=example,dc=com".to_string(),
+                ),
+                LdapFilter::Equality(
+                    "dn".to_string(),
+                    "uid=rockstars,ou=groups,dc=example,dc=com".to_string(),
+                ),
+                LdapFilter::Equality(
+                    "dn".to_string(),
+                    "uid=rockstars,ou=people,dc=example,dc=com".to_string(),
+                ),
+                LdapFilter::Equality(
+                    "uuid".to_string(),
+                    "04ac75e0-2900-3e21-926c-2f732c26b3fc".to_string(),
+                ),
+                LdapFilter::Equality("obJEctclass".to_string(), "groupofUniqueNames".to_string()),
+                LdapFilter::Equality("objectclass".to_string(), "groupOfNames".to_string()),
+                LdapFilter::Present("objectclass".to_string()),
+                LdapFilter::Present("dn".to_string()),
+                LdapFilter::Not(Box::new(LdapFilter::Present(
+                    "random_attribUte".to_string(),
+                ))),
+                LdapFilter::Equality("unknown_attribute".to_string(), "randomValue".to_string()),
+                LdapFilter::Substring(
+                    "cn".to_owned(),
+                    LdapSubstringFilter {
+                        initial: Some("iNIt".to_owned()),
+                        any: vec!["1".to_owned(), "2aA".to_owned()],
+                        final_: Some("finAl".to_owned()),
+                    },
+                ),
+            ]),
+            vec!["1.1"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "cn=group_1,ou=groups,dc=example,dc=com".to_string(),
+                    attributes: vec![],
+                }),
+                make_search_success(),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_groups_filter_2() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::Or(vec![
+                GroupRequestFilter::Not(Box::new(GroupRequestFilter::DisplayName(
+                    "group_2".to_string(),
+                ))),
+            ]))))
+            .times(1)
+            .return_once(|_| {
+                Ok(vec![Group {
+                    display_name: "group_1".to_string(),
+                    id: GroupId(1),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
+                    users: vec![],
+                    attributes: vec![],
+                    dynamic_filter: None,
+                    member_groups: vec![],
+                    uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                }])
+            });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_group_search_request(
+            LdapFilter::Or(vec![LdapFilter::Not(Box::new(LdapFilter::Equality(
+                "displayname".to_string(),
+                "group_2".to_string(),
+            )))]),
+            vec!["cn"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "cn=group_1,ou=groups,dc=example,dc=com".to_string(),
+                    attributes: vec![LdapPartialAttribute {
+                        atype: "cn".to_string(),
+                        vals: vec![b"group_1".to_vec()]
+                    },],
+                }),
+                make_search_success(),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_groups_posix_filter() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::And(vec![
+                GroupRequestFilter::GroupId(GroupId(1)),
+                GroupRequestFilter::Member(UserId::new("bob")),
+                true.into(),
+                true.into(),
+                false.into(),
+                GroupRequestFilter::MemberSubString(SubStringFilter {
+                    initial: Some("b".to_owned()),
+                    any: vec![],
+                    final_: None,
+                }),
+            ]))))
+            .times(1)
+            .return_once(|_| Ok(vec![]));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_group_search_request(
+            LdapFilter::And(vec![
+                LdapFilter::Equality("gidNumber".to_string(), "10001".to_string()),
+                LdapFilter::Equality("memberUid".to_string(), "Bob".to_string()),
+                LdapFilter::Equality("objectClass".to_string(), "posixGroup".to_string()),
+                LdapFilter::Present("gidnumber".to_string()),
+                LdapFilter::Equality("gidNumber".to_string(), "not_a_number".to_string()),
+                LdapFilter::Substring(
+                    "memberUid".to_owned(),
+                    LdapSubstringFilter {
+                        initial: Some("b".to_owned()),
+                        any: vec![],
+                        final_: None,
+                    },
+                ),
+            ]),
+            vec!["1.1"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_group_as_scope() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::And(vec![
+                true.into(),
+                GroupRequestFilter::DisplayName("rockstars".to_string()),
+            ]))))
+            .times(1)
+            .return_once(|_| Ok(vec![]));
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+
+        let request = LdapSearchRequest {
+            base: "uid=rockstars,ou=groups,Dc=example,dc=com".to_string(),
+            scope: LdapSearchScope::Base,
+            aliases: LdapDerefAliases::Never,
+            sizelimit: 0,
+            timelimit: 0,
+            typesonly: false,
+            filter: LdapFilter::And(vec![]),
+            attrs: vec!["1.1".to_string()],
+        };
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()]),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_groups_unsupported_substring() {
+        let mut ldap_handler = setup_bound_admin_handler(MockTestBackendHandler::new()).await;
+        let request = make_group_search_request(
+            LdapFilter::Substring("member".to_owned(), LdapSubstringFilter::default()),
+            vec!["cn"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Err(LdapError {
+                code: LdapResultCode::UnwillingToPerform,
+                message:
+                    r#"Unsupported group attribute for substring filter: "member" in (member=*)"#
+                        .to_owned()
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_groups_error() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::Or(vec![
+                GroupRequestFilter::Not(Box::new(GroupRequestFilter::DisplayName(
+                    "group_2".to_string(),
+                ))),
+            ]))))
+            .times(1)
+            .return_once(|_| {
+                Err(crate::domain::error::DomainError::InternalError(
+                    "Error getting groups".to_string(),
+                ))
+            });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_group_search_request(
+            LdapFilter::Or(vec![LdapFilter::Not(Box::new(LdapFilter::Equality(
+                "displayname".to_string(),
+                "group_2".to_string(),
+            )))]),
+            vec!["cn"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Err(LdapError{
+                code: LdapResultCode::Other,
+                message: r#"Error while listing groups "ou=groups,dc=example,dc=com": Internal error: `Error getting groups`"#.to_string()
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_groups_filter_error() {
+        let mut ldap_handler = setup_bound_admin_handler(MockTestBackendHandler::new()).await;
+        let request = make_group_search_request(
+            LdapFilter::And(vec![LdapFilter::Approx(
+                "whatever".to_owned(),
+                "value".to_owned(),
+            )]),
+            vec!["cn"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Err(LdapError {
+                code: LdapResultCode::UnwillingToPerform,
+                message: "Unsupported group filter in (whatever~=value)".to_string()
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_normalized_user_id() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::Or(vec![
+                    UserRequestFilter::UserId(UserId::new("jsmith")),
+                    UserRequestFilter::UserId(UserId::new("jsmith")),
+                ]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| Ok(vec![]));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request(
+            LdapFilter::Or(vec![
+                LdapFilter::Equality("uid".to_string(), "JSmith".to_string()),
+                LdapFilter::Equality("uid".to_string(), "jsmith ".to_string()),
+            ]),
+            vec!["1.1"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_filters() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::And(vec![UserRequestFilter::Or(
+                    vec![
+                        UserRequestFilter::Not(Box::new(UserRequestFilter::UserId(UserId::new(
+                            "bob",
+                        )))),
+                        UserRequestFilter::UserId("bob_1".to_string().into()),
+                        false.into(),
+                        true.into(),
+                        false.into(),
+                        true.into(),
+                        true.into(),
+                        false.into(),
+                        UserRequestFilter::AttributeEqualityIgnoreCase(
+                            "first_name".to_owned(),
+                            "firstname".to_owned(),
+                        ),
+                        false.into(),
+                        UserRequestFilter::UserIdSubString(SubStringFilter {
+                            initial: Some("iNIt".to_owned()),
+                            any: vec!["1".to_owned(), "2aA".to_owned()],
+                            final_: Some("finAl".to_owned()),
+                        }),
+                        UserRequestFilter::SubString(
+                            UserColumn::DisplayName,
+                            SubStringFilter {
+                                initial: Some("iNIt".to_owned()),
+                                any: vec!["1".to_owned(), "2aA".to_owned()],
+                                final_: Some("finAl".to_owned()),
+                            },
+                        ),
+                        UserRequestFilter::SubString(
+                            UserColumn::Email,
+                            SubStringFilter {
+                                initial: None,
+                                any: vec![],
+                                final_: Some("@example.com".to_owned()),
+                            },
+                        ),
+                        UserRequestFilter::AttributeSubString(
+                            "last_name".to_owned(),
+                            SubStringFilter {
+                                initial: Some("john".to_owned()),
+                                any: vec![],
+                                final_: None,
+                            },
+                        ),
+                    ],
+                )]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| Ok(vec![]));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request(
+            LdapFilter::And(vec![LdapFilter::Or(vec![
+                LdapFilter::Not(Box::new(LdapFilter::Equality(
+                    "uid".to_string(),
+                    "bob".to_string(),
+                ))),
+                LdapFilter::Equality(
+                    "dn".to_string(),
+                    "uid=bob_1,ou=people,dc=example,dc=com".to_string(),
+                ),
+                LdapFilter::Equality(
+                    "dn".to_string(),
+                    "uid=bob_1,ou=groups,dc=example,dc=com".to_string(),
+                ),
+                LdapFilter::Equality("objectclass".to_string(), "persOn".to_string()),
+                LdapFilter::Equality("objectclass".to_string(), "other".to_string()),
+                LdapFilter::Present("objectClass".to_string()),
+                LdapFilter::Present("uid".to_string()),
+                LdapFilter::Present("unknown".to_string()),
+                LdapFilter::Equality("givenname".to_string(), "firstname".to_string()),
+                LdapFilter::Equality("unknown_attribute".to_string(), "randomValue".to_string()),
+                LdapFilter::Substring(
+                    "uid".to_owned(),
+                    LdapSubstringFilter {
+                        initial: Some("iNIt".to_owned()),
+                        any: vec!["1".to_owned(), "2aA".to_owned()],
+                        final_: Some("finAl".to_owned()),
+                    },
+                ),
+                LdapFilter::Substring(
+                    "displayName".to_owned(),
+                    LdapSubstringFilter {
+                        initial: Some("iNIt".to_owned()),
+                        any: vec!["1".to_owned(), "2aA".to_owned()],
+                        final_: Some("finAl".to_owned()),
+                    },
+                ),
+                LdapFilter::Substring(
+                    "mail".to_owned(),
+                    LdapSubstringFilter {
+                        initial: None,
+                        any: vec![],
+                        final_: Some("@example.com".to_owned()),
+                    },
+                ),
+                LdapFilter::Substring(
+                    "sn".to_owned(),
+                    LdapSubstringFilter {
+                        initial: Some("john".to_owned()),
+                        any: vec![],
+                        final_: None,
+                    },
+                ),
+            ])]),
+            vec!["objectClass"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_attribute_filters() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::And(vec![
+                    UserRequestFilter::AttributePresent("first_name".to_owned()),
+                    UserRequestFilter::AttributeSubString(
+                        "first_name".to_owned(),
+                        SubStringFilter {
+                            initial: Some("jo".to_owned()),
+                            any: vec![],
+                            final_: None,
+                        },
+                    ),
+                ]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| Ok(vec![]));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request(
+            LdapFilter::And(vec![
+                LdapFilter::Present("givenName".to_owned()),
+                LdapFilter::Substring(
+                    "givenName".to_owned(),
+                    LdapSubstringFilter {
+                        initial: Some("jo".to_owned()),
+                        any: vec![],
+                        final_: None,
+                    },
+                ),
+            ]),
+            vec!["objectClass"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_entry_uuid_filter() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::UuidEquality(uuid!(
+                    "698e1d5f-7a40-3151-8745-b9b8a37839da"
+                )))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| Ok(vec![]));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request(
+            LdapFilter::Equality(
+                "entryUUID".to_owned(),
+                "698E1D5F-7A40-3151-8745-B9B8A37839DA".to_owned(),
+            ),
+            vec!["objectClass"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()])
+        );
+        let request = make_user_search_request(
+            LdapFilter::Equality("entryUUID".to_owned(), "not-a-uuid".to_owned()),
+            vec!["objectClass"],
+        );
+        assert_eq!(
+            ldap_handler
+                .do_search_or_dse(&request)
+                .await
+                .unwrap_err()
+                .code,
+            LdapResultCode::UnwillingToPerform
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_extensible_filter() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::And(vec![
+                    UserRequestFilter::Equality(UserColumn::DisplayName, "John".to_owned()),
+                    UserRequestFilter::AttributeEquality(
+                        "first_name".to_owned(),
+                        "John".to_owned(),
+                    ),
+                    UserRequestFilter::UserId(UserId::new("bob")),
+                ]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| Ok(vec![]));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let make_extensible = |rule: Option<&str>, field: &str, value: &str, dn: bool| {
+            LdapFilter::Extensible(LdapMatchingRuleAssertion {
+                matching_rule: rule.map(str::to_owned),
+                type_: Some(field.to_owned()),
+                match_value: value.to_owned(),
+                dn_attributes: dn,
+            })
+        };
+        let request = make_user_search_request(
+            LdapFilter::And(vec![
+                make_extensible(Some("caseIgnoreMatch"), "cn", "John", false),
+                make_extensible(Some("2.5.13.5"), "givenName", "John", false),
+                make_extensible(None, "uid", "bob", true),
+            ]),
+            vec!["objectClass"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()])
+        );
+        let request = make_user_search_request(
+            make_extensible(Some("1.2.3.4"), "cn", "John", false),
+            vec!["objectClass"],
+        );
+        assert_eq!(
+            ldap_handler
+                .do_search_or_dse(&request)
+                .await
+                .unwrap_err()
+                .code,
+            LdapResultCode::UnwillingToPerform
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_case_sensitive_attribute_filter() {
+        let mut mock = MockTestBackendHandler::new();
+        // Takes precedence over the default schema.
+        mock.expect_get_schema().returning(|| {
+            Ok(Schema {
+                user_attributes: AttributeList {
+                    attributes: vec![
+                        AttributeSchema {
+                            name: "api_key".to_owned(),
+                            attribute_type: AttributeType::String,
+                            is_list: false,
+                            is_visible: false,
+                            is_editable: false,
+                            is_hardcoded: false,
+                            is_case_sensitive: true,
+                            allowed_values: None,
+                        },
+                        AttributeSchema {
+                            name: "nickname".to_owned(),
+                            attribute_type: AttributeType::String,
+                            is_list: false,
+                            is_visible: true,
+                            is_editable: true,
+                            is_hardcoded: false,
+                            is_case_sensitive: false,
+                            allowed_values: None,
+                        },
+                    ],
+                },
+                group_attributes: AttributeList {
+                    attributes: Vec::new(),
+                },
+            })
+        });
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::And(vec![
+                    UserRequestFilter::AttributeEquality("api_key".to_owned(), "AbC".to_owned()),
+                    UserRequestFilter::AttributeEqualityIgnoreCase(
+                        "nickname".to_owned(),
+                        "Bob".to_owned(),
+                    ),
+                ]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| Ok(vec![]));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request(
+            LdapFilter::And(vec![
+                LdapFilter::Equality("api_key".to_owned(), "AbC".to_owned()),
+                LdapFilter::Equality("nickname".to_owned(), "Bob".to_owned()),
+            ]),
+            vec!["objectClass"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_unsupported_substring_filter() {
+        let mut ldap_handler = setup_bound_admin_handler(MockTestBackendHandler::new()).await;
+        let request = make_user_search_request(
+            LdapFilter::Substring(
+                "uuid".to_owned(),
+                LdapSubstringFilter {
+                    initial: Some("iNIt".to_owned()),
+                    any: vec!["1".to_owned(), "2aA".to_owned()],
+                    final_: Some("finAl".to_owned()),
+                },
+            ),
+            vec!["objectClass"],
+        );
+        ldap_handler.do_search_or_dse(&request).await.unwrap_err();
+        let request = make_user_search_request(
+            LdapFilter::Substring(
+                "jpegPhoto".to_owned(),
+                LdapSubstringFilter {
+                    initial: Some("iNIt".to_owned()),
+                    any: vec!["1".to_owned(), "2aA".to_owned()],
+                    final_: Some("finAl".to_owned()),
+                },
+            ),
+            vec!["objectClass"],
+        );
+        ldap_handler.do_search_or_dse(&request).await.unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn test_search_member_of_filter() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::MemberOf("group_1".to_string()))),
+                eq(false),
+            )
+            .times(2)
+            .returning(|_, _| Ok(vec![]));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request(
+            LdapFilter::Equality(
+                "memberOf".to_string(),
+                "cn=group_1, ou=groups, dc=example,dc=com".to_string(),
+            ),
+            vec!["objectClass"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()])
+        );
+        // Bare group name.
+        let request = make_user_search_request(
+            LdapFilter::Equality("memberOf".to_string(), "group_1".to_string()),
+            vec!["objectClass"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()])
+        );
+        let request = make_user_search_request(
+            LdapFilter::Equality(
+                "memberOf".to_string(),
+                "cn=mygroup,dc=example,dc=com".to_string(),
+            ),
+            vec!["objectClass"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Err(LdapError{
+                code: LdapResultCode::InvalidDNSyntax,
+                message: r#"Unexpected DN format. Got "cn=mygroup,dc=example,dc=com", expected: "uid=id,ou=groups,dc=example,dc=com" in (memberOf=cn=mygroup,dc=example,dc=com)"#.to_string()
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_filters_lowercase() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::And(vec![UserRequestFilter::Or(
+                    vec![UserRequestFilter::Not(Box::new(
+                        UserRequestFilter::Equality(UserColumn::DisplayName, "bob".to_string()),
+                    ))],
+                )]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob_1"),
+                        ..Default::default()
+                    },
+                    groups: None,
+                }])
+            });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request(
+            LdapFilter::And(vec![LdapFilter::Or(vec![LdapFilter::Not(Box::new(
+                LdapFilter::Equality("displayname".to_string(), "bob".to_string()),
+            ))])]),
+            vec!["objectclass"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob_1,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![LdapPartialAttribute {
+                        atype: "objectclass".to_string(),
+                        vals: vec![
+                            b"inetOrgPerson".to_vec(),
+                            b"posixAccount".to_vec(),
+                            b"mailAccount".to_vec(),
+                            b"person".to_vec(),
+                            b"shadowAccount".to_vec(),
+                            b"top".to_vec(),
+                        ]
+                    },]
+                }),
+                make_search_success()
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_custom_object_classes() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::And(vec![
+                    true.into(),
+                    false.into(),
+                    true.into(),
+                ]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob_1"),
+                        ..Default::default()
+                    },
+                    groups: None,
+                }])
+            });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        ldap_handler.ldap_info = LdapInfo::new(&crate::infra::configuration::Configuration {
+            ldap_user_object_classes: vec!["organizationalPerson".to_owned()],
+            ..crate::infra::configuration::ConfigurationBuilder::for_tests()
+        });
+        let request = make_user_search_request(
+            LdapFilter::And(vec![
+                LdapFilter::Equality(
+                    "objectClass".to_string(),
+                    "organizationalperson".to_string(),
+                ),
+                LdapFilter::Equality("objectClass".to_string(), "posixAccount".to_string()),
+                // Always matches, even if not in the list.
+                LdapFilter::Equality("objectClass".to_string(), "Top".to_string()),
+            ]),
+            vec!["objectclass"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob_1,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![LdapPartialAttribute {
+                        atype: "objectclass".to_string(),
+                        vals: vec![b"organizationalPerson".to_vec()]
+                    },]
+                }),
+                make_search_success()
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_restricted_attributes() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users().times(1).return_once(|_, _| {
+            Ok(vec![
+                UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob_1"),
+                        email: "bob@bobmail.bob".to_string(),
+                        ..Default::default()
+                    },
+                    groups: None,
+                },
+                UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("test"),
+                        email: "test@example.com".to_string(),
+                        ..Default::default()
+                    },
+                    groups: None,
+                },
+            ])
+        });
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+        ldap_handler.ldap_info = LdapInfo::new(&crate::infra::configuration::Configuration {
+            ldap_attribute_access: vec![crate::infra::configuration::LdapAttributeAccess {
+                attribute: "mail".to_owned(),
+                readers: vec!["self".to_owned(), "hr".to_owned()],
+            }],
+            ..crate::infra::configuration::ConfigurationBuilder::for_tests()
+        });
+        // The bound user only sees their own email, under all its names.
+        let request = make_user_search_request(LdapFilter::And(vec![]), vec!["uid", "email"]);
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob_1,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![LdapPartialAttribute {
+                        atype: "uid".to_string(),
+                        vals: vec![b"bob_1".to_vec()]
+                    }]
+                }),
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=test,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "uid".to_string(),
+                            vals: vec![b"test".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "email".to_string(),
+                            vals: vec![b"test@example.com".to_vec()]
+                        },
+                    ]
+                }),
+                make_search_success()
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_disabled_users() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(eq(Some(UserRequestFilter::IsEnabled(false))), eq(false))
+            .times(1)
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        is_enabled: false,
+                        ..Default::default()
+                    },
+                    groups: None,
+                }])
+            });
+        // With ldap_hide_disabled_users, the same filter can't match anything.
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::And(vec![
+                    UserRequestFilter::IsEnabled(false),
+                    UserRequestFilter::IsEnabled(true),
+                ]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| Ok(vec![]));
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+        let request = make_user_search_request(
+            LdapFilter::Equality("nsAccountLock".to_string(), "TRUE".to_string()),
+            vec!["uid", "nsAccountLock"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "uid".to_string(),
+                            vals: vec![b"bob".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "nsAccountLock".to_string(),
+                            vals: vec![b"TRUE".to_vec()]
+                        },
+                    ]
+                }),
+                make_search_success(),
+            ]),
+        );
+        ldap_handler.ldap_info = LdapInfo::new(&crate::infra::configuration::Configuration {
+            ldap_hide_disabled_users: true,
+            ..crate::infra::configuration::ConfigurationBuilder::for_tests()
+        });
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()]),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_expiration_date() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users().times(1).return_once(|_, _| {
+            Ok(vec![
+                UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        expiration_date: Some(
+                            chrono::Utc
+                                .with_ymd_and_hms(2030, 1, 1, 0, 0, 0)
+                                .unwrap()
+                                .naive_utc(),
+                        ),
+                        ..Default::default()
+                    },
+                    groups: None,
+                },
+                UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("john"),
+                        ..Default::default()
+                    },
+                    groups: None,
+                },
+            ])
+        });
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+        let request = make_user_search_request(
+            LdapFilter::And(vec![]),
+            vec!["uid", "shadowExpire", "accountExpires"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "uid".to_string(),
+                            vals: vec![b"bob".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "shadowExpire".to_string(),
+                            vals: vec![b"21915".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "accountExpires".to_string(),
+                            vals: vec![b"135379296000000000".to_vec()]
+                        },
+                    ]
+                }),
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=john,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![LdapPartialAttribute {
+                        atype: "uid".to_string(),
+                        vals: vec![b"john".to_vec()]
+                    }]
+                }),
+                make_search_success(),
+            ]),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_user_password() {
+        // The bound user is "test".
+        let make_ldap_info = |reader: &str| {
+            LdapInfo::new(&crate::infra::configuration::Configuration {
+                ldap_password_replication:
+                    crate::infra::configuration::LdapPasswordReplicationOptions {
+                        format: Some(crate::infra::configuration::PasswordReplicationFormat::Ssha),
+                        readers: vec![reader.to_owned()],
+                        ..Default::default()
+                    },
+                ..crate::infra::configuration::ConfigurationBuilder::for_tests()
+            })
+        };
+        let make_mock = || {
+            let mut mock = MockTestBackendHandler::new();
+            mock.expect_list_users().times(1).return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob_1"),
+                        replicated_password_hash: Some("{SSHA}abcd".to_owned()),
+                        ..Default::default()
+                    },
+                    groups: None,
+                }])
+            });
+            mock
+        };
+        let request =
+            make_user_search_request(LdapFilter::And(vec![]), vec!["uid", "userPassword"]);
+        let expected_uid = LdapPartialAttribute {
+            atype: "uid".to_string(),
+            vals: vec![b"bob_1".to_vec()],
+        };
+        // The configured reader.
+        let mut ldap_handler = setup_bound_readonly_handler(make_mock()).await;
+        ldap_handler.ldap_info = make_ldap_info("test");
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob_1,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        expected_uid.clone(),
+                        LdapPartialAttribute {
+                            atype: "userPassword".to_string(),
+                            vals: vec![b"{SSHA}abcd".to_vec()]
+                        },
+                    ]
+                }),
+                make_search_success()
+            ])
+        );
+        // Not even the admins otherwise.
+        let mut ldap_handler = setup_bound_admin_handler(make_mock()).await;
+        ldap_handler.ldap_info = make_ldap_info("replication");
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob_1,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![expected_uid]
+                }),
+                make_search_success()
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_anonymous_bind_refused_by_default() {
+        let mut ldap_handler =
+            LdapHandler::new_for_tests(MockTestBackendHandler::new(), "dc=example,dc=com");
+        let request = LdapBindRequest {
+            dn: "".to_string(),
+            cred: LdapBindCred::Simple("".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::InsufficentAccessRights
+        );
+        let request = make_user_search_request(
+            LdapFilter::Equality("uid".to_string(), "bob".to_string()),
+            vec!["uid"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Err(LdapError {
+                code: LdapResultCode::InsufficentAccessRights,
+                message: "No user currently bound".to_string()
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_anonymous_search() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users().times(1).return_once(|_, _| {
+            Ok(vec![UserAndGroups {
+                user: User {
+                    user_id: UserId::new("bob_1"),
+                    email: "bob@bobmail.bob".to_string(),
+                    display_name: Some("Bob".to_string()),
+                    ..Default::default()
+                },
+                groups: None,
+            }])
+        });
+        setup_default_schema(&mut mock);
+        let mut ldap_handler = LdapHandler::new(
+            AccessControlledBackendHandler::new(mock),
+            LdapInfo::new(&crate::infra::configuration::Configuration {
+                ldap_anonymous_bind: true,
+                ldap_anonymous_attributes: vec!["uid".to_owned(), "Mail".to_owned()],
+                ..crate::infra::configuration::ConfigurationBuilder::for_tests()
+            }),
+        );
+        let request = LdapBindRequest {
+            dn: "".to_string(),
+            cred: LdapBindCred::Simple("".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::Success
+        );
+        // Only the allowed attributes are returned.
+        let request = make_user_search_request(
+            LdapFilter::Equality("mail".to_string(), "bob@bobmail.bob".to_string()),
+            vec!["uid", "mail", "cn"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob_1,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "uid".to_string(),
+                            vals: vec![b"bob_1".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "mail".to_string(),
+                            vals: vec![b"bob@bobmail.bob".to_vec()]
+                        },
+                    ]
+                }),
+                make_search_success()
+            ])
+        );
+        // The filters can't use the other attributes.
+        let request = make_user_search_request(
+            LdapFilter::Substring(
+                "cn".to_string(),
+                LdapSubstringFilter {
+                    initial: Some("B".to_string()),
+                    ..Default::default()
+                },
+            ),
+            vec!["uid"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Err(LdapError {
+                code: LdapResultCode::InsufficentAccessRights,
+                message: "Anonymous searches can only filter on: uid, mail".to_string()
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_both() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users().times(1).return_once(|_, _| {
+            Ok(vec![UserAndGroups {
+                user: User {
+                    user_id: UserId::new("bob_1"),
+                    email: "bob@bobmail.bob".to_string(),
+                    display_name: Some("Bôb Böbberson".to_string()),
+                    attributes: vec![
+                        AttributeValue {
                             name: "first_name".to_owned(),
                             value: Serialized::from("Bôb"),
                         },
@@ -1849,6 +5892,9 @@ mod tests {
                     display_name: "group_1".to_string(),
                     creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
                     users: vec![UserId::new("bob"), UserId::new("john")],
+                    attributes: vec![],
+                    dynamic_filter: None,
+                    member_groups: vec![],
                     uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
                 }])
             });
@@ -1870,7 +5916,9 @@ mod tests {
                                 b"inetOrgPerson".to_vec(),
                                 b"posixAccount".to_vec(),
                                 b"mailAccount".to_vec(),
-                                b"person".to_vec()
+                                b"person".to_vec(),
+                                b"shadowAccount".to_vec(),
+                                b"top".to_vec(),
                             ]
                         },
                         LdapPartialAttribute {
@@ -1884,7 +5932,11 @@ mod tests {
                     attributes: vec![
                         LdapPartialAttribute {
                             atype: "objectClass".to_string(),
-                            vals: vec![b"groupOfUniqueNames".to_vec(),]
+                            vals: vec![
+                                b"groupOfUniqueNames".to_vec(),
+                                b"groupOfNames".to_vec(),
+                                b"posixGroup".to_vec()
+                            ]
                         },
                         LdapPartialAttribute {
                             atype: "cn".to_string(),
@@ -1897,6 +5949,72 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_search_cn_fallback() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users().times(2).returning(|_, _| {
+            Ok(vec![
+                UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        attributes: vec![
+                            AttributeValue {
+                                name: "first_name".to_owned(),
+                                value: Serialized::from("Bob"),
+                            },
+                            AttributeValue {
+                                name: "last_name".to_owned(),
+                                value: Serialized::from("Bobberson"),
+                            },
+                        ],
+                        ..Default::default()
+                    },
+                    groups: None,
+                },
+                UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("john"),
+                        ..Default::default()
+                    },
+                    groups: None,
+                },
+            ])
+        });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request(LdapFilter::And(vec![]), vec!["cn", "gecos"]);
+        let make_entry = |name: &str, cn: Option<&str>| {
+            LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                dn: format!("uid={},ou=people,dc=example,dc=com", name),
+                attributes: cn
+                    .into_iter()
+                    .flat_map(|cn| {
+                        ["cn", "gecos"].map(|atype| LdapPartialAttribute {
+                            atype: atype.to_string(),
+                            vals: vec![cn.as_bytes().to_vec()],
+                        })
+                    })
+                    .collect(),
+            })
+        };
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                make_entry("bob", Some("Bob Bobberson")),
+                make_entry("john", Some("john")),
+                make_search_success(),
+            ])
+        );
+        ldap_handler.ldap_info.cn_sources = vec![LdapCnSource::DisplayName];
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                make_entry("bob", None),
+                make_entry("john", None),
+                make_search_success(),
+            ])
+        );
+    }
+
     #[tokio::test]
     async fn test_search_wildcards() {
         let mut mock = MockTestBackendHandler::new();
@@ -1931,17 +6049,19 @@ mod tests {
                     display_name: "group_1".to_string(),
                     creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
                     users: vec![UserId::new("bob"), UserId::new("john")],
+                    attributes: vec![],
+                    dynamic_filter: None,
+                    member_groups: vec![],
                     uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
                 }])
             });
         let mut ldap_handler = setup_bound_admin_handler(mock).await;
 
         // Test simple wildcard
-        let request =
-            make_search_request("dc=example,dc=com", LdapFilter::And(vec![]), vec!["*", "+"]);
+        let request = make_search_request("dc=example,dc=com", LdapFilter::And(vec![]), vec!["*"]);
 
         // all: "objectclass", "dn", "uid", "mail", "givenname", "sn", "cn"
-        // Operational: "createtimestamp"
+        // Operational (only with "+"): "createtimestamp", "entryuuid", ...
 
         let expected_result = Ok(vec![
             LdapOp::SearchResultEntry(LdapSearchResultEntry {
@@ -1954,6 +6074,8 @@ mod tests {
                             b"posixAccount".to_vec(),
                             b"mailAccount".to_vec(),
                             b"person".to_vec(),
+                            b"shadowAccount".to_vec(),
+                            b"top".to_vec(),
                         ],
                     },
                     LdapPartialAttribute {
@@ -1977,16 +6099,24 @@ mod tests {
                         vals: vec![JpegPhoto::for_tests().into_bytes()],
                     },
                     LdapPartialAttribute {
-                        atype: "createtimestamp".to_string(),
-                        vals: vec![chrono::Utc
-                            .timestamp_opt(0, 0)
-                            .unwrap()
-                            .to_rfc3339()
-                            .into_bytes()],
+                        atype: "uidnumber".to_string(),
+                        vals: vec![b"10000".to_vec()],
+                    },
+                    LdapPartialAttribute {
+                        atype: "gidnumber".to_string(),
+                        vals: vec![b"10000".to_vec()],
+                    },
+                    LdapPartialAttribute {
+                        atype: "homedirectory".to_string(),
+                        vals: vec![b"/home/bob_1".to_vec()],
                     },
                     LdapPartialAttribute {
-                        atype: "entryuuid".to_string(),
-                        vals: vec![b"b4ac75e0-2900-3e21-926c-2f732c26b3fc".to_vec()],
+                        atype: "loginshell".to_string(),
+                        vals: vec![b"/bin/bash".to_vec()],
+                    },
+                    LdapPartialAttribute {
+                        atype: "gecos".to_string(),
+                        vals: vec!["Bôb Böbberson".to_string().into_bytes()],
                     },
                 ],
             }),
@@ -1996,7 +6126,11 @@ mod tests {
                 attributes: vec![
                     LdapPartialAttribute {
                         atype: "objectclass".to_string(),
-                        vals: vec![b"groupOfUniqueNames".to_vec()],
+                        vals: vec![
+                            b"groupOfUniqueNames".to_vec(),
+                            b"groupOfNames".to_vec(),
+                            b"posixGroup".to_vec(),
+                        ],
                     },
                     // UID
                     LdapPartialAttribute {
@@ -2023,8 +6157,12 @@ mod tests {
                         ],
                     },
                     LdapPartialAttribute {
-                        atype: "entryuuid".to_string(),
-                        vals: vec![b"04ac75e0-2900-3e21-926c-2f732c26b3fc".to_vec()],
+                        atype: "gidnumber".to_string(),
+                        vals: vec![b"10001".to_vec()],
+                    },
+                    LdapPartialAttribute {
+                        atype: "memberuid".to_string(),
+                        vals: vec![b"bob".to_vec(), b"john".to_vec()],
                     },
                 ],
             }),
@@ -2033,83 +6171,719 @@ mod tests {
 
         assert_eq!(
             ldap_handler.do_search_or_dse(&request).await,
-            expected_result
+            expected_result
+        );
+
+        let request2 = make_search_request(
+            "dc=example,dc=com",
+            LdapFilter::And(vec![]),
+            vec!["objectclass", "obJEctclaSS", "dn", "*", "*"],
+        );
+
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request2).await,
+            expected_result
+        );
+
+        let request3 = make_search_request(
+            "dc=example,dc=com",
+            LdapFilter::And(vec![]),
+            vec!["*", "+", "+"],
+        );
+        let mut expected_operational_result = expected_result.clone().unwrap();
+        if let LdapOp::SearchResultEntry(entry) = &mut expected_operational_result[0] {
+            let create_timestamp = chrono::Utc
+                .timestamp_opt(0, 0)
+                .unwrap()
+                .to_rfc3339()
+                .into_bytes();
+            entry.attributes.extend([
+                LdapPartialAttribute {
+                    atype: "createtimestamp".to_string(),
+                    vals: vec![create_timestamp.clone()],
+                },
+                LdapPartialAttribute {
+                    atype: "modifytimestamp".to_string(),
+                    vals: vec![create_timestamp],
+                },
+                LdapPartialAttribute {
+                    atype: "entryuuid".to_string(),
+                    vals: vec![b"b4ac75e0-2900-3e21-926c-2f732c26b3fc".to_vec()],
+                },
+                LdapPartialAttribute {
+                    atype: "entrydn".to_string(),
+                    vals: vec![b"uid=bob_1,ou=people,dc=example,dc=com".to_vec()],
+                },
+                LdapPartialAttribute {
+                    atype: "creatorsname".to_string(),
+                    vals: vec![b"uid=admin,ou=people,dc=example,dc=com".to_vec()],
+                },
+                LdapPartialAttribute {
+                    atype: "hassubordinates".to_string(),
+                    vals: vec![b"FALSE".to_vec()],
+                },
+            ]);
+        }
+        if let LdapOp::SearchResultEntry(entry) = &mut expected_operational_result[1] {
+            let create_timestamp = chrono::Utc
+                .timestamp_opt(42, 42)
+                .unwrap()
+                .to_rfc3339()
+                .into_bytes();
+            entry.attributes.extend([
+                LdapPartialAttribute {
+                    atype: "createtimestamp".to_string(),
+                    vals: vec![create_timestamp.clone()],
+                },
+                LdapPartialAttribute {
+                    atype: "modifytimestamp".to_string(),
+                    vals: vec![create_timestamp],
+                },
+                LdapPartialAttribute {
+                    atype: "entryuuid".to_string(),
+                    vals: vec![b"04ac75e0-2900-3e21-926c-2f732c26b3fc".to_vec()],
+                },
+            ]);
+        }
+
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request3).await,
+            Ok(expected_operational_result)
+        );
+
+        let request4 =
+            make_search_request("dc=example,dc=com", LdapFilter::And(vec![]), vec![""; 0]);
+
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request4).await,
+            expected_result
+        );
+
+        let request5 = make_search_request(
+            "dc=example,dc=com",
+            LdapFilter::And(vec![]),
+            vec!["objectclass", "dn", "uid", "*"],
+        );
+
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request5).await,
+            expected_result
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_wrong_base() {
+        let mut ldap_handler = setup_bound_admin_handler(MockTestBackendHandler::new()).await;
+        let request = make_search_request(
+            "ou=users,dc=example,dc=com",
+            LdapFilter::And(vec![]),
+            vec!["objectClass"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_unsupported_filters() {
+        let mut ldap_handler = setup_bound_admin_handler(MockTestBackendHandler::new()).await;
+        let request = make_user_search_request(
+            LdapFilter::Approx("createTimestamp".to_owned(), "value".to_owned()),
+            vec!["objectClass"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Err(LdapError {
+                code: LdapResultCode::UnwillingToPerform,
+                message: r#"Unsupported user attribute for approximate filter: "createtimestamp" in (createTimestamp~=value)"#
+                    .to_string()
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_last_login() {
+        let date = chrono::Utc
+            .with_ymd_and_hms(2024, 1, 1, 0, 0, 0)
+            .unwrap()
+            .naive_utc();
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::Or(vec![
+                    UserRequestFilter::LastLoginBefore(date),
+                    UserRequestFilter::Not(Box::new(UserRequestFilter::LastLoginAfter(
+                        chrono::NaiveDateTime::default(),
+                    ))),
+                ]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(move |_, _| {
+                Ok(vec![
+                    UserAndGroups {
+                        user: User {
+                            user_id: UserId::new("bob"),
+                            last_login: Some(date - chrono::Duration::days(1)),
+                            ..Default::default()
+                        },
+                        groups: None,
+                    },
+                    UserAndGroups {
+                        user: User {
+                            user_id: UserId::new("john"),
+                            ..Default::default()
+                        },
+                        groups: None,
+                    },
+                ])
+            });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        // The dormant accounts, and the ones never used.
+        let request = make_user_search_request(
+            LdapFilter::Or(vec![
+                LdapFilter::LessOrEqual("authTimestamp".to_owned(), "20240101000000Z".to_owned()),
+                LdapFilter::Not(Box::new(LdapFilter::Present("authTimestamp".to_owned()))),
+            ]),
+            vec!["uid", "authTimestamp"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "uid".to_string(),
+                            vals: vec![b"bob".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "authTimestamp".to_string(),
+                            vals: vec![b"2023-12-31T00:00:00+00:00".to_vec()]
+                        },
+                    ]
+                }),
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=john,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![LdapPartialAttribute {
+                        atype: "uid".to_string(),
+                        vals: vec![b"john".to_vec()]
+                    }]
+                }),
+                make_search_success(),
+            ]),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_authentication_failures() {
+        let date = chrono::Utc
+            .with_ymd_and_hms(2024, 1, 1, 0, 0, 0)
+            .unwrap()
+            .naive_utc();
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users().times(1).return_once(move |_, _| {
+            Ok(vec![
+                UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        authentication_failures: Some(AuthenticationFailures {
+                            failure_count: 5,
+                            last_failure_date: date,
+                            locked_until: Some(date + chrono::Duration::minutes(15)),
+                        }),
+                        ..Default::default()
+                    },
+                    groups: None,
+                },
+                UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("john"),
+                        authentication_failures: Some(AuthenticationFailures {
+                            failure_count: 1,
+                            last_failure_date: date,
+                            locked_until: None,
+                        }),
+                        ..Default::default()
+                    },
+                    groups: None,
+                },
+            ])
+        });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request(
+            LdapFilter::And(vec![]),
+            vec!["uid", "pwdFailureTime", "pwdAccountLockedTime"],
+        );
+        let failure_time = LdapPartialAttribute {
+            atype: "pwdFailureTime".to_string(),
+            vals: vec![b"2024-01-01T00:00:00+00:00".to_vec()],
+        };
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "uid".to_string(),
+                            vals: vec![b"bob".to_vec()]
+                        },
+                        failure_time.clone(),
+                        LdapPartialAttribute {
+                            atype: "pwdAccountLockedTime".to_string(),
+                            vals: vec![b"2024-01-01T00:00:00+00:00".to_vec()]
+                        },
+                    ]
+                }),
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=john,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "uid".to_string(),
+                            vals: vec![b"john".to_vec()]
+                        },
+                        failure_time,
+                    ]
+                }),
+                make_search_success(),
+            ]),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_creation_date_filters() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::And(vec![
+                    UserRequestFilter::CreationDateAfter(
+                        chrono::Utc
+                            .with_ymd_and_hms(2024, 1, 1, 0, 0, 0)
+                            .unwrap()
+                            .naive_utc(),
+                    ),
+                    UserRequestFilter::CreationDateBefore(
+                        chrono::Utc
+                            .with_ymd_and_hms(2024, 2, 1, 11, 30, 0)
+                            .unwrap()
+                            .naive_utc(),
+                    ),
+                ]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| Ok(vec![]));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request(
+            LdapFilter::And(vec![
+                LdapFilter::GreaterOrEqual(
+                    "createTimestamp".to_owned(),
+                    "20240101000000Z".to_owned(),
+                ),
+                LdapFilter::LessOrEqual(
+                    "createTimestamp".to_owned(),
+                    "20240201123000+0100".to_owned(),
+                ),
+            ]),
+            vec!["objectClass"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()])
+        );
+        let request = make_user_search_request(
+            LdapFilter::GreaterOrEqual("createTimestamp".to_owned(), "yesterday".to_owned()),
+            vec!["objectClass"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Err(LdapError {
+                code: LdapResultCode::UnwillingToPerform,
+                message: r#"Invalid date for createtimestamp: "yesterday" in (createTimestamp>=yesterday)"#.to_string()
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_ignore_unsupported_filters() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::Or(vec![
+                    false.into(),
+                    UserRequestFilter::UserId(UserId::new("bob")),
+                ]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| Ok(vec![]));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        ldap_handler.ldap_info = LdapInfo::new(&crate::infra::configuration::Configuration {
+            ldap_ignore_unsupported_filters: true,
+            ..crate::infra::configuration::ConfigurationBuilder::for_tests()
+        });
+        let request = make_user_search_request(
+            LdapFilter::Or(vec![
+                LdapFilter::GreaterOrEqual("createTimestamp".to_owned(), "yesterday".to_owned()),
+                LdapFilter::Equality("uid".to_owned(), "bob".to_owned()),
+            ]),
+            vec!["objectClass"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()])
         );
+    }
 
-        let request2 = make_search_request(
-            "dc=example,dc=com",
-            LdapFilter::And(vec![]),
-            vec!["objectclass", "obJEctclaSS", "dn", "*", "*"],
+    #[tokio::test]
+    async fn test_search_modification_date_filter() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::ModifiedDateAfter(
+                    chrono::Utc
+                        .with_ymd_and_hms(2024, 1, 1, 0, 0, 0)
+                        .unwrap()
+                        .naive_utc(),
+                ))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        modified_date: chrono::Utc
+                            .with_ymd_and_hms(2024, 1, 2, 0, 0, 0)
+                            .unwrap()
+                            .naive_utc(),
+                        ..Default::default()
+                    },
+                    groups: None,
+                }])
+            });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request(
+            LdapFilter::GreaterOrEqual("modifyTimestamp".to_owned(), "20240101000000Z".to_owned()),
+            vec!["createTimestamp", "modifyTimestamp"],
         );
-
         assert_eq!(
-            ldap_handler.do_search_or_dse(&request2).await,
-            expected_result
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "createTimestamp".to_string(),
+                            vals: vec![b"1970-01-01T00:00:00+00:00".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "modifyTimestamp".to_string(),
+                            vals: vec![b"2024-01-02T00:00:00+00:00".to_vec()]
+                        },
+                    ],
+                }),
+                make_search_success(),
+            ])
         );
+    }
 
-        let request3 = make_search_request(
-            "dc=example,dc=com",
-            LdapFilter::And(vec![]),
-            vec!["*", "+", "+"],
+    #[tokio::test]
+    async fn test_search_approx_filters() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::Or(vec![
+                    UserRequestFilter::ApproxMatch(UserColumn::DisplayName, "Smith".to_owned()),
+                    UserRequestFilter::ApproxMatch(UserColumn::Email, "smith@".to_owned()),
+                    UserRequestFilter::AttributeEquality(
+                        "last_name".to_owned(),
+                        "Smith".to_owned(),
+                    ),
+                    false.into(),
+                ]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| Ok(vec![]));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request(
+            LdapFilter::Or(vec![
+                LdapFilter::Approx("cn".to_owned(), "Smith".to_owned()),
+                LdapFilter::Approx("mail".to_owned(), "smith@".to_owned()),
+                LdapFilter::Approx("sn".to_owned(), "Smith".to_owned()),
+                LdapFilter::Approx("unknown".to_owned(), "Smith".to_owned()),
+            ]),
+            vec!["objectClass"],
         );
-
         assert_eq!(
-            ldap_handler.do_search_or_dse(&request3).await,
-            expected_result
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()])
         );
+    }
 
-        let request4 =
-            make_search_request("dc=example,dc=com", LdapFilter::And(vec![]), vec![""; 0]);
-
-        assert_eq!(
-            ldap_handler.do_search_or_dse(&request4).await,
-            expected_result
+    #[tokio::test]
+    async fn test_password_change() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .returning(|_| Ok(HashSet::new()));
+        use lldap_auth::*;
+        let mut rng = rand::rngs::OsRng;
+        let registration_start_request =
+            opaque::client::registration::start_registration("password".as_bytes(), &mut rng)
+                .unwrap();
+        let request = registration::ClientRegistrationStartRequest {
+            username: "bob".to_string(),
+            registration_start_request: registration_start_request.message,
+        };
+        let start_response = opaque::server::registration::start_registration(
+            &opaque::server::ServerSetup::new(&mut rng),
+            request.registration_start_request,
+            &request.username,
+        )
+        .unwrap();
+        mock.expect_registration_start().times(1).return_once(|_| {
+            Ok(registration::ServerRegistrationStartResponse {
+                server_data: "".to_string(),
+                registration_response: start_response.message,
+                password_policy: None,
+            })
+        });
+        mock.expect_is_password_recently_used()
+            .returning(|_, _| Ok(false));
+        mock.expect_registration_finish()
+            .times(1)
+            .return_once(|_| Ok(()));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = LdapOp::ExtendedRequest(
+            LdapPasswordModifyRequest {
+                user_identity: Some("uid=bob,ou=people,dc=example,dc=com".to_string()),
+                old_password: None,
+                new_password: Some("password".to_string()),
+            }
+            .into(),
         );
-
-        let request5 = make_search_request(
-            "dc=example,dc=com",
-            LdapFilter::And(vec![]),
-            vec!["objectclass", "dn", "uid", "*"],
+        assert_eq!(
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_extended_response(
+                LdapResultCode::Success,
+                "".to_string(),
+            )])
         );
+    }
 
+    #[tokio::test]
+    async fn test_password_change_modify_request() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .returning(|_| Ok(HashSet::new()));
+        use lldap_auth::*;
+        let mut rng = rand::rngs::OsRng;
+        let registration_start_request =
+            opaque::client::registration::start_registration("password".as_bytes(), &mut rng)
+                .unwrap();
+        let request = registration::ClientRegistrationStartRequest {
+            username: "bob".to_string(),
+            registration_start_request: registration_start_request.message,
+        };
+        let start_response = opaque::server::registration::start_registration(
+            &opaque::server::ServerSetup::new(&mut rng),
+            request.registration_start_request,
+            &request.username,
+        )
+        .unwrap();
+        mock.expect_registration_start().times(1).return_once(|_| {
+            Ok(registration::ServerRegistrationStartResponse {
+                server_data: "".to_string(),
+                registration_response: start_response.message,
+                password_policy: None,
+            })
+        });
+        mock.expect_is_password_recently_used()
+            .returning(|_, _| Ok(false));
+        mock.expect_registration_finish()
+            .times(1)
+            .return_once(|_| Ok(()));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = LdapOp::ModifyRequest(LdapModifyRequest {
+            dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+            changes: vec![LdapModify {
+                operation: LdapModifyType::Replace,
+                modification: LdapPartialAttribute {
+                    atype: "userPassword".to_owned(),
+                    vals: vec!["password".as_bytes().to_vec()],
+                },
+            }],
+        });
         assert_eq!(
-            ldap_handler.do_search_or_dse(&request5).await,
-            expected_result
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_modify_response(
+                LdapResultCode::Success,
+                "".to_string(),
+            )])
         );
     }
 
+    fn make_modify(operation: LdapModifyType, atype: &str, vals: Vec<&str>) -> LdapModify {
+        LdapModify {
+            operation,
+            modification: LdapPartialAttribute {
+                atype: atype.to_owned(),
+                vals: vals.into_iter().map(|v| v.as_bytes().to_vec()).collect(),
+            },
+        }
+    }
+
+    fn expect_bob_details(mock: &mut MockTestBackendHandler) {
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .returning(|_| Ok(HashSet::new()));
+        mock.expect_get_user_details()
+            .with(eq(UserId::new("bob")))
+            .returning(|_| {
+                Ok(User {
+                    user_id: UserId::new("bob"),
+                    email: "bob@bob".to_owned(),
+                    attributes: vec![AttributeValue {
+                        name: "last_name".to_owned(),
+                        value: Serialized::from("Bobberson"),
+                    }],
+                    ..Default::default()
+                })
+            });
+    }
+
     #[tokio::test]
-    async fn test_search_wrong_base() {
-        let mut ldap_handler = setup_bound_admin_handler(MockTestBackendHandler::new()).await;
-        let request = make_search_request(
-            "ou=users,dc=example,dc=com",
-            LdapFilter::And(vec![]),
-            vec!["objectClass"],
-        );
+    async fn test_modify_request_attributes() {
+        let mut mock = MockTestBackendHandler::new();
+        expect_bob_details(&mut mock);
+        mock.expect_update_user()
+            .with(eq(UpdateUserRequest {
+                user_id: UserId::new("bob"),
+                email: Some("bob@example.com".to_owned()),
+                display_name: Some("Bob".to_owned()),
+                insert_attributes: vec![AttributeValue {
+                    name: "first_name".to_owned(),
+                    value: Serialized::from("Bobby"),
+                }],
+                delete_attributes: vec!["last_name".to_owned()],
+                ..Default::default()
+            }))
+            .times(1)
+            .return_once(|_| Ok(()));
+        mock.expect_record_audit_event()
+            .times(1)
+            .return_once(|_| Ok(()));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = LdapOp::ModifyRequest(LdapModifyRequest {
+            dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+            changes: vec![
+                make_modify(LdapModifyType::Replace, "mail", vec!["bob@example.com"]),
+                make_modify(LdapModifyType::Add, "displayName", vec!["Bob"]),
+                make_modify(LdapModifyType::Add, "givenName", vec!["Bobby"]),
+                make_modify(LdapModifyType::Delete, "sn", vec![]),
+            ],
+        });
         assert_eq!(
-            ldap_handler.do_search_or_dse(&request).await,
-            Ok(vec![make_search_success()])
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_modify_response(
+                LdapResultCode::Success,
+                "".to_string(),
+            )])
         );
     }
 
     #[tokio::test]
-    async fn test_search_unsupported_filters() {
-        let mut ldap_handler = setup_bound_admin_handler(MockTestBackendHandler::new()).await;
-        let request = make_user_search_request(
-            LdapFilter::Approx("uid".to_owned(), "value".to_owned()),
-            vec!["objectClass"],
+    async fn test_modify_request_errors() {
+        let mut mock = MockTestBackendHandler::new();
+        expect_bob_details(&mut mock);
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        async fn modify_code(
+            ldap_handler: &mut LdapHandler<MockTestBackendHandler>,
+            change: LdapModify,
+        ) -> LdapResultCode {
+            let request = LdapOp::ModifyRequest(LdapModifyRequest {
+                dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                changes: vec![change],
+            });
+            match ldap_handler
+                .handle_ldap_message(request)
+                .await
+                .unwrap()
+                .pop()
+            {
+                Some(LdapOp::ModifyResponse(response)) => response.code,
+                _ => panic!("Unexpected response"),
+            }
+        }
+        assert_eq!(
+            modify_code(
+                &mut ldap_handler,
+                make_modify(LdapModifyType::Replace, "entryUUID", vec!["abc"])
+            )
+            .await,
+            LdapResultCode::ConstraintViolation
         );
         assert_eq!(
-            ldap_handler.do_search_or_dse(&request).await,
-            Err(LdapError {
-                code: LdapResultCode::UnwillingToPerform,
-                message: r#"Unsupported user filter: Approx("uid", "value")"#.to_string()
-            })
+            modify_code(
+                &mut ldap_handler,
+                make_modify(
+                    LdapModifyType::Replace,
+                    "createTimestamp",
+                    vec!["20230101000000Z"]
+                )
+            )
+            .await,
+            LdapResultCode::ConstraintViolation
+        );
+        assert_eq!(
+            modify_code(
+                &mut ldap_handler,
+                make_modify(LdapModifyType::Delete, "objectClass", vec![])
+            )
+            .await,
+            LdapResultCode::ConstraintViolation
+        );
+        assert_eq!(
+            modify_code(
+                &mut ldap_handler,
+                make_modify(LdapModifyType::Delete, "mail", vec![])
+            )
+            .await,
+            LdapResultCode::ConstraintViolation
+        );
+        assert_eq!(
+            modify_code(
+                &mut ldap_handler,
+                make_modify(LdapModifyType::Add, "sn", vec!["Bob"])
+            )
+            .await,
+            LdapResultCode::AttributeOrValueExists
+        );
+        assert_eq!(
+            modify_code(
+                &mut ldap_handler,
+                make_modify(LdapModifyType::Replace, "jpegPhoto", vec!["not a jpeg"])
+            )
+            .await,
+            LdapResultCode::InvalidAttributeSyntax
+        );
+        assert_eq!(
+            modify_code(
+                &mut ldap_handler,
+                make_modify(LdapModifyType::Replace, "unknown", vec!["value"])
+            )
+            .await,
+            LdapResultCode::UnwillingToPerform
         );
     }
 
     #[tokio::test]
-    async fn test_password_change() {
+    async fn test_password_change_password_manager() {
         let mut mock = MockTestBackendHandler::new();
         mock.expect_get_user_groups()
             .with(eq(UserId::new("bob")))
@@ -2133,12 +6907,15 @@ mod tests {
             Ok(registration::ServerRegistrationStartResponse {
                 server_data: "".to_string(),
                 registration_response: start_response.message,
+                password_policy: None,
             })
         });
+        mock.expect_is_password_recently_used()
+            .returning(|_, _| Ok(false));
         mock.expect_registration_finish()
             .times(1)
             .return_once(|_| Ok(()));
-        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let mut ldap_handler = setup_bound_password_manager_handler(mock).await;
         let request = LdapOp::ExtendedRequest(
             LdapPasswordModifyRequest {
                 user_identity: Some("uid=bob,ou=people,dc=example,dc=com".to_string()),
@@ -2157,86 +6934,79 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_password_change_modify_request() {
+    async fn test_password_change_generated_password() {
         let mut mock = MockTestBackendHandler::new();
         mock.expect_get_user_groups()
             .with(eq(UserId::new("bob")))
             .returning(|_| Ok(HashSet::new()));
-        use lldap_auth::*;
-        let mut rng = rand::rngs::OsRng;
-        let registration_start_request =
-            opaque::client::registration::start_registration("password".as_bytes(), &mut rng)
+        mock.expect_bind()
+            .with(eq(BindRequest {
+                name: UserId::new("bob"),
+                password: "old_pass".to_string(),
+            }))
+            .times(1)
+            .return_once(|_| Ok(()));
+        mock.expect_registration_start()
+            .times(1)
+            .return_once(|request| {
+                use lldap_auth::*;
+                let mut rng = rand::rngs::OsRng;
+                let start_response = opaque::server::registration::start_registration(
+                    &opaque::server::ServerSetup::new(&mut rng),
+                    request.registration_start_request,
+                    &request.username,
+                )
                 .unwrap();
-        let request = registration::ClientRegistrationStartRequest {
-            username: "bob".to_string(),
-            registration_start_request: registration_start_request.message,
-        };
-        let start_response = opaque::server::registration::start_registration(
-            &opaque::server::ServerSetup::new(&mut rng),
-            request.registration_start_request,
-            &request.username,
-        )
-        .unwrap();
-        mock.expect_registration_start().times(1).return_once(|_| {
-            Ok(registration::ServerRegistrationStartResponse {
-                server_data: "".to_string(),
-                registration_response: start_response.message,
-            })
-        });
+                Ok(registration::ServerRegistrationStartResponse {
+                    server_data: "".to_string(),
+                    registration_response: start_response.message,
+                    password_policy: None,
+                })
+            });
+        mock.expect_is_password_recently_used()
+            .returning(|_, _| Ok(false));
         mock.expect_registration_finish()
             .times(1)
             .return_once(|_| Ok(()));
         let mut ldap_handler = setup_bound_admin_handler(mock).await;
-        let request = LdapOp::ModifyRequest(LdapModifyRequest {
-            dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
-            changes: vec![LdapModify {
-                operation: LdapModifyType::Replace,
-                modification: LdapPartialAttribute {
-                    atype: "userPassword".to_owned(),
-                    vals: vec!["password".as_bytes().to_vec()],
-                },
-            }],
-        });
-        assert_eq!(
-            ldap_handler.handle_ldap_message(request).await,
-            Some(vec![make_modify_response(
-                LdapResultCode::Success,
-                "".to_string(),
-            )])
+        let request = LdapOp::ExtendedRequest(
+            LdapPasswordModifyRequest {
+                user_identity: Some("uid=bob,ou=people,dc=example,dc=com".to_string()),
+                old_password: Some("old_pass".to_string()),
+                new_password: None,
+            }
+            .into(),
         );
+        let response = ldap_handler.handle_ldap_message(request).await.unwrap();
+        match response.as_slice() {
+            [LdapOp::ExtendedResponse(LdapExtendedResponse {
+                res,
+                value: Some(value),
+                ..
+            })] => {
+                assert_eq!(res.code, LdapResultCode::Success);
+                assert_eq!(value.len(), 24);
+                assert_eq!(value[..4], [0x30, 22, 0x80, 20]);
+            }
+            _ => panic!("Unexpected response: {:?}", response),
+        }
     }
 
     #[tokio::test]
-    async fn test_password_change_password_manager() {
+    async fn test_password_change_policy() {
         let mut mock = MockTestBackendHandler::new();
         mock.expect_get_user_groups()
             .with(eq(UserId::new("bob")))
             .returning(|_| Ok(HashSet::new()));
-        use lldap_auth::*;
-        let mut rng = rand::rngs::OsRng;
-        let registration_start_request =
-            opaque::client::registration::start_registration("password".as_bytes(), &mut rng)
-                .unwrap();
-        let request = registration::ClientRegistrationStartRequest {
-            username: "bob".to_string(),
-            registration_start_request: registration_start_request.message,
-        };
-        let start_response = opaque::server::registration::start_registration(
-            &opaque::server::ServerSetup::new(&mut rng),
-            request.registration_start_request,
-            &request.username,
-        )
-        .unwrap();
-        mock.expect_registration_start().times(1).return_once(|_| {
-            Ok(registration::ServerRegistrationStartResponse {
-                server_data: "".to_string(),
-                registration_response: start_response.message,
-            })
-        });
-        mock.expect_registration_finish()
+        mock.expect_is_password_recently_used()
             .times(1)
-            .return_once(|_| Ok(()));
-        let mut ldap_handler = setup_bound_password_manager_handler(mock).await;
+            .returning(|_, _| Ok(true));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        ldap_handler.ldap_info.password_policy = PasswordPolicyOptionsBuilder::default()
+            .min_length(12)
+            .require_digit(true)
+            .build()
+            .unwrap();
         let request = LdapOp::ExtendedRequest(
             LdapPasswordModifyRequest {
                 user_identity: Some("uid=bob,ou=people,dc=example,dc=com".to_string()),
@@ -2248,8 +7018,82 @@ mod tests {
         assert_eq!(
             ldap_handler.handle_ldap_message(request).await,
             Some(vec![make_extended_response(
-                LdapResultCode::Success,
-                "".to_string(),
+                LdapResultCode::ConstraintViolation,
+                "The password doesn't match the policy: at least 12 characters, a digit"
+                    .to_string(),
+            )])
+        );
+        let request = LdapOp::ExtendedRequest(
+            LdapPasswordModifyRequest {
+                user_identity: Some("uid=bob,ou=people,dc=example,dc=com".to_string()),
+                old_password: None,
+                new_password: Some("password1234".to_string()),
+            }
+            .into(),
+        );
+        assert_eq!(
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_extended_response(
+                LdapResultCode::ConstraintViolation,
+                "Password recently used".to_string(),
+            )])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_password_change_modify_request_policy() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .returning(|_| Ok(HashSet::new()));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        ldap_handler.ldap_info.password_policy = PasswordPolicyOptionsBuilder::default()
+            .min_length(12)
+            .require_digit(true)
+            .build()
+            .unwrap();
+        let request = LdapOp::ModifyRequest(LdapModifyRequest {
+            dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+            changes: vec![make_modify(
+                LdapModifyType::Replace,
+                "userPassword",
+                vec!["password"],
+            )],
+        });
+        assert_eq!(
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_modify_response(
+                LdapResultCode::ConstraintViolation,
+                "The password doesn't match the policy: at least 12 characters, a digit"
+                    .to_string(),
+            )])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_password_change_modify_request_history() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .returning(|_| Ok(HashSet::new()));
+        mock.expect_is_password_recently_used()
+            .with(eq(UserId::new("bob")), eq("password1234"))
+            .times(1)
+            .returning(|_, _| Ok(true));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = LdapOp::ModifyRequest(LdapModifyRequest {
+            dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+            changes: vec![make_modify(
+                LdapModifyType::Replace,
+                "userPassword",
+                vec!["password1234"],
+            )],
+        });
+        assert_eq!(
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_modify_response(
+                LdapResultCode::ConstraintViolation,
+                "Password recently used".to_string(),
             )])
         );
     }
@@ -2260,20 +7104,31 @@ mod tests {
         mock.expect_get_user_groups()
             .with(eq(UserId::new("bob")))
             .returning(|_| Ok(HashSet::new()));
+        mock.expect_bind()
+            .with(eq(BindRequest {
+                name: UserId::new("bob"),
+                password: "wrong".to_string(),
+            }))
+            .times(1)
+            .return_once(|_| {
+                Err(crate::domain::error::DomainError::AuthenticationError(
+                    "bad password".to_string(),
+                ))
+            });
         let mut ldap_handler = setup_bound_admin_handler(mock).await;
         let request = LdapOp::ExtendedRequest(
             LdapPasswordModifyRequest {
-                user_identity: None,
-                old_password: None,
-                new_password: None,
+                user_identity: Some("uid=bob,ou=people,dc=example,dc=com".to_string()),
+                old_password: Some("wrong".to_string()),
+                new_password: Some("password".to_string()),
             }
             .into(),
         );
         assert_eq!(
             ldap_handler.handle_ldap_message(request).await,
             Some(vec![make_extended_response(
-                LdapResultCode::ConstraintViolation,
-                "Missing either user_id or password".to_string(),
+                LdapResultCode::InvalidCredentials,
+                "Invalid old password".to_string(),
             )])
         );
         let request = LdapOp::ExtendedRequest(
@@ -2377,10 +7232,81 @@ mod tests {
         assert_eq!(
             ldap_handler.do_search_or_dse(&request).await,
             Ok(vec![
-                root_dse_response("dc=example,dc=com"),
+                root_dse_response(&ldap_handler.ldap_info, false),
                 make_search_success()
             ])
         );
+        let entry = match root_dse_response(&ldap_handler.ldap_info, false) {
+            LdapOp::SearchResultEntry(entry) => entry,
+            _ => panic!(),
+        };
+        let get_values = |atype: &str| {
+            entry
+                .attributes
+                .iter()
+                .find(|a| a.atype == atype)
+                .map(|a| a.vals.clone())
+        };
+        assert_eq!(
+            get_values("namingContexts"),
+            Some(vec![b"dc=example,dc=com".to_vec()])
+        );
+        assert_eq!(
+            get_values("supportedLDAPVersion"),
+            Some(vec![b"3".to_vec()])
+        );
+        assert_eq!(
+            get_values("supportedControl"),
+            Some(vec![
+                b"1.2.840.113556.1.4.319".to_vec(),
+                b"1.2.826.0.1.3344810.2.3".to_vec(),
+                b"1.2.840.113556.1.4.473".to_vec()
+            ])
+        );
+        assert_eq!(
+            get_values("supportedExtension"),
+            Some(vec![
+                b"1.3.6.1.4.1.4203.1.11.1".to_vec(),
+                b"1.3.6.1.4.1.4203.1.11.3".to_vec()
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_whoami() {
+        let make_whoami_request = || {
+            LdapOp::ExtendedRequest(LdapExtendedRequest {
+                name: "1.3.6.1.4.1.4203.1.11.3".to_string(),
+                value: None,
+            })
+        };
+        let make_whoami_response = |authz_id: &str| {
+            Some(vec![LdapOp::ExtendedResponse(LdapExtendedResponse {
+                res: LdapResultOp {
+                    code: LdapResultCode::Success,
+                    matcheddn: "".to_string(),
+                    message: "".to_string(),
+                    referral: vec![],
+                },
+                name: None,
+                value: Some(authz_id.as_bytes().to_vec()),
+            })])
+        };
+        let mut ldap_handler =
+            LdapHandler::new_for_tests(MockTestBackendHandler::new(), "dc=example,dc=com");
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_message(make_whoami_request())
+                .await,
+            make_whoami_response("")
+        );
+        let mut ldap_handler = setup_bound_readonly_handler(MockTestBackendHandler::new()).await;
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_message(make_whoami_request())
+                .await,
+            make_whoami_response("dn:uid=test,ou=people,dc=example,dc=com")
+        );
     }
 
     #[tokio::test]
@@ -2395,6 +7321,9 @@ mod tests {
             }))
             .times(1)
             .return_once(|_| Ok(()));
+        mock.expect_record_audit_event()
+            .times(1)
+            .return_once(|_| Ok(()));
         let ldap_handler = setup_bound_admin_handler(mock).await;
         let request = LdapAddRequest {
             dn: "uid=bob,ou=people,dc=example,dc=com".to_owned(),
@@ -2437,6 +7366,9 @@ mod tests {
             }))
             .times(1)
             .return_once(|_| Ok(()));
+        mock.expect_record_audit_event()
+            .times(1)
+            .return_once(|_| Ok(()));
         let ldap_handler = setup_bound_admin_handler(mock).await;
         let request = LdapAddRequest {
             dn: "uid=bob,ou=people,dc=example,dc=com".to_owned(),
@@ -2528,6 +7460,142 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_search_typed_attribute_filters() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_schema().returning(|| {
+            Ok(Schema {
+                user_attributes: AttributeList {
+                    attributes: [
+                        ("employee_id", AttributeType::Integer),
+                        ("account_enabled", AttributeType::Boolean),
+                    ]
+                    .into_iter()
+                    .map(|(name, attribute_type)| AttributeSchema {
+                        name: name.to_owned(),
+                        attribute_type,
+                        is_list: false,
+                        is_visible: true,
+                        is_editable: true,
+                        is_hardcoded: false,
+                        is_case_sensitive: false,
+                        allowed_values: None,
+                    })
+                    .collect(),
+                },
+                group_attributes: AttributeList {
+                    attributes: Vec::new(),
+                },
+            })
+        });
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::And(vec![
+                    UserRequestFilter::AttributeValueEquality(
+                        "account_enabled".to_owned(),
+                        Serialized::from(&true),
+                    ),
+                    UserRequestFilter::AttributeValueEquality(
+                        "employee_id".to_owned(),
+                        Serialized::from(&42i64),
+                    ),
+                    false.into(),
+                ]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| Ok(vec![]));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request(
+            LdapFilter::And(vec![
+                LdapFilter::Equality("account_enabled".to_string(), "true".to_string()),
+                LdapFilter::Equality("employee_id".to_string(), "42".to_string()),
+                // Not an integer: matches nothing.
+                LdapFilter::Equality("employee_id".to_string(), "abc".to_string()),
+            ]),
+            vec!["uid"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_compare_user_object_class_and_custom_attribute() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_schema().returning(|| {
+            Ok(Schema {
+                user_attributes: AttributeList {
+                    attributes: vec![AttributeSchema {
+                        name: "nickname".to_owned(),
+                        attribute_type: AttributeType::String,
+                        is_list: false,
+                        is_visible: true,
+                        is_editable: true,
+                        is_hardcoded: false,
+                        is_case_sensitive: false,
+                        allowed_values: None,
+                    }],
+                },
+                group_attributes: AttributeList {
+                    attributes: Vec::new(),
+                },
+            })
+        });
+        mock.expect_list_users().returning(|_, _| {
+            Ok(vec![UserAndGroups {
+                user: User {
+                    user_id: UserId::new("bob"),
+                    attributes: vec![AttributeValue {
+                        name: "nickname".to_owned(),
+                        value: Serialized::from("bobby"),
+                    }],
+                    ..Default::default()
+                },
+                groups: None,
+            }])
+        });
+        mock.expect_list_groups().returning(|_| Ok(vec![]));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let dn = "uid=bob,ou=people,dc=example,dc=com";
+        let make_compare_result = |code| {
+            Ok(vec![LdapOp::CompareResult(LdapResultOp {
+                code,
+                matcheddn: dn.to_string(),
+                message: "".to_string(),
+                referral: vec![],
+            })])
+        };
+        let request = LdapCompareRequest {
+            dn: dn.to_string(),
+            atype: "objectClass".to_owned(),
+            val: b"inetorgperson".to_vec(),
+        };
+        assert_eq!(
+            ldap_handler.do_compare(request).await,
+            make_compare_result(LdapResultCode::CompareTrue)
+        );
+        let request = LdapCompareRequest {
+            dn: dn.to_string(),
+            atype: "nickname".to_owned(),
+            val: b"bobby".to_vec(),
+        };
+        assert_eq!(
+            ldap_handler.do_compare(request).await,
+            make_compare_result(LdapResultCode::CompareTrue)
+        );
+        let request = LdapCompareRequest {
+            dn: dn.to_string(),
+            atype: "nickname".to_owned(),
+            val: b"Bobby".to_vec(),
+        };
+        assert_eq!(
+            ldap_handler.do_compare(request).await,
+            make_compare_result(LdapResultCode::CompareFalse)
+        );
+    }
+
     #[tokio::test]
     async fn test_compare_group() {
         let mut mock = MockTestBackendHandler::new();
@@ -2539,6 +7607,9 @@ mod tests {
                 display_name: "group".to_string(),
                 creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
                 users: vec![UserId::new("bob")],
+                attributes: vec![],
+                dynamic_filter: None,
+                member_groups: vec![],
                 uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
             }])
         });
@@ -2632,6 +7703,9 @@ mod tests {
                 display_name: "group".to_string(),
                 creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap().naive_utc(),
                 users: vec![UserId::new("bob")],
+                attributes: vec![],
+                dynamic_filter: None,
+                member_groups: vec![],
                 uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
             }])
         });
@@ -1,50 +1,158 @@
 use crate::{
     domain::{
         handler::{BackendHandler, LoginHandler},
+        ldap::utils::LdapInfo,
         opaque_handler::OpaqueHandler,
     },
     infra::{
         access_control::AccessControlledBackendHandler,
         configuration::{Configuration, LdapsOptions},
-        ldap_handler::LdapHandler,
+        ldap_codec::{LdapRequest, LdapResponse, LldapCodec},
+        ldap_handler::{filter_matched_values, make_bind_response, LdapHandler, TlsStatus},
     },
 };
 use actix_rt::net::TcpStream;
 use actix_server::ServerBuilder;
 use actix_service::{fn_service, ServiceFactoryExt};
-use anyhow::{anyhow, Context, Result};
-use ldap3_proto::{proto::LdapMsg, LdapCodec};
+use anyhow::{anyhow, bail, Context, Result};
+use ldap3_proto::proto::{LdapMsg, LdapOp};
 use rustls::PrivateKey;
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 use tokio_rustls::TlsAcceptor as RustlsTlsAcceptor;
 use tokio_util::codec::{FramedRead, FramedWrite};
-use tracing::{debug, error, info, instrument};
+use tracing::{debug, error, info, instrument, warn};
+
+/// Caps the number of concurrent LDAP connections (LDAP and LDAPS combined), and keeps count of
+/// the active and rejected ones.
+#[derive(Clone)]
+struct ConnectionLimiter {
+    /// None for no limit.
+    permits: Option<Arc<Semaphore>>,
+    active: Arc<AtomicUsize>,
+    rejected: Arc<AtomicU64>,
+}
+
+/// Counts as an active connection until dropped.
+struct ConnectionGuard {
+    _permit: Option<OwnedSemaphorePermit>,
+    active: Arc<AtomicUsize>,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl ConnectionLimiter {
+    fn new(max_connections: u32) -> Self {
+        Self {
+            permits: (max_connections > 0)
+                .then(|| Arc::new(Semaphore::new(max_connections as usize))),
+            active: Arc::new(AtomicUsize::new(0)),
+            rejected: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Returns None if the limit is reached.
+    fn try_acquire(&self) -> Option<ConnectionGuard> {
+        let permit = match &self.permits {
+            Some(permits) => match permits.clone().try_acquire_owned() {
+                Ok(permit) => Some(permit),
+                Err(_) => {
+                    let rejected = self.rejected.fetch_add(1, Ordering::Relaxed) + 1;
+                    warn!(
+                        active = self.active.load(Ordering::Relaxed),
+                        rejected, "Too many LDAP connections, rejecting a new one"
+                    );
+                    return None;
+                }
+            },
+            None => None,
+        };
+        let active = self.active.fetch_add(1, Ordering::Relaxed) + 1;
+        debug!(active, "New LDAP connection");
+        Some(ConnectionGuard {
+            _permit: permit,
+            active: self.active.clone(),
+        })
+    }
+}
 
 #[instrument(skip_all, level = "info", name = "LDAP request")]
 async fn handle_ldap_message<Backend, Writer>(
-    msg: Result<LdapMsg, std::io::Error>,
+    msg: Result<LdapRequest, std::io::Error>,
     resp: &mut Writer,
     session: &mut LdapHandler<Backend>,
 ) -> Result<bool>
 where
     Backend: BackendHandler + LoginHandler + OpaqueHandler,
-    Writer: futures_util::Sink<LdapMsg> + Unpin,
-    <Writer as futures_util::Sink<LdapMsg>>::Error: std::error::Error + Send + Sync + 'static,
+    Writer: futures_util::Sink<LdapResponse> + Unpin,
+    <Writer as futures_util::Sink<LdapResponse>>::Error: std::error::Error + Send + Sync + 'static,
 {
     use futures_util::SinkExt;
     let msg = msg.context("while receiving LDAP op")?;
     debug!(?msg);
-    match session.handle_ldap_message(msg.op).await {
+    let (msgid, result) = match msg {
+        LdapRequest::Message {
+            msg,
+            matched_values,
+            sort,
+        } => {
+            let mut result = match (msg.op, sort) {
+                (LdapOp::SearchRequest(request), Some(sort)) => {
+                    let (ops, controls, sort_result) =
+                        session.handle_sorted_search(request, msg.ctrl, &sort).await;
+                    Some((ops, controls, Some(sort_result)))
+                }
+                // The sort control only applies to the searches.
+                (op, _) => session
+                    .handle_ldap_message_with_controls(op, msg.ctrl)
+                    .await
+                    .map(|(ops, controls)| (ops, controls, None)),
+            };
+            if let Some((ops, _, _)) = &mut result {
+                filter_matched_values(ops, &matched_values);
+            }
+            (msg.msgid, result)
+        }
+        LdapRequest::SaslBind { msgid, request } => {
+            let (code, message) = session.do_sasl_bind(&request).await;
+            (
+                msgid,
+                Some((vec![make_bind_response(code, message)], vec![], None)),
+            )
+        }
+    };
+    match result {
         None => return Ok(false),
-        Some(result) => {
+        Some((result, mut controls, mut sort_result)) => {
             if result.is_empty() {
                 debug!("No response");
             }
-            for response in result.into_iter() {
+            let last_response = result.len().saturating_sub(1);
+            for (i, response) in result.into_iter().enumerate() {
                 debug!(?response);
-                resp.send(LdapMsg {
-                    msgid: msg.msgid,
-                    op: response,
-                    ctrl: vec![],
+                // The response controls go with the last message (e.g. SearchResultDone).
+                let is_last = i == last_response;
+                resp.send(LdapResponse {
+                    msg: LdapMsg {
+                        msgid,
+                        op: response,
+                        ctrl: if is_last {
+                            std::mem::take(&mut controls)
+                        } else {
+                            vec![]
+                        },
+                    },
+                    sort_result: if is_last { sort_result.take() } else { None },
                 })
                 .await
                 .context("while sending a response: {:#}")?
@@ -58,13 +166,12 @@ where
     Ok(true)
 }
 
-#[instrument(skip_all, level = "info", name = "LDAP session")]
-async fn handle_ldap_stream<Stream, Backend>(
+/// Serves the requests until the end of the session, or until StartTLS is accepted. Returns the
+/// stream, to be upgraded in the latter case.
+async fn serve_requests<Stream, Backend>(
     stream: Stream,
-    backend_handler: Backend,
-    ldap_base_dn: String,
-    ignored_user_attributes: Vec<String>,
-    ignored_group_attributes: Vec<String>,
+    session: &mut LdapHandler<Backend>,
+    idle_timeout: Option<Duration>,
 ) -> Result<Stream>
 where
     Backend: BackendHandler + LoginHandler + OpaqueHandler + 'static,
@@ -73,27 +180,75 @@ where
     use tokio_stream::StreamExt;
     let (r, w) = tokio::io::split(stream);
     // Configure the codec etc.
-    let mut requests = FramedRead::new(r, LdapCodec);
-    let mut resp = FramedWrite::new(w, LdapCodec);
-
-    let mut session = LdapHandler::new(
-        AccessControlledBackendHandler::new(backend_handler),
-        ldap_base_dn,
-        ignored_user_attributes,
-        ignored_group_attributes,
-    );
+    let mut requests = FramedRead::new(r, LldapCodec);
+    let mut resp = FramedWrite::new(w, LldapCodec);
 
-    while let Some(msg) = requests.next().await {
-        if !handle_ldap_message(msg, &mut resp, &mut session)
+    loop {
+        let msg = match idle_timeout {
+            Some(idle_timeout) => match tokio::time::timeout(idle_timeout, requests.next()).await {
+                Ok(msg) => msg,
+                Err(_) => {
+                    debug!("Closing the idle connection");
+                    break;
+                }
+            },
+            None => requests.next().await,
+        };
+        let msg = match msg {
+            Some(msg) => msg,
+            None => break,
+        };
+        if !handle_ldap_message(msg, &mut resp, session)
             .await
             .context("while handling incoming messages")?
         {
             break;
         }
+        if session.tls_status() == TlsStatus::Upgrading {
+            // The client must wait for the response before starting the handshake.
+            if !requests.read_buffer().is_empty() {
+                bail!("Received a request before the StartTLS handshake");
+            }
+            break;
+        }
     }
     Ok(requests.into_inner().unsplit(resp.into_inner()))
 }
 
+#[instrument(skip_all, level = "info", name = "LDAP session")]
+async fn handle_ldap_stream<Stream, Backend>(
+    stream: Stream,
+    backend_handler: Backend,
+    ldap_info: LdapInfo,
+    tls_status: TlsStatus,
+    start_tls_acceptor: Option<RustlsTlsAcceptor>,
+    idle_timeout: Option<Duration>,
+) -> Result<()>
+where
+    Backend: BackendHandler + LoginHandler + OpaqueHandler + 'static,
+    Stream: tokio::io::AsyncRead + tokio::io::AsyncWrite + std::marker::Unpin,
+{
+    let mut session = LdapHandler::new(
+        AccessControlledBackendHandler::new(backend_handler),
+        ldap_info,
+    );
+    session.set_tls_status(tls_status);
+
+    let stream = serve_requests(stream, &mut session, idle_timeout).await?;
+    if session.tls_status() == TlsStatus::Upgrading {
+        let tls_acceptor = start_tls_acceptor
+            .ok_or_else(|| anyhow!("StartTLS accepted without a TLS configuration"))?;
+        let tls_stream = tls_acceptor
+            .accept(stream)
+            .await
+            .context("during the StartTLS handshake")?;
+        debug!("Connection upgraded with StartTLS");
+        session.set_tls_status(TlsStatus::Encrypted);
+        serve_requests(tls_stream, &mut session, idle_timeout).await?;
+    }
+    Ok(())
+}
+
 fn read_private_key(key_file: &str) -> Result<PrivateKey> {
     use rustls_pemfile::{ec_private_keys, pkcs8_private_keys, rsa_private_keys};
     use std::{fs::File, io::BufReader};
@@ -158,27 +313,50 @@ pub fn build_ldap_server<Backend>(
 where
     Backend: BackendHandler + LoginHandler + OpaqueHandler + Clone + 'static,
 {
+    let idle_timeout = (config.ldap_idle_timeout_seconds > 0)
+        .then(|| Duration::from_secs(config.ldap_idle_timeout_seconds));
     let context = (
         backend_handler,
-        config.ldap_base_dn.clone(),
-        config.ignored_user_attributes.clone(),
-        config.ignored_group_attributes.clone(),
+        LdapInfo::new(config),
+        ConnectionLimiter::new(config.ldap_max_connections),
+        idle_timeout,
     );
 
+    let tls_acceptor = if config.ldaps_options.enabled {
+        Some(
+            get_tls_acceptor(&config.ldaps_options)
+                .context("while setting up the SSL certificate")?,
+        )
+    } else {
+        None
+    };
     let context_for_tls = context.clone();
+    let start_tls_acceptor = tls_acceptor.clone();
 
     let binder = move || {
         let context = context.clone();
+        let start_tls_acceptor = start_tls_acceptor.clone();
         fn_service(move |stream: TcpStream| {
             let context = context.clone();
+            let start_tls_acceptor = start_tls_acceptor.clone();
             async move {
-                let (handler, base_dn, ignored_user_attributes, ignored_group_attributes) = context;
+                let (handler, ldap_info, limiter, idle_timeout) = context;
+                let _guard = match limiter.try_acquire() {
+                    Some(guard) => guard,
+                    None => return Ok(()),
+                };
+                let tls_status = if start_tls_acceptor.is_some() {
+                    TlsStatus::StartTlsAvailable
+                } else {
+                    TlsStatus::Unavailable
+                };
                 handle_ldap_stream(
                     stream,
                     handler,
-                    base_dn,
-                    ignored_user_attributes,
-                    ignored_group_attributes,
+                    ldap_info,
+                    tls_status,
+                    start_tls_acceptor,
+                    idle_timeout,
                 )
                 .await
             }
@@ -190,28 +368,26 @@ where
     let server_builder = server_builder
         .bind("ldap", (config.ldap_host.clone(), config.ldap_port), binder)
         .with_context(|| format!("while binding to the port {}", config.ldap_port));
-    if config.ldaps_options.enabled {
-        let tls_context = (
-            context_for_tls,
-            get_tls_acceptor(&config.ldaps_options)
-                .context("while setting up the SSL certificate")?,
-        );
+    if let Some(tls_acceptor) = tls_acceptor {
+        let tls_context = (context_for_tls, tls_acceptor);
         let tls_binder = move || {
             let tls_context = tls_context.clone();
             fn_service(move |stream: TcpStream| {
                 let tls_context = tls_context.clone();
                 async move {
-                    let (
-                        (handler, base_dn, ignored_user_attributes, ignored_group_attributes),
-                        tls_acceptor,
-                    ) = tls_context;
+                    let ((handler, ldap_info, limiter, idle_timeout), tls_acceptor) = tls_context;
+                    let _guard = match limiter.try_acquire() {
+                        Some(guard) => guard,
+                        None => return Ok(()),
+                    };
                     let tls_stream = tls_acceptor.accept(stream).await?;
                     handle_ldap_stream(
                         tls_stream,
                         handler,
-                        base_dn,
-                        ignored_user_attributes,
-                        ignored_group_attributes,
+                        ldap_info,
+                        TlsStatus::Encrypted,
+                        None,
+                        idle_timeout,
                     )
                     .await
                 }
@@ -235,3 +411,30 @@ where
         server_builder
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connection_limiter() {
+        let limiter = ConnectionLimiter::new(2);
+        let first = limiter.try_acquire().unwrap();
+        let _second = limiter.try_acquire().unwrap();
+        assert!(limiter.try_acquire().is_none());
+        assert_eq!(limiter.active.load(Ordering::Relaxed), 2);
+        assert_eq!(limiter.rejected.load(Ordering::Relaxed), 1);
+        drop(first);
+        assert_eq!(limiter.active.load(Ordering::Relaxed), 1);
+        assert!(limiter.try_acquire().is_some());
+    }
+
+    #[test]
+    fn test_connection_limiter_unlimited() {
+        let limiter = ConnectionLimiter::new(0);
+        let _guards = (0..100)
+            .map(|_| limiter.try_acquire().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(limiter.active.load(Ordering::Relaxed), 100);
+    }
+}
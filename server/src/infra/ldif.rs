@@ -0,0 +1,1008 @@
+//! Export and import of the whole directory as LDIF (RFC 2849).
+
+use crate::domain::{
+    error::Result as DomainResult,
+    handler::{
+        BackendHandler, CreateUserRequest, GroupListerBackendHandler, LoginHandler, Schema,
+        UpdateUserRequest, UserListerBackendHandler,
+    },
+    imported_password::check_imported_password_hash,
+    ldap::{
+        group::make_group_export_entry,
+        user::{get_overriding_custom_attribute, make_user_export_entry, MAIL_ALIASES_ATTRIBUTE},
+        utils::{
+            convert_custom_attribute_values, get_group_id_from_distinguished_name,
+            get_user_id_from_distinguished_name, map_user_field_with_schema, LdapInfo,
+            UserFieldType,
+        },
+    },
+    types::{AttributeValue, UserColumn, UserId},
+};
+use anyhow::{Context, Result};
+use base64::Engine;
+use ldap3_proto::{LdapPartialAttribute, LdapSearchResultEntry};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io::Write,
+};
+
+/// Number of users read from the database at a time.
+const PAGE_SIZE: u64 = 100;
+/// Lines longer than this are folded.
+const MAX_LINE_LENGTH: usize = 76;
+
+/// Whether the value can be written as is, or must be base64-encoded (SAFE-STRING in the RFC).
+fn is_safe_string(value: &[u8]) -> bool {
+    match value.first() {
+        None => true,
+        Some(b' ' | b':' | b'<') => false,
+        Some(_) => {
+            value.last() != Some(&b' ')
+                && value
+                    .iter()
+                    .all(|&c| c.is_ascii() && c != b'\0' && c != b'\n' && c != b'\r')
+        }
+    }
+}
+
+fn write_line(out: &mut impl Write, name: &str, value: &[u8]) -> std::io::Result<()> {
+    let line = if is_safe_string(value) {
+        // Safe strings are ASCII.
+        format!("{}: {}", name, String::from_utf8_lossy(value))
+    } else {
+        format!(
+            "{}:: {}",
+            name,
+            base64::engine::general_purpose::STANDARD.encode(value)
+        )
+    };
+    // The line only contains ASCII characters, so it can be split anywhere. Continuation lines
+    // start with a space.
+    let (first, mut rest) = line.split_at(line.len().min(MAX_LINE_LENGTH));
+    writeln!(out, "{}", first)?;
+    while !rest.is_empty() {
+        let (chunk, remainder) = rest.split_at(rest.len().min(MAX_LINE_LENGTH - 1));
+        writeln!(out, " {}", chunk)?;
+        rest = remainder;
+    }
+    Ok(())
+}
+
+fn write_entry(out: &mut impl Write, entry: &LdapSearchResultEntry) -> std::io::Result<()> {
+    write_line(out, "dn", entry.dn.as_bytes())?;
+    for attribute in &entry.attributes {
+        for value in &attribute.vals {
+            write_line(out, &attribute.atype, value)?;
+        }
+    }
+    writeln!(out)
+}
+
+fn write_organizational_unit(out: &mut impl Write, ou: &str, base_dn: &str) -> Result<()> {
+    write_entry(
+        out,
+        &LdapSearchResultEntry {
+            dn: format!("ou={},{}", ou, base_dn),
+            attributes: vec![
+                LdapPartialAttribute {
+                    atype: "objectclass".to_owned(),
+                    vals: vec![b"organizationalUnit".to_vec()],
+                },
+                LdapPartialAttribute {
+                    atype: "ou".to_owned(),
+                    vals: vec![ou.as_bytes().to_vec()],
+                },
+            ],
+        },
+    )?;
+    Ok(())
+}
+
+/// Writes all the users and groups, with the organizational units containing them. The base DN
+/// entry itself is expected to exist in the target directory.
+///
+/// The users are read one page at a time, so that the whole directory is never held in memory.
+pub async fn export_ldif<Backend>(
+    backend: &Backend,
+    ldap_info: &LdapInfo,
+    out: &mut impl Write,
+) -> Result<()>
+where
+    Backend: UserListerBackendHandler + GroupListerBackendHandler,
+{
+    let schema = backend
+        .get_schema()
+        .await
+        .context("while reading the schema")?;
+    writeln!(out, "version: 1")?;
+    writeln!(out)?;
+    write_organizational_unit(out, &ldap_info.people_ou.1, &ldap_info.base_dn_str)?;
+    write_organizational_unit(out, &ldap_info.groups_ou.1, &ldap_info.base_dn_str)?;
+    let mut offset = 0;
+    loop {
+        let users = backend
+            .list_users_page(None, true, offset, PAGE_SIZE)
+            .await
+            .context("while listing the users")?;
+        for user in &users {
+            let entry = make_user_export_entry(
+                user.user.clone(),
+                user.groups.as_deref(),
+                &schema,
+                ldap_info,
+            );
+            write_entry(out, &entry)?;
+        }
+        if (users.len() as u64) < PAGE_SIZE {
+            break;
+        }
+        offset += PAGE_SIZE;
+    }
+    for group in backend
+        .list_groups(None)
+        .await
+        .context("while listing the groups")?
+    {
+        write_entry(out, &make_group_export_entry(group, ldap_info, &schema))?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Attributes computed by LLDAP, that are skipped without a warning when importing a user.
+const COMPUTED_USER_ATTRIBUTES: &[&str] = &[
+    "objectclass",
+    "uidnumber",
+    "gidnumber",
+    "gecos",
+    "shadowlastchange",
+    "memberof",
+    "homedirectory",
+    "loginshell",
+    "shadowmax",
+    "shadowexpire",
+    "createtimestamp",
+    "modifytimestamp",
+    "entryuuid",
+    "entrydn",
+    "creatorsname",
+    "hassubordinates",
+    "nsaccountlock",
+    "accountexpires",
+    "authtimestamp",
+    "pwdfailuretime",
+    "pwdaccountlockedtime",
+];
+
+/// Attributes computed by LLDAP, that are skipped without a warning when importing a group.
+const COMPUTED_GROUP_ATTRIBUTES: &[&str] = &[
+    "objectclass",
+    "createtimestamp",
+    "modifytimestamp",
+    "entryuuid",
+];
+
+/// A line of an imported LDIF file that couldn't be imported.
+#[derive(Debug, PartialEq, Eq)]
+pub struct LdifIssue {
+    pub line: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for LdifIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct LdifAttribute {
+    line: usize,
+    name: String,
+    value: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct LdifEntry {
+    line: usize,
+    dn: String,
+    attributes: Vec<LdifAttribute>,
+}
+
+/// Joins the folded lines and drops the comments. Returns the lines with the number of their
+/// first physical line.
+fn unfold_lines(input: &str) -> Vec<(usize, String)> {
+    let mut lines: Vec<(usize, String)> = Vec::new();
+    let mut in_comment = false;
+    for (index, line) in input.lines().enumerate() {
+        if let Some(continuation) = line.strip_prefix(' ') {
+            match lines.last_mut() {
+                _ if in_comment => continue,
+                Some((_, last)) if !last.is_empty() => {
+                    last.push_str(continuation);
+                    continue;
+                }
+                _ => (),
+            }
+        }
+        in_comment = line.starts_with('#');
+        if !in_comment {
+            lines.push((index + 1, line.to_owned()));
+        }
+    }
+    lines
+}
+
+fn parse_line(line: usize, content: &str) -> std::result::Result<LdifAttribute, LdifIssue> {
+    let issue = |message: String| LdifIssue { line, message };
+    let (name, value) = content
+        .split_once(':')
+        .ok_or_else(|| issue(format!(r#"Expected "attribute: value", got "{}""#, content)))?;
+    let value = if let Some(encoded) = value.strip_prefix(':') {
+        base64::engine::general_purpose::STANDARD
+            .decode(encoded.trim())
+            .map_err(|e| issue(format!("Invalid base64 value: {}", e)))?
+    } else if value.starts_with('<') {
+        return Err(issue("URL values are not supported".to_owned()));
+    } else {
+        value.trim_start_matches(' ').as_bytes().to_vec()
+    };
+    Ok(LdifAttribute {
+        line,
+        name: name.trim().to_owned(),
+        value,
+    })
+}
+
+/// Parses the entries of an LDIF file. The lines that can't be parsed are reported, and the
+/// rest of the entry is kept.
+fn parse_ldif(input: &str) -> (Vec<LdifEntry>, Vec<LdifIssue>) {
+    let mut lines = unfold_lines(input);
+    if lines
+        .first()
+        .map(|(_, l)| l.to_ascii_lowercase().starts_with("version:"))
+        .unwrap_or(false)
+    {
+        lines.remove(0);
+    }
+    let mut entries = Vec::new();
+    let mut issues = Vec::new();
+    for record in lines.split(|(_, l)| l.is_empty()) {
+        let mut attributes = record
+            .iter()
+            .filter_map(|(line, content)| {
+                parse_line(*line, content)
+                    .map_err(|issue| issues.push(issue))
+                    .ok()
+            })
+            .collect::<Vec<_>>()
+            .into_iter();
+        let (line, dn) = match attributes.next() {
+            None => continue,
+            Some(attribute) if attribute.name.eq_ignore_ascii_case("dn") => {
+                match String::from_utf8(attribute.value) {
+                    Ok(dn) => (attribute.line, dn),
+                    Err(e) => {
+                        issues.push(LdifIssue {
+                            line: attribute.line,
+                            message: format!("Invalid dn: {}", e),
+                        });
+                        continue;
+                    }
+                }
+            }
+            Some(attribute) => {
+                issues.push(LdifIssue {
+                    line: attribute.line,
+                    message: "Expected a dn at the start of the entry".to_owned(),
+                });
+                continue;
+            }
+        };
+        let attributes = attributes.collect::<Vec<_>>();
+        if let Some(change) = attributes
+            .iter()
+            .find(|a| a.name.eq_ignore_ascii_case("changetype"))
+        {
+            issues.push(LdifIssue {
+                line: change.line,
+                message: "Change records are not supported".to_owned(),
+            });
+            continue;
+        }
+        entries.push(LdifEntry {
+            line,
+            dn,
+            attributes,
+        });
+    }
+    (entries, issues)
+}
+
+fn has_object_class(entry: &LdifEntry, object_classes: &[&str]) -> bool {
+    entry
+        .attributes
+        .iter()
+        .filter(|a| a.name.eq_ignore_ascii_case("objectclass"))
+        .any(|a| {
+            object_classes
+                .iter()
+                .any(|c| c.as_bytes().eq_ignore_ascii_case(&a.value))
+        })
+}
+
+fn to_string(attribute: &LdifAttribute) -> std::result::Result<String, LdifIssue> {
+    String::from_utf8(attribute.value.clone()).map_err(|e| LdifIssue {
+        line: attribute.line,
+        message: format!("Invalid UTF-8 value for `{}`: {}", attribute.name, e),
+    })
+}
+
+#[derive(Debug)]
+struct ImportedUser {
+    line: usize,
+    user_id: UserId,
+    email: Option<String>,
+    display_name: Option<String>,
+    attributes: Vec<AttributeValue>,
+    password_hash: Option<String>,
+}
+
+#[derive(Debug)]
+struct ImportedGroup {
+    line: usize,
+    name: String,
+    members: Vec<(usize, UserId)>,
+}
+
+fn to_user(
+    entry: LdifEntry,
+    user_id: UserId,
+    schema: &Schema,
+    ldap_info: &LdapInfo,
+    issues: &mut Vec<LdifIssue>,
+) -> Option<ImportedUser> {
+    if !has_object_class(&entry, &["inetOrgPerson", "posixAccount"]) {
+        issues.push(LdifIssue {
+            line: entry.line,
+            message: "Expected an inetOrgPerson or posixAccount entry".to_owned(),
+        });
+        return None;
+    }
+    let mut email = None;
+    let mut display_name = None;
+    let mut password_hash = None;
+    // Custom attribute -> (first line, values).
+    let mut custom_values = BTreeMap::<String, (usize, Vec<Vec<u8>>)>::new();
+    for attribute in entry.attributes {
+        if attribute.name.eq_ignore_ascii_case("userpassword") {
+            let result = match password_hash {
+                Some(_) => Err(LdifIssue {
+                    line: attribute.line,
+                    message: format!("Extra value for single-valued `{}`", attribute.name),
+                }),
+                None => to_string(&attribute).and_then(|value| {
+                    check_imported_password_hash(&value)
+                        .map(|()| password_hash = Some(value))
+                        .map_err(|message| LdifIssue {
+                            line: attribute.line,
+                            message,
+                        })
+                }),
+            };
+            if let Err(issue) = result {
+                issues.push(issue);
+            }
+            continue;
+        }
+        let field = ldap_info.resolve_user_attribute(&attribute.name);
+        let custom_attribute = get_overriding_custom_attribute(&field)
+            .filter(|a| schema.user_attributes.get_attribute_type(a).is_some())
+            .map(str::to_owned);
+        let field_type = match custom_attribute {
+            Some(name) => UserFieldType::Attribute(name),
+            None if COMPUTED_USER_ATTRIBUTES.contains(&field.as_str()) => continue,
+            None => map_user_field_with_schema(&field, schema),
+        };
+        let result = match field_type {
+            UserFieldType::PrimaryField(UserColumn::UserId) => {
+                to_string(&attribute).and_then(|value| {
+                    if UserId::new(&value) == user_id {
+                        Ok(())
+                    } else {
+                        Err(LdifIssue {
+                            line: attribute.line,
+                            message: format!("The uid `{}` doesn't match the dn", value),
+                        })
+                    }
+                })
+            }
+            UserFieldType::PrimaryField(UserColumn::Email) if email.is_none() => {
+                to_string(&attribute).map(|value| email = Some(value))
+            }
+            UserFieldType::PrimaryField(UserColumn::Email)
+                if schema
+                    .user_attributes
+                    .get_attribute_type(MAIL_ALIASES_ATTRIBUTE)
+                    .is_some() =>
+            {
+                let values = custom_values
+                    .entry(MAIL_ALIASES_ATTRIBUTE.to_owned())
+                    .or_insert_with(|| (attribute.line, Vec::new()));
+                values.1.push(attribute.value);
+                Ok(())
+            }
+            UserFieldType::PrimaryField(UserColumn::DisplayName) if display_name.is_none() => {
+                to_string(&attribute).map(|value| display_name = Some(value))
+            }
+            UserFieldType::Attribute(name) => {
+                let values = custom_values
+                    .entry(name)
+                    .or_insert_with(|| (attribute.line, Vec::new()));
+                values.1.push(attribute.value);
+                Ok(())
+            }
+            UserFieldType::PrimaryField(UserColumn::Email | UserColumn::DisplayName) => {
+                Err(LdifIssue {
+                    line: attribute.line,
+                    message: format!("Extra value for single-valued `{}`", attribute.name),
+                })
+            }
+            UserFieldType::PrimaryField(_) | UserFieldType::NoMatch => Err(LdifIssue {
+                line: attribute.line,
+                message: format!("Unsupported attribute: `{}`", attribute.name),
+            }),
+        };
+        if let Err(issue) = result {
+            issues.push(issue);
+        }
+    }
+    let attributes = custom_values
+        .into_iter()
+        .filter_map(|(name, (line, values))| {
+            match convert_custom_attribute_values(&name, values, schema) {
+                Ok(value) => Some(AttributeValue { name, value }),
+                Err(e) => {
+                    issues.push(LdifIssue {
+                        line,
+                        message: e.message,
+                    });
+                    None
+                }
+            }
+        })
+        .collect();
+    Some(ImportedUser {
+        line: entry.line,
+        user_id,
+        email,
+        display_name,
+        attributes,
+        password_hash,
+    })
+}
+
+fn to_group(
+    entry: LdifEntry,
+    ldap_info: &LdapInfo,
+    issues: &mut Vec<LdifIssue>,
+) -> Option<ImportedGroup> {
+    if !has_object_class(&entry, &["groupOfNames", "groupOfUniqueNames"]) {
+        issues.push(LdifIssue {
+            line: entry.line,
+            message: "Expected a groupOfNames or groupOfUniqueNames entry".to_owned(),
+        });
+        return None;
+    }
+    // The name with its original case, the dn has already been validated.
+    let name = entry
+        .dn
+        .split(',')
+        .next()
+        .and_then(|rdn| rdn.split_once('='))
+        .map(|(_, name)| name.trim().to_owned())?;
+    let mut members = Vec::new();
+    for attribute in entry.attributes {
+        let field = attribute.name.to_ascii_lowercase();
+        let result = match field.as_str() {
+            _ if COMPUTED_GROUP_ATTRIBUTES.contains(&field.as_str()) => Ok(()),
+            "cn" | "uid" | "displayname" => to_string(&attribute).and_then(|value| {
+                if value.eq_ignore_ascii_case(&name) {
+                    Ok(())
+                } else {
+                    Err(LdifIssue {
+                        line: attribute.line,
+                        message: format!("The name `{}` doesn't match the dn", value),
+                    })
+                }
+            }),
+            "member" | "uniquemember" => to_string(&attribute).and_then(|dn| {
+                get_user_id_from_distinguished_name(&dn.to_ascii_lowercase(), ldap_info)
+                    .map(|user_id| members.push((attribute.line, user_id)))
+                    .map_err(|e| LdifIssue {
+                        line: attribute.line,
+                        message: format!("Invalid member: {}", e.message),
+                    })
+            }),
+            _ => Err(LdifIssue {
+                line: attribute.line,
+                message: format!("Unsupported attribute: `{}`", attribute.name),
+            }),
+        };
+        if let Err(issue) = result {
+            issues.push(issue);
+        }
+    }
+    Some(ImportedGroup {
+        line: entry.line,
+        name,
+        members,
+    })
+}
+
+async fn write_user<Backend: BackendHandler + LoginHandler>(
+    backend: &Backend,
+    user: ImportedUser,
+    exists: bool,
+) -> DomainResult<()> {
+    if !exists {
+        backend
+            .create_user(CreateUserRequest {
+                user_id: user.user_id.clone(),
+                email: user.email.clone().unwrap_or_default(),
+                ..Default::default()
+            })
+            .await?;
+    }
+    backend
+        .update_user(UpdateUserRequest {
+            user_id: user.user_id.clone(),
+            email: user.email,
+            display_name: user.display_name,
+            insert_attributes: user.attributes,
+            ..Default::default()
+        })
+        .await?;
+    if let Some(password_hash) = user.password_hash {
+        backend
+            .import_password_hash(&user.user_id, &password_hash)
+            .await?;
+    }
+    Ok(())
+}
+
+/// What an import did (or would do, for a dry run).
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    pub created_users: usize,
+    pub updated_users: usize,
+    pub created_groups: usize,
+    pub added_memberships: usize,
+    pub issues: Vec<LdifIssue>,
+}
+
+/// Creates or updates the users and the groups (under the configured organizational units, by
+/// default `ou=people` and `ou=groups`) of an LDIF file. The existing users and groups are
+/// matched by id and name, and the existing group members are kept.
+///
+/// The password hashes (`userPassword`) are kept until the first bind of the user, see
+/// `imported_password` for the supported schemes.
+///
+/// The lines that can't be imported are reported in the summary, and skipped. With `dry_run`,
+/// the file is only checked, and nothing is written.
+pub async fn import_ldif<Backend: BackendHandler + LoginHandler>(
+    backend: &Backend,
+    ldap_info: &LdapInfo,
+    input: &str,
+    dry_run: bool,
+) -> Result<ImportSummary> {
+    let schema = backend
+        .get_schema()
+        .await
+        .context("while reading the schema")?;
+    let (entries, mut issues) = parse_ldif(input);
+    let containers = [
+        ldap_info.base_dn_str.clone(),
+        ldap_info.people_dn(),
+        ldap_info.groups_dn(),
+    ];
+    let mut users = Vec::new();
+    let mut groups = Vec::new();
+    for entry in entries {
+        let dn = entry.dn.to_ascii_lowercase();
+        if containers.contains(&dn) {
+            continue;
+        }
+        if let Ok(user_id) = get_user_id_from_distinguished_name(&dn, ldap_info) {
+            users.extend(to_user(entry, user_id, &schema, ldap_info, &mut issues));
+        } else if get_group_id_from_distinguished_name(&dn, ldap_info).is_ok() {
+            groups.extend(to_group(entry, ldap_info, &mut issues));
+        } else {
+            issues.push(LdifIssue {
+                line: entry.line,
+                message: format!(
+                    r#"Unexpected dn "{}", expected "{}" or "{}""#,
+                    entry.dn,
+                    ldap_info.make_user_dn("id"),
+                    ldap_info.make_group_dn("name"),
+                ),
+            });
+        }
+    }
+
+    let mut summary = ImportSummary::default();
+    let mut known_users = backend
+        .list_users(None, false)
+        .await
+        .context("while listing the users")?
+        .into_iter()
+        .map(|u| u.user.user_id)
+        .collect::<HashSet<_>>();
+    for user in users {
+        let exists = known_users.contains(&user.user_id);
+        let (line, user_id) = (user.line, user.user_id.clone());
+        if !dry_run {
+            if let Err(e) = write_user(backend, user, exists).await {
+                issues.push(LdifIssue {
+                    line,
+                    message: format!("Could not import user `{}`: {:#}", user_id, e),
+                });
+                continue;
+            }
+        }
+        if exists {
+            summary.updated_users += 1;
+        } else {
+            summary.created_users += 1;
+            known_users.insert(user_id);
+        }
+    }
+
+    // Group name -> (id, members). The groups created in a dry run have no id.
+    let mut known_groups = backend
+        .list_groups(None)
+        .await
+        .context("while listing the groups")?
+        .into_iter()
+        .map(|g| {
+            (
+                g.display_name,
+                (Some(g.id), g.users.into_iter().collect::<HashSet<_>>()),
+            )
+        })
+        .collect::<HashMap<_, _>>();
+    for group in groups {
+        if !known_groups.contains_key(&group.name) {
+            let group_id = if dry_run {
+                None
+            } else {
+                match backend.create_group(&group.name).await {
+                    Ok(group_id) => Some(group_id),
+                    Err(e) => {
+                        issues.push(LdifIssue {
+                            line: group.line,
+                            message: format!("Could not create group `{}`: {:#}", group.name, e),
+                        });
+                        continue;
+                    }
+                }
+            };
+            summary.created_groups += 1;
+            known_groups.insert(group.name.clone(), (group_id, HashSet::new()));
+        }
+        let (group_id, members) = known_groups
+            .get_mut(&group.name)
+            .expect("The group was just inserted");
+        for (line, user_id) in group.members {
+            if !known_users.contains(&user_id) {
+                issues.push(LdifIssue {
+                    line,
+                    message: format!("Unknown user `{}`", user_id),
+                });
+                continue;
+            }
+            if members.contains(&user_id) {
+                continue;
+            }
+            if let Some(group_id) = group_id {
+                if let Err(e) = backend.add_user_to_group(&user_id, *group_id).await {
+                    issues.push(LdifIssue {
+                        line,
+                        message: format!("Could not add `{}` to the group: {:#}", user_id, e),
+                    });
+                    continue;
+                }
+            }
+            summary.added_memberships += 1;
+            members.insert(user_id);
+        }
+    }
+    issues.sort_by_key(|issue| issue.line);
+    summary.issues = issues;
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        handler::UserBackendHandler,
+        sql_backend_handler::{tests::*, SqlBackendHandler},
+        types::Serialized,
+    };
+
+    fn entry_to_string(entry: &LdapSearchResultEntry) -> String {
+        let mut out = Vec::new();
+        write_entry(&mut out, entry).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_safe_string() {
+        assert!(is_safe_string(b""));
+        assert!(is_safe_string(b"bob@example.com"));
+        assert!(is_safe_string(b"a: b"));
+        assert!(!is_safe_string(b" leading space"));
+        assert!(!is_safe_string(b"trailing space "));
+        assert!(!is_safe_string(b":colon"));
+        assert!(!is_safe_string(b"<url"));
+        assert!(!is_safe_string(b"new\nline"));
+        assert!(!is_safe_string("Bär".as_bytes()));
+        assert!(!is_safe_string(&[0xFF, 0xD8, 0xFF]));
+    }
+
+    #[test]
+    fn test_write_entry() {
+        let entry = LdapSearchResultEntry {
+            dn: "uid=bob,ou=people,dc=example,dc=com".to_owned(),
+            attributes: vec![
+                LdapPartialAttribute {
+                    atype: "objectclass".to_owned(),
+                    vals: vec![b"inetOrgPerson".to_vec(), b"person".to_vec()],
+                },
+                LdapPartialAttribute {
+                    atype: "cn".to_owned(),
+                    vals: vec!["Bär".as_bytes().to_vec()],
+                },
+                LdapPartialAttribute {
+                    atype: "jpegPhoto".to_owned(),
+                    vals: vec![vec![0xFF, 0xD8, 0xFF, 0xD9]],
+                },
+            ],
+        };
+        assert_eq!(
+            entry_to_string(&entry),
+            "dn: uid=bob,ou=people,dc=example,dc=com\n\
+             objectclass: inetOrgPerson\n\
+             objectclass: person\n\
+             cn:: QsOkcg==\n\
+             jpegPhoto:: /9j/2Q==\n\
+             \n"
+        );
+    }
+
+    #[test]
+    fn test_write_long_line() {
+        let entry = LdapSearchResultEntry {
+            dn: "uid=bob,ou=people,dc=example,dc=com".to_owned(),
+            attributes: vec![LdapPartialAttribute {
+                atype: "description".to_owned(),
+                vals: vec![b"a".repeat(150)],
+            }],
+        };
+        let output = entry_to_string(&entry);
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines[1], format!("description: {}", "a".repeat(63)));
+        assert_eq!(lines[2], format!(" {}", "a".repeat(75)));
+        assert_eq!(lines[3], format!(" {}", "a".repeat(12)));
+        assert!(lines.iter().all(|l| l.len() <= MAX_LINE_LENGTH));
+    }
+
+    #[tokio::test]
+    async fn test_export_ldif() {
+        let config = get_default_config();
+        let handler = SqlBackendHandler::new(config.clone(), get_initialized_db().await);
+        insert_user_no_password(&handler, "bob").await;
+        let group = insert_group(&handler, "Best Group").await;
+        insert_membership(&handler, group, "bob").await;
+
+        let mut out = Vec::new();
+        export_ldif(&handler, &LdapInfo::new(&config), &mut out)
+            .await
+            .unwrap();
+        let output = String::from_utf8(out).unwrap();
+
+        assert!(output.starts_with("version: 1\n\ndn: ou=people,dc=example,dc=com\n"));
+        assert!(output.contains("\ndn: uid=bob,ou=people,dc=example,dc=com\n"));
+        assert!(output.contains("\nmail: bob@bob.bob\n"));
+        assert!(output.contains("\ngivenname: first bob\n"));
+        assert!(output.contains(
+            "\ndn: cn=Best Group,ou=groups,dc=example,dc=com\n\
+             objectclass: groupOfUniqueNames\n\
+             cn: Best Group\n\
+             uniquemember: uid=bob,ou=people,dc=example,dc=com\n"
+        ));
+    }
+
+    #[test]
+    fn test_parse_ldif() {
+        let input = "version: 1\n\
+                     # A comment\n \
+                      folded into the comment\n\
+                     dn: uid=bob,ou=people,dc=example,dc=com\n\
+                     cn: Bob\n \
+                      Bobbersson\n\
+                     sn:: QsOkcg==\n\
+                     invalid line\n\
+                     \n\
+                     \n\
+                     dn: cn=group,ou=groups,dc=example,dc=com\n\
+                     changetype: delete\n";
+        let (entries, issues) = parse_ldif(input);
+        assert_eq!(
+            entries,
+            vec![LdifEntry {
+                line: 4,
+                dn: "uid=bob,ou=people,dc=example,dc=com".to_owned(),
+                attributes: vec![
+                    LdifAttribute {
+                        line: 5,
+                        name: "cn".to_owned(),
+                        value: b"BobBobbersson".to_vec(),
+                    },
+                    LdifAttribute {
+                        line: 7,
+                        name: "sn".to_owned(),
+                        value: "Bär".as_bytes().to_vec(),
+                    },
+                ],
+            }]
+        );
+        assert_eq!(
+            issues,
+            vec![
+                LdifIssue {
+                    line: 8,
+                    message: r#"Expected "attribute: value", got "invalid line""#.to_owned(),
+                },
+                LdifIssue {
+                    line: 12,
+                    message: "Change records are not supported".to_owned(),
+                },
+            ]
+        );
+    }
+
+    async fn get_handler() -> SqlBackendHandler {
+        let handler = SqlBackendHandler::new(get_default_config(), get_initialized_db().await);
+        insert_user_no_password(&handler, "bob").await;
+        handler
+    }
+
+    const IMPORT_LDIF: &str = "dn: ou=people,dc=example,dc=com\n\
+                               objectClass: organizationalUnit\n\
+                               ou: people\n\
+                               \n\
+                               dn: uid=bob,ou=people,dc=example,dc=com\n\
+                               objectClass: inetOrgPerson\n\
+                               uid: bob\n\
+                               mail: bob@example.com\n\
+                               uidNumber: 1000\n\
+                               \n\
+                               dn: uid=jane,ou=people,dc=example,dc=com\n\
+                               objectClass: inetOrgPerson\n\
+                               uid: jane\n\
+                               cn: Jane Doe\n\
+                               givenName: Jane\n\
+                               mail: jane@example.com\n\
+                               userPassword: {SSHA}yrht1iYXEIkejLVu42JWkadd80RzYWx0c2FsdA==\n\
+                               \n\
+                               dn: cn=Admins,ou=groups,dc=example,dc=com\n\
+                               objectClass: groupOfNames\n\
+                               cn: Admins\n\
+                               member: uid=jane,ou=people,dc=example,dc=com\n\
+                               member: uid=john,ou=people,dc=example,dc=com\n\
+                               \n\
+                               dn: cn=printer,dc=example,dc=com\n\
+                               objectClass: device\n";
+
+    #[tokio::test]
+    async fn test_import_ldif() {
+        let handler = get_handler().await;
+        let ldap_info = LdapInfo::new(&get_default_config());
+
+        let summary = import_ldif(&handler, &ldap_info, IMPORT_LDIF, false)
+            .await
+            .unwrap();
+
+        assert_eq!(summary.created_users, 1);
+        assert_eq!(summary.updated_users, 1);
+        assert_eq!(summary.created_groups, 1);
+        assert_eq!(summary.added_memberships, 1);
+        assert_eq!(
+            summary
+                .issues
+                .iter()
+                .map(|issue| issue.line)
+                .collect::<Vec<_>>(),
+            vec![23, 25]
+        );
+        let bob = handler.get_user_details(&UserId::new("bob")).await.unwrap();
+        assert_eq!(bob.email, "bob@example.com");
+        let jane = handler
+            .get_user_details(&UserId::new("jane"))
+            .await
+            .unwrap();
+        assert_eq!(jane.display_name.as_deref(), Some("Jane Doe"));
+        assert_eq!(
+            jane.attributes,
+            vec![AttributeValue {
+                name: "first_name".to_owned(),
+                value: Serialized::from("Jane"),
+            }]
+        );
+        // The imported password hash.
+        handler
+            .bind(crate::domain::handler::BindRequest {
+                name: UserId::new("jane"),
+                password: "password".to_owned(),
+            })
+            .await
+            .unwrap();
+        let groups = handler.list_groups(None).await.unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].display_name, "Admins");
+        assert_eq!(groups[0].users, vec![UserId::new("jane")]);
+    }
+
+    #[tokio::test]
+    async fn test_import_ldif_dry_run() {
+        let handler = get_handler().await;
+        let ldap_info = LdapInfo::new(&get_default_config());
+
+        let summary = import_ldif(&handler, &ldap_info, IMPORT_LDIF, true)
+            .await
+            .unwrap();
+
+        assert_eq!(summary.created_users, 1);
+        assert_eq!(summary.created_groups, 1);
+        assert_eq!(summary.added_memberships, 1);
+        assert_eq!(summary.issues.len(), 2);
+        assert_eq!(handler.list_users(None, false).await.unwrap().len(), 1);
+        assert!(handler.list_groups(None).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_export_then_import() {
+        let config = get_default_config();
+        let ldap_info = LdapInfo::new(&config);
+        let source = get_handler().await;
+        let group = insert_group(&source, "Best Group").await;
+        insert_membership(&source, group, "bob").await;
+        let mut out = Vec::new();
+        export_ldif(&source, &ldap_info, &mut out).await.unwrap();
+
+        let target = SqlBackendHandler::new(config, get_initialized_db().await);
+        let summary = import_ldif(
+            &target,
+            &ldap_info,
+            std::str::from_utf8(&out).unwrap(),
+            false,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            summary,
+            ImportSummary {
+                created_users: 1,
+                created_groups: 1,
+                added_memberships: 1,
+                ..Default::default()
+            }
+        );
+        let bob = target.get_user_details(&UserId::new("bob")).await.unwrap();
+        assert_eq!(bob.email, "bob@bob.bob");
+        assert_eq!(bob.display_name.as_deref(), Some("display bob"));
+    }
+}
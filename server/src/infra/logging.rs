@@ -1,4 +1,4 @@
-use crate::infra::configuration::Configuration;
+use crate::infra::configuration::{Configuration, LogFormat};
 use actix_web::{
     dev::{ServiceRequest, ServiceResponse},
     Error,
@@ -42,10 +42,18 @@ pub fn init(config: &Configuration) -> anyhow::Result<()> {
             "sqlx=warn,reqwest=warn,info"
         })
     });
-    tracing_subscriber::registry()
-        .with(env_filter)
-        .with(tracing_forest::ForestLayer::default())
-        .init();
+    let registry = tracing_subscriber::registry().with(env_filter);
+    match config.log_format {
+        LogFormat::Pretty => registry.with(tracing_forest::ForestLayer::default()).init(),
+        LogFormat::Json => registry
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .json()
+                    .with_current_span(true)
+                    .with_span_list(true),
+            )
+            .init(),
+    }
     Ok(())
 }
 
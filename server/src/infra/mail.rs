@@ -1,28 +1,146 @@
 use crate::infra::{cli::SmtpEncryption, configuration::MailOptions};
-use anyhow::{anyhow, Ok, Result};
+use anyhow::{anyhow, Context, Result};
+use handlebars::Handlebars;
 use lettre::{
-    message::Mailbox, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
-    AsyncTransport, Message, Tokio1Executor,
+    message::{Mailbox, MultiPart, SinglePart},
+    transport::smtp::{authentication::Credentials, PoolConfig},
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
 };
-use tracing::debug;
+use std::{
+    collections::{HashMap, VecDeque},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::{mpsc, Semaphore};
+use tracing::{debug, error, warn};
 
-async fn send_email(
+type Transport = AsyncSmtpTransport<Tokio1Executor>;
+
+/// Maximum number of emails waiting to be sent.
+const QUEUE_SIZE: usize = 256;
+const MAX_ATTEMPTS: u32 = 3;
+/// Delay before the first retry, doubled after each attempt.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(2);
+
+const PASSWORD_RESET_TEMPLATE: &str = "password_reset";
+const DEFAULT_PASSWORD_RESET_SUBJECT: &str = "[LLDAP] Password reset requested";
+const DEFAULT_PASSWORD_RESET_TEXT: &str = "Hello {{display_name}},
+This email has been sent to you in order to validate your identity.
+If you did not initiate the process your credentials might have been
+compromised. You should reset your password and contact an administrator.
+
+To reset your password please visit the following URL: {{reset_link}}
+The link expires in {{expiry}}.
+
+Please contact an administrator if you did not initiate the process.";
+
+/// The templates of the emails, in the handlebars syntax.
+///
+/// Each email has a subject (`<name>.subject.hbs`), a plain text body (`<name>.txt.hbs`) and an
+/// optional HTML body (`<name>.html.hbs`), read from the templates directory. The missing files
+/// fall back to the built-in templates, which have no HTML body.
+pub struct EmailTemplates {
+    /// Subjects and plain text bodies, rendered without escaping.
+    text: Handlebars<'static>,
+    html: Handlebars<'static>,
+}
+
+struct RenderedEmail {
+    subject: String,
+    text: String,
+    html: Option<String>,
+}
+
+fn read_template(
+    templates_dir: Option<&Path>,
+    name: &str,
+    extension: &str,
+) -> Result<Option<String>> {
+    let path = match templates_dir {
+        None => return Ok(None),
+        Some(dir) => dir.join(format!("{}.{}.hbs", name, extension)),
+    };
+    if !path.exists() {
+        debug!("No template at {}, using the default", path.display());
+        return Ok(None);
+    }
+    std::fs::read_to_string(&path)
+        .map(Some)
+        .with_context(|| format!("while reading the template {}", path.display()))
+}
+
+impl EmailTemplates {
+    pub fn load(templates_dir: Option<&str>) -> Result<Self> {
+        let templates_dir = templates_dir.map(PathBuf::from);
+        let templates_dir = templates_dir.as_deref();
+        let mut text = Handlebars::new();
+        text.register_escape_fn(handlebars::no_escape);
+        let mut html = Handlebars::new();
+        for (extension, default) in [
+            ("subject", DEFAULT_PASSWORD_RESET_SUBJECT),
+            ("txt", DEFAULT_PASSWORD_RESET_TEXT),
+        ] {
+            let template = read_template(templates_dir, PASSWORD_RESET_TEMPLATE, extension)?;
+            text.register_template_string(
+                &format!("{}.{}", PASSWORD_RESET_TEMPLATE, extension),
+                template.as_deref().unwrap_or(default),
+            )
+            .with_context(|| {
+                format!(
+                    "while parsing the template {}.{}",
+                    PASSWORD_RESET_TEMPLATE, extension
+                )
+            })?;
+        }
+        if let Some(template) = read_template(templates_dir, PASSWORD_RESET_TEMPLATE, "html")? {
+            html.register_template_string(&format!("{}.html", PASSWORD_RESET_TEMPLATE), template)
+                .with_context(|| {
+                    format!(
+                        "while parsing the template {}.html",
+                        PASSWORD_RESET_TEMPLATE
+                    )
+                })?;
+        }
+        Ok(Self { text, html })
+    }
+
+    fn render(&self, name: &str, data: &serde_json::Value) -> Result<RenderedEmail> {
+        let html_name = format!("{}.html", name);
+        Ok(RenderedEmail {
+            subject: self
+                .text
+                .render(&format!("{}.subject", name), data)?
+                .trim()
+                .to_owned(),
+            text: self.text.render(&format!("{}.txt", name), data)?,
+            html: if self.html.has_template(&html_name) {
+                Some(self.html.render(&html_name, data)?)
+            } else {
+                None
+            },
+        })
+    }
+}
+
+fn make_email(
     to: Mailbox,
     subject: &str,
     body: String,
+    html_body: Option<String>,
     options: &MailOptions,
     server_url: &url::Url,
-) -> Result<()> {
+) -> Result<Message> {
     let from = options
         .from
         .clone()
         .unwrap_or_else(|| "LLDAP <nobody@lldap>".parse().unwrap());
     let reply_to = options.reply_to.clone().unwrap_or_else(|| from.clone());
     debug!(
-        "Sending email to '{}' as '{}' via '{}'@'{}':'{}'",
+        "Preparing email to '{}' as '{}' via '{}'@'{}':'{}'",
         &to, &from, &options.user, &options.server, options.port
     );
-    let email = Message::builder()
+    let builder = Message::builder()
         .message_id(Some(format!(
             "<{}@{}>",
             uuid::Uuid::new_v1(
@@ -34,20 +152,24 @@ async fn send_email(
         .from(from)
         .reply_to(reply_to)
         .to(to)
-        .subject(subject)
-        .singlepart(
-            lettre::message::SinglePart::builder()
+        .subject(subject);
+    Ok(match html_body {
+        None => builder.singlepart(
+            SinglePart::builder()
                 .header(lettre::message::header::ContentType::TEXT_PLAIN)
                 .body(body),
-        )?;
+        )?,
+        Some(html_body) => builder.multipart(MultiPart::alternative_plain_html(body, html_body))?,
+    })
+}
+
+/// Builds the transport. The connections are pooled and reused across the emails sent with the
+/// same transport (or its clones).
+fn make_transport(options: &MailOptions) -> Result<Transport> {
     let mut mailer = match options.smtp_encryption {
-        SmtpEncryption::None => {
-            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&options.server)
-        }
-        SmtpEncryption::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&options.server)?,
-        SmtpEncryption::StartTls => {
-            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&options.server)?
-        }
+        SmtpEncryption::None => Transport::builder_dangerous(&options.server),
+        SmtpEncryption::Tls => Transport::relay(&options.server)?,
+        SmtpEncryption::StartTls => Transport::starttls_relay(&options.server)?,
     };
     if options.user.as_str() != "" {
         let creds = Credentials::new(
@@ -56,8 +178,15 @@ async fn send_email(
         );
         mailer = mailer.credentials(creds)
     }
+    Ok(mailer
+        .port(options.port)
+        .timeout(Some(Duration::from_secs(options.timeout_seconds)))
+        .pool_config(PoolConfig::new().max_size(options.pool_size))
+        .build())
+}
 
-    if let Err(e) = mailer.port(options.port).build().send(email).await {
+async fn send_with_transport(transport: &Transport, email: Message) -> Result<()> {
+    if let Err(e) = transport.send(email).await {
         if e.to_string().contains("CorruptMessage") {
             Err(anyhow!("CorruptMessage returned by lettre, this usually means the SMTP encryption setting is wrong.").context(e))
         } else {
@@ -68,38 +197,212 @@ async fn send_email(
     }
 }
 
-pub async fn send_password_reset_email(
+async fn send_email(
+    to: Mailbox,
+    subject: &str,
+    body: String,
+    options: &MailOptions,
+    server_url: &url::Url,
+) -> Result<()> {
+    let email = make_email(to, subject, body, None, options, server_url)?;
+    send_with_transport(&make_transport(options)?, email).await
+}
+
+/// Limits the number of password reset emails sent to the same address over a sliding window.
+struct RateLimiter {
+    /// 0 for no limit.
+    limit: usize,
+    window: Duration,
+    sent: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl RateLimiter {
+    fn new(limit: usize, window: Duration) -> Self {
+        Self {
+            limit,
+            window,
+            sent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records an email sent to the address, unless it reached the limit.
+    fn try_acquire(&self, address: &str, now: Instant) -> bool {
+        if self.limit == 0 {
+            return true;
+        }
+        let mut sent = self.sent.lock().unwrap();
+        // Forget the emails outside of the window, so that the map doesn't grow forever.
+        sent.retain(|_, times| {
+            while matches!(times.front(), Some(t) if now.duration_since(*t) >= self.window) {
+                times.pop_front();
+            }
+            !times.is_empty()
+        });
+        let times = sent.entry(address.to_lowercase()).or_default();
+        if times.len() >= self.limit {
+            return false;
+        }
+        times.push_back(now);
+        true
+    }
+}
+
+/// Queues the emails, to be sent in the background, so that the requests don't wait for the
+/// SMTP server.
+#[derive(Clone)]
+pub struct MailSender {
+    sender: mpsc::Sender<Message>,
+    options: MailOptions,
+    templates: Arc<EmailTemplates>,
+    password_reset_limiter: Arc<RateLimiter>,
+}
+
+impl MailSender {
+    /// Starts the task sending the emails, if the password reset is enabled.
+    pub fn start(options: MailOptions) -> Result<Option<Self>> {
+        if !options.enable_password_reset {
+            return Ok(None);
+        }
+        let templates = EmailTemplates::load(options.templates_dir.as_deref())
+            .context("while loading the email templates")?;
+        let transport = make_transport(&options).context("while setting up the SMTP transport")?;
+        let (sender, receiver) = mpsc::channel(QUEUE_SIZE);
+        tokio::spawn(send_queued_emails(
+            transport,
+            options.pool_size as usize,
+            receiver,
+        ));
+        Ok(Some(Self {
+            sender,
+            options,
+            templates: Arc::new(templates),
+            password_reset_limiter: Arc::new(RateLimiter::new(
+                options.password_reset_rate_limit as usize,
+                Duration::from_secs(options.password_reset_rate_limit_window_seconds),
+            )),
+        }))
+    }
+
+    /// Whether another password reset email can be sent to the address, and if so, counts it
+    /// against the rate limit.
+    pub fn allow_password_reset_email(&self, to: &str) -> bool {
+        self.password_reset_limiter.try_acquire(to, Instant::now())
+    }
+
+    /// Never blocks: fails if the queue is full.
+    pub fn queue_password_reset_email(
+        &self,
+        username: &str,
+        to: &str,
+        token: &str,
+        server_url: &url::Url,
+    ) -> Result<()> {
+        let email = make_password_reset_email(
+            username,
+            to,
+            token,
+            server_url,
+            &self.options,
+            &self.templates,
+        )?;
+        self.sender
+            .try_send(email)
+            .map_err(|e| anyhow!("Could not queue the email: {}", e))
+    }
+}
+
+async fn send_queued_emails(
+    transport: Transport,
+    max_concurrent_sends: usize,
+    mut receiver: mpsc::Receiver<Message>,
+) {
+    // At most one email per pooled connection at a time.
+    let permits = Arc::new(Semaphore::new(max_concurrent_sends.max(1)));
+    while let Some(email) = receiver.recv().await {
+        let permit = permits
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("The semaphore is never closed");
+        let transport = transport.clone();
+        tokio::spawn(async move {
+            send_with_retries(&transport, email).await;
+            drop(permit);
+        });
+    }
+}
+
+async fn send_with_retries(transport: &Transport, email: Message) {
+    let to = email
+        .envelope()
+        .to()
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ");
+    let mut backoff = INITIAL_BACKOFF;
+    for attempt in 1..=MAX_ATTEMPTS {
+        match send_with_transport(transport, email.clone()).await {
+            Ok(()) => {
+                debug!("Email sent to {}", to);
+                return;
+            }
+            Err(e) => warn!(
+                "Error sending email to {} (attempt {}/{}): {:#}",
+                to, attempt, MAX_ATTEMPTS, e
+            ),
+        }
+        if attempt < MAX_ATTEMPTS {
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+    }
+    error!("Giving up on sending email to {}", to);
+}
+
+fn make_password_reset_email(
     username: &str,
     to: &str,
     token: &str,
     server_url: &url::Url,
     options: &MailOptions,
-) -> Result<()> {
+    templates: &EmailTemplates,
+) -> Result<Message> {
     let to = to.parse()?;
     let mut reset_url = server_url.clone();
     reset_url
         .path_segments_mut()
         .unwrap()
         .extend(["reset-password", "step2", token]);
-    let body = format!(
-        "Hello {},
-This email has been sent to you in order to validate your identity.
-If you did not initiate the process your credentials might have been
-compromised. You should reset your password and contact an administrator.
-
-To reset your password please visit the following URL: {}
-
-Please contact an administrator if you did not initiate the process.",
-        username, reset_url
-    );
-    send_email(
+    let email = templates.render(
+        PASSWORD_RESET_TEMPLATE,
+        &serde_json::json!({
+            "display_name": username,
+            "reset_link": reset_url.as_str(),
+            "expiry": format!("{} minutes", options.reset_token_validity_minutes),
+        }),
+    )?;
+    make_email(
         to,
-        "[LLDAP] Password reset requested",
-        body,
+        &email.subject,
+        email.text,
+        email.html,
         options,
         server_url,
     )
-    .await
+}
+
+/// Sends the email right away, without queuing it.
+pub async fn send_password_reset_email(
+    username: &str,
+    to: &str,
+    token: &str,
+    server_url: &url::Url,
+    options: &MailOptions,
+    templates: &EmailTemplates,
+) -> Result<()> {
+    let email = make_password_reset_email(username, to, token, server_url, options, templates)?;
+    send_with_transport(&make_transport(options)?, email).await
 }
 
 pub async fn send_test_email(to: Mailbox, options: &MailOptions) -> Result<()> {
@@ -112,3 +415,101 @@ pub async fn send_test_email(to: Mailbox, options: &MailOptions) -> Result<()> {
     )
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body_of(email: &Message) -> String {
+        String::from_utf8(email.formatted()).unwrap()
+    }
+
+    #[test]
+    fn test_make_password_reset_email() {
+        let server_url = url::Url::parse("https://ldap.example.com").unwrap();
+        let templates = EmailTemplates::load(None).unwrap();
+        let email = make_password_reset_email(
+            "Bob",
+            "bob@example.com",
+            "token",
+            &server_url,
+            &MailOptions::default(),
+            &templates,
+        )
+        .unwrap();
+        assert_eq!(email.envelope().to(), &["bob@example.com".parse().unwrap()]);
+        let body = body_of(&email);
+        assert!(body.contains("Subject: [LLDAP] Password reset requested"));
+        assert!(body.contains("Hello Bob,"));
+        assert!(body.contains("https://ldap.example.com/reset-password/step2/token"));
+        assert!(body.contains("The link expires in 10 minutes."));
+        assert!(!body.contains("text/html"));
+        make_password_reset_email(
+            "Bob",
+            "not an email",
+            "token",
+            &server_url,
+            &MailOptions::default(),
+            &templates,
+        )
+        .unwrap_err();
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(60));
+        let start = Instant::now();
+        assert!(limiter.try_acquire("bob@example.com", start));
+        assert!(limiter.try_acquire("Bob@Example.com", start + Duration::from_secs(10)));
+        assert!(!limiter.try_acquire("bob@example.com", start + Duration::from_secs(20)));
+        assert!(limiter.try_acquire("alice@example.com", start + Duration::from_secs(20)));
+        // The first email is out of the window.
+        assert!(limiter.try_acquire("bob@example.com", start + Duration::from_secs(60)));
+        assert!(!limiter.try_acquire("bob@example.com", start + Duration::from_secs(65)));
+        assert!(limiter.try_acquire("bob@example.com", start + Duration::from_secs(130)));
+        assert_eq!(limiter.sent.lock().unwrap().len(), 1);
+
+        let unlimited = RateLimiter::new(0, Duration::from_secs(60));
+        for _ in 0..10 {
+            assert!(unlimited.try_acquire("bob@example.com", start));
+        }
+    }
+
+    #[test]
+    fn test_custom_templates() {
+        let dir = std::env::temp_dir().join(format!("lldap-templates-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("password_reset.subject.hbs"),
+            "Reset for {{display_name}}\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("password_reset.html.hbs"),
+            "<p>Hi {{display_name}}, <a href=\"{{reset_link}}\">reset</a></p>",
+        )
+        .unwrap();
+        let templates = EmailTemplates::load(dir.to_str()).unwrap();
+        let email = templates
+            .render(
+                PASSWORD_RESET_TEMPLATE,
+                &serde_json::json!({
+                    "display_name": "<Bob>",
+                    "reset_link": "https://ldap.example.com/reset",
+                    "expiry": "10 minutes",
+                }),
+            )
+            .unwrap();
+        assert_eq!(email.subject, "Reset for <Bob>");
+        // The plain text body falls back to the default.
+        assert!(email.text.starts_with("Hello <Bob>,"));
+        assert_eq!(
+            email.html.unwrap(),
+            "<p>Hi &lt;Bob&gt;, <a href=\"https://ldap.example.com/reset\">reset</a></p>"
+        );
+
+        std::fs::write(dir.join("password_reset.txt.hbs"), "Hello {{#if}}").unwrap();
+        EmailTemplates::load(dir.to_str()).unwrap_err();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,19 +1,25 @@
 pub mod access_control;
 pub mod auth_service;
+pub mod backup;
 pub mod cli;
 pub mod configuration;
+pub mod csv_import;
 pub mod db_cleaner;
 pub mod graphql;
 pub mod healthcheck;
 pub mod jwt_sql_tables;
+pub mod ldap_codec;
 pub mod ldap_handler;
 pub mod ldap_server;
+pub mod ldif;
 pub mod logging;
 pub mod mail;
+pub mod oidc;
 pub mod schema;
 pub mod sql_backend_handler;
 pub mod tcp_backend_handler;
 pub mod tcp_server;
+pub mod webhooks;
 
 #[cfg(test)]
 pub mod test_utils;
@@ -0,0 +1,867 @@
+//! OpenID Connect provider, with the authorization code flow and PKCE.
+//!
+//! The users log in through the web UI: the authorization endpoint relies on its session cookies,
+//! and sends the users that aren't logged in to the login page, which brings them back here.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use actix_web_httpauth::extractors::{basic::BasicAuth, bearer::BearerAuth};
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use rsa::{
+    pkcs1v15::{Signature, SigningKey, VerifyingKey},
+    pkcs8::{DecodePrivateKey, EncodePrivateKey, LineEnding},
+    signature::{Keypair, SignatureEncoding, Signer, Verifier},
+    traits::PublicKeyParts,
+    RsaPrivateKey,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use tracing::{debug, info, instrument};
+use url::Url;
+
+use crate::{
+    domain::{
+        error::DomainError,
+        handler::BackendHandler,
+        types::{GroupDetails, User, UserId},
+    },
+    infra::{
+        access_control::UserReadableBackendHandler,
+        auth_service::{check_if_token_is_valid, get_refresh_token},
+        configuration::{write_to_readonly_file, OidcClient, OidcOptions},
+        tcp_backend_handler::TcpBackendHandler,
+        tcp_server::{error_to_http_response, AppState, TcpError, TcpResult},
+    },
+};
+
+const KEY_BITS: usize = 2048;
+/// The codes are exchanged right after the redirection, they don't need to last long.
+const CODE_LIFETIME_SECONDS: i64 = 60;
+/// JWT type of the access tokens (RFC 9068), to tell them apart from the ID tokens.
+const ACCESS_TOKEN_TYPE: &str = "at+jwt";
+
+struct AuthorizationCode {
+    user_id: UserId,
+    client_id: String,
+    redirect_uri: Url,
+    code_challenge: String,
+    nonce: Option<String>,
+    scope: String,
+    expires_at: DateTime<Utc>,
+}
+
+struct SigningKeys {
+    signing_key: SigningKey<Sha256>,
+    verifying_key: VerifyingKey<Sha256>,
+    key_id: String,
+    jwk: Value,
+}
+
+impl SigningKeys {
+    fn new(private_key: RsaPrivateKey) -> Self {
+        let public_key = private_key.to_public_key();
+        let modulus = public_key.n().to_bytes_be();
+        let key_id = data_encoding::HEXLOWER.encode(&Sha256::digest(&modulus)[..8]);
+        let jwk = json!({
+            "kty": "RSA",
+            "use": "sig",
+            "alg": "RS256",
+            "kid": key_id,
+            "n": URL_SAFE_NO_PAD.encode(modulus),
+            "e": URL_SAFE_NO_PAD.encode(public_key.e().to_bytes_be()),
+        });
+        let signing_key = SigningKey::<Sha256>::new(private_key);
+        Self {
+            verifying_key: signing_key.verifying_key(),
+            signing_key,
+            key_id,
+            jwk,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct JwtHeader {
+    alg: String,
+    typ: String,
+    kid: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AccessTokenClaims {
+    iss: String,
+    sub: String,
+    aud: String,
+    iat: i64,
+    exp: i64,
+    scope: String,
+}
+
+/// State shared by the HTTP workers.
+#[derive(Clone)]
+pub struct OidcProvider {
+    issuer: String,
+    options: OidcOptions,
+    keys: Arc<SigningKeys>,
+    codes: Arc<Mutex<HashMap<String, AuthorizationCode>>>,
+}
+
+impl OidcProvider {
+    /// Loads (or generates) the signing key. Returns `None` if there are no clients configured.
+    pub fn new(options: &OidcOptions, http_url: &Url) -> Result<Option<Self>> {
+        if options.clients.is_empty() {
+            return Ok(None);
+        }
+        let private_key = load_or_generate_key(&options.key_file)?;
+        Ok(Some(Self::with_key(options.clone(), http_url, private_key)))
+    }
+
+    fn with_key(options: OidcOptions, http_url: &Url, private_key: RsaPrivateKey) -> Self {
+        Self {
+            issuer: http_url.as_str().trim_end_matches('/').to_owned(),
+            options,
+            keys: Arc::new(SigningKeys::new(private_key)),
+            codes: Arc::default(),
+        }
+    }
+
+    fn get_client(&self, client_id: &str) -> Option<&OidcClient> {
+        self.options
+            .clients
+            .iter()
+            .find(|client| client.client_id == client_id)
+    }
+
+    fn create_code(&self, code: AuthorizationCode) -> String {
+        let mut bytes = [0; 32];
+        rand::rngs::OsRng.fill_bytes(&mut bytes);
+        let key = URL_SAFE_NO_PAD.encode(bytes);
+        let mut codes = self.codes.lock().unwrap();
+        let now = Utc::now();
+        codes.retain(|_, code| code.expires_at > now);
+        codes.insert(key.clone(), code);
+        key
+    }
+
+    /// The codes can only be used once.
+    fn take_code(&self, code: &str) -> Option<AuthorizationCode> {
+        self.codes
+            .lock()
+            .unwrap()
+            .remove(code)
+            .filter(|code| code.expires_at > Utc::now())
+    }
+
+    fn sign_jwt(&self, typ: &str, claims: &impl Serialize) -> String {
+        let header = JwtHeader {
+            alg: "RS256".to_owned(),
+            typ: typ.to_owned(),
+            kid: self.keys.key_id.clone(),
+        };
+        let signing_input = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header).unwrap()),
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims).unwrap())
+        );
+        let signature = self.keys.signing_key.sign(signing_input.as_bytes());
+        format!(
+            "{}.{}",
+            signing_input,
+            URL_SAFE_NO_PAD.encode(signature.to_bytes())
+        )
+    }
+
+    fn verify_access_token(&self, token: &str) -> Option<AccessTokenClaims> {
+        let (signing_input, signature) = token.rsplit_once('.')?;
+        let signature =
+            Signature::try_from(URL_SAFE_NO_PAD.decode(signature).ok()?.as_slice()).ok()?;
+        self.keys
+            .verifying_key
+            .verify(signing_input.as_bytes(), &signature)
+            .ok()?;
+        let (header, claims) = signing_input.split_once('.')?;
+        let header: JwtHeader =
+            serde_json::from_slice(&URL_SAFE_NO_PAD.decode(header).ok()?).ok()?;
+        if header.typ != ACCESS_TOKEN_TYPE {
+            return None;
+        }
+        let claims: AccessTokenClaims =
+            serde_json::from_slice(&URL_SAFE_NO_PAD.decode(claims).ok()?).ok()?;
+        (claims.iss == self.issuer && claims.exp > Utc::now().timestamp()).then_some(claims)
+    }
+
+    /// The claims about the user, shared by the ID token and the userinfo endpoint.
+    fn user_claims(&self, user: &User, groups: &HashSet<GroupDetails>) -> Map<String, Value> {
+        let mut group_names: Vec<_> = groups.iter().map(|g| g.display_name.clone()).collect();
+        group_names.sort();
+        let mut claims = Map::new();
+        claims.insert("sub".to_owned(), json!(user.user_id.as_str()));
+        claims.insert(
+            "preferred_username".to_owned(),
+            json!(user.user_id.as_str()),
+        );
+        claims.insert("email".to_owned(), json!(user.email));
+        if let Some(name) = &user.display_name {
+            claims.insert("name".to_owned(), json!(name));
+        }
+        claims.insert(self.options.groups_claim.clone(), json!(group_names));
+        claims
+    }
+
+    fn create_tokens(
+        &self,
+        code: &AuthorizationCode,
+        user: &User,
+        groups: &HashSet<GroupDetails>,
+    ) -> Value {
+        let now = Utc::now().timestamp();
+        let lifetime = self.options.token_lifetime_seconds as i64;
+        let access_token = self.sign_jwt(
+            ACCESS_TOKEN_TYPE,
+            &AccessTokenClaims {
+                iss: self.issuer.clone(),
+                sub: user.user_id.to_string(),
+                aud: code.client_id.clone(),
+                iat: now,
+                exp: now + lifetime,
+                scope: code.scope.clone(),
+            },
+        );
+        let mut id_claims = self.user_claims(user, groups);
+        id_claims.insert("iss".to_owned(), json!(self.issuer));
+        id_claims.insert("aud".to_owned(), json!(code.client_id));
+        id_claims.insert("iat".to_owned(), json!(now));
+        id_claims.insert("exp".to_owned(), json!(now + lifetime));
+        if let Some(nonce) = &code.nonce {
+            id_claims.insert("nonce".to_owned(), json!(nonce));
+        }
+        json!({
+            "access_token": access_token,
+            "token_type": "Bearer",
+            "expires_in": lifetime,
+            "scope": code.scope,
+            "id_token": self.sign_jwt("JWT", &id_claims),
+        })
+    }
+}
+
+fn load_or_generate_key(file_path: &str) -> Result<RsaPrivateKey> {
+    let path = std::path::Path::new(file_path);
+    if path.exists() {
+        let pem = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read the OIDC key file `{}`", file_path))?;
+        RsaPrivateKey::from_pkcs8_pem(&pem)
+            .with_context(|| format!("Invalid OIDC key in `{}`", file_path))
+    } else {
+        info!("Generating the OIDC signing key in `{}`", file_path);
+        let key = RsaPrivateKey::new(&mut rand::rngs::OsRng, KEY_BITS)?;
+        write_to_readonly_file(path, key.to_pkcs8_pem(LineEnding::LF)?.as_bytes()).with_context(
+            || {
+                format!(
+                    "Could not write the generated OIDC key to file `{}`",
+                    file_path
+                )
+            },
+        )?;
+        Ok(key)
+    }
+}
+
+/// PKCE with the S256 method (RFC 7636).
+fn verify_pkce(code_verifier: &str, code_challenge: &str) -> bool {
+    (43..=128).contains(&code_verifier.len())
+        && URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes())) == code_challenge
+}
+
+/// An error returned to the client, as defined by OAuth 2.0.
+#[derive(Debug, Serialize)]
+struct OAuthError {
+    error: &'static str,
+    error_description: String,
+}
+
+impl OAuthError {
+    fn new(error: &'static str, error_description: impl Into<String>) -> Self {
+        Self {
+            error,
+            error_description: error_description.into(),
+        }
+    }
+}
+
+impl From<DomainError> for OAuthError {
+    fn from(error: DomainError) -> Self {
+        Self::new("server_error", error.to_string())
+    }
+}
+
+fn oauth_error_response(error: OAuthError) -> HttpResponse {
+    match error.error {
+        "invalid_client" => HttpResponse::Unauthorized(),
+        "server_error" => HttpResponse::InternalServerError(),
+        _ => HttpResponse::BadRequest(),
+    }
+    .json(error)
+}
+
+async fn get_discovery(provider: web::Data<OidcProvider>) -> HttpResponse {
+    let issuer = &provider.issuer;
+    HttpResponse::Ok().json(json!({
+        "issuer": issuer,
+        "authorization_endpoint": format!("{}/auth/oidc/authorize", issuer),
+        "token_endpoint": format!("{}/auth/oidc/token", issuer),
+        "userinfo_endpoint": format!("{}/auth/oidc/userinfo", issuer),
+        "jwks_uri": format!("{}/auth/oidc/jwks", issuer),
+        "response_types_supported": ["code"],
+        "grant_types_supported": ["authorization_code"],
+        "subject_types_supported": ["public"],
+        "id_token_signing_alg_values_supported": ["RS256"],
+        "scopes_supported": ["openid", "profile", "email", "groups"],
+        "token_endpoint_auth_methods_supported": ["client_secret_basic", "client_secret_post", "none"],
+        "code_challenge_methods_supported": ["S256"],
+        "claims_supported": [
+            "sub", "iss", "aud", "exp", "iat", "nonce", "preferred_username", "email", "name",
+            provider.options.groups_claim,
+        ],
+    }))
+}
+
+async fn get_jwks(provider: web::Data<OidcProvider>) -> HttpResponse {
+    HttpResponse::Ok().json(json!({ "keys": [provider.keys.jwk] }))
+}
+
+#[derive(Deserialize)]
+struct AuthorizeRequest {
+    client_id: String,
+    redirect_uri: String,
+    #[serde(default)]
+    response_type: String,
+    #[serde(default)]
+    scope: String,
+    state: Option<String>,
+    nonce: Option<String>,
+    #[serde(default)]
+    code_challenge: String,
+    #[serde(default)]
+    code_challenge_method: String,
+}
+
+fn check_authorize_request(request: &AuthorizeRequest) -> std::result::Result<(), OAuthError> {
+    if request.response_type != "code" {
+        return Err(OAuthError::new(
+            "unsupported_response_type",
+            "Only the authorization code flow is supported",
+        ));
+    }
+    if !request.scope.split(' ').any(|scope| scope == "openid") {
+        return Err(OAuthError::new(
+            "invalid_scope",
+            "The \"openid\" scope is required",
+        ));
+    }
+    if request.code_challenge.is_empty() || request.code_challenge_method != "S256" {
+        return Err(OAuthError::new(
+            "invalid_request",
+            "PKCE with the S256 method is required",
+        ));
+    }
+    Ok(())
+}
+
+fn redirect_to(mut url: Url, params: &[(&str, &str)], state: Option<&str>) -> HttpResponse {
+    {
+        let mut query = url.query_pairs_mut();
+        for (key, value) in params {
+            query.append_pair(key, value);
+        }
+        if let Some(state) = state {
+            query.append_pair("state", state);
+        }
+    }
+    HttpResponse::Found()
+        .insert_header((header::LOCATION, url.as_str()))
+        .finish()
+}
+
+/// The user logged in to the web UI, if any.
+async fn get_logged_in_user<Backend>(
+    data: &AppState<Backend>,
+    request: &HttpRequest,
+) -> Option<UserId>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    if let Some(token) = request.cookie("token") {
+        if let Ok(validation) = check_if_token_is_valid(data, token.value()) {
+            return Some(validation.user);
+        }
+    }
+    // The JWT is short-lived, fall back to the refresh token.
+    let (refresh_token_hash, user) = get_refresh_token(request.clone()).ok()?;
+    data.get_tcp_handler()
+        .check_token(refresh_token_hash, &user)
+        .await
+        .ok()?
+        .then_some(user)
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn authorize<Backend>(
+    data: web::Data<AppState<Backend>>,
+    provider: web::Data<OidcProvider>,
+    request: HttpRequest,
+    query: web::Query<AuthorizeRequest>,
+) -> TcpResult<HttpResponse>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    let query = query.into_inner();
+    debug!(client_id = %query.client_id);
+    let client = provider
+        .get_client(&query.client_id)
+        .ok_or_else(|| TcpError::BadRequest(format!("Unknown client `{}`", query.client_id)))?;
+    // The errors can't be sent back to an unknown redirect URI.
+    let redirect_uri = Url::parse(&query.redirect_uri)
+        .ok()
+        .filter(|uri| client.redirect_uris.contains(uri))
+        .ok_or_else(|| TcpError::BadRequest("Invalid redirect URI".to_string()))?;
+    let state = query.state.as_deref();
+    if let Err(error) = check_authorize_request(&query) {
+        return Ok(redirect_to(
+            redirect_uri,
+            &[
+                ("error", error.error),
+                ("error_description", error.error_description.as_str()),
+            ],
+            state,
+        ));
+    }
+    let user_id = match get_logged_in_user(data.get_ref(), &request).await {
+        Some(user_id) => user_id,
+        None => {
+            return Ok(HttpResponse::Found()
+                .insert_header((
+                    header::LOCATION,
+                    format!(
+                        "/login?continue={}",
+                        urlencoding::encode(&request.uri().to_string())
+                    ),
+                ))
+                .finish())
+        }
+    };
+    // The web UI session outlives the disabling of the user until the JWT expires.
+    if data
+        .get_readonly_handler()
+        .get_user_details(&user_id)
+        .await?
+        .is_disabled()
+    {
+        return Ok(redirect_to(
+            redirect_uri,
+            &[
+                ("error", "access_denied"),
+                ("error_description", "The user is disabled"),
+            ],
+            state,
+        ));
+    }
+    let code = provider.create_code(AuthorizationCode {
+        user_id,
+        client_id: query.client_id.clone(),
+        redirect_uri: redirect_uri.clone(),
+        code_challenge: query.code_challenge.clone(),
+        nonce: query.nonce.clone(),
+        scope: query.scope.clone(),
+        expires_at: Utc::now() + chrono::Duration::seconds(CODE_LIFETIME_SECONDS),
+    });
+    Ok(redirect_to(redirect_uri, &[("code", code.as_str())], state))
+}
+
+async fn authorize_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    provider: web::Data<OidcProvider>,
+    request: HttpRequest,
+    query: web::Query<AuthorizeRequest>,
+) -> HttpResponse
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    authorize(data, provider, request, query)
+        .await
+        .unwrap_or_else(error_to_http_response)
+}
+
+#[derive(Deserialize)]
+struct TokenRequest {
+    #[serde(default)]
+    grant_type: String,
+    #[serde(default)]
+    code: String,
+    #[serde(default)]
+    redirect_uri: String,
+    #[serde(default)]
+    code_verifier: String,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn token<Backend>(
+    data: web::Data<AppState<Backend>>,
+    provider: web::Data<OidcProvider>,
+    credentials: Option<BasicAuth>,
+    request: web::Form<TokenRequest>,
+) -> std::result::Result<HttpResponse, OAuthError>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    let request = request.into_inner();
+    if request.grant_type != "authorization_code" {
+        return Err(OAuthError::new(
+            "unsupported_grant_type",
+            "Only the authorization_code grant is supported",
+        ));
+    }
+    let (client_id, client_secret) = match &credentials {
+        Some(credentials) => (
+            Some(credentials.user_id().to_owned()),
+            credentials.password().map(str::to_owned),
+        ),
+        None => (request.client_id, request.client_secret),
+    };
+    let client_id =
+        client_id.ok_or_else(|| OAuthError::new("invalid_client", "Missing client ID"))?;
+    debug!(%client_id);
+    let client = provider
+        .get_client(&client_id)
+        .ok_or_else(|| OAuthError::new("invalid_client", "Unknown client"))?;
+    if let Some(expected_secret) = &client.client_secret {
+        // Constant-time, not to leak the secret through the response times.
+        let matches = client_secret.as_deref().map_or(false, |secret| {
+            orion::util::secure_cmp(secret.as_bytes(), expected_secret.unsecure().as_bytes())
+                .is_ok()
+        });
+        if !matches {
+            return Err(OAuthError::new("invalid_client", "Invalid client secret"));
+        }
+    }
+    let code = provider
+        .take_code(&request.code)
+        .filter(|code| code.client_id == client_id)
+        .ok_or_else(|| OAuthError::new("invalid_grant", "Invalid or expired code"))?;
+    if Url::parse(&request.redirect_uri).ok().as_ref() != Some(&code.redirect_uri) {
+        return Err(OAuthError::new("invalid_grant", "Mismatched redirect URI"));
+    }
+    if !verify_pkce(&request.code_verifier, &code.code_challenge) {
+        return Err(OAuthError::new("invalid_grant", "Invalid code verifier"));
+    }
+    let handler = data.get_readonly_handler();
+    let user = handler.get_user_details(&code.user_id).await?;
+    // The user could have been disabled since the code was issued.
+    if user.is_disabled() {
+        return Err(OAuthError::new("invalid_grant", "The user is disabled"));
+    }
+    let groups = handler.get_user_groups(&code.user_id).await?;
+    Ok(HttpResponse::Ok()
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .json(provider.create_tokens(&code, &user, &groups)))
+}
+
+async fn token_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    provider: web::Data<OidcProvider>,
+    credentials: Option<BasicAuth>,
+    request: web::Form<TokenRequest>,
+) -> HttpResponse
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    token(data, provider, credentials, request)
+        .await
+        .unwrap_or_else(oauth_error_response)
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn userinfo<Backend>(
+    data: web::Data<AppState<Backend>>,
+    provider: web::Data<OidcProvider>,
+    credentials: BearerAuth,
+) -> TcpResult<HttpResponse>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    let claims = provider
+        .verify_access_token(credentials.token())
+        .ok_or_else(|| TcpError::UnauthorizedError("Invalid access token".to_string()))?;
+    let user_id = UserId::new(&claims.sub);
+    let handler = data.get_readonly_handler();
+    let user = handler.get_user_details(&user_id).await?;
+    let groups = handler.get_user_groups(&user_id).await?;
+    Ok(HttpResponse::Ok().json(provider.user_claims(&user, &groups)))
+}
+
+async fn userinfo_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    provider: web::Data<OidcProvider>,
+    credentials: BearerAuth,
+) -> HttpResponse
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    userinfo(data, provider, credentials)
+        .await
+        .unwrap_or_else(error_to_http_response)
+}
+
+/// The discovery document, served at the root.
+pub fn configure_discovery(cfg: &mut web::ServiceConfig, provider: OidcProvider) {
+    cfg.app_data(web::Data::new(provider)).service(
+        web::resource("/.well-known/openid-configuration").route(web::get().to(get_discovery)),
+    );
+}
+
+/// The endpoints, served under `/auth/oidc`.
+pub fn configure_server<Backend>(cfg: &mut web::ServiceConfig)
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    cfg.service(web::resource("/jwks").route(web::get().to(get_jwks)))
+        .service(web::resource("/authorize").route(web::get().to(authorize_handler::<Backend>)))
+        .service(web::resource("/token").route(web::post().to(token_handler::<Backend>)))
+        .service(
+            web::resource("/userinfo")
+                .route(web::get().to(userinfo_handler::<Backend>))
+                .route(web::post().to(userinfo_handler::<Backend>)),
+        );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        domain::{
+            handler::UserBackendHandler,
+            sql_backend_handler::{
+                tests::{get_default_config, get_initialized_db, insert_user_no_password},
+                SqlBackendHandler,
+            },
+            types::GroupId,
+        },
+        infra::{
+            access_control::AccessControlledBackendHandler,
+            auth_service::create_jwt,
+            configuration::{OidcOptionsBuilder, PasswordPolicyOptions, SessionOptions},
+            webhooks::WebhookNotifier,
+        },
+    };
+    use actix_web::{cookie::Cookie, test::TestRequest};
+    use hmac::Mac;
+    use secstr::SecUtf8;
+    use std::sync::RwLock;
+
+    // From RFC 7636, appendix B.
+    const CODE_VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+    const CODE_CHALLENGE: &str = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM";
+    const REDIRECT_URI: &str = "https://app.example.com/callback";
+
+    fn get_provider() -> OidcProvider {
+        let options = OidcOptionsBuilder::default()
+            .clients(vec![OidcClient {
+                client_id: "app".to_owned(),
+                client_secret: Some(SecUtf8::from("secret")),
+                redirect_uris: vec![Url::parse(REDIRECT_URI).unwrap()],
+            }])
+            .groups_claim("roles".to_owned())
+            .build()
+            .unwrap();
+        // Small key, to keep the test fast.
+        let private_key = RsaPrivateKey::new(&mut rand::rngs::OsRng, 1024).unwrap();
+        OidcProvider::with_key(
+            options,
+            &Url::parse("https://auth.example.com/").unwrap(),
+            private_key,
+        )
+    }
+
+    fn decode_claims(jwt: &str) -> Value {
+        let claims = jwt.split('.').nth(1).unwrap();
+        serde_json::from_slice(&URL_SAFE_NO_PAD.decode(claims).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_verify_pkce() {
+        assert!(verify_pkce(CODE_VERIFIER, CODE_CHALLENGE));
+        assert!(!verify_pkce(
+            CODE_VERIFIER,
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cN"
+        ));
+        assert!(!verify_pkce("short", CODE_CHALLENGE));
+    }
+
+    #[test]
+    fn test_tokens() {
+        let provider = get_provider();
+        let code = AuthorizationCode {
+            user_id: UserId::new("bob"),
+            client_id: "app".to_owned(),
+            redirect_uri: Url::parse("https://app.example.com/callback").unwrap(),
+            code_challenge: String::new(),
+            nonce: Some("n0nce".to_owned()),
+            scope: "openid email".to_owned(),
+            expires_at: Utc::now(),
+        };
+        let user = User {
+            user_id: UserId::new("bob"),
+            email: "bob@example.com".to_owned(),
+            display_name: Some("Bob".to_owned()),
+            ..Default::default()
+        };
+        let groups = HashSet::from([GroupDetails {
+            group_id: GroupId(1),
+            display_name: "admins".to_owned(),
+            creation_date: user.creation_date,
+            uuid: crate::uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+        }]);
+        let tokens = provider.create_tokens(&code, &user, &groups);
+
+        let id_token = tokens["id_token"].as_str().unwrap();
+        let claims = decode_claims(id_token);
+        assert_eq!(claims["iss"], "https://auth.example.com");
+        assert_eq!(claims["aud"], "app");
+        assert_eq!(claims["sub"], "bob");
+        assert_eq!(claims["email"], "bob@example.com");
+        assert_eq!(claims["name"], "Bob");
+        assert_eq!(claims["nonce"], "n0nce");
+        assert_eq!(claims["roles"], json!(["admins"]));
+        // Only the access tokens are accepted by the userinfo endpoint.
+        assert!(provider.verify_access_token(id_token).is_none());
+
+        let access_token = tokens["access_token"].as_str().unwrap();
+        let access_claims = provider.verify_access_token(access_token).unwrap();
+        assert_eq!(access_claims.sub, "bob");
+        assert_eq!(access_claims.scope, "openid email");
+        let mut tampered = access_token.to_owned();
+        tampered.push('A');
+        assert!(provider.verify_access_token(&tampered).is_none());
+    }
+
+    #[test]
+    fn test_codes_are_single_use() {
+        let provider = get_provider();
+        let new_code = |expires_at| AuthorizationCode {
+            user_id: UserId::new("bob"),
+            client_id: "app".to_owned(),
+            redirect_uri: Url::parse("https://app.example.com/callback").unwrap(),
+            code_challenge: String::new(),
+            nonce: None,
+            scope: "openid".to_owned(),
+            expires_at,
+        };
+        let code = provider.create_code(new_code(Utc::now() + chrono::Duration::seconds(60)));
+        assert!(provider.take_code(&code).is_some());
+        assert!(provider.take_code(&code).is_none());
+        let expired = provider.create_code(new_code(Utc::now() - chrono::Duration::seconds(1)));
+        assert!(provider.take_code(&expired).is_none());
+    }
+
+    fn get_app_state(handler: SqlBackendHandler) -> web::Data<AppState<SqlBackendHandler>> {
+        web::Data::new(AppState {
+            backend_handler: AccessControlledBackendHandler::new(handler),
+            jwt_key: Mac::new_from_slice(b"jwt_secret").unwrap(),
+            jwt_blacklist: RwLock::new(HashSet::new()),
+            server_url: Url::parse("https://auth.example.com/").unwrap(),
+            mail_sender: None,
+            password_policy: PasswordPolicyOptions::default(),
+            session_options: SessionOptions::default(),
+            events: WebhookNotifier::start(Vec::new()),
+            self_service_attributes: Vec::new(),
+        })
+    }
+
+    /// Where `authorize` sent the user back to the client, as the query parameters.
+    fn get_redirect_params(response: &HttpResponse) -> HashMap<String, String> {
+        let location = response.headers().get(header::LOCATION).unwrap();
+        Url::parse(location.to_str().unwrap())
+            .unwrap()
+            .query_pairs()
+            .into_owned()
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_disabled_users_are_rejected() {
+        let handler = SqlBackendHandler::new(get_default_config(), get_initialized_db().await);
+        insert_user_no_password(&handler, "bob").await;
+        let bob = UserId::new("bob");
+        let data = get_app_state(handler.clone());
+        let provider = web::Data::new(get_provider());
+        let jwt = create_jwt(
+            &data.jwt_key,
+            "bob".to_owned(),
+            HashSet::new(),
+            chrono::Duration::minutes(5),
+        );
+        let authorize = || {
+            let request = TestRequest::get()
+                .uri("/auth/oidc/authorize")
+                .cookie(Cookie::new("token", jwt.as_str().to_owned()))
+                .to_http_request();
+            let query = web::Query(AuthorizeRequest {
+                client_id: "app".to_owned(),
+                redirect_uri: REDIRECT_URI.to_owned(),
+                response_type: "code".to_owned(),
+                scope: "openid".to_owned(),
+                state: None,
+                nonce: None,
+                code_challenge: CODE_CHALLENGE.to_owned(),
+                code_challenge_method: "S256".to_owned(),
+            });
+            super::authorize(data.clone(), provider.clone(), request, query)
+        };
+        let token = |code: &str, client_secret: &str| {
+            let request = web::Form(TokenRequest {
+                grant_type: "authorization_code".to_owned(),
+                code: code.to_owned(),
+                redirect_uri: REDIRECT_URI.to_owned(),
+                code_verifier: CODE_VERIFIER.to_owned(),
+                client_id: Some("app".to_owned()),
+                client_secret: Some(client_secret.to_owned()),
+            });
+            super::token(data.clone(), provider.clone(), None, request)
+        };
+
+        let params = get_redirect_params(&authorize().await.unwrap());
+        let code = params["code"].clone();
+        let error = token(&code, "wrong").await.unwrap_err();
+        assert_eq!(error.error, "invalid_client");
+
+        // Disabled between the authorization and the token request.
+        handler.set_user_enabled(&bob, false).await.unwrap();
+        let error = token(&code, "secret").await.unwrap_err();
+        assert_eq!(error.error, "invalid_grant");
+        let params = get_redirect_params(&authorize().await.unwrap());
+        assert_eq!(params["error"], "access_denied");
+        assert!(!params.contains_key("code"));
+
+        handler.set_user_enabled(&bob, true).await.unwrap();
+        let params = get_redirect_params(&authorize().await.unwrap());
+        assert!(token(&params["code"], "secret").await.is_ok());
+
+        handler
+            .set_user_expiration_date(
+                &bob,
+                Some(Utc::now().naive_utc() - chrono::Duration::days(1)),
+            )
+            .await
+            .unwrap();
+        let params = get_redirect_params(&authorize().await.unwrap());
+        assert_eq!(params["error"], "access_denied");
+    }
+}
@@ -23,6 +23,8 @@ impl From<Schema> for PublicSchema {
                 is_visible: true,
                 is_editable: false,
                 is_hardcoded: true,
+                is_case_sensitive: false,
+                allowed_values: None,
             },
             AttributeSchema {
                 name: "creation_date".to_owned(),
@@ -31,6 +33,8 @@ impl From<Schema> for PublicSchema {
                 is_visible: true,
                 is_editable: false,
                 is_hardcoded: true,
+                is_case_sensitive: false,
+                allowed_values: None,
             },
             AttributeSchema {
                 name: "mail".to_owned(),
@@ -39,6 +43,8 @@ impl From<Schema> for PublicSchema {
                 is_visible: true,
                 is_editable: true,
                 is_hardcoded: true,
+                is_case_sensitive: false,
+                allowed_values: None,
             },
             AttributeSchema {
                 name: "uuid".to_owned(),
@@ -47,6 +53,8 @@ impl From<Schema> for PublicSchema {
                 is_visible: true,
                 is_editable: false,
                 is_hardcoded: true,
+                is_case_sensitive: false,
+                allowed_values: None,
             },
             AttributeSchema {
                 name: "display_name".to_owned(),
@@ -55,6 +63,8 @@ impl From<Schema> for PublicSchema {
                 is_visible: true,
                 is_editable: true,
                 is_hardcoded: true,
+                is_case_sensitive: false,
+                allowed_values: None,
             },
         ]);
         schema
@@ -69,6 +79,8 @@ impl From<Schema> for PublicSchema {
                 is_visible: true,
                 is_editable: false,
                 is_hardcoded: true,
+                is_case_sensitive: false,
+                allowed_values: None,
             },
             AttributeSchema {
                 name: "creation_date".to_owned(),
@@ -77,6 +89,8 @@ impl From<Schema> for PublicSchema {
                 is_visible: true,
                 is_editable: false,
                 is_hardcoded: true,
+                is_case_sensitive: false,
+                allowed_values: None,
             },
             AttributeSchema {
                 name: "uuid".to_owned(),
@@ -85,6 +99,8 @@ impl From<Schema> for PublicSchema {
                 is_visible: true,
                 is_editable: false,
                 is_hardcoded: true,
+                is_case_sensitive: false,
+                allowed_values: None,
             },
             AttributeSchema {
                 name: "display_name".to_owned(),
@@ -93,6 +109,8 @@ impl From<Schema> for PublicSchema {
                 is_visible: true,
                 is_editable: true,
                 is_hardcoded: true,
+                is_case_sensitive: false,
+                allowed_values: None,
             },
         ]);
         schema
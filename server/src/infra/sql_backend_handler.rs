@@ -1,17 +1,30 @@
-use super::tcp_backend_handler::TcpBackendHandler;
+use super::tcp_backend_handler::{Session, TcpBackendHandler};
 use crate::domain::{
     error::*,
-    model::{self, JwtRefreshStorageColumn, JwtStorageColumn, PasswordResetTokensColumn},
+    model::{
+        self, JwtRefreshStorageColumn, JwtStorageColumn, PasswordResetTokensColumn, UserColumn,
+    },
     sql_backend_handler::SqlBackendHandler,
     types::UserId,
 };
 use async_trait::async_trait;
 use sea_orm::{
     sea_query::{Cond, Expr},
-    ActiveModelTrait, ColumnTrait, EntityTrait, IntoActiveModel, QueryFilter, QuerySelect,
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, EntityTrait, IntoActiveModel, QueryFilter,
+    QueryOrder, QuerySelect, Statement,
 };
 use std::collections::HashSet;
-use tracing::{debug, instrument};
+use tracing::{debug, instrument, warn};
+
+const SESSION_ID_LENGTH: usize = 32;
+
+fn hash_refresh_token(refresh_token: &str) -> u64 {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+    let mut s = DefaultHasher::new();
+    refresh_token.hash(&mut s);
+    s.finish()
+}
 
 fn gen_random_string(len: usize) -> String {
     use rand::{distributions::Alphanumeric, rngs::SmallRng, Rng, SeedableRng};
@@ -23,6 +36,48 @@ fn gen_random_string(len: usize) -> String {
         .collect()
 }
 
+impl SqlBackendHandler {
+    /// The refresh token if it exists and hasn't expired, even if it was replaced.
+    async fn find_refresh_token(
+        &self,
+        refresh_token_hash: u64,
+        user: &UserId,
+    ) -> Result<Option<model::jwt_refresh_storage::Model>> {
+        Ok(
+            model::JwtRefreshStorage::find_by_id(refresh_token_hash as i64)
+                .filter(JwtRefreshStorageColumn::UserId.eq(user))
+                .filter(JwtRefreshStorageColumn::ExpiryDate.gt(chrono::Utc::now().naive_utc()))
+                .one(&self.sql_pool)
+                .await?,
+        )
+    }
+
+    async fn insert_refresh_token(
+        &self,
+        user: &UserId,
+        session_id: String,
+        session_creation_date: chrono::NaiveDateTime,
+    ) -> Result<(String, chrono::Duration)> {
+        // TODO: Initialize the rng only once. Maybe Arc<Cell>?
+        let refresh_token = gen_random_string(100);
+        let refresh_token_hash = hash_refresh_token(&refresh_token);
+        let duration = self.config.session_options.refresh_token_ttl();
+        let now = chrono::Utc::now().naive_utc();
+        let new_token = model::jwt_refresh_storage::Model {
+            refresh_token_hash: refresh_token_hash as i64,
+            user_id: user.clone(),
+            expiry_date: now + duration,
+            session_id,
+            session_creation_date,
+            creation_date: now,
+            replaced: false,
+        }
+        .into_active_model();
+        new_token.insert(&self.sql_pool).await?;
+        Ok((refresh_token, duration))
+    }
+}
+
 #[async_trait]
 impl TcpBackendHandler for SqlBackendHandler {
     #[instrument(skip_all, level = "debug")]
@@ -42,36 +97,66 @@ impl TcpBackendHandler for SqlBackendHandler {
     #[instrument(skip_all, level = "debug")]
     async fn create_refresh_token(&self, user: &UserId) -> Result<(String, chrono::Duration)> {
         debug!(?user);
-        // TODO: Initialize the rng only once. Maybe Arc<Cell>?
-        let refresh_token = gen_random_string(100);
-        let refresh_token_hash = {
-            use std::collections::hash_map::DefaultHasher;
-            use std::hash::{Hash, Hasher};
-            let mut s = DefaultHasher::new();
-            refresh_token.hash(&mut s);
-            s.finish()
-        };
-        let duration = chrono::Duration::days(30);
-        let new_token = model::jwt_refresh_storage::Model {
-            refresh_token_hash: refresh_token_hash as i64,
-            user_id: user.clone(),
-            expiry_date: chrono::Utc::now().naive_utc() + duration,
-        }
-        .into_active_model();
-        new_token.insert(&self.sql_pool).await?;
-        Ok((refresh_token, duration))
+        self.insert_refresh_token(
+            user,
+            gen_random_string(SESSION_ID_LENGTH),
+            chrono::Utc::now().naive_utc(),
+        )
+        .await
     }
 
     #[instrument(skip_all, level = "debug")]
     async fn check_token(&self, refresh_token_hash: u64, user: &UserId) -> Result<bool> {
         debug!(?user);
-        Ok(
-            model::JwtRefreshStorage::find_by_id(refresh_token_hash as i64)
-                .filter(JwtRefreshStorageColumn::UserId.eq(user))
-                .one(&self.sql_pool)
-                .await?
-                .is_some(),
-        )
+        Ok(self
+            .find_refresh_token(refresh_token_hash, user)
+            .await?
+            .map_or(false, |token| !token.replaced))
+    }
+
+    #[instrument(skip_all, level = "debug")]
+    async fn use_refresh_token(
+        &self,
+        refresh_token_hash: u64,
+        user: &UserId,
+    ) -> Result<Option<(String, chrono::Duration)>> {
+        debug!(?user);
+        let invalid_token =
+            || DomainError::AuthenticationError("Invalid refresh token".to_string());
+        let token = self
+            .find_refresh_token(refresh_token_hash, user)
+            .await?
+            .ok_or_else(invalid_token)?;
+        if token.replaced {
+            warn!(
+                "Reuse of a replaced refresh token of user {}, revoking the session",
+                user
+            );
+            self.delete_session(user, &token.session_id).await?;
+            return Err(invalid_token());
+        }
+        // The sessions are revoked when a user is disabled, but not when their account expires.
+        if self.is_user_disabled(user).await? {
+            debug!("User {} is disabled, revoking the session", user);
+            self.delete_session(user, &token.session_id).await?;
+            return Err(invalid_token());
+        }
+        if !self.config.session_options.refresh_token_rotation {
+            return Ok(None);
+        }
+        // Only one of concurrent refreshes with the same token gets a new one.
+        let result = model::JwtRefreshStorage::update_many()
+            .col_expr(JwtRefreshStorageColumn::Replaced, Expr::value(true))
+            .filter(JwtRefreshStorageColumn::RefreshTokenHash.eq(refresh_token_hash as i64))
+            .filter(JwtRefreshStorageColumn::Replaced.eq(false))
+            .exec(&self.sql_pool)
+            .await?;
+        if result.rows_affected == 0 {
+            return Err(invalid_token());
+        }
+        self.insert_refresh_token(user, token.session_id, token.session_creation_date)
+            .await
+            .map(Some)
     }
 
     #[instrument(skip_all, level = "debug")]
@@ -101,9 +186,52 @@ impl TcpBackendHandler for SqlBackendHandler {
 
     #[instrument(skip_all, level = "debug")]
     async fn delete_refresh_token(&self, refresh_token_hash: u64) -> Result<()> {
-        model::JwtRefreshStorage::delete_by_id(refresh_token_hash as i64)
+        if let Some(token) = model::JwtRefreshStorage::find_by_id(refresh_token_hash as i64)
+            .one(&self.sql_pool)
+            .await?
+        {
+            model::JwtRefreshStorage::delete_many()
+                .filter(JwtRefreshStorageColumn::SessionId.eq(token.session_id))
+                .exec(&self.sql_pool)
+                .await?;
+        }
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug")]
+    async fn list_sessions(&self, user: &UserId) -> Result<Vec<Session>> {
+        debug!(?user);
+        Ok(model::JwtRefreshStorage::find()
+            .filter(JwtRefreshStorageColumn::UserId.eq(user))
+            .filter(JwtRefreshStorageColumn::Replaced.eq(false))
+            .filter(JwtRefreshStorageColumn::ExpiryDate.gt(chrono::Utc::now().naive_utc()))
+            .order_by_asc(JwtRefreshStorageColumn::SessionCreationDate)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(|token| Session {
+                id: token.session_id,
+                creation_date: token.session_creation_date,
+                last_refresh_date: token.creation_date,
+                expiry_date: token.expiry_date,
+            })
+            .collect())
+    }
+
+    #[instrument(skip_all, level = "debug")]
+    async fn delete_session(&self, user: &UserId, session_id: &str) -> Result<()> {
+        debug!(?user, session_id);
+        let result = model::JwtRefreshStorage::delete_many()
+            .filter(JwtRefreshStorageColumn::UserId.eq(user))
+            .filter(JwtRefreshStorageColumn::SessionId.eq(session_id))
             .exec(&self.sql_pool)
             .await?;
+        if result.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such session: '{}'",
+                session_id
+            )));
+        }
         Ok(())
     }
 
@@ -111,6 +239,7 @@ impl TcpBackendHandler for SqlBackendHandler {
     async fn start_password_reset(&self, user: &UserId) -> Result<Option<String>> {
         debug!(?user);
         if model::User::find_by_id(user.clone())
+            .filter(UserColumn::DeletedDate.is_null())
             .one(&self.sql_pool)
             .await?
             .is_none()
@@ -119,8 +248,13 @@ impl TcpBackendHandler for SqlBackendHandler {
             return Ok(None);
         }
 
+        // Only the last link sent is valid.
+        model::PasswordResetTokens::delete_many()
+            .filter(PasswordResetTokensColumn::UserId.eq(user))
+            .exec(&self.sql_pool)
+            .await?;
         let token = gen_random_string(100);
-        let duration = chrono::Duration::minutes(10);
+        let duration = self.config.smtp_options.reset_token_validity();
 
         let new_token = model::password_reset_tokens::Model {
             token: token.clone(),
@@ -155,4 +289,192 @@ impl TcpBackendHandler for SqlBackendHandler {
         }
         Ok(())
     }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn check_database(&self) -> Result<()> {
+        self.sql_pool
+            .execute(Statement::from_string(
+                self.sql_pool.get_database_backend(),
+                "SELECT 1".to_owned(),
+            ))
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::sql_backend_handler::tests::*;
+
+    async fn setup_handler() -> SqlBackendHandler {
+        let handler = SqlBackendHandler::new(get_default_config(), get_initialized_db().await);
+        insert_user_no_password(&handler, "bob").await;
+        insert_user_no_password(&handler, "patrick").await;
+        handler
+    }
+
+    #[tokio::test]
+    async fn test_refresh_token_rotation() {
+        let handler = setup_handler().await;
+        let bob = UserId::new("bob");
+        let (token, _) = handler.create_refresh_token(&bob).await.unwrap();
+        let (new_token, _) = handler
+            .use_refresh_token(hash_refresh_token(&token), &bob)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!handler
+            .check_token(hash_refresh_token(&token), &bob)
+            .await
+            .unwrap());
+        assert!(handler
+            .check_token(hash_refresh_token(&new_token), &bob)
+            .await
+            .unwrap());
+        assert_eq!(handler.list_sessions(&bob).await.unwrap().len(), 1);
+
+        // Reusing the replaced token revokes the session.
+        handler
+            .use_refresh_token(hash_refresh_token(&token), &bob)
+            .await
+            .unwrap_err();
+        assert!(!handler
+            .check_token(hash_refresh_token(&new_token), &bob)
+            .await
+            .unwrap());
+        assert!(handler.list_sessions(&bob).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_refresh_token_without_rotation() {
+        let mut handler = setup_handler().await;
+        handler.config.session_options.refresh_token_rotation = false;
+        let bob = UserId::new("bob");
+        let (token, _) = handler.create_refresh_token(&bob).await.unwrap();
+        for _ in 0..2 {
+            assert_eq!(
+                handler
+                    .use_refresh_token(hash_refresh_token(&token), &bob)
+                    .await
+                    .unwrap(),
+                None
+            );
+        }
+        // The token is checked against its user.
+        handler
+            .use_refresh_token(hash_refresh_token(&token), &UserId::new("patrick"))
+            .await
+            .unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn test_delete_session() {
+        let handler = setup_handler().await;
+        let bob = UserId::new("bob");
+        let (token, _) = handler.create_refresh_token(&bob).await.unwrap();
+        handler.create_refresh_token(&bob).await.unwrap();
+        assert_eq!(handler.list_sessions(&bob).await.unwrap().len(), 2);
+
+        // The logout ends the session of the token.
+        handler
+            .delete_refresh_token(hash_refresh_token(&token))
+            .await
+            .unwrap();
+        assert!(!handler
+            .check_token(hash_refresh_token(&token), &bob)
+            .await
+            .unwrap());
+        let sessions = handler.list_sessions(&bob).await.unwrap();
+        assert_eq!(sessions.len(), 1);
+
+        // Only the user's own sessions can be deleted.
+        handler
+            .delete_session(&UserId::new("patrick"), &sessions[0].id)
+            .await
+            .unwrap_err();
+        handler.delete_session(&bob, &sessions[0].id).await.unwrap();
+        assert!(handler.list_sessions(&bob).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_password_reset_tokens() {
+        let handler = setup_handler().await;
+        let bob = UserId::new("bob");
+        let first_token = handler.start_password_reset(&bob).await.unwrap().unwrap();
+        let token = handler.start_password_reset(&bob).await.unwrap().unwrap();
+        assert_eq!(
+            handler
+                .start_password_reset(&UserId::new("unknown"))
+                .await
+                .unwrap(),
+            None
+        );
+
+        // A new link invalidates the previous ones.
+        handler
+            .get_user_id_for_password_reset_token(&first_token)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            handler
+                .get_user_id_for_password_reset_token(&token)
+                .await
+                .unwrap(),
+            bob
+        );
+
+        // The links can only be used once.
+        handler.delete_password_reset_token(&token).await.unwrap();
+        handler
+            .get_user_id_for_password_reset_token(&token)
+            .await
+            .unwrap_err();
+        handler
+            .delete_password_reset_token(&token)
+            .await
+            .unwrap_err();
+
+        // Changing the password invalidates the pending links.
+        let token = handler.start_password_reset(&bob).await.unwrap().unwrap();
+        crate::domain::sql_opaque_handler::register_password(
+            &handler,
+            &bob,
+            &secstr::SecUtf8::from("bob00"),
+        )
+        .await
+        .unwrap();
+        handler
+            .get_user_id_for_password_reset_token(&token)
+            .await
+            .unwrap_err();
+
+        let mut handler = handler;
+        handler.config.smtp_options.reset_token_validity_minutes = 0;
+        let token = handler.start_password_reset(&bob).await.unwrap().unwrap();
+        handler
+            .get_user_id_for_password_reset_token(&token)
+            .await
+            .unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn test_refresh_token_of_expired_user() {
+        use crate::domain::handler::UserBackendHandler;
+        let handler = setup_handler().await;
+        let bob = UserId::new("bob");
+        let (token, _) = handler.create_refresh_token(&bob).await.unwrap();
+        handler
+            .set_user_expiration_date(
+                &bob,
+                Some(chrono::Utc::now().naive_utc() - chrono::Duration::days(1)),
+            )
+            .await
+            .unwrap();
+        handler
+            .use_refresh_token(hash_refresh_token(&token), &bob)
+            .await
+            .unwrap_err();
+        assert!(handler.list_sessions(&bob).await.unwrap().is_empty());
+    }
 }
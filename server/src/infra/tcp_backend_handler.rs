@@ -1,15 +1,37 @@
 use async_trait::async_trait;
+use serde::Serialize;
 use std::collections::HashSet;
 
 use crate::domain::{error::Result, types::UserId};
 
+/// A login to the web UI, kept alive by its refresh token.
+#[derive(PartialEq, Eq, Debug, Serialize, Clone)]
+pub struct Session {
+    pub id: String,
+    pub creation_date: chrono::NaiveDateTime,
+    pub last_refresh_date: chrono::NaiveDateTime,
+    pub expiry_date: chrono::NaiveDateTime,
+}
+
 #[async_trait]
 pub trait TcpBackendHandler: Sync {
     async fn get_jwt_blacklist(&self) -> anyhow::Result<HashSet<u64>>;
+    /// Starts a new session, and returns its refresh token with its lifetime.
     async fn create_refresh_token(&self, user: &UserId) -> Result<(String, chrono::Duration)>;
+    /// Whether the refresh token is valid, without using it.
     async fn check_token(&self, refresh_token_hash: u64, user: &UserId) -> Result<bool>;
+    /// Checks the refresh token and, with the rotation, replaces it with a new one returned with
+    /// its lifetime. Reusing a replaced token revokes the whole session.
+    async fn use_refresh_token(
+        &self,
+        refresh_token_hash: u64,
+        user: &UserId,
+    ) -> Result<Option<(String, chrono::Duration)>>;
     async fn blacklist_jwts(&self, user: &UserId) -> Result<HashSet<u64>>;
+    /// Ends the session of the refresh token.
     async fn delete_refresh_token(&self, refresh_token_hash: u64) -> Result<()>;
+    async fn list_sessions(&self, user: &UserId) -> Result<Vec<Session>>;
+    async fn delete_session(&self, user: &UserId, session_id: &str) -> Result<()>;
 
     /// Request a token to reset a user's password.
     /// If the user doesn't exist, returns `Ok(None)`, otherwise `Ok(Some(token))`.
@@ -19,4 +41,7 @@ pub trait TcpBackendHandler: Sync {
     async fn get_user_id_for_password_reset_token(&self, token: &str) -> Result<UserId>;
 
     async fn delete_password_reset_token(&self, token: &str) -> Result<()>;
+
+    /// Runs a trivial query, to check that the database is reachable.
+    async fn check_database(&self) -> Result<()>;
 }
@@ -3,13 +3,18 @@ use crate::{
         error::DomainError,
         handler::{BackendHandler, LoginHandler},
         opaque_handler::OpaqueHandler,
+        passkey_handler::PasskeyHandler,
+        totp_handler::TotpHandler,
     },
     infra::{
         access_control::{AccessControlledBackendHandler, ReadonlyBackendHandler},
         auth_service,
-        configuration::{Configuration, MailOptions},
+        configuration::{Configuration, PasswordPolicyOptions, SessionOptions},
         logging::CustomRootSpanBuilder,
+        mail::MailSender,
+        oidc::{self, OidcProvider},
         tcp_backend_handler::*,
+        webhooks::WebhookNotifier,
     },
 };
 use actix_files::{Files, NamedFile};
@@ -23,7 +28,10 @@ use sha2::Sha512;
 use std::collections::HashSet;
 use std::path::PathBuf;
 use std::sync::RwLock;
-use tracing::info;
+use tracing::{info, warn};
+
+/// Maximum time for the database to answer the readiness check.
+const READINESS_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
 
 async fn index() -> actix_web::Result<NamedFile> {
     let path = PathBuf::from(r"app/index.html");
@@ -49,6 +57,7 @@ pub type TcpResult<T> = std::result::Result<T, TcpError>;
 pub(crate) fn error_to_http_response(error: TcpError) -> HttpResponse {
     match error {
         TcpError::DomainError(ref de) => match de {
+            de if de.is_busy() => HttpResponse::ServiceUnavailable(),
             DomainError::AuthenticationError(_) | DomainError::AuthenticationProtocolError(_) => {
                 HttpResponse::Unauthorized()
             }
@@ -58,7 +67,9 @@ pub(crate) fn error_to_http_response(error: TcpError) -> HttpResponse {
             | DomainError::UnknownCryptoError(_) => HttpResponse::InternalServerError(),
             DomainError::Base64DecodeError(_)
             | DomainError::BinarySerializationError(_)
-            | DomainError::EntityNotFound(_) => HttpResponse::BadRequest(),
+            | DomainError::EntityNotFound(_)
+            | DomainError::EntityAlreadyExists(_)
+            | DomainError::InvalidInput(_) => HttpResponse::BadRequest(),
         },
         TcpError::BadRequest(_) => HttpResponse::BadRequest(),
         TcpError::NotFoundError(_) => HttpResponse::NotFound(),
@@ -82,29 +93,73 @@ async fn wasm_handler_compressed() -> actix_web::Result<impl Responder> {
     )
 }
 
+/// Ready when the database answers. Kept cheap, for the Kubernetes probes.
+async fn readiness_handler<Backend>(data: web::Data<AppState<Backend>>) -> HttpResponse
+where
+    Backend: TcpBackendHandler + 'static,
+{
+    match tokio::time::timeout(READINESS_TIMEOUT, data.get_tcp_handler().check_database()).await {
+        Ok(Ok(())) => HttpResponse::Ok().json(serde_json::json!({ "status": "ready" })),
+        Ok(Err(e)) => {
+            warn!("Readiness check failed: {:#}", e);
+            HttpResponse::ServiceUnavailable().json(serde_json::json!({
+                "status": "unavailable",
+                "reason": "database unreachable",
+            }))
+        }
+        Err(_) => {
+            warn!("Readiness check timed out");
+            HttpResponse::ServiceUnavailable().json(serde_json::json!({
+                "status": "unavailable",
+                "reason": "database timeout",
+            }))
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn http_config<Backend>(
     cfg: &mut web::ServiceConfig,
     backend_handler: Backend,
     jwt_secret: secstr::SecUtf8,
     jwt_blacklist: HashSet<u64>,
     server_url: url::Url,
-    mail_options: MailOptions,
+    mail_sender: Option<MailSender>,
+    password_policy: PasswordPolicyOptions,
+    session_options: SessionOptions,
+    oidc_provider: Option<OidcProvider>,
+    events: WebhookNotifier,
+    self_service_attributes: Vec<String>,
 ) where
     Backend: TcpBackendHandler + BackendHandler + LoginHandler + OpaqueHandler + Clone + 'static,
 {
-    let enable_password_reset = mail_options.enable_password_reset;
+    let enable_password_reset = mail_sender.is_some();
     cfg.app_data(web::Data::new(AppState::<Backend> {
         backend_handler: AccessControlledBackendHandler::new(backend_handler),
         jwt_key: hmac::Mac::new_from_slice(jwt_secret.unsecure().as_bytes()).unwrap(),
         jwt_blacklist: RwLock::new(jwt_blacklist),
         server_url,
-        mail_options,
+        mail_sender,
+        password_policy,
+        session_options,
+        events,
+        self_service_attributes,
     }))
     .route(
         "/health",
         web::get().to(|| async { HttpResponse::Ok().finish() }),
     )
-    .service(
+    .route(
+        "/healthz",
+        web::get().to(|| async { HttpResponse::Ok().finish() }),
+    )
+    .route("/readyz", web::get().to(readiness_handler::<Backend>));
+    if let Some(oidc_provider) = oidc_provider {
+        // Before the "/auth" scope, which would shadow it.
+        cfg.configure(|cfg| oidc::configure_discovery(cfg, oidc_provider))
+            .service(web::scope("/auth/oidc").configure(oidc::configure_server::<Backend>));
+    }
+    cfg.service(
         web::scope("/auth")
             .configure(|cfg| auth_service::configure_server::<Backend>(cfg, enable_password_reset)),
     )
@@ -133,7 +188,13 @@ pub(crate) struct AppState<Backend> {
     pub jwt_key: Hmac<Sha512>,
     pub jwt_blacklist: RwLock<HashSet<u64>>,
     pub server_url: url::Url,
-    pub mail_options: MailOptions,
+    /// Only set if the password reset is enabled.
+    pub mail_sender: Option<MailSender>,
+    pub password_policy: PasswordPolicyOptions,
+    pub session_options: SessionOptions,
+    /// Source of the GraphQL subscriptions.
+    pub events: WebhookNotifier,
+    pub self_service_attributes: Vec<String>,
 }
 
 impl<Backend: BackendHandler> AppState<Backend> {
@@ -156,10 +217,21 @@ impl<Backend: LoginHandler> AppState<Backend> {
         self.backend_handler.unsafe_get_handler()
     }
 }
+impl<Backend: TotpHandler> AppState<Backend> {
+    pub fn get_totp_handler(&self) -> &impl TotpHandler {
+        self.backend_handler.unsafe_get_handler()
+    }
+}
+impl<Backend: PasskeyHandler> AppState<Backend> {
+    pub fn get_passkey_handler(&self) -> &impl PasskeyHandler {
+        self.backend_handler.unsafe_get_handler()
+    }
+}
 
 pub async fn build_tcp_server<Backend>(
     config: &Configuration,
     backend_handler: Backend,
+    events: WebhookNotifier,
     server_builder: ServerBuilder,
 ) -> Result<ServerBuilder>
 where
@@ -171,7 +243,12 @@ where
         .await
         .context("while getting the jwt blacklist")?;
     let server_url = config.http_url.clone();
-    let mail_options = config.smtp_options.clone();
+    let mail_sender = MailSender::start(config.smtp_options.clone())?;
+    let password_policy = config.password_policy.clone();
+    let session_options = config.session_options.clone();
+    let oidc_provider = OidcProvider::new(&config.oidc_options, &config.http_url)
+        .context("while setting up the OIDC provider")?;
+    let self_service_attributes = config.self_service_attributes.clone();
     let verbose = config.verbose;
     info!("Starting the API/web server on port {}", config.http_port);
     server_builder
@@ -183,7 +260,12 @@ where
                 let jwt_secret = jwt_secret.clone();
                 let jwt_blacklist = jwt_blacklist.clone();
                 let server_url = server_url.clone();
-                let mail_options = mail_options.clone();
+                let mail_sender = mail_sender.clone();
+                let password_policy = password_policy.clone();
+                let session_options = session_options.clone();
+                let oidc_provider = oidc_provider.clone();
+                let events = events.clone();
+                let self_service_attributes = self_service_attributes.clone();
                 HttpServiceBuilder::default()
                     .finish(map_config(
                         App::new()
@@ -198,7 +280,12 @@ where
                                     jwt_secret,
                                     jwt_blacklist,
                                     server_url,
-                                    mail_options,
+                                    mail_sender,
+                                    password_policy,
+                                    session_options,
+                                    oidc_provider,
+                                    events,
+                                    self_service_attributes,
                                 )
                             }),
                         |_| AppConfig::default(),
@@ -1,4 +1,7 @@
-use crate::domain::{error::Result, handler::*, opaque_handler::*, types::*};
+use crate::domain::{
+    api_token_handler::*, audit_log_handler::*, error::Result, handler::*, lockout_handler::*,
+    opaque_handler::*, passkey_handler::*, session_handler::*, totp_handler::*, types::*,
+};
 
 use async_trait::async_trait;
 use std::collections::HashSet;
@@ -11,6 +14,9 @@ mockall::mock! {
     #[async_trait]
     impl LoginHandler for TestBackendHandler {
         async fn bind(&self, request: BindRequest) -> Result<()>;
+        async fn reject_bind(&self, request: BindRequest) -> Result<()>;
+        async fn is_password_recently_used(&self, user_id: &UserId, password: &str) -> Result<bool>;
+        async fn import_password_hash(&self, user_id: &UserId, password_hash: &str) -> Result<()>;
     }
     #[async_trait]
     impl GroupListerBackendHandler for TestBackendHandler {
@@ -22,17 +28,25 @@ mockall::mock! {
         async fn update_group(&self, request: UpdateGroupRequest) -> Result<()>;
         async fn create_group(&self, group_name: &str) -> Result<GroupId>;
         async fn delete_group(&self, group_id: GroupId) -> Result<()>;
+        async fn set_group_dynamic_filter(&self, group_id: GroupId, filter: Option<UserRequestFilter>) -> Result<()>;
+        async fn add_group_to_group(&self, member_group_id: GroupId, group_id: GroupId) -> Result<()>;
+        async fn remove_group_from_group(&self, member_group_id: GroupId, group_id: GroupId) -> Result<()>;
     }
     #[async_trait]
     impl UserListerBackendHandler for TestBackendHandler {
         async fn list_users(&self, filters: Option<UserRequestFilter>, get_groups: bool) -> Result<Vec<UserAndGroups>>;
+        async fn list_users_sorted(&self, filters: Option<UserRequestFilter>, get_groups: bool, sort: Vec<UserSortKey>, page: Option<(u64, u64)>) -> Result<Vec<UserAndGroups>>;
     }
     #[async_trait]
     impl UserBackendHandler for TestBackendHandler {
         async fn get_user_details(&self, user_id: &UserId) -> Result<User>;
         async fn create_user(&self, request: CreateUserRequest) -> Result<()>;
+        async fn import_users(&self, requests: Vec<ImportUserRequest>, atomic: bool) -> Result<Vec<Result<()>>>;
         async fn update_user(&self, request: UpdateUserRequest) -> Result<()>;
         async fn delete_user(&self, user_id: &UserId) -> Result<()>;
+        async fn restore_user(&self, user_id: &UserId) -> Result<()>;
+        async fn set_user_enabled(&self, user_id: &UserId, enabled: bool) -> Result<()>;
+        async fn set_user_expiration_date(&self, user_id: &UserId, expiration_date: Option<chrono::NaiveDateTime>) -> Result<()>;
         async fn get_user_groups(&self, user_id: &UserId) -> Result<HashSet<GroupDetails>>;
         async fn add_user_to_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()>;
         async fn remove_user_from_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()>;
@@ -42,6 +56,62 @@ mockall::mock! {
         async fn get_schema(&self) -> Result<Schema>;
     }
     #[async_trait]
+    impl SchemaWriterBackendHandler for TestBackendHandler {
+        async fn set_user_attribute_allowed_values(
+            &self,
+            name: &str,
+            allowed_values: Option<Vec<String>>,
+        ) -> Result<()>;
+    }
+    #[async_trait]
+    impl TotpHandler for TestBackendHandler {
+        async fn start_totp_enrollment(&self, user_id: &UserId) -> Result<Vec<u8>>;
+        async fn confirm_totp_enrollment(&self, user_id: &UserId, code: &str) -> Result<()>;
+        async fn disable_totp(&self, user_id: &UserId) -> Result<()>;
+        async fn check_totp_code(&self, user_id: &UserId, code: Option<String>) -> Result<()>;
+    }
+    #[async_trait]
+    impl PasskeyHandler for TestBackendHandler {
+        async fn list_passkeys(&self, user_id: &UserId) -> Result<Vec<PasskeyInfo>>;
+        async fn start_passkey_registration(&self, user_id: &UserId) -> Result<PasskeyChallenge>;
+        async fn finish_passkey_registration(
+            &self,
+            user_id: &UserId,
+            name: &str,
+            server_data: &str,
+            credential: &str,
+        ) -> Result<PasskeyInfo>;
+        async fn delete_passkey(&self, user_id: &UserId, passkey_id: i32) -> Result<()>;
+        async fn start_passkey_login(&self, user_id: &UserId) -> Result<PasskeyChallenge>;
+        async fn finish_passkey_login(&self, server_data: &str, credential: &str) -> Result<UserId>;
+    }
+    #[async_trait]
+    impl SessionHandler for TestBackendHandler {
+        async fn revoke_all_sessions(&self, user_id: &UserId) -> Result<()>;
+    }
+    #[async_trait]
+    impl LockoutHandler for TestBackendHandler {
+        async fn list_user_lockouts(&self) -> Result<Vec<UserLockout>>;
+        async fn clear_user_lockout(&self, user_id: &UserId) -> Result<()>;
+    }
+    #[async_trait]
+    impl ApiTokenHandler for TestBackendHandler {
+        async fn list_api_tokens(&self) -> Result<Vec<ApiToken>>;
+        async fn create_api_token(
+            &self,
+            name: &str,
+            scope: ApiTokenScope,
+            expiry_date: Option<chrono::NaiveDateTime>,
+        ) -> Result<String>;
+        async fn revoke_api_token(&self, name: &str) -> Result<()>;
+        async fn check_api_token(&self, token: &str) -> Result<ApiToken>;
+    }
+    #[async_trait]
+    impl AuditLogHandler for TestBackendHandler {
+        async fn record_audit_event(&self, entry: AuditLogEntry) -> Result<()>;
+        async fn list_audit_events(&self, filter: AuditLogFilter) -> Result<Vec<AuditLogEntry>>;
+    }
+    #[async_trait]
     impl BackendHandler for TestBackendHandler {}
     #[async_trait]
     impl OpaqueHandler for TestBackendHandler {
@@ -73,6 +143,8 @@ pub fn setup_default_schema(mock: &mut MockTestBackendHandler) {
                         is_visible: true,
                         is_editable: true,
                         is_hardcoded: true,
+                        is_case_sensitive: false,
+                        allowed_values: None,
                     },
                     AttributeSchema {
                         name: "first_name".to_owned(),
@@ -81,6 +153,8 @@ pub fn setup_default_schema(mock: &mut MockTestBackendHandler) {
                         is_visible: true,
                         is_editable: true,
                         is_hardcoded: true,
+                        is_case_sensitive: false,
+                        allowed_values: None,
                     },
                     AttributeSchema {
                         name: "last_name".to_owned(),
@@ -89,6 +163,8 @@ pub fn setup_default_schema(mock: &mut MockTestBackendHandler) {
                         is_visible: true,
                         is_editable: true,
                         is_hardcoded: true,
+                        is_case_sensitive: false,
+                        allowed_values: None,
                     },
                 ],
             },
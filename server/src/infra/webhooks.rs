@@ -0,0 +1,246 @@
+//! Notifications of the user and group lifecycle events, sent to the configured webhooks and to
+//! the GraphQL subscriptions.
+
+use crate::{
+    domain::types::{GroupId, UserId},
+    infra::configuration::WebhookOptions,
+};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc};
+use tracing::{debug, error, warn};
+
+/// Maximum number of events waiting to be sent. Further events are dropped.
+const QUEUE_SIZE: usize = 256;
+/// Number of events buffered for each subscriber. A subscriber that falls behind misses the
+/// older events.
+const SUBSCRIBER_QUEUE_SIZE: usize = 64;
+const MAX_ATTEMPTS: u32 = 5;
+/// Delay before the first retry, doubled after each attempt.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Header with the HMAC-SHA256 of the body, as "sha256=<hex>".
+const SIGNATURE_HEADER: &str = "X-Lldap-Signature";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEventType {
+    UserCreated,
+    UserUpdated,
+    UserDeleted,
+    UserAddedToGroup,
+    UserRemovedFromGroup,
+    GroupCreated,
+    GroupUpdated,
+    GroupDeleted,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct WebhookEvent {
+    pub event: WebhookEventType,
+    /// Not set for the group events.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<UserId>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub changed_fields: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group_id: Option<GroupId>,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+}
+
+impl WebhookEvent {
+    pub fn new(event: WebhookEventType, user_id: UserId) -> Self {
+        Self {
+            event,
+            user_id: Some(user_id),
+            changed_fields: Vec::new(),
+            group_id: None,
+            timestamp: chrono::Utc::now(),
+        }
+    }
+
+    pub fn for_group(event: WebhookEventType, group_id: GroupId) -> Self {
+        Self {
+            event,
+            user_id: None,
+            changed_fields: Vec::new(),
+            group_id: Some(group_id),
+            timestamp: chrono::Utc::now(),
+        }
+    }
+}
+
+/// Queues the events, to be sent to the webhooks in the background, and broadcasts them to the
+/// subscribers.
+#[derive(Clone)]
+pub struct WebhookNotifier {
+    /// Not set if there are no webhooks configured.
+    sender: Option<mpsc::Sender<WebhookEvent>>,
+    subscribers: broadcast::Sender<WebhookEvent>,
+}
+
+impl WebhookNotifier {
+    /// Starts the task sending the events, if there are webhooks configured.
+    pub fn start(webhooks: Vec<WebhookOptions>) -> Self {
+        let sender = if webhooks.is_empty() {
+            None
+        } else {
+            let (sender, receiver) = mpsc::channel(QUEUE_SIZE);
+            tokio::spawn(send_events(webhooks, receiver));
+            Some(sender)
+        };
+        Self {
+            sender,
+            subscribers: broadcast::channel(SUBSCRIBER_QUEUE_SIZE).0,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn new_for_tests() -> (Self, mpsc::Receiver<WebhookEvent>) {
+        let (sender, receiver) = mpsc::channel(QUEUE_SIZE);
+        (
+            Self {
+                sender: Some(sender),
+                subscribers: broadcast::channel(SUBSCRIBER_QUEUE_SIZE).0,
+            },
+            receiver,
+        )
+    }
+
+    /// Never blocks: the event is dropped if the queue is full.
+    pub fn notify(&self, event: WebhookEvent) {
+        // Only fails if there are no subscribers.
+        let _ = self.subscribers.send(event.clone());
+        if let Some(sender) = &self.sender {
+            if let Err(e) = sender.try_send(event) {
+                warn!("Dropping webhook event: {}", e);
+            }
+        }
+    }
+
+    /// Receives the events notified from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<WebhookEvent> {
+        self.subscribers.subscribe()
+    }
+}
+
+fn sign(secret: &[u8], body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any size");
+    mac.update(body);
+    format!(
+        "sha256={}",
+        data_encoding::HEXLOWER.encode(&mac.finalize().into_bytes())
+    )
+}
+
+async fn send_events(webhooks: Vec<WebhookOptions>, mut receiver: mpsc::Receiver<WebhookEvent>) {
+    let client = match reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Could not create the webhook client: {}", e);
+            return;
+        }
+    };
+    while let Some(event) = receiver.recv().await {
+        let body = match serde_json::to_vec(&event) {
+            Ok(body) => body,
+            Err(e) => {
+                error!("Could not serialize webhook event: {}", e);
+                continue;
+            }
+        };
+        for webhook in &webhooks {
+            send_with_retries(&client, webhook, &body).await;
+        }
+    }
+}
+
+async fn send_with_retries(client: &reqwest::Client, webhook: &WebhookOptions, body: &[u8]) {
+    let signature = sign(webhook.secret.unsecure().as_bytes(), body);
+    let mut backoff = INITIAL_BACKOFF;
+    for attempt in 1..=MAX_ATTEMPTS {
+        match client
+            .post(webhook.url.clone())
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, &signature)
+            .body(body.to_vec())
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+        {
+            Ok(_) => {
+                debug!("Webhook event sent to {}", webhook.url);
+                return;
+            }
+            Err(e) => warn!(
+                "Error sending webhook event to {} (attempt {}/{}): {}",
+                webhook.url, attempt, MAX_ATTEMPTS, e
+            ),
+        }
+        if attempt < MAX_ATTEMPTS {
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+    }
+    error!("Giving up on sending webhook event to {}", webhook.url);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign() {
+        // From RFC 4231, test case 2.
+        assert_eq!(
+            sign(b"Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_serialize_event() {
+        let event = WebhookEvent {
+            group_id: Some(GroupId(3)),
+            timestamp: chrono::DateTime::parse_from_rfc3339("2023-01-02T03:04:05Z")
+                .unwrap()
+                .into(),
+            ..WebhookEvent::new(WebhookEventType::UserAddedToGroup, UserId::new("bob"))
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"event":"user_added_to_group","user_id":"bob","group_id":3,"timestamp":"2023-01-02T03:04:05Z"}"#
+        );
+        let event = WebhookEvent {
+            timestamp: chrono::DateTime::parse_from_rfc3339("2023-01-02T03:04:05Z")
+                .unwrap()
+                .into(),
+            ..WebhookEvent::for_group(WebhookEventType::GroupDeleted, GroupId(3))
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"event":"group_deleted","group_id":3,"timestamp":"2023-01-02T03:04:05Z"}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_subscribe() {
+        let notifier = WebhookNotifier::start(Vec::new());
+        // Nobody is listening.
+        notifier.notify(WebhookEvent::new(
+            WebhookEventType::UserDeleted,
+            UserId::new("bob"),
+        ));
+        let mut events = notifier.subscribe();
+        notifier.notify(WebhookEvent::for_group(
+            WebhookEventType::GroupCreated,
+            GroupId(3),
+        ));
+        let event = events.try_recv().unwrap();
+        assert_eq!(event.event, WebhookEventType::GroupCreated);
+        assert_eq!(event.group_id, Some(GroupId(3)));
+        assert!(events.try_recv().is_err());
+    }
+}
@@ -14,7 +14,10 @@ use crate::{
         sql_backend_handler::SqlBackendHandler,
         sql_opaque_handler::register_password,
     },
-    infra::{cli::*, configuration::Configuration, db_cleaner::Scheduler, healthcheck, mail},
+    infra::{
+        cli::*, configuration::Configuration, db_cleaner::Scheduler, healthcheck, mail,
+        webhooks::WebhookNotifier,
+    },
 };
 use actix::Actor;
 use actix_server::ServerBuilder;
@@ -75,9 +78,10 @@ async fn set_up_server(config: Configuration) -> Result<ServerBuilder> {
     info!("Starting LLDAP version {}", env!("CARGO_PKG_VERSION"));
 
     let sql_pool = {
-        let mut sql_opt = sea_orm::ConnectOptions::new(config.database_url.clone());
+        let mut sql_opt = config
+            .database_pool_options
+            .connect_options(&config.database_url);
         sql_opt
-            .max_connections(5)
             .sqlx_logging(true)
             .sqlx_logging_level(log::LevelFilter::Debug);
         Database::connect(sql_opt).await?
@@ -85,16 +89,41 @@ async fn set_up_server(config: Configuration) -> Result<ServerBuilder> {
     domain::sql_tables::init_table(&sql_pool)
         .await
         .context("while creating the tables")?;
-    let backend_handler = SqlBackendHandler::new(config.clone(), sql_pool.clone());
+    let replica_pool = match &config.database_replica_url {
+        Some(url) => {
+            let mut sql_opt = config.database_pool_options.connect_options(url);
+            sql_opt
+                .sqlx_logging(true)
+                .sqlx_logging_level(log::LevelFilter::Debug);
+            Some(
+                Database::connect(sql_opt)
+                    .await
+                    .context("while connecting to the database replica")?,
+            )
+        }
+        None => None,
+    };
+    let events = WebhookNotifier::start(config.webhooks.clone());
+    let backend_handler = SqlBackendHandler::new(config.clone(), sql_pool.clone())
+        .with_read_replica(replica_pool)
+        .with_webhooks(Some(events.clone()));
     ensure_group_exists(&backend_handler, "lldap_admin").await?;
     ensure_group_exists(&backend_handler, "lldap_password_manager").await?;
     ensure_group_exists(&backend_handler, "lldap_strict_readonly").await?;
     if let Err(e) = backend_handler.get_user_details(&config.ldap_user_dn).await {
-        warn!("Could not get admin user, trying to create it: {:#}", e);
-        create_admin_user(&backend_handler, &config)
+        if backend_handler
+            .restore_user(&config.ldap_user_dn)
             .await
-            .map_err(|e| anyhow!("Error setting up admin login/account: {:#}", e))
-            .context("while creating the admin user")?;
+            .is_ok()
+        {
+            warn!("The admin user was deleted, it has been restored");
+        } else {
+            warn!("Could not get admin user, trying to create it: {:#}", e);
+            create_admin_user(&backend_handler, &config)
+                .await
+                .map_err(|e| anyhow!("Error setting up admin login/account: {:#}", e))
+                .context("while creating the admin user")?;
+        }
     }
     let server_builder = infra::ldap_server::build_ldap_server(
         &config,
@@ -104,11 +133,15 @@ async fn set_up_server(config: Configuration) -> Result<ServerBuilder> {
     .context("while binding the LDAP server")?;
     infra::jwt_sql_tables::init_table(&sql_pool).await?;
     let server_builder =
-        infra::tcp_server::build_tcp_server(&config, backend_handler, server_builder)
+        infra::tcp_server::build_tcp_server(&config, backend_handler, events, server_builder)
             .await
             .context("while binding the TCP server")?;
     // Run every hour.
-    let scheduler = Scheduler::new("0 0 * * * * *", sql_pool);
+    let scheduler = Scheduler::new(
+        "0 0 * * * * *",
+        sql_pool,
+        config.deleted_users_retention_days,
+    );
     scheduler.start();
     Ok(server_builder)
 }
@@ -221,6 +254,244 @@ fn create_schema_command(opts: RunOpts) -> Result<()> {
     Ok(())
 }
 
+/// Returns whether some migrations are pending.
+async fn migrate(config: Configuration, opts: MigrateOpts) -> Result<bool> {
+    let sql_pool = {
+        let mut sql_opt = sea_orm::ConnectOptions::new(config.database_url.clone());
+        // The statements of the dry run are printed through the logs.
+        sql_opt
+            .max_connections(1)
+            .sqlx_logging(opts.dry_run)
+            .sqlx_logging_level(log::LevelFilter::Warn);
+        Database::connect(sql_opt).await?
+    };
+    let last_version = domain::sql_tables::LAST_SCHEMA_VERSION;
+    let version = domain::sql_migrations::get_schema_version(&sql_pool).await;
+    if let Some(version) = version.filter(|v| *v > last_version) {
+        return Err(anyhow!(
+            "The DB schema version {} is newer than this version of lldap ({})",
+            version.0,
+            last_version.0
+        ));
+    }
+    let pending = version != Some(last_version);
+    match (opts.command, version) {
+        (Some(MigrateCommand::Status), _) => {
+            match version {
+                Some(version) => println!(
+                    "DB schema version: {} (latest: {})",
+                    version.0, last_version.0
+                ),
+                None => println!("No DB schema, it will be created"),
+            }
+            for migration in 2..=last_version.0 {
+                let migration = domain::sql_tables::SchemaVersion(migration);
+                println!(
+                    "  {} v{}: {}",
+                    if version.map_or(false, |v| migration <= v) {
+                        "applied"
+                    } else {
+                        "pending"
+                    },
+                    migration.0,
+                    domain::sql_migrations::migration_description(migration)
+                );
+            }
+        }
+        (None, None) if opts.dry_run => {
+            info!("No DB schema, the dry run is only possible once it is created")
+        }
+        (None, Some(version)) if opts.dry_run => {
+            domain::sql_migrations::dry_run_migrations(&sql_pool, version, last_version).await?
+        }
+        (None, _) => {
+            domain::sql_tables::init_table(&sql_pool)
+                .await
+                .context("while migrating the tables")?;
+            infra::jwt_sql_tables::init_table(&sql_pool)
+                .await
+                .context("while creating jwt tables")?;
+            return Ok(false);
+        }
+    }
+    Ok(pending)
+}
+
+fn migrate_command(opts: MigrateOpts) -> Result<()> {
+    debug!("CLI: {:#?}", &opts);
+    let config = infra::configuration::init(opts.clone())?;
+    infra::logging::init(&config)?;
+
+    let pending = actix::run(migrate(config, opts))?.context("while migrating the database")?;
+    // For the scripts checking whether an upgrade changes the database.
+    std::process::exit(if pending { 2 } else { 0 })
+}
+
+async fn connect_to_database(config: &Configuration) -> Result<SqlBackendHandler> {
+    let sql_pool = {
+        let mut sql_opt = sea_orm::ConnectOptions::new(config.database_url.clone());
+        sql_opt
+            .max_connections(1)
+            .sqlx_logging(true)
+            .sqlx_logging_level(log::LevelFilter::Debug);
+        Database::connect(sql_opt).await?
+    };
+    domain::sql_tables::init_table(&sql_pool)
+        .await
+        .context("while creating base tables")?;
+    Ok(SqlBackendHandler::new(config.clone(), sql_pool))
+}
+
+async fn export_ldif(config: Configuration, output_file: String) -> Result<()> {
+    let backend_handler = connect_to_database(&config).await?;
+    let file =
+        std::fs::File::create(&output_file).context(format!("while creating {}", output_file))?;
+    infra::ldif::export_ldif(
+        &backend_handler,
+        &domain::ldap::utils::LdapInfo::new(&config),
+        &mut std::io::BufWriter::new(file),
+    )
+    .await
+}
+
+fn export_ldif_command(opts: ExportLdifOpts) -> Result<()> {
+    debug!("CLI: {:#?}", &opts);
+    let output_file = opts.output_file.clone();
+    let config = infra::configuration::init(opts)?;
+    infra::logging::init(&config)?;
+
+    actix::run(export_ldif(config, output_file.clone()))?
+        .context("while exporting the directory")?;
+
+    info!("Directory exported to {}.", output_file);
+    Ok(())
+}
+
+async fn import_ldif(config: Configuration, input: String, dry_run: bool) -> Result<()> {
+    let backend_handler = connect_to_database(&config).await?;
+    let summary = infra::ldif::import_ldif(
+        &backend_handler,
+        &domain::ldap::utils::LdapInfo::new(&config),
+        &input,
+        dry_run,
+    )
+    .await?;
+    for issue in &summary.issues {
+        warn!("{}", issue);
+    }
+    info!(
+        "{}{} users created, {} users updated, {} groups created, {} memberships added.",
+        if dry_run { "Dry run: " } else { "" },
+        summary.created_users,
+        summary.updated_users,
+        summary.created_groups,
+        summary.added_memberships
+    );
+    if summary.issues.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "{} lines could not be imported",
+            summary.issues.len()
+        ))
+    }
+}
+
+fn import_ldif_command(opts: ImportLdifOpts) -> Result<()> {
+    debug!("CLI: {:#?}", &opts);
+    let input = std::fs::read_to_string(&opts.input_file)
+        .context(format!("while reading {}", opts.input_file))?;
+    let dry_run = opts.dry_run;
+    let config = infra::configuration::init(opts)?;
+    infra::logging::init(&config)?;
+
+    actix::run(import_ldif(config, input, dry_run))?.context("while importing the directory")
+}
+
+async fn import_csv(config: Configuration, opts: ImportCsvOpts) -> Result<()> {
+    let mapping = infra::csv_import::parse_mapping(&opts.mapping)?;
+    let input = std::fs::File::open(&opts.input_file)
+        .context(format!("while opening {}", opts.input_file))?;
+    let backend_handler = connect_to_database(&config).await?;
+    let result = infra::csv_import::import_csv(&backend_handler, input, &mapping).await?;
+    info!("{} users created.", result.created_users.len());
+    if opts.send_password_reset {
+        infra::csv_import::send_password_reset_emails(
+            &backend_handler,
+            &result.created_users,
+            &config,
+        )
+        .await?;
+    }
+    if result.errors.is_empty() {
+        return Ok(());
+    }
+    let errors_file = opts
+        .errors_file
+        .unwrap_or_else(|| format!("{}.errors.csv", opts.input_file));
+    let file =
+        std::fs::File::create(&errors_file).context(format!("while creating {}", errors_file))?;
+    infra::csv_import::write_errors(&result, file)?;
+    Err(anyhow!(
+        "{} rows could not be imported, see {}",
+        result.errors.len(),
+        errors_file
+    ))
+}
+
+fn import_csv_command(opts: ImportCsvOpts) -> Result<()> {
+    debug!("CLI: {:#?}", &opts);
+    let config = infra::configuration::init(opts.clone())?;
+    infra::logging::init(&config)?;
+
+    actix::run(import_csv(config, opts))?.context("while importing the users")
+}
+
+async fn backup(config: Configuration, opts: BackupOpts) -> Result<()> {
+    let backend_handler = connect_to_database(&config).await?;
+    let backup = infra::backup::create_backup(&backend_handler, opts.include_passwords).await?;
+    let data = infra::backup::write_backup(&backup, opts.passphrase.as_deref())?;
+    std::fs::write(&opts.output_file, data)
+        .context(format!("while writing {}", opts.output_file))?;
+    info!(
+        "{} users and {} groups backed up to {}.",
+        backup.user_count(),
+        backup.group_count(),
+        opts.output_file
+    );
+    Ok(())
+}
+
+fn backup_command(opts: BackupOpts) -> Result<()> {
+    debug!("CLI: {:#?}", &opts);
+    let config = infra::configuration::init(opts.clone())?;
+    infra::logging::init(&config)?;
+
+    actix::run(backup(config, opts))?.context("while backing up the directory")
+}
+
+async fn restore(config: Configuration, opts: RestoreOpts) -> Result<()> {
+    let data =
+        std::fs::read(&opts.input_file).context(format!("while reading {}", opts.input_file))?;
+    let backup = infra::backup::read_backup(&data, opts.passphrase.as_deref())?;
+    let backend_handler = connect_to_database(&config).await?;
+    let (user_count, group_count) = (backup.user_count(), backup.group_count());
+    infra::backup::restore_backup(&backend_handler, backup).await?;
+    info!(
+        "{} users and {} groups restored from {}.",
+        user_count, group_count, opts.input_file
+    );
+    Ok(())
+}
+
+fn restore_command(opts: RestoreOpts) -> Result<()> {
+    debug!("CLI: {:#?}", &opts);
+    let config = infra::configuration::init(opts.clone())?;
+    infra::logging::init(&config)?;
+
+    actix::run(restore(config, opts))?.context("while restoring the backup")
+}
+
 fn main() -> Result<()> {
     let cli_opts = infra::cli::init();
     match cli_opts.command {
@@ -229,5 +500,11 @@ fn main() -> Result<()> {
         Command::HealthCheck(opts) => run_healthcheck(opts),
         Command::SendTestEmail(opts) => send_test_email_command(opts),
         Command::CreateSchema(opts) => create_schema_command(opts),
+        Command::Migrate(opts) => migrate_command(opts),
+        Command::ExportLdif(opts) => export_ldif_command(opts),
+        Command::ImportLdif(opts) => import_ldif_command(opts),
+        Command::ImportCsv(opts) => import_csv_command(opts),
+        Command::Backup(opts) => backup_command(opts),
+        Command::Restore(opts) => restore_command(opts),
     }
 }
@@ -47,6 +47,7 @@ fn get_token(base_url: &Url, username: &str, password: &str) -> Result<String> {
             serde_json::to_string(&lldap_auth::login::ClientSimpleLoginRequest {
                 username: username.to_string(),
                 password: password.to_string(),
+                totp_code: None,
             })
             .expect("Failed to encode the username/password as json to log in"),
         )
@@ -121,6 +122,10 @@ fn main() -> Result<()> {
         registration_start_request: registration_start_request.message,
     };
     let res = register_start(&opts.base_url, &token, start_request)?;
+    // The server never sees the password: the policy is only checked here.
+    if let Some(policy) = &res.password_policy {
+        policy.check(&opts.password)?;
+    }
 
     let registration_finish = opaque::client::registration::finish_registration(
         registration_start_request.state,